@@ -0,0 +1,398 @@
+//! Typed build artifacts.
+//!
+//! [`BuildReport::artifacts`](crate::report::BuildReport::artifacts) lists
+//! the files in the output directory. [`BuildReport::typed_artifacts`]
+//! classifies them: executables, shared, static, and import libraries,
+//! loadable modules, and debug symbols, with the name they were built from
+//! and the CMake target that produced them. The kind is taken from the
+//! target's type in the CMake file API where a target lists the file, and
+//! from the platform's naming conventions otherwise, so the same code finds
+//! `foo.exe` and `foo`, or `foo.dll` and `libfoo.so.1.2.3`.
+//!
+//! [`Cmd::artifact_rule`](crate::Cmd::artifact_rule) renames artifacts or
+//! copies them elsewhere after a successful build, e.g. to drop the version
+//! from `libfoo.so.1.2.3` or to put a tool into the crate's `bin/`, and
+//! updates the report's artifacts accordingly.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, artifact::ArtifactKind};
+//!
+//! let report = Cmd::default().set_path("./my_project").build().unwrap();
+//! for artifact in report.typed_artifacts().unwrap() {
+//!     if artifact.kind == ArtifactKind::Executable {
+//!         println!("{}: {}", artifact.name, artifact.path.display());
+//!     }
+//! }
+//! ```
+//!
+//!
+//! ```no_run
+//! use cmakr::{Cmd, artifact::Action};
+//!
+//! Cmd::default()
+//!     .set_path("./my_project")
+//!     .artifact_rule("libfoo.so.*.*.*", Action::RenameTo("lib{name}.so".to_string()))
+//!     .artifact_rule("foo-cli*", Action::CopyTo("./bin".into()))
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! [`BuildReport::typed_artifacts`]: crate::report::BuildReport::typed_artifacts
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::fileapi::Target;
+
+/// What an artifact is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArtifactKind {
+    Executable,
+    /// A shared library loaded at runtime (`foo.dll`, `libfoo.so`,
+    /// `libfoo.dylib`, and their versioned names).
+    SharedLibrary,
+    /// The import library of a Windows DLL (`foo.lib`, `libfoo.dll.a`).
+    ImportLibrary,
+    /// A static library (`foo.lib`, `libfoo.a`).
+    StaticLibrary,
+    /// A library loaded with `dlopen` or `LoadLibrary`, not linked
+    /// (`MODULE_LIBRARY` target).
+    Module,
+    /// Debug symbols (`foo.pdb`, `foo.dbg`).
+    DebugSymbols,
+    /// A WebAssembly binary (`foo.wasm`), e.g. the one an Emscripten
+    /// executable's `foo.js` loads.
+    Wasm,
+    /// Any other file, e.g. a copied resource.
+    Other,
+}
+
+/// A classified file of the output directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    pub path: PathBuf,
+    pub kind: ArtifactKind,
+    /// The name without platform prefix, extension, and version, e.g. `foo`
+    /// for `libfoo.so.1.2.3`; the file name for [`ArtifactKind::Other`].
+    pub name: String,
+    /// The CMake target that produced the file, if the file API lists it.
+    pub target: Option<String>,
+}
+
+/// What an [artifact rule](crate::Cmd::artifact_rule) does with the
+/// artifacts it matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Copies the artifact into the directory, which is created if needed.
+    /// The copy is listed as an artifact too.
+    CopyTo(PathBuf),
+    /// Renames the artifact within its directory, replacing a file of the
+    /// new name. In the pattern, `{file}` stands for the file name and
+    /// `{name}` for the name without platform prefix, extension, and
+    /// version (see [`Artifact::name`]), e.g. `lib{name}.so`.
+    RenameTo(String),
+}
+
+/// An action for the artifacts whose file name matches a glob pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Rule {
+    pub(crate) pattern: String,
+    pub(crate) action: Action,
+}
+
+/// Applies `rules` in order to `artifacts`, a sorted list of files, and
+/// updates it with the renamed and copied files.
+///
+/// Renaming the real file of a versioned library leaves its other link
+/// names dangling; they are dropped from the list.
+pub(crate) fn apply_rules(rules: &[Rule], artifacts: &mut Vec<PathBuf>) -> io::Result<()> {
+    for rule in rules {
+        let matched: Vec<PathBuf> = artifacts
+            .iter()
+            .filter(|path| {
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                glob_match(&rule.pattern, &file_name)
+            })
+            .cloned()
+            .collect();
+        for path in matched {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            let destination = match &rule.action {
+                Action::CopyTo(dir) => {
+                    std::fs::create_dir_all(dir)?;
+                    let destination = std::path::absolute(dir)?.join(&*file_name);
+                    std::fs::copy(&path, &destination)?;
+                    destination
+                }
+                Action::RenameTo(pattern) => {
+                    let (_, name) = classify(&file_name, is_executable(&path));
+                    let renamed = pattern
+                        .replace("{file}", &file_name)
+                        .replace("{name}", &name);
+                    let destination = path.with_file_name(renamed);
+                    std::fs::rename(&path, &destination)?;
+                    artifacts.retain(|artifact| artifact != &path);
+                    destination
+                }
+            };
+            if !artifacts.contains(&destination) {
+                artifacts.push(destination);
+            }
+        }
+    }
+    artifacts.retain(|artifact| artifact.exists());
+    artifacts.sort();
+    Ok(())
+}
+
+/// Returns whether `text` matches the glob `pattern`, where `*` stands for
+/// any run of characters and `?` for a single one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // the last `*` and the text position it was tried at, for backtracking
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Classifies `files`, using the kinds of the `targets` that list them.
+pub(crate) fn discover(files: &[PathBuf], targets: &[Target]) -> Vec<Artifact> {
+    let produced: Vec<(PathBuf, &Target)> = targets
+        .iter()
+        .flat_map(|target| {
+            target
+                .artifacts
+                .iter()
+                .map(move |artifact| (canonical(artifact), target))
+        })
+        .collect();
+
+    files
+        .iter()
+        .map(|path| {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            let (by_name, name) = classify(&file_name, is_executable(path));
+            // symlinks to a versioned library belong to the target of the real file
+            let real = canonical(path);
+            let produced_by = |file: &Path| {
+                produced
+                    .iter()
+                    .find(|(artifact, _)| artifact == file)
+                    .map(|(_, target)| *target)
+            };
+            // Emscripten lists the .js loader of an executable, not its .wasm binary
+            let target = produced_by(&real).or_else(|| {
+                (by_name == ArtifactKind::Wasm)
+                    .then(|| produced_by(&real.with_extension("js")))
+                    .flatten()
+            });
+            let kind = match target.map(|target| target.ty.as_str()) {
+                _ if matches!(by_name, ArtifactKind::DebugSymbols | ArtifactKind::Wasm) => by_name,
+                Some("EXECUTABLE") => ArtifactKind::Executable,
+                Some("SHARED_LIBRARY") if by_name == ArtifactKind::StaticLibrary => {
+                    ArtifactKind::ImportLibrary
+                }
+                Some("SHARED_LIBRARY") if by_name == ArtifactKind::ImportLibrary => by_name,
+                Some("SHARED_LIBRARY") => ArtifactKind::SharedLibrary,
+                Some("STATIC_LIBRARY") => ArtifactKind::StaticLibrary,
+                Some("MODULE_LIBRARY") => ArtifactKind::Module,
+                _ => by_name,
+            };
+            Artifact {
+                path: path.clone(),
+                kind,
+                name,
+                target: target.map(|target| target.name.clone()),
+            }
+        })
+        .collect()
+}
+
+/// Classifies a file by its name, returning its kind and base name.
+///
+/// A `.lib` file is taken for a static library; only its target tells an
+/// import library apart.
+fn classify(file_name: &str, executable: bool) -> (ArtifactKind, String) {
+    let is_version =
+        |s: &str| !s.is_empty() && s.split('.').all(|part| part.parse::<u32>().is_ok());
+    let unprefixed = |stem: &str| stem.strip_prefix("lib").unwrap_or(stem).to_string();
+    let lower = file_name.to_lowercase();
+    let stem = |suffix: &str| file_name[..file_name.len() - suffix.len()].to_string();
+
+    if lower.ends_with(".pdb") {
+        (ArtifactKind::DebugSymbols, stem(".pdb"))
+    } else if lower.ends_with(".dbg") {
+        (ArtifactKind::DebugSymbols, stem(".dbg"))
+    } else if lower.ends_with(".wasm") {
+        (ArtifactKind::Wasm, stem(".wasm"))
+    } else if lower.ends_with(".exe") {
+        (ArtifactKind::Executable, stem(".exe"))
+    } else if lower.ends_with(".dll") {
+        (ArtifactKind::SharedLibrary, stem(".dll"))
+    } else if lower.ends_with(".dll.a") {
+        (ArtifactKind::ImportLibrary, unprefixed(&stem(".dll.a")))
+    } else if lower.ends_with(".lib") {
+        (ArtifactKind::StaticLibrary, stem(".lib"))
+    } else if lower.ends_with(".a") {
+        (ArtifactKind::StaticLibrary, unprefixed(&stem(".a")))
+    } else if let Some(base) = file_name.strip_suffix(".dylib") {
+        let base = match base.split_once('.') {
+            Some((base, version)) if is_version(version) => base,
+            _ => base,
+        };
+        (ArtifactKind::SharedLibrary, unprefixed(base))
+    } else if let Some((base, version)) = file_name.rsplit_once(".so")
+        && (version.is_empty() || version.strip_prefix('.').is_some_and(is_version))
+    {
+        (ArtifactKind::SharedLibrary, unprefixed(base))
+    } else if executable && !file_name.contains('.') {
+        (ArtifactKind::Executable, file_name.to_string())
+    } else {
+        (ArtifactKind::Other, file_name.to_string())
+    }
+}
+
+/// Returns whether `path` is a file with an execute permission.
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata()
+            .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_artifacts() {
+        let names = [
+            ("app.exe", ArtifactKind::Executable, "app"),
+            ("foo.dll", ArtifactKind::SharedLibrary, "foo"),
+            ("libfoo.dll.a", ArtifactKind::ImportLibrary, "foo"),
+            ("foo.lib", ArtifactKind::StaticLibrary, "foo"),
+            ("libfoo.a", ArtifactKind::StaticLibrary, "foo"),
+            ("libfoo.so.1.2.3", ArtifactKind::SharedLibrary, "foo"),
+            ("libfoo.1.dylib", ArtifactKind::SharedLibrary, "foo"),
+            ("foo.pdb", ArtifactKind::DebugSymbols, "foo"),
+            ("app.wasm", ArtifactKind::Wasm, "app"),
+            ("libfoo.so.conf", ArtifactKind::Other, "libfoo.so.conf"),
+        ];
+        for (file_name, kind, name) in names {
+            assert_eq!(classify(file_name, false), (kind, name.to_string()));
+        }
+        assert_eq!(classify("app", true).0, ArtifactKind::Executable);
+        assert_eq!(classify("README", false).0, ArtifactKind::Other);
+
+        let target = |name: &str, ty: &str, artifacts: &[&str]| Target {
+            name: name.to_string(),
+            ty: ty.to_string(),
+            artifacts: artifacts.iter().map(PathBuf::from).collect(),
+            ..Target::default()
+        };
+        let targets = [
+            target("foo", "SHARED_LIBRARY", &["/out/foo.dll", "/out/foo.lib"]),
+            target("plugin", "MODULE_LIBRARY", &["/out/plugin.so"]),
+            target("app", "EXECUTABLE", &["/out/app.js"]),
+        ];
+        let files: Vec<PathBuf> = [
+            "/out/foo.dll",
+            "/out/foo.lib",
+            "/out/plugin.so",
+            "/out/x.lib",
+            "/out/app.js",
+            "/out/app.wasm",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        let artifacts = discover(&files, &targets);
+        let kinds: Vec<(ArtifactKind, Option<&str>)> = artifacts
+            .iter()
+            .map(|artifact| (artifact.kind, artifact.target.as_deref()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (ArtifactKind::SharedLibrary, Some("foo")),
+                (ArtifactKind::ImportLibrary, Some("foo")),
+                (ArtifactKind::Module, Some("plugin")),
+                (ArtifactKind::StaticLibrary, None),
+                (ArtifactKind::Executable, Some("app")),
+                (ArtifactKind::Wasm, Some("app")),
+            ]
+        );
+    }
+
+    #[test]
+    fn apply_artifact_rules() {
+        assert!(glob_match("libfoo.so.*.*.*", "libfoo.so.1.2.3"));
+        assert!(glob_match("*.d?l", "foo.dll"));
+        assert!(!glob_match("libfoo.so.*.*.*", "libfoo.so.1"));
+
+        let temp = crate::guard::TempBuildDir::new("artifact-rules").unwrap();
+        let out = temp.path().join("out");
+        std::fs::create_dir_all(&out).unwrap();
+        std::fs::write(out.join("libfoo.so.1.2.3"), "so").unwrap();
+        std::fs::write(out.join("tool"), "tool").unwrap();
+        let mut artifacts = vec![out.join("libfoo.so.1.2.3"), out.join("tool")];
+
+        let bin = temp.path().join("bin");
+        let rules = [
+            Rule {
+                pattern: "libfoo.so.*".to_string(),
+                action: Action::RenameTo("lib{name}.so".to_string()),
+            },
+            Rule {
+                pattern: "tool".to_string(),
+                action: Action::CopyTo(bin.clone()),
+            },
+        ];
+        apply_rules(&rules, &mut artifacts).unwrap();
+        assert_eq!(
+            artifacts,
+            [bin.join("tool"), out.join("libfoo.so"), out.join("tool")]
+        );
+        assert_eq!(
+            std::fs::read_to_string(out.join("libfoo.so")).unwrap(),
+            "so"
+        );
+        assert!(!out.join("libfoo.so.1.2.3").exists());
+    }
+}
@@ -0,0 +1,317 @@
+//! The `cmakr` command line tool, built with the `cli` feature.
+//!
+//! Exposes the library to Makefiles, justfiles, and shells:
+//!
+//! ```text
+//! cmakr build --path . --preset default --define FOO=ON --jobs 8
+//! cmakr configure --config cmakr.toml
+//! cmakr test --build-dir build -j 4
+//! cmakr install --prefix dist
+//! cmakr workflow ci --fresh
+//! cmakr presets list
+//! cmakr presets pin 3.25
+//! ```
+//!
+//! The settings of a `--config` file, as read by
+//! [`Cmd::from_file`](cmakr::Cmd::from_file), are the starting point that
+//! the other options override. Paths in the file are relative to the file,
+//! paths given as options to the current directory.
+
+use std::{io::IsTerminal, path::PathBuf, process::ExitCode};
+
+use clap::{Args, Parser, Subcommand};
+use cmakr::{
+    Cmd,
+    cmake::{CMakePresets, CMakeVersion},
+    error::Error,
+    report::BuildReport,
+};
+
+#[derive(Parser)]
+#[command(
+    name = "cmakr",
+    version,
+    about = "Configure, build, test, and install CMake projects"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Configure and build the project.
+    Build(BuildArgs),
+    /// Configure the project without building it.
+    Configure(BuildArgs),
+    /// Run the tests of a built tree with ctest.
+    Test {
+        #[command(flatten)]
+        build: BuildArgs,
+        /// Run only the tests matching this regular expression.
+        #[arg(short = 'R', long = "tests-regex")]
+        include: Option<String>,
+        /// Skip the tests matching this regular expression.
+        #[arg(short = 'E', long = "exclude-regex")]
+        exclude: Option<String>,
+    },
+    /// Configure, build, and install the project.
+    Install {
+        #[command(flatten)]
+        build: BuildArgs,
+        /// The install prefix.
+        #[arg(long)]
+        prefix: PathBuf,
+    },
+    /// Run a workflow preset.
+    Workflow {
+        /// The name of the workflow preset.
+        name: String,
+        #[command(flatten)]
+        build: BuildArgs,
+    },
+    /// Inspect and pin the presets of the project.
+    #[command(subcommand)]
+    Presets(PresetsCommand),
+}
+
+#[derive(Subcommand)]
+enum PresetsCommand {
+    /// List the non-hidden presets.
+    List {
+        /// The source directory.
+        #[arg(long, default_value = ".")]
+        path: PathBuf,
+    },
+    /// Lower the schema version of the presets files to what a CMake
+    /// version reads.
+    Pin {
+        /// The CMake version (e.g. `3.25`).
+        version: String,
+        /// The source directory.
+        #[arg(long, default_value = ".")]
+        path: PathBuf,
+    },
+}
+
+/// The options shared by the commands that configure or build.
+#[derive(Args)]
+struct BuildArgs {
+    /// A JSON or TOML file of builder settings to start from.
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// The source directory.
+    #[arg(long)]
+    path: Option<String>,
+    /// The build directory.
+    #[arg(short = 'B', long)]
+    build_dir: Option<String>,
+    /// The configure preset.
+    #[arg(long)]
+    preset: Option<String>,
+    /// The build preset.
+    #[arg(long)]
+    build_preset: Option<String>,
+    /// The test preset.
+    #[arg(long)]
+    test_preset: Option<String>,
+    /// The generator (e.g. `Ninja`).
+    #[arg(short = 'G', long)]
+    generator: Option<String>,
+    /// The build type (e.g. `Release`).
+    #[arg(long)]
+    build_type: Option<String>,
+    /// A cache variable, as `NAME=VALUE` or `NAME:TYPE=VALUE`.
+    #[arg(short = 'D', long = "define", value_name = "NAME=VALUE")]
+    defines: Vec<String>,
+    /// A target to build instead of the default one.
+    #[arg(short, long = "target")]
+    targets: Vec<String>,
+    /// The number of parallel jobs.
+    #[arg(short, long)]
+    jobs: Option<usize>,
+    /// Discard the cache and configure from scratch.
+    #[arg(long)]
+    fresh: bool,
+}
+
+impl BuildArgs {
+    /// Returns the builder these options describe.
+    fn command(&self) -> Result<Cmd, Error> {
+        let mut cmd = match &self.config {
+            Some(config) => Cmd::from_file(config)?,
+            None => Cmd::default(),
+        };
+        // paths on the command line are relative to where cmakr runs, not to the config file
+        if let Some(path) = &self.path {
+            cmd = cmd.set_path(std::path::absolute(path)?.to_string_lossy());
+        }
+        if let Some(dir) = &self.build_dir {
+            cmd = cmd.set_binary_path(std::path::absolute(dir)?.to_string_lossy());
+        }
+        if let Some(preset) = &self.preset {
+            cmd = cmd.set_preset(preset);
+        }
+        if let Some(preset) = &self.build_preset {
+            cmd = cmd.set_build_preset(preset);
+        }
+        if let Some(preset) = &self.test_preset {
+            cmd = cmd.set_test_preset(preset);
+        }
+        if let Some(generator) = &self.generator {
+            cmd = cmd.set_generator(generator);
+        }
+        if let Some(build_type) = &self.build_type {
+            cmd = cmd.set_build_type(build_type);
+        }
+        for define in &self.defines {
+            let Some((name, value)) = define.split_once('=') else {
+                return Err(Error::InvalidDefine(format!(
+                    "{:?} is not NAME=VALUE",
+                    define
+                )));
+            };
+            cmd = cmd.add_define(name, value);
+        }
+        for target in &self.targets {
+            cmd = cmd.add_target(target);
+        }
+        if let Some(jobs) = self.jobs {
+            cmd = cmd.set_jobs(jobs);
+        }
+        Ok(cmd.fresh(self.fresh))
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode, Error> {
+    match cli.command {
+        Command::Build(build) => summarize(build.command()?.build()?),
+        Command::Configure(build) => summarize(build.command()?.configure()?),
+        Command::Install { build, prefix } => summarize(
+            build
+                .command()?
+                .set_install_prefix(prefix.to_string_lossy())
+                .build()?,
+        ),
+        Command::Workflow { name, build } => summarize(build.command()?.run_workflow(name)?),
+        Command::Test {
+            build,
+            include,
+            exclude,
+        } => {
+            let mut ctest = build.command()?.ctest().output_on_failure(true);
+            if let Some(regex) = include {
+                ctest = ctest.include_regex(regex);
+            }
+            if let Some(regex) = exclude {
+                ctest = ctest.exclude_regex(regex);
+            }
+            if let Some(jobs) = build.jobs {
+                ctest = ctest.parallel(jobs);
+            }
+            let tests = ctest.run()?;
+            for test in tests.failed() {
+                println!("FAILED {}\n{}", test.name, test.output);
+            }
+            println!(
+                "{} passed, {} failed, {} skipped",
+                tests.passed().count(),
+                tests.failed().count(),
+                tests.skipped().count()
+            );
+            Ok(if tests.success() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            })
+        }
+        Command::Presets(PresetsCommand::List { path }) => {
+            let presets = CMakePresets::new(path)?;
+            for preset in presets.list_visible() {
+                match &preset.display_name {
+                    Some(display_name) => {
+                        println!("{:<10} {:<24} {}", preset.kind, preset.name, display_name)
+                    }
+                    None => println!("{:<10} {}", preset.kind, preset.name),
+                }
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Presets(PresetsCommand::Pin { version, path }) => {
+            let cmake = CMakeVersion::parse(&version).ok_or_else(|| {
+                Error::InvalidConfig(format!("{:?} is not a CMake version", version))
+            })?;
+            let presets = CMakePresets::new(path)?;
+            let report = presets.pin_versions(cmake)?;
+            for field in &report.unsupported {
+                eprintln!("{}", field);
+            }
+            for file in &report.rewritten {
+                println!(
+                    "pinned {} to version {}",
+                    file.display(),
+                    report.schema_version
+                );
+            }
+            Ok(if report.is_compatible() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            })
+        }
+    }
+}
+
+/// Prints the summary of a finished run.
+fn summarize(report: BuildReport) -> Result<ExitCode, Error> {
+    let colored = std::io::stderr().is_terminal();
+    eprintln!("{}", report.summary().colored(colored));
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_command_line() {
+        let cli = Cli::try_parse_from([
+            "cmakr",
+            "build",
+            "--path",
+            "./test/",
+            "--preset",
+            "default",
+            "-D",
+            "FOO=ON",
+            "--define",
+            "BAR:BOOL=OFF",
+            "--jobs",
+            "8",
+        ])
+        .unwrap();
+        let Command::Build(build) = cli.command else {
+            panic!("not a build command");
+        };
+        let cmd = build.command().unwrap();
+        assert_eq!(cmd.preset(), Some("default"));
+        assert_eq!(cmd.defines(), [("FOO", "ON"), ("BAR:BOOL", "OFF")]);
+
+        let cli = Cli::try_parse_from(["cmakr", "build", "-D", "FOO"]).unwrap();
+        let Command::Build(build) = cli.command else {
+            panic!("not a build command");
+        };
+        assert!(matches!(build.command(), Err(Error::InvalidDefine(_))));
+        assert!(Cli::try_parse_from(["cmakr", "install"]).is_err());
+    }
+}
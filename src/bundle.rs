@@ -0,0 +1,157 @@
+//! Diagnostic bundles for failed builds.
+//!
+//! With [`Cmd::write_failure_bundle`](crate::Cmd::write_failure_bundle) set,
+//! a failed run packs the files needed to diagnose it into a single tar
+//! archive, so CI can upload one artifact per failed build. The archive
+//! holds, under a `cmakr-failure/` directory:
+//!
+//! - `CMakeCache.txt`
+//! - `CMakeFiles/CMakeConfigureLog.yaml` (CMake 3.26 and later), or the older
+//!   `CMakeFiles/CMakeError.log` and `CMakeFiles/CMakeOutput.log`
+//! - `output-tail.txt`, the last lines of the failed step's output
+//!
+//! Files that don't exist are left out. Contents are redacted like all other
+//! output.
+
+use std::{
+    io::Write,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::credentials::Redactor;
+
+/// Build tree files copied into the bundle, relative to the build directory.
+const FILES: [&str; 4] = [
+    "CMakeCache.txt",
+    "CMakeFiles/CMakeConfigureLog.yaml",
+    "CMakeFiles/CMakeError.log",
+    "CMakeFiles/CMakeOutput.log",
+];
+
+/// How many trailing lines of output are kept.
+const OUTPUT_TAIL_LINES: usize = 200;
+
+/// The directory all entries are placed in.
+const ROOT: &str = "cmakr-failure";
+
+/// Size of a tar header and of the blocks entries are padded to.
+const BLOCK: usize = 512;
+
+/// Writes the bundle of a failed run of `binary_dir` to `path`.
+///
+/// `output` is the captured output of the failed step.
+pub(crate) fn write(
+    path: &Path,
+    binary_dir: &Path,
+    output: &str,
+    redactor: &Redactor,
+) -> std::io::Result<()> {
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+    for file in FILES {
+        if let Ok(content) = std::fs::read(binary_dir.join(file)) {
+            let content = redactor
+                .redact(&String::from_utf8_lossy(&content))
+                .into_owned();
+            entries.push((format!("{}/{}", ROOT, file), content.into_bytes()));
+        }
+    }
+    entries.push((
+        format!("{}/output-tail.txt", ROOT),
+        output_tail(output).into_bytes(),
+    ));
+
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut archive = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mtime = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    for (name, content) in &entries {
+        archive.write_all(&tar_header(name, content.len() as u64, mtime))?;
+        archive.write_all(content)?;
+        archive.write_all(&vec![0; padding(content.len())])?;
+    }
+    // an archive ends with two zero blocks
+    archive.write_all(&[0; 2 * BLOCK])?;
+    archive.flush()
+}
+
+/// Returns the last [`OUTPUT_TAIL_LINES`] lines of `output`.
+fn output_tail(output: &str) -> String {
+    let lines: Vec<&str> = output.lines().collect();
+    let start = lines.len().saturating_sub(OUTPUT_TAIL_LINES);
+    let mut tail = lines[start..].join("\n");
+    tail.push('\n');
+    tail
+}
+
+/// Returns the number of zero bytes that pad `len` bytes to a full block.
+fn padding(len: usize) -> usize {
+    (BLOCK - len % BLOCK) % BLOCK
+}
+
+/// Builds the ustar header of a regular file.
+///
+/// Names must fit the 100 byte name field, which holds for [`FILES`].
+fn tar_header(name: &str, size: u64, mtime: u64) -> [u8; BLOCK] {
+    let mut header = [0u8; BLOCK];
+    let mut field = |offset: usize, value: &[u8]| {
+        header[offset..offset + value.len()].copy_from_slice(value);
+    };
+    field(0, name.as_bytes());
+    field(100, b"0000644\0");
+    field(108, b"0000000\0");
+    field(116, b"0000000\0");
+    field(124, format!("{:011o}\0", size).as_bytes());
+    field(136, format!("{:011o}\0", mtime).as_bytes());
+    // the checksum is computed with its own field set to spaces
+    field(148, b"        ");
+    field(156, b"0");
+    field(257, b"ustar\0");
+    field(263, b"00");
+
+    let checksum: u32 = header.iter().map(|&byte| u32::from(byte)).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::TempBuildDir;
+
+    #[test]
+    fn bundle_failure_files_as_tar() {
+        let temp = TempBuildDir::new("bundle").unwrap();
+        let binary = temp.path().join("build");
+        std::fs::create_dir_all(binary.join("CMakeFiles")).unwrap();
+        std::fs::write(binary.join("CMakeCache.txt"), "TOKEN:STRING=ghp_secret\n").unwrap();
+        std::fs::write(binary.join("CMakeFiles/CMakeError.log"), "error\n").unwrap();
+
+        let output: String = (0..300).map(|i| format!("line {}\n", i)).collect();
+        let bundle = temp.path().join("ci/failure.tar");
+        write(&bundle, &binary, &output, &Redactor::default()).unwrap();
+
+        let archive = std::fs::read(&bundle).unwrap();
+        assert_eq!(archive.len() % BLOCK, 0);
+        assert_eq!(&archive[..28], b"cmakr-failure/CMakeCache.txt");
+        assert_eq!(&archive[257..263], b"ustar\0");
+        let text = String::from_utf8_lossy(&archive);
+        assert!(text.contains("TOKEN:STRING=***"));
+        assert!(text.contains("cmakr-failure/CMakeFiles/CMakeError.log"));
+        assert!(!text.contains("CMakeConfigureLog.yaml"));
+        assert!(text.contains("line 100\n") && !text.contains("line 99\n"));
+
+        // the stored checksum matches the header with the field blanked
+        let mut header = archive[..BLOCK].to_vec();
+        let stored = u32::from_str_radix(std::str::from_utf8(&header[148..154]).unwrap(), 8);
+        header[148..156].copy_from_slice(b"        ");
+        let sum: u32 = header.iter().map(|&byte| u32::from(byte)).sum();
+        assert_eq!(stored.unwrap(), sum);
+    }
+}
@@ -0,0 +1,288 @@
+//! Reading and editing `CMakeCache.txt`.
+//!
+//! [`CMakeCache`] loads the cache of a configured build tree into typed
+//! entries, as `cmake -LAH` lists them: name, type, value, help string and
+//! whether the entry is advanced. Entries can be changed and saved back, as
+//! `ccmake` does; the next configure of the tree picks up the new values.
+//! Lines that are not changed, including comments, are kept as they are.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, cache::CMakeCache};
+//!
+//! let mut cache = CMakeCache::load("./build").unwrap();
+//! println!("{:?}", cache.value("CMAKE_CXX_COMPILER"));
+//! for entry in cache.entries().iter().filter(|entry| !entry.advanced) {
+//!     println!("{}:{}={}", entry.name, entry.ty, entry.value);
+//! }
+//!
+//! cache.set("BUILD_TESTING", "OFF");
+//! cache.save().unwrap();
+//! Cmd::default()
+//!     .set_path("./my_project")
+//!     .set_binary_path("./build")
+//!     .configure()
+//!     .unwrap();
+//! ```
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::cmake;
+
+/// The file name of the cache in the build directory.
+const CACHE_FILE: &str = "CMakeCache.txt";
+
+/// The suffix of the entries that mark other entries as advanced.
+const ADVANCED_SUFFIX: &str = "-ADVANCED";
+
+/// An entry of the CMake cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub name: String,
+    /// The entry type (e.g. `"BOOL"`, `"STRING"` or `"INTERNAL"`).
+    pub ty: String,
+    pub value: String,
+    /// The help string of the entry, if any.
+    pub help: Option<String>,
+    /// Whether the entry is marked as advanced.
+    pub advanced: bool,
+}
+
+/// The `CMakeCache.txt` of a build tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CMakeCache {
+    path: PathBuf,
+    lines: Vec<String>,
+}
+
+impl CMakeCache {
+    /// Reads the cache of the configured build tree `binary_dir`.
+    ///
+    /// # Arguments
+    ///
+    /// * `binary_dir` - A build directory containing a `CMakeCache.txt`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the cache cannot be read.
+    pub fn load<P>(binary_dir: P) -> io::Result<CMakeCache>
+    where
+        P: AsRef<Path>,
+    {
+        let path = binary_dir.as_ref().join(CACHE_FILE);
+        let content = std::fs::read_to_string(&path)?;
+        Ok(CMakeCache {
+            path,
+            lines: content.lines().map(str::to_string).collect(),
+        })
+    }
+
+    /// Returns the path of the cache file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the entries in file order.
+    ///
+    /// The internal `NAME-ADVANCED` entries are not listed; they set the
+    /// [`advanced`](CacheEntry::advanced) flag of the entry they belong to.
+    pub fn entries(&self) -> Vec<CacheEntry> {
+        let advanced: Vec<String> = self
+            .lines
+            .iter()
+            .filter_map(|line| parse_line(line))
+            .filter(|entry| entry.value == "1")
+            .filter_map(|entry| Some(entry.name.strip_suffix(ADVANCED_SUFFIX)?.to_string()))
+            .collect();
+
+        let mut entries = Vec::new();
+        let mut help: Vec<&str> = Vec::new();
+        for line in &self.lines {
+            if let Some(comment) = line.strip_prefix("//") {
+                help.push(comment);
+                continue;
+            }
+            if let Some(entry) = parse_line(line)
+                && !entry.name.ends_with(ADVANCED_SUFFIX)
+            {
+                entries.push(CacheEntry {
+                    help: (!help.is_empty()).then(|| help.join("\n")),
+                    advanced: advanced.contains(&entry.name),
+                    name: entry.name,
+                    ty: entry.ty,
+                    value: entry.value,
+                });
+            }
+            help.clear();
+        }
+        entries
+    }
+
+    /// Returns the entry named `name`.
+    pub fn get(&self, name: &str) -> Option<CacheEntry> {
+        self.entries().into_iter().find(|entry| entry.name == name)
+    }
+
+    /// Returns the value of the entry named `name`.
+    pub fn value(&self, name: &str) -> Option<String> {
+        self.lines
+            .iter()
+            .filter_map(|line| parse_line(line))
+            .find(|entry| entry.name == name)
+            .map(|entry| entry.value)
+    }
+
+    /// Sets the value of the entry named `name`, keeping its type.
+    ///
+    /// A new entry is added as `UNINITIALIZED`, as `cmake -D NAME=VALUE`
+    /// does, and gets its type from the project on the next configure.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the entry.
+    /// * `value` - The new value.
+    pub fn set<T>(&mut self, name: &str, value: T)
+    where
+        T: Into<String>,
+    {
+        let value = value.into();
+        match self.position(name) {
+            Some(index) => {
+                let key = self.lines[index]
+                    .split_once('=')
+                    .map(|(key, _)| key.to_string())
+                    .unwrap_or_default();
+                self.lines[index] = format!("{}={}", key, value);
+            }
+            None => self
+                .lines
+                .push(format!("{}:UNINITIALIZED={}", quote_name(name), value)),
+        }
+    }
+
+    /// Sets the value and type of the entry named `name`, adding it if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the entry.
+    /// * `ty` - The entry type (e.g. `"BOOL"`, `"PATH"` or `"STRING"`).
+    /// * `value` - The new value.
+    pub fn set_typed<T>(&mut self, name: &str, ty: &str, value: T)
+    where
+        T: Into<String>,
+    {
+        let line = format!("{}:{}={}", quote_name(name), ty, value.into());
+        match self.position(name) {
+            Some(index) => self.lines[index] = line,
+            None => self.lines.push(line),
+        }
+    }
+
+    /// Removes the entry named `name` with its help string and advanced
+    /// flag. Returns whether it existed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.remove_line(&format!("{}{}", name, ADVANCED_SUFFIX));
+        self.remove_line(name)
+    }
+
+    /// Writes the cache back to [`path`](CMakeCache::path).
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the file cannot be written.
+    pub fn save(&self) -> io::Result<()> {
+        let mut content = self.lines.join("\n");
+        content.push('\n');
+        std::fs::write(&self.path, content)
+    }
+
+    /// Removes the line of the entry named `name` and the comments above it.
+    fn remove_line(&mut self, name: &str) -> bool {
+        let Some(index) = self.position(name) else {
+            return false;
+        };
+        let start = self.lines[..index]
+            .iter()
+            .rposition(|line| !line.starts_with("//"))
+            .map_or(0, |previous| previous + 1);
+        self.lines.drain(start..=index);
+        true
+    }
+
+    /// Returns the index of the line of the entry named `name`.
+    fn position(&self, name: &str) -> Option<usize> {
+        self.lines
+            .iter()
+            .position(|line| parse_line(line).is_some_and(|entry| entry.name == name))
+    }
+}
+
+/// Parses a single `NAME:TYPE=VALUE` line.
+fn parse_line(line: &str) -> Option<cmake::CacheEntry> {
+    cmake::parse_cache(line).pop()
+}
+
+/// Quotes a name that CMake would otherwise split at a colon or `=`.
+fn quote_name(name: &str) -> String {
+    if name.contains([':', '=']) {
+        format!("\"{}\"", name)
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_cache_entries() {
+        let temp = crate::guard::TempBuildDir::new("cache").unwrap();
+        std::fs::write(
+            temp.path().join(CACHE_FILE),
+            "# This is the CMakeCache file.\n\n\
+             //Build the testing tree.\nBUILD_TESTING:BOOL=ON\n\n\
+             //CXX compiler\n//(full path)\nCMAKE_CXX_COMPILER:FILEPATH=/usr/bin/c++\n\n\
+             //ADVANCED property for variable: CMAKE_CXX_COMPILER\n\
+             CMAKE_CXX_COMPILER-ADVANCED:INTERNAL=1\n",
+        )
+        .unwrap();
+
+        let mut cache = CMakeCache::load(temp.path()).unwrap();
+        assert_eq!(cache.entries().len(), 2);
+        let compiler = cache.get("CMAKE_CXX_COMPILER").unwrap();
+        assert_eq!(compiler.ty, "FILEPATH");
+        assert_eq!(compiler.help.as_deref(), Some("CXX compiler\n(full path)"));
+        assert!(compiler.advanced);
+        assert!(!cache.get("BUILD_TESTING").unwrap().advanced);
+        assert_eq!(
+            cache.value("CMAKE_CXX_COMPILER").as_deref(),
+            Some("/usr/bin/c++")
+        );
+
+        cache.set("BUILD_TESTING", "OFF");
+        cache.set("WITH:COLON", "x=y");
+        cache.set_typed("CMAKE_BUILD_TYPE", "STRING", "Release");
+        assert!(cache.remove("CMAKE_CXX_COMPILER"));
+        assert!(!cache.remove("CMAKE_CXX_COMPILER"));
+        cache.save().unwrap();
+
+        let content = std::fs::read_to_string(cache.path()).unwrap();
+        assert!(content.starts_with("# This is the CMakeCache file.\n"));
+        assert!(content.contains("//Build the testing tree.\nBUILD_TESTING:BOOL=OFF\n"));
+        assert!(!content.contains("CXX compiler"));
+        assert!(!content.contains("CMAKE_CXX_COMPILER"));
+        let cache = CMakeCache::load(temp.path()).unwrap();
+        let entry = cache.get("WITH:COLON").unwrap();
+        assert_eq!(
+            (entry.ty.as_str(), entry.value.as_str()),
+            ("UNINITIALIZED", "x=y")
+        );
+        assert_eq!(cache.value("CMAKE_BUILD_TYPE").as_deref(), Some("Release"));
+        assert!(CMakeCache::load(temp.path().join("missing")).is_err());
+    }
+}
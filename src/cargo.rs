@@ -0,0 +1,1162 @@
+//! Link directives for Cargo build scripts.
+//!
+//! Library names in `build.rs` files are easy to get wrong: the file CMake
+//! produces for a target depends on the platform (`libfoo.a` vs `foo.lib`),
+//! on `OUTPUT_NAME` and `<CONFIG>_POSTFIX` (e.g. a `d` suffix in debug
+//! builds), and on `VERSION` (`libfoo.so.1.2.3`). [`CargoBuild`] runs the
+//! build, looks up the actual output files of the requested targets through
+//! the CMake file API, and prints the matching `cargo::rustc-link-search` and
+//! `cargo::rustc-link-lib` lines, followed by `cargo::rerun-if-changed` lines
+//! for the project's CMake files and the sources of its targets. When Cargo
+//! builds for another target than the host, the build
+//! [cross-compiles](crate::Cmd::cross_compile_for_cargo) for it. If the
+//! package has a `links` key, the files of every installed component are
+//! passed on to dependent build scripts as `DEP_<LINKS>_COMPONENT_<NAME>`.
+//!
+//! This module requires the `cargo` feature, enabled by default.
+//!
+//! # Example
+//!
+//! ```no_run
+//! // build.rs
+//! use cmakr::{
+//!     Cmd,
+//!     cargo::{CargoBuild, LinkKind},
+//! };
+//!
+//! let out_dir = std::env::var("OUT_DIR").unwrap();
+//! CargoBuild::from(Cmd::default().set_binary_path(&format!("{}/cmake-build", out_dir)))
+//!     .link_target("foo_static", LinkKind::Static)
+//!     .emit()
+//!     .unwrap();
+//! ```
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    Cmd, cmake,
+    error::Error,
+    fileapi::{self, Target},
+    fingerprint,
+    graph::{BuildGraph, TargetKind},
+    report::BuildReport,
+};
+
+/// How a target's library is linked into the Rust crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    /// A static library (`STATIC_LIBRARY` target).
+    Static,
+    /// A shared library (`SHARED_LIBRARY` target).
+    Dylib,
+    /// An object library (`OBJECT_LIBRARY` target), whose object files are
+    /// archived into a static library in the output directory and linked
+    /// from there.
+    Object,
+}
+
+impl fmt::Display for LinkKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkKind::Static | LinkKind::Object => write!(f, "static"),
+            LinkKind::Dylib => write!(f, "dylib"),
+        }
+    }
+}
+
+/// The C++ standard library linked along with static C++ targets.
+///
+/// A static library built from C++ sources references the C++ runtime, which
+/// rustc does not link on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CxxRuntime {
+    /// Picks the platform's default: nothing on MSVC, `c++` (LLVM libc++) on
+    /// Apple platforms, FreeBSD, OpenBSD, or when `CMAKE_CXX_FLAGS` contains
+    /// `-stdlib=libc++`, `c++_shared` on Android, and `stdc++` otherwise.
+    #[default]
+    Auto,
+    /// GNU libstdc++ (`stdc++`).
+    LibStdCpp,
+    /// LLVM libc++ (`c++`).
+    LibCpp,
+    /// GNU libstdc++ linked statically, located with the C++ compiler's
+    /// `-print-file-name=libstdc++.a`.
+    Static,
+}
+
+/// A [`Cmd`] whose build is followed by Cargo link directives.
+pub struct CargoBuild {
+    cmd: Cmd,
+    /// Targets to link, in the order they are passed to rustc.
+    links: Vec<(String, LinkKind)>,
+    /// Static library targets linked in full rather than by referenced objects.
+    whole_archive: Vec<String>,
+    /// Whether system libraries needed by static targets are linked.
+    system_libs: bool,
+    /// C++ standard library linked along with static C++ targets.
+    cxx_runtime: CxxRuntime,
+    /// Directory the runtime files of linked shared libraries are copied to.
+    runtime_dir: Option<PathBuf>,
+    /// Whether the build script reruns only when the project's files change.
+    rerun_if_changed: bool,
+}
+
+impl From<Cmd> for CargoBuild {
+    fn from(cmd: Cmd) -> Self {
+        Self {
+            cmd: cmd.cross_compile_for_cargo(),
+            links: Vec::new(),
+            whole_archive: Vec::new(),
+            system_libs: true,
+            cxx_runtime: CxxRuntime::Auto,
+            runtime_dir: None,
+            rerun_if_changed: true,
+        }
+    }
+}
+
+impl CargoBuild {
+    /// Links the library produced by a CMake target.
+    ///
+    /// The library name passed to rustc is derived from the file the target
+    /// actually produces, so output names, debug postfixes, and version
+    /// suffixes don't have to be repeated in `build.rs`. Shared libraries on
+    /// Windows are linked through their import library (`foo.lib` or
+    /// `libfoo.dll.a`), wherever CMake placed it.
+    ///
+    /// A static library is linked along with every library it depends on
+    /// through other static libraries, dependents before dependencies, since
+    /// an archive does not carry its own dependencies.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The CMake target name (e.g. `"foo_static"`).
+    /// * `kind` - Whether the target is a static or a shared library.
+    pub fn link_target<T>(mut self, target: T, kind: LinkKind) -> Self
+    where
+        T: Into<String>,
+    {
+        self.links.push((target.into(), kind));
+        self
+    }
+
+    /// Links every object of a static library target, not only the ones that
+    /// resolve a referenced symbol.
+    ///
+    /// Libraries that register plugins or factories from static initializers
+    /// are never referenced directly, so the linker would otherwise drop those
+    /// objects. When enabled, the target is passed to rustc with the
+    /// `+whole-archive` modifier (`cargo::rustc-link-lib=static:+whole-archive=foo`),
+    /// which makes the linker keep all of its objects. Shared library targets
+    /// are not affected.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The CMake target name, as passed to [`link_target`](CargoBuild::link_target).
+    /// * `enabled` - Whether to link the whole archive.
+    pub fn whole_archive<T>(mut self, target: T, enabled: bool) -> Self
+    where
+        T: Into<String>,
+    {
+        let target = target.into();
+        self.whole_archive.retain(|name| *name != target);
+        if enabled {
+            self.whole_archive.push(target);
+        }
+        self
+    }
+
+    /// Links the system libraries that linked static library targets depend on.
+    ///
+    /// A static archive does not record the libraries it needs, so symbols
+    /// from e.g. `pthread`, `m`, `dl`, or `ws2_32` stay unresolved unless they
+    /// are passed to rustc as well. The libraries are taken from the project's
+    /// target graph (`cmake --graphviz`), following the dependencies of each
+    /// static target through other static and interface libraries; static
+    /// targets have no link step in the CMake file API to read them from.
+    /// Enabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to link the system libraries.
+    pub fn link_system_libs(mut self, enabled: bool) -> Self {
+        self.system_libs = enabled;
+        self
+    }
+
+    /// Sets the C++ standard library linked when a linked static library
+    /// target has C++ sources.
+    ///
+    /// Whether a target uses C++ is read from the CMake file API. Shared
+    /// libraries already carry their runtime dependency and need nothing.
+    /// Defaults to [`CxxRuntime::Auto`].
+    ///
+    /// # Arguments
+    ///
+    /// * `runtime` - The C++ standard library to link.
+    pub fn cxx_runtime(mut self, runtime: CxxRuntime) -> Self {
+        self.cxx_runtime = runtime;
+        self
+    }
+
+    /// Copies the runtime files of the linked shared libraries (`foo.dll`,
+    /// `libfoo.so.1.2.3`, ...) into `dir` after the build.
+    ///
+    /// Windows only finds DLLs next to the executable or on `PATH`, so
+    /// binaries linking a CMake-built DLL usually need it copied next to them.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory to copy to. Created if it does not exist.
+    pub fn copy_runtime_to<T>(mut self, dir: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.runtime_dir = Some(PathBuf::from(dir.into()));
+        self
+    }
+
+    /// Prints a `cargo::rerun-if-changed` line for every CMake file of the
+    /// project (`CMakeLists.txt`, `*.cmake`, and the presets files) and
+    /// every source of its targets, as listed by the CMake file API.
+    /// Sources of a read-only tree built from a copy are watched in the
+    /// original tree, and [path remaps](Cmd::path_remap) apply to the files.
+    ///
+    /// Cargo then reruns the build script when one of them changes, instead
+    /// of on any change in the package. Enabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to print the lines.
+    pub fn rerun_if_changed(mut self, enabled: bool) -> Self {
+        self.rerun_if_changed = enabled;
+        self
+    }
+
+    /// Runs the build and prints the link directives to stdout.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the build fails, if CMake is too old for the file
+    /// API (before 3.14), if a target does not exist, is not a library of the
+    /// requested kind, or has no linkable output, or if copying runtime files
+    /// fails.
+    pub fn emit(self) -> Result<BuildReport, Error> {
+        let output_dir = self.cmd.archive_output_path().to_path_buf();
+        let report = self.cmd.build()?;
+        let Some(mut targets) = fileapi::targets(&report.binary_dir)? else {
+            return Err(Error::Unsupported(
+                "linking targets requires the CMake file API (CMake 3.14 or later)".to_string(),
+            ));
+        };
+
+        // object libraries are linked through an archive of their objects
+        for (name, kind) in &self.links {
+            let target = targets.iter_mut().find(|target| &target.name == name);
+            if let Some(target) =
+                target.filter(|target| *kind == LinkKind::Object && target.ty == "OBJECT_LIBRARY")
+            {
+                let archive = archive_objects(target, &report.binary_dir, &output_dir)?;
+                target.artifacts = vec![archive];
+            }
+        }
+
+        let resolved =
+            resolve(&self.links, &self.whole_archive, &targets).map_err(Error::InvalidConfig)?;
+        if let Some(dir) = &self.runtime_dir {
+            std::fs::create_dir_all(dir)?;
+            for runtime in resolved.iter().filter_map(|link| link.runtime.as_ref()) {
+                if let Some(file_name) = runtime.file_name() {
+                    std::fs::copy(runtime, dir.join(file_name))?;
+                }
+            }
+        }
+
+        let mut lines = link_lines(&resolved);
+        let static_targets: Vec<&str> = self
+            .links
+            .iter()
+            .filter(|(_, kind)| *kind != LinkKind::Dylib)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        if self.system_libs && !static_targets.is_empty() {
+            let graph = BuildGraph::load(&report.binary_dir)?;
+            lines.extend(system_lib_lines(&graph, &static_targets));
+        }
+        if resolved.iter().any(|link| link.uses_cxx) {
+            lines.extend(cxx_runtime_lines(self.cxx_runtime, &report.binary_dir)?);
+        }
+        if self.rerun_if_changed {
+            lines.extend(
+                watched_files(&report, &targets)?
+                    .iter()
+                    .map(|file| format!("cargo::rerun-if-changed={}", file.display())),
+            );
+        }
+        // only crates with a `links` key may pass metadata on to dependents
+        if std::env::var_os("CARGO_MANIFEST_LINKS").is_some() {
+            lines.extend(component_metadata_lines(&report.installed_components));
+        }
+        for line in lines {
+            println!("{}", line);
+        }
+        Ok(report)
+    }
+}
+
+/// Returns the files whose changes make the build script rerun: the CMake
+/// files of the project and the sources of its `targets`, with the
+/// [path remaps](BuildReport::path_remaps) of `report` applied.
+///
+/// Files are listed where the build sees them, so that the sources of a
+/// read-only tree built from a copy are found in the copy, and reported as
+/// the originals. Files generated in the build directory are left out.
+fn watched_files(report: &BuildReport, targets: &[Target]) -> std::io::Result<Vec<PathBuf>> {
+    // the source directory as seen by the build, before it was remapped
+    let source_dir = report
+        .path_remaps
+        .iter()
+        .find_map(|(from, to)| {
+            report
+                .source_dir
+                .strip_prefix(to)
+                .ok()
+                .map(|rest| from.join(rest))
+        })
+        .unwrap_or_else(|| report.source_dir.clone());
+    let mut files: Vec<PathBuf> = fingerprint::project_files(&source_dir, &report.binary_dir)?
+        .iter()
+        .chain(targets.iter().flat_map(|target| &target.sources))
+        .map(|file| report.remap_path(file))
+        .filter(|file| !file.starts_with(&report.binary_dir))
+        .collect();
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// A requested link, resolved to the files of its target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ResolvedLink {
+    /// The directory containing the file to link against.
+    search_dir: PathBuf,
+    /// The library name passed to rustc.
+    name: String,
+    kind: LinkKind,
+    /// Whether all objects of a static library are linked.
+    whole_archive: bool,
+    /// Whether a static library has C++ sources and needs the C++ runtime.
+    uses_cxx: bool,
+    /// The file loaded at runtime, for shared libraries.
+    runtime: Option<PathBuf>,
+}
+
+/// Resolves the requested links against the targets of the codemodel.
+///
+/// Static libraries are expanded to their transitive link closure in
+/// [`link_order`], followed by the requested shared libraries and the shared
+/// libraries the static ones depend on. Every library appears once, except
+/// that the members of a dependency cycle are repeated once after each other,
+/// so single-pass linkers resolve references in both directions.
+fn resolve(
+    links: &[(String, LinkKind)],
+    whole_archive: &[String],
+    targets: &[Target],
+) -> Result<Vec<ResolvedLink>, String> {
+    let mut roots = Vec::new();
+    let mut shared = Vec::new();
+    for (name, kind) in links {
+        let Some(index) = targets.iter().position(|target| &target.name == name) else {
+            return Err(format!("CMake target {} not found", name));
+        };
+        let expected = match kind {
+            LinkKind::Static => "STATIC_LIBRARY",
+            LinkKind::Dylib => "SHARED_LIBRARY",
+            LinkKind::Object => "OBJECT_LIBRARY",
+        };
+        if targets[index].ty != expected {
+            return Err(format!(
+                "CMake target {} is a {}, not a {}",
+                name, targets[index].ty, expected
+            ));
+        }
+        match kind {
+            LinkKind::Static | LinkKind::Object => roots.push(index),
+            LinkKind::Dylib => shared.push(index),
+        }
+    }
+
+    let mut order = Vec::new();
+    for component in link_order(&roots, targets) {
+        let (libraries, dependencies): (Vec<usize>, Vec<usize>) = component
+            .into_iter()
+            .partition(|&index| targets[index].ty != "SHARED_LIBRARY");
+        for index in dependencies {
+            if !shared.contains(&index) {
+                shared.push(index);
+            }
+        }
+        let repeat = libraries.len() > 1;
+        order.extend(libraries.iter().map(|&index| (index, LinkKind::Static)));
+        if repeat {
+            order.extend(libraries.iter().map(|&index| (index, LinkKind::Static)));
+        }
+    }
+    order.extend(shared.into_iter().map(|index| (index, LinkKind::Dylib)));
+
+    order
+        .into_iter()
+        .map(|(index, kind)| resolve_target(&targets[index], kind, whole_archive))
+        .collect()
+}
+
+/// Resolves a single library target to the file to link against.
+fn resolve_target(
+    target: &Target,
+    kind: LinkKind,
+    whole_archive: &[String],
+) -> Result<ResolvedLink, String> {
+    let (link_file, runtime) = match kind {
+        LinkKind::Static | LinkKind::Object => {
+            let is_library = |file: &&PathBuf| {
+                file.file_name()
+                    .is_some_and(|name| link_name(&name.to_string_lossy(), kind).is_some())
+            };
+            (target.artifacts.iter().find(is_library).cloned(), None)
+        }
+        LinkKind::Dylib => match target.shared_library() {
+            Some(library) => (
+                Some(library.link_file().to_path_buf()),
+                Some(library.runtime),
+            ),
+            None => (None, None),
+        },
+    };
+    let Some((search_dir, lib)) = link_file.and_then(|file| {
+        let file_name = file.file_name()?.to_string_lossy();
+        Some((file.parent()?.to_path_buf(), link_name(&file_name, kind)?))
+    }) else {
+        return Err(format!(
+            "CMake target {} has no linkable output",
+            target.name
+        ));
+    };
+
+    Ok(ResolvedLink {
+        search_dir,
+        name: lib,
+        kind,
+        whole_archive: kind == LinkKind::Static && whole_archive.contains(&target.name),
+        uses_cxx: kind == LinkKind::Static && target.languages.iter().any(|l| l == "CXX"),
+        runtime,
+    })
+}
+
+/// Orders the static library `roots` and the libraries they depend on for
+/// linking, as strongly connected components of the dependency graph.
+///
+/// Dependencies are followed through static libraries only, since shared
+/// libraries resolve their own. Components come before the components they
+/// depend on, and roots keep their relative order where dependencies allow.
+fn link_order(roots: &[usize], targets: &[Target]) -> Vec<Vec<usize>> {
+    struct Tarjan<'a> {
+        targets: &'a [Target],
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next: usize,
+        components: Vec<Vec<usize>>,
+    }
+
+    impl Tarjan<'_> {
+        fn edges(&self, node: usize) -> Vec<usize> {
+            let target = &self.targets[node];
+            if !matches!(target.ty.as_str(), "STATIC_LIBRARY" | "OBJECT_LIBRARY") {
+                return Vec::new();
+            }
+            target
+                .dependencies
+                .iter()
+                .filter_map(|id| self.targets.iter().position(|t| &t.id == id))
+                .filter(|&i| {
+                    matches!(
+                        self.targets[i].ty.as_str(),
+                        "STATIC_LIBRARY" | "SHARED_LIBRARY"
+                    )
+                })
+                .collect()
+        }
+
+        fn visit(&mut self, node: usize) {
+            self.index[node] = Some(self.next);
+            self.low[node] = self.next;
+            self.next += 1;
+            self.stack.push(node);
+            self.on_stack[node] = true;
+
+            for next in self.edges(node) {
+                match self.index[next] {
+                    None => {
+                        self.visit(next);
+                        self.low[node] = self.low[node].min(self.low[next]);
+                    }
+                    Some(index) if self.on_stack[next] => {
+                        self.low[node] = self.low[node].min(index);
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            if Some(self.low[node]) == self.index[node] {
+                let mut component = Vec::new();
+                while let Some(member) = self.stack.pop() {
+                    self.on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                component.sort();
+                self.components.push(component);
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        targets,
+        index: vec![None; targets.len()],
+        low: vec![0; targets.len()],
+        on_stack: vec![false; targets.len()],
+        stack: Vec::new(),
+        next: 0,
+        components: Vec::new(),
+    };
+    // components are completed dependencies first, so the reversed result of
+    // visiting the roots backwards lists dependents first in root order
+    for &root in roots.iter().rev() {
+        if tarjan.index[root].is_none() {
+            tarjan.visit(root);
+        }
+    }
+    tarjan.components.reverse();
+    tarjan.components
+}
+
+/// Renders resolved links as `cargo::` directives, search paths first.
+fn link_lines(resolved: &[ResolvedLink]) -> Vec<String> {
+    let mut search_dirs: Vec<&PathBuf> = Vec::new();
+    for link in resolved {
+        if !search_dirs.contains(&&link.search_dir) {
+            search_dirs.push(&link.search_dir);
+        }
+    }
+
+    let mut lines: Vec<String> = search_dirs
+        .iter()
+        .map(|dir| format!("cargo::rustc-link-search=native={}", dir.display()))
+        .collect();
+    lines.extend(resolved.iter().map(|link| {
+        let modifiers = if link.whole_archive {
+            ":+whole-archive"
+        } else {
+            ""
+        };
+        format!(
+            "cargo::rustc-link-lib={}{}={}",
+            link.kind, modifiers, link.name
+        )
+    }));
+    lines
+}
+
+/// Archives the object files of an object library target into a static
+/// library in `output_dir`, returning the path of the archive.
+///
+/// The archiver is the build tree's `CMAKE_AR`: `lib.exe`-style archivers
+/// (MSVC, `llvm-lib`) produce `<name>.lib`, all others `lib<name>.a`.
+fn archive_objects(
+    target: &Target,
+    binary_dir: &Path,
+    output_dir: &Path,
+) -> Result<PathBuf, Error> {
+    let objects: Vec<&PathBuf> = target
+        .artifacts
+        .iter()
+        .filter(|artifact| {
+            let name = artifact.to_string_lossy();
+            name.ends_with(".o") || name.ends_with(".obj")
+        })
+        .collect();
+    if objects.is_empty() {
+        return Err(Error::InvalidConfig(format!(
+            "object library {} has no object files",
+            target.name
+        )));
+    }
+
+    let archiver = cmake::read_cache_value(binary_dir, "CMAKE_AR")
+        .filter(|ar| !ar.is_empty())
+        .unwrap_or_else(|| "ar".to_string());
+    let archiver_name = Path::new(&archiver)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let msvc = archiver_name == "lib" || archiver_name == "llvm-lib";
+
+    std::fs::create_dir_all(output_dir)?;
+    let archive = match msvc {
+        true => output_dir.join(format!("{}.lib", target.name)),
+        false => output_dir.join(format!("lib{}.a", target.name)),
+    };
+    // an archiver adds to an existing archive instead of replacing its members
+    if archive.exists() {
+        std::fs::remove_file(&archive)?;
+    }
+
+    let mut command = std::process::Command::new(&archiver);
+    match msvc {
+        true => command
+            .arg("/NOLOGO")
+            .arg(format!("/OUT:{}", archive.display())),
+        false => command.arg("rcs").arg(&archive),
+    };
+    let output = command.args(&objects).output()?;
+    if !output.status.success() {
+        return Err(Error::ToolFailed(format!(
+            "archiving object library {} failed: {}",
+            target.name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(archive)
+}
+
+/// Returns the directives linking the external libraries that the static
+/// `targets` depend on, directly or through other static or interface
+/// libraries of the graph.
+///
+/// Shared libraries and executables are not followed, since they resolve
+/// their own dependencies.
+fn system_lib_lines(graph: &BuildGraph, targets: &[&str]) -> Vec<String> {
+    let mut visited: Vec<usize> = targets
+        .iter()
+        .filter_map(|name| graph.index_of(name))
+        .collect();
+    let mut lines = Vec::new();
+    let mut next = 0;
+    while next < visited.len() {
+        let index = visited[next];
+        next += 1;
+
+        match graph.targets[index].kind {
+            TargetKind::External => {
+                for line in external_lib_lines(&graph.targets[index].name) {
+                    if !lines.contains(&line) {
+                        lines.push(line);
+                    }
+                }
+                continue;
+            }
+            TargetKind::StaticLibrary
+            | TargetKind::InterfaceLibrary
+            | TargetKind::ObjectLibrary => {}
+            _ => continue,
+        }
+        for dependency in &graph.dependencies {
+            if dependency.from == index && !visited.contains(&dependency.to) {
+                visited.push(dependency.to);
+            }
+        }
+    }
+    lines
+}
+
+/// Translates an external link item of the target graph (`m`, `-lpthread`,
+/// `-pthread`, `ws2_32.lib`, `/usr/lib/libz.so`, `-framework Cocoa`) into
+/// `cargo::` directives. Linker flags and unresolved imported targets yield
+/// nothing.
+fn external_lib_lines(item: &str) -> Vec<String> {
+    let item = item.trim();
+    if item == "-pthread" {
+        return vec!["cargo::rustc-link-lib=pthread".to_string()];
+    }
+    if let Some(framework) = item.strip_prefix("-framework") {
+        return vec![format!(
+            "cargo::rustc-link-lib=framework={}",
+            framework.trim()
+        )];
+    }
+    if let Some(name) = item.strip_prefix("-l") {
+        return vec![format!("cargo::rustc-link-lib={}", name)];
+    }
+    if item.starts_with('-') || item.contains("::") || item.is_empty() {
+        return Vec::new();
+    }
+
+    let path = Path::new(item);
+    if path.is_absolute() {
+        let Some(file_name) = path.file_name().map(|name| name.to_string_lossy()) else {
+            return Vec::new();
+        };
+        let Some(dir) = path.parent() else {
+            return Vec::new();
+        };
+        let (kind, name) = match link_name(&file_name, LinkKind::Static) {
+            Some(name) if !file_name.ends_with(".lib") => ("static=", name),
+            _ => match link_name(&file_name, LinkKind::Dylib) {
+                Some(name) => ("", name),
+                None => return Vec::new(),
+            },
+        };
+        return vec![
+            format!("cargo::rustc-link-search=native={}", dir.display()),
+            format!("cargo::rustc-link-lib={}{}", kind, name),
+        ];
+    }
+
+    let name = item
+        .strip_suffix(".lib")
+        .or_else(|| item.strip_suffix(".LIB"))
+        .unwrap_or(item);
+    vec![format!("cargo::rustc-link-lib={}", name)]
+}
+
+/// Renders the files of installed components as `cargo::metadata` lines,
+/// which dependents read as `DEP_<LINKS>_COMPONENT_<NAME>`, a list joined
+/// like `PATH`.
+fn component_metadata_lines(components: &[(String, Vec<PathBuf>)]) -> Vec<String> {
+    components
+        .iter()
+        .filter_map(|(name, files)| {
+            let files = std::env::join_paths(files).ok()?;
+            Some(format!(
+                "cargo::metadata=component_{}={}",
+                name,
+                files.to_string_lossy()
+            ))
+        })
+        .collect()
+}
+
+/// Returns the directives linking the C++ standard library `runtime`.
+fn cxx_runtime_lines(runtime: CxxRuntime, binary_dir: &Path) -> Result<Vec<String>, Error> {
+    // build scripts describe the crate's target, not the host they run on
+    let target_os =
+        std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_else(|_| std::env::consts::OS.to_string());
+    let target_env = std::env::var("CARGO_CFG_TARGET_ENV").unwrap_or_default();
+
+    if runtime != CxxRuntime::Static {
+        let cxx_flags = cmake::read_cache_value(binary_dir, "CMAKE_CXX_FLAGS").unwrap_or_default();
+        return Ok(
+            cxx_runtime_lib(runtime, &target_os, &target_env, &cxx_flags)
+                .map(|lib| vec![format!("cargo::rustc-link-lib={}", lib)])
+                .unwrap_or_default(),
+        );
+    }
+
+    let compiler = cmake::read_cache_value(binary_dir, "CMAKE_CXX_COMPILER").ok_or_else(|| {
+        Error::Unsupported("CMAKE_CXX_COMPILER is not set in the build tree".to_string())
+    })?;
+    let output = std::process::Command::new(compiler)
+        .arg("-print-file-name=libstdc++.a")
+        .output()?;
+    let archive = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    // compilers echo the bare name back when the file is not found
+    let Some(dir) = archive
+        .parent()
+        .filter(|dir| archive.is_absolute() && !dir.as_os_str().is_empty())
+    else {
+        return Err(Error::ToolFailed(
+            "libstdc++.a not found by the C++ compiler".to_string(),
+        ));
+    };
+    Ok(vec![
+        format!("cargo::rustc-link-search=native={}", dir.display()),
+        "cargo::rustc-link-lib=static=stdc++".to_string(),
+    ])
+}
+
+/// Returns the dynamically linked C++ standard library for a target
+/// platform, or `None` if the toolchain links it implicitly (MSVC).
+fn cxx_runtime_lib(
+    runtime: CxxRuntime,
+    target_os: &str,
+    target_env: &str,
+    cxx_flags: &str,
+) -> Option<&'static str> {
+    match runtime {
+        CxxRuntime::LibStdCpp | CxxRuntime::Static => Some("stdc++"),
+        CxxRuntime::LibCpp => Some("c++"),
+        CxxRuntime::Auto => match (target_os, target_env) {
+            (_, "msvc") => None,
+            ("android", _) => Some("c++_shared"),
+            ("macos" | "ios" | "tvos" | "watchos" | "visionos" | "freebsd" | "openbsd", _) => {
+                Some("c++")
+            }
+            _ if cxx_flags.contains("-stdlib=libc++") => Some("c++"),
+            _ => Some("stdc++"),
+        },
+    }
+}
+
+/// Derives the name rustc links a library file by, or `None` if the file is
+/// not a library of that kind.
+///
+/// `lib` prefixes are only part of the file name outside of MSVC-style
+/// `.lib` files. Versioned shared libraries (`libfoo.so.1.2.3`,
+/// `libfoo.1.2.3.dylib`) are linked through the unversioned name CMake
+/// creates next to them.
+fn link_name(file_name: &str, kind: LinkKind) -> Option<String> {
+    let is_version =
+        |s: &str| !s.is_empty() && s.split('.').all(|part| part.parse::<u32>().is_ok());
+
+    let (stem, msvc) = match kind {
+        LinkKind::Static | LinkKind::Object => match file_name.strip_suffix(".lib") {
+            Some(stem) => (stem, true),
+            None => (file_name.strip_suffix(".a")?, false),
+        },
+        LinkKind::Dylib => {
+            if let Some(stem) = file_name.strip_suffix(".lib") {
+                (stem, true)
+            } else if let Some(stem) = file_name.strip_suffix(".dll.a") {
+                (stem, false)
+            } else if let Some(stem) = file_name.strip_suffix(".dylib") {
+                let stem = match stem.split_once('.') {
+                    Some((base, version)) if is_version(version) => base,
+                    _ => stem,
+                };
+                (stem, false)
+            } else {
+                let (stem, version) = file_name.rsplit_once(".so")?;
+                if !version.is_empty() && !is_version(version.strip_prefix('.')?) {
+                    return None;
+                }
+                (stem, false)
+            }
+        }
+    };
+
+    let name = match msvc {
+        true => stem,
+        false => stem.strip_prefix("lib").unwrap_or(stem),
+    };
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::TempBuildDir;
+
+    fn library(name: &str, ty: &str, artifacts: &[&str], dependencies: &[&str]) -> Target {
+        Target {
+            id: format!("{}::@1", name),
+            name: name.to_string(),
+            ty: ty.to_string(),
+            directory: PathBuf::new(),
+            artifacts: artifacts.iter().map(PathBuf::from).collect(),
+            languages: Vec::new(),
+            dependencies: dependencies.iter().map(|d| format!("{}::@1", d)).collect(),
+            link_libraries: Vec::new(),
+            compile_groups: Vec::new(),
+            sources: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn watch_original_sources_of_a_copy() {
+        let temp = TempBuildDir::new("cargo-watch").unwrap();
+        let source = temp.path().join("src");
+        let binary = temp.path().join("build");
+        let copy = binary.join("cmakr-source");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::create_dir_all(copy.join("lib")).unwrap();
+        std::fs::write(copy.join("CMakeLists.txt"), "").unwrap();
+        std::fs::write(copy.join("lib/config.cmake"), "").unwrap();
+        let report = BuildReport {
+            source_dir: source.clone(),
+            binary_dir: binary.clone(),
+            path_remaps: vec![
+                (copy.join("lib"), PathBuf::from("/workspace/lib")),
+                (copy.clone(), source.clone()),
+            ],
+            ..Default::default()
+        };
+        let mut target = library("foo", "STATIC_LIBRARY", &[], &[]);
+        target.sources = vec![copy.join("foo.c"), binary.join("generated.c")];
+
+        assert_eq!(
+            watched_files(&report, &[target]).unwrap(),
+            [
+                source.join("CMakeLists.txt"),
+                source.join("foo.c"),
+                PathBuf::from("/workspace/lib/config.cmake"),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_link_names() {
+        assert_eq!(
+            link_name("libfoo_static.a", LinkKind::Static).as_deref(),
+            Some("foo_static")
+        );
+        assert_eq!(
+            link_name("foo_staticd.lib", LinkKind::Static).as_deref(),
+            Some("foo_staticd")
+        );
+        assert_eq!(
+            link_name("libfoo.so.1.2.3", LinkKind::Dylib).as_deref(),
+            Some("foo")
+        );
+        assert_eq!(
+            link_name("libfoo.1.2.dylib", LinkKind::Dylib).as_deref(),
+            Some("foo")
+        );
+        assert_eq!(
+            link_name("libfoo.dll.a", LinkKind::Dylib).as_deref(),
+            Some("foo")
+        );
+        assert_eq!(link_name("foo.dll", LinkKind::Dylib), None);
+        assert_eq!(link_name("libfoo.so", LinkKind::Static), None);
+
+        let targets = vec![
+            library("foo", "SHARED_LIBRARY", &["/out/libfoo.so.1.2.3"], &[]),
+            library(
+                "bar",
+                "SHARED_LIBRARY",
+                &[
+                    "C:/out/bin/bar.dll",
+                    "C:/out/lib/bar.lib",
+                    "C:/out/bin/bar.pdb",
+                ],
+                &[],
+            ),
+        ];
+        let links = vec![
+            ("foo".to_string(), LinkKind::Dylib),
+            ("bar".to_string(), LinkKind::Dylib),
+        ];
+        let resolved = resolve(&links, &[], &targets).unwrap();
+        assert_eq!(
+            resolved[1].runtime,
+            Some(PathBuf::from("C:/out/bin/bar.dll"))
+        );
+        assert_eq!(
+            link_lines(&resolved),
+            vec![
+                "cargo::rustc-link-search=native=/out",
+                "cargo::rustc-link-search=native=C:/out/lib",
+                "cargo::rustc-link-lib=dylib=foo",
+                "cargo::rustc-link-lib=dylib=bar"
+            ]
+        );
+        let links = vec![("foo".to_string(), LinkKind::Static)];
+        assert!(resolve(&links, &[], &targets).is_err());
+
+        let targets = vec![library(
+            "plugins",
+            "STATIC_LIBRARY",
+            &["/out/libplugins.a"],
+            &[],
+        )];
+        let links = vec![("plugins".to_string(), LinkKind::Static)];
+        let resolved = resolve(&links, &["plugins".to_string()], &targets).unwrap();
+        assert_eq!(
+            link_lines(&resolved)[1],
+            "cargo::rustc-link-lib=static:+whole-archive=plugins"
+        );
+        assert!(!resolved[0].uses_cxx);
+
+        let graph = BuildGraph::from_graphviz(
+            r#"digraph "app" {
+    "node0" [ label = "app", shape = egg ];
+    "node1" [ label = "core", shape = octagon ];
+    "node2" [ label = "net", shape = octagon ];
+    "node3" [ label = "Threads::Threads", shape = pentagon ];
+    "node4" [ label = "-pthread", shape = septagon ];
+    "node5" [ label = "m", shape = septagon ];
+    "node6" [ label = "ws2_32.lib", shape = septagon ];
+    "node7" [ label = "plugin", shape = doubleoctagon ];
+    "node8" [ label = "dl", shape = septagon ];
+    "node0" -> "node1"
+    "node1" -> "node2" [ style = dotted ]
+    "node1" -> "node5"
+    "node2" -> "node3"
+    "node3" -> "node4"
+    "node2" -> "node6"
+    "node2" -> "node7"
+    "node7" -> "node8"
+}"#,
+        );
+        assert_eq!(
+            system_lib_lines(&graph, &["core"]),
+            vec![
+                "cargo::rustc-link-lib=m",
+                "cargo::rustc-link-lib=ws2_32",
+                "cargo::rustc-link-lib=pthread"
+            ]
+        );
+        assert_eq!(
+            external_lib_lines("/usr/lib/libz.a"),
+            vec![
+                "cargo::rustc-link-search=native=/usr/lib",
+                "cargo::rustc-link-lib=static=z"
+            ]
+        );
+
+        assert_eq!(
+            cxx_runtime_lib(CxxRuntime::Auto, "windows", "msvc", ""),
+            None
+        );
+        assert_eq!(
+            cxx_runtime_lib(CxxRuntime::Auto, "macos", "", ""),
+            Some("c++")
+        );
+        assert_eq!(
+            cxx_runtime_lib(CxxRuntime::Auto, "linux", "gnu", ""),
+            Some("stdc++")
+        );
+        assert_eq!(
+            cxx_runtime_lib(CxxRuntime::Auto, "linux", "gnu", "-O2 -stdlib=libc++"),
+            Some("c++")
+        );
+        assert_eq!(
+            cxx_runtime_lib(CxxRuntime::LibCpp, "linux", "gnu", ""),
+            Some("c++")
+        );
+    }
+
+    #[test]
+    fn order_static_link_closure() {
+        let targets = vec![
+            library("app", "EXECUTABLE", &["/out/app"], &["core"]),
+            library(
+                "core",
+                "STATIC_LIBRARY",
+                &["/out/libcore.a"],
+                &["net", "log"],
+            ),
+            library(
+                "net",
+                "STATIC_LIBRARY",
+                &["/out/libnet.a"],
+                &["util", "ssl"],
+            ),
+            library(
+                "util",
+                "STATIC_LIBRARY",
+                &["/out/libutil.a"],
+                &["net", "log"],
+            ),
+            library("log", "STATIC_LIBRARY", &["/out/liblog.a"], &[]),
+            library("ssl", "SHARED_LIBRARY", &["/out/libssl.so"], &["crypto"]),
+            library("crypto", "STATIC_LIBRARY", &["/out/libcrypto.a"], &[]),
+        ];
+        let links = vec![("core".to_string(), LinkKind::Static)];
+        let resolved = resolve(&links, &[], &targets).unwrap();
+        let names: Vec<String> = resolved
+            .iter()
+            .map(|link| format!("{}={}", link.kind, link.name))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "static=core",
+                "static=net",
+                "static=util",
+                "static=net",
+                "static=util",
+                "static=log",
+                "dylib=ssl"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn archive_object_library() {
+        let temp = TempBuildDir::new("objects").unwrap();
+        let dir = temp.path().to_path_buf();
+        std::fs::create_dir_all(&dir).unwrap();
+        for object in ["a.c.o", "b.c.o"] {
+            std::fs::write(dir.join(object), object).unwrap();
+        }
+
+        let mut targets = vec![library("objs", "OBJECT_LIBRARY", &[], &[])];
+        targets[0].artifacts = vec![dir.join("a.c.o"), dir.join("b.c.o")];
+        let archive = archive_objects(&targets[0], &dir, &dir.join("out")).unwrap();
+        assert_eq!(archive, dir.join("out").join("libobjs.a"));
+        assert!(archive.is_file());
+
+        targets[0].artifacts = vec![archive];
+        let links = vec![("objs".to_string(), LinkKind::Object)];
+        let resolved = resolve(&links, &[], &targets).unwrap();
+        assert_eq!(
+            link_lines(&resolved)[1],
+            "cargo::rustc-link-lib=static=objs"
+        );
+    }
+
+    #[test]
+    fn translate_external_libraries() {
+        assert_eq!(
+            external_lib_lines("-framework Cocoa"),
+            ["cargo::rustc-link-lib=framework=Cocoa"]
+        );
+        assert_eq!(external_lib_lines("-lz"), ["cargo::rustc-link-lib=z"]);
+        assert_eq!(
+            external_lib_lines("/usr/lib/libssl.so.3"),
+            [
+                "cargo::rustc-link-search=native=/usr/lib",
+                "cargo::rustc-link-lib=ssl"
+            ]
+        );
+        // linker flags and imported targets CMake could not resolve
+        assert!(external_lib_lines("-Wl,--as-needed").is_empty());
+        assert!(external_lib_lines("OpenSSL::SSL").is_empty());
+        assert!(external_lib_lines("").is_empty());
+    }
+
+    #[test]
+    fn link_cxx_runtime_for_static_cxx_targets() {
+        let mut targets = vec![
+            library("engine", "STATIC_LIBRARY", &["/out/libengine.a"], &[]),
+            library("codec", "STATIC_LIBRARY", &["/out/libcodec.a"], &[]),
+            library("gui", "SHARED_LIBRARY", &["/out/libgui.so"], &[]),
+        ];
+        targets[0].languages = vec!["C".to_string(), "CXX".to_string()];
+        targets[2].languages = vec!["CXX".to_string()];
+        let links = vec![
+            ("engine".to_string(), LinkKind::Static),
+            ("codec".to_string(), LinkKind::Static),
+            ("gui".to_string(), LinkKind::Dylib),
+        ];
+        // whole-archive only applies to static libraries
+        let whole_archive = ["engine".to_string(), "gui".to_string()];
+        let resolved = resolve(&links, &whole_archive, &targets).unwrap();
+
+        let uses_cxx: Vec<bool> = resolved.iter().map(|link| link.uses_cxx).collect();
+        assert_eq!(uses_cxx, [true, false, false]);
+        assert_eq!(
+            link_lines(&resolved)[1..],
+            [
+                "cargo::rustc-link-lib=static:+whole-archive=engine",
+                "cargo::rustc-link-lib=static=codec",
+                "cargo::rustc-link-lib=dylib=gui"
+            ]
+        );
+        assert_eq!(
+            cxx_runtime_lib(CxxRuntime::Auto, "android", "", ""),
+            Some("c++_shared")
+        );
+        assert_eq!(
+            cxx_runtime_lib(CxxRuntime::Static, "linux", "gnu", ""),
+            Some("stdc++")
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! CI annotations for compiler diagnostics.
+//!
+//! With [`Cmd::ci_annotations`](crate::Cmd::ci_annotations) enabled, every
+//! parsed diagnostic is printed to stdout in a format the CI system picks up,
+//! so native build errors show up inline on pull requests. Annotation lines
+//! start with `::` or a color code, never with `cargo:`, so cargo doesn't
+//! take them for directives when they come from a build script. While
+//! [machine output](crate::Cmd::machine_output) owns stdout, they go to
+//! stderr instead, which GitHub Actions and GitLab read the same way.
+//!
+//! File paths are made relative to the checkout directory of the CI job
+//! (`GITHUB_WORKSPACE` or `CI_PROJECT_DIR`) when they lie inside it.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    diagnostics::{Diagnostic, Severity},
+    report::BuildReport,
+};
+
+/// The CI system to emit annotations for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CiFlavor {
+    /// GitHub Actions workflow commands (`::error file=...,line=...::message`).
+    GitHub,
+    /// GitLab job log lines (`ERROR: file:line:col: message`), highlighted with ANSI colors.
+    GitLab,
+}
+
+impl CiFlavor {
+    /// Returns the environment variable holding the job's checkout directory.
+    fn workspace_var(self) -> &'static str {
+        match self {
+            CiFlavor::GitHub => "GITHUB_WORKSPACE",
+            CiFlavor::GitLab => "CI_PROJECT_DIR",
+        }
+    }
+}
+
+/// Formats a single diagnostic as an annotation line.
+///
+/// `file` is the location to report, already resolved by the caller.
+pub fn annotation(flavor: CiFlavor, diagnostic: &Diagnostic, file: &Path) -> String {
+    let file = file.to_string_lossy().replace('\\', "/");
+
+    match flavor {
+        CiFlavor::GitHub => {
+            let command = match diagnostic.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Note => "notice",
+            };
+
+            let mut properties = vec![format!("file={}", escape_github_property(&file))];
+            if let Some(line) = diagnostic.line {
+                properties.push(format!("line={}", line));
+            }
+            if let Some(column) = diagnostic.column {
+                properties.push(format!("col={}", column));
+            }
+            if let Some(code) = &diagnostic.code {
+                properties.push(format!("title={}", escape_github_property(code)));
+            }
+
+            format!(
+                "::{} {}::{}",
+                command,
+                properties.join(","),
+                escape_github_data(&diagnostic.message)
+            )
+        }
+        CiFlavor::GitLab => {
+            let (color, label) = match diagnostic.severity {
+                Severity::Error => ("\x1b[31;1m", "ERROR"),
+                Severity::Warning => ("\x1b[33;1m", "WARNING"),
+                Severity::Note => ("\x1b[36;1m", "NOTE"),
+            };
+
+            let mut location = file;
+            if let Some(line) = diagnostic.line {
+                location.push_str(&format!(":{}", line));
+            }
+            if let Some(column) = diagnostic.column {
+                location.push_str(&format!(":{}", column));
+            }
+
+            format!(
+                "{}{}:\x1b[0m {}: {}",
+                color,
+                label,
+                location,
+                diagnostic.message.replace('\n', " ")
+            )
+        }
+    }
+}
+
+/// Prints an annotation for every diagnostic in `report` to stdout, or to
+/// stderr if `stdout_taken`.
+pub(crate) fn emit(flavor: CiFlavor, report: &BuildReport, stdout_taken: bool) {
+    let workspace = std::env::var_os(flavor.workspace_var())
+        .map(PathBuf::from)
+        .map(|workspace| workspace.canonicalize().unwrap_or(workspace));
+
+    for diagnostic in &report.diagnostics {
+        let mut file = report.resolve_path(diagnostic);
+        if let Some(relative) = workspace
+            .as_deref()
+            .and_then(|workspace| file.strip_prefix(workspace).ok())
+        {
+            file = relative.to_path_buf();
+        }
+        let annotation = annotation(flavor, diagnostic, &file);
+        if stdout_taken {
+            eprintln!("{}", annotation);
+        } else {
+            println!("{}", annotation);
+        }
+    }
+}
+
+/// Escapes the message part of a GitHub workflow command.
+fn escape_github_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a property value of a GitHub workflow command.
+fn escape_github_property(value: &str) -> String {
+    escape_github_data(value)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_annotations() {
+        let diagnostic = Diagnostic {
+            file: PathBuf::from("src/func.c"),
+            line: Some(12),
+            column: Some(5),
+            severity: Severity::Error,
+            code: Some("C2065".to_string()),
+            message: "'x': undeclared identifier, 100% sure".to_string(),
+        };
+
+        assert_eq!(
+            annotation(CiFlavor::GitHub, &diagnostic, Path::new("src/func.c")),
+            "::error file=src/func.c,line=12,col=5,title=C2065::'x': undeclared identifier, 100%25 sure"
+        );
+        assert_eq!(
+            annotation(CiFlavor::GitLab, &diagnostic, Path::new("src\\func.c")),
+            "\x1b[31;1mERROR:\x1b[0m src/func.c:12:5: 'x': undeclared identifier, 100% sure"
+        );
+    }
+}
@@ -1,46 +1,239 @@
 //! CMake presets parsing and lookup.
 //!
 //! This module handles reading and deserializing `CMakePresets.json` files,
-//! providing access to the configure presets defined within.
+//! together with `CMakeUserPresets.json` and the files they `include`,
+//! providing access to the configure, build, test, package, and workflow
+//! presets defined within. [`CMakePresets::resolve`] flattens the
+//! inheritance chain of a configure preset into a [`ResolvedPreset`].
+//!
+//! [`version`] reports the version of the installed cmake as a
+//! [`CMakeVersion`], e.g. to check for features like `--fresh` (3.24) or a
+//! preset schema version before using them.
 
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::SystemTime,
+};
 
 use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    error::Error,
+    instrument,
+    preset_version::{self, PinReport},
+    tools::Tools,
+};
+
+/// The types of cache variables, as in `-D<name>:<type>=<value>`.
+const CACHE_TYPES: [&str; 5] = ["BOOL", "FILEPATH", "PATH", "STRING", "INTERNAL"];
+
+/// Configure output of a generator, build tool, or toolset that is not
+/// available.
+const GENERATOR_UNAVAILABLE: [&str; 6] = [
+    "Could not create named generator",
+    "CMAKE_MAKE_PROGRAM is not set",
+    "could not find any instance of Visual Studio",
+    "does not support toolset specification",
+    "does not support platform specification",
+    // MSBuild: the build tools of the platform toolset cannot be found
+    "MSB8020",
+];
 
 /// A CMake variable definition consisting of a name-value pair.
 ///
 /// Used to pass `-D<name>=<value>` arguments to the CMake configure step.
+#[derive(Debug, Clone)]
 pub(crate) struct Defination {
     pub name: String,
     pub value: String,
+    /// Whether the definition is only passed until the cache has the variable.
+    pub once: bool,
+}
+
+impl Defination {
+    /// Renders the definition as a `-D<name>=<value>` argument.
+    ///
+    /// The argument is passed to cmake directly rather than through a shell,
+    /// so spaces, semicolons and double quotes need no escaping on any
+    /// platform. CMake itself, however, trims trailing whitespace from `-D`
+    /// values and strips one pair of enclosing single quotes, so values that
+    /// would be changed by that are wrapped in single quotes, which CMake then
+    /// strips again.
+    ///
+    /// Unless `raw` is set, returns an error for a name that CMake would
+    /// split differently (empty, or containing `=` or `"`) or with an
+    /// unknown type after a `:`, and for a value
+    /// containing a line break or NUL, which `CMakeCache.txt` cannot store. A
+    /// raw definition is passed on verbatim.
+    pub(crate) fn to_arg(&self, raw: bool) -> Result<String, String> {
+        if raw {
+            return Ok(format!("-D{}={}", self.name, self.value));
+        }
+        if self.name.is_empty() || self.name.contains(['=', '"']) {
+            return Err(format!("invalid define name {:?}", self.name));
+        }
+        if let Some((name, kind)) = self.name.split_once(':')
+            && (name.is_empty() || !CACHE_TYPES.contains(&kind))
+        {
+            return Err(format!(
+                "invalid define {:?}, expected a name and one of the types {}",
+                self.name,
+                CACHE_TYPES.join(", ")
+            ));
+        }
+        if self.value.contains(['\n', '\r', '\0']) {
+            return Err(format!(
+                "the value of define {} contains a line break or NUL, which CMake cannot store",
+                self.name
+            ));
+        }
+
+        let quoted =
+            self.value.len() >= 2 && self.value.starts_with('\'') && self.value.ends_with('\'');
+        if quoted || self.value.ends_with([' ', '\t']) {
+            Ok(format!("-D{}='{}'", self.name, self.value))
+        } else {
+            Ok(format!("-D{}={}", self.name, self.value))
+        }
+    }
 }
 
-/// A single CMake configure preset.
+/// A single CMake configure, build, test, or package preset.
 ///
-/// Represents one entry in the `configurePresets` array of a `CMakePresets.json` file.
+/// Represents one entry in the `configurePresets`, `buildPresets`,
+/// `testPresets`, or `packagePresets` array of a `CMakePresets.json` file.
 /// Hidden presets (with `hidden: true`) are excluded from lookup by [`CMakePresets::get_preset`].
 #[derive(Deserialize)]
-pub(crate) struct CMakePreset {
+pub struct CMakePreset {
     name: String,
+    #[serde(rename = "displayName", default)]
+    display_name: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
     #[serde(default = "default_hidden")]
     hidden: bool,
+    /// The condition that enables the preset on a host.
+    #[serde(default)]
+    condition: Option<Value>,
+    #[serde(default)]
+    inherits: Inherits,
+    #[serde(default)]
+    generator: Option<String>,
+    #[serde(rename = "binaryDir", default)]
+    binary_dir: Option<String>,
+    /// The configure preset a build, test, or package preset applies to.
+    #[serde(rename = "configurePreset", default)]
+    configure_preset: Option<String>,
+    #[serde(rename = "toolchainFile", default)]
+    toolchain_file: Option<String>,
+    #[serde(rename = "cacheVariables", default)]
+    cache_variables: BTreeMap<String, Value>,
+    /// Environment variables, where `null` unsets an inherited variable.
+    #[serde(default)]
+    environment: BTreeMap<String, Option<String>>,
+}
+
+/// The `inherits` field of a preset: one name or a list of names.
+#[derive(Deserialize, Default)]
+#[serde(untagged)]
+enum Inherits {
+    #[default]
+    None,
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Inherits {
+    fn names(&self) -> &[String] {
+        match self {
+            Inherits::None => &[],
+            Inherits::One(name) => std::slice::from_ref(name),
+            Inherits::Many(names) => names,
+        }
+    }
 }
 
 impl CMakePreset {
     /// Returns the name of this preset.
-    pub(crate) fn get_name(&self) -> &str {
+    pub fn get_name(&self) -> &str {
         &self.name
     }
+
+    /// Returns the generator the preset sets itself, not one it inherits.
+    pub fn generator(&self) -> Option<&str> {
+        self.generator.as_deref()
+    }
+
+    /// Returns the toolchain file the preset sets itself, as `toolchainFile`
+    /// or as the `CMAKE_TOOLCHAIN_FILE` cache variable, with macros unexpanded.
+    pub fn toolchain_file(&self) -> Option<&str> {
+        self.toolchain_file.as_deref().or_else(|| {
+            match self.cache_variables.get("CMAKE_TOOLCHAIN_FILE")? {
+                Value::String(value) => Some(value.as_str()),
+                Value::Object(variable) => variable.get("value")?.as_str(),
+                _ => None,
+            }
+        })
+    }
+
+    /// Returns the names of the presets this preset inherits from, in order
+    /// of precedence.
+    pub fn inherits(&self) -> &[String] {
+        self.inherits.names()
+    }
+
+    /// Returns the configure preset of a build, test, or package preset, if
+    /// the preset sets it itself.
+    pub fn configure_preset(&self) -> Option<&str> {
+        self.configure_preset.as_deref()
+    }
+}
+
+/// A workflow preset: a sequence of configure, build, test, and package
+/// presets run with `cmake --workflow --preset=<name>`.
+#[derive(Deserialize)]
+pub struct WorkflowPreset {
+    pub name: String,
+    #[serde(rename = "displayName", default)]
+    display_name: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    steps: Vec<WorkflowStep>,
+}
+
+impl WorkflowPreset {
+    /// Returns the steps of the workflow, in order.
+    pub fn steps(&self) -> &[WorkflowStep] {
+        &self.steps
+    }
 }
 
-/// A collection of CMake configure presets parsed from a `CMakePresets.json` file.
+/// One step of a [`WorkflowPreset`].
+#[derive(Deserialize)]
+pub struct WorkflowStep {
+    /// The kind of preset: `configure`, `build`, `test`, or `package`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The name of the preset run by the step.
+    pub name: String,
+}
+
+/// A collection of CMake presets parsed from a `CMakePresets.json` file.
 ///
 /// This struct deserializes the top-level JSON object and extracts the
-/// `configurePresets` array. Unknown fields (such as `version`) are silently ignored.
+/// `configurePresets`, `buildPresets`, `testPresets`, `packagePresets`, and
+/// `workflowPresets` arrays. Unknown fields (such as `version`) are silently
+/// ignored. The presets of `CMakeUserPresets.json` next to it and of every
+/// file named in an `include` field are merged in, as CMake does.
 ///
 /// # Example
 ///
-/// ```ignore
+/// ```no_run
 /// use cmakr::cmake::CMakePresets;
 ///
 /// let presets = CMakePresets::new("./my_project").unwrap();
@@ -48,15 +241,96 @@ impl CMakePreset {
 ///     println!("Found preset: {}", preset.get_name());
 /// }
 /// ```
-#[derive(Deserialize)]
-pub(crate) struct CMakePresets {
-    #[serde(rename = "configurePresets")]
+#[derive(Deserialize, Default)]
+pub struct CMakePresets {
+    #[serde(rename = "configurePresets", default)]
     configure_presets: Vec<CMakePreset>,
+    #[serde(rename = "buildPresets", default)]
+    build_presets: Vec<CMakePreset>,
+    #[serde(rename = "testPresets", default)]
+    test_presets: Vec<CMakePreset>,
+    #[serde(rename = "packagePresets", default)]
+    package_presets: Vec<CMakePreset>,
+    #[serde(rename = "workflowPresets", default)]
+    workflow_presets: Vec<WorkflowPreset>,
+    /// Files whose presets are merged in, relative to the including file.
+    #[serde(default)]
+    include: Vec<String>,
+    /// The directory of the presets file, which `${sourceDir}` expands to.
+    #[serde(skip)]
+    source_dir: PathBuf,
+}
+
+/// A configure preset with its inheritance chain flattened and its macros
+/// expanded, as returned by [`CMakePresets::resolve`].
+///
+/// A field set by the preset itself wins over an inherited one, and a parent
+/// listed earlier in `inherits` wins over a later one. `cacheVariables` and
+/// `environment` are merged entry by entry in the same order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResolvedPreset {
+    /// The name of the preset.
+    pub name: String,
+    /// The generator, if the preset or a parent sets one.
+    pub generator: Option<String>,
+    /// The absolute build directory, if the preset or a parent sets one.
+    pub binary_dir: Option<PathBuf>,
+    /// The absolute toolchain file, from `toolchainFile` or the
+    /// `CMAKE_TOOLCHAIN_FILE` cache variable.
+    pub toolchain_file: Option<PathBuf>,
+    /// Cache variables by name. Booleans are rendered as `TRUE`/`FALSE`, and
+    /// variables set to `null` are left out.
+    pub cache_variables: BTreeMap<String, String>,
+    /// Environment variables by name. Variables set to `null` are left out.
+    pub environment: BTreeMap<String, String>,
+}
+
+/// A preset of any kind, as listed by [`CMakePresets::list`].
+///
+/// The generator, build directory, and condition are the preset's own or
+/// else the first one its parents set, in order of precedence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresetInfo {
+    /// The kind of preset: `configure`, `build`, `test`, `package`, or
+    /// `workflow`.
+    pub kind: &'static str,
+    /// The name of the preset.
+    pub name: String,
+    /// The `displayName` of the preset.
+    pub display_name: Option<String>,
+    /// The `description` of the preset.
+    pub description: Option<String>,
+    /// Whether the preset is hidden.
+    pub hidden: bool,
+    /// The generator of a configure preset.
+    pub generator: Option<String>,
+    /// The absolute build directory of a configure preset, with macros
+    /// expanded.
+    pub binary_dir: Option<PathBuf>,
+    /// Whether the `condition` of the preset holds on this host, or the
+    /// preset has none.
+    pub condition_met: bool,
+}
+
+/// The fields of a preset and its parents, merged by precedence.
+#[derive(Default)]
+struct Merged<'a> {
+    generator: Option<&'a str>,
+    binary_dir: Option<&'a str>,
+    toolchain_file: Option<&'a str>,
+    cache_variables: BTreeMap<&'a str, &'a Value>,
+    environment: BTreeMap<&'a str, Option<&'a str>>,
 }
 
 impl CMakePresets {
     /// Creates a new [`CMakePresets`] by reading and parsing a `CMakePresets.json` file.
     ///
+    /// A `CMakeUserPresets.json` in the same directory is read as well, and
+    /// so are the files named in the `include` field of any of them,
+    /// relative to the including file. As in CMake, a preset name may only
+    /// be defined once across all files, and a file included several times
+    /// is read once.
+    ///
     /// # Arguments
     ///
     /// * `path` - A path that can be either:
@@ -65,10 +339,10 @@ impl CMakePresets {
     ///
     /// # Errors
     ///
-    /// Returns an error if:
-    /// - The file cannot be read (I/O error)
-    /// - The JSON content is malformed or does not match the expected schema
-    pub fn new<T>(path: T) -> Result<Self, Box<dyn std::error::Error>>
+    /// Returns [`Error::Io`] if a file cannot be read, [`Error::Json`] if
+    /// its content is malformed or does not match the expected schema, and
+    /// [`Error::PresetParse`] if a preset name is defined more than once.
+    pub fn new<T>(path: T) -> Result<Self, Error>
     where
         T: Into<PathBuf>,
     {
@@ -79,13 +353,151 @@ impl CMakePresets {
         } else {
             path.join("CMakePresets.json")
         };
+        let source_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
 
-        let content = std::fs::read_to_string(path)?;
-        let presets: CMakePresets = serde_json::from_str(&content)?;
+        let mut presets = CMakePresets {
+            source_dir: source_dir.clone(),
+            ..Default::default()
+        };
+        let mut loaded = Vec::new();
+        presets.load(&path, &mut loaded)?;
+        let user_presets = source_dir.join("CMakeUserPresets.json");
+        if user_presets.is_file() {
+            presets.load(&user_presets, &mut loaded)?;
+        }
 
         Ok(presets)
     }
 
+    /// Reads the presets file at `path` and the files it includes, and
+    /// merges their presets in. Files in `loaded` are skipped.
+    fn load(&mut self, path: &Path, loaded: &mut Vec<PathBuf>) -> Result<(), Error> {
+        let canonical = path.canonicalize()?;
+        if loaded.contains(&canonical) {
+            return Ok(());
+        }
+        loaded.push(canonical);
+
+        let content = std::fs::read_to_string(path)?;
+        let file: CMakePresets = serde_json::from_str(&content)?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        for include in &file.include {
+            self.load(&dir.join(include), loaded)?;
+        }
+
+        let kinds = [
+            (
+                &mut self.configure_presets,
+                file.configure_presets,
+                "configure",
+            ),
+            (&mut self.build_presets, file.build_presets, "build"),
+            (&mut self.test_presets, file.test_presets, "test"),
+            (&mut self.package_presets, file.package_presets, "package"),
+        ];
+        for (presets, added, kind) in kinds {
+            for preset in added {
+                if presets.iter().any(|p| p.name == preset.name) {
+                    return Err(duplicate(kind, &preset.name, path));
+                }
+                presets.push(preset);
+            }
+        }
+        for preset in file.workflow_presets {
+            if self.workflow_presets.iter().any(|p| p.name == preset.name) {
+                return Err(duplicate("workflow", &preset.name, path));
+            }
+            self.workflow_presets.push(preset);
+        }
+        Ok(())
+    }
+
+    /// Pins `CMakePresets.json` and the files it includes to the newest
+    /// preset schema `cmake_version` reads, so that CI agents running that
+    /// CMake release can read them.
+    ///
+    /// Lowers the `version` of newer files and reports the fields, macros,
+    /// and `cmakeMinimumRequired` values that CMake release doesn't support;
+    /// see [`preset_version`](crate::preset_version). Files are only
+    /// rewritten if nothing is unsupported. `CMakeUserPresets.json` is
+    /// local to a checkout and left alone.
+    ///
+    /// # Arguments
+    ///
+    /// * `cmake_version` - The oldest CMake release the presets must work with.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Unsupported`] for CMake releases before presets
+    /// (3.19), [`Error::PresetParse`] if a file can't be parsed, or an I/O
+    /// error.
+    pub fn pin_versions(&self, cmake_version: CMakeVersion) -> Result<PinReport, Error> {
+        preset_version::pin(&self.source_dir.join("CMakePresets.json"), cmake_version)
+    }
+
+    /// Lists all presets of all kinds, hidden ones and those whose condition
+    /// doesn't hold included, in file order within each kind.
+    ///
+    /// Conditions are evaluated for this host, with `${hostSystemName}`
+    /// expanded to `Linux`, `Windows`, `Darwin`, and so on, and environment
+    /// macros read from the process environment. `matches` and `notMatches`
+    /// conditions, which need a regular expression engine, count as met.
+    pub fn list(&self) -> Vec<PresetInfo> {
+        let source_dir = std::path::absolute(&self.source_dir).unwrap_or_default();
+        let kinds = [
+            ("configure", &self.configure_presets),
+            ("build", &self.build_presets),
+            ("test", &self.test_presets),
+            ("package", &self.package_presets),
+        ];
+        let mut list: Vec<PresetInfo> = kinds
+            .into_iter()
+            .flat_map(|(kind, presets)| presets.iter().map(move |p| (kind, presets, p)))
+            .map(|(kind, presets, preset)| {
+                let generator = inherited(presets, preset, |p| p.generator.as_deref());
+                let expand = |value: &str| {
+                    let value = expand_macros(value, &source_dir, &preset.name)
+                        .replace("${generator}", generator.unwrap_or_default())
+                        .replace("${hostSystemName}", host_system_name());
+                    expand_env(&value, &BTreeMap::new())
+                };
+                PresetInfo {
+                    kind,
+                    name: preset.name.clone(),
+                    display_name: preset.display_name.clone(),
+                    description: preset.description.clone(),
+                    hidden: preset.hidden,
+                    generator: generator.map(str::to_string),
+                    binary_dir: inherited(presets, preset, |p| p.binary_dir.as_deref())
+                        .map(|dir| source_dir.join(expand(dir))),
+                    condition_met: inherited(presets, preset, |p| p.condition.as_ref())
+                        .is_none_or(|condition| condition_met(condition, &expand)),
+                }
+            })
+            .collect();
+        list.extend(self.workflow_presets.iter().map(|preset| PresetInfo {
+            kind: "workflow",
+            name: preset.name.clone(),
+            display_name: preset.display_name.clone(),
+            description: preset.description.clone(),
+            hidden: false,
+            generator: None,
+            binary_dir: None,
+            condition_met: true,
+        }));
+        list
+    }
+
+    /// Lists the presets `cmake --list-presets` shows: those that are not
+    /// hidden and whose condition holds on this host. See
+    /// [`list`](CMakePresets::list).
+    pub fn list_visible(&self) -> Vec<PresetInfo> {
+        self.list()
+            .into_iter()
+            .filter(|preset| !preset.hidden && preset.condition_met)
+            .collect()
+    }
+
     /// Finds a non-hidden preset by name.
     ///
     /// Returns `None` if no preset with the given name exists, or if the
@@ -99,6 +511,687 @@ impl CMakePresets {
             .iter()
             .find(|p| p.name == name && !p.hidden)
     }
+
+    /// Finds a non-hidden build preset by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The build preset name to search for.
+    pub fn get_build_preset(&self, name: &str) -> Option<&CMakePreset> {
+        self.build_presets
+            .iter()
+            .find(|p| p.name == name && !p.hidden)
+    }
+
+    /// Finds a non-hidden test preset by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The test preset name to search for.
+    pub fn get_test_preset(&self, name: &str) -> Option<&CMakePreset> {
+        self.test_presets
+            .iter()
+            .find(|p| p.name == name && !p.hidden)
+    }
+
+    /// Finds a non-hidden package preset by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The package preset name to search for.
+    pub fn get_package_preset(&self, name: &str) -> Option<&CMakePreset> {
+        self.package_presets
+            .iter()
+            .find(|p| p.name == name && !p.hidden)
+    }
+
+    /// Finds a workflow preset by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The workflow preset name to search for.
+    pub fn get_workflow_preset(&self, name: &str) -> Option<&WorkflowPreset> {
+        self.workflow_presets.iter().find(|p| p.name == name)
+    }
+
+    /// Resolves a non-hidden configure preset with everything it inherits.
+    ///
+    /// Macros are expanded in the build directory, toolchain file, cache
+    /// variables, and environment: `${sourceDir}`, `${sourceParentDir}`,
+    /// `${sourceDirName}`, `${presetName}`, `${generator}`, `${dollar}`,
+    /// `$env{NAME}` (the preset's environment, then the process's), and
+    /// `$penv{NAME}`. A relative build directory is relative to the source
+    /// directory; a relative toolchain file is relative to the build
+    /// directory if it exists there, and to the source directory otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The configure preset name.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PresetNotFound`] if the preset is not defined or is
+    /// hidden, and [`Error::PresetParse`] if it inherits from an undefined
+    /// preset or from itself.
+    pub fn resolve(&self, name: &str) -> Result<ResolvedPreset, Error> {
+        let resolved = self.resolve_preset(name);
+        instrument::preset_resolved(name, &resolved);
+        resolved
+    }
+
+    /// Resolves the configure preset `name`; see [`resolve`](CMakePresets::resolve).
+    fn resolve_preset(&self, name: &str) -> Result<ResolvedPreset, Error> {
+        if self.get_preset(name).is_none() {
+            return Err(Error::PresetNotFound(name.to_string()));
+        }
+        let mut merged = Merged::default();
+        self.merge(name, &mut merged, &mut Vec::new())?;
+
+        let source_dir = std::path::absolute(&self.source_dir)?;
+        let generator = merged.generator.unwrap_or_default();
+        let raw_environment: BTreeMap<&str, &str> = merged
+            .environment
+            .iter()
+            .filter_map(|(key, value)| Some((*key, (*value)?)))
+            .collect();
+        let expand = |value: &str| {
+            let value = expand_macros(value, &source_dir, name).replace("${generator}", generator);
+            expand_env(&value, &raw_environment)
+        };
+
+        let binary_dir = merged.binary_dir.map(|dir| source_dir.join(expand(dir)));
+        let cache_variables: BTreeMap<String, String> = merged
+            .cache_variables
+            .iter()
+            .filter_map(|(key, value)| {
+                let value = match value {
+                    Value::Object(variable) => variable.get("value")?,
+                    value => value,
+                };
+                let value = match value {
+                    Value::String(value) => expand(value),
+                    Value::Bool(true) => "TRUE".to_string(),
+                    Value::Bool(false) => "FALSE".to_string(),
+                    _ => return None,
+                };
+                Some((key.to_string(), value))
+            })
+            .collect();
+        let toolchain_file = merged
+            .toolchain_file
+            .map(expand)
+            .or_else(|| cache_variables.get("CMAKE_TOOLCHAIN_FILE").cloned())
+            .map(|file| {
+                let in_binary_dir = binary_dir.as_ref().map(|dir| dir.join(&file));
+                match in_binary_dir {
+                    Some(path) if path.is_file() => path,
+                    _ => source_dir.join(file),
+                }
+            });
+
+        Ok(ResolvedPreset {
+            name: name.to_string(),
+            generator: merged.generator.map(str::to_string),
+            binary_dir,
+            toolchain_file,
+            environment: raw_environment
+                .iter()
+                .map(|(key, value)| (key.to_string(), expand(value)))
+                .collect(),
+            cache_variables,
+        })
+    }
+
+    /// Merges the configure preset `name` and its parents into `merged`,
+    /// keeping the values already merged. `chain` holds the presets being
+    /// merged, to detect cycles.
+    fn merge<'a>(
+        &'a self,
+        name: &str,
+        merged: &mut Merged<'a>,
+        chain: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        if chain.iter().any(|child| child == name) {
+            return Err(Error::PresetParse(format!(
+                "preset {} inherits from itself",
+                name
+            )));
+        }
+        let Some(preset) = self.configure_presets.iter().find(|p| p.name == name) else {
+            return Err(Error::PresetParse(format!(
+                "preset {} inherits from undefined preset {}",
+                chain.last().map(String::as_str).unwrap_or_default(),
+                name
+            )));
+        };
+
+        merged.generator = merged.generator.or(preset.generator.as_deref());
+        merged.binary_dir = merged.binary_dir.or(preset.binary_dir.as_deref());
+        merged.toolchain_file = merged.toolchain_file.or(preset.toolchain_file.as_deref());
+        for (key, value) in &preset.cache_variables {
+            merged.cache_variables.entry(key).or_insert(value);
+        }
+        for (key, value) in &preset.environment {
+            merged.environment.entry(key).or_insert(value.as_deref());
+        }
+
+        chain.push(name.to_string());
+        for parent in preset.inherits() {
+            self.merge(parent, merged, chain)?;
+        }
+        chain.pop();
+        Ok(())
+    }
+}
+
+/// Returns the first value of `field` set by `preset` or else by the presets
+/// it inherits from among `presets`, in order of precedence.
+fn inherited<'a, T, F>(
+    presets: &'a [CMakePreset],
+    preset: &'a CMakePreset,
+    field: F,
+) -> Option<&'a T>
+where
+    T: ?Sized,
+    F: Fn(&'a CMakePreset) -> Option<&'a T> + Copy,
+{
+    let mut pending = vec![preset];
+    let mut seen: Vec<&str> = Vec::new();
+    while let Some(preset) = pending.pop() {
+        // a cycle is reported by resolve, and only needs to end here
+        if seen.contains(&preset.name.as_str()) {
+            continue;
+        }
+        seen.push(&preset.name);
+        if let Some(value) = field(preset) {
+            return Some(value);
+        }
+        let parents = preset
+            .inherits()
+            .iter()
+            .filter_map(|name| presets.iter().find(|p| &p.name == name));
+        pending.extend(parents.rev());
+    }
+    None
+}
+
+/// Returns the `${hostSystemName}` of this host, as CMake names it.
+fn host_system_name() -> &'static str {
+    match std::env::consts::OS {
+        "linux" => "Linux",
+        "windows" => "Windows",
+        "macos" => "Darwin",
+        "freebsd" => "FreeBSD",
+        "netbsd" => "NetBSD",
+        "openbsd" => "OpenBSD",
+        "android" => "Android",
+        "ios" => "iOS",
+        os => os,
+    }
+}
+
+/// Evaluates a preset `condition`, with macros in its strings expanded by
+/// `expand`. Unknown conditions and regular expression matches count as
+/// met.
+fn condition_met(condition: &Value, expand: &dyn Fn(&str) -> String) -> bool {
+    let string = |key: &str| condition[key].as_str().map(expand).unwrap_or_default();
+    let in_list = || {
+        let value = string("string");
+        condition["list"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .any(|item| expand(item) == value)
+    };
+    match condition {
+        Value::Bool(value) => *value,
+        Value::Object(_) => match condition["type"].as_str().unwrap_or_default() {
+            "const" => condition["value"].as_bool().unwrap_or(true),
+            "equals" => string("lhs") == string("rhs"),
+            "notEquals" => string("lhs") != string("rhs"),
+            "inList" => in_list(),
+            "notInList" => !in_list(),
+            "anyOf" => condition["conditions"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .any(|c| condition_met(c, expand)),
+            "allOf" => condition["conditions"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .all(|c| condition_met(c, expand)),
+            "not" => !condition_met(&condition["condition"], expand),
+            _ => true,
+        },
+        _ => true,
+    }
+}
+
+/// Returns the error for a preset defined a second time, in `path`.
+fn duplicate(kind: &str, name: &str, path: &Path) -> Error {
+    Error::PresetParse(format!(
+        "{} preset {:?} in {} is already defined",
+        kind,
+        name,
+        path.display()
+    ))
+}
+
+/// Expands `$env{NAME}` from `environment` or else the process environment,
+/// and `$penv{NAME}` from the process environment. Undefined variables
+/// expand to an empty string.
+fn expand_env(value: &str, environment: &BTreeMap<&str, &str>) -> String {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let (prefix, preset_env) = if tail.starts_with("$env{") {
+            ("$env{", true)
+        } else if tail.starts_with("$penv{") {
+            ("$penv{", false)
+        } else {
+            expanded.push('$');
+            rest = &tail[1..];
+            continue;
+        };
+        let Some(end) = tail.find('}') else {
+            expanded.push_str(tail);
+            return expanded;
+        };
+        let variable = &tail[prefix.len()..end];
+        match environment.get(variable).filter(|_| preset_env) {
+            Some(value) => expanded.push_str(value),
+            None => expanded.push_str(&std::env::var(variable).unwrap_or_default()),
+        }
+        rest = &tail[end + 1..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// A single `NAME:TYPE=VALUE` entry of a `CMakeCache.txt`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CacheEntry {
+    pub name: String,
+    pub ty: String,
+    pub value: String,
+}
+
+/// Parses the entries of a `CMakeCache.txt`, skipping comments.
+pub(crate) fn parse_cache(content: &str) -> Vec<CacheEntry> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.starts_with("//"))
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let (name, ty) = key.rsplit_once(':')?;
+            Some(CacheEntry {
+                name: name.trim_matches('"').to_string(),
+                ty: ty.to_string(),
+                value: value.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Reads a single entry from the `CMakeCache.txt` of a configured build tree.
+///
+/// Returns `None` if the cache does not exist or has no entry named `name`.
+pub(crate) fn read_cache_value(binary_dir: &Path, name: &str) -> Option<String> {
+    let content = std::fs::read_to_string(binary_dir.join("CMakeCache.txt")).ok()?;
+
+    parse_cache(&content)
+        .into_iter()
+        .find(|entry| entry.name == name)
+        .map(|entry| entry.value)
+}
+
+/// Returns the names of the generators supported by `program`, including
+/// extra generator combinations such as `"CodeBlocks - Ninja"`.
+///
+/// # Errors
+///
+/// Returns an error if `cmake -E capabilities` cannot be run or its output
+/// cannot be parsed.
+pub(crate) fn generators(program: &Path) -> std::io::Result<Vec<String>> {
+    let output = std::process::Command::new(program)
+        .args(["-E", "capabilities"])
+        .output()?;
+    let capabilities: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    Ok(generator_names(&capabilities))
+}
+
+/// Collects the generator names of a `cmake -E capabilities` document.
+fn generator_names(capabilities: &serde_json::Value) -> Vec<String> {
+    let mut names = Vec::new();
+    for generator in capabilities["generators"].as_array().into_iter().flatten() {
+        let Some(name) = generator["name"].as_str() else {
+            continue;
+        };
+        names.push(name.to_string());
+        for extra in generator["extraGenerators"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            if let Some(extra) = extra.as_str() {
+                names.push(format!("{} - {}", extra, name));
+            }
+        }
+    }
+    names
+}
+
+/// Returns whether configure `output` shows that the generator, its build
+/// tool, or its toolset is unavailable.
+pub(crate) fn generator_unavailable(output: &str) -> bool {
+    GENERATOR_UNAVAILABLE
+        .iter()
+        .any(|marker| output.contains(marker))
+}
+
+/// The versions read by [`version_of`], by program path and modification
+/// time, so that a run probes each program once however many steps ask.
+type Versions = HashMap<(PathBuf, SystemTime), Option<String>>;
+
+/// Returns the version printed by `<program> --version` (e.g. `"3.28.3"`),
+/// for cmake, ctest and cpack alike.
+///
+/// The version of a program file is read once and remembered until the file
+/// changes; a bare name looked up on `PATH` is probed every time.
+pub(crate) fn version_of(program: &Path) -> Option<String> {
+    static VERSIONS: OnceLock<Mutex<Versions>> = OnceLock::new();
+
+    let Some(modified) = std::fs::metadata(program)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+    else {
+        return probe_version(program);
+    };
+    let key = (program.to_path_buf(), modified);
+    let versions = VERSIONS.get_or_init(Default::default);
+    if let Some(version) = versions.lock().unwrap().get(&key) {
+        return version.clone();
+    }
+    let version = probe_version(program);
+    versions.lock().unwrap().insert(key, version.clone());
+    version
+}
+
+/// Runs `<program> --version` and returns the version it prints.
+fn probe_version(program: &Path) -> Option<String> {
+    let output = std::process::Command::new(program)
+        .arg("--version")
+        .output()
+        .ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout.lines().next()?;
+    let (name, version) = line.split_once(" version ")?;
+    ["cmake", "ctest", "cpack"]
+        .contains(&name)
+        .then(|| version.trim().to_string())
+}
+
+/// Returns the parsed version of `program`, or `None` if it cannot be read.
+pub(crate) fn parsed_version_of(program: &Path) -> Option<CMakeVersion> {
+    version_of(program).as_deref().and_then(CMakeVersion::parse)
+}
+
+/// Returns the version of the cmake a [`Cmd`](crate::Cmd) runs by default:
+/// the one named by the `CMAKE` environment variable, or else the first one
+/// on `PATH`.
+///
+/// # Errors
+///
+/// Returns [`Error::CMakeNotFound`] if there is no cmake, and
+/// [`Error::ToolFailed`] if its version cannot be read.
+///
+/// # Example
+///
+/// ```no_run
+/// let version = cmakr::cmake::version().unwrap();
+/// if version >= cmakr::cmake::CMakeVersion::new(3, 24, 0) {
+///     println!("cmake {} supports --fresh", version);
+/// }
+/// ```
+pub fn version() -> Result<CMakeVersion, Error> {
+    let program = Tools::default().find_cmake()?;
+    version_of(&program)
+        .as_deref()
+        .and_then(CMakeVersion::parse)
+        .ok_or_else(|| {
+            Error::ToolFailed(format!("cannot read the version of {}", program.display()))
+        })
+}
+
+/// A CMake release, as printed by `cmake --version`.
+///
+/// Suffixes such as `-rc1` or the date and commit of development builds are
+/// ignored, so a release candidate compares equal to its release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CMakeVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl CMakeVersion {
+    /// Creates a version from its parts.
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parses a version like `"3.28.3"`, `"3.29.0-rc1"`, or `"3.21"` (the
+    /// missing patch is 0). Returns `None` if it doesn't start with a number.
+    pub fn parse(version: &str) -> Option<Self> {
+        let numbers = version.trim().split(['-', '+']).next()?;
+        let mut parts = numbers.split('.');
+        let mut next = || -> Option<u32> {
+            match parts.next() {
+                Some(part) => part.parse().ok(),
+                None => Some(0),
+            }
+        };
+        let major = next()?;
+        let minor = next()?;
+        // a patch with letters in it counts as 0
+        let patch = next().unwrap_or(0);
+        Some(Self::new(major, minor, patch))
+    }
+
+    /// Returns whether this version satisfies `requirement`: comma-separated
+    /// comparisons like `">=3.21"`, `">=3.21, <4"`, or `"=3.28.3"`. A version
+    /// without an operator is a minimum, so `"3.21"` means `">=3.21"`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if the requirement can't be parsed.
+    pub fn matches(&self, requirement: &str) -> Result<bool, Error> {
+        let mut matches = true;
+        for comparison in requirement.split(',').map(str::trim) {
+            let (operator, version) = ["<=", ">=", "==", "<", ">", "="]
+                .into_iter()
+                .find_map(|op| Some((op, comparison.strip_prefix(op)?)))
+                .unwrap_or((">=", comparison));
+            let version = CMakeVersion::parse(version).ok_or_else(|| {
+                Error::InvalidConfig(format!(
+                    "invalid cmake version requirement {:?}",
+                    requirement
+                ))
+            })?;
+            matches &= match operator {
+                "<=" => *self <= version,
+                ">=" => *self >= version,
+                "<" => *self < version,
+                ">" => *self > version,
+                _ => *self == version,
+            };
+        }
+        Ok(matches)
+    }
+}
+
+/// A command-line option of cmake or ctest that needs a minimum CMake
+/// version.
+///
+/// cmakr checks the options it would pass against the version of the tools
+/// it runs. Options with an older equivalent are replaced by it; the others
+/// fail the run up front with [`Error::Unsupported`] naming the required
+/// version, instead of with an "unknown option" error from the tool:
+///
+/// | Capability | Since | Older versions |
+/// |------------|-------|----------------|
+/// | [`SourceAndBinaryDir`](Capability::SourceAndBinaryDir) | 3.13 | error |
+/// | [`LogLevel`](Capability::LogLevel) | 3.16 | `--loglevel` on 3.15, no quiet configure before |
+/// | [`Install`](Capability::Install) | 3.15 | error |
+/// | [`MultipleTargets`](Capability::MultipleTargets) | 3.15 | error |
+/// | [`ConfigurePresets`](Capability::ConfigurePresets) | 3.19 | error |
+/// | [`BuildPresets`](Capability::BuildPresets) | 3.20 | error |
+/// | [`TestPresets`](Capability::TestPresets) | 3.20 | error |
+/// | [`TestDir`](Capability::TestDir) | 3.20 | ctest runs in the build directory |
+/// | [`Toolchain`](Capability::Toolchain) | 3.21 | `-DCMAKE_TOOLCHAIN_FILE=<path>` |
+/// | [`OutputJunit`](Capability::OutputJunit) | 3.21 | error |
+/// | [`Fresh`](Capability::Fresh) | 3.24 | `CMakeCache.txt` and `CMakeFiles` are removed |
+/// | [`Workflow`](Capability::Workflow) | 3.25 | the steps run one by one |
+///
+/// # Example
+///
+/// ```
+/// use cmakr::cmake::{CMakeVersion, Capability};
+///
+/// let version = CMakeVersion::new(3, 22, 1);
+/// assert!(version.supports(Capability::Toolchain));
+/// assert!(!version.supports(Capability::Fresh));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// `cmake -S <source> -B <binary>`.
+    SourceAndBinaryDir,
+    /// `cmake --log-level=<level>`.
+    LogLevel,
+    /// `cmake --install`.
+    Install,
+    /// Several targets after one `cmake --build --target`.
+    MultipleTargets,
+    /// `cmake --preset`.
+    ConfigurePresets,
+    /// `cmake --build --preset`.
+    BuildPresets,
+    /// `ctest --preset`.
+    TestPresets,
+    /// `ctest --test-dir`.
+    TestDir,
+    /// `cmake --toolchain`.
+    Toolchain,
+    /// `ctest --output-junit`.
+    OutputJunit,
+    /// `cmake --fresh`.
+    Fresh,
+    /// `cmake --workflow`.
+    Workflow,
+    /// `cmake --trace-format` and `--trace-redirect`.
+    Trace,
+    /// `cmake --build --verbose`.
+    BuildVerbose,
+    /// `cmake --log-context`.
+    LogContext,
+}
+
+impl Capability {
+    /// Returns the first CMake version with the capability.
+    pub fn min_version(self) -> CMakeVersion {
+        match self {
+            Capability::SourceAndBinaryDir => CMakeVersion::new(3, 13, 0),
+            Capability::LogLevel => CMakeVersion::new(3, 16, 0),
+            Capability::BuildVerbose => CMakeVersion::new(3, 14, 0),
+            Capability::Trace | Capability::LogContext => CMakeVersion::new(3, 17, 0),
+            Capability::Install | Capability::MultipleTargets => CMakeVersion::new(3, 15, 0),
+            Capability::ConfigurePresets => CMakeVersion::new(3, 19, 0),
+            Capability::BuildPresets | Capability::TestPresets | Capability::TestDir => {
+                CMakeVersion::new(3, 20, 0)
+            }
+            Capability::Toolchain | Capability::OutputJunit => CMakeVersion::new(3, 21, 0),
+            Capability::Fresh => CMakeVersion::new(3, 24, 0),
+            Capability::Workflow => CMakeVersion::new(3, 25, 0),
+        }
+    }
+
+    /// Returns the command-line option, as used in error messages.
+    pub fn option(self) -> &'static str {
+        match self {
+            Capability::SourceAndBinaryDir => "cmake -S/-B",
+            Capability::LogLevel => "cmake --log-level",
+            Capability::Install => "cmake --install",
+            Capability::MultipleTargets => "cmake --build --target with several targets",
+            Capability::ConfigurePresets => "cmake --preset",
+            Capability::BuildPresets => "cmake --build --preset",
+            Capability::TestPresets => "ctest --preset",
+            Capability::TestDir => "ctest --test-dir",
+            Capability::Toolchain => "cmake --toolchain",
+            Capability::OutputJunit => "ctest --output-junit",
+            Capability::Fresh => "cmake --fresh",
+            Capability::Workflow => "cmake --workflow",
+            Capability::Trace => "cmake --trace-format",
+            Capability::BuildVerbose => "cmake --build --verbose",
+            Capability::LogContext => "cmake --log-context",
+        }
+    }
+
+    /// Fails with [`Error::Unsupported`] if `version` is known and lacks the
+    /// capability. An unknown version is assumed to have it.
+    pub(crate) fn require(self, version: Option<CMakeVersion>) -> Result<(), Error> {
+        match version {
+            Some(version) if !version.supports(self) => Err(Error::Unsupported(format!(
+                "{} requires CMake >= {}, found {}",
+                self.option(),
+                self.min_version(),
+                version
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl CMakeVersion {
+    /// Returns whether this version has `capability`.
+    pub fn supports(&self, capability: Capability) -> bool {
+        *self >= capability.min_version()
+    }
+}
+
+impl fmt::Display for CMakeVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Expands the preset macros that don't depend on the environment:
+/// `${sourceDir}`, `${sourceParentDir}`, `${sourceDirName}`, `${presetName}`,
+/// and `${dollar}`.
+pub(crate) fn expand_macros(value: &str, source_dir: &Path, preset_name: &str) -> String {
+    let name = |path: Option<&Path>| {
+        path.map(|path| path.to_string_lossy().replace('\\', "/"))
+            .unwrap_or_default()
+    };
+    value
+        .replace("${sourceDir}", &name(Some(source_dir)))
+        .replace("${sourceParentDir}", &name(source_dir.parent()))
+        .replace(
+            "${sourceDirName}",
+            &source_dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        )
+        .replace("${presetName}", preset_name)
+        .replace("${dollar}", "$")
 }
 
 /// Default value for the `hidden` field in [`CMakePreset`].
@@ -110,6 +1203,30 @@ fn default_hidden() -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn escape_define_values() {
+        let define = |name: &str, value: &str| Defination {
+            name: name.to_string(),
+            value: value.to_string(),
+            once: false,
+        };
+        assert_eq!(
+            define("FLAGS", "-O2 -DX=\"a b\";c").to_arg(false).unwrap(),
+            "-DFLAGS=-O2 -DX=\"a b\";c"
+        );
+        assert_eq!(define("SEP", ", ").to_arg(false).unwrap(), "-DSEP=', '");
+        assert_eq!(define("Q", "'x'").to_arg(false).unwrap(), "-DQ=''x''");
+        assert_eq!(
+            define("OPT:BOOL", "ON").to_arg(false).unwrap(),
+            "-DOPT:BOOL=ON"
+        );
+        assert!(define("TEXT", "a\nb").to_arg(false).is_err());
+        assert!(define("A=B", "c").to_arg(false).is_err());
+        assert!(define("OPT:BOOLEAN", "ON").to_arg(false).is_err());
+        assert!(define(":BOOL", "ON").to_arg(false).is_err());
+        assert_eq!(define("TEXT", "a\nb").to_arg(true).unwrap(), "-DTEXT=a\nb");
+    }
+
     #[test]
     fn get_cmake_preset() {
         let presets = CMakePresets::new("test").unwrap();
@@ -118,4 +1235,270 @@ mod tests {
             .expect("Failed to get preset default");
         assert_eq!(preset.get_name(), "default");
     }
+
+    #[test]
+    fn preset_toolchain_and_versions() {
+        let preset: CMakePreset = serde_json::from_str(
+            r#"{"name": "arm", "cacheVariables": {"CMAKE_TOOLCHAIN_FILE": {"type": "FILEPATH", "value": "${sourceDir}/cmake/${presetName}.cmake"}}}"#,
+        )
+        .unwrap();
+        let toolchain = preset.toolchain_file().unwrap();
+        assert_eq!(
+            expand_macros(toolchain, Path::new("/src/app"), preset.get_name()),
+            "/src/app/cmake/arm.cmake"
+        );
+    }
+
+    #[test]
+    fn parse_and_match_versions() {
+        let version = |v: &str| CMakeVersion::parse(v).unwrap();
+        assert_eq!(version("3.28.3"), CMakeVersion::new(3, 28, 3));
+        assert_eq!(version("4.0.0-rc1"), CMakeVersion::new(4, 0, 0));
+        assert_eq!(
+            version("3.31.20250101-g1234abc"),
+            CMakeVersion::new(3, 31, 20250101)
+        );
+        assert_eq!(version("3.21").to_string(), "3.21.0");
+        assert_eq!(CMakeVersion::parse("latest"), None);
+
+        assert!(version("3.21.0").matches(">=3.21").unwrap());
+        assert!(version("3.21.0").matches("3.21").unwrap());
+        assert!(!version("3.20.6").matches(">=3.21").unwrap());
+        assert!(version("3.28.3").matches(">=3.21, <4").unwrap());
+        assert!(!version("4.0.0").matches(">=3.21, <4").unwrap());
+        assert!(version("3.28.3").matches("=3.28.3").unwrap());
+        assert!(matches!(
+            version("3.28.3").matches(">=three"),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn resolve_inherited_preset() {
+        let mut presets: CMakePresets = serde_json::from_str(
+            r#"{
+                "configurePresets": [
+                    {
+                        "name": "base",
+                        "hidden": true,
+                        "generator": "Ninja",
+                        "binaryDir": "${sourceDir}/out/${presetName}",
+                        "cacheVariables": {"CMAKE_BUILD_TYPE": "Debug", "WITH_TESTS": true, "OLD": "1"},
+                        "environment": {"CC": "gcc", "LEVEL": "base"}
+                    },
+                    {
+                        "name": "cross",
+                        "hidden": true,
+                        "toolchainFile": "cmake/$env{TARGET}.cmake",
+                        "environment": {"TARGET": "aarch64", "LEVEL": "cross"}
+                    },
+                    {
+                        "name": "release",
+                        "inherits": ["cross", "base"],
+                        "cacheVariables": {"CMAKE_BUILD_TYPE": {"type": "STRING", "value": "Release"}, "OLD": null},
+                        "environment": {"CC": null}
+                    },
+                    {"name": "loop", "inherits": "loop"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        presets.source_dir = PathBuf::from("/src/app");
+
+        let preset = presets.resolve("release").unwrap();
+        assert_eq!(preset.generator.as_deref(), Some("Ninja"));
+        assert_eq!(
+            preset.binary_dir,
+            Some(PathBuf::from("/src/app/out/release"))
+        );
+        assert_eq!(
+            preset.toolchain_file,
+            Some(PathBuf::from("/src/app/cmake/aarch64.cmake"))
+        );
+        assert_eq!(preset.cache_variables["CMAKE_BUILD_TYPE"], "Release");
+        assert_eq!(preset.cache_variables["WITH_TESTS"], "TRUE");
+        assert!(!preset.cache_variables.contains_key("OLD"));
+        assert_eq!(preset.environment["LEVEL"], "cross");
+        assert!(!preset.environment.contains_key("CC"));
+
+        assert!(matches!(
+            presets.resolve("base"),
+            Err(Error::PresetNotFound(_))
+        ));
+        assert!(matches!(
+            presets.resolve("loop"),
+            Err(Error::PresetParse(_))
+        ));
+    }
+
+    #[test]
+    fn merge_user_presets_and_includes() {
+        let temp = crate::guard::TempBuildDir::new("presets").unwrap();
+        let dir = temp.path();
+        std::fs::create_dir(dir.join("cmake")).unwrap();
+        std::fs::write(
+            dir.join("CMakePresets.json"),
+            r#"{"version": 4, "include": ["cmake/base.json"], "configurePresets": [{"name": "release", "inherits": "base"}]}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("cmake/base.json"),
+            r#"{"version": 4, "configurePresets": [{"name": "base", "hidden": true, "generator": "Ninja"}]}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("CMakeUserPresets.json"),
+            r#"{"version": 4, "include": ["CMakePresets.json", "cmake/base.json"], "configurePresets": [{"name": "mine", "inherits": "release"}]}"#,
+        )
+        .unwrap();
+
+        let presets = CMakePresets::new(dir).unwrap();
+        assert!(presets.get_preset("release").is_some());
+        let visible: Vec<String> = presets
+            .list_visible()
+            .into_iter()
+            .map(|preset| preset.name)
+            .collect();
+        assert_eq!(visible, ["release", "mine"]);
+        let mine = presets.resolve("mine").unwrap();
+        assert_eq!(mine.generator.as_deref(), Some("Ninja"));
+
+        std::fs::write(
+            dir.join("CMakeUserPresets.json"),
+            r#"{"version": 4, "configurePresets": [{"name": "release"}]}"#,
+        )
+        .unwrap();
+        let error = CMakePresets::new(dir).err().unwrap();
+        assert!(matches!(&error, Error::PresetParse(_)));
+        assert!(error.to_string().contains("\"release\""));
+
+        std::fs::write(dir.join("CMakeUserPresets.json"), "{").unwrap();
+        assert!(matches!(CMakePresets::new(dir), Err(Error::Json(_))));
+        std::fs::remove_file(dir.join("CMakeUserPresets.json")).unwrap();
+        std::fs::remove_file(dir.join("CMakePresets.json")).unwrap();
+        assert!(matches!(CMakePresets::new(dir), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn parse_all_preset_kinds() {
+        let presets: CMakePresets = serde_json::from_str(
+            r#"{
+                "version": 6,
+                "configurePresets": [{"name": "ci", "generator": "Ninja"}],
+                "buildPresets": [
+                    {"name": "base", "hidden": true},
+                    {"name": "ci-build", "configurePreset": "ci"}
+                ],
+                "testPresets": [{"name": "ci-test", "configurePreset": "ci"}],
+                "packagePresets": [{"name": "ci-package", "configurePreset": "ci"}],
+                "workflowPresets": [{
+                    "name": "ci-all",
+                    "steps": [
+                        {"type": "configure", "name": "ci"},
+                        {"type": "build", "name": "ci-build"}
+                    ]
+                }]
+            }"#,
+        )
+        .unwrap();
+        assert!(presets.get_build_preset("base").is_none());
+        let build = presets.get_build_preset("ci-build").unwrap();
+        assert_eq!(build.configure_preset(), Some("ci"));
+        assert!(presets.get_test_preset("ci-test").is_some());
+        assert!(presets.get_package_preset("ci-package").is_some());
+        let workflow = presets.get_workflow_preset("ci-all").unwrap();
+        let steps: Vec<(&str, &str)> = workflow
+            .steps()
+            .iter()
+            .map(|step| (step.kind.as_str(), step.name.as_str()))
+            .collect();
+        assert_eq!(steps, [("configure", "ci"), ("build", "ci-build")]);
+    }
+
+    #[test]
+    fn list_presets_with_conditions() {
+        let presets: CMakePresets = serde_json::from_str(
+            r#"{
+                "version": 6,
+                "configurePresets": [
+                    {
+                        "name": "base",
+                        "hidden": true,
+                        "generator": "Ninja",
+                        "binaryDir": "${sourceDir}/out/${presetName}"
+                    },
+                    {
+                        "name": "windows",
+                        "inherits": "base",
+                        "displayName": "Windows",
+                        "condition": {"type": "equals", "lhs": "${hostSystemName}", "rhs": "Windows"}
+                    },
+                    {
+                        "name": "unix",
+                        "inherits": "base",
+                        "description": "Any Unix",
+                        "condition": {
+                            "type": "not",
+                            "condition": {"type": "inList", "string": "${hostSystemName}", "list": ["Windows"]}
+                        }
+                    },
+                    {"name": "never", "condition": {"type": "allOf", "conditions": [{"type": "const", "value": false}]}}
+                ],
+                "workflowPresets": [{"name": "all", "displayName": "Everything"}]
+            }"#,
+        )
+        .unwrap();
+        let list = presets.list();
+        assert_eq!(list.len(), 5);
+        let unix = &list[2];
+        assert_eq!(unix.generator.as_deref(), Some("Ninja"));
+        assert_eq!(unix.description.as_deref(), Some("Any Unix"));
+        assert!(unix.binary_dir.as_ref().unwrap().ends_with("out/unix"));
+        assert_eq!(list[1].condition_met, cfg!(windows));
+        assert!(!list[3].condition_met);
+
+        let visible: Vec<(&str, String)> = presets
+            .list_visible()
+            .into_iter()
+            .map(|preset| (preset.kind, preset.name))
+            .collect();
+        let host = if cfg!(windows) { "windows" } else { "unix" };
+        assert_eq!(
+            visible,
+            [
+                ("configure", host.to_string()),
+                ("workflow", "all".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn list_capability_generators() {
+        let capabilities = serde_json::json!({
+            "generators": [
+                {"name": "Ninja", "extraGenerators": ["CodeBlocks"]},
+                {"name": "Unix Makefiles", "extraGenerators": []}
+            ]
+        });
+        assert_eq!(
+            generator_names(&capabilities),
+            vec!["Ninja", "CodeBlocks - Ninja", "Unix Makefiles"]
+        );
+    }
+
+    #[test]
+    fn require_capabilities() {
+        let old = CMakeVersion::new(3, 16, 3);
+        assert!(old.supports(Capability::LogLevel));
+        assert!(!old.supports(Capability::Toolchain));
+        assert!(Capability::Fresh.require(None).is_ok());
+        assert!(Capability::Install.require(Some(old)).is_ok());
+        match Capability::OutputJunit.require(Some(old)) {
+            Err(Error::Unsupported(message)) => assert_eq!(
+                message,
+                "ctest --output-junit requires CMake >= 3.21.0, found 3.16.3"
+            ),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
@@ -5,18 +5,371 @@
 //! via [`Cmd::build`] and asynchronous execution via [`Cmd::spawn`].
 
 use std::{
-    path::PathBuf,
-    sync::mpsc::{self, Receiver},
+    borrow::Cow,
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvError, RecvTimeoutError, TryRecvError},
+    },
     thread,
+    time::{Duration, Instant, SystemTime},
 };
 
-use crate::cmake::{CMakePresets, Defination};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    artifact::{self, Action},
+    bundle,
+    ci::{self, CiFlavor},
+    cmake::{self, CMakePresets, CMakeVersion, Capability, Defination, ResolvedPreset},
+    command::{CommandPlan, PreparedCommand},
+    compiler_cache, config,
+    cpack::CPackCmd,
+    credentials::{Credentials, CredentialsProvider, Redactor},
+    ctest::CTestCmd,
+    diagnostics,
+    embedded::EmbeddedToolchain,
+    emscripten, encoding,
+    error::Error,
+    fetch_cache,
+    fileapi::{self, Target},
+    fingerprint::{self, FileMetadata, Fingerprinter},
+    gc,
+    graph::{self, BuildGraph},
+    hooks::{self, HookContext, Hooks},
+    ide::{self, IdeIntegration},
+    impact, instrument,
+    interop::BuildDescription,
+    launcher::{self, Launcher},
+    lock::DirLock,
+    machine::{self, Emitter, Format, ProgressEvent},
+    mobile::{self, AndroidAbi, IosPlatform},
+    msvc, notices,
+    notify::{Notification, Notifier},
+    offline,
+    oom::{self, MemoryMonitor, OomBackoff},
+    output::{OutputColor, OutputStreams, OutputStyle},
+    pgo::PgoConfig,
+    pipeline::{Pipeline, Stage},
+    pool,
+    process::{self, CancelToken, LineHandler},
+    rebuild::RebuildReason,
+    report::{
+        BuildFailure, BuildReport, CompilerCacheStats, Phase, PhaseTiming, ProcessExit, remap_path,
+    },
+    repro,
+    retry::{Retry, RetryPolicy},
+    sanitizer,
+    sanitizer::Sanitizer,
+    sdk,
+    seed::CacheSeed,
+    size::{self, SizeAnalysis},
+    source_copy,
+    state::{BuildState, Configuration},
+    toolchain::ToolchainFile,
+    tools::Tools,
+    trace::TraceFormat,
+    watchdog::{Trigger, Watchdog},
+};
 
 /// The result type returned by CMake execution methods.
 ///
-/// Returns `Ok(())` on success, or an error describing the failure
-/// (e.g., cmake not found, configure/build failure, I/O error).
-type ExecResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+/// Returns a [`BuildReport`] on success, or an error describing the failure
+/// (e.g., cmake not found, configure/build failure, I/O error). A failed
+/// build step is reported as a [`BuildFailure`], which carries the report.
+type ExecResult = Result<BuildReport, Error>;
+
+/// What happens when [`Cmd::add_define`] is called more than once for a name.
+///
+/// Names are compared without a type suffix, so `NAME:BOOL` and `NAME`
+/// define the same variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DefinePolicy {
+    /// The value added last is used.
+    #[default]
+    LastWins,
+    /// The value added first is used; later ones are ignored.
+    FirstWins,
+    /// The build fails before running cmake.
+    Error,
+}
+
+/// The order of the definitions on the configure command line.
+///
+/// cmake applies `-D` arguments from left to right, so a variable defined
+/// twice gets the later value, and project logic reading the cache can
+/// depend on the order. The configure command line is always:
+///
+/// 1. `--fresh`, the [cache seed](Cmd::seed_cache) (`-C`), `-S`, `-B`,
+///    `--preset`, and `-G`/`-A`/`-T`;
+/// 2. the definitions, ordered by this policy: the user's
+///    ([`add_define`](Cmd::add_define), in the order they were added, with
+///    repetitions resolved by the [`DefinePolicy`]) and the ones cmakr
+///    derives, in this order: the toolchain file, the cross-compiling,
+///    compiler flag, and option defines, the output directory, build type,
+///    and make program defines, and the `FetchContent` and offline defines;
+/// 3. [`add_arg`](Cmd::add_arg) and
+///    [`add_configure_arg`](Cmd::add_configure_arg) arguments, in the order
+///    they were added, and the log level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArgOrder {
+    /// The user's definitions in the order they were added, then the
+    /// derived ones.
+    #[default]
+    Insertion,
+    /// The user's definitions sorted by variable name, then the derived
+    /// ones, so that the command line doesn't depend on the order of the
+    /// builder calls.
+    Sorted,
+    /// The derived definitions, then the user's in the order they were
+    /// added, so that a user's definition of a variable cmakr derives is
+    /// the one cmake sees last.
+    UserDefinesLast,
+}
+
+/// How a [`Cmd`] waits for other processes using the same build directory.
+///
+/// Configuring or building one tree from two processes at once corrupts its
+/// `CMakeCache.txt`, e.g. when parallel cargo builds of workspace members
+/// share a native build tree. A run therefore holds an advisory lock on the
+/// build directory from configure to install. Locks of processes that exit
+/// are released by the operating system, so a crashed run never blocks
+/// later ones.
+///
+/// Locking is on by default; [`Cmd::lock_build_dir`] turns it off. The
+/// policy applies to the lock on a shared
+/// [fetch cache](Cmd::set_fetch_cache_dir) as well, which is always taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LockPolicy {
+    /// Wait until the other process is done.
+    #[default]
+    Wait,
+    /// Wait at most this long, then fail with [`Error::BuildDirLocked`]. A
+    /// zero duration fails right away if the directory is in use.
+    Timeout(Duration),
+    /// Don't lock the build directory.
+    Disabled,
+}
+
+impl LockPolicy {
+    /// Returns how long a lock is waited for, `None` meaning indefinitely.
+    fn timeout(self) -> Option<Duration> {
+        match self {
+            LockPolicy::Timeout(timeout) => Some(timeout),
+            LockPolicy::Wait | LockPolicy::Disabled => None,
+        }
+    }
+}
+
+/// What happens when the build directory is the source directory, or a
+/// directory of the source tree that has its own `CMakeLists.txt`.
+///
+/// Such in-source builds scatter `CMakeCache.txt`, `CMakeFiles/`, and
+/// generated build files between the sources, which is hard to undo in a
+/// vendored or checked-out source tree. A dedicated subdirectory such as
+/// `<source>/build` is not an in-source build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InSourcePolicy {
+    /// Print a warning to stderr suggesting a separate build directory, then
+    /// build anyway.
+    #[default]
+    Warn,
+    /// The build fails before running cmake.
+    Error,
+    /// Build without a warning.
+    Allow,
+}
+
+/// What happens when the source directory is not writable, as for crates
+/// unpacked into the cargo registry.
+///
+/// Projects that generate files next to their sources fail to configure in
+/// such a tree, usually with an error that doesn't mention permissions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadOnlySourcePolicy {
+    /// Copy the sources into the build directory and build from the copy.
+    /// Reported paths are mapped back to the original sources.
+    #[default]
+    Copy,
+    /// The build fails before running cmake.
+    Error,
+    /// Build from the read-only sources.
+    Build,
+}
+
+/// The build types CMake defines, for [`Cmd::set_build_type`].
+///
+/// Projects may define others; pass their names as strings instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildType {
+    /// No optimization, with debug information.
+    Debug,
+    /// Optimized, without debug information.
+    Release,
+    /// Optimized, with debug information.
+    RelWithDebInfo,
+    /// Optimized for size.
+    MinSizeRel,
+}
+
+impl BuildType {
+    /// Returns the name CMake uses for the build type (e.g. `"RelWithDebInfo"`).
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BuildType::Debug => "Debug",
+            BuildType::Release => "Release",
+            BuildType::RelWithDebInfo => "RelWithDebInfo",
+            BuildType::MinSizeRel => "MinSizeRel",
+        }
+    }
+}
+
+impl From<BuildType> for String {
+    fn from(build_type: BuildType) -> Self {
+        build_type.as_str().to_string()
+    }
+}
+
+/// The message log levels of configure, for [`Cmd::log_level`].
+///
+/// Each level includes the messages of the levels before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Only errors.
+    Error,
+    /// Errors and warnings.
+    Warning,
+    /// `message(NOTICE)` and above, which is what `message("...")` prints.
+    Notice,
+    /// `message(STATUS)` and above, CMake's default.
+    Status,
+    /// `message(VERBOSE)` and above.
+    Verbose,
+    /// `message(DEBUG)` and above.
+    Debug,
+    /// Everything, including `message(TRACE)`.
+    Trace,
+}
+
+impl LogLevel {
+    /// Returns the name CMake uses for the level (e.g. `"WARNING"`).
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warning => "WARNING",
+            LogLevel::Notice => "NOTICE",
+            LogLevel::Status => "STATUS",
+            LogLevel::Verbose => "VERBOSE",
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Trace => "TRACE",
+        }
+    }
+}
+
+/// The C++ standards CMake knows for `CMAKE_CXX_STANDARD`.
+const CXX_STANDARDS: [u32; 7] = [98, 11, 14, 17, 20, 23, 26];
+
+/// A build running in the background, returned by [`Cmd::spawn`].
+///
+/// The result is delivered once; after [`recv`](BuildHandle::recv),
+/// [`try_recv`](BuildHandle::try_recv), or
+/// [`wait_timeout`](BuildHandle::wait_timeout) has returned it, further calls
+/// report a disconnected channel.
+pub struct BuildHandle {
+    receiver: Receiver<ExecResult>,
+    cancel: CancelToken,
+    running: Arc<AtomicBool>,
+}
+
+impl BuildHandle {
+    /// Asks the build to stop: the running cmake process and everything it
+    /// started receive `SIGTERM` (and are killed after a grace period of a few
+    /// seconds), and no further phase is started. The result is
+    /// [`Error::Cancelled`]. On Windows, this is the same as
+    /// [`kill`](BuildHandle::kill).
+    pub fn cancel(&self) {
+        self.cancel.terminate();
+    }
+
+    /// Kills the running cmake process and everything it started right away,
+    /// and starts no further phase. The result is [`Error::Cancelled`].
+    pub fn kill(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Returns whether the build is queued or running.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Blocks until the build finishes and returns its result.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RecvError`] if the result was already received.
+    pub fn recv(&self) -> Result<ExecResult, RecvError> {
+        self.receiver.recv()
+    }
+
+    /// Returns the result if the build has finished, without blocking.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TryRecvError::Empty`] while the build is running, and
+    /// [`TryRecvError::Disconnected`] if the result was already received.
+    pub fn try_recv(&self) -> Result<ExecResult, TryRecvError> {
+        self.receiver.try_recv()
+    }
+
+    /// Waits at most `timeout` for the build to finish and returns its result.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RecvTimeoutError::Timeout`] if the build is still running
+    /// after `timeout`, and [`RecvTimeoutError::Disconnected`] if the result
+    /// was already received.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<ExecResult, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+}
+
+/// A snapshot of what a [`Cmd`] would run with, for logging and testing.
+///
+/// Returned by [`Cmd::effective_settings`], with defaults applied, paths
+/// made absolute, and repeated defines resolved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EffectiveSettings {
+    /// The source directory.
+    pub source_dir: PathBuf,
+    /// The build directory.
+    pub binary_dir: PathBuf,
+    /// The artifact output directory.
+    pub output_dir: PathBuf,
+    pub preset: Option<String>,
+    /// The generator of an already configured tree, or else the one selected
+    /// with [`set_generator`](Cmd::set_generator), or else the one selected
+    /// by the `CMAKE_GENERATOR` environment variable. `None` means the
+    /// preset's or cmake's default.
+    pub generator: Option<String>,
+    /// The `-D` definitions, including the ones cmakr adds (output
+    /// directories, fetch cache, debug postfix), with redactions applied.
+    pub defines: Vec<(String, String)>,
+    /// The extra arguments, with redactions applied.
+    pub args: Vec<String>,
+    /// The prefix of [`set_install_prefix`](Cmd::set_install_prefix) or
+    /// [`install_only`](Cmd::install_only), if set.
+    pub install_prefix: Option<PathBuf>,
+    /// The components of [`add_install_component`](Cmd::add_install_component).
+    pub install_components: Vec<String>,
+    /// The toolchain file of [`set_toolchain_file`](Cmd::set_toolchain_file), if set.
+    pub toolchain_file: Option<PathBuf>,
+    /// The subdirectory of [`restrict_to_subdirectory`](Cmd::restrict_to_subdirectory), if set.
+    pub subdirectory: Option<PathBuf>,
+}
 
 /// A builder for constructing and executing CMake commands.
 ///
@@ -28,6 +381,8 @@ type ExecResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 /// The execution performs two steps:
 /// 1. **Configure** - runs `cmake -S <source> -B <binary> [--preset=<name>] [defines] [args]`
 /// 2. **Build** - runs `cmake --build <binary> [args]`
+/// 3. **Install** - runs `cmake --install <binary> --prefix <prefix>`, only if
+///    an [install prefix](Cmd::set_install_prefix) is set
 ///
 /// # Defaults
 ///
@@ -53,41 +408,651 @@ type ExecResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 ///     .build();
 ///
 /// match result {
-///     Ok(()) => println!("Build succeeded"),
+///     Ok(_) => println!("Build succeeded"),
 ///     Err(e) => eprintln!("Build failed: {}", e),
 /// }
 /// ```
+#[derive(Clone)]
 pub struct Cmd {
     /// Extra arguments passed to both configure and build steps.
     args: Vec<String>,
+    /// Extra arguments passed to the configure step only.
+    configure_args: Vec<String>,
+    /// Extra arguments passed to the build step only.
+    build_args: Vec<String>,
+    /// Arguments forwarded to the native build tool after `--`.
+    native_build_args: Vec<String>,
+    /// Explicitly set `cmake`, `ctest`, `cpack`, and `ninja` executables.
+    tools: Tools,
+    /// The cmake version the run requires, like `">=3.21"`.
+    required_version: Option<String>,
     /// CMake source directory (passed as `-S`). Defaults to `"."`.
     path: Option<PathBuf>,
+    /// Working directory of the child processes, which relative paths are
+    /// resolved against.
+    current_dir: Option<PathBuf>,
     /// CMake build directory (passed as `-B`). Defaults to `"build"`.
     binary_path: PathBuf,
+    /// Whether the build directory was set, rather than left to the preset.
+    binary_path_set: bool,
+    /// Whether the build directory is moved to a short one, see
+    /// [`Cmd::short_build_dir`].
+    short_build_dir: bool,
+    /// The root of the short build directories, see
+    /// [`Cmd::set_short_build_root`].
+    short_build_root: Option<PathBuf>,
+    /// The build directory of the configuration, if the run moved it to a
+    /// short one.
+    relocated_from: Option<PathBuf>,
     /// Output directory for built artifacts (`CMAKE_RUNTIME_OUTPUT_DIRECTORY`,
     /// `CMAKE_LIBRARY_OUTPUT_DIRECTORY`, `CMAKE_ARCHIVE_OUTPUT_DIRECTORY`).
     /// Defaults to `"build"`.
     output_path: PathBuf,
+    /// Output directory for executables and DLLs, instead of `output_path`.
+    runtime_output_path: Option<PathBuf>,
+    /// Output directory for shared and module libraries, instead of
+    /// `output_path`.
+    library_output_path: Option<PathBuf>,
+    /// Output directory for static and import libraries, instead of
+    /// `output_path`.
+    archive_output_path: Option<PathBuf>,
     /// Optional CMake preset name (passed as `--preset=<name>`).
     preset: Option<String>,
+    /// Optional generator (passed as `-G <name>` at configure).
+    generator: Option<String>,
+    /// Optional generator platform (passed as `-A <platform>` at configure).
+    generator_platform: Option<String>,
+    /// Optional generator toolset (passed as `-T <toolset>` at configure).
+    generator_toolset: Option<String>,
+    /// Generators to configure a fresh tree with when the selected one is
+    /// unavailable, in order of preference.
+    fallback_generators: Vec<String>,
+    /// Optional build preset name (passed as `cmake --build --preset=<name>`).
+    build_preset: Option<String>,
+    /// Build type, passed as `CMAKE_BUILD_TYPE` or `--config` depending on the generator.
+    build_type: Option<String>,
+    /// Configuration of the build and install steps (passed as `--config <config>`).
+    config: Option<String>,
+    /// Optional test preset name, used by [`ctest`](Cmd::ctest).
+    test_preset: Option<String>,
     /// Custom CMake variable definitions (passed as `-D<name>=<value>`).
     defines: Vec<Defination>,
+    /// Whether define values are passed on without escaping or validation.
+    raw_defines: bool,
+    /// Optional toolchain file (passed as `--toolchain` or `-DCMAKE_TOOLCHAIN_FILE`).
+    toolchain_file: Option<PathBuf>,
+    /// Toolchain file written into the build directory and used, unless a
+    /// toolchain file path is set.
+    generated_toolchain: Option<ToolchainFile>,
+    /// Optional target system of a cross build (passed as `-DCMAKE_SYSTEM_NAME=<name>`).
+    system_name: Option<String>,
+    /// Optional target processor of a cross build (passed as `-DCMAKE_SYSTEM_PROCESSOR=<name>`).
+    system_processor: Option<String>,
+    /// Optional sysroot of a cross build (passed as `-DCMAKE_SYSROOT=<path>`).
+    sysroot: Option<PathBuf>,
+    /// Optional staging prefix of a cross build (passed as `-DCMAKE_STAGING_PREFIX=<path>`).
+    staging_prefix: Option<PathBuf>,
+    /// How repeated definitions of the same variable are resolved.
+    define_policy: DefinePolicy,
+    /// The order of the definitions on the configure command line.
+    arg_order: ArgOrder,
+    /// How concurrent runs on the same build directory are serialized.
+    lock_policy: LockPolicy,
+    /// What happens when the build directory is inside the sources.
+    in_source_policy: InSourcePolicy,
+    /// What happens when the source directory is not writable.
+    read_only_source_policy: ReadOnlySourcePolicy,
+    /// Optional shared cache of `FetchContent` sources (passed as
+    /// `-DFETCHCONTENT_SOURCE_DIR_<NAME>=<path>` per cached dependency).
+    fetch_cache_dir: Option<PathBuf>,
+    /// Optional user-level cache of compiler detection results.
+    compiler_cache_dir: Option<PathBuf>,
+    /// Renaming and copying rules for the artifacts of a successful build.
+    artifact_rules: Vec<artifact::Rule>,
+    /// Optional launcher of the C and C++ compilers, e.g. a compiler cache.
+    compiler_launcher: Option<Launcher>,
+    /// Sanitizers compiled into the C and C++ code.
+    sanitizers: Vec<Sanitizer>,
+    /// Whether to compile with link-time optimization.
+    lto: bool,
+    /// Optional C++ standard, passed as `CMAKE_CXX_STANDARD`.
+    cxx_standard: Option<u32>,
+    /// Optional `BUILD_SHARED_LIBS` value.
+    shared_libs: Option<bool>,
+    /// Directories searched by `find_package`, passed as `CMAKE_PREFIX_PATH`.
+    prefix_path: Vec<PathBuf>,
+    /// Prefix to install into after the build step.
+    install_prefix: Option<PathBuf>,
+    /// Components installed one by one instead of the whole project.
+    install_components: Vec<String>,
+    /// Whether the install step replaces the build step, for header-only projects.
+    install_only: bool,
+    /// Whether `CMAKE_DEBUG_POSTFIX` is cleared so debug libraries keep their names.
+    strip_debug_postfix: bool,
+    /// The editor integration refreshed after every configure.
+    ide: Option<IdeIntegration>,
+    /// Whether configure only prints errors, and is replayed verbosely if it fails.
+    quiet_configure: bool,
+    /// Whether the native build tool keeps going after a failed job.
+    keep_going: bool,
+    /// Whether configure discards the cache of an existing tree.
+    fresh: bool,
+    /// Whether the build prints the commands it runs.
+    verbose: bool,
+    /// Log level of configure, if not CMake's default.
+    log_level: Option<LogLevel>,
+    /// File the target graph is written to by configure.
+    graphviz: Option<PathBuf>,
+    /// Format of the configure trace, if configure is traced.
+    trace: Option<TraceFormat>,
+    /// Whether configure is skipped if its inputs match the last configure.
+    skip_unchanged_configure: bool,
+    /// How the CMake files are fingerprinted for skipping configure.
+    fingerprinter: Arc<dyn Fingerprinter>,
+    /// Whether the build step cleans the tree before building.
+    clean_first: bool,
+    /// Retry policy for builds killed by running out of memory.
+    oom_backoff: Option<OomBackoff>,
+    /// Retry policy for configure and build steps that failed transiently.
+    retry: Option<RetryPolicy>,
+    /// How the binaries of a successful build are measured, if at all.
+    size_analysis: Option<SizeAnalysis>,
+    /// The warnings a build may have, see [`Cmd::fail_on_new_warnings`].
+    warnings_baseline: Option<PathBuf>,
+    /// The directory dependency license files are collected in, if any.
+    notices_dir: Option<PathBuf>,
+    /// Timeouts and stall detection of the phases that have them.
+    watchdogs: Vec<(Phase, Watchdog)>,
+    /// Heartbeat interval and line of silent commands.
+    keepalive: Option<(Duration, String)>,
+    /// How long the commands of a phase may run, by phase.
+    timeouts: Vec<(Phase, Duration)>,
+    /// Source subdirectory whose targets are the only ones built.
+    subdirectory: Option<PathBuf>,
+    /// Targets passed to the build step as `--target`.
+    targets: Vec<String>,
+    /// The `--parallel` job count of the build step.
+    jobs: Option<usize>,
+    /// Targets built by concurrent invocations, and their shared job budget.
+    parallel_targets: Option<(Vec<String>, usize)>,
+    /// Path prefixes rewritten in reported locations, as `(from, to)` pairs.
+    path_remaps: Vec<(PathBuf, PathBuf)>,
+    /// CI system to print annotations for parsed diagnostics to.
+    ci_annotations: Option<CiFlavor>,
+    /// Where and how progress events are written, if enabled.
+    machine_output: Option<Emitter>,
+    /// Whether output is only captured into the report, not forwarded to
+    /// the parent's streams.
+    capture_output: bool,
+    /// How forwarded output lines are prefixed or buffered.
+    output_style: OutputStyle,
+    /// Handles the output of configure, build, and install is written to
+    /// directly, instead of being forwarded.
+    output_streams: Option<OutputStreams>,
+    /// Whether configure, build, and install run in a pseudo-terminal.
+    #[cfg(feature = "pty")]
+    pty: bool,
+    /// The steps a run executes.
+    steps: Steps,
+    /// Where the spans of a run are exported to.
+    #[cfg(feature = "otel")]
+    otlp_exporter: Option<crate::otel::OtlpExporter>,
+    /// Who is told about the outcome of a run.
+    notifiers: Vec<Arc<dyn Notifier>>,
+    /// Callbacks run around the configure and build phases.
+    hooks: Hooks,
+    /// Whether a failed run writes reproduction scripts into the build directory.
+    repro_script: bool,
+    /// Path of the diagnostic archive written when a run fails.
+    failure_bundle: Option<PathBuf>,
+    /// Cache entries used to pre-populate a fresh build tree.
+    cache_seed: Option<CacheSeed>,
+    /// Token used by orchestrators to kill a running build.
+    cancel: Option<CancelToken>,
+    /// Variables set (or, if `None`, removed) in the environment of cmake.
+    environment: Vec<(String, Option<String>)>,
+    /// Whether cmake starts from an empty environment instead of inheriting it.
+    environment_cleared: bool,
+    /// Target architecture of a Visual Studio environment to set up on
+    /// Windows, unless one is active.
+    msvc_arch: Option<msvc::Arch>,
+    /// Whether the compilers are asked for English diagnostics.
+    english_diagnostics: bool,
+    /// Provider of secrets injected into the environment of cmake.
+    credentials: Option<Arc<dyn CredentialsProvider>>,
+    /// Credentials obtained from the provider for the running build.
+    resolved_credentials: Credentials,
+    /// Additional secrets and `NAME=` prefixes of secret values to redact.
+    redactions: Vec<String>,
 }
 
-impl Cmd {
+/// The steps a run of [`Cmd`] executes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Steps {
+    /// Configure, then build (and install, if requested).
+    #[default]
+    All,
+    /// Configure only, see [`Cmd::configure`].
+    ConfigureOnly,
+    /// Build (and install) an already configured tree, see [`Cmd::build_only`].
+    BuildOnly,
+    /// Install an already built tree, see [`Cmd::run_pipeline`].
+    InstallOnly,
+}
+
+impl Steps {
+    /// Returns whether the run configures the tree.
+    fn configures(self) -> bool {
+        matches!(self, Steps::All | Steps::ConfigureOnly)
+    }
+}
+
+/// A declarative description of a [`Cmd`], e.g. read from a JSON file.
+///
+/// Every field is optional and defaults to the builder's default. Convert
+/// it with [`Cmd::try_from`], or parse JSON directly with [`str::parse`]:
+///
+/// ```no_run
+/// use cmakr::Cmd;
+///
+/// let cmd: Cmd = r#"{"path": "./my_project", "preset": "release", "defines": {"BUILD_TESTING": "OFF"}}"#
+///     .parse()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CmdConfig {
+    pub path: Option<String>,
+    pub binary_path: Option<String>,
+    pub output_path: Option<String>,
+    /// See [`Cmd::set_runtime_output_path`].
+    pub runtime_output_path: Option<String>,
+    /// See [`Cmd::set_library_output_path`].
+    pub library_output_path: Option<String>,
+    /// See [`Cmd::set_archive_output_path`].
+    pub archive_output_path: Option<String>,
+    pub preset: Option<String>,
+    pub build_preset: Option<String>,
+    /// Build type, see [`Cmd::set_build_type`].
+    pub build_type: Option<String>,
+    /// Configuration of a multi-config generator, see [`Cmd::set_config`].
+    pub config: Option<String>,
+    pub test_preset: Option<String>,
+    pub generator: Option<String>,
+    pub generator_platform: Option<String>,
+    pub generator_toolset: Option<String>,
+    /// Fallback generators, see [`Cmd::fallback_generators`].
+    pub fallback_generators: Vec<String>,
+    /// Toolchain file, see [`Cmd::set_toolchain_file`].
+    pub toolchain_file: Option<String>,
+    /// Target system of a cross build, see [`Cmd::set_system_name`].
+    pub system_name: Option<String>,
+    /// Target processor of a cross build, see [`Cmd::set_system_processor`].
+    pub system_processor: Option<String>,
+    /// Sysroot of a cross build, see [`Cmd::set_sysroot`].
+    pub sysroot: Option<String>,
+    /// Staging prefix of a cross build, see [`Cmd::set_staging_prefix`].
+    pub staging_prefix: Option<String>,
+    /// `-D` definitions by name.
+    pub defines: BTreeMap<String, String>,
+    pub args: Vec<String>,
+    /// Arguments of the configure step only, see [`Cmd::add_configure_arg`].
+    pub configure_args: Vec<String>,
+    /// Arguments of the build step only, see [`Cmd::add_build_arg`].
+    pub build_args: Vec<String>,
+    /// Arguments of the native build tool, see [`Cmd::add_native_build_arg`].
+    pub native_build_args: Vec<String>,
+    pub fetch_cache_dir: Option<String>,
+    pub compiler_cache_dir: Option<String>,
+    /// Compiler launcher: `"ccache"`, `"sccache"`, `"auto"`, or a program,
+    /// see [`Cmd::with_compiler_launcher`].
+    pub compiler_launcher: Option<String>,
+    /// Sanitizers by name (`"address"`, `"undefined"`, `"thread"`), see
+    /// [`Cmd::enable_sanitizer`].
+    pub sanitizers: Vec<Sanitizer>,
+    /// See [`Cmd::enable_lto`].
+    pub lto: bool,
+    /// See [`Cmd::set_cxx_standard`].
+    pub cxx_standard: Option<u32>,
+    /// See [`Cmd::enable_shared_libs`].
+    pub shared_libs: Option<bool>,
+    /// See [`Cmd::set_prefix_path`].
+    pub prefix_path: Vec<String>,
+    pub keep_going: bool,
+    pub raw_defines: bool,
+    /// Targets to build, see [`Cmd::add_target`].
+    pub targets: Vec<String>,
+    /// Parallel jobs of the build step, see [`Cmd::set_jobs`].
+    pub jobs: Option<usize>,
+    /// Prefix to install into after the build, see [`Cmd::set_install_prefix`].
+    pub install_prefix: Option<String>,
+    /// Short build directory, see [`Cmd::short_build_dir`].
+    pub short_build_dir: bool,
+    /// Root of the short build directories, see [`Cmd::set_short_build_root`].
+    pub short_build_root: Option<String>,
+    /// Components to install, see [`Cmd::add_install_component`].
+    pub install_components: Vec<String>,
+    /// Environment variables to set, see [`Cmd::env`].
+    pub env: BTreeMap<String, String>,
+    /// Environment variables to remove, see [`Cmd::env_remove`].
+    pub env_remove: Vec<String>,
+    /// Whether to start from an empty environment, see [`Cmd::env_clear`].
+    pub env_clear: bool,
+}
+
+impl From<&Cmd> for CmdConfig {
+    /// Describes the settings of `cmd` that a config has fields for.
+    ///
+    /// Definitions added with [`Cmd::add_define_once`] become plain ones.
+    fn from(cmd: &Cmd) -> Self {
+        let path = |path: &Path| path.to_string_lossy().into_owned();
+        let env = cmd
+            .environment
+            .iter()
+            .filter_map(|(name, value)| Some((name.clone(), value.clone()?)))
+            .collect();
+        let env_remove = cmd
+            .environment
+            .iter()
+            .filter(|(_, value)| value.is_none())
+            .map(|(name, _)| name.clone())
+            .collect();
+        CmdConfig {
+            path: cmd.path.as_deref().map(path),
+            binary_path: cmd.binary_path_set.then(|| path(&cmd.binary_path)),
+            output_path: Some(path(&cmd.output_path)),
+            runtime_output_path: cmd.runtime_output_path.as_deref().map(path),
+            library_output_path: cmd.library_output_path.as_deref().map(path),
+            archive_output_path: cmd.archive_output_path.as_deref().map(path),
+            preset: cmd.preset.clone(),
+            build_preset: cmd.build_preset.clone(),
+            build_type: cmd.build_type.clone(),
+            config: cmd.config.clone(),
+            test_preset: cmd.test_preset.clone(),
+            generator: cmd.generator.clone(),
+            generator_platform: cmd.generator_platform.clone(),
+            generator_toolset: cmd.generator_toolset.clone(),
+            fallback_generators: cmd.fallback_generators.clone(),
+            toolchain_file: cmd.toolchain_file.as_deref().map(path),
+            system_name: cmd.system_name.clone(),
+            system_processor: cmd.system_processor.clone(),
+            sysroot: cmd.sysroot.as_deref().map(path),
+            staging_prefix: cmd.staging_prefix.as_deref().map(path),
+            defines: cmd
+                .defines()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            args: cmd.args.clone(),
+            configure_args: cmd.configure_args.clone(),
+            build_args: cmd.build_args.clone(),
+            native_build_args: cmd.native_build_args.clone(),
+            fetch_cache_dir: cmd.fetch_cache_dir.as_deref().map(path),
+            compiler_cache_dir: cmd.compiler_cache_dir.as_deref().map(path),
+            compiler_launcher: cmd.compiler_launcher.as_ref().map(Launcher::to_string),
+            sanitizers: cmd.sanitizers.clone(),
+            lto: cmd.lto,
+            cxx_standard: cmd.cxx_standard,
+            shared_libs: cmd.shared_libs,
+            prefix_path: cmd.prefix_path.iter().map(|p| path(p)).collect(),
+            keep_going: cmd.keep_going,
+            raw_defines: cmd.raw_defines,
+            targets: cmd.targets.clone(),
+            jobs: cmd.jobs,
+            install_prefix: cmd.install_prefix.as_deref().map(path),
+            short_build_dir: cmd.short_build_dir,
+            short_build_root: cmd.short_build_root.as_deref().map(path),
+            install_components: cmd.install_components.clone(),
+            env,
+            env_remove,
+            env_clear: cmd.environment_cleared,
+        }
+    }
+}
+
+impl Default for Cmd {
     /// Creates a new [`Cmd`] with default settings.
     ///
     /// The default configuration uses `"build"` as both the binary and output
     /// directory paths. No source path, preset, or custom defines are set.
-    pub fn default() -> Self {
-        Self {
+    /// The crate-wide [defaults](crate::config) are applied on top.
+    fn default() -> Self {
+        let cmd = Self {
             args: Vec::new(),
+            configure_args: Vec::new(),
+            build_args: Vec::new(),
+            native_build_args: Vec::new(),
+            tools: Tools::default(),
+            required_version: None,
             path: None,
+            current_dir: None,
             binary_path: PathBuf::from("build"),
+            binary_path_set: false,
+            short_build_dir: false,
+            short_build_root: None,
+            relocated_from: None,
             output_path: PathBuf::from("build"),
+            runtime_output_path: None,
+            library_output_path: None,
+            archive_output_path: None,
             preset: None,
+            generator: None,
+            generator_platform: None,
+            generator_toolset: None,
+            fallback_generators: Vec::new(),
+            build_preset: None,
+            build_type: None,
+            config: None,
+            test_preset: None,
             defines: Vec::new(),
+            raw_defines: false,
+            toolchain_file: None,
+            generated_toolchain: None,
+            system_name: None,
+            system_processor: None,
+            sysroot: None,
+            staging_prefix: None,
+            define_policy: DefinePolicy::default(),
+            arg_order: ArgOrder::default(),
+            lock_policy: LockPolicy::default(),
+            in_source_policy: InSourcePolicy::default(),
+            read_only_source_policy: ReadOnlySourcePolicy::default(),
+            fetch_cache_dir: None,
+            compiler_cache_dir: None,
+            compiler_launcher: None,
+            sanitizers: Vec::new(),
+            lto: false,
+            cxx_standard: None,
+            shared_libs: None,
+            prefix_path: Vec::new(),
+            artifact_rules: Vec::new(),
+            install_prefix: None,
+            install_components: Vec::new(),
+            install_only: false,
+            strip_debug_postfix: false,
+            ide: None,
+            quiet_configure: false,
+            keep_going: false,
+            fresh: false,
+            verbose: false,
+            log_level: None,
+            graphviz: None,
+            trace: None,
+            skip_unchanged_configure: false,
+            fingerprinter: Arc::new(FileMetadata),
+            clean_first: false,
+            oom_backoff: None,
+            retry: None,
+            size_analysis: None,
+            warnings_baseline: None,
+            notices_dir: None,
+            watchdogs: Vec::new(),
+            keepalive: None,
+            timeouts: Vec::new(),
+            subdirectory: None,
+            targets: Vec::new(),
+            jobs: None,
+            parallel_targets: None,
+            path_remaps: Vec::new(),
+            ci_annotations: None,
+            machine_output: None,
+            capture_output: false,
+            output_style: OutputStyle::default(),
+            output_streams: None,
+            #[cfg(feature = "pty")]
+            pty: false,
+            steps: Steps::All,
+            #[cfg(feature = "otel")]
+            otlp_exporter: None,
+            notifiers: Vec::new(),
+            hooks: Hooks::default(),
+            repro_script: false,
+            failure_bundle: None,
+            cache_seed: None,
+            cancel: None,
+            environment: Vec::new(),
+            environment_cleared: false,
+            msvc_arch: None,
+            english_diagnostics: false,
+            credentials: None,
+            resolved_credentials: Credentials::default(),
+            redactions: Vec::new(),
+        };
+        config::defaults().apply(cmd)
+    }
+}
+
+impl Cmd {
+    /// Creates a [`Cmd`] that runs cmake in the environment of a Yocto or
+    /// Buildroot SDK.
+    ///
+    /// The SDK's `environment-setup-*` script is sourced in a shell, and the
+    /// variables it sets, changes, or unsets (`PATH`, `CC`, `CFLAGS`,
+    /// `PKG_CONFIG_SYSROOT_DIR`, ...) are applied to every cmake process of
+    /// the build. A toolchain file exported as `OE_CMAKE_TOOLCHAIN_FILE`, as
+    /// Yocto SDKs do, is used as the [toolchain file](Cmd::set_toolchain_file).
+    /// Otherwise the returned `Cmd` has the default settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `script_path` - Path to the SDK's environment setup script (e.g.
+    ///   `"/opt/poky/4.0/environment-setup-cortexa57-poky-linux"`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the script cannot be sourced, e.g. because it
+    /// does not exist, it fails, or no POSIX shell is available (Windows).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use cmakr::Cmd;
+    ///
+    /// Cmd::from_sdk_environment("/opt/poky/4.0/environment-setup-cortexa57-poky-linux")
+    ///     .unwrap()
+    ///     .set_path("./my_project")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn from_sdk_environment<T>(script_path: T) -> Result<Self, Error>
+    where
+        T: Into<String>,
+    {
+        let environment = sdk::capture(Path::new(&script_path.into()))?;
+        let toolchain = environment
+            .iter()
+            .find(|(name, _)| name == "OE_CMAKE_TOOLCHAIN_FILE")
+            .and_then(|(_, value)| value.clone());
+
+        let mut cmd = Cmd {
+            environment,
+            ..Default::default()
+        };
+        if let Some(toolchain) = toolchain {
+            cmd = cmd.set_toolchain_file(toolchain);
         }
+        Ok(cmd)
+    }
+
+    /// Creates a [`Cmd`] that cross-compiles with a bare-metal toolchain.
+    ///
+    /// The toolchain's [toolchain file](EmbeddedToolchain::toolchain_file)
+    /// is used as with [`set_toolchain`](Cmd::set_toolchain). It targets
+    /// `CMAKE_SYSTEM_NAME Generic`, builds try-compiles as static libraries,
+    /// and passes the CPU flags to every compile and link; see
+    /// [`embedded`](crate::embedded).
+    ///
+    /// # Arguments
+    ///
+    /// * `toolchain` - The toolchain and target CPU.
+    pub fn embedded(toolchain: EmbeddedToolchain) -> Self {
+        Cmd::default().set_toolchain(toolchain.toolchain_file())
+    }
+
+    /// Creates a [`Cmd`] that cross-compiles for Android with an NDK.
+    ///
+    /// Configures with the NDK's `build/cmake/android.toolchain.cmake` and
+    /// defines `ANDROID_ABI` and `ANDROID_PLATFORM=android-<api_level>`; see
+    /// [`mobile`](crate::mobile).
+    ///
+    /// # Arguments
+    ///
+    /// * `ndk_path` - The root of the NDK (e.g. `$ANDROID_NDK_HOME`).
+    /// * `abi` - The ABI to build for.
+    /// * `api_level` - The minimum Android API level (e.g. `24`).
+    pub fn for_android<T>(ndk_path: T, abi: AndroidAbi, api_level: u32) -> Self
+    where
+        T: Into<String>,
+    {
+        let toolchain = mobile::ndk_toolchain_file(Path::new(&ndk_path.into()));
+        Cmd::default()
+            .set_toolchain_file(toolchain.to_string_lossy())
+            .add_define("ANDROID_ABI", abi.name())
+            .add_define("ANDROID_PLATFORM", format!("android-{}", api_level))
+    }
+
+    /// Creates a [`Cmd`] that builds for WebAssembly with Emscripten.
+    ///
+    /// Configures with the Emscripten toolchain file, as `emcmake cmake`
+    /// does, and the Ninja generator; see [`emscripten`](crate::emscripten)
+    /// for how the toolchain file is found and how the `.js` and `.wasm`
+    /// outputs are reported.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Unsupported`] if no Emscripten installation is found.
+    pub fn for_emscripten() -> Result<Self, Error> {
+        let toolchain = emscripten::toolchain_file().ok_or_else(|| {
+            Error::Unsupported(
+                "the Emscripten toolchain file was found neither through EMSDK or EMSCRIPTEN \
+                 nor next to emcc on PATH; activate the SDK with emsdk_env"
+                    .to_string(),
+            )
+        })?;
+        Ok(Cmd::default()
+            .set_toolchain_file(toolchain.to_string_lossy())
+            .set_generator("Ninja"))
+    }
+
+    /// Creates a [`Cmd`] that cross-compiles for iOS devices or the simulator.
+    ///
+    /// Sets the [system name](Cmd::set_system_name) `iOS` and defines the SDK
+    /// (`CMAKE_OSX_SYSROOT`), the architecture (`CMAKE_OSX_ARCHITECTURES`),
+    /// and `CMAKE_OSX_DEPLOYMENT_TARGET`; see [`mobile`](crate::mobile).
+    ///
+    /// # Arguments
+    ///
+    /// * `platform` - Whether to build for devices or the simulator.
+    /// * `deployment_target` - The minimum iOS version (e.g. `"15.0"`).
+    pub fn for_ios<T>(platform: IosPlatform, deployment_target: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Cmd::default()
+            .set_system_name("iOS")
+            .add_define("CMAKE_OSX_SYSROOT", platform.sdk())
+            .add_define("CMAKE_OSX_ARCHITECTURES", platform.architecture())
+            .add_define("CMAKE_OSX_DEPLOYMENT_TARGET", deployment_target)
     }
 
     /// Adds an extra argument to be passed to the CMake command.
@@ -105,6 +1070,53 @@ impl Cmd {
         self
     }
 
+    /// Returns the extra arguments passed to the configure and build steps.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Adds an extra argument to be passed to the configure step only
+    /// (`cmake -S <source> -B <binary>`).
+    ///
+    /// # Arguments
+    ///
+    /// * `arg` - The argument string (e.g., `"-Wno-dev"`, `"--log-level=WARNING"`).
+    pub fn add_configure_arg<T>(mut self, arg: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.configure_args.push(arg.into());
+        self
+    }
+
+    /// Adds an extra argument to be passed to the build step only
+    /// (`cmake --build <binary>`).
+    ///
+    /// # Arguments
+    ///
+    /// * `arg` - The argument string (e.g., `"--verbose"`, `"--config=Release"`).
+    pub fn add_build_arg<T>(mut self, arg: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.build_args.push(arg.into());
+        self
+    }
+
+    /// Adds an argument to be forwarded to the native build tool, after the
+    /// `--` of `cmake --build`.
+    ///
+    /// # Arguments
+    ///
+    /// * `arg` - The argument string (e.g., `"-d"` for Ninja, `"/m"` for MSBuild).
+    pub fn add_native_build_arg<T>(mut self, arg: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.native_build_args.push(arg.into());
+        self
+    }
+
     /// Sets the CMake source directory.
     ///
     /// This is the directory containing `CMakeLists.txt` and optionally
@@ -123,13 +1135,46 @@ impl Cmd {
         self
     }
 
+    /// Returns the CMake source directory, `"."` unless set.
+    pub fn path(&self) -> &Path {
+        self.path.as_deref().unwrap_or(Path::new("."))
+    }
+
+    /// Sets the working directory of cmake and every other process the run
+    /// starts.
+    ///
+    /// Relative source, build, output, install, toolchain, and cache paths
+    /// are resolved against it instead of the working directory of the
+    /// calling process, by runs and by the methods that read the build tree
+    /// ([`check`](Cmd::check), [`clean`](Cmd::clean), [`ctest`](Cmd::ctest),
+    /// [`changed_targets`](Cmd::changed_targets), ...), so a `Cmd` behaves
+    /// the same wherever it is driven from.
+    ///
+    /// Paths are passed to cmake as single arguments, so they may contain
+    /// spaces with every generator; paths embedded in compiler flags, like
+    /// the profile directory of a [PGO](Cmd::pgo) build, are quoted.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The working directory, itself resolved against the
+    ///   calling process's one if relative.
+    pub fn current_dir<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.current_dir = Some(PathBuf::from(path.into()));
+        self.tools.current_dir = self.current_dir.clone();
+        self
+    }
+
     /// Sets the CMake build (binary) directory.
     ///
     /// This is where CMake generates build system files and intermediate
     /// artifacts. Passed to CMake as `-B <path>`. The directory is created
     /// automatically if it does not exist.
     ///
-    /// Defaults to `"build"`.
+    /// Defaults to the `binaryDir` of the configure preset, if it declares
+    /// one, and to `"build"` otherwise.
     ///
     /// # Arguments
     ///
@@ -139,6 +1184,47 @@ impl Cmd {
         T: Into<String>,
     {
         self.binary_path = PathBuf::from(path.into());
+        self.binary_path_set = true;
+        self
+    }
+
+    /// Returns the CMake build directory.
+    pub fn binary_path(&self) -> &Path {
+        &self.binary_path
+    }
+
+    /// Moves the build directory to a short one named after a hash of its
+    /// absolute path, under the [short build root](Cmd::set_short_build_root).
+    ///
+    /// Deeply nested build directories, such as a build script's `OUT_DIR`,
+    /// run into the 260 character `MAX_PATH` limit of Windows tools once
+    /// CMake appends its own subdirectories. The same directory always maps
+    /// to the same short one, so incremental builds keep working. The
+    /// directory the tree was moved from is recorded in
+    /// [`BuildReport::relocated_from`] and in the
+    /// [state file](crate::state::Configuration::relocated_from).
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to move the build directory.
+    pub fn short_build_dir(mut self, enabled: bool) -> Self {
+        self.short_build_dir = enabled;
+        self
+    }
+
+    /// Sets the root of the [short build directories](Cmd::short_build_dir).
+    ///
+    /// Defaults to `\bld` on the system drive on Windows (e.g. `C:\bld`),
+    /// and to `cmakr-bld` in the temporary directory elsewhere.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - Path to the root directory.
+    pub fn set_short_build_root<T>(mut self, root: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.short_build_root = Some(PathBuf::from(root.into()));
         self
     }
 
@@ -148,7 +1234,10 @@ impl Cmd {
     /// `CMAKE_LIBRARY_OUTPUT_DIRECTORY`, and `CMAKE_ARCHIVE_OUTPUT_DIRECTORY`
     /// so that executables, shared libraries, and static libraries are placed
     /// in the specified directory. The directory is created automatically if
-    /// it does not exist.
+    /// it does not exist. Each kind of artifact can be moved to a directory
+    /// of its own with [`set_runtime_output_path`](Cmd::set_runtime_output_path),
+    /// [`set_library_output_path`](Cmd::set_library_output_path), and
+    /// [`set_archive_output_path`](Cmd::set_archive_output_path).
     ///
     /// Defaults to `"build"`.
     ///
@@ -163,211 +1252,8283 @@ impl Cmd {
         self
     }
 
-    /// Sets the CMake preset to use.
+    /// Returns the output directory for final build artifacts.
+    pub fn output_path(&self) -> &Path {
+        &self.output_path
+    }
+
+    /// Sets the output directory for executables, and on Windows for DLLs
+    /// (`CMAKE_RUNTIME_OUTPUT_DIRECTORY`), instead of the
+    /// [output path](Cmd::set_output_path).
     ///
-    /// The preset name is looked up in the `CMakePresets.json` file located
-    /// in the source directory. Hidden presets are excluded from lookup.
-    /// Passed to CMake as `--preset=<name>`.
+    /// Putting DLLs next to the executables that load them, e.g. test
+    /// runners, while static and import libraries go to a directory of
+    /// their own lets the executables run without changing `PATH`.
     ///
     /// # Arguments
     ///
-    /// * `preset` - The name of the configure preset.
-    pub fn set_preset<T>(mut self, preset: T) -> Self
+    /// * `path` - Path to the runtime output directory.
+    pub fn set_runtime_output_path<T>(mut self, path: T) -> Self
     where
         T: Into<String>,
     {
-        self.preset = Some(preset.into());
+        self.runtime_output_path = Some(PathBuf::from(path.into()));
         self
     }
 
-    /// Adds a CMake cache variable definition.
-    ///
-    /// Passed to CMake as `-D<name>=<value>` during the configure step.
+    /// Returns the output directory for executables and DLLs.
+    pub fn runtime_output_path(&self) -> &Path {
+        self.runtime_output_path
+            .as_deref()
+            .unwrap_or(&self.output_path)
+    }
+
+    /// Sets the output directory for shared libraries other than DLLs and
+    /// for loadable modules (`CMAKE_LIBRARY_OUTPUT_DIRECTORY`), instead of
+    /// the [output path](Cmd::set_output_path).
     ///
     /// # Arguments
     ///
-    /// * `define` - The variable name (e.g., `"CMAKE_BUILD_TYPE"`).
-    /// * `value` - The variable value (e.g., `"Release"`).
-    pub fn add_define<T, U>(mut self, define: T, value: U) -> Self
+    /// * `path` - Path to the library output directory.
+    pub fn set_library_output_path<T>(mut self, path: T) -> Self
     where
         T: Into<String>,
-        U: Into<String>,
     {
-        self.defines.push(Defination {
-            name: define.into(),
-            value: value.into(),
-        });
+        self.library_output_path = Some(PathBuf::from(path.into()));
         self
     }
 
-    /// Executes CMake configure and build synchronously.
-    ///
-    /// This consumes the builder and runs the full CMake workflow
-    /// (configure + build) in the current thread, blocking until completion.
+    /// Returns the output directory for shared libraries and modules.
+    pub fn library_output_path(&self) -> &Path {
+        self.library_output_path
+            .as_deref()
+            .unwrap_or(&self.output_path)
+    }
+
+    /// Sets the output directory for static libraries and the import
+    /// libraries of DLLs (`CMAKE_ARCHIVE_OUTPUT_DIRECTORY`), instead of the
+    /// [output path](Cmd::set_output_path).
     ///
-    /// # Errors
+    /// # Arguments
     ///
-    /// Returns an error if:
-    /// - `cmake` is not found on `PATH`
-    /// - The preset name is invalid or not found
-    /// - The configure step fails (non-zero exit code)
-    /// - The build step fails (non-zero exit code)
-    /// - Any I/O error occurs (directory creation, path resolution, etc.)
-    pub fn build(mut self) -> ExecResult {
-        self.execute()
+    /// * `path` - Path to the archive output directory.
+    pub fn set_archive_output_path<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.archive_output_path = Some(PathBuf::from(path.into()));
+        self
     }
 
-    /// Executes CMake configure and build asynchronously in a background thread.
-    ///
-    /// This consumes the builder and spawns a new thread to run the full CMake
-    /// workflow. Returns a [`Receiver`] that will receive the result once the
-    /// build completes.
+    /// Returns the output directory for static and import libraries.
+    pub fn archive_output_path(&self) -> &Path {
+        self.archive_output_path
+            .as_deref()
+            .unwrap_or(&self.output_path)
+    }
+
+    /// Returns the output directories by the variable that sets them.
+    fn output_dirs(&self) -> [(&'static str, &Path); 3] {
+        [
+            ("CMAKE_RUNTIME_OUTPUT_DIRECTORY", self.runtime_output_path()),
+            ("CMAKE_LIBRARY_OUTPUT_DIRECTORY", self.library_output_path()),
+            ("CMAKE_ARCHIVE_OUTPUT_DIRECTORY", self.archive_output_path()),
+        ]
+    }
+
+    /// Sets the CMake preset to use.
     ///
-    /// # Example
+    /// The preset name is looked up in the `CMakePresets.json` file located
+    /// in the source directory. Hidden presets are excluded from lookup.
+    /// Passed to CMake as `--preset=<name>`.
+    ///
+    /// The preset's `binaryDir`, `generator`, and `environment`, with their
+    /// macros expanded, are the defaults of the build directory, generator,
+    /// and environment of every cmake process of the run (including
+    /// `cmake --build`, which cmake runs without the configure preset).
+    /// [`set_binary_path`](Cmd::set_binary_path),
+    /// [`set_generator`](Cmd::set_generator), and [`env`](Cmd::env) override
+    /// them.
+    ///
+    /// # Arguments
+    ///
+    /// * `preset` - The name of the configure preset.
+    pub fn set_preset<T>(mut self, preset: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.preset = Some(preset.into());
+        self
+    }
+
+    /// Returns the name of the CMake preset, if one is set.
+    pub fn preset(&self) -> Option<&str> {
+        self.preset.as_deref()
+    }
+
+    /// Sets the cmake executable to run.
+    ///
+    /// Without it, cmake is the one named by the `CMAKE` environment
+    /// variable, or else the first one on `PATH`. `ctest` and `cpack` are
+    /// taken from the same directory, if they are installed there.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the executable (e.g. `"/opt/cmake/bin/cmake"`).
+    pub fn set_cmake_executable<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.tools.cmake = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Requires a cmake version, checked before anything is configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `requirement` - Comma-separated comparisons like `">=3.21"` or
+    ///   `">=3.21, <4"`; see [`CMakeVersion::matches`].
+    ///
+    /// # Example
     ///
     /// ```no_run
     /// use cmakr::Cmd;
     ///
-    /// let rx = Cmd::default()
-    ///     .set_path("./my_project")
+    /// // presets with schema version 3 need cmake 3.21
+    /// Cmd::default()
     ///     .set_preset("default")
-    ///     .spawn();
+    ///     .require_version(">=3.21")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn require_version<T>(mut self, requirement: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.required_version = Some(requirement.into());
+        self
+    }
+
+    /// Sets the ctest executable of [`ctest`](Cmd::ctest) and
+    /// [`test_affected`](Cmd::test_affected).
     ///
-    /// // Do other work while cmake runs...
+    /// # Arguments
     ///
-    /// let result = rx.recv().unwrap();
-    /// match result {
-    ///     Ok(()) => println!("Build succeeded"),
-    ///     Err(e) => eprintln!("Build failed: {}", e),
-    /// }
-    /// ```
-    pub fn spawn(mut self) -> Receiver<ExecResult> {
-        let (tx, rx) = mpsc::channel();
+    /// * `path` - Path to the executable.
+    pub fn set_ctest_executable<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.tools.ctest = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Sets the cpack executable of [`cpack`](Cmd::cpack).
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the executable.
+    pub fn set_cpack_executable<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.tools.cpack = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Sets the ninja executable.
+    ///
+    /// With a Ninja generator, it is passed to configure as
+    /// `CMAKE_MAKE_PROGRAM` (unless that is defined), so ninja need not be
+    /// on `PATH`. It is also used to query the dependency graph of the tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the executable.
+    pub fn set_ninja_executable<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.tools.ninja = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Sets the CMake generator (`-G <name>`).
+    ///
+    /// Passed to the configure step only, and overrides the generator of the
+    /// preset and of the `CMAKE_GENERATOR` environment variable. An already
+    /// configured build tree keeps its generator; cmake fails if a different
+    /// one is requested.
+    ///
+    /// # Arguments
+    ///
+    /// * `generator` - The generator name (e.g. `"Ninja"`, `"Visual Studio 17 2022"`).
+    pub fn set_generator<T>(mut self, generator: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.generator = Some(generator.into());
+        self
+    }
+
+    /// Sets the generator platform (`-A <platform>`), for generators that
+    /// support it, such as Visual Studio.
+    ///
+    /// # Arguments
+    ///
+    /// * `platform` - The target platform (e.g. `"x64"`, `"ARM64"`).
+    pub fn set_generator_platform<T>(mut self, platform: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.generator_platform = Some(platform.into());
+        self
+    }
+
+    /// Sets the generator toolset (`-T <toolset>`), for generators that
+    /// support it, such as Visual Studio and Xcode.
+    ///
+    /// # Arguments
+    ///
+    /// * `toolset` - The toolset specification (e.g. `"ClangCL"`, `"v143,host=x64"`).
+    pub fn set_generator_toolset<T>(mut self, toolset: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.generator_toolset = Some(toolset.into());
+        self
+    }
+
+    /// Retries a failed configure with the next of `generators` when the
+    /// selected generator, its build tool, or its toolset is unavailable
+    /// (e.g. `CMAKE_MAKE_PROGRAM is not set` for a missing Ninja, or no
+    /// Visual Studio instance found). The generator that failed is skipped,
+    /// so the list can start with the preferred one.
+    ///
+    /// Only fresh trees are retried, as a configured tree keeps its
+    /// generator. The fallback generators run without the
+    /// [platform](Cmd::set_generator_platform) and
+    /// [toolset](Cmd::set_generator_toolset), which belong to the selected
+    /// one. The generator that configured the tree is recorded in
+    /// [`BuildReport::generator_fallback`], and the phases of the failed
+    /// attempts stay in [`BuildReport::phases`].
+    ///
+    /// # Arguments
+    ///
+    /// * `generators` - The generators to try, in order of preference (e.g.
+    ///   `["Ninja", "Unix Makefiles"]`).
+    pub fn fallback_generators<I, T>(mut self, generators: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.fallback_generators = generators.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Builds with Visual Studio's C++ tools for `arch` on Windows, also
+    /// outside a Developer Command Prompt.
+    ///
+    /// For the Ninja and Makefile generators, the newest Visual Studio with
+    /// the C++ tools is located with `vswhere`, and the environment its
+    /// `vcvarsall.bat` sets up is passed to cmake and the build tool, before
+    /// the variables set with [`env`](Cmd::env). Without a generator, or
+    /// with a Visual Studio generator, which finds the tools itself, `arch`
+    /// only selects the [generator platform](Cmd::set_generator_platform)
+    /// of a new tree. Nothing changes on other platforms, or when a
+    /// developer environment is already active. See [`msvc`](crate::msvc).
+    ///
+    /// # Arguments
+    ///
+    /// * `arch` - The target architecture.
+    ///
+    /// # Errors
+    ///
+    /// The run fails with [`Error::Unsupported`] if no Visual Studio with
+    /// the C++ tools is installed, and with an I/O error if its
+    /// `vcvarsall.bat` fails.
+    pub fn with_msvc_env(mut self, arch: msvc::Arch) -> Self {
+        self.msvc_arch = Some(arch);
+        self
+    }
+
+    /// Sets the build preset used by the build step.
+    ///
+    /// The build step then runs `cmake --build --preset=<name>` in the source
+    /// directory instead of `cmake --build <binary>`. The build tree is the
+    /// binary directory of the build preset's configure preset, so it must
+    /// be the same as this `Cmd`'s binary path.
+    ///
+    /// # Arguments
+    ///
+    /// * `preset` - The name of a build preset (from `buildPresets`).
+    pub fn set_build_preset<T>(mut self, preset: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.build_preset = Some(preset.into());
+        self
+    }
+
+    /// Sets the test preset used by [`ctest`](Cmd::ctest).
+    ///
+    /// # Arguments
+    ///
+    /// * `preset` - The name of a test preset (from `testPresets`).
+    pub fn set_test_preset<T>(mut self, preset: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.test_preset = Some(preset.into());
+        self
+    }
+
+    /// Sets the build type, a [`BuildType`] or the name of one the project
+    /// defines (e.g. `"Release"`).
+    ///
+    /// Single-config generators (Makefiles, Ninja) fix the build type at
+    /// configure time, so it is passed as `-DCMAKE_BUILD_TYPE=<type>` unless
+    /// that variable is defined explicitly. Multi-config generators (Visual
+    /// Studio, Xcode, Ninja Multi-Config) ignore `CMAKE_BUILD_TYPE`; for them
+    /// the build type is passed to the build and install steps as `--config`
+    /// instead, unless [`set_config`](Cmd::set_config) is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `build_type` - The build type.
+    pub fn set_build_type<T>(mut self, build_type: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.build_type = Some(build_type.into());
+        self
+    }
+
+    /// Sets the configuration built and installed by a multi-config generator
+    /// (passed as `--config <config>` to the build and install steps).
+    ///
+    /// Single-config generators ignore it and build the configured
+    /// `CMAKE_BUILD_TYPE`; use [`set_build_type`](Cmd::set_build_type) to
+    /// select the build type regardless of the generator.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The configuration (e.g. `"Release"`).
+    pub fn set_config<T>(mut self, config: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.config = Some(config.into());
+        self
+    }
+
+    /// Adds a CMake cache variable definition.
+    ///
+    /// Passed to CMake as `-D<name>=<value>` during the configure step. The
+    /// name may carry a type (`"NAME:BOOL"`; `BOOL`, `FILEPATH`, `PATH`,
+    /// `STRING`, or `INTERNAL`). Values are passed on exactly as
+    /// given, including spaces, quotes and semicolons (which separate list
+    /// items in CMake); trailing whitespace and enclosing single quotes are
+    /// protected from CMake's own trimming. A value containing a line break
+    /// or NUL fails the build, see [`raw_defines`](Cmd::raw_defines).
+    ///
+    /// # Arguments
+    ///
+    /// * `define` - The variable name (e.g., `"CMAKE_BUILD_TYPE"`).
+    /// * `value` - The variable value (e.g., `"Release"`).
+    pub fn add_define<T, U>(mut self, define: T, value: U) -> Self
+    where
+        T: Into<String>,
+        U: Into<String>,
+    {
+        self.defines.push(Defination {
+            name: define.into(),
+            value: value.into(),
+            once: false,
+        });
+        self
+    }
+
+    /// Adds a `BOOL` cache variable, passed as `-D<name>:BOOL=ON` or `OFF`.
+    ///
+    /// # Arguments
+    ///
+    /// * `define` - The variable name (e.g., `"BUILD_TESTING"`), typed
+    ///   `BOOL` unless it carries a type.
+    /// * `value` - The variable value.
+    pub fn add_define_bool<T>(self, define: T, value: bool) -> Self
+    where
+        T: Into<String>,
+    {
+        let value = if value { "ON" } else { "OFF" };
+        self.add_define(typed_name(define.into(), "BOOL"), value)
+    }
+
+    /// Adds a `PATH` cache variable, or `FILEPATH` for an existing file.
+    ///
+    /// The path is passed with forward slashes, which CMake code expects on
+    /// every platform; backslashes would be read as escapes wherever the
+    /// value is expanded into a quoted argument. CMake resolves a relative
+    /// path of these types against the current directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `define` - The variable name (e.g., `"OPENSSL_ROOT_DIR"`), typed
+    ///   unless it carries a type.
+    /// * `path` - The path.
+    pub fn add_define_path<T, P>(self, define: T, path: P) -> Self
+    where
+        T: Into<String>,
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let kind = if path.is_file() { "FILEPATH" } else { "PATH" };
+        let value = forward_slash_path(path);
+        self.add_define(typed_name(define.into(), kind), value)
+    }
+
+    /// Adds a CMake list, passed as its items separated by semicolons.
+    ///
+    /// Semicolons within an item are escaped (`\;`), so that each item stays
+    /// one list element.
+    ///
+    /// # Arguments
+    ///
+    /// * `define` - The variable name (e.g., `"CMAKE_CUDA_ARCHITECTURES"`).
+    /// * `items` - The list items.
+    pub fn add_define_list<T, I, U>(self, define: T, items: I) -> Self
+    where
+        T: Into<String>,
+        I: IntoIterator<Item = U>,
+        U: AsRef<str>,
+    {
+        let items: Vec<String> = items
+            .into_iter()
+            .map(|item| item.as_ref().replace(';', "\\;"))
+            .collect();
+        self.add_define(define, items.join(";"))
+    }
+
+    /// Adds a CMake cache variable definition that is only passed until the
+    /// build tree's cache has the variable.
+    ///
+    /// Like [`add_define`](Cmd::add_define), but later configures of the
+    /// tree leave the variable to the cache instead of passing it again.
+    /// Some projects redo expensive detection whenever certain variables are
+    /// specified on the command line, even with an unchanged value. A fresh
+    /// configure passes the definition again. A value changed later is only
+    /// picked up by a fresh configure.
+    ///
+    /// # Arguments
+    ///
+    /// * `define` - The variable name (e.g., `"CMAKE_CUDA_ARCHITECTURES"`).
+    /// * `value` - The variable value (e.g., `"native"`).
+    pub fn add_define_once<T, U>(mut self, define: T, value: U) -> Self
+    where
+        T: Into<String>,
+        U: Into<String>,
+    {
+        self.defines.push(Defination {
+            name: define.into(),
+            value: value.into(),
+            once: true,
+        });
+        self
+    }
+
+    /// Sets how repeated definitions of the same variable are resolved.
+    ///
+    /// Defaults to [`DefinePolicy::LastWins`], so a later
+    /// [`add_define`](Cmd::add_define) overrides an earlier one.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The policy to apply.
+    pub fn set_define_policy(mut self, policy: DefinePolicy) -> Self {
+        self.define_policy = policy;
+        self
+    }
+
+    /// Sets the order of the definitions on the configure command line.
+    ///
+    /// Defaults to [`ArgOrder::Insertion`]; see [`ArgOrder`] for the order
+    /// of the whole command line, which doesn't change between releases
+    /// without a note in the changelog.
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The policy to apply.
+    pub fn arg_order(mut self, order: ArgOrder) -> Self {
+        self.arg_order = order;
+        self
+    }
+
+    /// Sets how a run waits for another process using the same build directory.
+    ///
+    /// Defaults to [`LockPolicy::Wait`].
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The policy to apply.
+    pub fn set_lock_policy(mut self, policy: LockPolicy) -> Self {
+        self.lock_policy = policy;
+        self
+    }
+
+    /// Sets whether a run locks the build directory, so that concurrent runs
+    /// on the same tree wait for each other instead of corrupting it.
+    ///
+    /// Enabled by default, waiting as long as it takes. Disabling it is
+    /// [`LockPolicy::Disabled`]; enabling it keeps a
+    /// [timeout](LockPolicy::Timeout) set with
+    /// [`set_lock_policy`](Cmd::set_lock_policy).
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to lock the build directory.
+    pub fn lock_build_dir(mut self, enabled: bool) -> Self {
+        self.lock_policy = match (enabled, self.lock_policy) {
+            (false, _) => LockPolicy::Disabled,
+            (true, LockPolicy::Disabled) => LockPolicy::Wait,
+            (true, policy) => policy,
+        };
+        self
+    }
+
+    /// Sets what happens when the build directory is the source directory or
+    /// one of its source subdirectories.
+    ///
+    /// Defaults to [`InSourcePolicy::Warn`].
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The policy to apply.
+    pub fn set_in_source_policy(mut self, policy: InSourcePolicy) -> Self {
+        self.in_source_policy = policy;
+        self
+    }
+
+    /// Sets what happens when the source directory is not writable.
+    ///
+    /// Defaults to [`ReadOnlySourcePolicy::Copy`], which builds from a copy
+    /// in the `cmakr-source` directory of the build tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The policy to apply.
+    pub fn set_read_only_source_policy(mut self, policy: ReadOnlySourcePolicy) -> Self {
+        self.read_only_source_policy = policy;
+        self
+    }
+
+    /// Returns the definitions passed to cmake as `(name, value)` pairs, in
+    /// the order they were added.
+    ///
+    /// Repeated definitions are resolved by the [`DefinePolicy`]; with
+    /// [`DefinePolicy::Error`], every definition is returned as added.
+    pub fn defines(&self) -> Vec<(&str, &str)> {
+        let defines = dedup_defines(&self.defines, self.define_policy)
+            .unwrap_or_else(|_| self.defines.iter().collect());
+        defines
+            .into_iter()
+            .map(|d| (d.name.as_str(), d.value.as_str()))
+            .collect()
+    }
+
+    /// Returns a snapshot of the settings a build would use.
+    ///
+    /// Unlike the individual getters, the snapshot has defaults applied and
+    /// resolved against the current directory and environment, and lists the
+    /// defines cmakr adds on its own. Nothing is created or run.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the current directory cannot be determined.
+    pub fn effective_settings(&self) -> std::io::Result<EffectiveSettings> {
+        let preset = self.resolved_preset();
+        let binary_dir = std::path::absolute(self.build_dir(preset.as_ref()))?;
+        let redactor = self.redactor();
+
+        let mut defines: Vec<(String, String)> = self
+            .defines()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        for (name, dir) in self.output_dirs() {
+            let dir = forward_slash_path(&std::path::absolute(dir)?);
+            defines.push((name.to_string(), dir));
+        }
+        defines.extend(self.cross_defines()?);
+        defines.extend(self.flag_defines().map_err(std::io::Error::other)?);
+        defines.extend(self.option_defines().map_err(std::io::Error::other)?);
+        if self.strip_debug_postfix {
+            defines.push(("CMAKE_DEBUG_POSTFIX".to_string(), String::new()));
+        }
+        if self.exports_compile_commands() {
+            defines.push((
+                "CMAKE_EXPORT_COMPILE_COMMANDS".to_string(),
+                "ON".to_string(),
+            ));
+        }
+        defines.extend(self.build_type_define(&binary_dir, preset.as_ref()));
+        if let Some(cache_dir) = &self.fetch_cache_dir {
+            let declared = offline::declared_fetches(self.path(), &binary_dir);
+            let shared = fetch_cache::shared_sources(&std::path::absolute(cache_dir)?, &declared);
+            for (name, dir) in shared {
+                defines.push((name, forward_slash_path(&dir)));
+            }
+        }
+        for (name, value) in &mut defines {
+            let define = format!("-D{}={}", name, value);
+            if let Some(redacted) = redactor
+                .redact(&define)
+                .strip_prefix(&format!("-D{}=", name))
+            {
+                *value = redacted.to_string();
+            }
+        }
+
+        Ok(EffectiveSettings {
+            source_dir: std::path::absolute(self.path())?,
+            generator: cmake::read_cache_value(&binary_dir, "CMAKE_GENERATOR")
+                .or_else(|| self.generator.clone())
+                .or_else(|| std::env::var("CMAKE_GENERATOR").ok())
+                .filter(|generator| !generator.is_empty()),
+            binary_dir,
+            output_dir: std::path::absolute(&self.output_path)?,
+            preset: self.preset.clone(),
+            defines,
+            args: self
+                .args
+                .iter()
+                .map(|arg| redactor.redact(arg).into_owned())
+                .collect(),
+            install_prefix: self
+                .install_prefix
+                .as_deref()
+                .map(std::path::absolute)
+                .transpose()?,
+            install_components: self.install_components.clone(),
+            toolchain_file: self
+                .toolchain_file
+                .as_deref()
+                .map(std::path::absolute)
+                .transpose()?,
+            subdirectory: self.subdirectory.clone(),
+        })
+    }
+
+    /// Passes define values on verbatim, without escaping or validation.
+    ///
+    /// An escape hatch for values that rely on CMake's own trimming and
+    /// quote stripping, or that are known to be handled by the project.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to pass defines as raw `-D<name>=<value>` arguments.
+    pub fn raw_defines(mut self, enabled: bool) -> Self {
+        self.raw_defines = enabled;
+        self
+    }
+
+    /// Sets the CMake toolchain file, e.g. for cross-compiling.
+    ///
+    /// Passed as `--toolchain <path>` to CMake 3.21 and later, and as
+    /// `-DCMAKE_TOOLCHAIN_FILE=<path>` to older versions. If the configure
+    /// preset or a [define](Cmd::add_define) already selects a different
+    /// toolchain file, the conflict is resolved by the [`DefinePolicy`], with
+    /// this setting counting as the last definition: by default it replaces
+    /// the other toolchain file, and only one is passed to cmake.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the toolchain file (e.g. `"cmake/aarch64.cmake"`).
+    pub fn set_toolchain_file<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.toolchain_file = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Configures with a generated toolchain file.
+    ///
+    /// The file is written into the build directory as
+    /// `.cmakr/toolchain.cmake` before configuring, and then passed like a
+    /// [toolchain file](Cmd::set_toolchain_file), which takes precedence if
+    /// both are set. Since CMake only reads the toolchain file when a tree is
+    /// first configured, changing it needs a fresh build directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `toolchain` - The toolchain file, built with [`ToolchainFile::builder`].
+    pub fn set_toolchain(mut self, toolchain: ToolchainFile) -> Self {
+        self.generated_toolchain = Some(toolchain);
+        self
+    }
+
+    /// Configures a cross build for the Rust target of the running build
+    /// script, if it is not the host.
+    ///
+    /// Cargo passes the target triple to build scripts as `TARGET` and the
+    /// host's as `HOST`. If they differ, a toolchain file with the settings
+    /// of [`ToolchainFile::for_rust_target`] is generated, e.g. the system
+    /// name `Linux`, the processor `aarch64`, and the compilers
+    /// `aarch64-linux-gnu-gcc`/`g++` for `aarch64-unknown-linux-gnu`. Nothing
+    /// changes for native builds, outside of build scripts, for targets
+    /// CMake doesn't know, or if a toolchain file, generated toolchain, or
+    /// [system name](Cmd::set_system_name) is set already.
+    pub fn cross_compile_for_cargo(self) -> Self {
+        let (Ok(target), Ok(host)) = (std::env::var("TARGET"), std::env::var("HOST")) else {
+            return self;
+        };
+        let configured = self.toolchain_file.is_some()
+            || self.generated_toolchain.is_some()
+            || self.system_name.is_some();
+        if target == host || configured {
+            return self;
+        }
+        match ToolchainFile::for_rust_target(&target) {
+            Some(toolchain) => self.set_toolchain(toolchain.build()),
+            None => self,
+        }
+    }
+
+    /// Sets the target system of a cross build, e.g. `"Linux"`, `"Windows"`,
+    /// `"Android"`, or `"Generic"` for bare metal.
+    ///
+    /// Passes `-DCMAKE_SYSTEM_NAME=<name>`, which makes CMake cross-compile
+    /// (`CMAKE_CROSSCOMPILING`), unless the variable is
+    /// [defined](Cmd::add_define).
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The CMake system name.
+    pub fn set_system_name<T>(mut self, name: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.system_name = Some(name.into());
+        self
+    }
+
+    /// Sets the target processor of a cross build, e.g. `"aarch64"` or
+    /// `"arm"`.
+    ///
+    /// Passes `-DCMAKE_SYSTEM_PROCESSOR=<name>`, unless the variable is
+    /// [defined](Cmd::add_define).
+    ///
+    /// # Arguments
+    ///
+    /// * `processor` - The CMake processor name.
+    pub fn set_system_processor<T>(mut self, processor: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.system_processor = Some(processor.into());
+        self
+    }
+
+    /// Sets the sysroot of a cross build.
+    ///
+    /// Passes `-DCMAKE_SYSROOT=<path>` and adds the sysroot to
+    /// `CMAKE_FIND_ROOT_PATH`, with the search modes a cross build needs:
+    /// programs are only searched on the host, and libraries, headers, and
+    /// packages only under the root paths (`CMAKE_FIND_ROOT_PATH_MODE_*`).
+    /// A variable of the bundle that is also [defined](Cmd::add_define) is
+    /// left to the definition.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the target's root filesystem (e.g. `"/opt/sdk/sysroots/aarch64"`).
+    pub fn set_sysroot<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.sysroot = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Sets the staging prefix of a cross build.
+    ///
+    /// Passes `-DCMAKE_STAGING_PREFIX=<path>`, so the install step writes
+    /// into the staging directory on the host while `CMAKE_INSTALL_PREFIX`
+    /// stays the path on the target. The prefix is added to
+    /// `CMAKE_FIND_ROOT_PATH` ahead of the [sysroot](Cmd::set_sysroot), so
+    /// dependencies staged there are found first, with the same search modes.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the staging directory (e.g. `"./stage"`).
+    pub fn set_staging_prefix<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.staging_prefix = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Sets a shared download cache directory for `FetchContent` dependencies.
+    ///
+    /// Several projects (or several `OUT_DIR`s of the same crate) that fetch
+    /// the same upstream sources reuse one download instead of fetching it
+    /// again for every build tree: the sources of a dependency declared with
+    /// a pinned revision (`URL_HASH`, or a `GIT_TAG` naming a commit hash or
+    /// a tag) are copied into the cache after the first configure that
+    /// downloads them, and later trees declaring the same URL and revision
+    /// are configured with `FETCHCONTENT_SOURCE_DIR_<NAME>` pointing at them.
+    /// Dependencies following a branch are downloaded by every tree. Only
+    /// `FetchContent` sources are shared: each tree builds its dependencies
+    /// in its own `_deps` directory, and `ExternalProject_Add` downloads are
+    /// not cached.
+    ///
+    /// The directory is created automatically if it does not exist, and an
+    /// exclusive lock on it is held for the duration of the configure step so
+    /// concurrent builds don't populate it at the same time.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the shared cache directory (e.g. `"~/.cache/cmakr/fetch"`).
+    pub fn set_fetch_cache_dir<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.fetch_cache_dir = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Sets a user-level cache directory for CMake's compiler detection results.
+    ///
+    /// Configuring a fresh build tree spends several seconds identifying and
+    /// testing the compilers. With a cache directory set, the results of a
+    /// fresh configure are stored there under a fingerprint of the toolchain
+    /// (CMake version, compiler-related environment variables and defines,
+    /// generator arguments, and the compilers' modification times), and copied
+    /// into later fresh trees with the same fingerprint so CMake skips the
+    /// detection. Already configured trees are not affected.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the cache directory (e.g. `"~/.cache/cmakr/compilers"`).
+    pub fn set_compiler_cache_dir<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.compiler_cache_dir = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Compiles C and C++ sources through `launcher`, usually a compiler
+    /// cache.
+    ///
+    /// The launcher's program is looked up when configuring and passed as
+    /// `CMAKE_C_COMPILER_LAUNCHER` and `CMAKE_CXX_COMPILER_LAUNCHER`, unless
+    /// the variables are [defined](Cmd::add_define). With
+    /// [`Launcher::Auto`], `sccache` or `ccache` is used if installed, and
+    /// the build runs without a launcher otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `launcher` - The launcher, e.g. [`Launcher::Sccache`].
+    ///
+    /// # Errors
+    ///
+    /// Configuring fails if the program of a launcher other than
+    /// [`Launcher::Auto`] cannot be found.
+    pub fn with_compiler_launcher(mut self, launcher: Launcher) -> Self {
+        self.compiler_launcher = Some(launcher);
+        self
+    }
+
+    /// Compiles and links C and C++ code with `sanitizer`; repeatable.
+    ///
+    /// The flags of the tree's compiler (see [`sanitizer`](crate::sanitizer))
+    /// are appended to the user's `CMAKE_C_FLAGS`, `CMAKE_CXX_FLAGS`, and
+    /// linker flags defines.
+    ///
+    /// # Arguments
+    ///
+    /// * `sanitizer` - The sanitizer, e.g. [`Sanitizer::Address`].
+    ///
+    /// # Errors
+    ///
+    /// Configuring fails with [`Error::Unsupported`] if the sanitizers can't
+    /// be combined, e.g. address and thread, or the compiler doesn't support
+    /// one of them.
+    pub fn enable_sanitizer(mut self, sanitizer: Sanitizer) -> Self {
+        if !self.sanitizers.contains(&sanitizer) {
+            self.sanitizers.push(sanitizer);
+        }
+        self
+    }
+
+    /// Compiles and links C and C++ code with link-time optimization.
+    ///
+    /// The flags of the tree's compiler (see [`sanitizer`](crate::sanitizer))
+    /// are appended to the user's compiler and linker flags defines.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to optimize at link time.
+    pub fn enable_lto(mut self, enabled: bool) -> Self {
+        self.lto = enabled;
+        self
+    }
+
+    /// Sets the C++ standard (`CMAKE_CXX_STANDARD`, e.g. `17`), with
+    /// `CMAKE_CXX_STANDARD_REQUIRED` so that compilers without it fail to
+    /// configure instead of falling back to an older standard.
+    ///
+    /// Not passed if the user defines `CMAKE_CXX_STANDARD`.
+    ///
+    /// # Arguments
+    ///
+    /// * `standard` - One of 98, 11, 14, 17, 20, 23, or 26.
+    ///
+    /// # Errors
+    ///
+    /// The build fails with [`Error::InvalidConfig`] before running cmake for
+    /// other standards.
+    pub fn set_cxx_standard(mut self, standard: u32) -> Self {
+        self.cxx_standard = Some(standard);
+        self
+    }
+
+    /// Builds the libraries of the project as shared (`true`) or static
+    /// (`false`) libraries, passed as `BUILD_SHARED_LIBS` unless the user
+    /// defines it.
+    ///
+    /// Only affects `add_library` calls without `STATIC` or `SHARED`.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to build shared libraries.
+    pub fn enable_shared_libs(mut self, enabled: bool) -> Self {
+        self.shared_libs = Some(enabled);
+        self
+    }
+
+    /// Sets the directories `find_package` and the other `find_*` commands
+    /// search first, passed as `CMAKE_PREFIX_PATH` unless the user defines
+    /// it. Relative paths are resolved against the current directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - The install prefixes of dependencies, replacing earlier
+    ///   ones.
+    pub fn set_prefix_path<I, T>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.prefix_path = paths
+            .into_iter()
+            .map(|path| PathBuf::from(path.into()))
+            .collect();
+        self
+    }
+
+    /// Installs the project into `prefix` after a successful build.
+    ///
+    /// Runs `cmake --install <binary> --prefix <prefix>` as a third step and
+    /// lists the installed files in
+    /// [`installed`](crate::report::BuildReport::installed). A failed install
+    /// is reported as [`Error::InstallFailed`].
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - Path to the install prefix. Created if it does not exist.
+    pub fn set_install_prefix<T>(mut self, prefix: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.install_prefix = Some(PathBuf::from(prefix.into()));
+        self
+    }
+
+    /// Installs only the given component (`cmake --install ... --component <component>`).
+    ///
+    /// Can be called several times; the components are installed one after
+    /// another, in the order they were added. Without components, the whole
+    /// project is installed. Only takes effect with an install prefix.
+    ///
+    /// # Arguments
+    ///
+    /// * `component` - The name of an install component (e.g. `"Runtime"`).
+    pub fn add_install_component<T>(mut self, component: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.install_components.push(component.into());
+        self
+    }
+
+    /// Installs the components of the enabled Cargo features of the crate
+    /// whose build script runs.
+    ///
+    /// Every `(feature, component)` pair whose feature is enabled, as Cargo
+    /// tells build scripts with `CARGO_FEATURE_<NAME>`, adds the component
+    /// as with [`add_install_component`](Cmd::add_install_component), so
+    /// optional native pieces follow the crate's features. The files of
+    /// each component are listed in
+    /// [`installed_components`](crate::report::BuildReport::installed_components).
+    /// Since a `Cmd` without components installs the whole project, the
+    /// components needed regardless of features have to be added too.
+    ///
+    /// # Arguments
+    ///
+    /// * `features` - The `(feature, component)` pairs, e.g.
+    ///   `[("tools", "cli-bins")]`.
+    pub fn install_components_for_features<I, F, C>(mut self, features: I) -> Self
+    where
+        I: IntoIterator<Item = (F, C)>,
+        F: AsRef<str>,
+        C: Into<String>,
+    {
+        for (feature, component) in features {
+            let variable = format!(
+                "CARGO_FEATURE_{}",
+                feature.as_ref().to_uppercase().replace('-', "_")
+            );
+            let component = component.into();
+            if std::env::var_os(variable).is_some() && !self.install_components.contains(&component)
+            {
+                self.install_components.push(component);
+            }
+        }
+        self
+    }
+
+    /// Installs the project into `prefix` instead of building it.
+    ///
+    /// Header-only projects define only interface libraries, so building them
+    /// produces nothing in the output directory. In install-only mode, the
+    /// project is configured and then installed with
+    /// `cmake --install <binary> --prefix <prefix>`; the report lists the
+    /// installed files as artifacts and `<prefix>/include` in
+    /// [`include_dirs`](crate::report::BuildReport::include_dirs). Whether a
+    /// project is header-only is reported in
+    /// [`header_only`](crate::report::BuildReport::header_only) of every run.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - Path to the install prefix. Created if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// The run fails if the CMake file API reports targets that produce a
+    /// binary, since those would have to be built before they can be installed.
+    pub fn install_only<T>(mut self, prefix: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.install_only = true;
+        self.set_install_prefix(prefix)
+    }
+
+    /// Runs configure quietly, replaying it verbosely if it fails.
+    ///
+    /// Configure is run with `--log-level=ERROR`, so a successful configure
+    /// prints only errors. If it fails, it is run once more with
+    /// `--log-level=DEBUG --debug-output`; the failed report then holds both
+    /// configure phases, the verbose one last, so that
+    /// [`BuildFailure::stderr`] returns the detailed output. A configure
+    /// that succeeds on the replay continues the run.
+    ///
+    /// # Arguments
+    ///
+    /// * `quiet` - Whether configure runs quietly.
+    pub fn quiet_configure(mut self, quiet: bool) -> Self {
+        self.quiet_configure = quiet;
+        self
+    }
+
+    /// Makes the build print every compiler and linker command it runs.
+    ///
+    /// The build runs with `cmake --build --verbose`. Before CMake 3.14,
+    /// which has no such option, configure sets `CMAKE_VERBOSE_MAKEFILE`
+    /// instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `verbose` - Whether the build is verbose.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Sets which `message()` calls configure prints, with the context of
+    /// each message (`--log-level` and `--log-context`).
+    ///
+    /// [`quiet_configure`](Cmd::quiet_configure) takes precedence. Before
+    /// CMake 3.16 the level is passed as `--loglevel`, and before 3.15 it is
+    /// ignored; the context needs CMake 3.17.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The most detailed level printed.
+    pub fn log_level(mut self, level: LogLevel) -> Self {
+        self.log_level = Some(level);
+        self
+    }
+
+    /// Prefixes every forwarded output line, to tell apart the output of
+    /// builds running at the same time; see [`output`](crate::output).
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The text written in front of every line (e.g. `"[zlib] "`).
+    pub fn set_output_prefix<T>(mut self, prefix: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.output_style.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Colors the [output prefix](Cmd::set_output_prefix).
+    ///
+    /// # Arguments
+    ///
+    /// * `color` - The color of the prefix.
+    pub fn set_output_color(mut self, color: OutputColor) -> Self {
+        self.output_style.color = Some(color);
+        self
+    }
+
+    /// Holds forwarded output lines back until the run finishes, then
+    /// writes them as one block, so they do not interleave with the output
+    /// of builds running at the same time.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - Whether output is buffered.
+    pub fn buffer_output(mut self, buffer: bool) -> Self {
+        self.output_style.buffer = buffer.then(Arc::default);
+        self
+    }
+
+    /// Connects the stdout and stderr of configure, build, and install
+    /// directly to `stdout` and `stderr`, e.g. the two ends of a pty.
+    ///
+    /// Output that cmakr forwards passes through pipes, so CMake and the
+    /// native build tool don't see a terminal: Ninja and Make drop their
+    /// progress displays and compilers their colors. With handles of a
+    /// terminal connected directly, they behave as in a shell. The output is
+    /// then not seen by cmakr: no diagnostics are parsed from it, secrets
+    /// are not redacted from it, it is not captured into the report, and
+    /// output prefixes, [machine output](Cmd::machine_output) lines, and the
+    /// watchdog's stall detection don't apply. Both handles may refer to the
+    /// same file, e.g. with [`File::try_clone`](std::fs::File::try_clone).
+    ///
+    /// # Arguments
+    ///
+    /// * `stdout` - The handle standard output is written to (a `File`, or
+    ///   an `OwnedFd` or `OwnedHandle`).
+    /// * `stderr` - The handle standard error is written to.
+    pub fn set_output_streams<S, E>(mut self, stdout: S, stderr: E) -> Self
+    where
+        S: Into<std::fs::File>,
+        E: Into<std::fs::File>,
+    {
+        self.output_streams = Some(OutputStreams::new(stdout.into(), stderr.into()));
+        self
+    }
+
+    /// Runs configure, build, and install in a pseudo-terminal, so CMake and
+    /// the native build tool print progress displays and colors as in an
+    /// interactive shell, while their output is still parsed and captured.
+    ///
+    /// Escape sequences and overwritten progress lines are removed from the
+    /// output in the report, and stderr arrives as stdout. Takes precedence
+    /// over [`set_output_streams`](Cmd::set_output_streams). See
+    /// [`pty`](crate::pty).
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether the tools run in a pseudo-terminal.
+    #[cfg(feature = "pty")]
+    pub fn use_pty(mut self, enabled: bool) -> Self {
+        self.pty = enabled;
+        self
+    }
+
+    /// Clears `CMAKE_DEBUG_POSTFIX`, so libraries have the same names in debug
+    /// and release builds.
+    ///
+    /// Many projects append a postfix such as `d` to debug libraries
+    /// (`foo.lib` becomes `food.lib`), which breaks hardcoded link names in
+    /// `build.rs`. When enabled, `-DCMAKE_DEBUG_POSTFIX=` is passed at
+    /// configure. Projects that set the variable in their `CMakeLists.txt` or
+    /// the `DEBUG_POSTFIX` property on targets still override it; the
+    /// effective value is recorded in
+    /// [`BuildReport::debug_postfix`](crate::report::BuildReport::debug_postfix)
+    /// either way.
+    ///
+    /// # Arguments
+    ///
+    /// * `strip` - Whether to clear the debug postfix.
+    pub fn strip_debug_postfix(mut self, strip: bool) -> Self {
+        self.strip_debug_postfix = strip;
+        self
+    }
+
+    /// Keeps an editor integration up to date with the build tree.
+    ///
+    /// For [`IdeIntegration::Clangd`], `-DCMAKE_EXPORT_COMPILE_COMMANDS=ON`
+    /// is passed at configure unless the variable is defined explicitly, and
+    /// `compile_commands.json` is linked into the requested directory after
+    /// every successful configure. [`clean`](Cmd::clean) removes the link.
+    ///
+    /// # Arguments
+    ///
+    /// * `integration` - The editor integration.
+    pub fn ide_support(mut self, integration: IdeIntegration) -> Self {
+        self.ide = Some(integration);
+        self
+    }
+
+    /// Removes the build directory, and the compilation database linked by
+    /// [`ide_support`](Cmd::ide_support).
+    ///
+    /// The output directory is left alone when it is outside the build
+    /// directory. To remove only the build outputs and keep the configured
+    /// tree, use [`clean_target`](Cmd::clean_target).
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the build directory or the link cannot be
+    /// removed.
+    pub fn clean(&self) -> Result<(), Error> {
+        let cmd = self.resolved()?;
+        let binary_path = cmd.build_dir(cmd.resolved_preset().as_ref());
+        if let Some(ide) = &cmd.ide {
+            ide.remove(&binary_path)?;
+        }
+        if binary_path.exists() {
+            std::fs::remove_dir_all(&binary_path)?;
+        }
+        Ok(())
+    }
+
+    /// Builds the `clean` target of the configured tree, removing the build
+    /// outputs while keeping the cache, synchronously.
+    ///
+    /// Runs like [`build_only`](Cmd::build_only), without installing,
+    /// collecting notices or measuring sizes.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`build_only`](Cmd::build_only).
+    pub fn clean_target(mut self) -> ExecResult {
+        self.targets = vec!["clean".to_string()];
+        self.parallel_targets = None;
+        self.subdirectory = None;
+        self.clean_first = false;
+        self.install_prefix = None;
+        self.notices_dir = None;
+        self.size_analysis = None;
+        self.build_only()
+    }
+
+    /// Writes the configuration of this `Cmd` as VS Code CMake Tools settings
+    /// into the `.vscode` directory of `workspace`.
+    ///
+    /// A `cmakr` kit with the toolchain file, generator, and environment goes
+    /// into `cmake-kits.json`; the source and build directories, defines,
+    /// arguments, and install prefix go into `settings.json` as `cmake.*`
+    /// settings. Other kits and settings are kept. Values are written as
+    /// reported by [`effective_settings`](Cmd::effective_settings), so
+    /// [redacted](Cmd::redact) secrets appear redacted.
+    ///
+    /// # Arguments
+    ///
+    /// * `workspace` - The folder opened in VS Code, usually the source root.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `settings.json` or `cmake-kits.json` exists but is
+    /// not plain JSON, or if a file cannot be written.
+    pub fn export_vscode<P>(&self, workspace: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let effective = self.effective_settings()?;
+        let redactor = self.redactor();
+        let redacted = |args: &[&String]| -> Vec<String> {
+            args.iter()
+                .map(|arg| redactor.redact(arg).into_owned())
+                .collect()
+        };
+        let environment: serde_json::Map<String, serde_json::Value> = self
+            .environment
+            .iter()
+            .filter_map(|(name, value)| Some((name.clone(), value.clone()?.into())))
+            .collect();
+
+        let mut settings = serde_json::Map::new();
+        let mut set = |name: &str, value: serde_json::Value| {
+            settings.insert(format!("cmake.{}", name), value);
+        };
+        set("sourceDirectory", json!(effective.source_dir));
+        set("buildDirectory", json!(effective.binary_dir));
+        set(
+            "useCMakePresets",
+            json!(if self.preset.is_some() {
+                "always"
+            } else {
+                "never"
+            }),
+        );
+        if let Some(generator) = &effective.generator {
+            set("generator", json!(generator));
+        }
+        if let Some(platform) = &self.generator_platform {
+            set("platform", json!(platform));
+        }
+        if let Some(toolset) = &self.generator_toolset {
+            set("toolset", json!(toolset));
+        }
+        let defines: serde_json::Map<String, serde_json::Value> = effective
+            .defines
+            .into_iter()
+            .map(|(name, value)| (name, value.into()))
+            .collect();
+        set("configureSettings", defines.into());
+        let configure_args: Vec<&String> = self.args.iter().chain(&self.configure_args).collect();
+        set("configureArgs", json!(redacted(&configure_args)));
+        let build_args: Vec<&String> = self.args.iter().chain(&self.build_args).collect();
+        set("buildArgs", json!(redacted(&build_args)));
+        let native_args: Vec<&String> = self.native_build_args.iter().collect();
+        set("buildToolArgs", json!(redacted(&native_args)));
+        if let Some(prefix) = &effective.install_prefix {
+            set("installPrefix", json!(prefix));
+        }
+        if !environment.is_empty() {
+            set("configureEnvironment", environment.clone().into());
+        }
+
+        // a generated toolchain file is written now, where a build would write it
+        let toolchain_file = match (&effective.toolchain_file, &self.generated_toolchain) {
+            (Some(toolchain_file), _) => Some(toolchain_file.clone()),
+            (None, Some(toolchain)) => {
+                Some(write_generated_toolchain(toolchain, &effective.binary_dir)?)
+            }
+            (None, None) => None,
+        };
+        let mut kit = serde_json::Map::new();
+        if let Some(toolchain_file) = &toolchain_file {
+            kit.insert("toolchainFile".to_string(), json!(toolchain_file));
+        }
+        if let Some(generator) = &effective.generator {
+            let mut preferred = json!({ "name": generator });
+            if let Some(platform) = &self.generator_platform {
+                preferred["platform"] = json!(platform);
+            }
+            if let Some(toolset) = &self.generator_toolset {
+                preferred["toolset"] = json!(toolset);
+            }
+            kit.insert("preferredGenerator".to_string(), preferred);
+        }
+        if !environment.is_empty() {
+            kit.insert("environmentVariables".to_string(), environment.into());
+        }
+
+        ide::write_vscode(workspace.as_ref(), settings, kit)
+    }
+
+    /// Writes a `compile_flags.txt` for clangd into `dir`, from the compile
+    /// flags, defines, and include directories of `targets`.
+    ///
+    /// This is a fallback for generators that write no
+    /// `compile_commands.json` (Visual Studio, Xcode): clangd applies the one
+    /// set of flags to every file in `dir` and below. The flags are read from
+    /// the CMake file API reply of the configured tree, so configure first.
+    /// Without targets, the flags of all targets are merged.
+    ///
+    /// Returns the path of the written file.
+    ///
+    /// # Arguments
+    ///
+    /// * `targets` - The names of the targets whose flags are exported.
+    /// * `dir` - The directory to write the file to, usually the source root.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The tree has no file API reply (not configured, or CMake before 3.14)
+    /// - A target is not defined by the project
+    /// - The file cannot be written
+    pub fn export_compile_flags<I, T, P>(&self, targets: I, dir: P) -> Result<PathBuf, Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+        P: AsRef<Path>,
+    {
+        let binary_path = self.build_dir(self.resolved_preset().as_ref());
+        let Some(codemodel) = fileapi::targets(&binary_path)? else {
+            return Err(Error::Unsupported(format!(
+                "{} has no CMake file API reply; configure it with CMake 3.14 or later first",
+                binary_path.display()
+            )));
+        };
+        let names: Vec<String> = targets.into_iter().map(Into::into).collect();
+        if let Some(missing) = names
+            .iter()
+            .find(|name| !codemodel.iter().any(|target| &target.name == *name))
+        {
+            return Err(Error::InvalidConfig(format!(
+                "target {} is not defined by the project",
+                missing
+            )));
+        }
+
+        let groups = codemodel
+            .iter()
+            .filter(|target| names.is_empty() || names.contains(&target.name))
+            .flat_map(|target| &target.compile_groups);
+        let msvc = ["CMAKE_CXX_COMPILER_ID", "CMAKE_C_COMPILER_ID"]
+            .iter()
+            .find_map(|name| cmake::read_cache_value(&binary_path, name))
+            .is_some_and(|id| id == "MSVC");
+        let flags = ide::compile_flags(groups, msvc);
+
+        let path = dir.as_ref().join("compile_flags.txt");
+        let mut content = flags.join("\n");
+        content.push('\n');
+        std::fs::write(&path, content)?;
+        Ok(path)
+    }
+
+    /// Describes the libraries and executables of the configured tree as
+    /// build rules for other build systems, see [`interop`](crate::interop).
+    ///
+    /// Read from the CMake file API reply of the configured tree, so
+    /// configure first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tree has no file API reply (not configured,
+    /// or CMake before 3.14) or the source directory cannot be resolved.
+    pub fn build_description(&self) -> Result<BuildDescription, Error> {
+        let binary_path = self.build_dir(self.resolved_preset().as_ref());
+        let Some(targets) = fileapi::targets(&binary_path)? else {
+            return Err(Error::Unsupported(format!(
+                "{} has no CMake file API reply; configure it with CMake 3.14 or later first",
+                binary_path.display()
+            )));
+        };
+        let source_dir = std::path::absolute(self.path())?;
+        let build_type = cmake::read_cache_value(&binary_path, "CMAKE_BUILD_TYPE")
+            .filter(|build_type| !build_type.is_empty());
+        Ok(BuildDescription::new(&targets, &source_dir, build_type))
+    }
+
+    /// Reads a [`CmdConfig`] from a JSON or, with the `toml` feature, TOML
+    /// file and builds a [`Cmd`] from it.
+    ///
+    /// Files ending in `.toml` are read as TOML, others as JSON. Relative
+    /// paths in the file are resolved against the directory of the file,
+    /// which becomes the [working directory](Cmd::current_dir) of the build,
+    /// so a checked-in file works wherever it is read from.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the file (e.g. `"cmakr.toml"`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, does not describe a
+    /// [`CmdConfig`], or is TOML without the `toml` feature, and for the
+    /// errors of [`Cmd::try_from`].
+    pub fn from_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        let invalid =
+            |e: &dyn std::fmt::Display| Error::InvalidConfig(format!("{}: {}", path.display(), e));
+        let config: CmdConfig = if is_toml(path) {
+            #[cfg(feature = "toml")]
+            {
+                toml::from_str(&content).map_err(|e| invalid(&e))?
+            }
+            #[cfg(not(feature = "toml"))]
+            return Err(Error::Unsupported(format!(
+                "{} is TOML, which needs the toml feature",
+                path.display()
+            )));
+        } else {
+            serde_json::from_str(&content).map_err(|e| invalid(&e))?
+        };
+        let dir = std::path::absolute(path)?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        Ok(Cmd::try_from(config)?.current_dir(dir.to_string_lossy()))
+    }
+
+    /// Writes the settings a [`CmdConfig`] has fields for to a JSON or, with
+    /// the `toml` feature, TOML file, which [`from_file`](Cmd::from_file)
+    /// reads back.
+    ///
+    /// Files ending in `.toml` are written as TOML, others as JSON. Settings
+    /// left at their defaults are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the file (e.g. `"cmakr.toml"`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written, or is TOML without the
+    /// `toml` feature.
+    pub fn to_file<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let config = compact(serde_json::to_value(CmdConfig::from(self))?);
+        let content = if is_toml(path) {
+            #[cfg(feature = "toml")]
+            {
+                toml::to_string_pretty(&config)
+                    .map_err(|e| Error::InvalidConfig(format!("{}: {}", path.display(), e)))?
+            }
+            #[cfg(not(feature = "toml"))]
+            return Err(Error::Unsupported(format!(
+                "{} is TOML, which needs the toml feature",
+                path.display()
+            )));
+        } else {
+            let mut content = serde_json::to_string_pretty(&config)?;
+            content.push('\n');
+            content
+        };
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Keeps building independent targets after a compile error.
+    ///
+    /// When enabled, the native build tool is asked to continue past failed
+    /// jobs (`-k 0` for Ninja, `-k` for Makefile generators), so a single run
+    /// reports every broken translation unit instead of stopping at the first.
+    /// All parsed compiler errors are collected into the [`BuildReport`] carried
+    /// by the returned [`BuildFailure`]. Generators without such a switch build
+    /// as usual.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep_going` - Whether to keep going after failures.
+    pub fn keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// Configures the build tree afresh, discarding its existing cache.
+    ///
+    /// Use this after changing the toolchain or compilers of a tree, which
+    /// CMake otherwise keeps from the first configure. CMake 3.24 and later
+    /// are passed `--fresh`; for older versions, `CMakeCache.txt` and
+    /// `CMakeFiles` are removed from the build directory before configure.
+    ///
+    /// # Arguments
+    ///
+    /// * `fresh` - Whether configure starts from an empty cache.
+    pub fn fresh(mut self, fresh: bool) -> Self {
+        self.fresh = fresh;
+        self
+    }
+
+    /// Writes the target dependency graph to `path` while configuring
+    /// (`--graphviz=<path>`), to be read with
+    /// [`BuildGraph::read_graphviz`](crate::graph::BuildGraph::read_graphviz).
+    ///
+    /// CMake also writes one file per target next to it, named after `path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The graphviz file (e.g. `"build/targets.dot"`).
+    pub fn emit_graphviz<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.graphviz = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Traces configure, writing every CMake command it runs to a file in
+    /// the build tree; see [`trace`](crate::trace). A JSON trace is read
+    /// with [`BuildReport::trace`].
+    ///
+    /// Requires CMake 3.17 or later.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format of the trace.
+    pub fn trace(mut self, format: TraceFormat) -> Self {
+        self.trace = Some(format);
+        self
+    }
+
+    /// Skips configure if nothing it depends on changed since the last
+    /// successful configure of the tree, going straight to the build.
+    ///
+    /// Meant for build scripts, which run on every `cargo build`. The inputs
+    /// are fingerprinted: the CMake version, the configure arguments
+    /// (defines, preset, generator, toolchain) and environment, the
+    /// toolchain file, and the project's `CMakeLists.txt`, `*.cmake`, and
+    /// presets files. The fingerprint is stored in the build directory. A
+    /// [fresh](Cmd::fresh) configure is never skipped. A skipped configure
+    /// has no configure phase in the report, and sets
+    /// [`BuildReport::configure_skipped`].
+    pub fn skip_configure_if_unchanged(mut self) -> Self {
+        self.skip_unchanged_configure = true;
+        self
+    }
+
+    /// Sets how [`skip_configure_if_unchanged`](Cmd::skip_configure_if_unchanged)
+    /// fingerprints the project's CMake files. Default:
+    /// [`FileMetadata`](crate::fingerprint::FileMetadata), their size and
+    /// modification time.
+    ///
+    /// # Arguments
+    ///
+    /// * `fingerprinter` - The strategy (e.g.
+    ///   [`ContentHash`](crate::fingerprint::ContentHash) or
+    ///   [`GitStatus`](crate::fingerprint::GitStatus)).
+    pub fn set_fingerprinter<T>(mut self, fingerprinter: T) -> Self
+    where
+        T: Fingerprinter + 'static,
+    {
+        self.fingerprinter = Arc::new(fingerprinter);
+        self
+    }
+
+    /// Cleans the build tree before building (`cmake --build --clean-first`),
+    /// so everything is rebuilt.
+    ///
+    /// Cannot be combined with
+    /// [`build_targets_parallel`](Cmd::build_targets_parallel), whose
+    /// concurrent invocations would clean each other's outputs.
+    ///
+    /// # Arguments
+    ///
+    /// * `clean_first` - Whether the build step cleans first.
+    pub fn clean_first(mut self, clean_first: bool) -> Self {
+        self.clean_first = clean_first;
+        self
+    }
+
+    /// Prints parsed compiler diagnostics as CI annotations.
+    ///
+    /// After the build step, every warning and error found in the output is
+    /// printed to stdout in the annotation format of the given CI system, so
+    /// it shows up inline on pull requests. With
+    /// [`machine_output`](Cmd::machine_output), which owns stdout, they are
+    /// printed to stderr. See [`ci`](crate::ci) for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `flavor` - The CI system to format annotations for.
+    pub fn ci_annotations(mut self, flavor: CiFlavor) -> Self {
+        self.ci_annotations = Some(flavor);
+        self
+    }
+
+    /// Prints structured progress events to stdout for embedding supervisors.
+    ///
+    /// Every phase start and end, every line of cmake and build tool output,
+    /// build progress, parsed diagnostics, and the final outcome are printed
+    /// to cmakr's own stdout in `format`, one event per line. Child output is
+    /// only delivered inside these events, so stdout can be parsed reliably.
+    /// See [`machine`](crate::machine) for the event schema.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The event encoding (e.g. [`Format::JsonLines`]).
+    pub fn machine_output(mut self, format: Format) -> Self {
+        self.machine_output = Some(self.machine_output.unwrap_or_default().stdout(format));
+        self
+    }
+
+    /// Hands progress events to `callback` while the run is in progress.
+    ///
+    /// The callback receives the same events as
+    /// [`machine_output`](Cmd::machine_output) prints, as
+    /// [`ProgressEvent`](crate::machine::ProgressEvent) values: phase starts
+    /// and ends, every line of cmake and build tool output, build progress
+    /// parsed from Ninja's `[3/10]` and Makefiles' `[ 42%]` prefixes, parsed
+    /// diagnostics, and the final outcome. It runs on the threads reading the
+    /// child's output, so it should return quickly; send the events over a
+    /// channel to process them elsewhere. As with `machine_output`, child
+    /// output is no longer forwarded to the parent's streams.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - Called with every event, in order per phase.
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&ProgressEvent) + Send + Sync + 'static,
+    {
+        self.machine_output = Some(
+            self.machine_output
+                .unwrap_or_default()
+                .callback(Arc::new(callback)),
+        );
+        self
+    }
+
+    /// Writes reproduction scripts into the build directory when a run fails.
+    ///
+    /// On a configure or build failure, `repro.sh` and `repro.ps1` are written
+    /// next to `CMakeCache.txt`. They set the compiler-related environment
+    /// variables (`CC`, `CXX`, `CFLAGS`, ..., and every `CMAKE_*` variable) to
+    /// the values cmakr saw, change to its working directory, and run the
+    /// exact configure and build commands, so the failure can be reproduced
+    /// by someone without cmakr installed. Review the scripts before sharing
+    /// them, as the recorded variables and defines may contain secrets.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to write the scripts.
+    pub fn write_repro_script(mut self, enabled: bool) -> Self {
+        self.repro_script = enabled;
+        self
+    }
+
+    /// Writes a diagnostic archive to `path` when a configure, build, or
+    /// install step fails.
+    ///
+    /// The archive is an uncompressed tar holding `CMakeCache.txt`, CMake's
+    /// configure logs, and the last lines of the failed step's output, so CI
+    /// can upload a single file per failed build. Writing it is best effort
+    /// and never replaces the build error.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the archive (e.g. `"target/cmake-failure.tar"`).
+    pub fn write_failure_bundle<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.failure_bundle = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Sets an environment variable for every cmake process of the run, and
+    /// thereby for the compilers and tools it starts.
+    ///
+    /// The environment is inherited from the current process otherwise, e.g.
+    /// to point `CC`/`CXX` at another compiler or to set `CMAKE_PREFIX_PATH`.
+    /// Setting a variable again replaces the earlier value.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The variable name.
+    /// * `value` - The value to set.
+    pub fn env<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let key = key.into();
+        self.environment.retain(|(name, _)| *name != key);
+        self.environment.push((key, Some(value.into())));
+        self.tools.search_path = self.search_path();
+        self
+    }
+
+    /// Sets several environment variables, as with [`env`](Cmd::env).
+    ///
+    /// # Arguments
+    ///
+    /// * `vars` - The `(name, value)` pairs to set.
+    pub fn envs<I, K, V>(self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        vars.into_iter()
+            .fold(self, |cmd, (key, value)| cmd.env(key, value))
+    }
+
+    /// Removes an environment variable from the environment of cmake, e.g.
+    /// the `CFLAGS` that cargo passes to build scripts.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The variable name.
+    pub fn env_remove<K>(mut self, key: K) -> Self
+    where
+        K: Into<String>,
+    {
+        let key = key.into();
+        self.environment.retain(|(name, _)| *name != key);
+        self.environment.push((key, None));
+        self.tools.search_path = self.search_path();
+        self
+    }
+
+    /// Runs cmake with an empty environment, apart from variables set
+    /// afterwards with [`env`](Cmd::env) and [`credentials`](Cmd::set_credentials).
+    ///
+    /// Variables set before are discarded. Without `PATH`, cmake finds
+    /// neither the build tool nor the compilers, so it usually has to be set
+    /// again.
+    pub fn env_clear(mut self) -> Self {
+        self.environment.clear();
+        self.environment_cleared = true;
+        self.tools.search_path = self.search_path();
+        self
+    }
+
+    /// Asks the compilers for English diagnostics, whatever the locale of
+    /// the system, so they are parsed into the report and logs read the same
+    /// everywhere.
+    ///
+    /// Sets `VSLANG` for MSVC and `LC_MESSAGES`/`LANGUAGE` for GCC, unless
+    /// they are set with [`env`](Cmd::env). Their messages are ASCII, so
+    /// they read the same in every console code page; output that is not
+    /// UTF-8 is decoded from the console code page on Windows either way.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether diagnostics are forced to English.
+    pub fn english_diagnostics(mut self, enabled: bool) -> Self {
+        self.english_diagnostics = enabled;
+        self
+    }
+
+    /// Injects secrets into the environment of cmake, e.g. tokens for
+    /// `FetchContent` downloads from private hosts.
+    ///
+    /// The provider is asked for [`Credentials`] once per build, before
+    /// configuring. They are passed to the configure, build, and install
+    /// steps only as environment variables, and their values are redacted
+    /// from forwarded and captured output, diagnostics, and machine events.
+    /// See [`credentials`](crate::credentials) for the available providers.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The source of the credentials (e.g. an
+    ///   [`EnvCredentials`](crate::credentials::EnvCredentials) or a closure).
+    pub fn set_credentials<T>(mut self, provider: T) -> Self
+    where
+        T: CredentialsProvider + 'static,
+    {
+        self.credentials = Some(Arc::new(provider));
+        self
+    }
+
+    /// Redacts secrets from all output and errors of the build.
+    ///
+    /// A pattern ending in `=` (e.g. `"SERVICE_TOKEN="`) redacts the value
+    /// following it up to the next whitespace or quote, which covers defines
+    /// like `-DSERVICE_TOKEN=...` and `NAME=value` environment dumps. Any other
+    /// pattern is a literal secret redacted wherever it appears. Matches are
+    /// replaced by `***` in forwarded and captured output, diagnostics,
+    /// machine events, the commands and messages of the returned report or
+    /// error, and the configuration recorded in `.cmakr/state.json`.
+    ///
+    /// # Arguments
+    ///
+    /// * `patterns` - The secrets or `NAME=` prefixes to redact.
+    pub fn redact<I, T>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.redactions.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Pre-populates a fresh build tree with cache entries from another machine.
+    ///
+    /// When the build directory has no `CMakeCache.txt` yet, the seed is
+    /// written as an initial-cache script and passed to configure with `-C`,
+    /// so feature checks whose results it contains are skipped. Defines set
+    /// with [`add_define`](Cmd::add_define) take precedence over seeded
+    /// values. Trees that are already configured are not touched. See
+    /// [`seed`](crate::seed) for how seeds are exported.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The exported cache entries.
+    pub fn seed_cache(mut self, seed: CacheSeed) -> Self {
+        self.cache_seed = Some(seed);
+        self
+    }
+
+    /// Retries builds that ran out of memory with fewer parallel jobs.
+    ///
+    /// The build step is run with an explicit `--parallel` job count while
+    /// system memory is monitored. If it fails with signs of memory
+    /// exhaustion (a process killed by `SIGKILL`, compiler messages such as
+    /// `Killed signal terminated program cc1plus`, or available memory
+    /// nearly running out), it is retried with half the jobs, as configured
+    /// by `backoff`. Every attempt is recorded in [`BuildReport::phases`].
+    ///
+    /// # Arguments
+    ///
+    /// * `backoff` - The job counts and retry limit to use.
+    pub fn oom_backoff(mut self, backoff: OomBackoff) -> Self {
+        self.oom_backoff = Some(backoff);
+        self
+    }
+
+    /// Runs a configure, and with [`RetryPolicy::retry_build`] a build, again
+    /// when it failed for a transient reason such as a network error of a
+    /// `FetchContent` download; see [`retry`](crate::retry).
+    ///
+    /// The retries are recorded in [`BuildReport::retries`], and the phases
+    /// of the failed attempts stay in [`BuildReport::phases`].
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - When and how often to retry.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Measures the binaries of a successful build.
+    ///
+    /// After the build, the sections and largest symbols of the produced
+    /// executables and libraries are recorded in
+    /// [`BuildReport::size`](crate::report::BuildReport::size), and checked
+    /// against the thresholds of `analysis`; see [`size`](crate::size).
+    ///
+    /// # Arguments
+    ///
+    /// * `analysis` - The symbol count and size thresholds to use.
+    pub fn analyze_size(mut self, analysis: SizeAnalysis) -> Self {
+        self.size_analysis = Some(analysis);
+        self
+    }
+
+    /// Fails a successful build with [`Error::NewWarnings`] if it has
+    /// warnings that are not in the baseline at `path`, as saved with
+    /// [`diagnostics::save`](crate::diagnostics::save).
+    ///
+    /// Warnings are matched by file relative to the source directory, code,
+    /// and message, see
+    /// [`diagnostics::new_since`](crate::diagnostics::new_since). A missing
+    /// baseline file is not an error, and no warning is new.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the baseline of warnings.
+    pub fn fail_on_new_warnings<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.warnings_baseline = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Collects the license files of third-party dependencies after a
+    /// successful build.
+    ///
+    /// The `LICENSE`, `COPYING` and `NOTICE` files of the `FetchContent` and
+    /// `ExternalProject` sources in the build tree are copied into `dir`, one
+    /// subdirectory per dependency, and recorded in
+    /// [`BuildReport::notices`](crate::report::BuildReport::notices) and
+    /// [`BuildReport::artifacts`](crate::report::BuildReport::artifacts); see
+    /// [`notices`](crate::notices).
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Path to the notices directory.
+    pub fn collect_notices<T>(mut self, dir: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.notices_dir = Some(PathBuf::from(dir.into()));
+        self
+    }
+
+    /// Renames or copies the artifacts whose file name matches `pattern`
+    /// after a successful build.
+    ///
+    /// In the pattern, `*` stands for any run of characters and `?` for a
+    /// single one. Rules apply in the order they are added, each to the
+    /// artifacts as the previous rules left them, before notices are
+    /// collected and the size is analyzed;
+    /// [`BuildReport::artifacts`](crate::report::BuildReport::artifacts)
+    /// lists the renamed files and the copies. See [`Action`].
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The glob pattern, e.g. `"libfoo.so.*.*.*"`.
+    /// * `action` - What to do with the matching artifacts.
+    ///
+    /// # Errors
+    ///
+    /// The build fails with an I/O error if a file cannot be renamed or
+    /// copied.
+    pub fn artifact_rule<T>(mut self, pattern: T, action: Action) -> Self
+    where
+        T: Into<String>,
+    {
+        self.artifact_rules.push(artifact::Rule {
+            pattern: pattern.into(),
+            action,
+        });
+        self
+    }
+
+    /// Watches the commands of `phase` for running too long or printing
+    /// nothing for too long.
+    ///
+    /// A command killed by the watchdog fails the run with
+    /// [`Error::TimedOut`]. Setting a watchdog for a phase again replaces
+    /// the previous one. See [`watchdog`](crate::watchdog) for stall actions.
+    ///
+    /// # Arguments
+    ///
+    /// * `phase` - The phase whose commands are watched.
+    /// * `watchdog` - The limits of the phase's commands.
+    pub fn set_watchdog(mut self, phase: Phase, watchdog: Watchdog) -> Self {
+        self.watchdogs.retain(|(watched, _)| *watched != phase);
+        self.watchdogs.push((phase, watchdog));
+        self
+    }
+
+    /// Prints `message` every `interval` while a configure, build, or install
+    /// command prints nothing, so that CI systems that kill silent jobs
+    /// don't kill a long link step.
+    ///
+    /// The heartbeat goes where the command's lines go: through the
+    /// [output prefix](Cmd::set_output_prefix) or
+    /// [machine output](Cmd::machine_output), or to stderr. `{elapsed}` in
+    /// the message is replaced with how long the command has run. A
+    /// [watchdog](Cmd::set_watchdog) with its own
+    /// [keepalive](Watchdog::keepalive) overrides this one for its phase.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - How long a command may go without a line before a
+    ///   heartbeat, and between heartbeats (e.g. 5 minutes).
+    /// * `message` - The heartbeat line (e.g. `"cmakr: still building ({elapsed})"`).
+    pub fn keepalive<T>(mut self, interval: Duration, message: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.keepalive = Some((interval, message.into()));
+        self
+    }
+
+    /// Kills a configure, build, or install command that runs longer than
+    /// `timeout`, with its child processes, failing the run with
+    /// [`Error::TimedOut`].
+    ///
+    /// The limit applies to each command, e.g. to each `cmake --build` of
+    /// [parallel targets](Cmd::build_targets_parallel). A
+    /// [watchdog](Cmd::set_watchdog) with its own timeout overrides this one
+    /// for its phase.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long a command may run.
+    pub fn set_timeout(self, timeout: Duration) -> Self {
+        [Phase::Configure, Phase::Build, Phase::Install]
+            .into_iter()
+            .fold(self, |cmd, phase| cmd.set_phase_timeout(phase, timeout))
+    }
+
+    /// Kills a configure command that runs longer than `timeout`, e.g. one
+    /// stuck in an `ExternalProject` or `FetchContent` download, see
+    /// [`set_timeout`](Cmd::set_timeout).
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long configure may run.
+    pub fn set_configure_timeout(self, timeout: Duration) -> Self {
+        self.set_phase_timeout(Phase::Configure, timeout)
+    }
+
+    /// Kills a build command that runs longer than `timeout`, see
+    /// [`set_timeout`](Cmd::set_timeout).
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long a build command may run.
+    pub fn set_build_timeout(self, timeout: Duration) -> Self {
+        self.set_phase_timeout(Phase::Build, timeout)
+    }
+
+    /// Sets how long the commands of `phase` may run.
+    fn set_phase_timeout(mut self, phase: Phase, timeout: Duration) -> Self {
+        self.timeouts.retain(|(limited, _)| *limited != phase);
+        self.timeouts.push((phase, timeout));
+        self
+    }
+
+    /// Adds a target to build, passed to the build step as `--target`.
+    ///
+    /// Without targets, the default target (`all`) is built. Targets of
+    /// [`restrict_to_subdirectory`](Cmd::restrict_to_subdirectory) are built
+    /// in addition to these.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The name of the target (e.g. `"mylib"`).
+    pub fn add_target<T>(mut self, target: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.targets.push(target.into());
+        self
+    }
+
+    /// Sets the number of parallel jobs of the build step (`--parallel <n>`).
+    ///
+    /// Without a job count, the native build tool decides (Ninja uses all
+    /// cores, Make runs one job at a time). With [`oom_backoff`](Cmd::oom_backoff)
+    /// enabled, the first attempt uses this count.
+    ///
+    /// # Arguments
+    ///
+    /// * `jobs` - The number of jobs, at least 1.
+    pub fn set_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = Some(jobs.max(1));
+        self
+    }
+
+    /// Sets the number of parallel jobs of the build step to the available
+    /// parallelism of this machine.
+    pub fn set_jobs_auto(self) -> Self {
+        let jobs = thread::available_parallelism().map_or(1, |n| n.get());
+        self.set_jobs(jobs)
+    }
+
+    /// Builds only the targets defined under a source subdirectory.
+    ///
+    /// In large monorepo trees, only one component is usually needed. The
+    /// whole project is still configured, but the build step is limited to the
+    /// targets that the CMake file API reports as defined in `subdirectory` or
+    /// one of its subdirectories (passed as `--target` arguments), along with
+    /// whatever they depend on. Targets given to
+    /// [`build_targets_parallel`](Cmd::build_targets_parallel) take precedence.
+    ///
+    /// # Arguments
+    ///
+    /// * `subdirectory` - Path relative to the source directory (e.g. `"libs/foo"`).
+    ///
+    /// # Errors
+    ///
+    /// The run fails if CMake is too old for the file API (before 3.14) or no
+    /// buildable target is defined under `subdirectory`.
+    pub fn restrict_to_subdirectory<T>(mut self, subdirectory: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.subdirectory = Some(PathBuf::from(subdirectory.into()));
+        self
+    }
+
+    /// Builds `targets` with concurrent `cmake --build --target` invocations.
+    ///
+    /// Instead of a single build step, one invocation per target is started at
+    /// once and `max_jobs` is split evenly between them as their `--parallel`
+    /// job counts, so the total number of compiler processes stays within the
+    /// budget. With more targets than jobs, at most `max_jobs` invocations of
+    /// one job each run at a time. This helps when a few large targets would
+    /// otherwise be built one after another by a generator that serializes
+    /// them. Each invocation is recorded as its own build phase in
+    /// [`BuildReport::phases`], in the order of `targets`.
+    ///
+    /// All invocations share the same build tree, which cmake doesn't guard
+    /// against concurrent builds: the targets should not depend on each other,
+    /// or shared dependencies may be built twice at the same time, and with
+    /// Ninja every invocation appends to the tree's `.ninja_log` and
+    /// `.ninja_deps`, which Ninja may find damaged and rebuild from scratch on
+    /// the next build. Build shared dependencies first, as targets of an
+    /// earlier run. With [`oom_backoff`](Cmd::oom_backoff) enabled, a retry
+    /// halves the whole budget.
+    ///
+    /// # Arguments
+    ///
+    /// * `targets` - The names of the targets to build (e.g. `["app", "tests"]`).
+    /// * `max_jobs` - The total number of jobs shared by all invocations.
+    pub fn build_targets_parallel<I, T>(mut self, targets: I, max_jobs: usize) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        let targets: Vec<String> = targets.into_iter().map(Into::into).collect();
+        self.parallel_targets = (!targets.is_empty()).then(|| (targets, max_jobs.max(1)));
+        self
+    }
+
+    /// Maps locations under `from` back to `to` in everything cmakr reports.
+    ///
+    /// Use this when the project is built from a copied or temporary source
+    /// tree, or inside a container, so that diagnostics (and the CI
+    /// annotations and SARIF logs derived from them) point at the real
+    /// workspace files. The report's `source_dir` is remapped as well. When
+    /// several remaps match, the first one added wins.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The path prefix as seen by the build (e.g. `"/tmp/src-copy"`).
+    /// * `to` - The path prefix to report instead (e.g. `"/home/me/project"`).
+    pub fn path_remap<T, U>(mut self, from: T, to: U) -> Self
+    where
+        T: Into<String>,
+        U: Into<String>,
+    {
+        self.path_remaps
+            .push((PathBuf::from(from.into()), PathBuf::from(to.into())));
+        self
+    }
+
+    /// Attaches a token that kills the running cmake process when cancelled.
+    pub(crate) fn set_cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Hands progress events to `sink` instead of printing them to stdout.
+    #[cfg(feature = "server")]
+    pub(crate) fn set_event_sink(mut self, sink: machine::EventSink) -> Self {
+        self.machine_output = Some(self.machine_output.unwrap_or_default().sink(sink));
+        self
+    }
+
+    /// Exports a trace of every run to an OpenTelemetry collector.
+    ///
+    /// See [`otel`](crate::otel) for the spans and their attributes.
+    ///
+    /// # Arguments
+    ///
+    /// * `exporter` - The collector to send the spans to.
+    #[cfg(feature = "otel")]
+    pub fn set_otlp_exporter(mut self, exporter: crate::otel::OtlpExporter) -> Self {
+        self.otlp_exporter = Some(exporter);
+        self
+    }
+
+    /// Notifies `notifier` when a run of [`build`](Cmd::build) or
+    /// [`spawn`](Cmd::spawn) is over, successful or not.
+    ///
+    /// Can be called several times to notify several receivers. See
+    /// [`notify`](crate::notify) for the built-in webhook and command
+    /// notifiers and their JSON payload.
+    ///
+    /// # Arguments
+    ///
+    /// * `notifier` - The receiver of the notification (e.g. a
+    ///   [`WebhookNotifier`](crate::notify::WebhookNotifier) or a closure).
+    pub fn notify<T>(mut self, notifier: T) -> Self
+    where
+        T: Notifier + 'static,
+    {
+        self.notifiers.push(Arc::new(notifier));
+        self
+    }
+
+    /// Calls `hook` before every configure, with the resolved configure
+    /// arguments; see [`hooks`](crate::hooks).
+    ///
+    /// Can be called several times; the hooks run in the order they were
+    /// added.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - The callback. An error it returns stops the run.
+    pub fn on_before_configure<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&HookContext<'_>) -> std::io::Result<()> + Send + Sync + 'static,
+    {
+        self.hooks.before_configure.push(Arc::new(hook));
+        self
+    }
+
+    /// Calls `hook` after every configure, successful or not, with its
+    /// result as [`previous`](HookContext::previous).
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - The callback. An error it returns stops the run.
+    pub fn on_after_configure<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&HookContext<'_>) -> std::io::Result<()> + Send + Sync + 'static,
+    {
+        self.hooks.after_configure.push(Arc::new(hook));
+        self
+    }
+
+    /// Calls `hook` before every build attempt, with the `cmake --build`
+    /// arguments and the configure, if one ran, as
+    /// [`previous`](HookContext::previous).
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - The callback. An error it returns stops the run.
+    pub fn on_before_build<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&HookContext<'_>) -> std::io::Result<()> + Send + Sync + 'static,
+    {
+        self.hooks.before_build.push(Arc::new(hook));
+        self
+    }
+
+    /// Calls `hook` after every build attempt, successful or not, with its
+    /// result as [`previous`](HookContext::previous).
+    ///
+    /// With [`build_targets_parallel`](Cmd::build_targets_parallel), the
+    /// hook runs once after all targets, with the last one as `previous`.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - The callback. An error it returns stops the run.
+    pub fn on_after_build<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&HookContext<'_>) -> std::io::Result<()> + Send + Sync + 'static,
+    {
+        self.hooks.after_build.push(Arc::new(hook));
+        self
+    }
+
+    /// Executes CMake configure and build synchronously.
+    ///
+    /// This consumes the builder and runs the full CMake workflow
+    /// (configure + build) in the current thread, blocking until completion.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - `cmake` is not found on `PATH`
+    /// - The preset name is invalid or not found
+    /// - The configure step fails (non-zero exit code)
+    /// - The build step fails (non-zero exit code)
+    /// - Any I/O error occurs (directory creation, path resolution, etc.)
+    pub fn build(mut self) -> ExecResult {
+        let started_at = SystemTime::now();
+        let result = scrub(self.execute(), &self.redactor());
+        self.output_style.flush();
+        self.export_trace(started_at, &result);
+        self.send_notifications(started_at, &result);
+        result
+    }
+
+    /// Runs only the CMake configure step, synchronously.
+    ///
+    /// Configures (or re-configures) the build tree like [`build`](Cmd::build)
+    /// would, without building. The returned report has the configure phase
+    /// and no artifacts. Follow up with [`build_only`](Cmd::build_only) to
+    /// build the tree.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`build`](Cmd::build), except that nothing is built.
+    pub fn configure(mut self) -> ExecResult {
+        self.steps = Steps::ConfigureOnly;
+        self.build()
+    }
+
+    /// Builds an already configured tree without re-configuring it.
+    ///
+    /// Skips the configure step, so a build script whose sources changed but
+    /// whose `CMakeLists.txt` and settings did not can go straight to the
+    /// build step. The native build tool still re-runs cmake on its own if a
+    /// `CMakeLists.txt` changed. Settings that only apply at configure time
+    /// (defines, preset, generator, toolchain) are not re-applied. Installs
+    /// afterwards if an [install prefix](Cmd::set_install_prefix) is set.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`build`](Cmd::build), and returns [`Error::InvalidConfig`] if
+    /// the build directory has no `CMakeCache.txt`.
+    pub fn build_only(mut self) -> ExecResult {
+        self.steps = Steps::BuildOnly;
+        self.build()
+    }
+
+    /// Runs the stages of `pipeline` in order, synchronously.
+    ///
+    /// Configure, build and install stages run like [`configure`](Cmd::configure),
+    /// [`build_only`](Cmd::build_only) (without installing) and an install of
+    /// the built tree; test and package stages run [`ctest`](Cmd::ctest) and
+    /// [`cpack`](Cmd::cpack). Custom stages may change this `Cmd` for the
+    /// stages that follow. The returned report has the phases of all stages
+    /// in order, the results of the last test stage in
+    /// [`tests`](BuildReport::tests), and the produced
+    /// [`packages`](BuildReport::packages). A cancellation stops the pipeline
+    /// between stages.
+    ///
+    /// # Arguments
+    ///
+    /// * `pipeline` - The stages to run.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first stage that fails, which for configure,
+    /// build and install carries the phases of all stages so far, and
+    /// [`Error::TestFailed`] if a test fails. Returns [`Error::InvalidConfig`]
+    /// if an install stage is reached without an
+    /// [install prefix](Cmd::set_install_prefix).
+    ///
+    /// ```no_run
+    /// use cmakr::{Cmd, pipeline::Pipeline};
+    ///
+    /// let report = Cmd::default()
+    ///     .set_path("./my_project")
+    ///     .set_install_prefix("./dist")
+    ///     .run_pipeline(&Pipeline::new().configure().build().test().install())
+    ///     .unwrap();
+    /// assert!(!report.installed.is_empty());
+    /// ```
+    pub fn run_pipeline(mut self, pipeline: &Pipeline) -> ExecResult {
+        let started_at = SystemTime::now();
+        let result = scrub(self.execute_pipeline(pipeline), &self.redactor());
+        self.output_style.flush();
+        self.export_trace(started_at, &result);
+        self.send_notifications(started_at, &result);
+        result
+    }
+
+    /// Runs a workflow preset of `CMakePresets.json`: its configure, build,
+    /// test, and package steps in order.
+    ///
+    /// With CMake 3.25 or newer, this is `cmake --workflow --preset=<name>`
+    /// in the source directory, with `--fresh` for a [fresh](Cmd::fresh)
+    /// configure, and the report has a single [`Phase::Workflow`]. Older
+    /// versions have no workflows, so cmakr runs the steps itself as
+    /// [pipeline](Cmd::run_pipeline) stages with their presets; a package
+    /// step runs cpack in the build tree, without the settings of its
+    /// package preset.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the workflow preset.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PresetNotFound`] if the workflow or a preset of its
+    /// steps is not defined, [`Error::PresetParse`] if it doesn't start with
+    /// a configure step, and [`Error::BuildFailed`] if `cmake --workflow`
+    /// fails. The steps run by cmakr fail like the stages of
+    /// [`run_pipeline`](Cmd::run_pipeline).
+    ///
+    /// ```no_run
+    /// use cmakr::Cmd;
+    ///
+    /// let report = Cmd::default().set_path("./my_project").run_workflow("ci").unwrap();
+    /// println!("{}", report.summary());
+    /// ```
+    pub fn run_workflow<T>(mut self, name: T) -> ExecResult
+    where
+        T: Into<String>,
+    {
+        let started_at = SystemTime::now();
+        let result = scrub(self.execute_workflow(&name.into()), &self.redactor());
+        self.output_style.flush();
+        self.export_trace(started_at, &result);
+        self.send_notifications(started_at, &result);
+        result
+    }
+
+    /// Builds the project with profile-guided optimization.
+    ///
+    /// Runs the generate, train, use cycle as a [pipeline](Cmd::run_pipeline):
+    /// configures the tree to detect its compiler, configures and builds it
+    /// with instrumentation, runs the training workload of `config`,
+    /// configures and builds it again with the recorded profile, and
+    /// installs it if an [install prefix](Cmd::set_install_prefix) is set.
+    /// The PGO flags of the compiler (see [`pgo`](crate::pgo)) are appended
+    /// to the user's own compiler and linker flags defines.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The training workload and PGO settings.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`run_pipeline`](Cmd::run_pipeline), and returns
+    /// [`Error::Unsupported`] if the compiler has no known PGO flags, and
+    /// [`Error::ToolFailed`] if the training workload fails or records no
+    /// profile.
+    pub fn pgo(self, config: PgoConfig) -> ExecResult {
+        let config = Arc::new(config);
+        let user_flags: Arc<Vec<(String, String)>> = Arc::new(
+            self.defines
+                .iter()
+                .map(|d| {
+                    let name = d.name.split(':').next().unwrap_or_default();
+                    (name.to_string(), d.value.clone())
+                })
+                .collect(),
+        );
+
+        let generate = {
+            let (config, user_flags) = (config.clone(), user_flags.clone());
+            move |cmd: &mut Cmd, report: &BuildReport| {
+                let compiler = config.compiler(&report.binary_dir)?;
+                let profile_dir = config.profile_dir(&report.binary_dir)?;
+                if profile_dir.exists() {
+                    std::fs::remove_dir_all(&profile_dir)?;
+                }
+                std::fs::create_dir_all(&profile_dir)?;
+                cmd.set_flags(&user_flags, compiler.generate_flags(&profile_dir));
+                Ok(())
+            }
+        };
+        let train = {
+            let config = config.clone();
+            move |cmd: &mut Cmd, report: &BuildReport| {
+                let mut command = config.training_command()?;
+                cmd.apply_environment(&mut command);
+                let output = command.output().map_err(|e| {
+                    Error::ToolFailed(format!("the training workload could not be run: {}", e))
+                })?;
+                if !output.status.success() {
+                    return Err(Error::ToolFailed(format!(
+                        "the training workload failed: {}\n{}",
+                        ProcessExit::from(output.status),
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    )));
+                }
+                let compiler = config.compiler(&report.binary_dir)?;
+                let profile_dir = config.profile_dir(&report.binary_dir)?;
+                compiler.merge_profiles(&report.binary_dir, &profile_dir, |command| {
+                    cmd.apply_environment(command)
+                })
+            }
+        };
+        let optimize = move |cmd: &mut Cmd, report: &BuildReport| {
+            let compiler = config.compiler(&report.binary_dir)?;
+            let profile_dir = config.profile_dir(&report.binary_dir)?;
+            cmd.set_flags(&user_flags, compiler.use_flags(&profile_dir));
+            Ok(())
+        };
+
+        let mut pipeline = Pipeline::new()
+            .configure()
+            .custom("pgo-generate", generate)
+            .configure()
+            .build()
+            .custom("pgo-train", train)
+            .custom("pgo-use", optimize)
+            .configure()
+            .build();
+        if self.install_prefix.is_some() {
+            pipeline = pipeline.install();
+        }
+        self.run_pipeline(&pipeline)
+    }
+
+    /// Executes CMake configure and build synchronously, capturing their
+    /// output instead of forwarding it to the parent's stdout and stderr.
+    ///
+    /// Every phase of the returned report, or of the report carried by a
+    /// [`BuildFailure`], holds the redacted
+    /// [`stdout`](PhaseTiming::stdout) and [`stderr`](PhaseTiming::stderr)
+    /// of its command next to its exit status. This keeps the output of a
+    /// build script from interleaving with Cargo's, while still being
+    /// available to log on failure.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`build`](Cmd::build).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use cmakr::Cmd;
+    ///
+    /// if let Err(e) = Cmd::default().set_path("./my_project").build_with_output() {
+    ///     for phase in e.report().map_or(&[][..], |report| &report.phases) {
+    ///         eprintln!("{}{}", phase.stdout, phase.stderr);
+    ///     }
+    /// }
+    /// ```
+    pub fn build_with_output(mut self) -> ExecResult {
+        self.capture_output = true;
+        self.build()
+    }
+
+    /// Executes CMake configure and build asynchronously in a background thread.
+    ///
+    /// This consumes the builder and queues the full CMake workflow on a
+    /// shared worker pool, whose size is set with
+    /// [`set_worker_threads`](crate::set_worker_threads). Returns a
+    /// [`BuildHandle`] that receives the result once the build completes, and
+    /// can cancel it while it runs.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use cmakr::Cmd;
+    ///
+    /// let rx = Cmd::default()
+    ///     .set_path("./my_project")
+    ///     .set_preset("default")
+    ///     .spawn();
+    ///
+    /// // Do other work while cmake runs...
+    ///
+    /// let result = rx.recv().unwrap();
+    /// match result {
+    ///     Ok(_) => println!("Build succeeded"),
+    ///     Err(e) => eprintln!("Build failed: {}", e),
+    /// }
+    /// ```
+    pub fn spawn(self) -> BuildHandle {
+        let (tx, rx) = mpsc::channel();
+        let running = Arc::new(AtomicBool::new(true));
+        let finished = running.clone();
+        let cancel = self.queue(move |result| {
+            finished.store(false, Ordering::SeqCst);
+            let _ = tx.send(result);
+        });
+
+        BuildHandle {
+            receiver: rx,
+            cancel,
+            running,
+        }
+    }
+
+    /// Executes CMake configure and build as a future.
+    ///
+    /// The workflow runs on a thread of its own, and the future only waits
+    /// for its result, so awaiting it blocks no thread of the async runtime.
+    /// Unlike [`spawn`](Cmd::spawn), it doesn't take a worker of the shared
+    /// pool: [`set_worker_threads`](crate::set_worker_threads) doesn't limit
+    /// how many futures build at once, and a build awaited from inside a
+    /// pool job can't wait for a worker. Dropping the future before it
+    /// completes cancels the build: the running cmake process and everything
+    /// it started are terminated, as with [`BuildHandle::cancel`]. Requires
+    /// the `tokio` feature, whose channel delivers the result; the future
+    /// runs on any executor.
+    ///
+    /// The cmake processes are not `tokio::process` children: they are run
+    /// by the workflow's thread like those of [`build`](Cmd::build), since
+    /// the steps between them (hooks, file API replies, watchdogs, install
+    /// manifests) are synchronous.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use cmakr::Cmd;
+    ///
+    /// # async fn run() -> Result<(), cmakr::Error> {
+    /// let report = Cmd::default()
+    ///     .set_path("./my_project")
+    ///     .build_async()
+    ///     .await?;
+    /// println!("{} artifacts", report.artifacts.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub fn build_async(self) -> impl Future<Output = ExecResult> + Send + 'static {
+        /// Terminates the build when the future is dropped unfinished.
+        struct CancelOnDrop(Option<CancelToken>);
+
+        impl CancelOnDrop {
+            fn disarm(&mut self) {
+                self.0 = None;
+            }
+        }
+
+        impl Drop for CancelOnDrop {
+            fn drop(&mut self) {
+                if let Some(cancel) = &self.0 {
+                    cancel.terminate();
+                }
+            }
+        }
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let (cancel, job) = self.workflow(move |result| {
+            let _ = tx.send(result);
+        });
+        let spawned = thread::Builder::new()
+            .name("cmakr-build".to_string())
+            .spawn(job)
+            .map(drop);
+        let mut guard = CancelOnDrop(Some(cancel));
+
+        async move {
+            spawned?;
+            let result = rx.await.unwrap_or(Err(Error::Panicked));
+            // the build is done, so there is nothing left to cancel
+            guard.disarm();
+            result
+        }
+    }
+
+    /// Queues the full workflow on the shared worker pool and hands its
+    /// result to `deliver`, returning the token that cancels it.
+    fn queue<F>(self, deliver: F) -> CancelToken
+    where
+        F: FnOnce(ExecResult) + Send + 'static,
+    {
+        let (token, job) = self.workflow(deliver);
+        pool::execute(job);
+        token
+    }
+
+    /// Returns the token that cancels the full workflow, and the job that
+    /// runs it and hands its result to `deliver`.
+    fn workflow<F>(mut self, deliver: F) -> (CancelToken, impl FnOnce() + Send + 'static)
+    where
+        F: FnOnce(ExecResult) + Send + 'static,
+    {
+        let cancel = self.cancel.get_or_insert_default().clone();
+        let token = cancel.clone();
+
+        let job = move || {
+            // a build cancelled while queued never starts
+            let result = if cancel.is_cancelled() {
+                Err(Error::cancelled("cmake build", BuildReport::default()))
+            } else {
+                let started_at = SystemTime::now();
+                let result = scrub(self.execute(), &self.redactor());
+                self.output_style.flush();
+                self.export_trace(started_at, &result);
+                self.send_notifications(started_at, &result);
+                result
+            };
+            deliver(result);
+        };
+
+        (token, job)
+    }
+
+    /// Explains why the next run would configure the build tree again,
+    /// without running anything.
+    ///
+    /// Compares this builder with the cache of the build tree and the
+    /// configuration of the last run in its [state file](crate::state); see
+    /// [`rebuild`](crate::rebuild) for the reasons and how they are
+    /// detected. An empty list means the configure of the next run is
+    /// [skipped](Cmd::skip_configure_if_unchanged), unless its environment
+    /// or toolchain file changed, which only the fingerprint covers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state file or the project's files cannot be
+    /// read.
+    pub fn explain_rebuild(&self) -> Result<Vec<RebuildReason>, Error> {
+        self.resolved()?.rebuild_reasons()
+    }
+
+    /// Returns the reasons of [`explain_rebuild`](Cmd::explain_rebuild), with
+    /// the paths of this builder resolved.
+    fn rebuild_reasons(&self) -> Result<Vec<RebuildReason>, Error> {
+        let preset = self.resolved_preset();
+        let binary_dir = self.build_dir(preset.as_ref());
+        let cache = binary_dir.join("CMakeCache.txt");
+        let mut reasons = Vec::new();
+        if self.fresh {
+            reasons.push(RebuildReason::Forced);
+        }
+        let Ok(configured_at) = std::fs::metadata(&cache).and_then(|m| m.modified()) else {
+            reasons.insert(0, RebuildReason::CacheMissing);
+            return Ok(reasons);
+        };
+
+        // the CMAKE_GENERATOR variable only applies to fresh trees
+        let requested = self
+            .generator
+            .clone()
+            .or_else(|| generator_arg(&self.args))
+            .or_else(|| generator_arg(&self.configure_args))
+            .or_else(|| preset.as_ref().and_then(|preset| preset.generator.clone()));
+        if let (Some(configured), Some(requested)) = (
+            cmake::read_cache_value(&binary_dir, "CMAKE_GENERATOR"),
+            requested,
+        ) && configured != requested
+        {
+            reasons.push(RebuildReason::GeneratorMismatch {
+                configured,
+                requested,
+            });
+        }
+
+        if let Some(state) = BuildState::load(&binary_dir)? {
+            let now = self.configuration(self.path(), &binary_dir)?;
+            let last = state.configuration;
+            if last.preset != now.preset {
+                reasons.push(RebuildReason::PresetChanged {
+                    before: last.preset,
+                    after: now.preset,
+                });
+            }
+            let before: BTreeMap<&str, &str> = last
+                .defines
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect();
+            let after: BTreeMap<&str, &str> = now
+                .defines
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect();
+            let names: std::collections::BTreeSet<&str> =
+                before.keys().chain(after.keys()).copied().collect();
+            for name in names {
+                let (before, after) = (before.get(name), after.get(name));
+                if before != after {
+                    reasons.push(RebuildReason::DefineChanged {
+                        name: name.to_string(),
+                        before: before.map(|value| value.to_string()),
+                        after: after.map(|value| value.to_string()),
+                    });
+                }
+            }
+            if last.args != now.args {
+                reasons.push(RebuildReason::ArgsChanged);
+            }
+            if let (Some(before), Some(after)) =
+                (state.cmake_version, cmake::version_of(&self.tools.cmake()))
+                && before != after
+            {
+                reasons.push(RebuildReason::CMakeVersionChanged { before, after });
+            }
+            if state.history.runs.last().is_some_and(|run| !run.success) {
+                reasons.push(RebuildReason::LastRunFailed);
+            }
+        }
+
+        let newer = |path: &Path| {
+            std::fs::metadata(path)
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| modified > configured_at)
+        };
+        for file in fingerprint::project_files(self.path(), &binary_dir)? {
+            if !newer(&file) {
+                continue;
+            }
+            let name = file.file_name().unwrap_or_default();
+            if name == "CMakePresets.json" || name == "CMakeUserPresets.json" {
+                reasons.push(RebuildReason::PresetFileNewer(file));
+            } else {
+                reasons.push(RebuildReason::ProjectFileNewer(file));
+            }
+        }
+
+        if !self.skip_unchanged_configure {
+            reasons.push(RebuildReason::AlwaysConfigures);
+        }
+        Ok(reasons)
+    }
+
+    /// Verifies the configuration without building or modifying anything.
+    ///
+    /// A pre-flight check that fails fast on configuration errors: it
+    /// validates the defines and credentials, resolves the preset, and checks
+    /// that `cmake`, the requested generator, and the generator's build tool
+    /// are available. If the build tree is already configured, it also checks
+    /// that the tree belongs to the same source directory and that cmake can
+    /// load its cache (`cmake -N`). No directory is created and no file is
+    /// written, so a fresh tree is not configured and errors in the project's
+    /// `CMakeLists.txt` still only show up in [`build`](Cmd::build).
+    ///
+    /// The returned report has the source and build directories, whether a
+    /// cache exists, and the `cmake -N` run as its configure phase.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The source directory has no `CMakeLists.txt`
+    /// - A define is invalid or conflicts under the [`DefinePolicy`]
+    /// - The credentials cannot be resolved
+    /// - `cmake`, the generator, or its build tool is not available
+    /// - The preset name is invalid or not found
+    /// - The build tree was configured for another source directory, or its
+    ///   cache cannot be loaded
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use cmakr::Cmd;
+    ///
+    /// let cmd = Cmd::default().set_path("./my_project").set_preset("ci");
+    /// cmd.check().unwrap();
+    /// ```
+    pub fn check(&self) -> ExecResult {
+        let result = self
+            .resolved()
+            .map_err(Error::from)
+            .and_then(|cmd| cmd.verify());
+        scrub(result, &self.redactor())
+    }
+
+    /// Returns the commands a run would execute, without running anything.
+    ///
+    /// The commands are those of [`build`](Cmd::build), or of
+    /// [`configure`](Cmd::configure) and [`build_only`](Cmd::build_only) if
+    /// called on their steps: configure, one build command per
+    /// [parallel target](Cmd::build_targets_parallel), and one install per
+    /// [component](Cmd::add_install_component) if an install prefix is set.
+    /// Their arguments, environment changes, and working directories are
+    /// exactly the ones cmake runs with, with secrets redacted; render them
+    /// with [`CommandPlan::to_shell`]. Nothing is written, and cmake need not
+    /// be installed.
+    ///
+    /// Some arguments are only known once a step has run, and are planned
+    /// from what is known beforehand: directories that don't exist yet are
+    /// made absolute instead of canonical, the build is not
+    /// [restricted to a subdirectory](Cmd::restrict_to_subdirectory) before
+    /// the tree is configured, the retries of an
+    /// [OOM backoff](Cmd::oom_backoff) are not listed, and the
+    /// [compiler cache](Cmd::set_compiler_cache_dir) and offline checks are
+    /// not done, and [credentials](Cmd::set_credentials) are not resolved
+    /// into the environment.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The preset name is invalid or not found
+    /// - A define is invalid or conflicts under the [`DefinePolicy`]
+    /// - The sources are read-only under [`ReadOnlySourcePolicy::Error`]
+    /// - A path cannot be made absolute
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use cmakr::{Cmd, command::Shell};
+    ///
+    /// let cmd = Cmd::default().set_path("./my_project").add_define("FOO", "ON");
+    /// for command in cmd.dry_run().unwrap() {
+    ///     println!("{}", command.to_shell(Shell::Posix));
+    /// }
+    /// ```
+    pub fn dry_run(&self) -> Result<CommandPlan, Error> {
+        let redactor = self.redactor();
+        let mut commands = self.resolved()?.plan()?;
+        for command in &mut commands {
+            command.redact(&redactor);
+        }
+        Ok(CommandPlan { commands })
+    }
+
+    /// Updates cache variables of an already configured build tree.
+    ///
+    /// Re-runs configure on the existing tree as `cmake -D<name>=<value>... <build_dir>`,
+    /// without a source path or any of the builder's settings, so flipping an
+    /// option like `BUILD_TESTING=ON` doesn't require reconstructing the whole
+    /// [`Cmd`]. The source directory is taken from the tree's `CMakeCache.txt`.
+    ///
+    /// # Arguments
+    ///
+    /// * `build_dir` - Path to a build directory containing a `CMakeCache.txt`.
+    /// * `defines` - The `(name, value)` pairs to set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - `build_dir` has not been configured yet
+    /// - The configure step fails (non-zero exit code)
+    /// - Any I/O error occurs
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use cmakr::Cmd;
+    ///
+    /// Cmd::update_cache("./build", [("BUILD_TESTING", "ON")]).unwrap();
+    /// ```
+    pub fn update_cache<P, I, K, V>(build_dir: P, defines: I) -> ExecResult
+    where
+        P: Into<String>,
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let build_dir = PathBuf::from(build_dir.into());
+        let Some(source_dir) = cmake::read_cache_value(&build_dir, "CMAKE_HOME_DIRECTORY") else {
+            return Err(Error::InvalidConfig(format!(
+                "{} is not a configured build directory",
+                build_dir.display()
+            )));
+        };
+        let _build_dir_lock = lock_dir(&build_dir, None)?;
+        gc::touch(&build_dir)?;
+
+        let define_args = defines
+            .into_iter()
+            .map(|(name, value)| {
+                Defination {
+                    name: name.into(),
+                    value: value.into(),
+                    once: false,
+                }
+                .to_arg(false)
+            })
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(Error::InvalidDefine)?;
+
+        let mut command = std::process::Command::new(Tools::default().find_cmake()?);
+        command.args(define_args).arg(&build_dir);
+
+        let start = Instant::now();
+        let started_at = SystemTime::now();
+        let redactor = Redactor::default();
+        let configure = instrument::run(Phase::Configure, &mut command, &redactor, |command| {
+            process::run(command, None, None, None, &redactor)
+        })?;
+
+        let exit = ProcessExit::from(configure.status);
+        let report = BuildReport {
+            phases: vec![PhaseTiming {
+                phase: Phase::Configure,
+                target: None,
+                command: PreparedCommand::from(&command),
+                started_at,
+                duration: start.elapsed(),
+                exit,
+                stdout: configure.stdout,
+                stderr: configure.stderr,
+            }],
+            source_dir: PathBuf::from(source_dir),
+            binary_dir: build_dir.canonicalize()?,
+            ..Default::default()
+        };
+
+        if !exit.success() {
+            let message = format!("cmake configure failed: {}", exit);
+            return Err(Error::ConfigureFailed(BuildFailure::new(message, report)));
+        }
+
+        Ok(report)
+    }
+
+    /// Returns the targets affected by changes to the files in `since`.
+    ///
+    /// The build tree must have been generated by Ninja and built at least once:
+    /// the dependency log written by the compilers (`ninja -t deps`) maps every
+    /// object file to the sources and headers it was compiled from, so a changed
+    /// header is traced to each target that includes it. Every target that
+    /// links against an affected target, directly or transitively, is affected
+    /// as well. Use this to run only the tests that a change can influence
+    /// without building anything.
+    ///
+    /// # Arguments
+    ///
+    /// * `since` - The changed files, absolute or relative to the
+    ///   [working directory](Cmd::current_dir).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The build tree was not generated by Ninja
+    /// - `ninja` or `cmake` cannot be run or fails
+    /// - Any I/O error occurs
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::path::PathBuf;
+    /// use cmakr::Cmd;
+    ///
+    /// let cmd = Cmd::default().set_binary_path("./build");
+    /// let targets = cmd.changed_targets(&[PathBuf::from("src/func.h")]).unwrap();
+    /// println!("affected: {:?}", targets);
+    /// ```
+    pub fn changed_targets(&self, since: &[PathBuf]) -> Result<Vec<String>, Error> {
+        let (build_graph, affected) = self.resolved()?.affected_targets(since)?;
+        let mut names: Vec<String> = affected
+            .into_iter()
+            .map(|index| build_graph.targets[index].name.clone())
+            .collect();
+        names.sort();
+
+        Ok(names)
+    }
+
+    /// Returns a [`CTestCmd`] for the tests of this build tree.
+    ///
+    /// The ctest run uses the build and source directories and the
+    /// [test preset](Cmd::set_test_preset) of this `Cmd`, and is killed with
+    /// it when cancelled; build the tree first.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use cmakr::Cmd;
+    ///
+    /// let cmd = Cmd::default().set_path("./my_project");
+    /// cmd.clone().build().unwrap();
+    /// let report = cmd.ctest().include_regex("^unit_").run().unwrap();
+    /// assert!(report.success());
+    /// ```
+    pub fn ctest(&self) -> CTestCmd {
+        // only an empty working directory can't be made absolute, which leaves the paths as set
+        let cmd = self.resolved().unwrap_or(Cow::Borrowed(self));
+        let binary_path = cmd.build_dir(cmd.resolved_preset().as_ref());
+        let ctest = CTestCmd::new(binary_path.to_string_lossy())
+            .set_executable(self.tools.ctest().to_string_lossy())
+            .set_path(cmd.path().to_string_lossy())
+            .set_cancel_token(self.cancel.clone());
+        match &self.test_preset {
+            Some(preset) => ctest.set_preset(preset),
+            None => ctest,
+        }
+    }
+
+    /// Returns a [`CPackCmd`] that packages this build tree.
+    ///
+    /// The cpack run uses the build directory of this `Cmd`, and is killed
+    /// with it when cancelled; build the tree first.
+    pub fn cpack(&self) -> CPackCmd {
+        let cmd = self.resolved().unwrap_or(Cow::Borrowed(self));
+        let binary_path = cmd.build_dir(cmd.resolved_preset().as_ref());
+        CPackCmd::new(binary_path.to_string_lossy())
+            .set_executable(self.tools.cpack().to_string_lossy())
+            .set_cancel_token(self.cancel.clone())
+    }
+
+    /// Runs only the CTest tests of the targets affected by `changed_files`.
+    ///
+    /// The affected targets are computed as by
+    /// [`changed_targets`](Cmd::changed_targets). A test is selected when its
+    /// command runs an artifact of an affected target, or when one of its
+    /// `LABELS` is the name of an affected target; label tests that run
+    /// through a wrapper script with the target they cover. The selected tests
+    /// are run with `ctest --test-dir <binary> -R <names>`, and nothing is run
+    /// if no test is affected.
+    ///
+    /// Returns the names of the tests that were run.
+    ///
+    /// # Arguments
+    ///
+    /// * `changed_files` - The changed files, absolute or relative to the
+    ///   [working directory](Cmd::current_dir).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The affected targets cannot be computed (see [`changed_targets`](Cmd::changed_targets))
+    /// - The tests cannot be listed
+    /// - Any selected test fails
+    pub fn test_affected(&self, changed_files: &[PathBuf]) -> Result<Vec<String>, Error> {
+        let cmd = self.resolved()?;
+        let (build_graph, affected) = cmd.affected_targets(changed_files)?;
+        let binary = cmd.binary_path.canonicalize()?;
+
+        let listing = graph::run(
+            std::process::Command::new(self.tools.ctest())
+                .arg("--test-dir")
+                .arg(&binary)
+                .arg("--show-only=json-v1"),
+        )?;
+        let tests = impact::parse_tests(&listing)?;
+        let targets: Vec<_> = affected
+            .iter()
+            .map(|&index| &build_graph.targets[index])
+            .collect();
+        let selected = impact::select_tests(&tests, &targets);
+        if selected.is_empty() {
+            return Ok(selected);
+        }
+
+        let redactor = Redactor::default();
+        let output = instrument::run(
+            Phase::Test,
+            std::process::Command::new(self.tools.ctest())
+                .arg("--test-dir")
+                .arg(&binary)
+                .arg("-R")
+                .arg(impact::exact_match_regex(&selected)),
+            &redactor,
+            |command| process::run(command, self.cancel.as_ref(), None, None, &redactor),
+        )?;
+        if output.cancelled {
+            return Err(Error::cancelled("ctest", BuildReport::default()));
+        }
+        if !output.status.success() {
+            return Err(Error::TestFailed(ProcessExit::from(output.status)));
+        }
+
+        Ok(selected)
+    }
+
+    /// Loads the build graph and returns the indices of the targets affected
+    /// by changes to `since`.
+    fn affected_targets(&self, since: &[PathBuf]) -> Result<(BuildGraph, Vec<usize>), Error> {
+        let binary = self.binary_path.canonicalize()?;
+        let generator = cmake::read_cache_value(&binary, "CMAKE_GENERATOR").unwrap_or_default();
+        if !generator.starts_with("Ninja") {
+            return Err(Error::Unsupported(format!(
+                "{} was not generated by Ninja (generator: {:?})",
+                binary.display(),
+                generator
+            )));
+        }
+
+        let resolve = |base: &Path, path: &Path| {
+            let path = base.join(path);
+            path.canonicalize().unwrap_or(path)
+        };
+        let current_dir = match &self.current_dir {
+            Some(dir) => dir.clone(),
+            None => std::env::current_dir()?,
+        };
+        let changed: Vec<PathBuf> = since
+            .iter()
+            .map(|path| resolve(&current_dir, path))
+            .collect();
+
+        let deps = graph::run(
+            std::process::Command::new(self.tools.ninja())
+                .arg("-C")
+                .arg(&binary)
+                .args(["-t", "deps"]),
+        )?;
+        let mut directly: Vec<String> = graph::parse_ninja_deps(&deps)
+            .into_iter()
+            .filter(|(_, deps)| {
+                deps.iter()
+                    .any(|dep| changed.contains(&resolve(&binary, dep)))
+            })
+            .filter_map(|(object, _)| graph::target_of_object(&object))
+            .collect();
+
+        // sources that were added since the last build are not in the deps log yet
+        let build_graph = BuildGraph::load_with(&binary, &self.tools)?;
+        for target in &build_graph.targets {
+            if target.sources.iter().any(|source| changed.contains(source)) {
+                directly.push(target.name.clone());
+            }
+        }
+
+        let mut roots: Vec<usize> = directly
+            .iter()
+            .filter_map(|name| build_graph.index_of(name))
+            .collect();
+        roots.sort();
+        roots.dedup();
+        let affected = build_graph.transitive_dependents(roots);
+
+        Ok((build_graph, affected))
+    }
+
+    /// Internal method behind [`check`](Cmd::check).
+    fn verify(&self) -> ExecResult {
+        let cmake_path = self.path();
+        if !cmake_path.join("CMakeLists.txt").is_file() {
+            return Err(Error::InvalidConfig(format!(
+                "{} has no CMakeLists.txt",
+                cmake_path.display()
+            )));
+        }
+
+        for define in
+            dedup_defines(&self.defines, self.define_policy).map_err(Error::InvalidDefine)?
+        {
+            define
+                .to_arg(self.raw_defines)
+                .map_err(Error::InvalidDefine)?;
+        }
+        if let Some(provider) = &self.credentials {
+            provider.credentials().map_err(Error::Credentials)?;
+        }
+
+        let cmake_program = self.tools.find_cmake()?;
+        self.check_version(&cmake_program)?;
+        self.check_clean_first()?;
+        self.check_capabilities(&cmake_program)?;
+
+        let preset = self.check_presets(cmake_path)?;
+        let binary_path = self.build_dir(preset.as_ref());
+
+        let cache_reused = binary_path.join("CMakeCache.txt").is_file();
+        let generator = self.expected_generator(&binary_path, preset.as_ref());
+        if let Some(generator) = &generator {
+            let available = cmake::generators(&cmake_program)?;
+            if !available.contains(generator) {
+                return Err(Error::Unsupported(format!(
+                    "generator {:?} is not supported by this cmake (available: {})",
+                    generator,
+                    available.join(", ")
+                )));
+            }
+            let make_program_set = self
+                .defines
+                .iter()
+                .any(|d| d.name.split(':').next() == Some("CMAKE_MAKE_PROGRAM"))
+                || cmake::read_cache_value(&binary_path, "CMAKE_MAKE_PROGRAM").is_some()
+                || self.ninja_make_program(generator).is_some();
+            if let Some(tools) = build_tools(generator)
+                && !make_program_set
+                && !tools.iter().any(|tool| which::which(tool).is_ok())
+            {
+                return Err(Error::Unsupported(format!(
+                    "generator {:?} needs {} on PATH",
+                    generator, tools[0]
+                )));
+            }
+        }
+
+        let mut report = BuildReport {
+            source_dir: std::path::absolute(cmake_path)?,
+            binary_dir: std::path::absolute(&binary_path)?,
+            cache_reused,
+            ..Default::default()
+        };
+        if !cache_reused {
+            return Ok(report);
+        }
+
+        if let Some(home) = cmake::read_cache_value(&binary_path, "CMAKE_HOME_DIRECTORY") {
+            let home = PathBuf::from(home);
+            let same = match (home.canonicalize(), cmake_path.canonicalize()) {
+                (Ok(home), Ok(source)) => home == source,
+                _ => false,
+            };
+            if !same {
+                return Err(Error::InvalidConfig(format!(
+                    "{} was configured for the source directory {}, not {}",
+                    binary_path.display(),
+                    home.display(),
+                    cmake_path.display()
+                )));
+            }
+        }
+
+        // view mode only loads the cache, without configuring or generating
+        let mut command = std::process::Command::new(&cmake_program);
+        command.arg("-N").arg(&binary_path);
+        self.apply_environment(&mut command);
+        let start = Instant::now();
+        let started_at = SystemTime::now();
+        let redactor = self.redactor();
+        let output = instrument::run(Phase::Configure, &mut command, &redactor, |command| {
+            process::run(command, None, None, None, &redactor)
+        })?;
+        let exit = ProcessExit::from(output.status);
+        report.phases.push(PhaseTiming {
+            phase: Phase::Configure,
+            target: None,
+            command: PreparedCommand::from(&command),
+            started_at,
+            duration: start.elapsed(),
+            exit,
+            stdout: output.stdout,
+            stderr: output.stderr,
+        });
+        if !exit.success() {
+            let message = format!("cmake could not load the cache: {}", exit);
+            return Err(Error::ConfigureFailed(BuildFailure::new(message, report)));
+        }
+
+        Ok(report)
+    }
+
+    /// Runs [`execute_attempt`](Cmd::execute_attempt), configuring again with
+    /// the next [fallback generator](Cmd::fallback_generators) while a fresh
+    /// configure fails because the generator is unavailable, and running it
+    /// again while it fails transiently under the [retry policy](Cmd::retry).
+    fn execute(&mut self) -> ExecResult {
+        let mut fallbacks = self.fallback_generators.clone().into_iter();
+        let mut failed_phases = Vec::new();
+        let mut fallback = None;
+        let mut retries: Vec<Retry> = Vec::new();
+        loop {
+            let mut result = self.execute_attempt();
+            if let Some(retry) = self.transient_failure(&result, retries.len()) {
+                if let Some(failure) = result.as_mut().err().and_then(Error::failure_mut) {
+                    failed_phases.append(&mut failure.report_mut().phases);
+                }
+                if !self.capture_output {
+                    eprintln!(
+                        "warning: {} failed ({}), retrying in {}s",
+                        retry.phase,
+                        retry.reason,
+                        retry.delay.as_secs_f64()
+                    );
+                }
+                thread::sleep(retry.delay);
+                retries.push(retry);
+                continue;
+            }
+            // the generator of the preset is the one that failed unless one is set
+            let failed = self
+                .generator
+                .clone()
+                .or_else(|| self.resolved_preset().and_then(|preset| preset.generator));
+            let next = match &result {
+                Err(Error::ConfigureFailed(failure)) if generator_unavailable(failure) => {
+                    fallbacks.find(|next| failed.as_ref() != Some(next))
+                }
+                _ => None,
+            };
+            let Some(next) = next else {
+                let report = match &mut result {
+                    Ok(report) => Some(report),
+                    Err(error) => error.failure_mut().map(BuildFailure::report_mut),
+                };
+                if let Some(report) = report {
+                    report.phases.splice(0..0, failed_phases);
+                    report.retries = retries;
+                    report.generator_fallback = fallback;
+                    report.relocated_from = self.relocated_from.clone();
+                }
+                return result;
+            };
+            if let Err(Error::ConfigureFailed(mut failure)) = result {
+                // cmake insists on the generator recorded by the failed attempt
+                let binary_dir = &failure.report().binary_dir;
+                let cache = binary_dir.join("CMakeCache.txt");
+                if cache.is_file() {
+                    std::fs::remove_file(cache)?;
+                }
+                let files = binary_dir.join("CMakeFiles");
+                if files.is_dir() {
+                    std::fs::remove_dir_all(files)?;
+                }
+                failed_phases.append(&mut failure.report_mut().phases);
+            }
+            if !self.capture_output {
+                let generator = match &failed {
+                    Some(generator) => format!("generator {:?}", generator),
+                    None => "the default generator".to_string(),
+                };
+                eprintln!(
+                    "warning: {} is not available, configuring with {:?}",
+                    generator, next
+                );
+            }
+            self.generator = Some(next.clone());
+            self.generator_platform = None;
+            self.generator_toolset = None;
+            fallback = Some(next);
+        }
+    }
+
+    /// Returns the retry of a configure or build that `result` failed, if
+    /// the [retry policy](Cmd::retry) retries it after `retried` retries.
+    fn transient_failure(&self, result: &ExecResult, retried: usize) -> Option<Retry> {
+        let policy = self.retry.as_ref()?;
+        let failure = match result {
+            Err(Error::ConfigureFailed(failure) | Error::BuildFailed(failure)) => failure,
+            _ => return None,
+        };
+        if self.is_cancelled() {
+            return None;
+        }
+        let timing = failure.report().phases.last()?;
+        let output = format!("{}\n{}", timing.stdout, timing.stderr);
+        policy.check(timing.phase, &output, retried)
+    }
+
+    /// Internal method that performs the actual CMake configure and build.
+    ///
+    /// This method:
+    /// 1. Verifies that `cmake` is available on `PATH`.
+    /// 2. Resolves the preset (if set) from `CMakePresets.json` and
+    ///    [sets up](Cmd::set_up) the build tree.
+    /// 3. Runs `cmake -S <source> -B <binary>` with all configured arguments,
+    ///    see [`configure_phase`](Cmd::configure_phase).
+    /// 4. Runs `cmake --build <binary>` to compile the project and parses the
+    ///    compiler diagnostics from its output, see
+    ///    [`build_phase`](Cmd::build_phase).
+    /// 5. Collects the artifacts and installs them, see
+    ///    [`finish_build`](Cmd::finish_build).
+    fn execute_attempt(&mut self) -> ExecResult {
+        self.resolve_paths()?;
+        // check cmake is exists
+        let cmake_program = self.tools.find_cmake()?;
+        self.check_version(&cmake_program)?;
+        self.check_clean_first()?;
+        self.check_capabilities(&cmake_program)?;
+
+        let mut setup = self.set_up(false)?;
+        if !self.steps.configures() && !setup.cache_reused {
+            return Err(Error::InvalidConfig(format!(
+                "{} is not configured; configure it before building only",
+                self.binary_path.display()
+            )));
+        }
+        let (phases, configure_skipped) = self.configure_phase(&mut setup)?;
+
+        let debug_postfix = cmake::read_cache_value(&self.binary_path, "CMAKE_DEBUG_POSTFIX")
+            .filter(|postfix| !postfix.is_empty());
+
+        // a project without compiled targets has nothing to build, only headers to install
+        let targets = fileapi::targets(&self.binary_path).ok().flatten();
+        let header_only = targets
+            .as_ref()
+            .is_some_and(|targets| !targets.iter().any(Target::produces_artifact));
+        let report = BuildReport {
+            configure_skipped,
+            header_only,
+            debug_postfix,
+            ..self.report(&setup, phases)?
+        };
+        if self.steps == Steps::ConfigureOnly {
+            if let Some(emitter) = &self.machine_output {
+                machine::finished(emitter, &report, true);
+            }
+            self.record_state(&setup.source_dir, &report, true)?;
+            return Ok(report);
+        }
+        if self.install_only
+            && let Some(prefix) = &self.install_prefix
+        {
+            if let Some(target) = targets.iter().flatten().find(|t| t.produces_artifact()) {
+                return Err(Error::InvalidConfig(format!(
+                    "install_only requires a project without compiled targets, but {} is a {}",
+                    target.name, target.ty
+                )));
+            }
+            return self.install(prefix, &setup.source_dir, report);
+        }
+        if self.steps == Steps::InstallOnly
+            && let Some(prefix) = &self.install_prefix
+        {
+            return self.install(prefix, &setup.source_dir, report);
+        }
+
+        // a cancellation after configure stops before the build starts
+        if self.is_cancelled() {
+            return Err(self.cancelled(Phase::Build, report));
+        }
+
+        let report = self.build_phase(&setup, targets.as_deref(), report)?;
+        self.finish_build(&setup, targets.as_deref(), report)
+    }
+
+    /// Sets up the build tree of a run before its first command.
+    ///
+    /// Resolves the preset and the build directory, creates the build and
+    /// output directories, locks the build directory, writes the generated
+    /// toolchain file, mirrors [read-only sources](ReadOnlySourcePolicy),
+    /// checks the downloads of an offline build, discards the cache of a
+    /// [fresh](Cmd::fresh) configure, and writes the
+    /// [seed script](Cmd::seed_cache) of a fresh tree.
+    ///
+    /// A `dry_run` only resolves what the run would do, see
+    /// [`Cmd::dry_run`]: nothing is written or locked, credentials are not
+    /// resolved, and offline builds are not checked.
+    fn set_up(&mut self, dry_run: bool) -> Result<RunSetup, Error> {
+        let resolve = |path: &Path| path.canonicalize().or_else(|_| std::path::absolute(path));
+        let mut source_dir = self.path().to_path_buf();
+
+        // add preset arg if preset is set, building where the preset declares
+        let preset = self.check_presets(&source_dir)?;
+        let requested = self.requested_build_dir(preset.as_ref());
+        self.binary_path = self.build_dir(preset.as_ref());
+        if self.binary_path != requested {
+            self.relocated_from = Some(std::path::absolute(requested)?);
+        }
+        self.apply_preset_environment(preset.as_ref());
+        self.bootstrap_msvc(preset.as_ref())?;
+
+        // defines that cmake would misread or that conflict fail before anything is run
+        define_args(&self.defines, self.define_policy, self.raw_defines)?;
+
+        // secrets are resolved before anything runs, so a missing token fails fast
+        if let Some(provider) = &self.credentials
+            && !dry_run
+        {
+            self.resolved_credentials = provider.credentials().map_err(Error::Credentials)?;
+        }
+
+        // an in-source build is caught before cmake writes into the sources
+        if self.in_source_policy != InSourcePolicy::Allow
+            && let Some(suggestion) = in_source_build(&source_dir, &self.binary_path)
+        {
+            let message = format!(
+                "the build directory {} is inside the source tree; use a separate one such as {}",
+                self.binary_path.display(),
+                suggestion.display()
+            );
+            match self.in_source_policy {
+                InSourcePolicy::Error => return Err(Error::InvalidConfig(message)),
+                _ if !self.capture_output && !dry_run => eprintln!("warning: {}", message),
+                _ => {}
+            }
+        }
+
+        // binary path and output path must be exists, if not exists, create it
+        if !dry_run {
+            check_dir_exists_and_create(&self.binary_path)?;
+            for (_, dir) in self.output_dirs() {
+                check_dir_exists_and_create(dir)?;
+            }
+        }
+
+        // another process configuring or building this tree would corrupt its cache
+        let build_dir_lock = match self.lock_policy {
+            _ if dry_run => None,
+            LockPolicy::Disabled => None,
+            policy => Some(lock_dir(&self.binary_path, policy.timeout())?),
+        };
+        if !dry_run {
+            gc::touch(&self.binary_path)?;
+        }
+
+        // a generated toolchain file is written where the tree keeps its state
+        if let Some(toolchain) = &self.generated_toolchain
+            && self.toolchain_file.is_none()
+        {
+            self.toolchain_file = Some(match dry_run {
+                true => generated_toolchain_path(&self.binary_path),
+                false => write_generated_toolchain(toolchain, &self.binary_path)?,
+            });
+        }
+
+        // share fetched sources between build trees, serialized by a lock on the cache dir
+        let fetch_cache_lock = match &self.fetch_cache_dir {
+            Some(cache_dir) if !dry_run => Some(lock_dir(cache_dir, self.lock_policy.timeout())?),
+            _ => None,
+        };
+
+        // reported locations are rewritten with canonical remap prefixes
+        let mut remaps: Vec<(PathBuf, PathBuf)> = self
+            .path_remaps
+            .iter()
+            .map(|(from, to)| (from.canonicalize().unwrap_or(from.clone()), to.clone()))
+            .collect();
+
+        // read-only sources are built from a copy whose paths are reported as the originals
+        if self.read_only_source_policy != ReadOnlySourcePolicy::Build
+            && !source_copy::is_writable(&source_dir)
+        {
+            if self.read_only_source_policy == ReadOnlySourcePolicy::Error {
+                return Err(Error::InvalidConfig(format!(
+                    "the source directory {} is not writable; build from a copy with ReadOnlySourcePolicy::Copy",
+                    source_dir.display()
+                )));
+            }
+            let source = resolve(&source_dir)?;
+            let binary = resolve(&self.binary_path)?;
+            let copy = binary.join(source_copy::COPY_DIR);
+            if !dry_run {
+                source_copy::mirror(&source, &copy, &binary)?;
+            }
+            remaps.push((copy.clone(), source));
+            source_dir = copy;
+        }
+
+        // offline builds fail before configure if a dependency would have to be downloaded
+        if offline::is_offline() && self.steps.configures() && !dry_run {
+            let defined = |name: &str| {
+                self.defines
+                    .iter()
+                    .any(|d| d.name.split(':').next() == Some(name))
+            };
+            let deps_dir = self.binary_path.join("_deps");
+            let missing: Vec<String> =
+                offline::declared_fetches(&source_dir, &self.binary_path.canonicalize()?)
+                    .into_iter()
+                    .filter(|dependency| {
+                        let source_dir =
+                            format!("FETCHCONTENT_SOURCE_DIR_{}", dependency.name.to_uppercase());
+                        let shared = self
+                            .fetch_cache_dir
+                            .as_ref()
+                            .is_some_and(|cache_dir| fetch_cache::is_shared(cache_dir, dependency));
+                        !defined(&source_dir) && !dependency.is_populated(&deps_dir) && !shared
+                    })
+                    .map(|dependency| format!("{} ({})", dependency.name, dependency.url))
+                    .collect();
+            if !missing.is_empty() {
+                return Err(Error::Offline(missing));
+            }
+        }
+
+        // a fresh configure discards the cache, with --fresh if cmake has it
+        let fresh = self.fresh && self.steps.configures();
+        if fresh && !dry_run && self.fresh_args(&self.tools.cmake()).is_empty() {
+            let cache = self.binary_path.join("CMakeCache.txt");
+            if cache.is_file() {
+                std::fs::remove_file(cache)?;
+            }
+            let files = self.binary_path.join("CMakeFiles");
+            if files.is_dir() {
+                std::fs::remove_dir_all(files)?;
+            }
+        }
+        let cache_reused = !fresh && self.binary_path.join("CMakeCache.txt").is_file();
+
+        // a fresh tree starts from the seeded cache, which -D defines then override
+        let mut seed_script = None;
+        if let Some(seed) = self.cache_seed.as_ref().filter(|_| !cache_reused)
+            && self.steps.configures()
+        {
+            let state_dir = self.binary_path.join(gc::STATE_DIR);
+            let script = state_dir.join("seed.cmake");
+            if !dry_run {
+                std::fs::create_dir_all(&state_dir)?;
+                std::fs::write(
+                    &script,
+                    seed.to_initial_cache(
+                        &forward_slash_path(&source_dir.canonicalize()?),
+                        &forward_slash_path(&self.binary_path.canonicalize()?),
+                    ),
+                )?;
+            }
+            seed_script = Some(script);
+        }
+
+        // the sources of dependencies fetched before come from the fetch cache
+        let fetch_declared = match &self.fetch_cache_dir {
+            Some(_) if self.steps.configures() => {
+                offline::declared_fetches(&source_dir, &resolve(&self.binary_path)?)
+            }
+            _ => Vec::new(),
+        };
+
+        Ok(RunSetup {
+            preset,
+            source_dir,
+            remaps,
+            cache_reused,
+            seed_script,
+            _build_dir_lock: build_dir_lock,
+            fetch_cache_lock,
+            fetch_declared,
+        })
+    }
+
+    /// Returns the arguments of the configure of `setup`, with the defines
+    /// the cache has already still included.
+    fn configure_args(&self, setup: &RunSetup) -> Result<ConfigureArgs<'_>, Error> {
+        let resolve = |path: &Path| path.canonicalize().or_else(|_| std::path::absolute(path));
+        let cmake_version = cmake::parsed_version_of(&self.tools.cmake());
+        let preset = setup.preset.as_ref();
+
+        let mut defines = define_args(&self.defines, self.define_policy, self.raw_defines)?;
+        let toolchain_file = self.toolchain_file_args(preset, &mut defines)?;
+        let output_dirs = self
+            .output_dirs()
+            .map(|(name, dir)| Ok((name, resolve(dir)?)));
+        let output_path = self.output_path_args(
+            &output_dirs
+                .into_iter()
+                .collect::<std::io::Result<Vec<_>>>()?,
+            preset,
+        );
+
+        let mut fetch_cache: Vec<String> = Vec::new();
+        if let Some(cache_dir) = &self.fetch_cache_dir {
+            let shared = fetch_cache::shared_sources(&resolve(cache_dir)?, &setup.fetch_declared);
+            for (name, dir) in shared {
+                if !self
+                    .defines
+                    .iter()
+                    .any(|d| d.name.split(':').next() == Some(&name))
+                {
+                    fetch_cache.push(format!("-D{}={}", name, forward_slash_path(&dir)));
+                }
+            }
+        }
+        if offline::is_offline() {
+            fetch_cache.extend(self.offline_args());
+        }
+
+        let mut seed: Vec<String> = Vec::new();
+        if let Some(script) = &setup.seed_script {
+            seed.push("-C".to_string());
+            seed.push(normalize_path(&resolve(script)?));
+        }
+
+        Ok(ConfigureArgs {
+            cmake_version,
+            fresh: self.fresh_args(&self.tools.cmake()),
+            seed,
+            preset: self.preset_args(),
+            generator: self.generator_args(),
+            defines,
+            toolchain_file,
+            cross: self.cross_args()?,
+            output_path,
+            fetch_cache,
+            diagnostic: self.diagnostic_args(&self.binary_path, cmake_version),
+        })
+    }
+
+    /// Returns the `cmake -S <source> -B <binary>` command of the configure
+    /// of `setup` with `args`, with `log_args` setting its log level.
+    fn configure_command(
+        &self,
+        setup: &RunSetup,
+        args: &ConfigureArgs,
+        log_args: &[String],
+    ) -> PreparedCommand {
+        let mut command = std::process::Command::new(self.tools.cmake());
+        command
+            .args(&args.fresh)
+            .args(&args.seed)
+            .arg("-S")
+            .arg(&setup.source_dir)
+            .arg("-B")
+            .arg(&self.binary_path)
+            .args(&args.preset)
+            .args(&args.generator)
+            .args(self.ordered_define_args(&args.defines, args.derived()))
+            .args(&self.args)
+            .args(&self.configure_args)
+            .args(&args.diagnostic)
+            .args(log_args);
+        self.prepare_command(command)
+    }
+
+    /// Returns the `cmake --build` commands of one build attempt with `jobs`
+    /// jobs, one per [build invocation](Cmd::build_invocations), with the
+    /// target each one builds.
+    fn build_commands(
+        &self,
+        targets: &[String],
+        native_args: &[String],
+        jobs: Option<usize>,
+    ) -> Vec<(Option<&str>, PreparedCommand)> {
+        self.build_invocations(jobs)
+            .into_iter()
+            .map(|(target, jobs)| {
+                let command = self.build_command(targets, native_args, target, jobs);
+                (target, self.prepare_command(command))
+            })
+            .collect()
+    }
+
+    /// Returns the `cmake --install` commands into the canonical `prefix`,
+    /// one per [component](Cmd::install_components), with their components.
+    fn install_commands(&self, prefix: &Path) -> Vec<(Option<&str>, PreparedCommand)> {
+        self.install_components()
+            .into_iter()
+            .map(|component| {
+                let command = self.install_command(prefix, component);
+                (component, self.prepare_command(command))
+            })
+            .collect()
+    }
+
+    /// Sets the configured environment on `command` and returns it as it runs.
+    fn prepare_command(&self, mut command: std::process::Command) -> PreparedCommand {
+        self.apply_environment(&mut command);
+        PreparedCommand {
+            env_cleared: self.environment_cleared,
+            ..PreparedCommand::from(&command)
+        }
+    }
+
+    /// Returns the report of a run of `setup` with the `phases` so far.
+    fn report(&self, setup: &RunSetup, phases: Vec<PhaseTiming>) -> std::io::Result<BuildReport> {
+        Ok(BuildReport {
+            phases,
+            source_dir: remap_path(&setup.source_dir.canonicalize()?, &setup.remaps),
+            binary_dir: self.binary_path.canonicalize()?,
+            path_remaps: setup.remaps.clone(),
+            cache_reused: setup.cache_reused,
+            ..Default::default()
+        })
+    }
+
+    /// Runs the configure of `setup`, unless the run only builds or an
+    /// [unchanged configure](Cmd::skip_configure_if_unchanged) is skipped.
+    ///
+    /// Returns the configure phases, a failed quiet configure being followed
+    /// by its verbose replay, and whether configure was skipped. The lock on
+    /// the [fetch cache](Cmd::set_fetch_cache_dir) is released afterwards.
+    fn configure_phase(&self, setup: &mut RunSetup) -> Result<(Vec<PhaseTiming>, bool), Error> {
+        let cmake_program = self.tools.cmake();
+        let mut args = self.configure_args(setup)?;
+
+        // an unchanged configure of a configured tree is skipped, if requested
+        let fingerprint = if self.skip_unchanged_configure && self.steps.configures() {
+            Some(self.configure_fingerprint(setup, &args)?)
+        } else {
+            None
+        };
+        let configure_skipped = setup.cache_reused
+            && !self.fresh
+            && fingerprint
+                .as_ref()
+                .is_some_and(|fingerprint| fingerprint::matches(&self.binary_path, fingerprint));
+        // after the fingerprint, which must not change once the cache has the variables
+        if !self.fresh {
+            retain_uncached_defines(&mut args.defines, &self.binary_path);
+        }
+        if !self.steps.configures() || configure_skipped {
+            setup.fetch_cache_lock = None;
+            return Ok((Vec::new(), configure_skipped));
+        }
+
+        fingerprint::clear(&self.binary_path)?;
+        if let Some(emitter) = &self.machine_output {
+            machine::phase_started(emitter, Phase::Configure, None);
+        }
+        let configure_output = self.output_handler(Phase::Configure, None);
+        let redactor = self.redactor();
+        // fresh trees reuse the compiler detection results of an identical toolchain
+        let compiler_cache = match (&self.compiler_cache_dir, setup.cache_reused) {
+            (Some(cache_dir), false) => cmake::version_of(&cmake_program).map(|version| {
+                let key = self.compiler_cache_key(&args, &setup.source_dir, &version);
+                (cache_dir, key, version)
+            }),
+            _ => None,
+        };
+        if let Some((cache_dir, key, version)) = &compiler_cache {
+            compiler_cache::restore(cache_dir, key, &self.binary_path, version)?;
+        }
+        fileapi::request(&self.binary_path)?;
+
+        let run_configure = |log_args: &[String]| {
+            let prepared = self.configure_command(setup, &args, log_args);
+            let context = HookContext {
+                phase: Phase::Configure,
+                source_dir: &setup.source_dir,
+                binary_dir: &self.binary_path,
+                args: &prepared.args,
+                previous: None,
+            };
+            hooks::run(&self.hooks.before_configure, "before configure", &context)?;
+
+            let mut command = prepared.to_command();
+            let start = Instant::now();
+            let started_at = SystemTime::now();
+            let output = self.run_step(
+                &mut command,
+                Phase::Configure,
+                configure_output.as_ref(),
+                &redactor,
+            )?;
+            let timing = PhaseTiming {
+                phase: Phase::Configure,
+                target: None,
+                command: prepared.clone(),
+                started_at,
+                duration: start.elapsed(),
+                exit: ProcessExit::from(output.status),
+                stdout: output.stdout.clone(),
+                stderr: output.stderr.clone(),
+            };
+            if let Some(emitter) = &self.machine_output {
+                machine::phase_finished(emitter, &timing);
+            }
+            let context = HookContext {
+                previous: Some(&timing),
+                ..context
+            };
+            hooks::run(&self.hooks.after_configure, "after configure", &context)?;
+            std::io::Result::Ok((output, timing))
+        };
+
+        let mut phases = Vec::new();
+        let quiet_args = if self.quiet_configure {
+            log_level_args(args.cmake_version, "ERROR")
+        } else {
+            Vec::new()
+        };
+        let (mut configure, timing) = run_configure(&quiet_args)?;
+        phases.push(timing);
+        // a quiet configure that failed on its own is replayed verbosely for the report
+        if self.quiet_configure
+            && !configure.status.success()
+            && !configure.cancelled
+            && configure.killed_by.is_none()
+        {
+            if let Some(emitter) = &self.machine_output {
+                machine::phase_started(emitter, Phase::Configure, None);
+            }
+            let mut replay_args = log_level_args(args.cmake_version, "DEBUG");
+            replay_args.push("--debug-output".to_string());
+            let (replay, timing) = run_configure(&replay_args)?;
+            configure = replay;
+            phases.push(timing);
+        }
+        // the sources this tree downloaded are shared with the next trees
+        if let Some(cache_dir) = &self.fetch_cache_dir
+            && configure.status.success()
+        {
+            let deps_dir = self.binary_path.join("_deps");
+            fetch_cache::store(cache_dir, &deps_dir, &setup.fetch_declared)?;
+        }
+        setup.fetch_cache_lock = None;
+
+        if configure.cancelled {
+            let report = self.report(setup, phases)?;
+            return Err(self.cancelled(Phase::Configure, report));
+        }
+        if !configure.status.success() {
+            let exit = ProcessExit::from(configure.status);
+            let message = step_failure_message("configure", exit, configure.killed_by);
+            let report = self.report(setup, phases)?;
+            if let Some(emitter) = &self.machine_output {
+                machine::finished(emitter, &report, false);
+            }
+            // the configure failure matters more to the caller than bookkeeping errors
+            let _ = self.record_state(&setup.source_dir, &report, false);
+            if self.repro_script {
+                let _ = repro::write(&self.binary_path, &[&report.phases[0].command]);
+            }
+            self.bundle_failure(&configure.stdout, &configure.stderr);
+            let failure = BuildFailure::new(message, report);
+            return Err(match configure.killed_by {
+                Some(_) => Error::TimedOut(failure),
+                None => Error::ConfigureFailed(failure),
+            });
+        }
+
+        if let Some((cache_dir, key, version)) = &compiler_cache {
+            compiler_cache::store(cache_dir, key, &self.binary_path, version)?;
+        }
+        if let Some(ide) = &self.ide {
+            ide.refresh(&self.binary_path)?;
+        }
+        if let Some(fingerprint) = &fingerprint {
+            fingerprint::store(&self.binary_path, fingerprint)?;
+        }
+        Ok((phases, false))
+    }
+
+    /// Returns the fingerprint of a configure of `setup` with `args`: its
+    /// arguments, environment, cmake version, and project files.
+    fn configure_fingerprint(
+        &self,
+        setup: &RunSetup,
+        args: &ConfigureArgs,
+    ) -> std::io::Result<String> {
+        let mut inputs: Vec<String> = [
+            setup.source_dir.to_string_lossy().into_owned(),
+            self.binary_path.to_string_lossy().into_owned(),
+        ]
+        .into_iter()
+        .chain(args.preset.iter().cloned())
+        .chain(args.generator.iter().cloned())
+        .chain(self.ordered_define_args(&args.defines, args.derived()))
+        .chain(self.args.iter().cloned())
+        .chain(self.configure_args.iter().cloned())
+        .chain(args.diagnostic.iter().cloned())
+        .collect();
+        inputs.push(format!("quiet={}", self.quiet_configure));
+        inputs.push(format!("env_clear={}", self.environment_cleared));
+        inputs.extend(
+            self.environment
+                .iter()
+                .map(|(name, value)| format!("{}={:?}", name, value)),
+        );
+        let version = cmake::version_of(&self.tools.cmake()).unwrap_or_default();
+        let extra_files: Vec<PathBuf> = self.toolchain_file.iter().cloned().collect();
+        fingerprint::compute(
+            self.fingerprinter.as_ref(),
+            &version,
+            &inputs,
+            &setup.source_dir,
+            &self.binary_path,
+            &extra_files,
+        )
+    }
+
+    /// Returns the key of the compiler detection results of a configure of
+    /// `source_dir` with `args` and cmake `version`: the arguments that
+    /// select the toolchain.
+    fn compiler_cache_key(&self, args: &ConfigureArgs, source_dir: &Path, version: &str) -> String {
+        let mut toolchain_args = args.preset.clone();
+        if self.preset.is_some() {
+            let presets = std::fs::read_to_string(source_dir.join("CMakePresets.json"));
+            toolchain_args.push(presets.unwrap_or_default());
+        }
+        toolchain_args.extend(
+            args.defines
+                .iter()
+                .filter(|(d, _)| compiler_cache::is_toolchain_define(&d.name))
+                .map(|(_, arg)| arg.clone()),
+        );
+        toolchain_args.extend(args.generator.iter().cloned());
+        toolchain_args.extend(args.toolchain_file.iter().cloned());
+        toolchain_args.extend(args.cross.iter().cloned());
+        toolchain_args.extend(self.args.iter().cloned());
+        toolchain_args.extend(self.configure_args.iter().cloned());
+        compiler_cache::fingerprint(version, &toolchain_args)
+    }
+
+    /// Returns the targets the build step builds: the
+    /// [targets](Cmd::add_target), and those defined under the
+    /// [subdirectory](Cmd::restrict_to_subdirectory) among the configured
+    /// `targets`.
+    fn restricted_targets(&self, targets: Option<&[Target]>) -> Result<Vec<String>, Error> {
+        let mut restricted_targets = self.targets.clone();
+        let Some(subdirectory) = &self.subdirectory else {
+            return Ok(restricted_targets);
+        };
+        let Some(targets) = targets else {
+            return Err(Error::Unsupported(
+                "restrict_to_subdirectory requires the CMake file API (CMake 3.14 or later)"
+                    .to_string(),
+            ));
+        };
+        let under = fileapi::targets_under(targets, subdirectory);
+        if under.is_empty() {
+            return Err(Error::InvalidConfig(format!(
+                "no buildable targets are defined under {}",
+                subdirectory.display()
+            )));
+        }
+        for target in under {
+            if !restricted_targets.contains(&target) {
+                restricted_targets.push(target);
+            }
+        }
+        Ok(restricted_targets)
+    }
+
+    /// Runs the build step of the configured tree of `setup`, retrying with
+    /// fewer jobs while it looks like it ran out of memory, and adds its
+    /// phases, diagnostics, and compiler cache statistics to `report`.
+    fn build_phase(
+        &self,
+        setup: &RunSetup,
+        targets: Option<&[Target]>,
+        mut report: BuildReport,
+    ) -> ExecResult {
+        // limit the build to the targets defined under the requested subdirectory
+        let restricted_targets = self.restricted_targets(targets)?;
+
+        // forward keep-going to the native tool, which is only known after configure
+        let generator = cmake::read_cache_value(&self.binary_path, "CMAKE_GENERATOR");
+        let native_args = self.native_args(generator.as_deref());
+
+        // the launcher's counters are global, so the build's share is their difference
+        let launcher = launcher::detect(&self.binary_path);
+        let counters_before = launcher
+            .as_ref()
+            .and_then(|launcher| launcher.counters(|command| self.apply_environment(command)));
+
+        // build cmake, retrying with fewer jobs while the build looks like it ran out of memory
+        let mut jobs = self.initial_jobs();
+        let mut retries = 0;
+        let build = loop {
+            let build_command = self.build_command(&restricted_targets, &native_args, None, jobs);
+            let args = PreparedCommand::from(&build_command).args;
+            let context = HookContext {
+                phase: Phase::Build,
+                source_dir: &setup.source_dir,
+                binary_dir: &self.binary_path,
+                args: &args,
+                previous: report.phases.last(),
+            };
+            hooks::run(&self.hooks.before_build, "before build", &context)?;
+
+            let monitor = self.oom_backoff.as_ref().map(|_| MemoryMonitor::start());
+            let attempt = self.run_build_step(&restricted_targets, &native_args, jobs)?;
+            let context = HookContext {
+                previous: attempt.phases.last(),
+                ..context
+            };
+            hooks::run(&self.hooks.after_build, "after build", &context)?;
+            report.phases.extend(attempt.phases.iter().cloned());
+            let memory = monitor.and_then(MemoryMonitor::finish);
+
+            if attempt.cancelled {
+                return Err(self.cancelled(Phase::Build, report));
+            }
+
+            let retry_jobs = match (&self.oom_backoff, jobs) {
+                (Some(backoff), Some(current)) => backoff.next_jobs(current, retries),
+                _ => None,
+            };
+            let output = format!("{}{}", attempt.stdout, attempt.stderr);
+            match retry_jobs {
+                // a watchdog kill looks like the OOM killer's, but is no reason to retry
+                Some(next)
+                    if attempt.killed_by.is_none()
+                        && oom::looks_like_oom(&attempt.exit, &output, memory) =>
+                {
+                    jobs = Some(next);
+                    retries += 1;
+                }
+                _ => break attempt,
+            }
+        };
+
+        report.compiler_cache = launcher
+            .zip(counters_before)
+            .and_then(|(launcher, before)| {
+                let after = launcher.counters(|command| self.apply_environment(command))?;
+                Some(CompilerCacheStats {
+                    tool: launcher.name().to_string(),
+                    hits: after.hits.saturating_sub(before.hits),
+                    misses: after.misses.saturating_sub(before.misses),
+                })
+            });
+
+        // compilers print paths relative to the build tool's directory, the binary dir
+        let mut parsed = diagnostics::parse(&build.stdout);
+        for diagnostic in diagnostics::parse(&build.stderr) {
+            if !parsed.contains(&diagnostic) {
+                parsed.push(diagnostic);
+            }
+        }
+        for diagnostic in &mut parsed {
+            let file = report.binary_dir.join(&diagnostic.file);
+            let file = file.canonicalize().unwrap_or(file);
+            diagnostic.file = remap_path(&file, &setup.remaps);
+        }
+        report.diagnostics = parsed;
+        report.parallel_jobs = jobs;
+
+        if let Some(flavor) = self.ci_annotations {
+            let stdout_taken = self.machine_output.as_ref().is_some_and(Emitter::prints);
+            ci::emit(flavor, &report, stdout_taken);
+        }
+
+        if !build.exit.success() {
+            let message = step_failure_message("build", build.exit, build.killed_by);
+            if let Some(emitter) = &self.machine_output {
+                machine::finished(emitter, &report, false);
+            }
+            let _ = self.record_state(&setup.source_dir, &report, false);
+            if self.repro_script {
+                let configure = report
+                    .phases
+                    .first()
+                    .filter(|t| t.phase == Phase::Configure);
+                let commands: Vec<&PreparedCommand> = configure
+                    .into_iter()
+                    .chain(&build.phases)
+                    .map(|timing| &timing.command)
+                    .collect();
+                let _ = repro::write(&self.binary_path, &commands);
+            }
+            self.bundle_failure(&build.stdout, &build.stderr);
+            let failure = BuildFailure::new(message, report);
+            return Err(match build.killed_by {
+                Some(_) => Error::TimedOut(failure),
+                None => Error::BuildFailed(failure),
+            });
+        }
+        Ok(report)
+    }
+
+    /// Lists the files in the output directories, sorted by path.
+    ///
+    /// An output directory that is the build directory also holds the files
+    /// of the build system (`CMakeCache.txt`, `build.ninja`, ...), so only
+    /// the files the configured `targets` produce, and symlinks to them, are
+    /// taken from it.
+    fn collect_artifacts(&self, targets: Option<&[Target]>) -> std::io::Result<Vec<PathBuf>> {
+        let built: HashSet<PathBuf> = targets
+            .into_iter()
+            .flatten()
+            .flat_map(|target| &target.artifacts)
+            .filter_map(|artifact| artifact.canonicalize().ok())
+            .collect();
+        let mut output_dirs: Vec<&Path> = self.output_dirs().map(|(_, dir)| dir).to_vec();
+        output_dirs.sort();
+        output_dirs.dedup();
+
+        let mut artifacts = Vec::new();
+        for dir in output_dirs {
+            let files = list_files(dir)?;
+            if dir == self.binary_path {
+                artifacts.extend(
+                    files
+                        .into_iter()
+                        .filter(|file| file.canonicalize().is_ok_and(|real| built.contains(&real))),
+                );
+            } else {
+                artifacts.extend(files);
+            }
+        }
+        artifacts.sort();
+        artifacts.dedup();
+        Ok(artifacts)
+    }
+
+    /// Finishes a successful build: collects the artifacts, dependency
+    /// notices, and shared libraries of the configured `targets` into
+    /// `report`, checks their sizes and the warnings, and installs them if
+    /// an install prefix is set.
+    fn finish_build(
+        &self,
+        setup: &RunSetup,
+        targets: Option<&[Target]>,
+        mut report: BuildReport,
+    ) -> ExecResult {
+        report.artifacts = self.collect_artifacts(targets)?;
+        artifact::apply_rules(&self.artifact_rules, &mut report.artifacts)?;
+        report.shared_libraries = targets
+            .into_iter()
+            .flatten()
+            .filter_map(Target::shared_library)
+            .collect();
+        if let Some(notices_dir) = &self.notices_dir {
+            let mut fetch_dirs = vec![self.binary_path.join("_deps")];
+            fetch_dirs.extend(self.fetch_cache_dir.clone());
+            let notices_dir = std::path::absolute(notices_dir)?;
+            report.notices = notices::collect(&self.binary_path, &fetch_dirs, &notices_dir)?;
+            for notice in &report.notices {
+                if !report.artifacts.contains(&notice.path) {
+                    report.artifacts.push(notice.path.clone());
+                }
+            }
+        }
+        if let Some(analysis) = &self.size_analysis {
+            let size = size::analyze(analysis, report.real_artifacts())?;
+            let regressions = size.regressions.len();
+            report.size = Some(size);
+            if regressions > 0 && analysis.fails_on_regression() {
+                if let Some(emitter) = &self.machine_output {
+                    machine::finished(emitter, &report, false);
+                }
+                let _ = self.record_state(&setup.source_dir, &report, false);
+                let message = format!("{} artifacts exceed their size limit", regressions);
+                return Err(Error::SizeRegression(BuildFailure::new(message, report)));
+            }
+        }
+        if let Some(baseline) = &self.warnings_baseline
+            && let Some(message) = new_warnings(baseline, &report)?
+        {
+            if let Some(emitter) = &self.machine_output {
+                machine::finished(emitter, &report, false);
+            }
+            let _ = self.record_state(&setup.source_dir, &report, false);
+            return Err(Error::NewWarnings(BuildFailure::new(message, report)));
+        }
+        if let Some(prefix) = &self.install_prefix {
+            return self.install(prefix, &setup.source_dir, report);
+        }
+        if let Some(emitter) = &self.machine_output {
+            machine::finished(emitter, &report, true);
+        }
+        self.record_state(&setup.source_dir, &report, true)?;
+        Ok(report)
+    }
+
+    /// Plans the commands of a run, see [`Cmd::dry_run`].
+    ///
+    /// The commands are assembled by the same [`set_up`](Cmd::set_up),
+    /// [`configure_command`](Cmd::configure_command),
+    /// [`build_commands`](Cmd::build_commands), and
+    /// [`install_commands`](Cmd::install_commands) a run executes.
+    fn plan(&self) -> Result<Vec<PreparedCommand>, Error> {
+        let mut plan = self.clone();
+        self.check_clean_first()?;
+        self.check_capabilities(&self.tools.cmake())?;
+        let setup = plan.set_up(true)?;
+        let mut commands: Vec<PreparedCommand> = Vec::new();
+
+        if plan.steps.configures() {
+            let mut args = plan.configure_args(&setup)?;
+            if !plan.fresh {
+                retain_uncached_defines(&mut args.defines, &plan.binary_path);
+            }
+            let quiet_args = if plan.quiet_configure {
+                log_level_args(args.cmake_version, "ERROR")
+            } else {
+                Vec::new()
+            };
+            commands.push(plan.configure_command(&setup, &args, &quiet_args));
+        }
+        if plan.steps == Steps::ConfigureOnly {
+            return Ok(commands);
+        }
+
+        let installs_only = plan.install_only || plan.steps == Steps::InstallOnly;
+        if !installs_only {
+            // the subdirectory's targets are only known once the tree is configured
+            let targets = match fileapi::targets(&plan.binary_path) {
+                Ok(Some(configured)) => plan.restricted_targets(Some(&configured))?,
+                _ => plan.targets.clone(),
+            };
+            let generator = cmake::read_cache_value(&plan.binary_path, "CMAKE_GENERATOR")
+                .or_else(|| plan.expected_generator(&plan.binary_path, setup.preset.as_ref()));
+            let native_args = plan.native_args(generator.as_deref());
+            let build_commands = plan.build_commands(&targets, &native_args, plan.initial_jobs());
+            commands.extend(build_commands.into_iter().map(|(_, command)| command));
+        }
+        if let Some(prefix) = &plan.install_prefix {
+            let prefix = prefix
+                .canonicalize()
+                .or_else(|_| std::path::absolute(prefix))?;
+            let install_commands = plan.install_commands(&prefix);
+            commands.extend(install_commands.into_iter().map(|(_, command)| command));
+        }
+        Ok(commands)
+    }
+
+    /// Runs the stages of a pipeline, see [`Cmd::run_pipeline`].
+    fn execute_pipeline(&mut self, pipeline: &Pipeline) -> ExecResult {
+        // the phases of earlier stages are kept in the report of a failed one
+        let with_phases = |mut error: Error, report: &BuildReport| {
+            if let Some(failure) = error.failure_mut() {
+                let phases = &mut failure.report_mut().phases;
+                phases.splice(0..0, report.phases.iter().cloned());
+            }
+            error
+        };
+
+        let mut report = BuildReport::default();
+        for stage in pipeline.stages() {
+            if self.is_cancelled() {
+                return Err(self.cancelled(stage.phase(), report));
+            }
+            if matches!(stage, Stage::Install) && self.install_prefix.is_none() {
+                return Err(Error::InvalidConfig(
+                    "an install stage requires an install prefix".to_string(),
+                ));
+            }
+            match stage {
+                Stage::Configure | Stage::Build | Stage::Install => {
+                    let mut step = self.clone();
+                    step.install_only = false;
+                    step.steps = match stage {
+                        Stage::Configure => Steps::ConfigureOnly,
+                        Stage::Build => {
+                            step.install_prefix = None;
+                            Steps::BuildOnly
+                        }
+                        _ => Steps::InstallOnly,
+                    };
+                    match step.execute() {
+                        Ok(stage_report) => report.merge(stage_report),
+                        Err(error) => return Err(with_phases(error, &report)),
+                    }
+                }
+                Stage::Test => {
+                    let (tests, timing) = self.ctest().run_phase()?;
+                    report.phases.push(timing);
+                    let (success, exit) = (tests.success(), tests.exit);
+                    report.tests = Some(tests);
+                    if !success {
+                        return Err(Error::TestFailed(exit));
+                    }
+                }
+                Stage::Package => {
+                    let (packages, timing) = self.cpack().run_phase()?;
+                    report.phases.push(timing);
+                    report.packages.extend(packages);
+                }
+                Stage::Custom(name, run) => {
+                    let start = Instant::now();
+                    let started_at = SystemTime::now();
+                    let result = run(self, &report);
+                    report.phases.push(PhaseTiming {
+                        phase: Phase::Custom,
+                        target: Some(name.clone()),
+                        command: PreparedCommand {
+                            program: name.clone(),
+                            ..PreparedCommand::default()
+                        },
+                        started_at,
+                        duration: start.elapsed(),
+                        exit: ProcessExit {
+                            code: Some(if result.is_ok() { 0 } else { 1 }),
+                            ..ProcessExit::default()
+                        },
+                        stdout: String::new(),
+                        stderr: String::new(),
+                    });
+                    if let Err(error) = result {
+                        return Err(with_phases(error, &report));
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Runs a workflow preset, see [`Cmd::run_workflow`].
+    fn execute_workflow(&mut self, name: &str) -> ExecResult {
+        let source_dir = self.path().to_path_buf();
+        let presets = CMakePresets::new(&source_dir)?;
+        let workflow = presets
+            .get_workflow_preset(name)
+            .ok_or_else(|| Error::PresetNotFound(name.to_string()))?;
+        if workflow
+            .steps()
+            .first()
+            .is_none_or(|step| step.kind != "configure")
+        {
+            return Err(Error::PresetParse(format!(
+                "workflow preset {} does not start with a configure step",
+                name
+            )));
+        }
+        let mut stages = Vec::new();
+        for step in workflow.steps() {
+            let (stage, found) = match step.kind.as_str() {
+                "configure" => (Stage::Configure, presets.get_preset(&step.name).is_some()),
+                "build" => (Stage::Build, presets.get_build_preset(&step.name).is_some()),
+                "test" => (Stage::Test, presets.get_test_preset(&step.name).is_some()),
+                "package" => (
+                    Stage::Package,
+                    presets.get_package_preset(&step.name).is_some(),
+                ),
+                kind => {
+                    return Err(Error::PresetParse(format!(
+                        "workflow preset {} has a step of unknown type {}",
+                        name, kind
+                    )));
+                }
+            };
+            if !found {
+                return Err(Error::PresetNotFound(step.name.clone()));
+            }
+            stages.push((stage, step.name.clone()));
+        }
+
+        let cmake_program = self.tools.find_cmake()?;
+        let native = cmake::parsed_version_of(&cmake_program)
+            .is_none_or(|version| version.supports(Capability::Workflow));
+        if !native {
+            let mut report = BuildReport::default();
+            for (stage, preset) in stages {
+                match stage {
+                    Stage::Configure => self.preset = Some(preset),
+                    Stage::Build => self.build_preset = Some(preset),
+                    Stage::Test => self.test_preset = Some(preset),
+                    _ => {}
+                }
+                match self.execute_pipeline(&Pipeline::new().then(stage)) {
+                    Ok(step_report) => report.merge(step_report),
+                    Err(mut error) => {
+                        if let Some(failure) = error.failure_mut() {
+                            let phases = &mut failure.report_mut().phases;
+                            phases.splice(0..0, report.phases);
+                        }
+                        return Err(error);
+                    }
+                }
+            }
+            return Ok(report);
+        }
+
+        let configure_preset = presets.resolve(&stages[0].1).ok();
+        let mut command = std::process::Command::new(&cmake_program);
+        command
+            .current_dir(&source_dir)
+            .arg("--workflow")
+            .arg(format!("--preset={}", name));
+        if self.fresh {
+            command.arg("--fresh");
+        }
+        self.apply_environment(&mut command);
+        let start = Instant::now();
+        let started_at = SystemTime::now();
+        let redactor = self.redactor();
+        let output = instrument::run(Phase::Workflow, &mut command, &redactor, |command| {
+            process::run(command, self.cancel.as_ref(), None, None, &redactor)
+        })?;
+        let exit = ProcessExit::from(output.status);
+        let report = BuildReport {
+            phases: vec![PhaseTiming {
+                phase: Phase::Workflow,
+                target: Some(name.to_string()),
+                command: PreparedCommand::from(&command),
+                started_at,
+                duration: start.elapsed(),
+                exit,
+                stdout: output.stdout,
+                stderr: output.stderr,
+            }],
+            source_dir: std::path::absolute(&source_dir)?,
+            binary_dir: std::path::absolute(self.build_dir(configure_preset.as_ref()))?,
+            ..Default::default()
+        };
+        if output.cancelled {
+            return Err(self.cancelled(Phase::Workflow, report));
+        }
+        if !exit.success() {
+            let message = format!("cmake --workflow failed: {}", exit);
+            return Err(Error::BuildFailed(BuildFailure::new(message, report)));
+        }
+        Ok(report)
+    }
+
+    /// Installs a configured project into `prefix`, after or in place of the
+    /// build step.
+    ///
+    /// `report` carries the phases so far; the install phases and the
+    /// installed files are added to it. In install-only mode, the installed
+    /// files are the artifacts, and the include directory is added.
+    fn install(&self, prefix: &Path, source_dir: &Path, mut report: BuildReport) -> ExecResult {
+        check_dir_exists_and_create(prefix)?;
+        let prefix = prefix.canonicalize()?;
+
+        for (component, prepared) in self.install_commands(&prefix) {
+            // a cancellation after the build, or between components, stops before installing
+            if self.is_cancelled() {
+                return Err(self.cancelled(Phase::Install, report));
+            }
+            let mut command = prepared.to_command();
+
+            let target = component.map(str::to_string);
+            if let Some(emitter) = &self.machine_output {
+                machine::phase_started(emitter, Phase::Install, component);
+            }
+            let on_line = self.output_handler(Phase::Install, target.clone());
+
+            let start = Instant::now();
+            let started_at = SystemTime::now();
+            let output = self.run_step(
+                &mut command,
+                Phase::Install,
+                on_line.as_ref(),
+                &self.redactor(),
+            )?;
+            let timing = PhaseTiming {
+                phase: Phase::Install,
+                target,
+                command: prepared,
+                started_at,
+                duration: start.elapsed(),
+                exit: ProcessExit::from(output.status),
+                stdout: output.stdout.clone(),
+                stderr: output.stderr.clone(),
+            };
+            if let Some(emitter) = &self.machine_output {
+                machine::phase_finished(emitter, &timing);
+            }
+            let exit = timing.exit;
+            report.phases.push(timing);
+
+            if output.cancelled {
+                return Err(self.cancelled(Phase::Install, report));
+            }
+            if !exit.success() {
+                let message = step_failure_message("install", exit, output.killed_by);
+                if let Some(emitter) = &self.machine_output {
+                    machine::finished(emitter, &report, false);
+                }
+                let _ = self.record_state(source_dir, &report, false);
+                if self.repro_script {
+                    let commands: Vec<&PreparedCommand> =
+                        report.phases.iter().map(|timing| &timing.command).collect();
+                    let _ = repro::write(&self.binary_path, &commands);
+                }
+                self.bundle_failure(&output.stdout, &output.stderr);
+                let failure = BuildFailure::new(message, report);
+                return Err(match output.killed_by {
+                    Some(_) => Error::TimedOut(failure),
+                    None => Error::InstallFailed(failure),
+                });
+            }
+
+            // CMake lists every installed file in the build tree's install manifest
+            let manifest = match component {
+                Some(component) => format!("install_manifest_{}.txt", component),
+                None => "install_manifest.txt".to_string(),
+            };
+            let manifest =
+                std::fs::read_to_string(self.binary_path.join(manifest)).unwrap_or_default();
+            let installed: Vec<PathBuf> = manifest
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(PathBuf::from)
+                .collect();
+            report.installed.extend(installed.iter().cloned());
+            if let Some(component) = component {
+                report
+                    .installed_components
+                    .push((component.to_string(), installed));
+            }
+        }
+
+        if self.install_only {
+            report.artifacts = report.installed.clone();
+            let include_dir = prefix.join("include");
+            if include_dir.is_dir() {
+                report.include_dirs.push(include_dir);
+            }
+        }
+
+        if let Some(emitter) = &self.machine_output {
+            machine::finished(emitter, &report, true);
+        }
+        self.record_state(source_dir, &report, true)?;
+        Ok(report)
+    }
+
+    /// Records the effective configuration and timings of this run in the
+    /// build tree's state file.
+    fn record_state(
+        &self,
+        source_dir: &Path,
+        report: &BuildReport,
+        success: bool,
+    ) -> std::io::Result<()> {
+        let configuration = self.configuration(source_dir, &self.binary_path)?;
+        BuildState::record(
+            &self.binary_path,
+            configuration,
+            cmake::version_of(&self.tools.cmake()),
+            report,
+            success,
+        )
+    }
+
+    /// Returns the configuration recorded in the state file of `binary_dir`,
+    /// with secrets redacted.
+    fn configuration(
+        &self,
+        source_dir: &Path,
+        binary_dir: &Path,
+    ) -> std::io::Result<Configuration> {
+        let redactor = self.redactor();
+        Ok(Configuration {
+            source_dir: source_dir.canonicalize()?,
+            preset: self.preset.clone(),
+            defines: self
+                .defines()
+                .into_iter()
+                .map(|(name, value)| {
+                    // the value is redacted as it appears on the command line
+                    let prefix = format!("-D{}=", name);
+                    let define = redactor
+                        .redact(&format!("{}{}", prefix, value))
+                        .into_owned();
+                    let value = define.strip_prefix(&prefix).unwrap_or(&define);
+                    (name.to_string(), value.to_string())
+                })
+                .collect(),
+            args: self
+                .args
+                .iter()
+                .chain(&self.configure_args)
+                .chain(&self.build_args)
+                .chain(&self.native_build_args)
+                .map(|arg| redactor.redact(arg).into_owned())
+                .collect(),
+            generator: cmake::read_cache_value(binary_dir, "CMAKE_GENERATOR"),
+            relocated_from: self.relocated_from.clone(),
+        })
+    }
+
+    /// Writes the failure bundle, if enabled, with the output of the failed step.
+    fn bundle_failure(&self, stdout: &str, stderr: &str) {
+        if let Some(path) = &self.failure_bundle {
+            let output = format!("{}{}", stdout, stderr);
+            // the build error matters more to the caller than a missing bundle
+            let _ = bundle::write(path, &self.binary_path, &output, &self.redactor());
+        }
+    }
+
+    /// Sends the spans of a finished run to the OTLP exporter, if one is set.
+    #[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+    fn export_trace(&self, started_at: SystemTime, result: &ExecResult) {
+        #[cfg(feature = "otel")]
+        if let Some(exporter) = &self.otlp_exporter {
+            let (report, error) = match result {
+                Ok(report) => (Some(report), None),
+                Err(e) => (e.report(), Some(e.to_string())),
+            };
+            // an unreachable collector must not fail the build
+            let _ = exporter.export(&crate::otel::Run {
+                started_at,
+                preset: self.preset.as_deref(),
+                report,
+                error: error.as_deref(),
+            });
+        }
+    }
+
+    /// Runs a configure, build, or install `command`, with its output
+    /// connected to the [output streams](Cmd::set_output_streams) if set.
+    fn run_step(
+        &self,
+        command: &mut std::process::Command,
+        phase: Phase,
+        on_line: Option<&LineHandler>,
+        redactor: &Redactor,
+    ) -> std::io::Result<process::CapturedOutput> {
+        let cancel = self.cancel.as_ref();
+        let watchdog = self.watchdog(phase);
+        instrument::run(phase, command, redactor, |command| {
+            #[cfg(feature = "pty")]
+            if self.pty {
+                return crate::pty::run(command, cancel, watchdog.as_ref(), on_line, redactor);
+            }
+            match &self.output_streams {
+                Some(streams) => {
+                    let (stdout, stderr) = streams.stdio()?;
+                    process::run_direct(command, cancel, watchdog.as_ref(), stdout, stderr)
+                }
+                None => process::run(command, cancel, watchdog.as_ref(), on_line, redactor),
+            }
+        })
+    }
+
+    /// Returns the handler for the output lines of `phase`, or `None` if
+    /// they are forwarded to the parent's streams.
+    fn output_handler(&self, phase: Phase, target: Option<String>) -> Option<LineHandler> {
+        match &self.machine_output {
+            Some(emitter) => Some(machine::output_handler(emitter, phase, target)),
+            // the lines are collected into the report either way
+            None if self.capture_output => Some(Arc::new(|_, _| {})),
+            None if !self.output_style.is_plain() => Some(self.output_style.handler()),
+            None => None,
+        }
+    }
+
+    /// Hands the outcome of a finished run to every notifier.
+    fn send_notifications(&self, started_at: SystemTime, result: &ExecResult) {
+        if self.notifiers.is_empty() {
+            return;
+        }
+        let (report, error) = match result {
+            Ok(report) => (Some(report), None),
+            Err(e) => (e.report(), Some(e)),
+        };
+        let notification = Notification {
+            source_dir: self.path(),
+            preset: self.preset.as_deref(),
+            duration: started_at.elapsed().unwrap_or_default(),
+            report,
+            error,
+        };
+        for notifier in &self.notifiers {
+            // a failed notification must not fail the build
+            let _ = notifier.notify(&notification);
+        }
+    }
+
+    /// Checks that the configure, build, and test presets exist in the
+    /// source directory's `CMakePresets.json`, returning the resolved
+    /// configure preset.
+    fn check_presets(&self, source_dir: &Path) -> Result<Option<ResolvedPreset>, Error> {
+        if self.preset.is_none() && self.build_preset.is_none() && self.test_preset.is_none() {
+            return Ok(None);
+        }
+        let presets = CMakePresets::new(source_dir)?;
+        let resolved = match &self.preset {
+            Some(name) => Some(presets.resolve(name)?),
+            None => None,
+        };
+        if let Some(name) = &self.build_preset
+            && presets.get_build_preset(name).is_none()
+        {
+            return Err(Error::PresetNotFound(name.clone()));
+        }
+        if let Some(name) = &self.test_preset
+            && presets.get_test_preset(name).is_none()
+        {
+            return Err(Error::PresetNotFound(name.clone()));
+        }
+        Ok(resolved)
+    }
+
+    /// Resolves the configure preset, if one is set and resolves.
+    fn resolved_preset(&self) -> Option<ResolvedPreset> {
+        let name = self.preset.as_deref()?;
+        CMakePresets::new(self.path()).ok()?.resolve(name).ok()
+    }
+
+    /// Returns the build directory: the one set with
+    /// [`set_binary_path`](Cmd::set_binary_path), or else the `binaryDir` of
+    /// the configure preset, or else `"build"`.
+    fn requested_build_dir(&self, preset: Option<&ResolvedPreset>) -> PathBuf {
+        match preset.and_then(|preset| preset.binary_dir.as_ref()) {
+            Some(binary_dir) if !self.binary_path_set => binary_dir.clone(),
+            _ => self.binary_path.clone(),
+        }
+    }
+
+    /// Returns the [requested build directory](Cmd::requested_build_dir), or
+    /// the [short one](Cmd::short_build_dir) it is moved to.
+    fn build_dir(&self, preset: Option<&ResolvedPreset>) -> PathBuf {
+        let binary_dir = self.requested_build_dir(preset);
+        if !self.short_build_dir {
+            return binary_dir;
+        }
+        let root = self
+            .short_build_root
+            .clone()
+            .unwrap_or_else(default_short_build_root);
+        let binary_dir = std::path::absolute(&binary_dir).unwrap_or(binary_dir);
+        // a directory already moved is not moved again
+        if binary_dir.starts_with(&root) {
+            return binary_dir;
+        }
+        let hash = compiler_cache::fnv1a(binary_dir.to_string_lossy().as_bytes());
+        root.join(format!("{:08x}", hash as u32))
+    }
+
+    /// Returns the generator the build tree at `binary_path` has or will be
+    /// configured with, if known before configuring.
+    fn expected_generator(
+        &self,
+        binary_path: &Path,
+        preset: Option<&ResolvedPreset>,
+    ) -> Option<String> {
+        // the generator of an existing tree can't change, so it wins over the requested ones
+        cmake::read_cache_value(binary_path, "CMAKE_GENERATOR")
+            .or_else(|| self.generator.clone())
+            .or_else(|| generator_arg(&self.args))
+            .or_else(|| generator_arg(&self.configure_args))
+            .or_else(|| preset.and_then(|preset| preset.generator.clone()))
+            .or_else(|| std::env::var("CMAKE_GENERATOR").ok())
+            .filter(|generator| !generator.is_empty())
+    }
+
+    /// Returns the [ninja executable](Cmd::set_ninja_executable) to pass as
+    /// `CMAKE_MAKE_PROGRAM` for `generator`, unless that is defined.
+    fn ninja_make_program(&self, generator: &str) -> Option<PathBuf> {
+        let ninja = self.tools.ninja.as_ref()?;
+        let defined = self
+            .defines
+            .iter()
+            .any(|d| d.name.split(':').next() == Some("CMAKE_MAKE_PROGRAM"));
+        (generator.starts_with("Ninja") && !defined)
+            .then(|| std::path::absolute(ninja).unwrap_or(ninja.clone()))
+    }
+
+    /// Returns the configuration of the build and install steps: the one of
+    /// [`set_config`](Cmd::set_config), or else the
+    /// [build type](Cmd::set_build_type) if the tree uses a multi-config
+    /// generator.
+    fn build_config(&self) -> Option<String> {
+        self.config.clone().or_else(|| {
+            let generator = cmake::read_cache_value(&self.binary_path, "CMAKE_GENERATOR")?;
+            self.build_type
+                .clone()
+                .filter(|_| is_multi_config(&generator))
+        })
+    }
+
+    /// Defines every variable of `flags` as the last of its `user_flags`
+    /// values followed by the flags, replacing earlier definitions.
+    fn set_flags(&mut self, user_flags: &[(String, String)], flags: Vec<(&str, String)>) {
+        for (name, flags) in flags {
+            let value = match user_flags.iter().rfind(|(user, _)| user == name) {
+                Some((_, user)) if !user.is_empty() => format!("{} {}", user, flags),
+                _ => flags,
+            };
+            self.defines
+                .retain(|d| d.name.split(':').next() != Some(name));
+            self.defines.push(Defination {
+                name: name.to_string(),
+                value,
+                once: false,
+            });
+        }
+    }
+
+    /// Sets up the [Visual Studio environment](Cmd::with_msvc_env), once.
+    fn bootstrap_msvc(&mut self, preset: Option<&ResolvedPreset>) -> Result<(), Error> {
+        let Some(arch) = self.msvc_arch.take() else {
+            return Ok(());
+        };
+        if !cfg!(windows) || msvc::is_active() {
+            return Ok(());
+        }
+        let generator = self.expected_generator(&self.binary_path, preset);
+        if generator
+            .as_deref()
+            .is_none_or(|generator| generator.starts_with("Visual Studio"))
+        {
+            // the platform of a configured tree can't change
+            let configured = self.binary_path.join("CMakeCache.txt").is_file();
+            if self.generator_platform.is_none() && preset.is_none() && !configured {
+                self.generator_platform = Some(arch.platform().to_string());
+            }
+            return Ok(());
+        }
+        let install_dir = msvc::installation(arch).ok_or_else(|| {
+            Error::Unsupported(format!(
+                "no Visual Studio with the C++ tools for {} was found by vswhere",
+                arch.platform()
+            ))
+        })?;
+        let environment = msvc::capture(&install_dir, arch)?;
+        self.environment.splice(0..0, environment);
+        Ok(())
+    }
+
+    /// Adds the environment of the configure preset below the variables set
+    /// with [`env`](Cmd::env), so that the build and install processes see
+    /// what cmake saw when configuring.
+    fn apply_preset_environment(&mut self, preset: Option<&ResolvedPreset>) {
+        let Some(preset) = preset else {
+            return;
+        };
+        let environment: Vec<(String, Option<String>)> = preset
+            .environment
+            .iter()
+            .filter(|(name, _)| !self.environment.iter().any(|(set, _)| set == *name))
+            .map(|(name, value)| (name.clone(), Some(value.clone())))
+            .collect();
+        self.environment.splice(0..0, environment);
+    }
+
+    /// Returns the user's `define_args` and the `derived` definitions in the
+    /// [`ArgOrder`] of the builder.
+    fn ordered_define_args(
+        &self,
+        define_args: &[(&Defination, String)],
+        derived: [&Vec<String>; 4],
+    ) -> Vec<String> {
+        let mut user: Vec<&(&Defination, String)> = define_args.iter().collect();
+        if self.arg_order == ArgOrder::Sorted {
+            // stable, so the order of a variable's repetitions is kept
+            user.sort_by_key(|(d, _)| d.name.split(':').next().unwrap_or_default());
+        }
+        let user = user.into_iter().map(|(_, arg)| arg.clone());
+        let derived = derived.into_iter().flatten().cloned();
+        match self.arg_order {
+            ArgOrder::UserDefinesLast => derived.chain(user).collect(),
+            ArgOrder::Insertion | ArgOrder::Sorted => user.chain(derived).collect(),
+        }
+    }
+
+    /// Returns the `--preset` argument of the configure preset.
+    fn preset_args(&self) -> Vec<String> {
+        self.preset
+            .iter()
+            .map(|preset| format!("--preset={}", preset))
+            .collect()
+    }
+
+    /// Returns the `-G`, `-A` and `-T` arguments of the generator.
+    fn generator_args(&self) -> Vec<String> {
+        let mut generator_args: Vec<String> = Vec::new();
+        for (flag, value) in [
+            ("-G", &self.generator),
+            ("-A", &self.generator_platform),
+            ("-T", &self.generator_toolset),
+        ] {
+            if let Some(value) = value {
+                generator_args.push(flag.to_string());
+                generator_args.push(value.clone());
+            }
+        }
+        generator_args
+    }
+
+    /// Returns the defines cmakr derives from its settings: the output
+    /// directories (`output_dirs`, canonical, by variable), debug postfix,
+    /// compile commands export, build type, and make program.
+    fn output_path_args(
+        &self,
+        output_dirs: &[(&str, PathBuf)],
+        preset: Option<&ResolvedPreset>,
+    ) -> Vec<String> {
+        let mut output_path_args: Vec<String> = output_dirs
+            .iter()
+            .map(|(name, dir)| format!("-D{}={}", name, forward_slash_path(dir)))
+            .collect();
+        if self.strip_debug_postfix {
+            output_path_args.push("-DCMAKE_DEBUG_POSTFIX=".to_string());
+        }
+        if self.exports_compile_commands() {
+            output_path_args.push("-DCMAKE_EXPORT_COMPILE_COMMANDS=ON".to_string());
+        }
+        if let Some((name, value)) = self.build_type_define(&self.binary_path, preset) {
+            output_path_args.push(format!("-D{}={}", name, value));
+        }
+        if let Some(generator) = self.expected_generator(&self.binary_path, preset)
+            && let Some(ninja) = self.ninja_make_program(&generator)
+        {
+            output_path_args.push(format!(
+                "-DCMAKE_MAKE_PROGRAM={}",
+                forward_slash_path(&ninja)
+            ));
+        }
+        output_path_args
+    }
+
+    /// Returns the `-D` arguments of [`cross_defines`](Cmd::cross_defines),
+    /// [`flag_defines`](Cmd::flag_defines), and
+    /// [`option_defines`](Cmd::option_defines).
+    fn cross_args(&self) -> Result<Vec<String>, Error> {
+        Ok(self
+            .cross_defines()?
+            .into_iter()
+            .chain(self.flag_defines()?)
+            .chain(self.option_defines()?)
+            .map(|(name, value)| format!("-D{}={}", name, value))
+            .collect())
+    }
+
+    /// Returns the flags variables with the [sanitizer](Cmd::enable_sanitizer)
+    /// and [LTO](Cmd::enable_lto) flags appended to the user's values.
+    fn flag_defines(&self) -> Result<Vec<(String, String)>, Error> {
+        let preset = self.resolved_preset();
+        let binary_dir = self.build_dir(preset.as_ref());
+        let generator = self.expected_generator(&binary_dir, preset.as_ref());
+        let flags = sanitizer::flags(
+            &self.sanitizers,
+            self.lto,
+            &binary_dir,
+            generator.as_deref(),
+        )
+        .map_err(Error::Unsupported)?;
+        Ok(flags
+            .into_iter()
+            .map(|(name, flags)| {
+                let user = self
+                    .defines
+                    .iter()
+                    .rfind(|d| d.name.split(':').next() == Some(name))
+                    .map(|d| d.value.as_str())
+                    .filter(|value| !value.is_empty());
+                let value = match user {
+                    Some(user) => format!("{} {}", user, flags),
+                    None => flags,
+                };
+                (name.to_string(), value)
+            })
+            .collect())
+    }
+
+    /// Returns the definitions of the [C++ standard](Cmd::set_cxx_standard),
+    /// [library type](Cmd::enable_shared_libs), and
+    /// [prefix path](Cmd::set_prefix_path), without the variables the user
+    /// defines.
+    fn option_defines(&self) -> Result<Vec<(String, String)>, Error> {
+        let defined = |name: &str| {
+            self.defines
+                .iter()
+                .any(|d| d.name.split(':').next() == Some(name))
+        };
+        let mut defines = Vec::new();
+        if let Some(standard) = self.cxx_standard {
+            if !CXX_STANDARDS.contains(&standard) {
+                return Err(Error::InvalidConfig(format!(
+                    "unknown C++ standard {}, expected one of 98, 11, 14, 17, 20, 23, or 26",
+                    standard
+                )));
+            }
+            // the standard and whether it is required go together
+            if !defined("CMAKE_CXX_STANDARD") {
+                defines.push(("CMAKE_CXX_STANDARD", standard.to_string()));
+                defines.push(("CMAKE_CXX_STANDARD_REQUIRED", "ON".to_string()));
+            }
+        }
+        if let Some(shared) = self.shared_libs {
+            let value = if shared { "ON" } else { "OFF" };
+            defines.push(("BUILD_SHARED_LIBS", value.to_string()));
+        }
+        if !self.prefix_path.is_empty() {
+            let paths = self
+                .prefix_path
+                .iter()
+                .map(|path| Ok(forward_slash_path(&std::path::absolute(path)?)))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            defines.push(("CMAKE_PREFIX_PATH", paths.join(";")));
+        }
+
+        Ok(defines
+            .into_iter()
+            .filter(|(name, _)| !defined(name))
+            .map(|(name, value)| (name.to_string(), value))
+            .collect())
+    }
+
+    /// Returns the arguments that keep configure off the network in
+    /// offline mode, unless the user defines them.
+    fn offline_args(&self) -> Option<String> {
+        let defined = self
+            .defines
+            .iter()
+            .any(|d| d.name.split(':').next() == Some("FETCHCONTENT_FULLY_DISCONNECTED"));
+        (!defined).then(|| "-DFETCHCONTENT_FULLY_DISCONNECTED=ON".to_string())
+    }
+
+    /// Returns the arguments passed to the native build tool: keep-going
+    /// for `generator`, then the [native build args](Cmd::add_native_build_arg).
+    fn native_args(&self, generator: Option<&str>) -> Vec<String> {
+        let mut native_args: Vec<String> = Vec::new();
+        if self.keep_going {
+            native_args.extend(keep_going_args(generator.unwrap_or_default()));
+        }
+        native_args.extend(self.native_build_args.iter().cloned());
+        native_args
+    }
+
+    /// Returns the job count of the first build attempt.
+    fn initial_jobs(&self) -> Option<usize> {
+        match &self.parallel_targets {
+            Some((_, max_jobs)) => Some(*max_jobs),
+            None => self
+                .jobs
+                .or_else(|| self.oom_backoff.as_ref().map(OomBackoff::initial_jobs)),
+        }
+    }
+
+    /// Returns the target and job count of every concurrent build
+    /// invocation of an attempt with `jobs` jobs.
+    fn build_invocations(&self, jobs: Option<usize>) -> Vec<(Option<&str>, Option<usize>)> {
+        match &self.parallel_targets {
+            Some((targets, max_jobs)) => {
+                let shares = split_jobs(jobs.unwrap_or(*max_jobs), targets.len());
+                targets
+                    .iter()
+                    .zip(shares)
+                    .map(|(target, share)| (Some(target.as_str()), Some(share)))
+                    .collect()
+            }
+            None => vec![(None, jobs)],
+        }
+    }
+
+    /// Returns the `cmake --build` command of one build invocation, without
+    /// its environment.
+    fn build_command(
+        &self,
+        targets: &[String],
+        native_args: &[String],
+        target: Option<&str>,
+        jobs: Option<usize>,
+    ) -> std::process::Command {
+        let mut command = std::process::Command::new(self.tools.cmake());
+        command.arg("--build");
+        match &self.build_preset {
+            Some(preset) => {
+                command
+                    .current_dir(self.path())
+                    .arg(format!("--preset={}", preset));
+            }
+            None => {
+                command.arg(&self.binary_path);
+            }
+        }
+        command.args(&self.args).args(&self.build_args);
+        if self.verbose
+            && cmake::parsed_version_of(&self.tools.cmake())
+                .is_none_or(|version| version.supports(Capability::BuildVerbose))
+        {
+            command.arg("--verbose");
+        }
+        if let Some(config) = self.build_config() {
+            command.arg("--config").arg(config);
+        }
+        match target {
+            Some(target) => {
+                command.arg("--target").arg(target);
+            }
+            None if !targets.is_empty() => {
+                command.arg("--target").args(targets);
+            }
+            None => {}
+        }
+        if let Some(jobs) = jobs {
+            command.arg("--parallel").arg(jobs.to_string());
+        }
+        if self.clean_first {
+            command.arg("--clean-first");
+        }
+        if !native_args.is_empty() {
+            command.arg("--").args(native_args);
+        }
+        command
+    }
+
+    /// Returns the components installed one after another, or a single
+    /// `None` to install everything.
+    fn install_components(&self) -> Vec<Option<&str>> {
+        match self.install_components.as_slice() {
+            [] => vec![None],
+            components => components.iter().map(|c| Some(c.as_str())).collect(),
+        }
+    }
+
+    /// Returns the `cmake --install` command of `component` into the
+    /// canonical `prefix`, without its environment.
+    fn install_command(&self, prefix: &Path, component: Option<&str>) -> std::process::Command {
+        let mut command = std::process::Command::new(self.tools.cmake());
+        command
+            .arg("--install")
+            .arg(&self.binary_path)
+            .arg("--prefix")
+            .arg(normalize_path(prefix));
+        if let Some(config) = self.build_config() {
+            command.arg("--config").arg(config);
+        }
+        if let Some(component) = component {
+            command.arg("--component").arg(component);
+        }
+        command
+    }
+
+    /// Returns the arguments selecting the toolchain file of
+    /// [`set_toolchain_file`](Cmd::set_toolchain_file).
+    ///
+    /// The toolchain file of the configure preset or of a
+    /// `CMAKE_TOOLCHAIN_FILE` define counts as defined before the builder's,
+    /// so a conflict is resolved by the [`DefinePolicy`]. When the builder's
+    /// file wins, conflicting defines are removed from `define_args`; when
+    /// the same file is already selected, nothing is added.
+    fn toolchain_file_args(
+        &self,
+        preset: Option<&ResolvedPreset>,
+        define_args: &mut Vec<(&Defination, String)>,
+    ) -> Result<Vec<String>, Error> {
+        let Some(toolchain) = &self.toolchain_file else {
+            return Ok(Vec::new());
+        };
+        let toolchain = std::path::absolute(toolchain)?;
+        let mut selected: Vec<PathBuf> = define_args
+            .iter()
+            .filter(|(d, _)| d.name.split(':').next() == Some("CMAKE_TOOLCHAIN_FILE"))
+            .map(|(d, _)| PathBuf::from(&d.value))
+            .collect();
+        selected.extend(preset.and_then(|preset| preset.toolchain_file.clone()));
+
+        let identity = |path: &Path| {
+            path.canonicalize()
+                .or_else(|_| std::path::absolute(path))
+                .ok()
+        };
+        let id = identity(&toolchain);
+        if let Some(other) = selected.iter().find(|path| identity(path) != id) {
+            match self.define_policy {
+                DefinePolicy::LastWins => {
+                    define_args
+                        .retain(|(d, _)| d.name.split(':').next() != Some("CMAKE_TOOLCHAIN_FILE"));
+                }
+                DefinePolicy::FirstWins => return Ok(Vec::new()),
+                DefinePolicy::Error => {
+                    return Err(Error::InvalidDefine(format!(
+                        "toolchain file {} conflicts with {}",
+                        toolchain.display(),
+                        other.display()
+                    )));
+                }
+            }
+        } else if !selected.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let path = forward_slash_path(&toolchain);
+        if cmake::parsed_version_of(&self.tools.cmake())
+            .is_some_and(|version| version.supports(Capability::Toolchain))
+        {
+            Ok(vec!["--toolchain".to_string(), path])
+        } else {
+            Ok(vec![format!("-DCMAKE_TOOLCHAIN_FILE={}", path)])
+        }
+    }
+
+    /// Returns the definitions of the [target system](Cmd::set_system_name),
+    /// [sysroot](Cmd::set_sysroot), and [staging prefix](Cmd::set_staging_prefix)
+    /// bundle, and of the [compiler launcher](Cmd::with_compiler_launcher),
+    /// without the variables the user defines.
+    fn cross_defines(&self) -> std::io::Result<Vec<(String, String)>> {
+        let mut defines = Vec::new();
+        if let Some(launcher) = &self.compiler_launcher
+            && let Some(program) = launcher.program()?
+        {
+            let program = forward_slash_path(&program);
+            defines.push(("CMAKE_C_COMPILER_LAUNCHER", program.clone()));
+            defines.push(("CMAKE_CXX_COMPILER_LAUNCHER", program));
+        }
+        if let Some(name) = &self.system_name {
+            defines.push(("CMAKE_SYSTEM_NAME", name.clone()));
+        }
+        if let Some(processor) = &self.system_processor {
+            defines.push(("CMAKE_SYSTEM_PROCESSOR", processor.clone()));
+        }
+        if self.sysroot.is_some() || self.staging_prefix.is_some() {
+            self.find_root_defines(&mut defines)?;
+        }
+
+        Ok(defines
+            .into_iter()
+            .filter(|(name, _)| {
+                !self
+                    .defines
+                    .iter()
+                    .any(|d| d.name.split(':').next() == Some(*name))
+            })
+            .map(|(name, value)| (name.to_string(), value))
+            .collect())
+    }
+
+    /// Adds the sysroot, staging prefix, and find root definitions to `defines`.
+    fn find_root_defines(&self, defines: &mut Vec<(&str, String)>) -> std::io::Result<()> {
+        let absolute = |path: &Option<PathBuf>| -> std::io::Result<Option<String>> {
+            path.as_deref()
+                .map(|path| Ok(forward_slash_path(&std::path::absolute(path)?)))
+                .transpose()
+        };
+        let sysroot = absolute(&self.sysroot)?;
+        let staging_prefix = absolute(&self.staging_prefix)?;
+
+        if let Some(sysroot) = &sysroot {
+            defines.push(("CMAKE_SYSROOT", sysroot.clone()));
+        }
+        if let Some(staging_prefix) = &staging_prefix {
+            defines.push(("CMAKE_STAGING_PREFIX", staging_prefix.clone()));
+        }
+        let roots: Vec<&str> = staging_prefix
+            .iter()
+            .chain(&sysroot)
+            .map(String::as_str)
+            .collect();
+        defines.push(("CMAKE_FIND_ROOT_PATH", roots.join(";")));
+        defines.push(("CMAKE_FIND_ROOT_PATH_MODE_PROGRAM", "NEVER".to_string()));
+        for name in [
+            "CMAKE_FIND_ROOT_PATH_MODE_LIBRARY",
+            "CMAKE_FIND_ROOT_PATH_MODE_INCLUDE",
+            "CMAKE_FIND_ROOT_PATH_MODE_PACKAGE",
+        ] {
+            defines.push((name, "ONLY".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Returns the `CMAKE_BUILD_TYPE` define of [`set_build_type`](Cmd::set_build_type),
+    /// unless the variable is defined explicitly or the generator is multi-config.
+    fn build_type_define(
+        &self,
+        binary_path: &Path,
+        preset: Option<&ResolvedPreset>,
+    ) -> Option<(String, String)> {
+        let build_type = self.build_type.as_ref()?;
+        let defined = self
+            .defines
+            .iter()
+            .any(|d| d.name.split(':').next() == Some("CMAKE_BUILD_TYPE"));
+        let multi_config = self
+            .expected_generator(binary_path, preset)
+            .is_some_and(|generator| is_multi_config(&generator));
+        (!defined && !multi_config).then(|| ("CMAKE_BUILD_TYPE".to_string(), build_type.clone()))
+    }
+
+    /// Returns whether cmakr adds `CMAKE_EXPORT_COMPILE_COMMANDS` for the
+    /// editor integration, which an explicit define overrides.
+    fn exports_compile_commands(&self) -> bool {
+        self.ide.is_some()
+            && !self
+                .defines
+                .iter()
+                .any(|d| d.name.split(':').next() == Some("CMAKE_EXPORT_COMPILE_COMMANDS"))
+    }
+
+    /// Sets the configured environment and the resolved credentials on `command`.
+    fn apply_environment(&self, command: &mut std::process::Command) {
+        if let Some(dir) = &self.current_dir
+            && command.get_current_dir().is_none()
+        {
+            command.current_dir(dir);
+        }
+        if self.environment_cleared {
+            command.env_clear();
+        }
+        if self.english_diagnostics {
+            command.envs(encoding::ENGLISH_ENVIRONMENT);
+        }
+        for (name, value) in &self.environment {
+            match value {
+                Some(value) => command.env(name, value),
+                None => command.env_remove(name),
+            };
+        }
+        self.resolved_credentials.apply(command);
+    }
+
+    /// Returns this builder with its relative paths resolved against the
+    /// [working directory](Cmd::current_dir), if one is set.
+    ///
+    /// Every public entry point that reads the build tree goes through it,
+    /// so that none of them resolves paths against the caller's directory.
+    fn resolved(&self) -> std::io::Result<Cow<'_, Self>> {
+        if self.current_dir.is_none() {
+            return Ok(Cow::Borrowed(self));
+        }
+        let mut cmd = self.clone();
+        cmd.resolve_paths()?;
+        Ok(Cow::Owned(cmd))
+    }
+
+    /// Resolves the relative paths of the run against the
+    /// [working directory](Cmd::current_dir), if one is set.
+    fn resolve_paths(&mut self) -> std::io::Result<()> {
+        let Some(dir) = &self.current_dir else {
+            return Ok(());
+        };
+        let dir = std::path::absolute(dir)?;
+        let resolve = |path: &mut PathBuf| {
+            if path.is_relative() {
+                *path = dir.join(&*path);
+            }
+        };
+        self.path = Some(dir.join(self.path()));
+        resolve(&mut self.binary_path);
+        resolve(&mut self.output_path);
+        for path in [
+            &mut self.runtime_output_path,
+            &mut self.library_output_path,
+            &mut self.archive_output_path,
+            &mut self.short_build_root,
+            &mut self.toolchain_file,
+            &mut self.sysroot,
+            &mut self.staging_prefix,
+            &mut self.fetch_cache_dir,
+            &mut self.compiler_cache_dir,
+            &mut self.install_prefix,
+            &mut self.graphviz,
+        ] {
+            path.iter_mut().for_each(resolve);
+        }
+        self.prefix_path.iter_mut().for_each(resolve);
+        self.tools.current_dir = Some(dir.clone());
+        self.current_dir = Some(dir);
+        Ok(())
+    }
+
+    /// Returns the `PATH` cmake runs with, if the [environment](Cmd::env)
+    /// changes the one of this process. The tools are looked up on it.
+    fn search_path(&self) -> Option<std::ffi::OsString> {
+        let is_path = |name: &str| {
+            if cfg!(windows) {
+                name.eq_ignore_ascii_case("PATH")
+            } else {
+                name == "PATH"
+            }
+        };
+        match self.environment.iter().rfind(|(name, _)| is_path(name)) {
+            Some((_, value)) => Some(value.clone().unwrap_or_default().into()),
+            None => self.environment_cleared.then(Default::default),
+        }
+    }
+
+    /// Checks `cmake_program` against the [required version](Cmd::require_version).
+    fn check_version(&self, cmake_program: &Path) -> Result<(), Error> {
+        let Some(requirement) = &self.required_version else {
+            return Ok(());
+        };
+        let version = cmake::version_of(cmake_program)
+            .as_deref()
+            .and_then(CMakeVersion::parse)
+            .ok_or_else(|| {
+                Error::ToolFailed(format!(
+                    "cannot read the version of {}",
+                    cmake_program.display()
+                ))
+            })?;
+        if !version.matches(requirement)? {
+            return Err(Error::Unsupported(format!(
+                "cmake {} does not satisfy the required version {}",
+                version, requirement
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns `--fresh` if configure discards the cache and `cmake_program`
+    /// supports the option (CMake 3.24 and later).
+    fn fresh_args(&self, cmake_program: &Path) -> Vec<String> {
+        let supported = cmake::parsed_version_of(cmake_program)
+            .is_some_and(|version| version.supports(Capability::Fresh));
+        if self.fresh && self.steps.configures() && supported {
+            vec!["--fresh".to_string()]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Returns the configure arguments setting the [log level](Cmd::log_level)
+    /// and [verbosity](Cmd::verbose) for cmake `version`, and writing the
+    /// [target graph](Cmd::emit_graphviz) and the [trace](Cmd::trace) of
+    /// the build tree `binary_path`.
+    fn diagnostic_args(&self, binary_path: &Path, version: Option<CMakeVersion>) -> Vec<String> {
+        let supports = |capability| version.is_none_or(|version| version.supports(capability));
+        let mut args = Vec::new();
+        if let Some(level) = self.log_level {
+            if !self.quiet_configure {
+                args.extend(log_level_args(version, level.as_str()));
+            }
+            if supports(Capability::LogContext) {
+                args.push("--log-context".to_string());
+            }
+        }
+        if self.verbose && !supports(Capability::BuildVerbose) {
+            args.push("-DCMAKE_VERBOSE_MAKEFILE=ON".to_string());
+        }
+        if let Some(graphviz) = &self.graphviz {
+            args.push(format!("--graphviz={}", graphviz.display()));
+        }
+        if let Some(format) = self.trace {
+            args.extend(format.args(binary_path));
+        }
+        args
+    }
+
+    /// Checks that `cmake_program` has the options the run needs and that
+    /// have no older equivalent, see [`Capability`].
+    fn check_capabilities(&self, cmake_program: &Path) -> Result<(), Error> {
+        let version = cmake::parsed_version_of(cmake_program);
+        let mut required = Vec::new();
+        if self.steps.configures() {
+            required.push(Capability::SourceAndBinaryDir);
+            if self.preset.is_some() {
+                required.push(Capability::ConfigurePresets);
+            }
+            if self.trace.is_some() {
+                required.push(Capability::Trace);
+            }
+        }
+        if self.steps != Steps::ConfigureOnly {
+            if self.build_preset.is_some() {
+                required.push(Capability::BuildPresets);
+            }
+            if self.targets.len() > 1 && self.parallel_targets.is_none() {
+                required.push(Capability::MultipleTargets);
+            }
+            if self.install_prefix.is_some() {
+                required.push(Capability::Install);
+            }
+        }
+        required
+            .into_iter()
+            .try_for_each(|capability| capability.require(version))
+    }
+
+    /// Checks that [`clean_first`](Cmd::clean_first) is not combined with
+    /// per-target build invocations.
+    fn check_clean_first(&self) -> Result<(), Error> {
+        if self.clean_first && self.parallel_targets.is_some() {
+            return Err(Error::InvalidConfig(
+                "clean_first cannot be combined with build_targets_parallel".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns whether the run has been cancelled.
+    fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// Reports the cancellation of `phase`, which was running or about to
+    /// start, and returns the error carrying the `report` collected so far.
+    fn cancelled(&self, phase: Phase, report: BuildReport) -> Error {
+        if let Some(emitter) = &self.machine_output {
+            machine::cancelled(emitter, phase);
+        }
+        Error::cancelled(&format!("cmake {}", phase), report)
+    }
+
+    /// Returns the watchdog of `phase`, if one is set, with the
+    /// [timeout](Cmd::set_timeout) and [keepalive](Cmd::keepalive) added
+    /// unless it has its own.
+    fn watchdog(&self, phase: Phase) -> Option<Watchdog> {
+        let mut watchdog = self
+            .watchdogs
+            .iter()
+            .find(|(watched, _)| *watched == phase)
+            .map(|(_, watchdog)| watchdog.clone());
+        if let Some((_, timeout)) = self.timeouts.iter().find(|(limited, _)| *limited == phase)
+            && !watchdog.as_ref().is_some_and(Watchdog::has_timeout)
+        {
+            watchdog = Some(watchdog.unwrap_or_default().timeout(*timeout));
+        }
+        match &self.keepalive {
+            Some((interval, message))
+                if !watchdog.as_ref().is_some_and(Watchdog::has_keepalive) =>
+            {
+                Some(
+                    watchdog
+                        .unwrap_or_default()
+                        .keepalive(*interval, message.clone()),
+                )
+            }
+            _ => watchdog,
+        }
+    }
+
+    /// Returns the redactor for the resolved credentials and redaction patterns.
+    fn redactor(&self) -> Redactor {
+        self.resolved_credentials.redactor(&self.redactions)
+    }
+
+    /// Runs one attempt of the build step.
+    ///
+    /// Without per-target splitting this is a single `cmake --build`
+    /// invocation, building `targets` or, if empty, the default target. With
+    /// [`build_targets_parallel`](Cmd::build_targets_parallel), one
+    /// `cmake --build --target <t>` per target runs concurrently, each with its
+    /// share of the `jobs` budget, and no more than `jobs` at a time.
+    ///
+    /// Returns [`Error::Panicked`] if a thread running an invocation panics.
+    fn run_build_step(
+        &self,
+        targets: &[String],
+        native_args: &[String],
+        jobs: Option<usize>,
+    ) -> Result<BuildAttempt, Error> {
+        let redactor = &self.redactor();
+        let commands = self.build_commands(targets, native_args, jobs);
+        let count = commands.len();
+        let queue = std::sync::Mutex::new(commands.into_iter().enumerate());
+
+        let results = thread::scope(|scope| {
+            let handles: Vec<_> = (0..jobs.unwrap_or(count).clamp(1, count.max(1)))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut results = Vec::new();
+                        loop {
+                            let next = queue.lock().map(|mut queue| queue.next());
+                            let Ok(Some((index, (target, prepared)))) = next else {
+                                return results;
+                            };
+                            let mut command = prepared.to_command();
+                            if let Some(emitter) = &self.machine_output {
+                                machine::phase_started(emitter, Phase::Build, target);
+                            }
+                            let on_line =
+                                self.output_handler(Phase::Build, target.map(str::to_string));
+
+                            let start = Instant::now();
+                            let started_at = SystemTime::now();
+                            let output = self.run_step(
+                                &mut command,
+                                Phase::Build,
+                                on_line.as_ref(),
+                                redactor,
+                            );
+                            results.push((
+                                index,
+                                target,
+                                prepared,
+                                started_at,
+                                start.elapsed(),
+                                output,
+                            ));
+                        }
+                    })
+                })
+                .collect();
+
+            // every thread is joined, so that a panic doesn't propagate out of the scope
+            let joined: Vec<_> = handles.into_iter().map(|handle| handle.join()).collect();
+            joined
+                .into_iter()
+                .map(|results| results.map_err(|_| Error::Panicked))
+                .collect::<Result<Vec<_>, Error>>()
+        });
+        let mut results: Vec<_> = results?.into_iter().flatten().collect();
+        results.sort_by_key(|(index, ..)| *index);
+
+        let mut attempt = BuildAttempt::default();
+        for (_, target, command, started_at, duration, output) in results {
+            let output = output?;
+            let exit = ProcessExit::from(output.status);
+            if attempt.exit.success() {
+                attempt.exit = exit;
+            }
+            attempt.stdout.push_str(&output.stdout);
+            attempt.stderr.push_str(&output.stderr);
+            attempt.cancelled |= output.cancelled;
+            attempt.killed_by = attempt.killed_by.or(output.killed_by);
+            let timing = PhaseTiming {
+                phase: Phase::Build,
+                target: target.map(str::to_string),
+                command,
+                started_at,
+                duration,
+                exit,
+                stdout: output.stdout,
+                stderr: output.stderr,
+            };
+            if let Some(emitter) = &self.machine_output {
+                machine::phase_finished(emitter, &timing);
+            }
+            attempt.phases.push(timing);
+        }
+
+        Ok(attempt)
+    }
+}
+
+/// The combined outcome of the `cmake --build` invocations of one build attempt.
+struct BuildAttempt {
+    /// One entry per invocation.
+    phases: Vec<PhaseTiming>,
+    stdout: String,
+    stderr: String,
+    /// The exit of the first failed invocation, or a success if none failed.
+    exit: ProcessExit,
+    cancelled: bool,
+    /// Why the watchdog killed an invocation, if it did.
+    killed_by: Option<Trigger>,
+}
+
+impl Default for BuildAttempt {
+    fn default() -> Self {
+        Self {
+            phases: Vec::new(),
+            stdout: String::new(),
+            stderr: String::new(),
+            exit: ProcessExit {
+                code: Some(0),
+                ..Default::default()
+            },
+            cancelled: false,
+            killed_by: None,
+        }
+    }
+}
+
+/// What a run settles before its first command, see [`Cmd::set_up`].
+struct RunSetup {
+    /// The resolved configure preset, if one is set.
+    preset: Option<ResolvedPreset>,
+    /// The source directory configure reads: the sources, or their copy if
+    /// they are read-only.
+    source_dir: PathBuf,
+    /// Path prefixes rewritten in reported locations, as canonical
+    /// `(from, to)` pairs.
+    remaps: Vec<(PathBuf, PathBuf)>,
+    /// Whether the run reuses the cache of a configured tree.
+    cache_reused: bool,
+    /// The initial cache script of a seeded fresh tree.
+    seed_script: Option<PathBuf>,
+    /// The lock on the build directory, held for the whole run.
+    _build_dir_lock: Option<DirLock>,
+    /// The lock on the shared fetch cache, held until configure finishes.
+    fetch_cache_lock: Option<DirLock>,
+    /// The remote `FetchContent` dependencies the project declares, if a
+    /// fetch cache is set.
+    fetch_declared: Vec<offline::Declared>,
+}
+
+/// The arguments of a configure, grouped by where they come from, see
+/// [`Cmd::configure_args`].
+struct ConfigureArgs<'a> {
+    /// The version of the cmake that runs, if it can be read.
+    cmake_version: Option<CMakeVersion>,
+    fresh: Vec<String>,
+    seed: Vec<String>,
+    preset: Vec<String>,
+    generator: Vec<String>,
+    /// The user's defines, with their `-D` arguments.
+    defines: Vec<(&'a Defination, String)>,
+    toolchain_file: Vec<String>,
+    cross: Vec<String>,
+    output_path: Vec<String>,
+    fetch_cache: Vec<String>,
+    diagnostic: Vec<String>,
+}
+
+impl ConfigureArgs<'_> {
+    /// Returns the definitions cmakr derives from the builder's settings,
+    /// which are ordered against the user's by the [`ArgOrder`].
+    fn derived(&self) -> [&Vec<String>; 4] {
+        [
+            &self.toolchain_file,
+            &self.cross,
+            &self.output_path,
+            &self.fetch_cache,
+        ]
+    }
+}
+
+/// Splits a budget of `jobs` as evenly as possible between `count` invocations,
+/// giving each at least one job.
+///
+/// With more invocations than jobs, each gets one job, and
+/// [`Cmd::run_build_step`] runs at most `jobs` of them at a time.
+fn split_jobs(jobs: usize, count: usize) -> Vec<usize> {
+    let base = jobs / count.max(1);
+    let remainder = jobs % count.max(1);
+    (0..count)
+        .map(|i| (base + usize::from(i < remainder)).max(1))
+        .collect()
+}
+
+/// Returns the arguments setting the configure log level, spelled
+/// `--loglevel` on CMake 3.15 and omitted before, which has no log levels.
+fn log_level_args(version: Option<CMakeVersion>, level: &str) -> Vec<String> {
+    match version {
+        Some(version) if !version.supports(Capability::LogLevel) => {
+            if version >= CMakeVersion::new(3, 15, 0) {
+                vec![format!("--loglevel={}", level)]
+            } else {
+                Vec::new()
+            }
+        }
+        _ => vec![format!("--log-level={}", level)],
+    }
+}
+
+/// Returns the native build tool arguments that make `generator` keep going
+/// after a failed job, or nothing if the generator has no such switch.
+fn keep_going_args(generator: &str) -> Vec<String> {
+    if generator.starts_with("Ninja") {
+        vec!["-k".to_string(), "0".to_string()]
+    } else if generator.ends_with("Makefiles") {
+        vec!["-k".to_string()]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Describes why the command of `step` failed, or why its watchdog killed it.
+fn step_failure_message(step: &str, exit: ProcessExit, killed_by: Option<Trigger>) -> String {
+    match killed_by {
+        Some(trigger) => format!("cmake {} killed by watchdog: {}", step, trigger),
+        None => format!("cmake {} failed: {}", step, exit),
+    }
+}
+
+/// Lists the files directly inside `dir`, including symlinks to files, sorted by path.
+fn list_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        // symlinks count too, e.g. `libfoo.so -> libfoo.so.1.2.3`
+        let path = entry?.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Returns the generator selected with `-G` in `args`.
+fn generator_arg(args: &[String]) -> Option<String> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "-G" {
+            return args.next().cloned();
+        }
+        if let Some(generator) = arg.strip_prefix("-G") {
+            return Some(generator.to_string());
+        }
+    }
+    None
+}
+
+/// Writes a generated toolchain file into the state directory of the build
+/// tree at `binary_dir`, and returns its path.
+fn write_generated_toolchain(
+    toolchain: &ToolchainFile,
+    binary_dir: &Path,
+) -> std::io::Result<PathBuf> {
+    let path = generated_toolchain_path(binary_dir);
+    std::fs::create_dir_all(binary_dir.join(gc::STATE_DIR))?;
+    std::fs::write(&path, toolchain.contents())?;
+    Ok(path)
+}
+
+/// Returns the path of the generated toolchain file of the build tree at
+/// `binary_dir`.
+fn generated_toolchain_path(binary_dir: &Path) -> PathBuf {
+    binary_dir.join(gc::STATE_DIR).join("toolchain.cmake")
+}
+
+/// Returns whether `generator` selects the build type at build time with
+/// `--config` instead of at configure time with `CMAKE_BUILD_TYPE`.
+fn is_multi_config(generator: &str) -> bool {
+    generator.starts_with("Visual Studio")
+        || generator == "Xcode"
+        || generator.ends_with("Ninja Multi-Config")
+}
+
+/// Returns the build tools a generator runs, any one of which must be
+/// installed, or `None` if the generator is not checked.
+fn build_tools(generator: &str) -> Option<&'static [&'static str]> {
+    if generator.ends_with("Ninja") || generator.ends_with("Ninja Multi-Config") {
+        Some(&["ninja", "ninja-build"])
+    } else if generator.ends_with("Unix Makefiles") {
+        Some(&["make", "gmake"])
+    } else {
+        None
+    }
+}
+
+/// Ensures a directory exists, creating it (and any parent directories) if necessary.
+///
+/// # Errors
+///
+/// Returns an I/O error if directory creation fails.
+fn check_dir_exists_and_create(path: &Path) -> std::io::Result<()> {
+    if !path.exists() {
+        std::fs::create_dir_all(path)?;
+    }
+    Ok(())
+}
+
+/// Converts a path to a normalized string, stripping the Windows `\\?\` extended-length
+/// prefix if present. This is necessary because some tools (e.g., GCC's linker) do not
+/// recognize UNC-style paths produced by [`std::path::Path::canonicalize`] on Windows.
+/// Returns a build directory to use instead of `binary_dir` if it is
+/// `source_dir` itself or a directory of the source tree with a
+/// `CMakeLists.txt`.
+fn in_source_build(source_dir: &Path, binary_dir: &Path) -> Option<PathBuf> {
+    let source = source_dir.canonicalize().ok()?;
+    let binary = binary_dir
+        .canonicalize()
+        .or_else(|_| std::path::absolute(binary_dir))
+        .ok()?;
+    if binary != source && !(binary.starts_with(&source) && binary.join("CMakeLists.txt").is_file())
+    {
+        return None;
+    }
+    let nested = source.join("build");
+    if nested != binary && !nested.join("CMakeLists.txt").exists() {
+        return Some(nested);
+    }
+    let name = source.file_name()?.to_string_lossy();
+    Some(source.with_file_name(format!("{}-build", name)))
+}
+
+/// Returns the message of a build with warnings that are not in the
+/// `baseline` file, listing them, or `None` if there are none.
+fn new_warnings(baseline: &Path, report: &BuildReport) -> std::io::Result<Option<String>> {
+    let baseline = match diagnostics::load(baseline, &report.source_dir) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        baseline => baseline?,
+    };
+    let warnings: Vec<diagnostics::Diagnostic> = report.warnings().cloned().collect();
+    let new = diagnostics::new_since(&baseline, &warnings, &report.source_dir);
+    if new.is_empty() {
+        return Ok(None);
+    }
+    let mut message = format!("{} new warnings", new.len());
+    for warning in new {
+        message.push_str(&format!("\n  {}", warning.file.display()));
+        if let Some(line) = warning.line {
+            message.push_str(&format!(":{}", line));
+        }
+        message.push_str(&format!(": {}", warning.message));
+        if let Some(code) = &warning.code {
+            message.push_str(&format!(" [{}]", code));
+        }
+    }
+    Ok(Some(message))
+}
+
+/// Locks `dir`, waiting at most `timeout` for another process to release it.
+///
+/// A lock still held after `timeout` is [`Error::BuildDirLocked`], naming
+/// the holder; other failures are I/O errors naming the directory.
+fn lock_dir(dir: &Path, timeout: Option<Duration>) -> Result<DirLock, Error> {
+    DirLock::acquire_within(dir, timeout).map_err(|e| match e.kind() {
+        std::io::ErrorKind::TimedOut => Error::BuildDirLocked(e.to_string()),
+        kind => Error::Io(std::io::Error::new(
+            kind,
+            format!("cannot lock {}: {}", dir.display(), e),
+        )),
+    })
+}
+
+/// Returns `name` with `kind` as its cache type, unless it has one.
+fn typed_name(name: String, kind: &str) -> String {
+    if name.contains(':') {
+        name
+    } else {
+        format!("{}:{}", name, kind)
+    }
+}
+
+/// Returns `path` as a string, without the `\\?\` prefix of Windows
+/// verbatim paths, which cmake and most tools cannot read.
+fn normalize_path(path: &std::path::Path) -> String {
+    let s = path.to_str().unwrap_or_default();
+    match s.strip_prefix(r"\\?\UNC\") {
+        Some(share) => format!(r"\\{}", share),
+        None => s.strip_prefix(r"\\?\").unwrap_or(s).to_string(),
+    }
+}
+
+/// Returns `path` as the value of a `-D` define or a CMake script: with
+/// forward slashes, which CMake never reads as escape sequences.
+fn forward_slash_path(path: &std::path::Path) -> String {
+    normalize_path(path).replace('\\', "/")
+}
+
+impl TryFrom<CmdConfig> for Cmd {
+    type Error = Error;
+
+    /// Builds a [`Cmd`] from a config.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a define cannot be passed to cmake (see
+    /// [`Cmd::add_define`]), unless `raw_defines` is set.
+    fn try_from(config: CmdConfig) -> Result<Self, Self::Error> {
+        let mut cmd = Cmd::default()
+            .keep_going(config.keep_going)
+            .raw_defines(config.raw_defines)
+            .enable_lto(config.lto)
+            .set_prefix_path(config.prefix_path);
+        cmd.cxx_standard = config.cxx_standard;
+        cmd.shared_libs = config.shared_libs;
+        for sanitizer in config.sanitizers {
+            cmd = cmd.enable_sanitizer(sanitizer);
+        }
+        cmd.args = config.args;
+        cmd.configure_args = config.configure_args;
+        cmd.build_args = config.build_args;
+        cmd.native_build_args = config.native_build_args;
+        cmd.targets = config.targets;
+        cmd.jobs = config.jobs.map(|jobs| jobs.max(1));
+        if let Some(path) = config.path {
+            cmd = cmd.set_path(path);
+        }
+        if let Some(path) = config.binary_path {
+            cmd = cmd.set_binary_path(path);
+        }
+        if let Some(path) = config.output_path {
+            cmd = cmd.set_output_path(path);
+        }
+        if let Some(path) = config.runtime_output_path {
+            cmd = cmd.set_runtime_output_path(path);
+        }
+        if let Some(path) = config.library_output_path {
+            cmd = cmd.set_library_output_path(path);
+        }
+        if let Some(path) = config.archive_output_path {
+            cmd = cmd.set_archive_output_path(path);
+        }
+        if let Some(preset) = config.preset {
+            cmd = cmd.set_preset(preset);
+        }
+        if let Some(generator) = config.generator {
+            cmd = cmd.set_generator(generator);
+        }
+        if let Some(platform) = config.generator_platform {
+            cmd = cmd.set_generator_platform(platform);
+        }
+        if let Some(toolset) = config.generator_toolset {
+            cmd = cmd.set_generator_toolset(toolset);
+        }
+        cmd = cmd.fallback_generators(config.fallback_generators);
+        if let Some(preset) = config.build_preset {
+            cmd = cmd.set_build_preset(preset);
+        }
+        if let Some(build_type) = config.build_type {
+            cmd = cmd.set_build_type(build_type);
+        }
+        if let Some(build_config) = config.config {
+            cmd = cmd.set_config(build_config);
+        }
+        if let Some(preset) = config.test_preset {
+            cmd = cmd.set_test_preset(preset);
+        }
+        if let Some(path) = config.toolchain_file {
+            cmd = cmd.set_toolchain_file(path);
+        }
+        if let Some(name) = config.system_name {
+            cmd = cmd.set_system_name(name);
+        }
+        if let Some(processor) = config.system_processor {
+            cmd = cmd.set_system_processor(processor);
+        }
+        if let Some(path) = config.sysroot {
+            cmd = cmd.set_sysroot(path);
+        }
+        if let Some(path) = config.staging_prefix {
+            cmd = cmd.set_staging_prefix(path);
+        }
+        if let Some(path) = config.fetch_cache_dir {
+            cmd = cmd.set_fetch_cache_dir(path);
+        }
+        if let Some(path) = config.compiler_cache_dir {
+            cmd = cmd.set_compiler_cache_dir(path);
+        }
+        if let Some(launcher) = config.compiler_launcher {
+            let Ok(launcher) = launcher.parse();
+            cmd = cmd.with_compiler_launcher(launcher);
+        }
+        if let Some(root) = config.short_build_root {
+            cmd = cmd.set_short_build_root(root);
+        }
+        cmd = cmd.short_build_dir(config.short_build_dir);
+        if let Some(prefix) = config.install_prefix {
+            cmd = cmd.set_install_prefix(prefix);
+        }
+        cmd.install_components = config.install_components;
+        if config.env_clear {
+            cmd = cmd.env_clear();
+        }
+        for name in config.env_remove {
+            cmd = cmd.env_remove(name);
+        }
+        cmd = cmd.envs(config.env);
+        for (name, value) in config.defines {
+            let define = Defination {
+                name,
+                value,
+                once: false,
+            };
+            define
+                .to_arg(config.raw_defines)
+                .map_err(Error::InvalidDefine)?;
+            cmd.defines.push(define);
+        }
+        Ok(cmd)
+    }
+}
+
+impl FromStr for Cmd {
+    type Err = Error;
+
+    /// Parses a [`CmdConfig`] in JSON and builds a [`Cmd`] from it.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let config: CmdConfig = serde_json::from_str(s)?;
+        Cmd::try_from(config)
+    }
+}
+
+/// Returns whether the configure of a fresh tree failed because its generator,
+/// build tool, or toolset is unavailable.
+fn generator_unavailable(failure: &BuildFailure) -> bool {
+    let report = failure.report();
+    !report.cache_reused
+        && report.phases.last().is_some_and(|timing| {
+            cmake::generator_unavailable(&timing.stdout)
+                || cmake::generator_unavailable(&timing.stderr)
+        })
+}
+
+/// Returns the default root of short build directories: `\\bld` on the
+/// system drive on Windows, `cmakr-bld` in the temporary directory elsewhere.
+fn default_short_build_root() -> PathBuf {
+    if cfg!(windows) {
+        let drive = std::env::var("SystemDrive").unwrap_or_else(|_| "C:".to_string());
+        PathBuf::from(format!("{}\\bld", drive))
+    } else {
+        std::env::temp_dir().join("cmakr-bld")
+    }
+}
+
+/// Returns whether `path` names a TOML file.
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "toml")
+}
+
+/// Returns `value` without nulls, `false`, and empty arrays and objects, which
+/// are the defaults of a [`CmdConfig`].
+fn compact(value: serde_json::Value) -> serde_json::Value {
+    use serde_json::Value;
+
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name, compact(value)))
+                .filter(|(_, value)| match value {
+                    Value::Null | Value::Bool(false) => false,
+                    Value::Array(items) => !items.is_empty(),
+                    Value::Object(fields) => !fields.is_empty(),
+                    _ => true,
+                })
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+/// Returns the defines left under `policy`, with their `-D` arguments.
+fn define_args(
+    defines: &[Defination],
+    policy: DefinePolicy,
+    raw: bool,
+) -> Result<Vec<(&Defination, String)>, Error> {
+    dedup_defines(defines, policy)
+        .and_then(|defines| {
+            defines
+                .into_iter()
+                .map(|d| d.to_arg(raw).map(|arg| (d, arg)))
+                .collect::<Result<Vec<_>, String>>()
+        })
+        .map_err(Error::InvalidDefine)
+}
+
+/// Removes the defines passed [once](Cmd::add_define_once) whose variable
+/// the cache of `binary_dir` has already.
+fn retain_uncached_defines(define_args: &mut Vec<(&Defination, String)>, binary_dir: &Path) {
+    define_args.retain(|(d, _)| {
+        let name = d.name.split(':').next().unwrap_or_default();
+        !d.once || cmake::read_cache_value(binary_dir, name).is_none()
+    });
+}
+
+/// Resolves repeated definitions of the same variable according to `policy`.
+///
+/// # Errors
+///
+/// With [`DefinePolicy::Error`], returns an error naming the first variable
+/// that is defined more than once.
+fn dedup_defines(defines: &[Defination], policy: DefinePolicy) -> Result<Vec<&Defination>, String> {
+    let key = |d: &Defination| d.name.split(':').next().unwrap_or_default().to_string();
+    let mut kept: Vec<&Defination> = Vec::with_capacity(defines.len());
+    for define in defines {
+        let Some(i) = kept.iter().position(|k| key(k) == key(define)) else {
+            kept.push(define);
+            continue;
+        };
+        match policy {
+            DefinePolicy::LastWins => {
+                kept.remove(i);
+                kept.push(define);
+            }
+            DefinePolicy::FirstWins => {}
+            DefinePolicy::Error => {
+                return Err(format!("{} is defined more than once", key(define)));
+            }
+        }
+    }
+    Ok(kept)
+}
+
+/// Redacts secrets from the commands of a finished run's report and from
+/// its error message.
+fn scrub(result: ExecResult, redactor: &Redactor) -> ExecResult {
+    match result {
+        Ok(mut report) => {
+            report.redact(redactor);
+            Ok(report)
+        }
+        Err(e) => Err(e.redact(redactor)),
+    }
+}
+
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    fn execute_cmake() {
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_preset("default")
+            .build();
+
+        assert_eq!(cmd.is_ok(), true);
+    }
+
+    #[test]
+    fn capture_phase_output() {
+        let temp = crate::guard::TempBuildDir::new("output").unwrap();
+        let report = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().to_string_lossy())
+            .build_with_output()
+            .unwrap();
+
+        let configure = &report.phases[0];
+        assert_eq!(configure.phase, Phase::Configure);
+        assert!(configure.stdout.lines().any(|line| line.starts_with("-- ")));
+    }
+
+    #[test]
+    fn toolchain_file_conflicts() {
+        let cmd = Cmd::default()
+            .set_toolchain_file("test/toolchain.cmake")
+            .add_define("CMAKE_TOOLCHAIN_FILE:FILEPATH", "other.cmake");
+        fn define_args(cmd: &Cmd) -> Vec<(&Defination, String)> {
+            cmd.defines
+                .iter()
+                .map(|d| (d, d.to_arg(false).unwrap()))
+                .collect()
+        }
+
+        let mut args = define_args(&cmd);
+        let toolchain = cmd.toolchain_file_args(None, &mut args);
+        assert!(
+            toolchain
+                .unwrap()
+                .last()
+                .unwrap()
+                .ends_with("toolchain.cmake")
+        );
+        assert!(args.is_empty());
+
+        let cmd = cmd.set_define_policy(DefinePolicy::FirstWins);
+        let mut args = define_args(&cmd);
+        let toolchain = cmd.toolchain_file_args(None, &mut args);
+        assert!(toolchain.unwrap().is_empty());
+        assert_eq!(args.len(), 1);
+
+        let cmd = cmd.set_define_policy(DefinePolicy::Error);
+        let mut args = define_args(&cmd);
+        let toolchain = cmd.toolchain_file_args(None, &mut args);
+        assert!(matches!(toolchain, Err(Error::InvalidDefine(_))));
+    }
+
+    #[test]
+    fn install_components_after_build() {
+        let temp = crate::guard::TempBuildDir::new("install").unwrap();
+        let prefix = temp.path().join("prefix");
+        let report = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().join("build").to_string_lossy())
+            .set_install_prefix(prefix.to_string_lossy())
+            .add_install_component("runtime")
+            .add_install_component("dev")
+            .build()
+            .unwrap();
+
+        let installs: Vec<&PhaseTiming> = report
+            .phases
+            .iter()
+            .filter(|timing| timing.phase == Phase::Install)
+            .collect();
+        assert_eq!(installs.len(), 2);
+        assert_eq!(installs[0].target.as_deref(), Some("runtime"));
+        assert!(installs[1].command.args.ends_with(&[
+            "--prefix".to_string(),
+            normalize_path(&prefix.canonicalize().unwrap()),
+            "--component".to_string(),
+            "dev".to_string(),
+        ]));
+        assert!(prefix.is_dir());
+    }
+
+    #[test]
+    fn install_components_of_enabled_features() {
+        let mut env = crate::guard::ScopedEnv::new();
+        // SAFETY: no other test uses these variables
+        unsafe {
+            env.set("CARGO_FEATURE_CMAKR_TEST_TOOLS", "1");
+            env.remove("CARGO_FEATURE_CMAKR_TEST_DOCS");
+        }
+        let plan = Cmd::default()
+            .set_path("./test/")
+            .set_install_prefix("/tmp/prefix")
+            .add_install_component("runtime")
+            .install_components_for_features([
+                ("cmakr-test-tools", "cli-bins"),
+                ("cmakr-test-docs", "docs"),
+                ("cmakr-test-tools", "runtime"),
+            ])
+            .dry_run()
+            .unwrap();
+
+        let components: Vec<&str> = plan
+            .commands
+            .iter()
+            .filter_map(|command| {
+                let at = command.args.iter().position(|arg| arg == "--component")?;
+                command.args.get(at + 1).map(String::as_str)
+            })
+            .collect();
+        assert_eq!(components, ["runtime", "cli-bins"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn configure_and_build_separately() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("steps").unwrap();
+        let build = temp.path().join("build");
+        let cmake = temp.path().join("cmake");
+        std::fs::write(
+            &cmake,
+            format!(
+                "#!/bin/sh\n[ \"$1\" = --version ] && echo 'cmake version 3.28.1' && exit 0\n\
+                 [ \"$1\" = --build ] && exit 0\n\
+                 mkdir -p {0}\n\
+                 echo 'CMAKE_GENERATOR:INTERNAL=Unix Makefiles' > {0}/CMakeCache.txt\n",
+                build.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&cmake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(build.to_string_lossy())
+            .set_cmake_executable(cmake.to_string_lossy())
+            .add_configure_arg("-Wno-dev")
+            .add_build_arg("--verbose")
+            .add_native_build_arg("-k0");
+        let error = cmd.clone().build_only().unwrap_err();
+        assert!(matches!(error, Error::InvalidConfig(_)));
+
+        let report = cmd.clone().configure().unwrap();
+        assert_eq!(report.phases.len(), 1);
+        assert_eq!(report.phases[0].phase, Phase::Configure);
+        let configure = &report.phases[0].command.args;
+        assert!(configure.contains(&"-Wno-dev".to_string()));
+        assert!(!configure.contains(&"--verbose".to_string()));
+
+        let report = cmd.build_only().unwrap();
+        assert!(
+            report
+                .phases
+                .iter()
+                .all(|timing| timing.phase == Phase::Build)
+        );
+        let build = &report.phases[0].command.args;
+        assert!(build.contains(&"--verbose".to_string()));
+        assert!(!build.contains(&"-Wno-dev".to_string()));
+        assert!(build.ends_with(&["--".to_string(), "-k0".to_string()]));
+    }
+
+    #[test]
+    fn build_selected_targets_in_parallel() {
+        let temp = crate::guard::TempBuildDir::new("targets").unwrap();
+        let report = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().to_string_lossy())
+            .add_target("test_lib")
+            .add_target("test_app")
+            .set_jobs(4)
+            .build()
+            .unwrap();
+        assert_eq!(report.parallel_jobs, Some(4));
+        let build = &report.phases.last().unwrap().command.args;
+        let target = build.iter().position(|arg| arg == "--target").unwrap();
+        assert_eq!(
+            build[target..target + 5],
+            ["--target", "test_lib", "test_app", "--parallel", "4"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn build_type_by_generator() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("config").unwrap();
+        let cmake = temp.path().join("cmake");
+        // configures like cmake would: the cache records the generator it was given
+        std::fs::write(
+            &cmake,
+            "#!/bin/sh\n[ \"$1\" = --version ] && echo 'cmake version 3.28.1' && exit 0\n\
+             [ \"$1\" = --build ] && exit 0\n\
+             generator='Unix Makefiles'\n\
+             while [ $# -gt 0 ]; do\n\
+             case $1 in -G) generator=$2;; -B) build=$2;; esac\n\
+             shift\n\
+             done\n\
+             mkdir -p \"$build\"\n\
+             echo \"CMAKE_GENERATOR:INTERNAL=$generator\" > \"$build/CMakeCache.txt\"\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&cmake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_cmake_executable(cmake.to_string_lossy())
+            .set_build_type("Release");
+        let report = cmd
+            .clone()
+            .set_binary_path(temp.path().join("single").to_string_lossy())
+            .set_generator("Unix Makefiles")
+            .build()
+            .unwrap();
+        assert!(
+            report.phases[0]
+                .command
+                .args
+                .contains(&"-DCMAKE_BUILD_TYPE=Release".to_string())
+        );
+        assert!(
+            !report.phases[1]
+                .command
+                .args
+                .contains(&"--config".to_string())
+        );
+
+        // a multi-config tree gets the build type at build time
+        let report = cmd
+            .set_binary_path(temp.path().join("multi").to_string_lossy())
+            .set_generator("Ninja Multi-Config")
+            .build()
+            .unwrap();
+        assert!(
+            !report.phases[0]
+                .command
+                .args
+                .contains(&"-DCMAKE_BUILD_TYPE=Release".to_string())
+        );
+        let build = &report.phases[1].command.args;
+        let config = build.iter().position(|arg| arg == "--config").unwrap();
+        assert_eq!(build[config + 1], "Release");
+        assert!(is_multi_config("Visual Studio 17 2022"));
+        assert!(!is_multi_config("Unix Makefiles"));
+    }
+
+    #[test]
+    fn detect_in_source_build() {
+        let temp = crate::guard::TempBuildDir::new("in-source").unwrap();
+        let source = temp.path().canonicalize().unwrap();
+        std::fs::write(source.join("CMakeLists.txt"), "").unwrap();
+        std::fs::create_dir(source.join("lib")).unwrap();
+        std::fs::write(source.join("lib/CMakeLists.txt"), "").unwrap();
+
+        assert_eq!(in_source_build(&source, &source.join("build")), None);
+        assert_eq!(
+            in_source_build(&source, &source.join("lib")),
+            Some(source.join("build"))
+        );
+
+        let cmd = Cmd::default()
+            .set_path(source.to_string_lossy())
+            .set_binary_path(source.to_string_lossy())
+            .set_in_source_policy(InSourcePolicy::Error);
+        assert!(matches!(cmd.build(), Err(Error::InvalidConfig(_))));
+        assert!(!source.join("CMakeCache.txt").exists());
+    }
+
+    #[test]
+    fn control_environment() {
+        use std::ffi::OsStr;
+
+        let cmd = Cmd::default()
+            .env("CC", "gcc")
+            .envs([("CC", "clang"), ("CXX", "clang++")])
+            .env_remove("CFLAGS");
+        let mut command = std::process::Command::new("cmake");
+        cmd.apply_environment(&mut command);
+        let envs: Vec<_> = command.get_envs().collect();
+        assert_eq!(
+            envs,
+            [
+                (OsStr::new("CC"), Some(OsStr::new("clang"))),
+                (OsStr::new("CFLAGS"), None),
+                (OsStr::new("CXX"), Some(OsStr::new("clang++"))),
+            ]
+        );
+
+        // a cleared environment still finds cmake with PATH set again
+        let report = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path("./build")
+            .set_output_path("./bin")
+            .env_clear()
+            .env("PATH", std::env::var("PATH").unwrap())
+            .build();
+        assert!(report.is_ok());
+    }
+
+    #[test]
+    fn export_vscode_settings() {
+        let temp = crate::guard::TempBuildDir::new("vscode").unwrap();
+        Cmd::embedded(crate::embedded::EmbeddedToolchain::RiscvNoneElf {
+            arch: "rv32imac_zicsr".to_string(),
+            abi: "ilp32".to_string(),
+        })
+        .set_path("./test/")
+        .set_binary_path(temp.path().join("build").to_string_lossy())
+        .set_generator("Ninja")
+        .add_define("BUILD_TESTING", "OFF")
+        .add_build_arg("--verbose")
+        .export_vscode(temp.path())
+        .unwrap();
+
+        let read = |name: &str| -> serde_json::Value {
+            let path = temp.path().join(".vscode").join(name);
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+        };
+        let settings = read("settings.json");
+        assert_eq!(settings["cmake.generator"], "Ninja");
+        assert_eq!(settings["cmake.configureSettings"]["BUILD_TESTING"], "OFF");
+        assert_eq!(settings["cmake.buildArgs"], json!(["--verbose"]));
+        let kit = &read("cmake-kits.json")[0];
+        assert_eq!(kit["name"], "cmakr");
+        assert_eq!(kit["preferredGenerator"]["name"], "Ninja");
+        let toolchain_file = PathBuf::from(kit["toolchainFile"].as_str().unwrap());
+        assert!(toolchain_file.is_file());
+    }
+
+    #[test]
+    fn clean_with_ide_support() {
+        let temp = crate::guard::TempBuildDir::new("clean").unwrap();
+        let binary = temp.path().join("build");
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(binary.to_string_lossy())
+            .ide_support(IdeIntegration::Clangd {
+                link_to: temp.path().to_path_buf(),
+            });
+        let report = cmd.clone().configure().unwrap();
+        assert!(
+            report.phases[0]
+                .command
+                .args
+                .contains(&"-DCMAKE_EXPORT_COMPILE_COMMANDS=ON".to_string())
+        );
+
+        let settings = cmd
+            .clone()
+            .add_define("CMAKE_EXPORT_COMPILE_COMMANDS", "OFF")
+            .effective_settings()
+            .unwrap();
+        let exports: Vec<_> = settings
+            .defines
+            .iter()
+            .filter(|(name, _)| name == "CMAKE_EXPORT_COMPILE_COMMANDS")
+            .collect();
+        assert_eq!(exports.len(), 1);
+
+        cmd.clean().unwrap();
+        assert!(!binary.exists());
+        cmd.clean().unwrap();
+    }
+
+    #[test]
+    fn quiet_configure_logs_errors_only() {
+        let temp = crate::guard::TempBuildDir::new("quiet").unwrap();
+        let report = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().to_string_lossy())
+            .quiet_configure(true)
+            .build()
+            .unwrap();
+
+        let configures: Vec<&PhaseTiming> = report
+            .phases
+            .iter()
+            .filter(|timing| timing.phase == Phase::Configure)
+            .collect();
+        assert_eq!(configures.len(), 1);
+        assert_eq!(
+            configures[0].command.args.last().map(String::as_str),
+            Some("--log-level=ERROR")
+        );
+    }
+
+    #[test]
+    fn resolve_repeated_defines() {
+        let cmd = Cmd::default()
+            .add_define("CMAKE_BUILD_TYPE", "Debug")
+            .add_define("BUILD_TESTING:BOOL", "ON")
+            .add_define("CMAKE_BUILD_TYPE", "Release")
+            .add_define("BUILD_TESTING", "OFF");
+        assert_eq!(
+            cmd.defines(),
+            vec![("CMAKE_BUILD_TYPE", "Release"), ("BUILD_TESTING", "OFF")]
+        );
+
+        let cmd = cmd.set_define_policy(DefinePolicy::FirstWins);
+        assert_eq!(
+            cmd.defines(),
+            vec![("CMAKE_BUILD_TYPE", "Debug"), ("BUILD_TESTING:BOOL", "ON")]
+        );
+
+        let error = dedup_defines(&cmd.defines, DefinePolicy::Error).unwrap_err();
+        assert_eq!(error, "CMAKE_BUILD_TYPE is defined more than once");
+    }
+
+    #[test]
+    fn typed_define_values() {
+        let cmd = Cmd::default()
+            .add_define_bool("BUILD_TESTING", false)
+            .add_define_bool("USE_SSL:STRING", true)
+            .add_define_path("DEPS_DIR", "/opt/deps")
+            .add_define_path("README", "README.md")
+            .add_define_list("FLAGS", ["-DA=1;2", "-O2"]);
+        assert_eq!(
+            cmd.defines(),
+            vec![
+                ("BUILD_TESTING:BOOL", "OFF"),
+                ("USE_SSL:STRING", "ON"),
+                ("DEPS_DIR:PATH", "/opt/deps"),
+                ("README:FILEPATH", "README.md"),
+                ("FLAGS", "-DA=1\\;2;-O2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_without_touching_dirs() {
+        let temp = crate::guard::TempBuildDir::new("check").unwrap();
+        let binary = temp.path().join("build");
+        let cmd = Cmd::default()
+            .set_path(temp.path().to_string_lossy())
+            .set_binary_path(binary.to_string_lossy())
+            .set_output_path(binary.join("out").to_string_lossy());
+        let error = cmd.check().unwrap_err();
+        assert!(error.to_string().ends_with("has no CMakeLists.txt"));
+        assert!(!binary.exists());
+
+        let args = [
+            "-Wno-dev".to_string(),
+            "-G".to_string(),
+            "Ninja".to_string(),
+        ];
+        assert_eq!(generator_arg(&args).as_deref(), Some("Ninja"));
+        assert_eq!(
+            generator_arg(&["-GUnix Makefiles".to_string()]).as_deref(),
+            Some("Unix Makefiles")
+        );
+        assert_eq!(build_tools("Visual Studio 17 2022"), None);
+    }
+
+    #[test]
+    fn report_effective_settings() {
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path("/tmp/cmakr-settings")
+            .add_arg("-Wno-dev")
+            .add_define("SERVICE_TOKEN", "abc123")
+            .redact(["SERVICE_TOKEN="]);
+        assert_eq!(cmd.path(), Path::new("./test/"));
+        assert_eq!(cmd.preset(), None);
+        assert_eq!(cmd.args(), ["-Wno-dev"]);
+
+        let settings = cmd.effective_settings().unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(settings.source_dir, cwd.join("test"));
+        assert_eq!(settings.binary_dir, PathBuf::from("/tmp/cmakr-settings"));
+        assert_eq!(settings.output_dir, cwd.join("build"));
+        assert_eq!(
+            settings.defines[0],
+            ("SERVICE_TOKEN".to_string(), "***".to_string())
+        );
+        assert_eq!(settings.defines[1].0, "CMAKE_RUNTIME_OUTPUT_DIRECTORY");
+
+        let settings = cmd.set_generator("Ninja").effective_settings().unwrap();
+        assert_eq!(settings.generator.as_deref(), Some("Ninja"));
+    }
+
+    #[test]
+    fn split_output_dirs_by_kind() {
+        let temp = crate::guard::TempBuildDir::new("output-dirs").unwrap();
+        let out = temp.path().join("out");
+        let lib = temp.path().join("lib");
+        let report = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().join("build").to_string_lossy())
+            .set_output_path(out.to_string_lossy())
+            .set_archive_output_path(lib.to_string_lossy())
+            .build()
+            .unwrap();
+
+        let args = &report.phases[0].command.args;
+        let define = |name: &str, dir: &Path| {
+            format!(
+                "-D{}={}",
+                name,
+                forward_slash_path(&dir.canonicalize().unwrap())
+            )
+        };
+        assert!(args.contains(&define("CMAKE_RUNTIME_OUTPUT_DIRECTORY", &out)));
+        assert!(args.contains(&define("CMAKE_LIBRARY_OUTPUT_DIRECTORY", &out)));
+        assert!(args.contains(&define("CMAKE_ARCHIVE_OUTPUT_DIRECTORY", &lib)));
+
+        let cmd: Cmd = r#"{"runtime_output_path": "bin"}"#.parse().unwrap();
+        assert_eq!(cmd.runtime_output_path(), Path::new("bin"));
+        assert_eq!(cmd.library_output_path(), Path::new("build"));
+    }
+
+    #[test]
+    fn pass_once_defines_until_cached() {
+        let temp = crate::guard::TempBuildDir::new("define-once").unwrap();
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().to_string_lossy())
+            .add_define_once("CACHED:STRING", "a")
+            .add_define_once("NEW", "b")
+            .add_define("ALWAYS", "c");
+        let configure_args = |cmd: &Cmd| cmd.dry_run().unwrap().commands[0].args.clone();
+        assert!(configure_args(&cmd).contains(&"-DCACHED:STRING=a".to_string()));
+
+        std::fs::write(
+            temp.path().join("CMakeCache.txt"),
+            "CACHED:STRING=a\nALWAYS:STRING=c\n",
+        )
+        .unwrap();
+        let args = configure_args(&cmd);
+        assert!(!args.iter().any(|arg| arg.starts_with("-DCACHED")));
+        assert!(args.contains(&"-DNEW=b".to_string()));
+        assert!(args.contains(&"-DALWAYS=c".to_string()));
+        assert!(configure_args(&cmd.fresh(true)).contains(&"-DCACHED:STRING=a".to_string()));
+    }
+
+    #[test]
+    fn cross_build_defines() {
+        let cmd = Cmd::default()
+            .set_system_name("Linux")
+            .set_system_processor("aarch64")
+            .set_sysroot("/opt/sysroot")
+            .set_staging_prefix("/tmp/stage")
+            .add_define("CMAKE_FIND_ROOT_PATH_MODE_PACKAGE", "BOTH");
+        let settings = cmd.effective_settings().unwrap();
+        let define = |name: &str| {
+            settings
+                .defines
+                .iter()
+                .filter(|(defined, _)| defined == name)
+                .map(|(_, value)| value.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(define("CMAKE_SYSTEM_NAME"), ["Linux"]);
+        assert_eq!(define("CMAKE_SYSTEM_PROCESSOR"), ["aarch64"]);
+        assert_eq!(define("CMAKE_SYSROOT"), ["/opt/sysroot"]);
+        assert_eq!(define("CMAKE_STAGING_PREFIX"), ["/tmp/stage"]);
+        assert_eq!(define("CMAKE_FIND_ROOT_PATH"), ["/tmp/stage;/opt/sysroot"]);
+        assert_eq!(define("CMAKE_FIND_ROOT_PATH_MODE_PROGRAM"), ["NEVER"]);
+        assert_eq!(define("CMAKE_FIND_ROOT_PATH_MODE_LIBRARY"), ["ONLY"]);
+        assert_eq!(define("CMAKE_FIND_ROOT_PATH_MODE_PACKAGE"), ["BOTH"]);
+    }
+
+    #[test]
+    fn common_option_defines() {
+        let cmd: Cmd = r#"{"cxx_standard": 17, "shared_libs": false, "prefix_path": ["/opt/deps", "/opt/qt"]}"#
+            .parse()
+            .unwrap();
+        let cmd = cmd.set_build_type(BuildType::RelWithDebInfo);
+        let settings = cmd.effective_settings().unwrap();
+        let define = |name: &str| {
+            settings
+                .defines
+                .iter()
+                .find(|(defined, _)| defined == name)
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(
+            define("CMAKE_BUILD_TYPE").as_deref(),
+            Some("RelWithDebInfo")
+        );
+        assert_eq!(define("CMAKE_CXX_STANDARD").as_deref(), Some("17"));
+        assert_eq!(define("CMAKE_CXX_STANDARD_REQUIRED").as_deref(), Some("ON"));
+        assert_eq!(define("BUILD_SHARED_LIBS").as_deref(), Some("OFF"));
+        assert_eq!(
+            define("CMAKE_PREFIX_PATH").as_deref(),
+            Some("/opt/deps;/opt/qt")
+        );
+
+        // the user's definition replaces the standard and whether it is required
+        let settings = cmd
+            .clone()
+            .add_define("CMAKE_CXX_STANDARD", "20")
+            .effective_settings()
+            .unwrap();
+        assert!(
+            !settings
+                .defines
+                .iter()
+                .any(|(name, _)| name == "CMAKE_CXX_STANDARD_REQUIRED")
+        );
+        assert!(matches!(
+            cmd.set_cxx_standard(18).dry_run(),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn parse_cmd_config() {
+        let cmd: Cmd =
+            r#"{"path":"./test/","preset":"default","defines":{"A":"1"},"args":["-Wno-dev"]}"#
+                .parse()
+                .unwrap();
+        assert_eq!(cmd.path(), Path::new("./test/"));
+        assert_eq!(cmd.preset(), Some("default"));
+        assert_eq!(cmd.defines(), vec![("A", "1")]);
+        assert_eq!(cmd.binary_path(), Path::new("build"));
+
+        assert!("{\"unknown\":1}".parse::<Cmd>().is_err());
+        assert!(r#"{"defines":{"A":"x\ny"}}"#.parse::<Cmd>().is_err());
+    }
+
+    #[test]
+    fn config_file_round_trip() {
+        let temp = crate::guard::TempBuildDir::new("config-file").unwrap();
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_build_type(BuildType::Release)
+            .add_define("BUILD_TESTING", "OFF")
+            .with_compiler_launcher(Launcher::Sccache)
+            .env("CC", "clang")
+            .env_remove("CFLAGS");
+        let mut files = vec![temp.path().join("cmakr.json")];
+        if cfg!(feature = "toml") {
+            files.push(temp.path().join("cmakr.toml"));
+        }
+        for file in files {
+            cmd.to_file(&file).unwrap();
+            let content = std::fs::read_to_string(&file).unwrap();
+            assert!(!content.contains("lto"), "{}", content);
+            let read = Cmd::from_file(&file).unwrap();
+            assert_eq!(CmdConfig::from(&read), CmdConfig::from(&cmd));
+        }
+        if !cfg!(feature = "toml") {
+            assert!(matches!(
+                cmd.to_file(temp.path().join("cmakr.toml")),
+                Err(Error::Unsupported(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn resolve_config_file_paths_against_its_directory() {
+        let temp = crate::guard::TempBuildDir::new("config-file-dir").unwrap();
+        let project = temp.path().join("checkout");
+        std::fs::create_dir_all(project.join("native")).unwrap();
+        let file = project.join("cmakr.json");
+        std::fs::write(&file, r#"{"path":"native","binary_path":"build"}"#).unwrap();
+
+        let plan = Cmd::from_file(&file).unwrap().dry_run().unwrap();
+        let project = project.canonicalize().unwrap();
+        let args = &plan.commands[0].args;
+        let after = |flag: &str| {
+            let i = args.iter().position(|arg| arg == flag).unwrap();
+            PathBuf::from(&args[i + 1])
+        };
+        assert_eq!(after("-S"), project.join("native"));
+        assert_eq!(after("-B"), project.join("build"));
+    }
+
+    #[test]
+    fn scrub_secrets_from_errors() {
+        let redactor = Credentials::default().redactor(&["SERVICE_TOKEN=".to_string()]);
+        let error = scrub(
+            Err(Error::InvalidConfig(
+                "unknown argument -DSERVICE_TOKEN=abc123".to_string(),
+            )),
+            &redactor,
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "unknown argument -DSERVICE_TOKEN=***");
+    }
 
-        thread::spawn(move || {
-            let _ = tx.send(self.execute());
-        });
+    #[test]
+    fn spawn_cmake() {
+        let rx = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path("./build")
+            .set_output_path("./bin")
+            .spawn();
+
+        let result = rx.recv().unwrap();
+        assert_eq!(result.is_ok(), true);
+        assert!(!rx.is_running());
+        assert!(rx.try_recv().is_err());
+
+        let handle = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path("./build")
+            .set_output_path("./bin")
+            .spawn();
+        handle.kill();
+        let result = handle.wait_timeout(Duration::from_secs(10)).unwrap();
+        // the fake cmake may finish before the kill reaches it
+        assert!(matches!(result, Ok(_) | Err(Error::Cancelled(_))));
+    }
 
-        rx
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn build_cmake_async() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let build = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path("./build")
+            .set_output_path("./bin")
+            .build_async();
+        assert!(runtime.block_on(build).is_ok());
     }
 
-    /// Internal method that performs the actual CMake configure and build.
-    ///
-    /// This method:
-    /// 1. Verifies that `cmake` is available on `PATH`.
-    /// 2. Resolves the preset (if set) from `CMakePresets.json`.
-    /// 3. Creates build and output directories if they don't exist.
-    /// 4. Runs `cmake -S <source> -B <binary>` with all configured arguments.
-    /// 5. Runs `cmake --build <binary>` to compile the project.
-    fn execute(&mut self) -> ExecResult {
-        // check cmake is exists in path
-        if which::which("cmake").is_err() {
-            panic!("cmake not found in path");
-        }
+    #[cfg(all(unix, feature = "tokio"))]
+    #[test]
+    fn build_async_on_own_thread() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("async-thread").unwrap();
+        let cmake = temp.path().join("cmake");
+        std::fs::write(
+            &cmake,
+            "#!/bin/sh\n[ \"$1\" = --version ] && echo 'cmake version 3.28.1'\nexit 0\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&cmake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let thread_name = Arc::new(std::sync::Mutex::new(None));
+        let recorded = thread_name.clone();
+        let build = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().join("build").to_string_lossy())
+            .set_cmake_executable(cmake.to_string_lossy())
+            .on_before_configure(move |_| {
+                *recorded.lock().unwrap() = thread::current().name().map(str::to_string);
+                Ok(())
+            })
+            .build_async();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(build).unwrap();
+        assert_eq!(thread_name.lock().unwrap().as_deref(), Some("cmakr-build"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn enforce_required_version() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("version").unwrap();
+        let cmake = temp.path().join("cmake");
+        std::fs::write(&cmake, "#!/bin/sh\necho 'cmake version 3.20.6'\n").unwrap();
+        std::fs::set_permissions(&cmake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().join("build").to_string_lossy())
+            .set_cmake_executable(cmake.to_string_lossy());
+        let error = cmd.clone().require_version(">=3.21").build().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "cmake 3.20.6 does not satisfy the required version >=3.21"
+        );
+        assert!(!temp.path().join("build").exists());
+        assert!(cmd.require_version("3.16, <3.21").build().is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_cmake_on_run_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("run-path").unwrap();
+        let bin = temp.path().canonicalize().unwrap().join("bin");
+        std::fs::create_dir_all(&bin).unwrap();
+        let cmake = bin.join("cmake");
+        std::fs::write(
+            &cmake,
+            "#!/bin/sh\n[ \"$1\" = --version ] && echo 'cmake version 3.28.1' && exit 0\nexit 0\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&cmake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().join("build").to_string_lossy());
+        let plan = cmd
+            .clone()
+            .env("PATH", bin.to_string_lossy())
+            .dry_run()
+            .unwrap();
+        assert_eq!(plan.commands[0].program, cmake.to_string_lossy());
+
+        let plan = cmd
+            .current_dir(temp.path().to_string_lossy())
+            .set_cmake_executable("bin/cmake")
+            .dry_run()
+            .unwrap();
+        assert_eq!(plan.commands[0].program, cmake.to_string_lossy());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn probe_cmake_version_once() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("version-probe").unwrap();
+        let cmake = temp.path().join("cmake");
+        let calls = temp.path().join("calls.log");
+        std::fs::write(
+            &cmake,
+            format!(
+                "#!/bin/sh\necho \"$1\" >> {}\n\
+                 [ \"$1\" = --version ] && echo 'cmake version 3.28.1' && exit 0\nexit 0\n",
+                calls.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&cmake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().join("build").to_string_lossy())
+            .set_cmake_executable(cmake.to_string_lossy())
+            .set_build_type("Release")
+            .build()
+            .unwrap();
+        let probes = std::fs::read_to_string(&calls)
+            .unwrap()
+            .lines()
+            .filter(|call| *call == "--version")
+            .count();
+        assert_eq!(probes, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kill_configure_on_timeout() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("timeout").unwrap();
+        let cmake = temp.path().join("cmake");
+        std::fs::write(
+            &cmake,
+            "#!/bin/sh\n[ \"$1\" = --version ] && echo 'cmake version 3.28.1' && exit 0\nsleep 30\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&cmake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let start = Instant::now();
+        let result = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().join("build").to_string_lossy())
+            .set_cmake_executable(cmake.to_string_lossy())
+            .set_timeout(Duration::from_secs(60))
+            .set_configure_timeout(Duration::from_millis(300))
+            .configure();
+        assert!(matches!(result, Err(Error::TimedOut(_))));
+        assert!(start.elapsed() < Duration::from_secs(20));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn retry_transient_configure_failure() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("retry").unwrap();
+        let cmake = temp.path().join("cmake");
+        let failed = temp.path().join("failed");
+        std::fs::write(
+            &cmake,
+            format!(
+                "#!/bin/sh\n[ \"$1\" = --version ] && echo 'cmake version 3.28.1' && exit 0\n\
+                 [ \"$1\" = --build ] && exit 0\n\
+                 [ -e {0} ] && exit 0\n\
+                 touch {0}\n\
+                 echo 'fatal: unable to access: Could not resolve host: github.com' >&2\n\
+                 exit 1\n",
+                failed.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&cmake, std::fs::Permissions::from_mode(0o755)).unwrap();
 
-        // add path arg if path is set
-        let cmake_path = match &self.path {
-            Some(path) => path.clone(),
-            None => PathBuf::from("."),
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().join("build").to_string_lossy())
+            .set_cmake_executable(cmake.to_string_lossy());
+        let policy = RetryPolicy::default().set_backoff(Duration::ZERO);
+        let report = cmd.clone().retry(policy.clone()).build().unwrap();
+        assert_eq!(report.retries.len(), 1);
+        assert_eq!(report.retries[0].phase, Phase::Configure);
+        assert_eq!(report.retries[0].attempt, 2);
+        assert!(report.retries[0].reason.contains("Could not resolve host"));
+        let configures = report.phases.iter().filter(|t| t.phase == Phase::Configure);
+        assert_eq!(configures.count(), 2);
+
+        // other failures are not transient
+        std::fs::remove_file(&failed).unwrap();
+        let policy = policy.set_retry_on(["Connection reset by peer"]);
+        assert!(matches!(
+            cmd.retry(policy).build(),
+            Err(Error::ConfigureFailed(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cap_parallel_targets_at_job_budget() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("parallel-budget").unwrap();
+        let cmake = temp.path().join("cmake");
+        let running = temp.path().join("running");
+        let log = temp.path().join("concurrency.log");
+        std::fs::create_dir_all(&running).unwrap();
+        std::fs::write(
+            &cmake,
+            format!(
+                "#!/bin/sh
+[ \"$1\" = --version ] && echo 'cmake version 3.28.1' && exit 0
+\
+                 [ \"$1\" = --build ] || exit 0
+\
+                 touch {0}/$$
+ls {0} | wc -l >> {1}
+sleep 0.2
+rm {0}/$$
+",
+                running.display(),
+                log.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&cmake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let report = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().join("build").to_string_lossy())
+            .set_cmake_executable(cmake.to_string_lossy())
+            .build_targets_parallel(["a", "b", "c", "d", "e"], 2)
+            .build()
+            .unwrap();
+        let targets: Vec<_> = report
+            .phases
+            .iter()
+            .filter_map(|timing| timing.target.as_deref())
+            .collect();
+        assert_eq!(targets, ["a", "b", "c", "d", "e"]);
+        let concurrency = std::fs::read_to_string(&log).unwrap();
+        assert_eq!(concurrency.lines().count(), 5);
+        assert!(
+            concurrency
+                .lines()
+                .all(|line| line.trim().parse::<usize>().unwrap() <= 2)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_phase_hooks() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("hooks").unwrap();
+        let cmake = temp.path().join("cmake");
+        std::fs::write(
+            &cmake,
+            "#!/bin/sh\n[ \"$1\" = --version ] && echo 'cmake version 3.28.1' && exit 0\nexit 0\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&cmake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let record = |name: &'static str| {
+            let calls = calls.clone();
+            move |context: &HookContext<'_>| {
+                let previous = context.previous.map(|timing| timing.phase);
+                calls.lock().unwrap().push((name, context.phase, previous));
+                Ok(())
+            }
+        };
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().join("build").to_string_lossy())
+            .set_cmake_executable(cmake.to_string_lossy())
+            .add_define("FOO", "ON")
+            .on_before_configure(|context| {
+                assert!(context.args.contains(&"-DFOO=ON".to_string()));
+                std::fs::write(context.binary_dir.join("extra.txt"), "")
+            })
+            .on_after_configure(record("after configure"))
+            .on_before_build(record("before build"))
+            .on_after_build(record("after build"));
+        cmd.clone().build().unwrap();
+        assert!(temp.path().join("build/extra.txt").exists());
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                ("after configure", Phase::Configure, Some(Phase::Configure)),
+                ("before build", Phase::Build, Some(Phase::Configure)),
+                ("after build", Phase::Build, Some(Phase::Build)),
+            ]
+        );
+
+        // a failing hook stops the run
+        let result = cmd
+            .on_before_build(|_| Err(std::io::Error::other("no resources")))
+            .build();
+        let Err(Error::Io(e)) = result else {
+            panic!("the hook did not stop the run");
         };
+        assert_eq!(e.to_string(), "before build hook failed: no resources");
+    }
 
-        // add preset arg if preset is set
-        let mut preset_args: Vec<String> = Vec::new();
-        if let Some(preset_name) = &self.preset {
-            let presets = CMakePresets::new(&cmake_path).expect("Failed to get cmake presets");
-            let Some(preset) = presets.get_preset(preset_name) else {
-                return Err(format!("preset {} not found", preset_name).into());
-            };
+    #[test]
+    fn fail_on_locked_build_dir() {
+        let temp = crate::guard::TempBuildDir::new("locked").unwrap();
+        let build = temp.path().join("build");
+        let _held = DirLock::acquire(&build).unwrap();
 
-            preset_args.push(format!("--preset={}", preset.get_name()));
-        }
+        let result = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(build.to_string_lossy())
+            .set_lock_policy(LockPolicy::Timeout(Duration::ZERO))
+            .configure();
+        let Err(Error::BuildDirLocked(message)) = result else {
+            panic!("not a locked build directory error");
+        };
+        assert!(message.contains(&format!("by process {}", std::process::id())));
+    }
 
-        // binary path and output path must be exists, if not exists, create it
-        check_dir_exists_and_create(&self.binary_path)?;
-        check_dir_exists_and_create(&self.output_path)?;
-        let output_dir = normalize_path(&self.output_path.canonicalize()?);
-        let output_path_args = vec![
-            format!("-DCMAKE_RUNTIME_OUTPUT_DIRECTORY={}", output_dir),
-            format!("-DCMAKE_LIBRARY_OUTPUT_DIRECTORY={}", output_dir),
-            format!("-DCMAKE_ARCHIVE_OUTPUT_DIRECTORY={}", output_dir),
-        ];
+    #[test]
+    fn fail_on_locked_fetch_cache() {
+        let temp = crate::guard::TempBuildDir::new("locked-cache").unwrap();
+        let cache = temp.path().join("cache");
+        let _held = DirLock::acquire(&cache).unwrap();
 
-        // configure cmake
-        let status = std::process::Command::new("cmake")
-            .args(["-S", cmake_path.to_str().unwrap()])
-            .args(["-B", self.binary_path.to_str().unwrap()])
-            .args(&preset_args)
-            .args(
-                self.defines
-                    .iter()
-                    .map(|d| format!("-D{}={}", d.name, d.value)),
+        let result = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().join("build").to_string_lossy())
+            .set_fetch_cache_dir(cache.to_string_lossy())
+            .set_lock_policy(LockPolicy::Timeout(Duration::ZERO))
+            .configure();
+        let Err(Error::BuildDirLocked(message)) = result else {
+            panic!("not a locked directory error");
+        };
+        assert!(message.contains("cache"));
+
+        let cmd = Cmd::default().set_lock_policy(LockPolicy::Timeout(Duration::ZERO));
+        assert_eq!(
+            cmd.clone().lock_build_dir(false).lock_policy,
+            LockPolicy::Disabled
+        );
+        assert_eq!(
+            cmd.lock_build_dir(true).lock_policy,
+            LockPolicy::Timeout(Duration::ZERO)
+        );
+        assert_eq!(
+            Cmd::default()
+                .lock_build_dir(false)
+                .lock_build_dir(true)
+                .lock_policy,
+            LockPolicy::Wait
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_workflow_preset() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("workflow").unwrap();
+        let source = temp.path().join("src");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("CMakeLists.txt"), "project(app)\n").unwrap();
+        std::fs::write(
+            source.join("CMakePresets.json"),
+            r#"{
+                "version": 6,
+                "configurePresets": [{"name": "dev", "binaryDir": "${sourceDir}/out"}],
+                "buildPresets": [{"name": "dev", "configurePreset": "dev"}],
+                "workflowPresets": [{
+                    "name": "ci",
+                    "steps": [
+                        {"type": "configure", "name": "dev"},
+                        {"type": "build", "name": "dev"}
+                    ]
+                }]
+            }"#,
+        )
+        .unwrap();
+        let fake_cmake = |version: &str| {
+            let cmake = temp.path().join(format!("cmake-{}", version));
+            std::fs::write(
+                &cmake,
+                format!(
+                    "#!/bin/sh\n[ \"$1\" = --version ] && echo 'cmake version {}' && exit 0\n\
+                     while [ $# -gt 0 ]; do\n  \
+                     [ \"$1\" = -B ] && mkdir -p \"$2\" && : > \"$2/CMakeCache.txt\"\n  shift\ndone\n",
+                    version
+                ),
             )
-            .args(output_path_args)
-            .args(self.args.clone())
-            .status()?;
+            .unwrap();
+            std::fs::set_permissions(&cmake, std::fs::Permissions::from_mode(0o755)).unwrap();
+            cmake.to_string_lossy().into_owned()
+        };
+        let cmd = Cmd::default().set_path(source.to_string_lossy());
 
-        if !status.success() {
-            return Err(format!("cmake configure failed with status: {}", status).into());
-        }
+        let report = cmd
+            .clone()
+            .set_cmake_executable(fake_cmake("3.28.1"))
+            .run_workflow("ci")
+            .unwrap();
+        assert_eq!(report.phases.len(), 1);
+        assert_eq!(report.phases[0].phase, Phase::Workflow);
+        assert_eq!(report.phases[0].command.args, ["--workflow", "--preset=ci"]);
+        assert!(report.binary_dir.ends_with("src/out"));
 
-        // build cmake
-        let status = std::process::Command::new("cmake")
-            .arg("--build")
-            .arg(self.binary_path.clone())
-            .args(self.args.clone())
-            .status()?;
+        // older versions run the steps with their presets
+        let report = cmd
+            .clone()
+            .set_cmake_executable(fake_cmake("3.22.1"))
+            .run_workflow("ci")
+            .unwrap();
+        let phases: Vec<Phase> = report.phases.iter().map(|timing| timing.phase).collect();
+        assert_eq!(phases, [Phase::Configure, Phase::Build]);
+        assert!(
+            report.phases[0]
+                .command
+                .args
+                .contains(&"--preset=dev".to_string())
+        );
 
-        if !status.success() {
-            return Err(format!("cmake build failed with status: {}", status).into());
-        }
+        assert!(matches!(
+            cmd.run_workflow("release"),
+            Err(Error::PresetNotFound(name)) if name == "release"
+        ));
+    }
 
-        Ok(())
+    #[cfg(unix)]
+    #[test]
+    fn retry_with_fallback_generator() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("fallback").unwrap();
+        let cmake = temp.path().join("cmake");
+        std::fs::write(
+            &cmake,
+            "#!/bin/sh\ncase \"$*\" in *'-G Ninja'*)\n  \
+             echo 'CMake Error: CMake was unable to find a build program corresponding to \"Ninja\".  CMAKE_MAKE_PROGRAM is not set.' >&2\n  \
+             echo 'CMAKE_GENERATOR:INTERNAL=Ninja' > \"$4/CMakeCache.txt\"\n  exit 1;;\nesac\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&cmake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().join("build").to_string_lossy())
+            .set_cmake_executable(cmake.to_string_lossy())
+            .set_generator("Ninja");
+        let report = cmd
+            .clone()
+            .fallback_generators(["Ninja", "Unix Makefiles"])
+            .configure()
+            .unwrap();
+        assert_eq!(report.generator_fallback.as_deref(), Some("Unix Makefiles"));
+        assert_eq!(report.phases.len(), 2);
+        assert!(!report.phases[0].exit.success());
+        assert!(
+            report.phases[1]
+                .command
+                .args
+                .contains(&"Unix Makefiles".to_string())
+        );
+        assert!(!temp.path().join("build/CMakeCache.txt").exists());
+
+        let error = cmd.configure().unwrap_err();
+        assert!(
+            error
+                .failure()
+                .unwrap()
+                .report()
+                .generator_fallback
+                .is_none()
+        );
     }
-}
 
-/// Ensures a directory exists, creating it (and any parent directories) if necessary.
-///
-/// # Errors
-///
-/// Returns an I/O error if directory creation fails.
-fn check_dir_exists_and_create(path: &PathBuf) -> std::io::Result<()> {
-    if !path.exists() {
-        std::fs::create_dir_all(path)?;
+    #[test]
+    fn relocate_to_short_build_dir() {
+        let temp = crate::guard::TempBuildDir::new("short").unwrap();
+        let root = temp.path().join("bld");
+        let requested = temp.path().join("very/deep/out/build");
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(requested.to_string_lossy())
+            .short_build_dir(true)
+            .set_short_build_root(root.to_string_lossy());
+        let report = cmd.clone().configure().unwrap();
+        assert_eq!(
+            report.binary_dir.parent(),
+            Some(root.canonicalize().unwrap().as_path())
+        );
+        assert_eq!(report.relocated_from.as_deref(), Some(requested.as_path()));
+        assert!(!requested.exists());
+
+        let state = BuildState::load(&report.binary_dir).unwrap().unwrap();
+        assert_eq!(state.configuration.relocated_from, Some(requested));
+        assert_eq!(cmd.configure().unwrap().binary_dir, report.binary_dir);
     }
-    Ok(())
-}
 
-/// Converts a path to a normalized string, stripping the Windows `\\?\` extended-length
-/// prefix if present. This is necessary because some tools (e.g., GCC's linker) do not
-/// recognize UNC-style paths produced by [`std::path::Path::canonicalize`] on Windows.
-fn normalize_path(path: &std::path::Path) -> String {
-    let s = path.to_str().unwrap_or_default();
-    s.strip_prefix(r"\\?\").unwrap_or(s).to_string()
-}
+    #[test]
+    fn configure_arg_order() {
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_build_type("Release")
+            .add_define("ZETA", "1")
+            .add_define("ALPHA", "2")
+            .add_arg("--warn-uninitialized");
+        let defines = |cmd: Cmd| -> Vec<String> {
+            let args = cmd.dry_run().unwrap().commands[0].args.clone();
+            args.into_iter()
+                .filter(|arg| {
+                    ["-DZETA", "-DALPHA", "-DCMAKE_BUILD_TYPE", "--warn"]
+                        .iter()
+                        .any(|prefix| arg.starts_with(prefix))
+                })
+                .collect()
+        };
+        assert_eq!(
+            defines(cmd.clone()),
+            [
+                "-DZETA=1",
+                "-DALPHA=2",
+                "-DCMAKE_BUILD_TYPE=Release",
+                "--warn-uninitialized"
+            ]
+        );
+        assert_eq!(
+            defines(cmd.clone().arg_order(ArgOrder::Sorted)),
+            [
+                "-DALPHA=2",
+                "-DZETA=1",
+                "-DCMAKE_BUILD_TYPE=Release",
+                "--warn-uninitialized"
+            ]
+        );
+        assert_eq!(
+            defines(cmd.arg_order(ArgOrder::UserDefinesLast)),
+            [
+                "-DCMAKE_BUILD_TYPE=Release",
+                "-DZETA=1",
+                "-DALPHA=2",
+                "--warn-uninitialized"
+            ]
+        );
+    }
 
-mod tests {
-    #[allow(unused)]
-    use super::*;
+    #[test]
+    fn preset_defaults() {
+        let temp = crate::guard::TempBuildDir::new("preset-defaults").unwrap();
+        std::fs::write(temp.path().join("CMakeLists.txt"), "project(app)\n").unwrap();
+        std::fs::write(
+            temp.path().join("CMakePresets.json"),
+            r#"{
+                "version": 4,
+                "configurePresets": [{
+                    "name": "dev",
+                    "generator": "Unix Makefiles",
+                    "binaryDir": "${sourceDir}/out/${presetName}",
+                    "environment": {"CC": "clang", "LEVEL": "preset"}
+                }]
+            }"#,
+        )
+        .unwrap();
+        let cmd = Cmd::default()
+            .set_path(temp.path().to_string_lossy())
+            .set_preset("dev")
+            .env("LEVEL", "user");
+        let commands = cmd.dry_run().unwrap().commands;
+        let binary_dir = std::path::absolute(temp.path().join("out/dev")).unwrap();
+        let configure = &commands[0];
+        let b = configure.args.iter().position(|arg| arg == "-B").unwrap();
+        assert_eq!(configure.args[b + 1], binary_dir.to_string_lossy());
+        assert!(!configure.args.contains(&"-G".to_string()));
+        // the build sees the environment of the preset, below the user's
+        for command in &commands {
+            assert!(
+                command
+                    .env
+                    .contains(&("CC".to_string(), Some("clang".to_string())))
+            );
+            assert!(
+                command
+                    .env
+                    .contains(&("LEVEL".to_string(), Some("user".to_string())))
+            );
+        }
+
+        let commands = cmd.set_binary_path("elsewhere").dry_run().unwrap().commands;
+        let b = commands[0].args.iter().position(|arg| arg == "-B").unwrap();
+        assert!(commands[0].args[b + 1].ends_with("elsewhere"));
+    }
 
     #[test]
-    fn execute_cmake() {
+    fn explain_reconfigure_reasons() {
+        let temp = crate::guard::TempBuildDir::new("explain").unwrap();
         let cmd = Cmd::default()
             .set_path("./test/")
-            .set_preset("default")
-            .build();
+            .set_binary_path(temp.path().to_string_lossy())
+            .add_define("FOO", "1")
+            .skip_configure_if_unchanged();
+        let reasons = cmd.explain_rebuild().unwrap();
+        assert_eq!(reasons, [RebuildReason::CacheMissing]);
 
-        assert_eq!(cmd.is_ok(), true);
+        cmd.clone().configure().unwrap();
+        std::fs::write(
+            temp.path().join("CMakeCache.txt"),
+            "CMAKE_GENERATOR:INTERNAL=Unix Makefiles\n",
+        )
+        .unwrap();
+        assert_eq!(cmd.explain_rebuild().unwrap(), []);
+
+        let reasons = cmd
+            .clone()
+            .add_define("FOO", "2")
+            .set_generator("Ninja")
+            .explain_rebuild()
+            .unwrap();
+        assert_eq!(
+            reasons,
+            [
+                RebuildReason::GeneratorMismatch {
+                    configured: "Unix Makefiles".to_string(),
+                    requested: "Ninja".to_string(),
+                },
+                RebuildReason::DefineChanged {
+                    name: "FOO".to_string(),
+                    before: Some("1".to_string()),
+                    after: Some("2".to_string()),
+                },
+            ]
+        );
+        assert_eq!(reasons[1].to_string(), "FOO changed from \"1\" to \"2\"");
     }
 
     #[test]
-    fn spawn_cmake() {
-        let rx = Cmd::default()
+    fn stop_at_phase_boundary() {
+        let cancel = CancelToken::default();
+        let token = cancel.clone();
+        let result = Cmd::default()
             .set_path("./test/")
             .set_binary_path("./build")
             .set_output_path("./bin")
-            .spawn();
+            .set_cancel_token(cancel)
+            .on_progress(move |event| {
+                if let ProgressEvent::PhaseFinished {
+                    phase: Phase::Configure,
+                    ..
+                } = event
+                {
+                    token.terminate();
+                }
+            })
+            .build();
 
-        let result = rx.recv().unwrap();
-        assert_eq!(result.is_ok(), true);
+        let Err(Error::Cancelled(failure)) = result else {
+            panic!("the build was not cancelled");
+        };
+        assert_eq!(failure.report().completed_phases(), [Phase::Configure]);
+        assert_eq!(failure.to_string(), "cmake build was cancelled");
+    }
+
+    #[test]
+    fn configure_with_graphviz_and_trace() {
+        let temp = crate::guard::TempBuildDir::new("graphviz").unwrap();
+        let binary = temp.path().join("build");
+        let plan = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(binary.to_string_lossy())
+            .emit_graphviz(temp.path().join("targets.dot").to_string_lossy())
+            .trace(TraceFormat::Json)
+            .dry_run()
+            .unwrap();
+        let args = &plan.commands[0].args;
+        let graphviz = format!("--graphviz={}", temp.path().join("targets.dot").display());
+        assert!(args.contains(&graphviz));
+        assert!(args.contains(&"--trace-format=json-v1".to_string()));
+        assert!(args.iter().any(|arg| {
+            arg.starts_with("--trace-redirect=") && arg.ends_with(".cmakr/trace.json")
+        }));
+    }
+
+    #[test]
+    fn apply_verbosity_to_its_phase() {
+        let plan = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path("./build/verbosity")
+            .verbose(true)
+            .log_level(LogLevel::Warning)
+            .dry_run()
+            .unwrap();
+        let configure = &plan.commands[0].args;
+        let build = &plan.commands[1].args;
+        assert!(configure.contains(&"--log-level=WARNING".to_string()));
+        assert!(configure.contains(&"--log-context".to_string()));
+        assert!(!configure.contains(&"--verbose".to_string()));
+        assert!(build.contains(&"--verbose".to_string()));
+        assert!(!build.iter().any(|arg| arg.starts_with("--log-")));
+
+        // quiet configure wins over the log level
+        let plan = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path("./build/verbosity")
+            .log_level(LogLevel::Debug)
+            .quiet_configure(true)
+            .dry_run()
+            .unwrap();
+        let levels: Vec<&String> = plan.commands[0]
+            .args
+            .iter()
+            .filter(|arg| arg.starts_with("--log-level"))
+            .collect();
+        assert_eq!(levels, ["--log-level=ERROR"]);
+    }
+
+    #[test]
+    fn entry_points_resolve_against_current_dir() {
+        let temp = crate::guard::TempBuildDir::new("current-dir-entry").unwrap();
+        let build = temp.path().join("build dir");
+        std::fs::create_dir_all(&build).unwrap();
+        let cmd = Cmd::default()
+            .current_dir(temp.path().to_string_lossy())
+            .set_binary_path("build dir");
+
+        assert_eq!(
+            cmd.explain_rebuild().unwrap(),
+            [RebuildReason::CacheMissing]
+        );
+        std::fs::write(build.join("CMakeCache.txt"), "").unwrap();
+        assert!(
+            !cmd.explain_rebuild()
+                .unwrap()
+                .contains(&RebuildReason::CacheMissing)
+        );
+        cmd.clean().unwrap();
+        assert!(!build.exists());
+    }
+
+    #[test]
+    fn resolve_paths_against_current_dir() {
+        let temp = crate::guard::TempBuildDir::new("current-dir").unwrap();
+        let project = temp.path().join("my project");
+        std::fs::create_dir_all(project.join("native")).unwrap();
+        let plan = Cmd::default()
+            .current_dir(project.to_string_lossy())
+            .set_path("native")
+            .set_binary_path("build dir")
+            .set_output_path("out dir")
+            .dry_run()
+            .unwrap();
+        for command in &plan.commands {
+            assert_eq!(command.cwd.as_deref(), Some(project.as_path()));
+        }
+        let project = project.canonicalize().unwrap();
+        let args = &plan.commands[0].args;
+        let after = |flag: &str| {
+            let i = args.iter().position(|arg| arg == flag).unwrap();
+            PathBuf::from(&args[i + 1])
+        };
+        assert_eq!(after("-S"), project.join("native"));
+        assert_eq!(after("-B"), project.join("build dir"));
+        let output = forward_slash_path(&project.join("out dir"));
+        assert!(args.contains(&format!("-DCMAKE_RUNTIME_OUTPUT_DIRECTORY={}", output)));
+        assert!(!std::env::current_dir().unwrap().join("build dir").exists());
+    }
+
+    #[test]
+    fn dry_run_matches_run() {
+        let temp = crate::guard::TempBuildDir::new("dry-run").unwrap();
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().join("build").to_string_lossy())
+            .set_output_path(temp.path().join("bin").to_string_lossy())
+            .set_install_prefix(temp.path().join("prefix").to_string_lossy())
+            .add_install_component("runtime")
+            .add_define("FOO", "ON")
+            .add_target("app")
+            .set_jobs(3)
+            .env("CC", "clang")
+            .env_remove("CFLAGS")
+            .quiet_configure(true);
+
+        let planned = cmd.dry_run().unwrap();
+        assert!(!temp.path().join("build").exists());
+        let report = cmd.build_with_output().unwrap();
+        let run: Vec<PreparedCommand> = report
+            .phases
+            .iter()
+            .map(|timing| timing.command.clone())
+            .collect();
+        assert_eq!(planned.commands, run);
+        assert!(planned.commands[0].args.contains(&"-DFOO=ON".to_string()));
+        assert_eq!(
+            planned.commands[0].env,
+            [
+                ("CC".to_string(), Some("clang".to_string())),
+                ("CFLAGS".to_string(), None)
+            ]
+        );
+
+        // the plan of a fixed configuration is stable, up to the absolute paths
+        let cwd = normalize_path(&std::env::current_dir().unwrap());
+        let mut plan = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path("./build/plan")
+            .set_build_type("Release")
+            .add_define("FOO", "ON")
+            .set_jobs(2)
+            .dry_run()
+            .unwrap();
+        for command in &mut plan.commands {
+            command.program = "cmake".to_string();
+            // offline mode is process-wide and may be switched on by another test
+            command
+                .args
+                .retain(|arg| !arg.starts_with("-DFETCHCONTENT_FULLY_DISCONNECTED"));
+        }
+        let golden = plan
+            .to_shell(crate::command::Shell::Posix)
+            .replace(&cwd, "$PWD");
+        assert_eq!(
+            golden,
+            "cmake -S ./test/ -B ./build/plan -DFOO=ON \
+             -DCMAKE_RUNTIME_OUTPUT_DIRECTORY=$PWD/build \
+             -DCMAKE_LIBRARY_OUTPUT_DIRECTORY=$PWD/build \
+             -DCMAKE_ARCHIVE_OUTPUT_DIRECTORY=$PWD/build \
+             -DCMAKE_BUILD_TYPE=Release\n\
+             cmake --build ./build/plan --parallel 2\n"
+        );
+    }
+
+    #[test]
+    fn dry_run_writes_nothing() {
+        let temp = crate::guard::TempBuildDir::new("dry-run-writes").unwrap();
+        let source = temp.path().join("src");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("CMakeLists.txt"), "project(p)").unwrap();
+        let modified = std::fs::metadata(&source).unwrap().modified().unwrap();
+
+        Cmd::default()
+            .set_path(source.to_string_lossy())
+            .set_binary_path(temp.path().join("build").to_string_lossy())
+            .set_read_only_source_policy(ReadOnlySourcePolicy::Copy)
+            .set_toolchain(ToolchainFile::builder().system_name("Generic").build())
+            .set_fetch_cache_dir(temp.path().join("fetch").to_string_lossy())
+            .dry_run()
+            .unwrap();
+
+        let entries: Vec<_> = std::fs::read_dir(temp.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(std::fs::read_dir(&source).unwrap().count(), 1);
+        assert_eq!(
+            std::fs::metadata(&source).unwrap().modified().unwrap(),
+            modified
+        );
+    }
+
+    #[test]
+    fn share_fetched_sources_only() {
+        let temp = crate::guard::TempBuildDir::new("fetch-cache-plan").unwrap();
+        let source = temp.path().join("src");
+        let cache = temp.path().join("fetch");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(
+            source.join("CMakeLists.txt"),
+            "FetchContent_Declare(fmt GIT_REPOSITORY https://example.com/fmt.git GIT_TAG 10.2.1)",
+        )
+        .unwrap();
+        let deps = temp.path().join("first/_deps");
+        std::fs::create_dir_all(deps.join("fmt-src")).unwrap();
+        let declared = offline::declared_fetches(&source, &temp.path().join("first"));
+        fetch_cache::store(&cache, &deps, &declared).unwrap();
+
+        let plan = Cmd::default()
+            .set_path(source.to_string_lossy())
+            .set_binary_path(temp.path().join("second").to_string_lossy())
+            .set_fetch_cache_dir(cache.to_string_lossy())
+            .dry_run()
+            .unwrap();
+        let args = &plan.commands[0].args;
+        let shared = forward_slash_path(&cache.canonicalize().unwrap().join("fmt-src"));
+        assert!(args.contains(&format!("-DFETCHCONTENT_SOURCE_DIR_FMT={}", shared)));
+        assert!(
+            !args
+                .iter()
+                .any(|arg| arg.starts_with("-DFETCHCONTENT_BASE_DIR"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn connect_output_streams() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("output-streams").unwrap();
+        let cmake = temp.path().join("cmake");
+        std::fs::write(
+            &cmake,
+            "#!/bin/sh\n[ \"$1\" = --version ] && echo 'cmake version 3.28.1' && exit 0\n\
+             [ \"$1\" = --build ] && echo '-- building' && echo 'build warning' >&2 && exit 0\n\
+             echo '-- configuring'\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&cmake, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let log = temp.path().join("output.log");
+        let file = std::fs::File::create(&log).unwrap();
+        let report = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(temp.path().join("build").to_string_lossy())
+            .set_output_path(temp.path().join("bin").to_string_lossy())
+            .set_cmake_executable(cmake.to_string_lossy())
+            .set_output_streams(file.try_clone().unwrap(), file)
+            .build_with_output()
+            .unwrap();
+
+        let output = std::fs::read_to_string(&log).unwrap();
+        assert!(output.contains("-- configuring"));
+        assert!(output.contains("-- building"));
+        assert!(output.contains("build warning"));
+        assert!(report.phases.iter().all(|timing| timing.stdout.is_empty()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skip_unchanged_configure() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("skip-configure").unwrap();
+        let build = temp.path().join("build");
+        let cmake = temp.path().join("cmake");
+        let calls = temp.path().join("calls.log");
+        // configures like cmake would: the cache holds the defines it was given
+        std::fs::write(
+            &cmake,
+            format!(
+                "#!/bin/sh\n[ \"$1\" = --version ] && echo 'cmake version 3.28.1' && exit 0\n\
+                 echo \"$1\" >> {}\n\
+                 [ \"$1\" = --build ] && exit 0\n\
+                 mkdir -p {1}\n\
+                 echo 'CMAKE_GENERATOR:INTERNAL=Unix Makefiles' > {1}/CMakeCache.txt\n\
+                 for arg; do case $arg in -DFOO=*) echo \"FOO:UNINITIALIZED=${{arg#-DFOO=}}\" >> {1}/CMakeCache.txt;; esac; done\n",
+                calls.display(),
+                build.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&cmake, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let configures = || {
+            std::fs::read_to_string(&calls)
+                .unwrap()
+                .lines()
+                .filter(|call| *call != "--build")
+                .count()
+        };
+
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(build.to_string_lossy())
+            .set_output_path(temp.path().join("bin").to_string_lossy())
+            .set_cmake_executable(cmake.to_string_lossy())
+            .add_define("FOO", "ON")
+            .skip_configure_if_unchanged();
+
+        let first = cmd.clone().build_with_output().unwrap();
+        assert!(!first.configure_skipped);
+        assert_eq!(configures(), 1);
+        let second = cmd.clone().build_with_output().unwrap();
+        assert!(second.configure_skipped);
+        assert!(second.phases.iter().all(|t| t.phase != Phase::Configure));
+        assert_eq!(configures(), 1);
+
+        let changed = cmd.add_define("FOO", "OFF").build_with_output().unwrap();
+        assert!(!changed.configure_skipped);
+        assert_eq!(changed.phases[0].phase, Phase::Configure);
+        assert_eq!(configures(), 2);
+    }
+
+    #[test]
+    fn fresh_configure_and_clean_first() {
+        let temp = crate::guard::TempBuildDir::new("fresh").unwrap();
+        let build = temp.path().join("build");
+        std::fs::create_dir_all(build.join("CMakeFiles")).unwrap();
+        std::fs::write(
+            build.join("CMakeCache.txt"),
+            "CMAKE_CXX_COMPILER:FILEPATH=g++\n",
+        )
+        .unwrap();
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(build.to_string_lossy())
+            .set_output_path(temp.path().join("bin").to_string_lossy());
+
+        let report = cmd.clone().fresh(true).clean_first(true).build().unwrap();
+        assert!(!report.cache_reused);
+        assert_eq!(report.phases[0].command.args[0], "--fresh");
+        assert!(
+            report.phases[1]
+                .command
+                .args
+                .contains(&"--clean-first".to_string())
+        );
+
+        let report = cmd.clone().add_target("app").clean_target().unwrap();
+        assert_eq!(report.phases.len(), 1);
+        assert!(
+            report.phases[0]
+                .command
+                .args
+                .ends_with(&["--target".to_string(), "clean".to_string()])
+        );
+
+        let parallel = cmd.clean_first(true).build_targets_parallel(["a", "b"], 2);
+        assert!(matches!(parallel.build(), Err(Error::InvalidConfig(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pgo_generate_train_use() {
+        let temp = crate::guard::TempBuildDir::new("pgo").unwrap();
+        let build = temp.path().join("build");
+        std::fs::create_dir_all(&build).unwrap();
+        std::fs::write(
+            build.join("CMakeCache.txt"),
+            "CMAKE_CXX_COMPILER_ID:STRING=GNU\n",
+        )
+        .unwrap();
+        let profile_dir = build.canonicalize().unwrap().join(".cmakr/pgo");
+
+        let report = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(build.to_string_lossy())
+            .add_define("CMAKE_CXX_FLAGS", "-O2")
+            .pgo(PgoConfig::new(["true"]))
+            .unwrap();
+        let phases: Vec<String> = report
+            .phases
+            .iter()
+            .map(|timing| match &timing.target {
+                Some(name) => name.clone(),
+                None => timing.phase.to_string(),
+            })
+            .collect();
+        assert_eq!(
+            phases,
+            [
+                "configure",
+                "pgo-generate",
+                "configure",
+                "build",
+                "pgo-train",
+                "pgo-use",
+                "configure",
+                "build"
+            ]
+        );
+        let define = |phase: usize| {
+            report.phases[phase]
+                .command
+                .args
+                .iter()
+                .find(|arg| arg.starts_with("-DCMAKE_CXX_FLAGS="))
+                .cloned()
+        };
+        assert_eq!(define(0), Some("-DCMAKE_CXX_FLAGS=-O2".to_string()));
+        assert_eq!(
+            define(2),
+            Some(format!(
+                "-DCMAKE_CXX_FLAGS=-O2 -fprofile-generate={}",
+                profile_dir.display()
+            ))
+        );
+        assert!(define(6).unwrap().contains("-fprofile-use="));
+        assert!(profile_dir.is_dir());
+
+        let error = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(build.to_string_lossy())
+            .pgo(PgoConfig::new(["false"]))
+            .unwrap_err();
+        assert!(matches!(error, Error::ToolFailed(_)));
+    }
+
+    #[test]
+    fn run_custom_pipeline() {
+        let temp = crate::guard::TempBuildDir::new("pipeline").unwrap();
+        let prefix = temp.path().join("prefix");
+        let build = temp.path().join("build");
+        let cmd = Cmd::default()
+            .set_path("./test/")
+            .set_binary_path(build.to_string_lossy());
+        let error = cmd
+            .clone()
+            .run_pipeline(&Pipeline::new().install())
+            .unwrap_err();
+        assert!(matches!(error, Error::InvalidConfig(_)));
+
+        let pipeline = Pipeline::new()
+            .configure()
+            .custom("prefix", move |cmd, report| {
+                assert_eq!(report.completed_phases(), [Phase::Configure]);
+                std::fs::write(build.join("CMakeCache.txt"), "")?;
+                *cmd = cmd.clone().set_install_prefix(prefix.to_string_lossy());
+                Ok(())
+            })
+            .build()
+            .build()
+            .install();
+        let report = cmd.run_pipeline(&pipeline).unwrap();
+        let phases: Vec<Phase> = report.phases.iter().map(|timing| timing.phase).collect();
+        assert_eq!(
+            phases,
+            [
+                Phase::Configure,
+                Phase::Custom,
+                Phase::Build,
+                Phase::Build,
+                Phase::Install
+            ]
+        );
+        assert_eq!(report.phases[1].target.as_deref(), Some("prefix"));
+    }
+
+    #[test]
+    fn split_parallel_job_budget() {
+        assert_eq!(split_jobs(8, 3), vec![3, 3, 2]);
+        assert_eq!(split_jobs(2, 4), vec![1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn remap_reported_paths() {
+        let remaps = vec![
+            (
+                PathBuf::from("/tmp/copy"),
+                PathBuf::from("/home/me/project"),
+            ),
+            (PathBuf::from("/tmp"), PathBuf::from("/other")),
+        ];
+
+        assert_eq!(
+            remap_path(Path::new("/tmp/copy/src/func.c"), &remaps),
+            PathBuf::from("/home/me/project/src/func.c")
+        );
+        assert_eq!(
+            remap_path(Path::new("/tmp/x.c"), &remaps),
+            PathBuf::from("/other/x.c")
+        );
+        assert_eq!(
+            remap_path(Path::new("/usr/include/stdio.h"), &remaps),
+            PathBuf::from("/usr/include/stdio.h")
+        );
+    }
+
+    #[test]
+    fn update_cache_requires_configured_tree() {
+        let result = Cmd::update_cache("./test/", [("BUILD_TESTING", "ON")]);
+        assert!(result.is_err());
+    }
+    #[test]
+    fn build_system_files_are_not_artifacts() {
+        let temp = crate::guard::TempBuildDir::new("artifacts-build-dir").unwrap();
+        let mut cmd = Cmd::default().current_dir(temp.path().to_string_lossy());
+        cmd.resolve_paths().unwrap();
+        let build = temp.path().join("build");
+        std::fs::create_dir_all(build.join("CMakeFiles")).unwrap();
+        for file in ["CMakeCache.txt", "build.ninja", "libfoo.a", "app"] {
+            std::fs::write(build.join(file), "").unwrap();
+        }
+        let targets = [Target {
+            ty: "EXECUTABLE".to_string(),
+            artifacts: vec![build.join("app")],
+            ..Target::default()
+        }];
+
+        let artifacts = cmd.collect_artifacts(Some(&targets)).unwrap();
+        assert_eq!(artifacts, vec![build.join("app")]);
+        assert!(!artifacts.contains(&build.join("CMakeCache.txt")));
+        assert!(cmd.collect_artifacts(None).unwrap().is_empty());
+
+        let cmd = cmd.set_output_path(temp.path().join("out").to_string_lossy());
+        std::fs::create_dir_all(temp.path().join("out")).unwrap();
+        std::fs::write(temp.path().join("out/app"), "").unwrap();
+        assert_eq!(
+            cmd.collect_artifacts(None).unwrap(),
+            vec![temp.path().join("out/app")]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn list_versioned_library_symlinks() {
+        let temp = crate::guard::TempBuildDir::new("artifacts").unwrap();
+        let dir = temp.path().to_path_buf();
+        std::fs::create_dir_all(dir.join("subdir")).unwrap();
+        std::fs::write(dir.join("libfoo.so.1.2.3"), "").unwrap();
+        std::os::unix::fs::symlink("libfoo.so.1.2.3", dir.join("libfoo.so.1")).unwrap();
+        std::os::unix::fs::symlink("libfoo.so.1", dir.join("libfoo.so")).unwrap();
+
+        let report = BuildReport {
+            artifacts: list_files(&dir).unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(
+            report.artifacts,
+            vec![
+                dir.join("libfoo.so"),
+                dir.join("libfoo.so.1"),
+                dir.join("libfoo.so.1.2.3")
+            ]
+        );
+        assert_eq!(
+            report.real_artifacts().collect::<Vec<_>>(),
+            vec![&dir.join("libfoo.so.1.2.3")]
+        );
     }
 }
@@ -0,0 +1,312 @@
+//! Reproducible command lines.
+//!
+//! Every process cmakr runs is recorded as a [`PreparedCommand`] in the
+//! [`PhaseTiming`](crate::report::PhaseTiming) of its phase.
+//! [`PreparedCommand::to_shell`] renders it as a correctly quoted command
+//! line for a given shell, ready to be pasted into a bug report.
+//!
+//! Before anything runs, [`Cmd::dry_run`](crate::Cmd::dry_run) assembles the
+//! same commands into a [`CommandPlan`]: every command of the run in order,
+//! with its arguments, environment changes, and working directory. A plan is
+//! plain data, so it can be audited, compared against a known-good plan, or
+//! serialized with `serde`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, command::Shell};
+//!
+//! let report = Cmd::default().set_path("./my_project").build().unwrap();
+//! for timing in &report.phases {
+//!     println!("{}", timing.command.to_shell(Shell::Posix));
+//! }
+//! ```
+
+use std::{path::PathBuf, process::Command};
+
+use serde::{Deserialize, Serialize};
+
+use crate::credentials::Redactor;
+
+/// A shell to render a [`PreparedCommand`] for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    /// `sh`, `bash`, `zsh` and other POSIX shells.
+    Posix,
+    /// The Windows command prompt, `cmd.exe`.
+    Cmd,
+    /// Windows PowerShell and PowerShell 7.
+    PowerShell,
+}
+
+/// A program and its arguments, as run by cmakr.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreparedCommand {
+    /// The program name or path (e.g. `"cmake"`).
+    pub program: String,
+    /// The arguments passed to the program.
+    pub args: Vec<String>,
+    /// The environment variables set (`Some`) or removed (`None`) on top of
+    /// the inherited environment, sorted by name.
+    pub env: Vec<(String, Option<String>)>,
+    /// The working directory, if not the one cmakr runs in.
+    pub cwd: Option<PathBuf>,
+    /// Whether the program starts from an empty environment instead of
+    /// inheriting cmakr's, see [`Cmd::env_clear`](crate::Cmd::env_clear).
+    pub env_cleared: bool,
+}
+
+impl From<&Command> for PreparedCommand {
+    fn from(command: &Command) -> Self {
+        let mut env: Vec<(String, Option<String>)> = command
+            .get_envs()
+            .map(|(name, value)| {
+                (
+                    name.to_string_lossy().into_owned(),
+                    value.map(|value| value.to_string_lossy().into_owned()),
+                )
+            })
+            .collect();
+        env.sort();
+        Self {
+            program: command.get_program().to_string_lossy().into_owned(),
+            args: command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            env,
+            cwd: command.get_current_dir().map(PathBuf::from),
+            env_cleared: false,
+        }
+    }
+}
+
+impl PreparedCommand {
+    /// Renders the command as a single line that `shell` runs with exactly
+    /// this program and these arguments.
+    ///
+    /// Arguments containing spaces, quotes, or other special characters are
+    /// quoted; plain arguments are left as they are. Non-ASCII text is kept
+    /// verbatim inside quotes.
+    ///
+    /// # Arguments
+    ///
+    /// * `shell` - The shell the line will be pasted into.
+    pub fn to_shell(&self, shell: Shell) -> String {
+        let mut parts = Vec::with_capacity(self.args.len() + 1);
+
+        match shell {
+            Shell::Posix => {
+                parts.push(quote_posix(&self.program));
+                parts.extend(self.args.iter().map(|arg| quote_posix(arg)));
+            }
+            Shell::Cmd => {
+                parts.push(escape_cmd(&quote_windows_argv(&self.program)));
+                parts.extend(
+                    self.args
+                        .iter()
+                        .map(|arg| escape_cmd(&quote_windows_argv(arg))),
+                );
+            }
+            Shell::PowerShell => {
+                // a quoted program name is just a string unless invoked with `&`
+                let program = quote_powershell(&self.program);
+                if program.starts_with('\'') {
+                    parts.push(format!("& {}", program));
+                } else {
+                    parts.push(program);
+                }
+                parts.extend(self.args.iter().map(|arg| quote_powershell(arg)));
+            }
+        }
+
+        parts.join(" ")
+    }
+
+    /// Returns a command that runs this program with these arguments,
+    /// environment, and working directory.
+    pub(crate) fn to_command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        if self.env_cleared {
+            command.env_clear();
+        }
+        for (name, value) in &self.env {
+            match value {
+                Some(value) => command.env(name, value),
+                None => command.env_remove(name),
+            };
+        }
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        command
+    }
+
+    /// Redacts secrets from the arguments and environment values.
+    pub(crate) fn redact(&mut self, redactor: &Redactor) {
+        for arg in &mut self.args {
+            *arg = redactor.redact(arg).into_owned();
+        }
+        for (name, value) in &mut self.env {
+            if let Some(value) = value {
+                *value = crate::report::redact_variable(redactor, name, value);
+            }
+        }
+    }
+}
+
+/// The commands of a run, in the order they run.
+///
+/// Returned by [`Cmd::dry_run`](crate::Cmd::dry_run). Iterating a plan
+/// yields its commands.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandPlan {
+    pub commands: Vec<PreparedCommand>,
+}
+
+impl CommandPlan {
+    /// Renders the plan for `shell`, one command line per command.
+    ///
+    /// Only the programs and arguments are rendered, see
+    /// [`PreparedCommand::to_shell`].
+    ///
+    /// # Arguments
+    ///
+    /// * `shell` - The shell the lines will be pasted into.
+    pub fn to_shell(&self, shell: Shell) -> String {
+        self.commands
+            .iter()
+            .map(|command| command.to_shell(shell) + "\n")
+            .collect()
+    }
+}
+
+impl IntoIterator for CommandPlan {
+    type Item = PreparedCommand;
+    type IntoIter = std::vec::IntoIter<PreparedCommand>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.commands.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a CommandPlan {
+    type Item = &'a PreparedCommand;
+    type IntoIter = std::slice::Iter<'a, PreparedCommand>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.commands.iter()
+    }
+}
+
+/// Quotes an argument for a POSIX shell, using single quotes when needed.
+pub(crate) fn quote_posix(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-+=/.,:@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// Quotes an argument so that `CommandLineToArgvW` and the MSVC runtime parse
+/// it back unchanged.
+///
+/// Backslashes are only special right before a double quote, where they have
+/// to be doubled.
+fn quote_windows_argv(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '"']) {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        let escapes = if c == '"' {
+            backslashes * 2 + 1
+        } else {
+            backslashes
+        };
+        quoted.extend(std::iter::repeat_n('\\', escapes));
+        quoted.push(c);
+        backslashes = 0;
+    }
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Escapes the `cmd.exe` metacharacters of an already argv-quoted argument
+/// with carets, so that neither variable expansion nor redirection applies.
+fn escape_cmd(arg: &str) -> String {
+    let mut escaped = String::with_capacity(arg.len());
+    for c in arg.chars() {
+        if "()%!^\"<>&|".contains(c) {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Quotes an argument for PowerShell, using single quotes when needed.
+///
+/// Windows PowerShell splits unquoted native arguments like `-DX=a.b` at the
+/// `.`, so such arguments are quoted too. The typographic quotes `‘’‚‛` act
+/// like `'` and are doubled as well.
+pub(crate) fn quote_powershell(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-+=/.:\\".contains(c);
+    let split = arg.starts_with('-') && arg.contains(['.', ':']);
+    if !arg.is_empty() && arg.chars().all(plain) && !split {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from('\'');
+    for c in arg.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_for_each_shell() {
+        let command = PreparedCommand {
+            program: "C:\\Program Files\\CMake\\bin\\cmake.exe".to_string(),
+            args: vec![
+                "-S".to_string(),
+                "my project".to_string(),
+                "-DNAME=it's 100%".to_string(),
+                "-DPATH=C:\\dir with space\\".to_string(),
+                "ünïcode".to_string(),
+                String::new(),
+            ],
+            ..PreparedCommand::default()
+        };
+
+        assert_eq!(
+            command.to_shell(Shell::Posix),
+            r"'C:\Program Files\CMake\bin\cmake.exe' -S 'my project' '-DNAME=it'\''s 100%' '-DPATH=C:\dir with space\' 'ünïcode' ''"
+        );
+        assert_eq!(
+            command.to_shell(Shell::Cmd),
+            r#"^"C:\Program Files\CMake\bin\cmake.exe^" -S ^"my project^" ^"-DNAME=it's 100^%^" ^"-DPATH=C:\dir with space\\^" ünïcode ^"^""#
+        );
+        assert_eq!(
+            command.to_shell(Shell::PowerShell),
+            r"& 'C:\Program Files\CMake\bin\cmake.exe' -S 'my project' '-DNAME=it''s 100%' '-DPATH=C:\dir with space\' 'ünïcode' ''"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! CMake's compilation database.
+//!
+//! With `CMAKE_EXPORT_COMPILE_COMMANDS` on, the Makefile and Ninja
+//! generators write `compile_commands.json` into the build tree: one entry
+//! per compiled source file, with the directory the compiler runs in and its
+//! command line. [`CompileCommands`] reads it into structured entries, and
+//! [`CompileCommands::link_into`] makes it available to clangd and other
+//! tooling that looks for it in the source tree. For a link kept up to date
+//! on every configure, see [`Cmd::ide_support`](crate::Cmd::ide_support).
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, compile_commands::CompileCommands};
+//!
+//! let report = Cmd::default()
+//!     .set_path("./my_project")
+//!     .add_define("CMAKE_EXPORT_COMPILE_COMMANDS", "ON")
+//!     .build()
+//!     .unwrap();
+//! if let Some(database) = report.compile_commands().unwrap() {
+//!     for entry in &database.entries {
+//!         println!("{}: {}", entry.file.display(), entry.arguments.join(" "));
+//!     }
+//!     CompileCommands::link_into(&report.binary_dir, "./my_project").unwrap();
+//! }
+//! ```
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use crate::ide::IdeIntegration;
+
+/// The file name of the database in the build directory.
+pub(crate) const COMPILE_COMMANDS: &str = "compile_commands.json";
+
+/// The entries of a compilation database, in file order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompileCommands {
+    pub entries: Vec<CompileCommand>,
+}
+
+/// The compilation of a single source file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompileCommand {
+    /// The source file, absolute.
+    pub file: PathBuf,
+    /// The working directory of the compiler.
+    pub directory: PathBuf,
+    /// The compiler followed by its arguments. Split from the `command`
+    /// string if the entry has no `arguments` list, as CMake writes it.
+    pub arguments: Vec<String>,
+    /// The object file produced, if listed.
+    pub output: Option<PathBuf>,
+}
+
+impl CompileCommands {
+    /// Reads the compilation database of `binary_dir`.
+    ///
+    /// Returns `None` if there is none, e.g. because the generator writes no
+    /// database or `CMAKE_EXPORT_COMPILE_COMMANDS` is off.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be read or is not valid.
+    pub fn load<P>(binary_dir: P) -> io::Result<Option<CompileCommands>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = binary_dir.as_ref().join(COMPILE_COMMANDS);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Self::parse(&std::fs::read_to_string(path)?).map(Some)
+    }
+
+    /// Parses the content of a compilation database.
+    ///
+    /// Relative file paths are resolved against the entry's directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`io::ErrorKind::InvalidData`] error if the content is not
+    /// a JSON array.
+    pub fn parse(content: &str) -> io::Result<CompileCommands> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let document: Value = serde_json::from_str(content).map_err(|e| invalid(e.to_string()))?;
+        let Some(entries) = document.as_array() else {
+            return Err(invalid(
+                "a compilation database is a JSON array".to_string(),
+            ));
+        };
+        let entries = entries
+            .iter()
+            .map(|entry| {
+                let directory = PathBuf::from(entry["directory"].as_str().unwrap_or_default());
+                let arguments = match entry["arguments"].as_array() {
+                    Some(arguments) => arguments
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(str::to_string)
+                        .collect(),
+                    None => split_command(entry["command"].as_str().unwrap_or_default()),
+                };
+                CompileCommand {
+                    file: directory.join(entry["file"].as_str().unwrap_or_default()),
+                    output: entry["output"]
+                        .as_str()
+                        .map(|output| directory.join(output)),
+                    directory,
+                    arguments,
+                }
+            })
+            .collect();
+        Ok(CompileCommands { entries })
+    }
+
+    /// Returns the entries compiling `file`.
+    ///
+    /// A file compiled by several targets has one entry per target.
+    pub fn find<P>(&self, file: P) -> impl Iterator<Item = &CompileCommand>
+    where
+        P: AsRef<Path>,
+    {
+        let file = file.as_ref().to_path_buf();
+        self.entries.iter().filter(move |entry| entry.file == file)
+    }
+
+    /// Links the database of `binary_dir` into `dir`, usually the source
+    /// root: as a symlink, or a copy on Windows, where symlinks need extra
+    /// privileges. Returns the path of the link.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`io::ErrorKind::NotFound`] error if the build tree has no
+    /// database, or an error if the link cannot be created.
+    pub fn link_into<P, Q>(binary_dir: P, dir: Q) -> io::Result<PathBuf>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let database = binary_dir.as_ref().join(COMPILE_COMMANDS);
+        if !database.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} does not exist", database.display()),
+            ));
+        }
+        let ide = IdeIntegration::Clangd {
+            link_to: dir.as_ref().to_path_buf(),
+        };
+        ide.refresh(binary_dir.as_ref())?;
+        Ok(ide.link_path())
+    }
+}
+
+/// Splits a `command` string into arguments.
+///
+/// Arguments are separated by whitespace and may be quoted with double or
+/// single quotes. A backslash escapes the next character, except on
+/// Windows, where it only escapes a double quote or a backslash in front of
+/// one, as backslashes separate path components there.
+fn split_command(command: &str) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', Some('\'')) => current.get_or_insert_default().push(c),
+            ('\\', _) => {
+                let escaped = chars
+                    .peek()
+                    .copied()
+                    .filter(|next| !cfg!(windows) || *next == '"' || *next == '\\');
+                match escaped {
+                    Some(next) => {
+                        chars.next();
+                        current.get_or_insert_default().push(next);
+                    }
+                    None => current.get_or_insert_default().push(c),
+                }
+            }
+            ('"' | '\'', None) => {
+                quote = Some(c);
+                current.get_or_insert_default();
+            }
+            (c, Some(open)) if c == open => quote = None,
+            (c, None) if c.is_whitespace() => arguments.extend(current.take()),
+            (c, _) => current.get_or_insert_default().push(c),
+        }
+    }
+    arguments.extend(current);
+    arguments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_compile_commands() {
+        let content = r#"[
+            {"directory": "/b", "command": "/usr/bin/c++ -DNAME=\"a b\" -I/s/include '-DEMPTY=' -o CMakeFiles/app.dir/main.cpp.o -c /s/main.cpp", "file": "/s/main.cpp", "output": "CMakeFiles/app.dir/main.cpp.o"},
+            {"directory": "/b/lib", "arguments": ["cc", "-c", "util.c"], "file": "util.c"}
+        ]"#;
+        let database = CompileCommands::parse(content).unwrap();
+        assert_eq!(database.entries.len(), 2);
+        let main = &database.entries[0];
+        assert_eq!(
+            main.arguments,
+            [
+                "/usr/bin/c++",
+                "-DNAME=a b",
+                "-I/s/include",
+                "-DEMPTY=",
+                "-o",
+                "CMakeFiles/app.dir/main.cpp.o",
+                "-c",
+                "/s/main.cpp"
+            ]
+        );
+        assert_eq!(
+            main.output,
+            Some(PathBuf::from("/b/CMakeFiles/app.dir/main.cpp.o"))
+        );
+        assert_eq!(database.entries[1].file, PathBuf::from("/b/lib/util.c"));
+        assert_eq!(database.entries[1].arguments, ["cc", "-c", "util.c"]);
+        assert_eq!(database.find("/s/main.cpp").count(), 1);
+        assert_eq!(split_command(r#"a "" b"#), ["a", "", "b"]);
+        assert!(CompileCommands::parse("{}").is_err());
+
+        let temp = crate::guard::TempBuildDir::new("compile-commands").unwrap();
+        assert!(CompileCommands::load(temp.path()).unwrap().is_none());
+        assert!(CompileCommands::link_into(temp.path(), temp.path()).is_err());
+        let binary = temp.path().join("build");
+        std::fs::create_dir(&binary).unwrap();
+        std::fs::write(binary.join(COMPILE_COMMANDS), content).unwrap();
+        assert_eq!(
+            CompileCommands::load(&binary).unwrap(),
+            Some(database.clone())
+        );
+        let link = CompileCommands::link_into(&binary, temp.path()).unwrap();
+        assert_eq!(
+            CompileCommands::load(link.parent().unwrap()).unwrap(),
+            Some(database)
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Caching of CMake's compiler detection results across build trees.
+//!
+//! On the first configure of a tree, CMake identifies every enabled language's
+//! compiler, checks that it works, and detects its ABI, then records the
+//! results in `CMakeFiles/<cmake version>/CMake<LANG>Compiler.cmake` and
+//! `CMakeSystem.cmake`. When those files already exist in a fresh tree, CMake
+//! loads them instead of repeating the detection. With
+//! [`Cmd::set_compiler_cache_dir`](crate::Cmd::set_compiler_cache_dir), the
+//! files are stored in a user-level directory under a fingerprint of the
+//! toolchain and copied into new build trees with the same fingerprint.
+
+use std::path::{Path, PathBuf};
+
+use crate::lock::DirLock;
+
+/// Environment variables that influence which compiler CMake finds and how
+/// it is invoked.
+const TOOLCHAIN_ENV: [&str; 12] = [
+    "CC",
+    "CXX",
+    "ASM",
+    "AR",
+    "CFLAGS",
+    "CXXFLAGS",
+    "CPPFLAGS",
+    "LDFLAGS",
+    "SDKROOT",
+    "MACOSX_DEPLOYMENT_TARGET",
+    "VCINSTALLDIR",
+    "PATH",
+];
+
+/// Defines starting with `CMAKE_` that don't affect compiler detection.
+const UNRELATED_DEFINES: [&str; 4] = [
+    "CMAKE_BUILD_TYPE",
+    "CMAKE_INSTALL_PREFIX",
+    "CMAKE_EXPORT_COMPILE_COMMANDS",
+    "CMAKE_PREFIX_PATH",
+];
+
+/// Computes the cache key of a toolchain.
+///
+/// The key covers the CMake version, the host platform, the compiler-related
+/// environment variables, the modification times of the compilers named by
+/// `CC`/`CXX` (or `cc`/`c++`), and `toolchain_args`: the toolchain-related
+/// defines, generator arguments, and preset. A changed compiler or toolchain
+/// setting thus leads to a new key instead of stale detection results.
+pub(crate) fn fingerprint(cmake_version: &str, toolchain_args: &[String]) -> String {
+    let mut parts = vec![
+        cmake_version.to_string(),
+        std::env::consts::OS.to_string(),
+        std::env::consts::ARCH.to_string(),
+    ];
+    for name in TOOLCHAIN_ENV {
+        parts.push(format!(
+            "{}={}",
+            name,
+            std::env::var(name).unwrap_or_default()
+        ));
+    }
+    for (var, default) in [("CC", "cc"), ("CXX", "c++")] {
+        let program = std::env::var(var).unwrap_or_else(|_| default.to_string());
+        let program = program
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let modified = which::which(&program)
+            .ok()
+            .and_then(|path| path.metadata().ok())
+            .and_then(|metadata| metadata.modified().ok());
+        parts.push(format!("{}={:?}", program, modified));
+    }
+    parts.extend(toolchain_args.iter().cloned());
+
+    format!("{:016x}", fnv1a(parts.join("\n").as_bytes()))
+}
+
+/// Returns whether a `-D` define may influence compiler detection.
+pub(crate) fn is_toolchain_define(name: &str) -> bool {
+    name.starts_with("CMAKE_")
+        && !UNRELATED_DEFINES.contains(&name)
+        && !name.ends_with("_OUTPUT_DIRECTORY")
+}
+
+/// Copies cached detection results for `key` into a fresh build tree.
+///
+/// Returns whether an entry was found.
+pub(crate) fn restore(
+    cache_dir: &Path,
+    key: &str,
+    binary_dir: &Path,
+    cmake_version: &str,
+) -> std::io::Result<bool> {
+    let _lock = DirLock::acquire(cache_dir)?;
+    let entry = cache_dir.join(key);
+    if !entry.is_dir() {
+        return Ok(false);
+    }
+
+    let target = version_dir(binary_dir, cmake_version);
+    std::fs::create_dir_all(&target)?;
+    copy_detection_files(&entry, &target)?;
+    Ok(true)
+}
+
+/// Stores the detection results of a configured tree under `key`.
+///
+/// Files already in the entry are kept, so languages enabled by other
+/// projects with the same toolchain accumulate in one entry.
+pub(crate) fn store(
+    cache_dir: &Path,
+    key: &str,
+    binary_dir: &Path,
+    cmake_version: &str,
+) -> std::io::Result<()> {
+    let source = version_dir(binary_dir, cmake_version);
+    if !source.is_dir() {
+        return Ok(());
+    }
+
+    let _lock = DirLock::acquire(cache_dir)?;
+    let entry = cache_dir.join(key);
+    std::fs::create_dir_all(&entry)?;
+    copy_detection_files(&source, &entry)
+}
+
+/// Returns the directory CMake writes its per-version detection results to.
+fn version_dir(binary_dir: &Path, cmake_version: &str) -> PathBuf {
+    binary_dir.join("CMakeFiles").join(cmake_version)
+}
+
+/// Copies `CMakeSystem.cmake` and `CMake<LANG>Compiler.cmake` files from
+/// `from` to `to`, skipping files that already exist there.
+fn copy_detection_files(from: &Path, to: &Path) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let is_detection_file = name == "CMakeSystem.cmake"
+            || (name.starts_with("CMake") && name.ends_with("Compiler.cmake"));
+
+        let target = to.join(&*name);
+        if is_detection_file && entry.file_type()?.is_file() && !target.exists() {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Hashes `bytes` with 64-bit FNV-1a, which is stable across Rust versions.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::TempBuildDir;
+
+    #[test]
+    fn store_and_restore_detection_results() {
+        let temp = TempBuildDir::new("compilers").unwrap();
+        let root = temp.path().to_path_buf();
+        let cache = root.join("cache");
+        let configured = root.join("a");
+        let fresh = root.join("b");
+
+        let version_dir = configured.join("CMakeFiles").join("3.28.3");
+        std::fs::create_dir_all(version_dir.join("CompilerIdC")).unwrap();
+        std::fs::write(version_dir.join("CMakeSystem.cmake"), "").unwrap();
+        std::fs::write(version_dir.join("CMakeCCompiler.cmake"), "").unwrap();
+        std::fs::write(version_dir.join("CMakeDetermineCompilerABI_C.bin"), "").unwrap();
+
+        let key = fingerprint("3.28.3", &["-DCMAKE_C_COMPILER=clang".to_string()]);
+        assert_ne!(key, fingerprint("3.28.3", &[]));
+        assert!(!restore(&cache, &key, &fresh, "3.28.3").unwrap());
+
+        store(&cache, &key, &configured, "3.28.3").unwrap();
+        assert!(restore(&cache, &key, &fresh, "3.28.3").unwrap());
+        let restored = fresh.join("CMakeFiles").join("3.28.3");
+        assert!(restored.join("CMakeCCompiler.cmake").is_file());
+        assert!(restored.join("CMakeSystem.cmake").is_file());
+        assert!(!restored.join("CMakeDetermineCompilerABI_C.bin").exists());
+
+        assert!(is_toolchain_define("CMAKE_TOOLCHAIN_FILE"));
+        assert!(!is_toolchain_define("CMAKE_BUILD_TYPE"));
+    }
+}
@@ -0,0 +1,197 @@
+//! Crate-wide defaults for every new [`Cmd`].
+//!
+//! An organization with dozens of build scripts can keep their conventions
+//! in one place: the [`Defaults`] set with [`set_defaults`], or read from
+//! `CMAKR_*` environment variables, seed every [`Cmd::default`] and the
+//! profiles built on it. Builder calls override them as usual.
+//!
+//! | Field | Environment variable |
+//! |-------|----------------------|
+//! | `generator` | `CMAKR_GENERATOR` |
+//! | `jobs` | `CMAKR_JOBS` |
+//! | `cmake_path` | `CMAKR_CMAKE` |
+//! | `build_type` | `CMAKR_BUILD_TYPE` |
+//! | `fetch_cache_dir` | `CMAKR_FETCH_CACHE_DIR` |
+//! | `compiler_cache_dir` | `CMAKR_COMPILER_CACHE_DIR` |
+//! | `compiler_launcher` | `CMAKR_COMPILER_LAUNCHER` (`ccache`, `sccache`, `auto`, or a program) |
+//!
+//! A field set with [`set_defaults`] wins over its variable, so a variable
+//! only fills in what the program leaves open.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{
+//!     Cmd,
+//!     config::{self, Defaults},
+//!     launcher::Launcher,
+//! };
+//!
+//! config::set_defaults(Defaults {
+//!     generator: Some("Ninja".to_string()),
+//!     compiler_launcher: Some(Launcher::Auto),
+//!     ..Defaults::default()
+//! });
+//! let report = Cmd::default().set_path("./native").build().unwrap();
+//! ```
+//!
+//! [`Cmd`]: crate::Cmd
+//! [`Cmd::default`]: crate::Cmd::default
+
+use std::{path::PathBuf, sync::RwLock};
+
+use crate::{Cmd, launcher::Launcher};
+
+/// The defaults set with [`set_defaults`].
+static DEFAULTS: RwLock<Option<Defaults>> = RwLock::new(None);
+
+/// Settings every new [`Cmd`](crate::Cmd) starts with.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Defaults {
+    /// See [`Cmd::set_generator`](crate::Cmd::set_generator).
+    pub generator: Option<String>,
+    /// See [`Cmd::set_jobs`](crate::Cmd::set_jobs).
+    pub jobs: Option<usize>,
+    /// See [`Cmd::set_cmake_executable`](crate::Cmd::set_cmake_executable).
+    pub cmake_path: Option<PathBuf>,
+    /// See [`Cmd::set_build_type`](crate::Cmd::set_build_type).
+    pub build_type: Option<String>,
+    /// See [`Cmd::set_fetch_cache_dir`](crate::Cmd::set_fetch_cache_dir).
+    pub fetch_cache_dir: Option<PathBuf>,
+    /// See [`Cmd::set_compiler_cache_dir`](crate::Cmd::set_compiler_cache_dir).
+    pub compiler_cache_dir: Option<PathBuf>,
+    /// See [`Cmd::with_compiler_launcher`](crate::Cmd::with_compiler_launcher).
+    pub compiler_launcher: Option<Launcher>,
+}
+
+impl Defaults {
+    /// Reads the defaults from the `CMAKR_*` environment variables; empty
+    /// variables and job counts that aren't numbers are ignored.
+    pub fn from_env() -> Self {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Reads the defaults through `lookup`, which returns the value of a
+    /// variable.
+    fn from_lookup<F>(lookup: F) -> Self
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let var = |name: &str| lookup(name).filter(|value| !value.is_empty());
+        Defaults {
+            generator: var("CMAKR_GENERATOR"),
+            jobs: var("CMAKR_JOBS").and_then(|jobs| jobs.trim().parse().ok()),
+            cmake_path: var("CMAKR_CMAKE").map(PathBuf::from),
+            build_type: var("CMAKR_BUILD_TYPE"),
+            fetch_cache_dir: var("CMAKR_FETCH_CACHE_DIR").map(PathBuf::from),
+            compiler_cache_dir: var("CMAKR_COMPILER_CACHE_DIR").map(PathBuf::from),
+            compiler_launcher: var("CMAKR_COMPILER_LAUNCHER")
+                .and_then(|launcher| launcher.parse().ok()),
+        }
+    }
+
+    /// Returns these defaults with the fields left open taken from `other`.
+    fn or(self, other: Defaults) -> Self {
+        Defaults {
+            generator: self.generator.or(other.generator),
+            jobs: self.jobs.or(other.jobs),
+            cmake_path: self.cmake_path.or(other.cmake_path),
+            build_type: self.build_type.or(other.build_type),
+            fetch_cache_dir: self.fetch_cache_dir.or(other.fetch_cache_dir),
+            compiler_cache_dir: self.compiler_cache_dir.or(other.compiler_cache_dir),
+            compiler_launcher: self.compiler_launcher.or(other.compiler_launcher),
+        }
+    }
+
+    /// Applies the defaults to `cmd`.
+    pub(crate) fn apply(self, mut cmd: Cmd) -> Cmd {
+        let path = |path: PathBuf| path.to_string_lossy().into_owned();
+        if let Some(generator) = self.generator {
+            cmd = cmd.set_generator(generator);
+        }
+        if let Some(jobs) = self.jobs {
+            cmd = cmd.set_jobs(jobs);
+        }
+        if let Some(cmake_path) = self.cmake_path {
+            cmd = cmd.set_cmake_executable(path(cmake_path));
+        }
+        if let Some(build_type) = self.build_type {
+            cmd = cmd.set_build_type(build_type);
+        }
+        if let Some(dir) = self.fetch_cache_dir {
+            cmd = cmd.set_fetch_cache_dir(path(dir));
+        }
+        if let Some(dir) = self.compiler_cache_dir {
+            cmd = cmd.set_compiler_cache_dir(path(dir));
+        }
+        if let Some(launcher) = self.compiler_launcher {
+            cmd = cmd.with_compiler_launcher(launcher);
+        }
+        cmd
+    }
+}
+
+/// Sets the defaults of every [`Cmd`](crate::Cmd) created afterwards in
+/// this process, replacing earlier ones. Fields left at `None` fall back to
+/// their environment variables.
+///
+/// # Arguments
+///
+/// * `defaults` - The defaults.
+pub fn set_defaults(defaults: Defaults) {
+    let mut slot = DEFAULTS
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    *slot = Some(defaults);
+}
+
+/// Returns the defaults new [`Cmd`](crate::Cmd)s start with: those set with
+/// [`set_defaults`], completed from the environment.
+pub fn defaults() -> Defaults {
+    let set = DEFAULTS
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+        .unwrap_or_default();
+    set.or(Defaults::from_env())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seed_commands_with_defaults() {
+        let env = Defaults::from_lookup(|name| match name {
+            "CMAKR_GENERATOR" => Some("Unix Makefiles".to_string()),
+            "CMAKR_JOBS" => Some("4".to_string()),
+            "CMAKR_BUILD_TYPE" => Some(String::new()),
+            "CMAKR_COMPILER_LAUNCHER" => Some("auto".to_string()),
+            _ => None,
+        });
+        assert_eq!(env.jobs, Some(4));
+        assert_eq!(env.build_type, None);
+        assert_eq!(env.compiler_launcher, Some(Launcher::Auto));
+
+        let defaults = Defaults {
+            generator: Some("Ninja".to_string()),
+            build_type: Some("Release".to_string()),
+            ..Defaults::default()
+        }
+        .or(env);
+        assert_eq!(defaults.generator.as_deref(), Some("Ninja"));
+        assert_eq!(defaults.jobs, Some(4));
+
+        let settings = defaults
+            .apply(Cmd::default())
+            .set_path("./test/")
+            .effective_settings()
+            .unwrap();
+        assert_eq!(settings.generator.as_deref(), Some("Ninja"));
+        assert!(
+            settings
+                .defines
+                .contains(&("CMAKE_BUILD_TYPE".to_string(), "Release".to_string()))
+        );
+    }
+}
@@ -0,0 +1,520 @@
+//! CMake's structured configure log.
+//!
+//! CMake 3.26 and later record the checks run during configure in
+//! `CMakeFiles/CMakeConfigureLog.yaml`: every `try_compile` and `try_run`
+//! with the name of the check, its result variable and the full output of the
+//! test build, and with CMake 3.31 and later every `find_*` call with the
+//! directories it searched. [`BuildReport::configure_log`] reads it into a
+//! [`ConfigureLog`], which tells why a feature check failed without scraping
+//! `CMakeError.log`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, Error};
+//!
+//! if let Err(Error::ConfigureFailed(failure)) = Cmd::default().set_path("./my_project").build() {
+//!     if let Ok(Some(log)) = failure.report().configure_log() {
+//!         for check in log.failed_checks() {
+//!             eprintln!("{:?} failed:\n{}", check.checks, check.stdout);
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! [`BuildReport::configure_log`]: crate::report::BuildReport::configure_log
+
+use std::path::{Path, PathBuf};
+
+use serde_json::{Map, Value};
+
+/// Path of the log relative to the build directory.
+pub(crate) const LOG_FILE: &str = "CMakeFiles/CMakeConfigureLog.yaml";
+
+/// The events of a configure log, in the order they happened.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigureLog {
+    pub events: Vec<ConfigureEvent>,
+}
+
+/// A single entry of the configure log.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigureEvent {
+    /// A `try_compile-v1` event.
+    TryCompile(TryCompile),
+    /// A `try_run-v1` event.
+    TryRun(TryRun),
+    /// A `find-v1` event.
+    Find(FindResult),
+    /// A `message-v1` event, written by `message(CONFIGURE_LOG)`.
+    Message(String),
+    /// An event of a kind not known to cmakr, with its `kind`.
+    Other(String),
+}
+
+/// A test build run by `try_compile` (or the compile step of `try_run`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TryCompile {
+    /// The names of the checks the build belongs to (e.g. `"Performing Test HAVE_FOO"`).
+    pub checks: Vec<String>,
+    pub source_dir: Option<PathBuf>,
+    pub binary_dir: Option<PathBuf>,
+    /// The variable the result is stored in.
+    pub variable: Option<String>,
+    /// Whether the result variable was stored in the cache.
+    pub cached: bool,
+    /// The output of the test build.
+    pub stdout: String,
+    pub exit_code: Option<i64>,
+}
+
+impl TryCompile {
+    /// Returns whether the test build succeeded.
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// A test program built and run by `try_run`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TryRun {
+    pub compile: TryCompile,
+    /// The variable the run result is stored in.
+    pub run_variable: Option<String>,
+    pub run_stdout: String,
+    pub run_stderr: String,
+    /// The exit code of the program, `None` if it was not run.
+    pub run_exit_code: Option<i64>,
+}
+
+/// A `find_program`, `find_library`, `find_file`, `find_path`, or
+/// `find_package` call.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FindResult {
+    /// What was searched for (e.g. `"program"` or `"library"`).
+    pub mode: String,
+    pub variable: String,
+    /// The names that were looked for.
+    pub names: Vec<String>,
+    /// The directories that were searched, in order.
+    pub searched_directories: Vec<PathBuf>,
+    /// The path found, `None` if nothing was found.
+    pub found: Option<PathBuf>,
+}
+
+impl ConfigureLog {
+    /// Reads the configure log of `binary_dir`.
+    ///
+    /// Returns `None` if there is no log, e.g. because CMake is older than 3.26.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the log cannot be read.
+    pub fn load<P>(binary_dir: P) -> std::io::Result<Option<ConfigureLog>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = binary_dir.as_ref().join(LOG_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(Self::parse(&std::fs::read_to_string(path)?)))
+    }
+
+    /// Parses the content of a configure log.
+    ///
+    /// Only the subset of YAML that CMake writes is understood; anything else
+    /// is skipped rather than treated as an error.
+    pub fn parse(content: &str) -> ConfigureLog {
+        let document = yaml::parse(content);
+        let events = document["events"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(ConfigureEvent::from_value)
+            .collect();
+        ConfigureLog { events }
+    }
+
+    /// Returns the test builds, including those of `try_run`, that failed.
+    pub fn failed_checks(&self) -> impl Iterator<Item = &TryCompile> {
+        self.events
+            .iter()
+            .filter_map(|event| match event {
+                ConfigureEvent::TryCompile(compile) => Some(compile),
+                ConfigureEvent::TryRun(run) => Some(&run.compile),
+                _ => None,
+            })
+            .filter(|compile| !compile.succeeded())
+    }
+
+    /// Returns the `find_*` calls that found nothing.
+    pub fn failed_finds(&self) -> impl Iterator<Item = &FindResult> {
+        self.events.iter().filter_map(|event| match event {
+            ConfigureEvent::Find(find) if find.found.is_none() => Some(find),
+            _ => None,
+        })
+    }
+}
+
+impl ConfigureEvent {
+    fn from_value(event: &Value) -> ConfigureEvent {
+        let kind = event["kind"].as_str().unwrap_or_default();
+        match kind {
+            "try_compile-v1" => ConfigureEvent::TryCompile(TryCompile::from_value(event)),
+            "try_run-v1" => {
+                let run = &event["runResult"];
+                ConfigureEvent::TryRun(TryRun {
+                    compile: TryCompile::from_value(event),
+                    run_variable: run["variable"].as_str().map(str::to_string),
+                    run_stdout: string(&run["stdout"]),
+                    run_stderr: string(&run["stderr"]),
+                    run_exit_code: run["exitCode"].as_i64(),
+                })
+            }
+            "find-v1" => ConfigureEvent::Find(FindResult {
+                mode: string(&event["mode"]),
+                variable: string(&event["variable"]),
+                names: strings(&event["names"]),
+                searched_directories: strings(&event["searched_directories"])
+                    .into_iter()
+                    .map(PathBuf::from)
+                    .collect(),
+                // `found` is `false` when nothing was found
+                found: event["found"].as_str().map(PathBuf::from),
+            }),
+            "message-v1" => ConfigureEvent::Message(string(&event["message"])),
+            _ => ConfigureEvent::Other(kind.to_string()),
+        }
+    }
+}
+
+impl TryCompile {
+    fn from_value(event: &Value) -> TryCompile {
+        let build = &event["buildResult"];
+        TryCompile {
+            checks: strings(&event["checks"]),
+            source_dir: event["directories"]["source"].as_str().map(PathBuf::from),
+            binary_dir: event["directories"]["binary"].as_str().map(PathBuf::from),
+            variable: build["variable"].as_str().map(str::to_string),
+            cached: build["cached"].as_bool().unwrap_or(false),
+            stdout: string(&build["stdout"]),
+            exit_code: build["exitCode"].as_i64(),
+        }
+    }
+}
+
+/// Returns a string value, or an empty string for anything else.
+fn string(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+/// Returns the strings of a sequence value.
+fn strings(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(str::to_string)
+        .collect()
+}
+
+/// A reader for the block-style YAML subset CMake writes: nested mappings
+/// and sequences, quoted and plain scalars, and `|` literal blocks.
+mod yaml {
+    use super::*;
+
+    /// Parses the first document of `content`.
+    pub(super) fn parse(content: &str) -> Value {
+        let mut lines: Vec<String> = content
+            .lines()
+            .skip_while(|line| line.trim().is_empty() || line.trim_start().starts_with('#'))
+            .skip_while(|line| line.trim() == "---")
+            .take_while(|line| line.trim() != "..." && line.trim() != "---")
+            .map(str::to_string)
+            .collect();
+        let mut i = 0;
+        node(&mut lines, &mut i, 0)
+    }
+
+    /// Returns the indentation of a line.
+    fn indent(line: &str) -> usize {
+        line.len() - line.trim_start_matches(' ').len()
+    }
+
+    /// Advances `i` past blank and comment lines.
+    fn skip_blank(lines: &[String], i: &mut usize) {
+        while *i < lines.len() {
+            let trimmed = lines[*i].trim();
+            if !trimmed.is_empty() && !trimmed.starts_with('#') {
+                break;
+            }
+            *i += 1;
+        }
+    }
+
+    /// Parses the node starting at line `i`, indented by at least `min_indent`.
+    fn node(lines: &mut [String], i: &mut usize, min_indent: usize) -> Value {
+        skip_blank(lines, i);
+        if *i >= lines.len() || indent(&lines[*i]) < min_indent {
+            return Value::Null;
+        }
+        let level = indent(&lines[*i]);
+        let content = lines[*i].trim_start();
+        if content == "-" || content.starts_with("- ") {
+            sequence(lines, i, level)
+        } else if split_key(content).is_some() {
+            mapping(lines, i, level)
+        } else {
+            let value = scalar(content.trim());
+            *i += 1;
+            value
+        }
+    }
+
+    /// Parses a block sequence whose dashes are indented by `level`.
+    fn sequence(lines: &mut [String], i: &mut usize, level: usize) -> Value {
+        let mut items = Vec::new();
+        loop {
+            skip_blank(lines, i);
+            if *i >= lines.len() || indent(&lines[*i]) != level {
+                break;
+            }
+            let content = lines[*i].trim_start();
+            let Some(rest) = content.strip_prefix('-') else {
+                break;
+            };
+            if !(rest.is_empty() || rest.starts_with(' ')) {
+                break;
+            }
+            let rest = rest.trim_start();
+            if rest.is_empty() {
+                *i += 1;
+                items.push(node(lines, i, level + 1));
+            } else {
+                // "- key: value" starts a mapping at the column of the key
+                let column = lines[*i].len() - rest.len();
+                lines[*i] = format!("{}{}", " ".repeat(column), rest);
+                items.push(node(lines, i, column));
+            }
+        }
+        Value::Array(items)
+    }
+
+    /// Parses a block mapping whose keys are indented by `level`.
+    fn mapping(lines: &mut [String], i: &mut usize, level: usize) -> Value {
+        let mut map = Map::new();
+        loop {
+            skip_blank(lines, i);
+            if *i >= lines.len() || indent(&lines[*i]) != level {
+                break;
+            }
+            let content = lines[*i].trim_start().to_string();
+            let Some((key, value)) = split_key(&content) else {
+                break;
+            };
+            *i += 1;
+            let value = match value {
+                "" => {
+                    skip_blank(lines, i);
+                    // a sequence may start at the indentation of its key
+                    let nested = *i < lines.len()
+                        && (indent(&lines[*i]) > level
+                            || (indent(&lines[*i]) == level
+                                && lines[*i].trim_start().starts_with('-')));
+                    if nested {
+                        node(lines, i, level)
+                    } else {
+                        Value::Null
+                    }
+                }
+                "|" | "|-" | "|+" => literal_block(lines, i, level, value),
+                value => scalar(value),
+            };
+            map.insert(key, value);
+        }
+        Value::Object(map)
+    }
+
+    /// Reads the lines of a `|` block scalar following a key at `level`.
+    fn literal_block(lines: &[String], i: &mut usize, level: usize, header: &str) -> Value {
+        let start = *i;
+        while *i < lines.len() && (lines[*i].trim().is_empty() || indent(&lines[*i]) > level) {
+            *i += 1;
+        }
+        let block = &lines[start..*i];
+        let block_indent = block
+            .iter()
+            .find(|line| !line.trim().is_empty())
+            .map_or(0, |line| indent(line));
+        let mut text: Vec<&str> = block
+            .iter()
+            .map(|line| line.get(block_indent..).unwrap_or_default())
+            .collect();
+        while header != "|+" && text.last().is_some_and(|line| line.is_empty()) {
+            text.pop();
+        }
+        let mut text = text.join("\n");
+        if header == "|" || header == "|+" {
+            text.push('\n');
+        }
+        Value::String(text)
+    }
+
+    /// Splits `key: value` (or `key:`), returning the unquoted key.
+    fn split_key(content: &str) -> Option<(String, &str)> {
+        let key_end = if let Some(quoted) = content.strip_prefix('"') {
+            let mut escaped = false;
+            let close = quoted.char_indices().find(|&(_, c)| {
+                let end = c == '"' && !escaped;
+                escaped = c == '\\' && !escaped;
+                end
+            })?;
+            close.0 + 2
+        } else {
+            content.find(": ").unwrap_or_else(|| {
+                if content.ends_with(':') {
+                    content.len() - 1
+                } else {
+                    usize::MAX
+                }
+            })
+        };
+        let rest = content.get(key_end..)?;
+        let value = rest.strip_prefix(':')?;
+        if !(value.is_empty() || value.starts_with(' ')) {
+            return None;
+        }
+        let key = match scalar(&content[..key_end]) {
+            Value::String(key) => key,
+            other => other.to_string(),
+        };
+        Some((key, value.trim()))
+    }
+
+    /// Parses a single-line scalar or empty flow collection.
+    fn scalar(value: &str) -> Value {
+        if let Some(quoted) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            return Value::String(unescape(quoted));
+        }
+        if let Some(quoted) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+            return Value::String(quoted.replace("''", "'"));
+        }
+        match value {
+            "" | "~" | "null" => Value::Null,
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "[]" => Value::Array(Vec::new()),
+            "{}" => Value::Object(Map::new()),
+            _ => value
+                .parse::<i64>()
+                .map_or_else(|_| Value::String(value.to_string()), Value::from),
+        }
+    }
+
+    /// Resolves the escape sequences of a double-quoted scalar.
+    fn unescape(value: &str) -> String {
+        let mut result = String::with_capacity(value.len());
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                result.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some('0') => result.push('\0'),
+                Some('u') => {
+                    let code: String = chars.by_ref().take(4).collect();
+                    if let Some(c) = u32::from_str_radix(&code, 16).ok().and_then(char::from_u32) {
+                        result.push(c);
+                    }
+                }
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = r#"
+---
+events:
+  -
+    kind: "message-v1"
+    backtrace:
+      - "CMakeLists.txt:2 (project)"
+    message: |
+      The system is: Linux - 6.1 - x86_64
+  -
+    kind: "try_compile-v1"
+    backtrace:
+      - "/usr/share/cmake/Modules/CheckIncludeFile.cmake:90 (try_compile)"
+    checks:
+      - "Looking for sys/epoll.h"
+    directories:
+      source: "/b/CMakeFiles/CMakeScratch/TryCompile-abc"
+      binary: "/b/CMakeFiles/CMakeScratch/TryCompile-abc"
+    cmakeVariables:
+      CMAKE_C_FLAGS: ""
+    buildResult:
+      variable: "HAVE_SYS_EPOLL_H"
+      cached: true
+      stdout: |
+        Change Dir: '/b/CMakeFiles/CMakeScratch/TryCompile-abc'
+
+        fatal error: sys/epoll.h: No such file or directory
+      exitCode: 1
+  -
+    kind: "find-v1"
+    mode: "program"
+    variable: "CMAKE_AR"
+    names:
+      - "ar"
+    searched_directories:
+      - "/usr/local/bin/"
+      - "/usr/bin/"
+    found: false
+  -
+    kind: "future-v9"
+...
+"#;
+
+    #[test]
+    fn parse_configure_log() {
+        let log = ConfigureLog::parse(LOG);
+        assert_eq!(log.events.len(), 4);
+        assert_eq!(
+            log.events[0],
+            ConfigureEvent::Message("The system is: Linux - 6.1 - x86_64\n".to_string())
+        );
+
+        let failed: Vec<&TryCompile> = log.failed_checks().collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].checks, vec!["Looking for sys/epoll.h"]);
+        assert_eq!(failed[0].variable.as_deref(), Some("HAVE_SYS_EPOLL_H"));
+        assert!(failed[0].cached);
+        assert_eq!(failed[0].exit_code, Some(1));
+        assert_eq!(
+            failed[0].stdout,
+            "Change Dir: '/b/CMakeFiles/CMakeScratch/TryCompile-abc'\n\nfatal error: sys/epoll.h: No such file or directory\n"
+        );
+
+        let finds: Vec<&FindResult> = log.failed_finds().collect();
+        assert_eq!(finds[0].variable, "CMAKE_AR");
+        assert_eq!(finds[0].names, vec!["ar"]);
+        assert_eq!(finds[0].searched_directories.len(), 2);
+        assert_eq!(
+            log.events[3],
+            ConfigureEvent::Other("future-v9".to_string())
+        );
+    }
+}
@@ -0,0 +1,294 @@
+//! Packaging a build tree with CPack.
+//!
+//! [`CPackCmd`] runs `cpack` in a built tree and returns the paths of the
+//! packages it produced, e.g. to upload them from CI. Create one with
+//! [`CPackCmd::new`], or with [`Cmd::cpack`](crate::Cmd::cpack) to package
+//! the tree of a build. The project's `CMakeLists.txt` must `include(CPack)`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, cpack::Generator};
+//!
+//! let cmd = Cmd::default().set_path("./my_project");
+//! cmd.clone().build().unwrap();
+//!
+//! let packages = cmd
+//!     .cpack()
+//!     .add_generator(Generator::Tgz)
+//!     .add_generator(Generator::Deb)
+//!     .run()
+//!     .unwrap();
+//! for package in packages {
+//!     println!("upload {}", package.display());
+//! }
+//! ```
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Instant, SystemTime},
+};
+
+use crate::{
+    command::PreparedCommand,
+    credentials::Redactor,
+    error::Error,
+    instrument,
+    process::{self, CancelToken},
+    report::{BuildReport, Phase, PhaseTiming, ProcessExit},
+};
+
+/// A CPack generator, i.e. a package format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Generator {
+    /// A `.tar.gz` archive.
+    Tgz,
+    /// A `.zip` archive.
+    Zip,
+    /// A Debian package.
+    Deb,
+    /// An RPM package.
+    Rpm,
+    /// A Windows installer built with NSIS.
+    Nsis,
+    /// Any other generator, by its CPack name (e.g. `"WIX"`, `"DragNDrop"`).
+    Other(String),
+}
+
+impl Generator {
+    /// Returns the name CPack knows the generator by.
+    pub fn name(&self) -> &str {
+        match self {
+            Generator::Tgz => "TGZ",
+            Generator::Zip => "ZIP",
+            Generator::Deb => "DEB",
+            Generator::Rpm => "RPM",
+            Generator::Nsis => "NSIS",
+            Generator::Other(name) => name,
+        }
+    }
+}
+
+/// A builder for a `cpack` run.
+#[derive(Debug, Clone)]
+pub struct CPackCmd {
+    /// The build directory to package, which cpack runs in.
+    binary_path: PathBuf,
+    /// Generators to run (passed as `-G <a;b>`). Empty means `CPACK_GENERATOR`.
+    generators: Vec<Generator>,
+    /// Config file used instead of `CPackConfig.cmake` (passed as `--config`).
+    config_file: Option<PathBuf>,
+    /// Build configuration to package (passed as `-C`).
+    configuration: Option<String>,
+    /// Directory the packages are written to (passed as `-B`).
+    package_dir: Option<PathBuf>,
+    /// Extra arguments passed to cpack.
+    args: Vec<String>,
+    /// Token used by orchestrators to kill a running cpack.
+    cancel: Option<CancelToken>,
+    /// The cpack executable. Defaults to `cpack` on `PATH`.
+    program: PathBuf,
+}
+
+impl CPackCmd {
+    /// Creates a cpack run for the build tree at `binary_path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `binary_path` - Path to a configured and built CMake build directory.
+    pub fn new<T>(binary_path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Self {
+            binary_path: PathBuf::from(binary_path.into()),
+            generators: Vec::new(),
+            config_file: None,
+            configuration: None,
+            package_dir: None,
+            args: Vec::new(),
+            cancel: None,
+            program: PathBuf::from("cpack"),
+        }
+    }
+
+    /// Sets the cpack executable to run, instead of `cpack` on `PATH`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the executable.
+    pub fn set_executable<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.program = PathBuf::from(path.into());
+        self
+    }
+
+    /// Adds a generator to run.
+    ///
+    /// Can be called several times to produce several package formats.
+    /// Without generators, the project's `CPACK_GENERATOR` is used.
+    pub fn add_generator(mut self, generator: Generator) -> Self {
+        self.generators.push(generator);
+        self
+    }
+
+    /// Reads the CPack configuration from `path` instead of the build
+    /// tree's `CPackConfig.cmake` (`--config <path>`).
+    pub fn set_config_file<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.config_file = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Packages the given configuration of a multi-config build (`-C <configuration>`).
+    pub fn set_configuration<T>(mut self, configuration: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.configuration = Some(configuration.into());
+        self
+    }
+
+    /// Writes the packages into `path` instead of the build directory (`-B <path>`).
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The package directory. Created if it does not exist.
+    pub fn set_package_dir<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.package_dir = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Adds an extra argument to the cpack command.
+    pub fn add_arg<T>(mut self, arg: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Attaches a token that kills the running cpack process when cancelled.
+    pub(crate) fn set_cancel_token(mut self, token: Option<CancelToken>) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Runs cpack and returns the absolute paths of the produced packages.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - `cpack` cannot be run, or is cancelled
+    /// - cpack fails, e.g. because the tree has no `CPackConfig.cmake` or a
+    ///   generator's tool (`dpkg-deb`, `rpmbuild`, `makensis`) is missing
+    pub fn run(&self) -> Result<Vec<PathBuf>, Error> {
+        self.run_phase().map(|(packages, _)| packages)
+    }
+
+    /// Runs cpack like [`run`](CPackCmd::run), and also returns the timing
+    /// of the run as a package phase.
+    pub(crate) fn run_phase(&self) -> Result<(Vec<PathBuf>, PhaseTiming), Error> {
+        let binary_dir = self.binary_path.canonicalize()?;
+        let mut command = Command::new(&self.program);
+        command.current_dir(&binary_dir);
+        if !self.generators.is_empty() {
+            let names: Vec<&str> = self.generators.iter().map(Generator::name).collect();
+            command.arg("-G").arg(names.join(";"));
+        }
+        if let Some(path) = &self.config_file {
+            command.arg("--config").arg(std::path::absolute(path)?);
+        }
+        if let Some(configuration) = &self.configuration {
+            command.arg("-C").arg(configuration);
+        }
+        if let Some(path) = &self.package_dir {
+            std::fs::create_dir_all(path)?;
+            command.arg("-B").arg(path.canonicalize()?);
+        }
+        command.args(&self.args);
+
+        let start = Instant::now();
+        let started_at = SystemTime::now();
+        let redactor = Redactor::default();
+        let output = instrument::run(Phase::Package, &mut command, &redactor, |command| {
+            process::run(command, self.cancel.as_ref(), None, None, &redactor)
+        })?;
+        if output.cancelled {
+            return Err(Error::cancelled("cpack", BuildReport::default()));
+        }
+        if !output.status.success() {
+            let error = output
+                .stderr
+                .lines()
+                .find(|line| line.starts_with("CPack Error:"))
+                .unwrap_or_else(|| output.stderr.trim());
+            return Err(Error::ToolFailed(format!(
+                "cpack failed: {}\n{}",
+                ProcessExit::from(output.status),
+                error
+            )));
+        }
+
+        let packages = parse_packages(&output.stdout, &binary_dir);
+        let timing = PhaseTiming {
+            phase: Phase::Package,
+            target: None,
+            command: PreparedCommand::from(&command),
+            started_at,
+            duration: start.elapsed(),
+            exit: ProcessExit::from(output.status),
+            stdout: output.stdout,
+            stderr: output.stderr,
+        };
+        Ok((packages, timing))
+    }
+}
+
+/// Collects the packages cpack reports as `CPack: - package: <path> generated.`
+fn parse_packages(stdout: &str, binary_dir: &Path) -> Vec<PathBuf> {
+    stdout
+        .lines()
+        .filter_map(|line| {
+            line.trim()
+                .strip_prefix("CPack: - package: ")?
+                .strip_suffix(" generated.")
+        })
+        .map(|path| binary_dir.join(path))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_generated_packages() {
+        let stdout = "\
+CPack: Create package using TGZ
+CPack: Install projects
+CPack: - Run preinstall target for: demo
+CPack: Create package
+CPack: - package: /work/build/demo-1.0-Linux.tar.gz generated.
+CPack: - checksum file: /work/build/demo-1.0-Linux.tar.gz.sha256 generated.
+CPack: Create package using DEB
+CPack: - package: packages/demo_1.0_amd64.deb generated.
+";
+        assert_eq!(
+            parse_packages(stdout, Path::new("/work/build")),
+            vec![
+                PathBuf::from("/work/build/demo-1.0-Linux.tar.gz"),
+                PathBuf::from("/work/build/packages/demo_1.0_amd64.deb"),
+            ]
+        );
+        assert_eq!(Generator::Other("WIX".to_string()).name(), "WIX");
+    }
+}
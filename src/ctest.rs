@@ -0,0 +1,535 @@
+//! Running CTest and collecting per-test results.
+//!
+//! [`CTestCmd`] runs `ctest` on a build tree, optionally through a test
+//! preset from `CMakePresets.json`, and reads the outcome of every test from
+//! the JUnit file ctest writes with `--output-junit` (CMake 3.21 or later).
+//! Create one with [`CTestCmd::new`], or with [`Cmd::ctest`](crate::Cmd::ctest)
+//! to test the tree of a build.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::Cmd;
+//!
+//! let cmd = Cmd::default().set_path("./my_project");
+//! cmd.clone().build().unwrap();
+//!
+//! let report = cmd
+//!     .ctest()
+//!     .exclude_regex("^slow_")
+//!     .parallel(8)
+//!     .output_on_failure(true)
+//!     .run()
+//!     .unwrap();
+//! for test in report.failed() {
+//!     eprintln!("{} failed after {:?}:\n{}", test.name, test.duration, test.output);
+//! }
+//! assert!(report.success());
+//! ```
+
+use std::{
+    path::PathBuf,
+    process::Command,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    cmake::{self, CMakePresets, Capability},
+    command::PreparedCommand,
+    credentials::Redactor,
+    error::Error,
+    gc::STATE_DIR,
+    instrument,
+    process::{self, CancelToken},
+    report::{BuildReport, Phase, PhaseTiming, ProcessExit},
+    watchdog::Watchdog,
+};
+
+/// The JUnit file ctest writes, inside the state directory of the build tree.
+const JUNIT_FILE: &str = "ctest-junit.xml";
+
+/// A builder for a `ctest` run.
+#[derive(Debug, Clone)]
+pub struct CTestCmd {
+    /// The build directory to test (passed as `--test-dir`).
+    binary_path: PathBuf,
+    /// The source directory, whose `CMakePresets.json` defines test presets.
+    path: Option<PathBuf>,
+    /// Optional test preset name (passed as `--preset <name>`).
+    preset: Option<String>,
+    /// Regex of the tests to run (passed as `-R`).
+    include: Option<String>,
+    /// Regex of the tests to skip (passed as `-E`).
+    exclude: Option<String>,
+    /// Number of tests run concurrently (passed as `--parallel`).
+    parallel: Option<usize>,
+    /// Whether the output of failed tests is printed (`--output-on-failure`).
+    output_on_failure: bool,
+    /// Extra arguments passed to ctest.
+    args: Vec<String>,
+    /// Timeout and stall detection of the ctest run.
+    watchdog: Option<Watchdog>,
+    /// Token used by orchestrators to kill a running ctest.
+    cancel: Option<CancelToken>,
+    /// The ctest executable. Defaults to `ctest` on `PATH`.
+    program: PathBuf,
+}
+
+impl CTestCmd {
+    /// Creates a ctest run for the build tree at `binary_path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `binary_path` - Path to a configured and built CMake build directory.
+    pub fn new<T>(binary_path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Self {
+            binary_path: PathBuf::from(binary_path.into()),
+            path: None,
+            preset: None,
+            include: None,
+            exclude: None,
+            parallel: None,
+            output_on_failure: false,
+            args: Vec::new(),
+            watchdog: None,
+            cancel: None,
+            program: PathBuf::from("ctest"),
+        }
+    }
+
+    /// Sets the ctest executable to run, instead of `ctest` on `PATH`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the executable.
+    pub fn set_executable<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.program = PathBuf::from(path.into());
+        self
+    }
+
+    /// Sets the CMake source directory, whose `CMakePresets.json` defines the
+    /// [test preset](CTestCmd::set_preset). Defaults to `"."`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the CMake source directory.
+    pub fn set_path<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.path = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Runs a test preset from `CMakePresets.json` (`--preset <name>`).
+    ///
+    /// The preset's configure preset decides which build tree is tested;
+    /// ctest is run in the source directory, without `--test-dir`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of a test preset (from `testPresets`).
+    pub fn set_preset<T>(mut self, name: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.preset = Some(name.into());
+        self
+    }
+
+    /// Runs only the tests whose names match `regex` (`-R`).
+    pub fn include_regex<T>(mut self, regex: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.include = Some(regex.into());
+        self
+    }
+
+    /// Skips the tests whose names match `regex` (`-E`).
+    pub fn exclude_regex<T>(mut self, regex: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.exclude = Some(regex.into());
+        self
+    }
+
+    /// Runs up to `jobs` tests concurrently (`--parallel <jobs>`).
+    pub fn parallel(mut self, jobs: usize) -> Self {
+        self.parallel = Some(jobs);
+        self
+    }
+
+    /// Prints the output of failed tests (`--output-on-failure`).
+    ///
+    /// The output of every test is collected into its [`TestResult`] either way.
+    pub fn output_on_failure(mut self, enabled: bool) -> Self {
+        self.output_on_failure = enabled;
+        self
+    }
+
+    /// Adds an extra argument to the ctest command.
+    pub fn add_arg<T>(mut self, arg: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Watches ctest for running too long or printing nothing for too long.
+    ///
+    /// A ctest run killed by the watchdog fails with an I/O error of kind
+    /// [`TimedOut`](std::io::ErrorKind::TimedOut) instead of reporting results.
+    pub fn set_watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    /// Attaches a token that kills the running ctest process when cancelled.
+    pub(crate) fn set_cancel_token(mut self, token: Option<CancelToken>) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Runs ctest and collects the result of every test.
+    ///
+    /// Failed tests don't make this method fail: check
+    /// [`TestReport::success`] or [`TestReport::failed`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The test preset is not found in `CMakePresets.json`
+    /// - `ctest` cannot be run, is cancelled, or is killed by its watchdog
+    /// - ctest predates CMake 3.21, which added `--output-junit`, or 3.20
+    ///   with a test preset
+    /// - ctest exits without writing its JUnit output, e.g. because the
+    ///   build tree doesn't exist
+    pub fn run(&self) -> Result<TestReport, Error> {
+        self.run_phase().map(|(report, _)| report)
+    }
+
+    /// Runs ctest like [`run`](CTestCmd::run), and also returns the timing
+    /// of the run as a test phase.
+    pub(crate) fn run_phase(&self) -> Result<(TestReport, PhaseTiming), Error> {
+        let source_dir = self.path.clone().unwrap_or_else(|| PathBuf::from("."));
+        let state_dir = self.binary_path.join(STATE_DIR);
+        std::fs::create_dir_all(&state_dir)?;
+        let junit = std::path::absolute(state_dir.join(JUNIT_FILE))?;
+        // a stale file must not be mistaken for the results of this run
+        if junit.exists() {
+            std::fs::remove_file(&junit)?;
+        }
+
+        let version = cmake::parsed_version_of(&self.program);
+        Capability::OutputJunit.require(version)?;
+        let mut command = Command::new(&self.program);
+        if let Some(preset) = &self.preset {
+            Capability::TestPresets.require(version)?;
+            let presets = CMakePresets::new(&source_dir)?;
+            if presets.get_test_preset(preset).is_none() {
+                return Err(Error::PresetNotFound(preset.clone()));
+            }
+            command.current_dir(&source_dir).arg("--preset").arg(preset);
+        } else if version.is_none_or(|version| version.supports(Capability::TestDir)) {
+            command.arg("--test-dir").arg(&self.binary_path);
+        } else {
+            command.current_dir(&self.binary_path);
+        }
+        if let Some(regex) = &self.include {
+            command.arg("-R").arg(regex);
+        }
+        if let Some(regex) = &self.exclude {
+            command.arg("-E").arg(regex);
+        }
+        if let Some(jobs) = self.parallel {
+            command.arg("--parallel").arg(jobs.to_string());
+        }
+        if self.output_on_failure {
+            command.arg("--output-on-failure");
+        }
+        command.arg("--output-junit").arg(&junit).args(&self.args);
+
+        let start = Instant::now();
+        let started_at = SystemTime::now();
+        let redactor = Redactor::default();
+        let output = instrument::run(Phase::Test, &mut command, &redactor, |command| {
+            process::run(
+                command,
+                self.cancel.as_ref(),
+                self.watchdog.as_ref(),
+                None,
+                &redactor,
+            )
+        })?;
+        let duration = start.elapsed();
+        if output.cancelled {
+            return Err(Error::cancelled("ctest", BuildReport::default()));
+        }
+        let exit = ProcessExit::from(output.status);
+        if let Some(trigger) = output.killed_by {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("ctest killed by watchdog: {}", trigger),
+            )));
+        }
+
+        let Ok(xml) = std::fs::read_to_string(&junit) else {
+            if !exit.success() {
+                return Err(Error::TestFailed(exit));
+            }
+            return Err(Error::Unsupported(
+                "ctest wrote no JUnit output".to_string(),
+            ));
+        };
+
+        let timing = PhaseTiming {
+            phase: Phase::Test,
+            target: None,
+            command: PreparedCommand::from(&command),
+            started_at,
+            duration,
+            exit,
+            stdout: output.stdout,
+            stderr: output.stderr,
+        };
+        let report = TestReport {
+            tests: parse_junit(&xml),
+            exit,
+            duration,
+        };
+        Ok((report, timing))
+    }
+}
+
+/// The outcome of a single test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    /// Not run, e.g. a disabled test or one whose executable is missing.
+    Skipped,
+}
+
+/// The result of a single test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestResult {
+    /// The test name, as given to `add_test`.
+    pub name: String,
+    pub status: TestStatus,
+    pub duration: Duration,
+    /// The output of the test, as captured by ctest.
+    pub output: String,
+}
+
+/// The results of a ctest run.
+#[derive(Debug, Clone)]
+pub struct TestReport {
+    /// The results of all tests ctest selected, in the order ctest reported them.
+    pub tests: Vec<TestResult>,
+    /// How ctest itself exited.
+    pub exit: ProcessExit,
+    /// Wall-clock duration of the ctest run.
+    pub duration: Duration,
+}
+
+impl TestReport {
+    /// Returns whether ctest succeeded and no test failed.
+    pub fn success(&self) -> bool {
+        self.exit.success() && self.failed().next().is_none()
+    }
+
+    /// Returns the tests that passed.
+    pub fn passed(&self) -> impl Iterator<Item = &TestResult> {
+        self.with_status(TestStatus::Passed)
+    }
+
+    /// Returns the tests that failed.
+    pub fn failed(&self) -> impl Iterator<Item = &TestResult> {
+        self.with_status(TestStatus::Failed)
+    }
+
+    /// Returns the tests that were not run.
+    pub fn skipped(&self) -> impl Iterator<Item = &TestResult> {
+        self.with_status(TestStatus::Skipped)
+    }
+
+    fn with_status(&self, status: TestStatus) -> impl Iterator<Item = &TestResult> {
+        self.tests.iter().filter(move |test| test.status == status)
+    }
+}
+
+/// Parses the `<testcase>` elements of a JUnit file written by ctest.
+fn parse_junit(xml: &str) -> Vec<TestResult> {
+    let mut tests = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find("<testcase") {
+        rest = &rest[start + "<testcase".len()..];
+        let Some(tag_end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..tag_end];
+        let (attributes, body) = match tag.strip_suffix('/') {
+            Some(attributes) => (attributes, ""),
+            None => {
+                let body = &rest[tag_end + 1..];
+                let end = body.find("</testcase>").unwrap_or(body.len());
+                (tag, &body[..end])
+            }
+        };
+
+        let status = match attribute(attributes, "status").as_deref() {
+            Some("fail") => TestStatus::Failed,
+            Some("notrun" | "disabled") => TestStatus::Skipped,
+            Some(_) => TestStatus::Passed,
+            None if body.contains("<failure") || body.contains("<error") => TestStatus::Failed,
+            None if body.contains("<skipped") => TestStatus::Skipped,
+            None => TestStatus::Passed,
+        };
+        let output = body
+            .split_once("<system-out>")
+            .and_then(|(_, out)| out.split_once("</system-out>"))
+            .map(|(out, _)| unescape(out))
+            .unwrap_or_default();
+        tests.push(TestResult {
+            name: attribute(attributes, "name").unwrap_or_default(),
+            status,
+            duration: attribute(attributes, "time")
+                .and_then(|time| time.parse().ok())
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .unwrap_or_default(),
+            output,
+        });
+    }
+    tests
+}
+
+/// Returns the unescaped value of the attribute `name` in an element's tag.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    loop {
+        let index = rest.find(name)?;
+        let before = rest[..index].chars().next_back();
+        rest = &rest[index + name.len()..];
+        if !before.is_some_and(char::is_whitespace) {
+            continue;
+        }
+        let Some(value) = rest.trim_start().strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let quote = value.chars().next()?;
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+        let value = &value[1..];
+        return Some(unescape(&value[..value.find(quote)?]));
+    }
+}
+
+/// Resolves the XML entities and CDATA sections in `text`.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find(['&', '<']) {
+        unescaped.push_str(&rest[..index]);
+        rest = &rest[index..];
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata.find("]]>").unwrap_or(cdata.len());
+            unescaped.push_str(&cdata[..end]);
+            rest = cdata.get(end + 3..).unwrap_or_default();
+            continue;
+        }
+        let entity = rest
+            .find(';')
+            .map(|end| &rest[1..end])
+            .and_then(|entity| match entity {
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "amp" => Some('&'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => entity
+                    .strip_prefix("#x")
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| entity.strip_prefix('#').map(str::parse))
+                    .and_then(Result::ok)
+                    .and_then(char::from_u32),
+            });
+        match entity {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[rest.find(';').unwrap_or(0) + 1..];
+            }
+            None => {
+                unescaped.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ctest_junit() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuite name="Linux-c++" tests="3" failures="1" disabled="1" skipped="0" hostname="" time="0" timestamp="2024-05-01T10:00:00">
+	<testcase name="math_add" classname="math_add" time="0.0125" status="run">
+		<system-out>1 + 1 == 2&#10;</system-out>
+	</testcase>
+	<testcase name="math &lt;div&gt;" classname="math &lt;div&gt;" time="1.5" status="fail">
+		<failure message="Failed"/>
+		<system-out>expected 2 &amp; got 3</system-out>
+	</testcase>
+	<testcase name="gpu" classname="gpu" time="0" status="disabled">
+		<skipped message="Disabled"/>
+		<system-out>Disabled</system-out>
+	</testcase>
+	<testcase name="empty" time="0"/>
+</testsuite>
+"#;
+        let tests = parse_junit(xml);
+        assert_eq!(tests.len(), 4);
+        assert_eq!(
+            tests[0],
+            TestResult {
+                name: "math_add".to_string(),
+                status: TestStatus::Passed,
+                duration: Duration::from_micros(12500),
+                output: "1 + 1 == 2\n".to_string(),
+            }
+        );
+        assert_eq!(tests[1].name, "math <div>");
+        assert_eq!(tests[1].status, TestStatus::Failed);
+        assert_eq!(tests[1].output, "expected 2 & got 3");
+        assert_eq!(tests[2].status, TestStatus::Skipped);
+        assert_eq!(tests[3].status, TestStatus::Passed);
+
+        let report = TestReport {
+            tests,
+            exit: ProcessExit {
+                code: Some(8),
+                signal: None,
+                core_dumped: false,
+            },
+            duration: Duration::from_secs(2),
+        };
+        assert!(!report.success());
+        assert_eq!(report.passed().count(), 2);
+        assert_eq!(report.failed().next().unwrap().name, "math <div>");
+        assert_eq!(report.skipped().count(), 1);
+    }
+}
@@ -0,0 +1,401 @@
+//! Compiler diagnostics parsing.
+//!
+//! This module extracts warnings and errors from captured build output.
+//! Both the GCC/Clang format (`file:line:col: error: message`) and the MSVC
+//! format (`file(line,col): error C2065: message`) are recognized; all other
+//! lines are ignored.
+//!
+//! The diagnostics of a build are in its
+//! [`BuildReport`](crate::report::BuildReport), and in the report of a
+//! [`BuildFailure`](crate::report::BuildFailure). To keep a project from
+//! gaining warnings, [save](save) the warnings of a build as a baseline and
+//! fail later builds on [new ones](new_since) with
+//! [`Cmd::fail_on_new_warnings`](crate::Cmd::fail_on_new_warnings):
+//!
+//! ```no_run
+//! use cmakr::{Cmd, diagnostics};
+//!
+//! let report = Cmd::default()
+//!     .set_path("./my_project")
+//!     .fail_on_new_warnings("warnings.json")
+//!     .build()
+//!     .unwrap();
+//! let warnings: Vec<_> = report.warnings().cloned().collect();
+//! diagnostics::save("warnings.json", &report.source_dir, &warnings).unwrap();
+//! ```
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The severity of a compiler diagnostic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+/// A single warning or error reported by a compiler.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// The source file the diagnostic refers to, as printed by the compiler.
+    ///
+    /// Diagnostics in a [`BuildReport`](crate::report::BuildReport) are
+    /// resolved to absolute paths.
+    pub file: PathBuf,
+    /// The 1-based line number, if reported.
+    pub line: Option<u32>,
+    /// The 1-based column number, if reported.
+    pub column: Option<u32>,
+    /// Whether this is an error, warning, or note.
+    pub severity: Severity,
+    /// The diagnostic code, if the compiler prints one (e.g. MSVC's `C2065`,
+    /// or the warning option `-Wunused-variable` for GCC and Clang).
+    pub code: Option<String>,
+    /// The diagnostic message text.
+    pub message: String,
+}
+
+/// Parses every recognizable compiler diagnostic in `output`.
+///
+/// Diagnostics that appear more than once (e.g. a warning in a header that is
+/// included by several translation units) are only reported the first time.
+pub fn parse(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+
+    for line in output.lines() {
+        let line = strip_ansi(line);
+        let parsed = parse_gcc_line(&line).or_else(|| parse_msvc_line(&line));
+
+        if let Some(diagnostic) = parsed
+            && !diagnostics.contains(&diagnostic)
+        {
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    diagnostics
+}
+
+/// Parses a GCC/Clang style line: `file:line[:col]: severity: message`.
+fn parse_gcc_line(line: &str) -> Option<Diagnostic> {
+    const MARKERS: [(&str, Severity); 4] = [
+        (": fatal error: ", Severity::Error),
+        (": error: ", Severity::Error),
+        (": warning: ", Severity::Warning),
+        (": note: ", Severity::Note),
+    ];
+
+    let (index, marker, severity) = MARKERS
+        .iter()
+        .filter_map(|(marker, severity)| line.find(marker).map(|i| (i, *marker, *severity)))
+        .min_by_key(|(i, ..)| *i)?;
+
+    let location = &line[..index];
+    let message = line[index + marker.len()..].trim();
+
+    // a trailing `[-Wflag]` names the warning option that triggered the diagnostic
+    let (message, code) = match message.strip_suffix(']').and_then(|m| m.rsplit_once(" [-")) {
+        Some((message, flag)) => (message, Some(format!("-{}", flag))),
+        None => (message, None),
+    };
+
+    // peel up to two numeric components (column, then line) off the end
+    let mut file = location;
+    let mut numbers = Vec::new();
+    while numbers.len() < 2 {
+        let Some((rest, last)) = file.rsplit_once(':') else {
+            break;
+        };
+        let Ok(number) = last.parse::<u32>() else {
+            break;
+        };
+        numbers.push(number);
+        file = rest;
+    }
+
+    // a bare tool name like `cc1plus` or `ld` is not a source location
+    if numbers.is_empty() || file.is_empty() {
+        return None;
+    }
+
+    let (line_no, column) = match numbers[..] {
+        [column, line_no] => (Some(line_no), Some(column)),
+        [line_no] => (Some(line_no), None),
+        _ => (None, None),
+    };
+
+    Some(Diagnostic {
+        file: PathBuf::from(file.trim()),
+        line: line_no,
+        column,
+        severity,
+        code,
+        message: message.to_string(),
+    })
+}
+
+/// Parses an MSVC style line: `file(line[,col]): severity CODE: message`.
+fn parse_msvc_line(line: &str) -> Option<Diagnostic> {
+    const MARKERS: [(&str, Severity); 4] = [
+        ("): fatal error ", Severity::Error),
+        ("): error ", Severity::Error),
+        ("): warning ", Severity::Warning),
+        ("): note", Severity::Note),
+    ];
+
+    let (index, marker, severity) = MARKERS
+        .iter()
+        .find_map(|(marker, severity)| line.find(marker).map(|i| (i, *marker, *severity)))?;
+
+    let location = &line[..index];
+    let (file, position) = location.rsplit_once('(')?;
+    let mut position = position.split(',').map(|n| n.trim().parse::<u32>());
+    let line_no = position.next()?.ok()?;
+    let column = position.next().and_then(Result::ok);
+
+    let rest = line[index + marker.len()..].trim_start_matches(':').trim();
+    let (code, message) = match rest.split_once(": ") {
+        Some((code, message)) if !code.contains(' ') => (Some(code.to_string()), message),
+        _ => (None, rest),
+    };
+
+    Some(Diagnostic {
+        file: PathBuf::from(file.trim()),
+        line: Some(line_no),
+        column,
+        severity,
+        code,
+        message: message.trim().to_string(),
+    })
+}
+
+/// Returns the diagnostics of `current` that are not in `baseline`.
+///
+/// Diagnostics are compared by file, severity, code, and message, but not
+/// by position, so that code moving within a file doesn't make its warnings
+/// new. Files are compared relative to `source_dir`, so that a baseline
+/// stays valid when the sources move. A warning occurring more often than in
+/// the baseline is new as many times as it occurs more often.
+///
+/// # Arguments
+///
+/// * `baseline` - The known diagnostics.
+/// * `current` - The diagnostics of the build to check.
+/// * `source_dir` - The source directory of the build.
+pub fn new_since<'a, P>(
+    baseline: &[Diagnostic],
+    current: &'a [Diagnostic],
+    source_dir: P,
+) -> Vec<&'a Diagnostic>
+where
+    P: AsRef<Path>,
+{
+    let source_dir = source_dir.as_ref();
+    let key = |d: &Diagnostic| {
+        (
+            relative_to(&d.file, source_dir),
+            d.severity,
+            d.code.clone(),
+            d.message.clone(),
+        )
+    };
+    let mut known: HashMap<_, usize> = HashMap::new();
+    for diagnostic in baseline {
+        *known.entry(key(diagnostic)).or_default() += 1;
+    }
+    current
+        .iter()
+        .filter(|diagnostic| match known.get_mut(&key(diagnostic)) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+/// Saves `diagnostics` as JSON, e.g. as the baseline of
+/// [`Cmd::fail_on_new_warnings`](crate::Cmd::fail_on_new_warnings).
+///
+/// Files inside `source_dir` are saved relative to it, so the baseline can
+/// be checked in with the sources.
+///
+/// # Errors
+///
+/// Returns an I/O error if the file cannot be written.
+pub fn save<P, S>(path: P, source_dir: S, diagnostics: &[Diagnostic]) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    S: AsRef<Path>,
+{
+    let relative: Vec<Diagnostic> = diagnostics
+        .iter()
+        .map(|diagnostic| Diagnostic {
+            file: relative_to(&diagnostic.file, source_dir.as_ref()),
+            ..diagnostic.clone()
+        })
+        .collect();
+    let json = serde_json::to_string_pretty(&relative).map_err(io::Error::other)?;
+    std::fs::write(path, json)
+}
+
+/// Loads diagnostics saved with [`save`], resolving relative files against
+/// `source_dir`.
+///
+/// # Errors
+///
+/// Returns an I/O error if the file cannot be read or parsed.
+pub fn load<P, S>(path: P, source_dir: S) -> io::Result<Vec<Diagnostic>>
+where
+    P: AsRef<Path>,
+    S: AsRef<Path>,
+{
+    let json = std::fs::read_to_string(path)?;
+    let mut diagnostics: Vec<Diagnostic> =
+        serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    for diagnostic in &mut diagnostics {
+        if diagnostic.file.is_relative() {
+            diagnostic.file = source_dir.as_ref().join(&diagnostic.file);
+        }
+    }
+    Ok(diagnostics)
+}
+
+/// Returns `file` relative to `dir` if it is inside it, else `file`.
+fn relative_to(file: &Path, dir: &Path) -> PathBuf {
+    file.strip_prefix(dir).unwrap_or(file).to_path_buf()
+}
+
+/// Removes ANSI color escape sequences (`ESC [ ... m`) from a line.
+fn strip_ansi(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_gcc_and_clang_output() {
+        let output = "\
+[1/2] Building C object CMakeFiles/test_lib.dir/func.c.o
+FAILED: CMakeFiles/test_lib.dir/func.c.o
+../func.c:12:5: error: 'x' undeclared (first use in this function)
+../func.c:3:10: warning: unused variable 'y' [-Wunused-variable]
+C:\\src\\func.c:7: note: declared here
+cc1: fatal error: func.h: No such file or directory
+../func.c:12:5: error: 'x' undeclared (first use in this function)
+ninja: build stopped: subcommand failed.
+";
+        let diagnostics = parse(output);
+        assert_eq!(diagnostics.len(), 3);
+
+        assert_eq!(diagnostics[0].file, PathBuf::from("../func.c"));
+        assert_eq!(diagnostics[0].line, Some(12));
+        assert_eq!(diagnostics[0].column, Some(5));
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(
+            diagnostics[0].message,
+            "'x' undeclared (first use in this function)"
+        );
+
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+        assert_eq!(diagnostics[1].code.as_deref(), Some("-Wunused-variable"));
+        assert_eq!(diagnostics[1].message, "unused variable 'y'");
+        assert_eq!(diagnostics[2].file, PathBuf::from("C:\\src\\func.c"));
+        assert_eq!(diagnostics[2].line, Some(7));
+        assert_eq!(diagnostics[2].column, None);
+    }
+
+    #[test]
+    fn parse_msvc_output() {
+        let output = "\
+C:\\src\\func.c(12,5): error C2065: 'x': undeclared identifier [C:\\build\\test_lib.vcxproj]
+C:\\src\\func.c(3): warning C4101: 'y': unreferenced local variable
+";
+        let diagnostics = parse(output);
+        assert_eq!(diagnostics.len(), 2);
+
+        assert_eq!(diagnostics[0].file, PathBuf::from("C:\\src\\func.c"));
+        assert_eq!(diagnostics[0].line, Some(12));
+        assert_eq!(diagnostics[0].column, Some(5));
+        assert_eq!(diagnostics[0].code.as_deref(), Some("C2065"));
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+        assert_eq!(diagnostics[1].column, None);
+    }
+
+    #[test]
+    fn warnings_since_baseline() {
+        let temp = crate::guard::TempBuildDir::new("warnings").unwrap();
+        let source = temp.path().join("project");
+        let baseline = resolved(
+            &source,
+            "src/a.c:3:5: warning: unused variable 'y' [-Wunused-variable]\n",
+        );
+        let path = temp.path().join("warnings.json");
+        save(&path, &source, &baseline).unwrap();
+        assert_eq!(load(&path, &source).unwrap(), baseline);
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("project"));
+
+        // the same warning on another line is not new
+        let current = resolved(
+            &source,
+            "src/a.c:9:5: warning: unused variable 'y' [-Wunused-variable]\n\
+             src/a.c:12:1: warning: unused variable 'z' [-Wunused-variable]\n",
+        );
+        let new = new_since(&baseline, &current, &source);
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].message, "unused variable 'z'");
+        assert!(new_since(&current, &baseline, &source).is_empty());
+    }
+
+    #[test]
+    fn baseline_of_moved_sources() {
+        let temp = crate::guard::TempBuildDir::new("warnings-moved").unwrap();
+        let old = temp.path().join("old");
+        let line = "src/a.c:3:5: warning: unused variable 'y' [-Wunused-variable]\n";
+        let path = temp.path().join("warnings.json");
+        save(&path, &old, &resolved(&old, line)).unwrap();
+
+        let moved = temp.path().join("moved");
+        let baseline = load(&path, &moved).unwrap();
+        assert!(new_since(&baseline, &resolved(&moved, line), &moved).is_empty());
+
+        // a warning occurring once more than in the baseline is new
+        let twice = resolved(&moved, &format!("{}{}", line, line.replace(":3:", ":30:")));
+        assert_eq!(new_since(&baseline, &twice, &moved).len(), 1);
+    }
+
+    /// Parses `output` with files resolved against `source_dir`, like the
+    /// diagnostics of a report.
+    fn resolved(source_dir: &Path, output: &str) -> Vec<Diagnostic> {
+        let mut diagnostics = parse(output);
+        for diagnostic in &mut diagnostics {
+            diagnostic.file = source_dir.join(&diagnostic.file);
+        }
+        diagnostics
+    }
+}
@@ -0,0 +1,169 @@
+//! Toolchains for bare-metal firmware.
+//!
+//! Cross-compiling firmware needs a toolchain file that tells CMake there is
+//! no operating system (`CMAKE_SYSTEM_NAME Generic`), that test programs
+//! can't be linked without a startup file and linker script (so try-compiles
+//! build static libraries), which GCC to use, and which CPU flags every
+//! translation unit must share. [`Cmd::embedded`](crate::Cmd::embedded)
+//! generates that file from an [`EmbeddedToolchain`] as a
+//! [`ToolchainFile`] and configures with it.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{
+//!     Cmd,
+//!     embedded::{EmbeddedToolchain, FloatAbi},
+//! };
+//!
+//! Cmd::embedded(EmbeddedToolchain::ArmNoneEabi {
+//!     cpu: "cortex-m4".to_string(),
+//!     fpu: Some("fpv4-sp-d16".to_string()),
+//!     float_abi: FloatAbi::Hard,
+//! })
+//! .set_path("./firmware")
+//! .build()
+//! .unwrap();
+//! ```
+
+use crate::toolchain::ToolchainFile;
+
+/// The floating-point ABI of an ARM target (`-mfloat-abi`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FloatAbi {
+    /// Floating-point operations are emulated in software.
+    #[default]
+    Soft,
+    /// The FPU is used, but floats are passed in integer registers.
+    SoftFp,
+    /// The FPU is used and floats are passed in FPU registers.
+    Hard,
+}
+
+impl FloatAbi {
+    /// Returns the value of `-mfloat-abi`.
+    pub fn name(self) -> &'static str {
+        match self {
+            FloatAbi::Soft => "soft",
+            FloatAbi::SoftFp => "softfp",
+            FloatAbi::Hard => "hard",
+        }
+    }
+}
+
+/// A bare-metal GCC toolchain, found on `PATH` by its target prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmbeddedToolchain {
+    /// The GNU Arm Embedded toolchain (`arm-none-eabi-gcc`).
+    ArmNoneEabi {
+        /// The CPU (`-mcpu`, e.g. `"cortex-m4"`). Cortex-M CPUs are built
+        /// for Thumb.
+        cpu: String,
+        /// The FPU (`-mfpu`, e.g. `"fpv4-sp-d16"`), if one is used.
+        fpu: Option<String>,
+        /// The floating-point ABI (`-mfloat-abi`).
+        float_abi: FloatAbi,
+    },
+    /// The RISC-V bare-metal toolchain (`riscv-none-elf-gcc`).
+    RiscvNoneElf {
+        /// The ISA (`-march`, e.g. `"rv32imac_zicsr"`).
+        arch: String,
+        /// The ABI (`-mabi`, e.g. `"ilp32"`).
+        abi: String,
+    },
+}
+
+impl EmbeddedToolchain {
+    /// Returns the prefix of the toolchain's executables (e.g. `"arm-none-eabi"`).
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            EmbeddedToolchain::ArmNoneEabi { .. } => "arm-none-eabi",
+            EmbeddedToolchain::RiscvNoneElf { .. } => "riscv-none-elf",
+        }
+    }
+
+    /// Returns the target flags passed to every compile and link.
+    pub fn flags(&self) -> Vec<String> {
+        match self {
+            EmbeddedToolchain::ArmNoneEabi {
+                cpu,
+                fpu,
+                float_abi,
+            } => {
+                let mut flags = vec![format!("-mcpu={}", cpu)];
+                if cpu.starts_with("cortex-m") {
+                    flags.push("-mthumb".to_string());
+                }
+                if let Some(fpu) = fpu {
+                    flags.push(format!("-mfpu={}", fpu));
+                }
+                flags.push(format!("-mfloat-abi={}", float_abi.name()));
+                flags
+            }
+            EmbeddedToolchain::RiscvNoneElf { arch, abi } => {
+                vec![format!("-march={}", arch), format!("-mabi={}", abi)]
+            }
+        }
+    }
+
+    /// Returns the toolchain file of the toolchain.
+    pub fn toolchain_file(&self) -> ToolchainFile {
+        let prefix = self.prefix();
+        let processor = match self {
+            EmbeddedToolchain::ArmNoneEabi { .. } => "arm",
+            EmbeddedToolchain::RiscvNoneElf { .. } => "riscv",
+        };
+        let mut builder = ToolchainFile::builder()
+            .system_name("Generic")
+            .system_processor(processor)
+            .try_compile_static_library(true)
+            .c_compiler(format!("{}-gcc", prefix))
+            .cxx_compiler(format!("{}-g++", prefix))
+            .asm_compiler(format!("{}-gcc", prefix))
+            .cross_find_modes();
+        for flag in self.flags() {
+            builder = builder.add_flag(flag);
+        }
+        for tool in ["ar", "objcopy", "objdump", "size"] {
+            builder = builder.set_variable(
+                format!("CMAKE_{}", tool.to_uppercase()),
+                format!("{}-{}", prefix, tool),
+            );
+        }
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_arm_toolchain_file() {
+        let toolchain = EmbeddedToolchain::ArmNoneEabi {
+            cpu: "cortex-m4".to_string(),
+            fpu: Some("fpv4-sp-d16".to_string()),
+            float_abi: FloatAbi::Hard,
+        };
+        let file = toolchain.toolchain_file().contents();
+        assert!(file.contains("set(CMAKE_SYSTEM_NAME \"Generic\")\n"));
+        assert!(file.contains("set(CMAKE_TRY_COMPILE_TARGET_TYPE \"STATIC_LIBRARY\")\n"));
+        assert!(file.contains("set(CMAKE_C_COMPILER \"arm-none-eabi-gcc\")\n"));
+        assert!(file.contains(
+            "set(CMAKE_C_FLAGS_INIT \"-mcpu=cortex-m4 -mthumb -mfpu=fpv4-sp-d16 -mfloat-abi=hard\")\n"
+        ));
+        assert!(file.contains("set(CMAKE_OBJCOPY \"arm-none-eabi-objcopy\")\n"));
+
+        let toolchain = EmbeddedToolchain::RiscvNoneElf {
+            arch: "rv32imac_zicsr".to_string(),
+            abi: "ilp32".to_string(),
+        };
+        assert_eq!(toolchain.flags(), ["-march=rv32imac_zicsr", "-mabi=ilp32"]);
+        assert!(
+            toolchain
+                .toolchain_file()
+                .contents()
+                .contains("set(CMAKE_CXX_COMPILER \"riscv-none-elf-g++\")\n")
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Building C libraries for WebAssembly with Emscripten.
+//!
+//! [`Cmd::for_emscripten`](crate::Cmd::for_emscripten) configures with the
+//! Emscripten toolchain file, as `emcmake cmake` does, and the Ninja
+//! generator. The toolchain file is [found](toolchain_file) through the
+//! `EMSDK` variable that the SDK's `emsdk_env` script sets, through
+//! `EMSCRIPTEN`, or next to `emcc` on `PATH`.
+//!
+//! Executables are built as a `.js` loader and a `.wasm` binary next to it;
+//! [`BuildReport::typed_artifacts`](crate::report::BuildReport::typed_artifacts)
+//! lists the binary as [`ArtifactKind::Wasm`](crate::artifact::ArtifactKind::Wasm)
+//! of the same target.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::Cmd;
+//!
+//! Cmd::for_emscripten()
+//!     .unwrap()
+//!     .set_path("./native")
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::path::{Path, PathBuf};
+
+/// The path of the toolchain file in an Emscripten installation.
+const TOOLCHAIN_FILE: &str = "cmake/Modules/Platform/Emscripten.cmake";
+
+/// Returns the Emscripten toolchain file, found through `EMSDK`,
+/// `EMSCRIPTEN`, or `emcc` on `PATH`, in this order.
+pub fn toolchain_file() -> Option<PathBuf> {
+    let var = |name: &str| std::env::var_os(name).map(PathBuf::from);
+    locate(
+        var("EMSDK").as_deref(),
+        var("EMSCRIPTEN").as_deref(),
+        which::which("emcc").ok().as_deref(),
+    )
+}
+
+/// Returns the first existing toolchain file of the SDK at `emsdk`, the
+/// Emscripten installation at `emscripten`, and the one `emcc` belongs to.
+fn locate(emsdk: Option<&Path>, emscripten: Option<&Path>, emcc: Option<&Path>) -> Option<PathBuf> {
+    // emcc on PATH is often a symlink into the installation
+    let emcc_root = emcc
+        .and_then(|emcc| emcc.canonicalize().ok())
+        .and_then(|emcc| emcc.parent().map(Path::to_path_buf));
+    let roots = [
+        emsdk.map(|emsdk| emsdk.join("upstream/emscripten")),
+        emscripten.map(Path::to_path_buf),
+        emcc_root,
+    ];
+    roots
+        .into_iter()
+        .flatten()
+        .map(|root| root.join(TOOLCHAIN_FILE))
+        .find(|file| file.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locate_toolchain_file() {
+        let temp = crate::guard::TempBuildDir::new("emscripten").unwrap();
+        let emsdk = temp.path().join("emsdk");
+        let root = emsdk.join("upstream/emscripten");
+        std::fs::create_dir_all(root.join("cmake/Modules/Platform")).unwrap();
+        std::fs::write(root.join(TOOLCHAIN_FILE), "").unwrap();
+        std::fs::write(root.join("emcc"), "").unwrap();
+
+        let expected = Some(root.join(TOOLCHAIN_FILE));
+        assert_eq!(locate(Some(&emsdk), None, None), expected);
+        assert_eq!(locate(None, Some(&root), None), expected);
+        assert_eq!(
+            locate(Some(temp.path()), None, Some(&root.join("emcc"))),
+            Some(root.canonicalize().unwrap().join(TOOLCHAIN_FILE))
+        );
+        assert_eq!(locate(Some(temp.path()), None, None), None);
+    }
+}
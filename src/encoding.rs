@@ -0,0 +1,117 @@
+//! Decoding tool output that is not UTF-8.
+//!
+//! MSVC and other Windows tools write their output in the console's code
+//! page (e.g. 850 or 1252, or 936 for Chinese diagnostics) rather than UTF-8.
+//! Output lines that are not valid UTF-8 are transcoded from the console
+//! output code page on Windows; bytes that have no character in it, and
+//! invalid UTF-8 elsewhere, become U+FFFD (`�`) markers.
+//!
+//! Localized diagnostics are readable then, but the parsers in
+//! [`diagnostics`](crate::diagnostics) only know the English wording.
+//! [`Cmd::english_diagnostics`](crate::Cmd::english_diagnostics) asks the
+//! compilers for English messages, which are plain ASCII in every code page,
+//! so switching the console code page with `chcp` isn't needed.
+
+use std::borrow::Cow;
+
+/// The environment variables that switch compilers to English messages:
+/// `VSLANG` for MSVC, `LC_MESSAGES` and `LANGUAGE` (which takes precedence
+/// over `LC_ALL`) for the gettext-based GCC and binutils.
+pub(crate) const ENGLISH_ENVIRONMENT: [(&str, &str); 3] =
+    [("VSLANG", "1033"), ("LC_MESSAGES", "C"), ("LANGUAGE", "en")];
+
+/// The code page identifier of UTF-8.
+#[cfg(windows)]
+const CP_UTF8: u32 = 65001;
+
+/// Decodes a line of tool output, without copying if it is valid UTF-8.
+pub(crate) fn decode(bytes: &[u8]) -> Cow<'_, str> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Cow::Borrowed(text),
+        Err(_) => Cow::Owned(transcode(bytes)),
+    }
+}
+
+/// Decodes `bytes` from the console output code page.
+#[cfg(windows)]
+fn transcode(bytes: &[u8]) -> String {
+    use std::sync::OnceLock;
+
+    static CODE_PAGE: OnceLock<u32> = OnceLock::new();
+    let code_page = *CODE_PAGE.get_or_init(|| {
+        // SAFETY: neither function takes arguments; 0 means there is no console
+        match unsafe { ffi::GetConsoleOutputCP() } {
+            0 => unsafe { ffi::GetOEMCP() },
+            code_page => code_page,
+        }
+    });
+    if code_page == CP_UTF8 || bytes.len() > i32::MAX as usize {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+
+    let len = bytes.len() as i32;
+    // a byte never decodes to more than one UTF-16 code unit
+    let mut wide = vec![0u16; bytes.len()];
+    // SAFETY: both buffers are valid for the lengths passed
+    let written = unsafe {
+        ffi::MultiByteToWideChar(code_page, 0, bytes.as_ptr(), len, wide.as_mut_ptr(), len)
+    };
+    if written <= 0 {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+    String::from_utf16_lossy(&wide[..written as usize])
+}
+
+/// Decodes `bytes` as UTF-8, replacing invalid sequences.
+#[cfg(not(windows))]
+fn transcode(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+#[cfg(windows)]
+mod ffi {
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        pub(super) fn GetConsoleOutputCP() -> u32;
+        pub(super) fn GetOEMCP() -> u32;
+        pub(super) fn MultiByteToWideChar(
+            code_page: u32,
+            flags: u32,
+            multi_byte: *const u8,
+            multi_byte_len: i32,
+            wide: *mut u16,
+            wide_len: i32,
+        ) -> i32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_tool_output() {
+        assert!(matches!(
+            decode("warning C4996: 'strcpy'".as_bytes()),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(decode("größe".as_bytes()), "größe");
+        // "Größe" in code page 1252
+        let decoded = decode(b"Gr\xf6\xdfe");
+        if cfg!(windows) {
+            assert_eq!(decoded.chars().count(), 5);
+        } else {
+            assert_eq!(decoded, "Gr\u{fffd}\u{fffd}e");
+        }
+
+        let plan = crate::Cmd::default()
+            .set_path("./test/")
+            .english_diagnostics(true)
+            .env("LANGUAGE", "de")
+            .dry_run()
+            .unwrap();
+        let env = &plan.commands[0].env;
+        assert!(env.contains(&("VSLANG".to_string(), Some("1033".to_string()))));
+        assert!(env.contains(&("LANGUAGE".to_string(), Some("de".to_string()))));
+    }
+}
@@ -0,0 +1,326 @@
+//! Obtaining the sources of a project before building it, built with the
+//! `fetch` feature.
+//!
+//! A [`Source`] is fetched into a directory of its own, like CMake's
+//! `ExternalProject`, so that a build script can build a C library without
+//! vendoring it:
+//!
+//! | Source | Fetched with |
+//! |--------|--------------|
+//! | [`Git`](Source::Git) | a shallow `git fetch` of the revision (a tag, branch, or commit) |
+//! | [`Archive`](Source::Archive) | `curl`, `cmake -E sha256sum` to verify it, and `cmake -E tar` to extract it |
+//!
+//! No HTTP, TLS, or archive crates are pulled in: `git`, `curl`, and `cmake`
+//! are on every machine that builds CMake projects. A single top-level
+//! directory of an archive is stripped, as `ExternalProject` does.
+//!
+//! The directory gets a `.cmakr-fetch` stamp naming the source, so fetching
+//! the same source again returns right away, and another source replaces
+//! the earlier one. A source is fetched into a sibling directory first, so
+//! an interrupted fetch never leaves a half-populated directory behind. In
+//! [offline mode](crate::set_offline) only sources on this machine are
+//! fetched.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::fetch::Source;
+//!
+//! let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+//! let report = Source::archive(
+//!     "https://zlib.net/zlib-1.3.1.tar.gz",
+//!     "9a93b2b7dfdac77ceba5a558a580e74667dd6fede4585b91eefb60f03b72df23",
+//! )
+//! .cmd(out_dir.join("zlib-src"))
+//! .unwrap()
+//! .set_binary_path(out_dir.join("zlib-build").to_string_lossy())
+//! .build()
+//! .unwrap();
+//! ```
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{Cmd, error::Error, http, offline, tools::Tools};
+
+/// The stamp file naming the source a directory was fetched from.
+const STAMP: &str = ".cmakr-fetch";
+
+/// Where the sources of a project come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// A revision of a git repository, fetched without its history.
+    Git {
+        /// The repository URL or local path.
+        url: String,
+        /// The tag, branch, or full commit hash.
+        rev: String,
+    },
+    /// A `.tar.gz`, `.tar.xz`, `.tar.bz2`, or `.zip` archive.
+    Archive {
+        /// The URL or local path of the archive.
+        url: String,
+        /// The expected SHA-256 of the archive, in hex.
+        sha256: String,
+    },
+}
+
+impl Source {
+    /// Creates a git source.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The repository URL or local path.
+    /// * `rev` - The tag, branch, or full commit hash to check out. Servers
+    ///   that don't allow fetching any commit (unlike GitHub and GitLab)
+    ///   need a tag or branch.
+    pub fn git<T, U>(url: T, rev: U) -> Self
+    where
+        T: Into<String>,
+        U: Into<String>,
+    {
+        Source::Git {
+            url: url.into(),
+            rev: rev.into(),
+        }
+    }
+
+    /// Creates an archive source.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL or local path of the archive.
+    /// * `sha256` - The expected SHA-256 of the archive, in hex.
+    pub fn archive<T, U>(url: T, sha256: U) -> Self
+    where
+        T: Into<String>,
+        U: Into<String>,
+    {
+        Source::Archive {
+            url: url.into(),
+            sha256: sha256.into(),
+        }
+    }
+
+    /// Returns the URL or local path of the source.
+    pub fn url(&self) -> &str {
+        match self {
+            Source::Git { url, .. } | Source::Archive { url, .. } => url,
+        }
+    }
+
+    /// Fetches the source into `dir`, unless `dir` was already fetched from
+    /// it, and returns `dir`.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory to fetch into. It must not exist, or have
+    ///   been fetched into before.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if `dir` has files not fetched by
+    /// cmakr or if the archive has another SHA-256, [`Error::Offline`] if
+    /// the source is remote in offline mode, and [`Error::ToolFailed`] if
+    /// git, curl, or cmake fails.
+    pub fn fetch<P>(&self, dir: P) -> Result<PathBuf, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        let stamp = self.to_string();
+        if dir.exists() {
+            match fs::read_to_string(dir.join(STAMP)) {
+                Ok(fetched) if fetched == stamp => return Ok(dir.to_path_buf()),
+                Ok(_) => {}
+                Err(_) if fs::read_dir(dir)?.next().is_none() => {}
+                Err(_) => {
+                    return Err(Error::InvalidConfig(format!(
+                        "{} was not fetched by cmakr and is not empty",
+                        dir.display()
+                    )));
+                }
+            }
+        }
+        if offline::is_offline() && !is_local(self.url()) {
+            return Err(Error::Offline(vec![self.url().to_string()]));
+        }
+
+        let mut partial = dir.as_os_str().to_owned();
+        partial.push(".cmakr-partial");
+        let partial = PathBuf::from(partial);
+        if partial.exists() {
+            fs::remove_dir_all(&partial)?;
+        }
+        fs::create_dir_all(&partial)?;
+        let fetched = match self {
+            Source::Git { url, rev } => fetch_git(url, rev, &partial),
+            Source::Archive { url, sha256 } => fetch_archive(url, sha256, &partial),
+        };
+        let fetched = match fetched {
+            Ok(fetched) => fetched,
+            Err(e) => {
+                let _ = fs::remove_dir_all(&partial);
+                return Err(e);
+            }
+        };
+        fs::write(fetched.join(STAMP), &stamp)?;
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        fs::rename(&fetched, dir)?;
+        if partial.exists() {
+            fs::remove_dir_all(&partial)?;
+        }
+        Ok(dir.to_path_buf())
+    }
+
+    /// Fetches the source into `dir` and returns a [`Cmd`] building it.
+    ///
+    /// # Errors
+    ///
+    /// See [`fetch`](Source::fetch).
+    pub fn cmd<P>(&self, dir: P) -> Result<Cmd, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = self.fetch(dir)?;
+        Ok(Cmd::default().set_path(dir.to_string_lossy()))
+    }
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Git { url, rev } => write!(f, "git {} {}", url, rev),
+            Source::Archive { url, sha256 } => {
+                write!(f, "archive {} {}", url, sha256.to_lowercase())
+            }
+        }
+    }
+}
+
+/// Returns whether `url` is a path or `file://` URL on this machine.
+fn is_local(url: &str) -> bool {
+    url.starts_with("file://") || !url.contains("://") && !url.contains('@')
+}
+
+/// Fetches `rev` of the repository at `url` into the new repository `dir`.
+fn fetch_git(url: &str, rev: &str, dir: &Path) -> Result<PathBuf, Error> {
+    run(Command::new("git").args(["init", "-q"]).arg(dir))?;
+    run(Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["fetch", "-q", "--depth", "1", url, rev]))?;
+    run(Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["checkout", "-q", "--detach", "FETCH_HEAD"]))?;
+    Ok(dir.to_path_buf())
+}
+
+/// Downloads, verifies, and extracts the archive at `url` into `dir`,
+/// returning the directory with the sources.
+fn fetch_archive(url: &str, sha256: &str, dir: &Path) -> Result<PathBuf, Error> {
+    let archive = dir.join(url.rsplit('/').next().unwrap_or("archive"));
+    match url.strip_prefix("file://") {
+        Some(path) => fs::copy(path, &archive).map(drop)?,
+        None if is_local(url) => fs::copy(url, &archive).map(drop)?,
+        None => http::download(url, &archive)?,
+    }
+
+    let cmake = Tools::default().find_cmake()?;
+    let output = run(Command::new(&cmake).args(["-E", "sha256sum"]).arg(&archive))?;
+    let actual = output.split_whitespace().next().unwrap_or_default();
+    if !actual.eq_ignore_ascii_case(sha256) {
+        return Err(Error::InvalidConfig(format!(
+            "{} has SHA-256 {}, expected {}",
+            url, actual, sha256
+        )));
+    }
+
+    let extracted = dir.join("extracted");
+    fs::create_dir_all(&extracted)?;
+    run(Command::new(&cmake)
+        .args(["-E", "tar", "xf"])
+        .arg(&archive)
+        .current_dir(&extracted))?;
+    // strip a single top-level directory
+    let entries: Vec<PathBuf> = fs::read_dir(&extracted)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    match entries.as_slice() {
+        [single] if single.is_dir() => Ok(single.clone()),
+        _ => Ok(extracted),
+    }
+}
+
+/// Runs `command` and returns its standard output.
+fn run(command: &mut Command) -> Result<String, Error> {
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(Error::ToolFailed(format!(
+            "{} failed: {}",
+            command.get_program().to_string_lossy(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::TempBuildDir;
+
+    #[test]
+    fn fetch_git_revision() {
+        let temp = TempBuildDir::new("fetch").unwrap();
+        let upstream = temp.path().join("upstream");
+        fs::create_dir_all(&upstream).unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(&upstream)
+                .args(["-c", "user.name=cmakr", "-c", "user.email=cmakr@localhost"])
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {:?}", args);
+        };
+        git(&["init", "-q"]);
+        fs::write(upstream.join("CMakeLists.txt"), "project(v1 C)\n").unwrap();
+        git(&["add", "CMakeLists.txt"]);
+        git(&["commit", "-q", "-m", "v1"]);
+        git(&["tag", "v1"]);
+        fs::write(upstream.join("CMakeLists.txt"), "project(v2 C)\n").unwrap();
+        git(&["commit", "-q", "-am", "v2"]);
+
+        let dir = temp.path().join("src");
+        let url = format!("file://{}", upstream.display());
+        let cmd = Source::git(&url, "v1").cmd(&dir).unwrap();
+        assert_eq!(cmd.path(), dir);
+        let lists = || fs::read_to_string(dir.join("CMakeLists.txt")).unwrap();
+        assert_eq!(lists(), "project(v1 C)\n");
+        assert!(!temp.path().join("src.cmakr-partial").exists());
+
+        // fetched already, so not touched again
+        fs::write(dir.join("local.txt"), "").unwrap();
+        Source::git(&url, "v1").fetch(&dir).unwrap();
+        assert!(dir.join("local.txt").exists());
+        Source::git(&url, "HEAD").fetch(&dir).unwrap();
+        assert_eq!(lists(), "project(v2 C)\n");
+        assert!(!dir.join("local.txt").exists());
+
+        let unknown = Source::git(&url, "v3").fetch(temp.path().join("other"));
+        assert!(matches!(unknown, Err(Error::ToolFailed(_))));
+        assert!(matches!(
+            Source::git(&url, "v1").fetch(&upstream),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+}
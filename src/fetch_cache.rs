@@ -0,0 +1,184 @@
+//! Sharing the `FetchContent` sources of build trees.
+//!
+//! With a [fetch cache](crate::Cmd::set_fetch_cache_dir), the sources of the
+//! remote dependencies a project declares with `FetchContent_Declare` are
+//! kept in the cache as `<name>-src`, with a `.cmakr-fetch` stamp naming the
+//! URL and revision they were fetched from. A build tree declaring the same
+//! URL and revision is configured with `FETCHCONTENT_SOURCE_DIR_<NAME>`
+//! pointing at the shared sources, so it skips the download; sources a tree
+//! downloaded itself are copied into the cache after its configure.
+//!
+//! Only declarations pinned to one set of sources are shared: an archive
+//! hash (`URL_HASH`, `URL_MD5`), a full commit hash, or a tag. Sources of a
+//! branch such as `GIT_TAG main` are downloaded by every tree, as freezing
+//! the first download would hide later commits.
+//!
+//! Everything else `FetchContent` writes (`<name>-build`, `<name>-subbuild`)
+//! stays in the `_deps` directory of each build tree, so concurrent builds
+//! only share sources, which neither configure nor the build modify.
+//! Downloads of `ExternalProject_Add` (and its `EP_BASE`) are not shared.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::offline::Declared;
+
+/// The stamp file naming the declaration shared sources were fetched for.
+const STAMP: &str = ".cmakr-fetch";
+
+/// Returns the directory of the sources of `dependency` under `dir`, a
+/// fetch cache or the `_deps` directory of a build tree.
+fn sources_dir(dir: &Path, dependency: &Declared) -> PathBuf {
+    dir.join(format!("{}-src", dependency.name.to_lowercase()))
+}
+
+/// Returns the stamp of the sources of `dependency`.
+fn stamp(dependency: &Declared) -> String {
+    match &dependency.revision {
+        Some(revision) => format!("{} {}", dependency.url, revision),
+        None => dependency.url.clone(),
+    }
+}
+
+/// Returns whether `cache_dir` has the sources of `dependency`, fetched for
+/// the same URL and pinned revision.
+pub(crate) fn is_shared(cache_dir: &Path, dependency: &Declared) -> bool {
+    dependency.pinned
+        && fs::read_to_string(sources_dir(cache_dir, dependency).join(STAMP))
+            .is_ok_and(|stamp_of_cache| stamp_of_cache == stamp(dependency))
+}
+
+/// Returns the `FETCHCONTENT_SOURCE_DIR_<NAME>` variables pointing at the
+/// sources in `cache_dir` of the `declared` dependencies that have any.
+pub(crate) fn shared_sources(cache_dir: &Path, declared: &[Declared]) -> Vec<(String, PathBuf)> {
+    declared
+        .iter()
+        .filter(|dependency| is_shared(cache_dir, dependency))
+        .map(|dependency| {
+            (
+                format!("FETCHCONTENT_SOURCE_DIR_{}", dependency.name.to_uppercase()),
+                sources_dir(cache_dir, dependency),
+            )
+        })
+        .collect()
+}
+
+/// Copies the sources of the `declared` dependencies that a build tree
+/// populated in its `deps_dir` into `cache_dir`.
+///
+/// Sources without a pinned revision are not shared, as their declaration
+/// doesn't say which sources they are, or names a branch that moves on. Sources the cache already has for
+/// another declaration are left alone, since other trees may be building
+/// from them.
+pub(crate) fn store(cache_dir: &Path, deps_dir: &Path, declared: &[Declared]) -> io::Result<()> {
+    for dependency in declared.iter().filter(|d| d.pinned) {
+        let populated = sources_dir(deps_dir, dependency);
+        let shared = sources_dir(cache_dir, dependency);
+        if !populated.is_dir() || shared.exists() {
+            continue;
+        }
+
+        // copied next to the cache entry first, so that it is never half-populated
+        let mut partial = shared.as_os_str().to_owned();
+        partial.push(".cmakr-partial");
+        let partial = PathBuf::from(partial);
+        if partial.exists() {
+            fs::remove_dir_all(&partial)?;
+        }
+        copy_dir(&populated, &partial)?;
+        fs::write(partial.join(STAMP), stamp(dependency))?;
+        fs::rename(&partial, &shared)?;
+    }
+    Ok(())
+}
+
+/// Copies the directory `from` to `to`, keeping symlinks as links.
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let kind = entry.file_type()?;
+        if kind.is_symlink() {
+            copy_link(&entry.path(), &target)?;
+        } else if kind.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_link(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+/// Copies what the link `from` points to, as links need privileges on Windows.
+#[cfg(not(unix))]
+fn copy_link(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::metadata(from) {
+        Ok(metadata) if metadata.is_dir() => copy_dir(from, to),
+        Ok(_) => fs::copy(from, to).map(|_| ()),
+        // a dangling link has nothing to copy
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::TempBuildDir;
+
+    fn declared(name: &str, revision: Option<&str>) -> Declared {
+        Declared {
+            name: name.to_string(),
+            url: format!("https://example.com/{}.git", name),
+            revision: revision.map(str::to_string),
+            pinned: revision.is_some(),
+        }
+    }
+
+    #[test]
+    fn share_only_pinned_sources() {
+        let temp = TempBuildDir::new("fetch-cache").unwrap();
+        let cache = temp.path().join("cache");
+        let deps = temp.path().join("build/_deps");
+        for dir in ["fmt-src/include", "fmt-build", "fmt-subbuild", "json-src"] {
+            fs::create_dir_all(deps.join(dir)).unwrap();
+        }
+        fs::write(deps.join("fmt-src/include/fmt.h"), "").unwrap();
+        let fmt = declared("fmt", Some("10.2.1"));
+        let json = declared("Json", None);
+
+        store(&cache, &deps, &[fmt.clone(), json.clone()]).unwrap();
+        assert!(cache.join("fmt-src/include/fmt.h").is_file());
+        assert!(!cache.join("fmt-build").exists());
+        assert!(!cache.join("fmt-subbuild").exists());
+        assert!(!cache.join("json-src").exists());
+        assert_eq!(
+            shared_sources(&cache, &[fmt.clone(), json]),
+            [(
+                "FETCHCONTENT_SOURCE_DIR_FMT".to_string(),
+                cache.join("fmt-src")
+            )]
+        );
+
+        // another revision neither uses nor replaces the shared sources
+        let newer = declared("fmt", Some("11.0.0"));
+        assert!(shared_sources(&cache, std::slice::from_ref(&newer)).is_empty());
+        store(&cache, &deps, &[newer]).unwrap();
+        assert!(is_shared(&cache, &fmt));
+
+        // a branch is never frozen, even with sources stored for its name
+        let branch = Declared {
+            pinned: false,
+            ..declared("fmt", Some("10.2.1"))
+        };
+        assert!(!is_shared(&cache, &branch));
+    }
+}
@@ -0,0 +1,619 @@
+//! Client of the CMake file API.
+//!
+//! Before configuring, cmakr places stateless `codemodel-v2`, `cache-v2` and
+//! `toolchains-v1` queries in the build tree
+//! (`.cmake/api/v1/query/client-cmakr/`), which CMake answers with JSON reply
+//! files during generation (3.14 and later; 3.20 for toolchains). This module
+//! reads the replies into typed structs:
+//!
+//! - [`targets`] - the targets of the project, with the files they produce,
+//!   the libraries they link and how their sources are compiled.
+//! - [`cache`] - the cache entries, with their types and help strings.
+//! - [`toolchains`] - the compiler of each enabled language.
+//!
+//! Build scripts can use them to find the exact names and paths of the
+//! libraries a project produced instead of guessing them. cmakr itself uses
+//! the codemodel to tell whether a project builds anything at all (a
+//! header-only project defines only `INTERFACE_LIBRARY` targets), to restrict
+//! a build to the targets of one source directory, and to give editors the
+//! compile flags when the generator writes no compilation database.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, fileapi};
+//!
+//! let report = Cmd::default().set_path("./my_project").build().unwrap();
+//! let targets = fileapi::targets(&report.binary_dir).unwrap().unwrap_or_default();
+//! for target in targets.iter().filter(|target| target.produces_artifact()) {
+//!     println!("{} ({}): {:?}", target.name, target.ty, target.artifacts);
+//!     println!("  links {:?}", target.link_libraries);
+//! }
+//! ```
+
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use crate::{cache::CacheEntry, report::SharedLibrary};
+
+/// Name of cmakr's client directory in the file API query and reply.
+const CLIENT: &str = "client-cmakr";
+
+/// Target types that produce a binary in the output directory.
+const ARTIFACT_TYPES: [&str; 4] = [
+    "EXECUTABLE",
+    "STATIC_LIBRARY",
+    "SHARED_LIBRARY",
+    "MODULE_LIBRARY",
+];
+
+/// A target of the generated build system.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Target {
+    /// The unique target ID, used to refer to it from other targets.
+    pub id: String,
+    pub name: String,
+    /// The CMake target type (e.g. `"EXECUTABLE"` or `"INTERFACE_LIBRARY"`).
+    pub ty: String,
+    /// The source directory defining the target, relative to the top-level
+    /// source directory (`""` for the top level).
+    pub directory: PathBuf,
+    /// Absolute paths of the files the target produces.
+    pub artifacts: Vec<PathBuf>,
+    /// The languages of the target's sources (e.g. `["C", "CXX"]`).
+    pub languages: Vec<String>,
+    /// IDs of the targets this target depends on, including linked libraries.
+    pub dependencies: Vec<String>,
+    /// The libraries on the link line, in order: paths of library files,
+    /// `-l` flags and linker flags, as CMake passes them to the linker.
+    /// Empty for targets that are not linked.
+    pub link_libraries: Vec<String>,
+    /// The groups of sources compiled with the same flags.
+    pub compile_groups: Vec<CompileGroup>,
+    /// Absolute paths of the target's sources, headers included, without
+    /// the ones generated during the build.
+    pub sources: Vec<PathBuf>,
+}
+
+/// Sources of a target compiled with the same flags.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CompileGroup {
+    /// The language of the sources (e.g. `"CXX"`).
+    pub language: String,
+    /// The compile command fragments other than defines and includes, in order.
+    pub fragments: Vec<String>,
+    /// The preprocessor definitions (`NAME` or `NAME=value`).
+    pub defines: Vec<String>,
+    /// The include directories, and whether each one is a system directory.
+    pub includes: Vec<(PathBuf, bool)>,
+}
+
+impl Target {
+    /// Returns whether building the target produces a binary.
+    pub fn produces_artifact(&self) -> bool {
+        ARTIFACT_TYPES.contains(&self.ty.as_str())
+    }
+
+    /// Splits the artifacts of a shared library target into the runtime file
+    /// and, on Windows, the import library. Debug symbol files are skipped.
+    pub fn shared_library(&self) -> Option<SharedLibrary> {
+        if self.ty != "SHARED_LIBRARY" {
+            return None;
+        }
+        let has_extension = |path: &Path, extensions: &[&str]| {
+            let name = path.to_string_lossy().to_lowercase();
+            extensions.iter().any(|extension| name.ends_with(extension))
+        };
+
+        let import = self
+            .artifacts
+            .iter()
+            .find(|artifact| has_extension(artifact, &[".lib", ".dll.a"]));
+        let runtime = self.artifacts.iter().find(|artifact| {
+            Some(*artifact) != import && !has_extension(artifact, &[".pdb", ".dbg", ".dsym"])
+        })?;
+        Some(SharedLibrary {
+            target: self.name.clone(),
+            runtime: runtime.clone(),
+            import: import.cloned(),
+        })
+    }
+}
+
+/// The compiler of a language enabled by the project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toolchain {
+    /// The language (e.g. `"CXX"`).
+    pub language: String,
+    /// The compiler ID (e.g. `"GNU"` or `"MSVC"`), if identified.
+    pub compiler_id: Option<String>,
+    /// The path of the compiler, if known.
+    pub compiler_path: Option<PathBuf>,
+    /// The compiler version, if identified.
+    pub compiler_version: Option<String>,
+    /// The include directories the compiler searches implicitly.
+    pub implicit_include_dirs: Vec<PathBuf>,
+    /// The link directories the compiler passes to the linker implicitly.
+    pub implicit_link_dirs: Vec<PathBuf>,
+    /// The libraries the compiler links implicitly.
+    pub implicit_link_libraries: Vec<String>,
+    /// The extensions of the source files of the language.
+    pub source_file_extensions: Vec<String>,
+}
+
+/// Asks CMake to write codemodel, cache and toolchains replies on the next
+/// configure of `binary_dir`.
+///
+/// [`Cmd`](crate::Cmd) does this before every configure.
+///
+/// # Arguments
+///
+/// * `binary_dir` - Path to the build directory.
+///
+/// # Errors
+///
+/// Returns an error if the query directory cannot be written.
+pub fn request<P>(binary_dir: P) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let query = query_dir(binary_dir.as_ref());
+    std::fs::create_dir_all(&query)?;
+    for kind in ["codemodel-v2", "cache-v2", "toolchains-v1"] {
+        std::fs::write(query.join(kind), "")?;
+    }
+    Ok(())
+}
+
+/// Reads the targets of the first configuration from the latest reply.
+///
+/// Returns `None` if there is no reply, e.g. because CMake is older than 3.14.
+///
+/// # Arguments
+///
+/// * `binary_dir` - Path to the build directory.
+///
+/// # Errors
+///
+/// Returns an error if a reply file cannot be read or parsed.
+pub fn targets<P>(binary_dir: P) -> io::Result<Option<Vec<Target>>>
+where
+    P: AsRef<Path>,
+{
+    let reply = reply_dir(binary_dir.as_ref());
+    let Some(codemodel) = reply_object(&reply, "codemodel-v2")? else {
+        return Ok(None);
+    };
+
+    let configuration = &codemodel["configurations"][0];
+    // artifact paths are relative to the top-level build directory unless outside of it
+    let build_dir = PathBuf::from(codemodel["paths"]["build"].as_str().unwrap_or_default());
+    // and source paths relative to the top-level source directory
+    let source_dir = PathBuf::from(codemodel["paths"]["source"].as_str().unwrap_or_default());
+    let mut targets = Vec::new();
+    let entries = configuration["targets"].as_array();
+    for entry in entries.into_iter().flatten() {
+        let Some(target_file) = entry["jsonFile"].as_str() else {
+            continue;
+        };
+        let target = read_json(&reply.join(target_file))?;
+        targets.push(Target {
+            id: target["id"].as_str().unwrap_or_default().to_string(),
+            name: target["name"].as_str().unwrap_or_default().to_string(),
+            ty: target["type"].as_str().unwrap_or_default().to_string(),
+            directory: entry["directoryIndex"]
+                .as_u64()
+                .and_then(|index| configuration["directories"][index as usize]["source"].as_str())
+                .map(relative_path)
+                .unwrap_or_default(),
+            artifacts: target["artifacts"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|artifact| artifact["path"].as_str())
+                .map(|path| build_dir.join(path))
+                .collect(),
+            languages: {
+                let mut languages: Vec<String> = target["compileGroups"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|group| group["language"].as_str())
+                    .map(str::to_string)
+                    .collect();
+                languages.sort();
+                languages.dedup();
+                languages
+            },
+            dependencies: target["dependencies"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|dependency| dependency["id"].as_str())
+                .map(str::to_string)
+                .collect(),
+            link_libraries: target["link"]["commandFragments"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|fragment| fragment["role"].as_str() == Some("libraries"))
+                .filter_map(|fragment| fragment["fragment"].as_str())
+                .map(str::to_string)
+                .collect(),
+            compile_groups: target["compileGroups"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(compile_group)
+                .collect(),
+            sources: target["sources"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|source| source["isGenerated"].as_bool() != Some(true))
+                .filter_map(|source| source["path"].as_str())
+                .map(|path| source_dir.join(path))
+                .collect(),
+        });
+    }
+    Ok(Some(targets))
+}
+
+/// Reads the cache entries from the latest reply.
+///
+/// Returns `None` if there is no reply, e.g. because CMake is older than 3.14.
+///
+/// # Arguments
+///
+/// * `binary_dir` - Path to the build directory.
+///
+/// # Errors
+///
+/// Returns an error if a reply file cannot be read or parsed.
+pub fn cache<P>(binary_dir: P) -> io::Result<Option<Vec<CacheEntry>>>
+where
+    P: AsRef<Path>,
+{
+    let reply = reply_dir(binary_dir.as_ref());
+    let Some(cache) = reply_object(&reply, "cache-v2")? else {
+        return Ok(None);
+    };
+    let property = |entry: &Value, name: &str| {
+        entry["properties"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|property| property["name"].as_str() == Some(name))
+            .and_then(|property| property["value"].as_str())
+            .map(str::to_string)
+    };
+    let entries = cache["entries"].as_array().into_iter().flatten();
+    Ok(Some(
+        entries
+            .map(|entry| CacheEntry {
+                name: entry["name"].as_str().unwrap_or_default().to_string(),
+                ty: entry["type"].as_str().unwrap_or_default().to_string(),
+                value: entry["value"].as_str().unwrap_or_default().to_string(),
+                help: property(entry, "HELPSTRING"),
+                advanced: property(entry, "ADVANCED").is_some_and(|value| value == "1"),
+            })
+            .collect(),
+    ))
+}
+
+/// Reads the toolchains from the latest reply.
+///
+/// Returns `None` if there is no reply, e.g. because CMake is older than 3.20.
+///
+/// # Arguments
+///
+/// * `binary_dir` - Path to the build directory.
+///
+/// # Errors
+///
+/// Returns an error if a reply file cannot be read or parsed.
+pub fn toolchains<P>(binary_dir: P) -> io::Result<Option<Vec<Toolchain>>>
+where
+    P: AsRef<Path>,
+{
+    let reply = reply_dir(binary_dir.as_ref());
+    let Some(toolchains) = reply_object(&reply, "toolchains-v1")? else {
+        return Ok(None);
+    };
+    let strings = |value: &Value| -> Vec<String> {
+        value
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect()
+    };
+    let paths = |value: &Value| strings(value).into_iter().map(PathBuf::from).collect();
+    let entries = toolchains["toolchains"].as_array().into_iter().flatten();
+    Ok(Some(
+        entries
+            .map(|toolchain| {
+                let compiler = &toolchain["compiler"];
+                let implicit = &compiler["implicit"];
+                Toolchain {
+                    language: toolchain["language"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    compiler_id: compiler["id"].as_str().map(str::to_string),
+                    compiler_path: compiler["path"].as_str().map(PathBuf::from),
+                    compiler_version: compiler["version"].as_str().map(str::to_string),
+                    implicit_include_dirs: paths(&implicit["includeDirectories"]),
+                    implicit_link_dirs: paths(&implicit["linkDirectories"]),
+                    implicit_link_libraries: strings(&implicit["linkLibraries"]),
+                    source_file_extensions: strings(&toolchain["sourceFileExtensions"]),
+                }
+            })
+            .collect(),
+    ))
+}
+
+/// Reads the object of the given kind (e.g. `"codemodel-v2"`) that answers
+/// cmakr's query in the latest index of `reply`.
+fn reply_object(reply: &Path, kind: &str) -> io::Result<Option<Value>> {
+    if !reply.is_dir() {
+        return Ok(None);
+    }
+
+    // index files are named after their creation time, so the last one is the latest
+    let mut indices: Vec<PathBuf> = std::fs::read_dir(reply)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("index-"))
+        })
+        .collect();
+    indices.sort();
+    let Some(index) = indices.last() else {
+        return Ok(None);
+    };
+
+    let index = read_json(index)?;
+    let Some(file) = index["reply"][CLIENT][kind]["jsonFile"].as_str() else {
+        return Ok(None);
+    };
+    read_json(&reply.join(file)).map(Some)
+}
+
+/// Reads a compile group of a target reply.
+fn compile_group(group: &Value) -> CompileGroup {
+    let strings = |key: &str, field: &str| -> Vec<String> {
+        group[key]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry[field].as_str())
+            .map(str::to_string)
+            .collect()
+    };
+    CompileGroup {
+        language: group["language"].as_str().unwrap_or_default().to_string(),
+        fragments: strings("compileCommandFragments", "fragment"),
+        defines: strings("defines", "define"),
+        includes: group["includes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|include| {
+                let path = include["path"].as_str()?;
+                Some((
+                    PathBuf::from(path),
+                    include["isSystem"].as_bool() == Some(true),
+                ))
+            })
+            .collect(),
+    }
+}
+
+/// Returns the names of the buildable targets defined in `directory` or below.
+///
+/// Interface libraries are left out, since they have no build rule unless
+/// they list sources.
+pub(crate) fn targets_under(targets: &[Target], directory: &Path) -> Vec<String> {
+    let directory = relative_path(&directory.to_string_lossy());
+    targets
+        .iter()
+        .filter(|target| target.ty != "INTERFACE_LIBRARY")
+        .filter(|target| target.directory.starts_with(&directory))
+        .map(|target| target.name.clone())
+        .collect()
+}
+
+/// Normalizes a source-relative path, dropping `.` components.
+fn relative_path(path: &str) -> PathBuf {
+    Path::new(path)
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+/// Returns the directory of cmakr's stateless query.
+fn query_dir(binary_dir: &Path) -> PathBuf {
+    binary_dir
+        .join(".cmake")
+        .join("api")
+        .join("v1")
+        .join("query")
+        .join(CLIENT)
+}
+
+/// Returns the directory CMake writes its replies to.
+fn reply_dir(binary_dir: &Path) -> PathBuf {
+    binary_dir
+        .join(".cmake")
+        .join("api")
+        .join("v1")
+        .join("reply")
+}
+
+/// Reads and parses a JSON file, mapping parse errors to I/O errors.
+fn read_json(path: &Path) -> io::Result<Value> {
+    let content = std::fs::read_to_string(path)?;
+    serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::TempBuildDir;
+
+    #[test]
+    fn read_replies() {
+        let temp = TempBuildDir::new("fileapi").unwrap();
+        let binary = temp.path().to_path_buf();
+        request(&binary).unwrap();
+        assert!(query_dir(&binary).join("codemodel-v2").is_file());
+        assert!(query_dir(&binary).join("toolchains-v1").is_file());
+        assert_eq!(targets(&binary).unwrap(), None);
+        assert_eq!(cache(&binary).unwrap(), None);
+
+        let reply = reply_dir(&binary);
+        std::fs::create_dir_all(&reply).unwrap();
+        let files = [
+            (
+                "index-2024-01-01T00-00-00-0000.json",
+                r#"{"reply":{"client-cmakr":{"codemodel-v2":{"jsonFile":"codemodel-v2-1.json"},"cache-v2":{"jsonFile":"cache-v2-1.json"},"toolchains-v1":{"jsonFile":"toolchains-v1-1.json"}}}}"#,
+            ),
+            (
+                "codemodel-v2-1.json",
+                r#"{"paths":{"build":"/b","source":"/s"},"configurations":[{"directories":[{"source":"."},{"source":"libs/foo"}],"targets":[{"name":"headers","directoryIndex":1,"jsonFile":"target-headers.json"},{"name":"docs","directoryIndex":0,"jsonFile":"target-docs.json"}]}]}"#,
+            ),
+            (
+                "target-headers.json",
+                r#"{"name":"headers","type":"INTERFACE_LIBRARY","compileGroups":[{"language":"CXX","compileCommandFragments":[{"fragment":"-O2 -std=gnu++17"}],"defines":[{"define":"FOO=1"}],"includes":[{"path":"/s/include"},{"path":"/usr/include/boost","isSystem":true}]},{"language":"C"},{"language":"CXX"}]}"#,
+            ),
+            (
+                "target-docs.json",
+                r#"{"id":"docs::@6890","name":"docs","type":"UTILITY","dependencies":[{"id":"headers::@6890"}],"sources":[{"path":"docs/index.md"},{"path":"/b/docs.stamp","isGenerated":true}],"link":{"commandFragments":[{"fragment":"-O2","role":"flags"},{"fragment":"/b/libfoo.a","role":"libraries"},{"fragment":"-lm","role":"libraries"}]}}"#,
+            ),
+            (
+                "cache-v2-1.json",
+                r#"{"entries":[{"name":"BUILD_TESTING","type":"BOOL","value":"ON","properties":[{"name":"HELPSTRING","value":"Build the testing tree."}]},{"name":"CMAKE_AR","type":"FILEPATH","value":"/usr/bin/ar","properties":[{"name":"ADVANCED","value":"1"}]}]}"#,
+            ),
+            (
+                "toolchains-v1-1.json",
+                r#"{"toolchains":[{"language":"CXX","compiler":{"id":"GNU","path":"/usr/bin/c++","version":"13.2.0","implicit":{"includeDirectories":["/usr/include"],"linkLibraries":["stdc++","m"]}},"sourceFileExtensions":["cpp","cxx"]}]}"#,
+            ),
+        ];
+        for (name, content) in files {
+            std::fs::write(reply.join(name), content).unwrap();
+        }
+
+        let targets = targets(&binary).unwrap().unwrap();
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].name, "headers");
+        assert_eq!(targets[0].ty, "INTERFACE_LIBRARY");
+        assert!(!targets.iter().any(Target::produces_artifact));
+        assert_eq!(targets[0].shared_library(), None);
+        assert_eq!(targets[0].directory, PathBuf::from("libs/foo"));
+        assert_eq!(targets[1].directory, PathBuf::new());
+        assert_eq!(targets[0].languages, vec!["C", "CXX"]);
+        assert_eq!(
+            targets[0].compile_groups[0],
+            CompileGroup {
+                language: "CXX".to_string(),
+                fragments: vec!["-O2 -std=gnu++17".to_string()],
+                defines: vec!["FOO=1".to_string()],
+                includes: vec![
+                    (PathBuf::from("/s/include"), false),
+                    (PathBuf::from("/usr/include/boost"), true)
+                ],
+            }
+        );
+        assert_eq!(targets[1].id, "docs::@6890");
+        assert_eq!(targets[1].sources, [PathBuf::from("/s/docs/index.md")]);
+        assert_eq!(targets[1].dependencies, vec!["headers::@6890"]);
+        assert_eq!(targets[1].link_libraries, vec!["/b/libfoo.a", "-lm"]);
+        assert!(targets[0].link_libraries.is_empty());
+        assert!(targets_under(&targets, Path::new("./libs/foo/")).is_empty());
+        assert_eq!(targets_under(&targets, Path::new(".")), vec!["docs"]);
+
+        let cache = cache(&binary).unwrap().unwrap();
+        assert_eq!(cache[0].name, "BUILD_TESTING");
+        assert_eq!(cache[0].ty, "BOOL");
+        assert_eq!(cache[0].help.as_deref(), Some("Build the testing tree."));
+        assert!(!cache[0].advanced);
+        assert!(cache[1].advanced);
+
+        let toolchains = toolchains(&binary).unwrap().unwrap();
+        assert_eq!(toolchains.len(), 1);
+        assert_eq!(toolchains[0].compiler_id.as_deref(), Some("GNU"));
+        assert_eq!(
+            toolchains[0].compiler_path,
+            Some(PathBuf::from("/usr/bin/c++"))
+        );
+        assert_eq!(toolchains[0].implicit_link_libraries, vec!["stdc++", "m"]);
+        assert!(toolchains[0].implicit_link_dirs.is_empty());
+    }
+
+    #[test]
+    fn split_import_libraries() {
+        let shared = |artifacts: &[&str]| Target {
+            name: "foo".to_string(),
+            ty: "SHARED_LIBRARY".to_string(),
+            artifacts: artifacts.iter().map(PathBuf::from).collect(),
+            ..Target::default()
+        };
+
+        let msvc = shared(&[
+            "C:/out/bin/foo.dll",
+            "C:/out/lib/foo.lib",
+            "C:/out/bin/foo.pdb",
+        ])
+        .shared_library()
+        .unwrap();
+        assert_eq!(msvc.runtime, PathBuf::from("C:/out/bin/foo.dll"));
+        assert_eq!(msvc.link_file(), Path::new("C:/out/lib/foo.lib"));
+
+        let mingw = shared(&["/out/libfoo.dll.a", "/out/libfoo.dll"])
+            .shared_library()
+            .unwrap();
+        assert_eq!(mingw.runtime, PathBuf::from("/out/libfoo.dll"));
+        assert_eq!(mingw.import, Some(PathBuf::from("/out/libfoo.dll.a")));
+
+        let elf = shared(&["/out/libfoo.so.1.2.3"]).shared_library().unwrap();
+        assert_eq!(elf.import, None);
+        assert_eq!(elf.link_file(), Path::new("/out/libfoo.so.1.2.3"));
+
+        let archive = Target {
+            ty: "STATIC_LIBRARY".to_string(),
+            ..shared(&["/out/libfoo.a"])
+        };
+        assert_eq!(archive.shared_library(), None);
+        assert_eq!(shared(&["C:/out/foo.pdb"]).shared_library(), None);
+    }
+
+    #[test]
+    fn find_targets_under_subdirectory() {
+        let target = |name: &str, ty: &str, directory: &str| Target {
+            name: name.to_string(),
+            ty: ty.to_string(),
+            directory: PathBuf::from(directory),
+            ..Target::default()
+        };
+        let targets = vec![
+            target("app", "EXECUTABLE", ""),
+            target("net", "STATIC_LIBRARY", "libs/net"),
+            target("net_tests", "EXECUTABLE", "libs/net/tests"),
+            target("net_headers", "INTERFACE_LIBRARY", "libs/net"),
+            target("netx", "STATIC_LIBRARY", "libs/netx"),
+        ];
+
+        assert_eq!(
+            targets_under(&targets, Path::new("./libs/net")),
+            ["net", "net_tests"]
+        );
+        assert_eq!(targets_under(&targets, Path::new("")).len(), 4);
+        assert!(targets_under(&targets, Path::new("apps")).is_empty());
+    }
+}
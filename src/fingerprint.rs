@@ -0,0 +1,332 @@
+//! Skipping configure when none of its inputs changed.
+//!
+//! In a build script, every `cargo build` runs the build script's `Cmd`
+//! again, and with it a configure that usually has nothing to do. With
+//! [`Cmd::skip_configure_if_unchanged`](crate::Cmd::skip_configure_if_unchanged),
+//! a successful configure stores a fingerprint of its inputs in the build
+//! tree: the CMake version, the configure arguments and environment, and the
+//! state of the project's CMake files (`CMakeLists.txt`, `*.cmake`, and the
+//! presets files). A later run with the same fingerprint goes straight to
+//! the build. The native build tool still reruns CMake on its own if a file
+//! CMake reads changes in a way the fingerprint doesn't cover.
+//!
+//! How the files are fingerprinted is up to a [`Fingerprinter`], chosen with
+//! [`Cmd::set_fingerprinter`](crate::Cmd::set_fingerprinter):
+//!
+//! | Fingerprinter | Cost | Misses |
+//! |---------------|------|--------|
+//! | [`FileMetadata`] (default) | one `stat` per file | changes that keep the size and modification time |
+//! | [`ContentHash`] | reads every file | nothing |
+//! | [`GitStatus`] | one `git status` | nothing in the repository; files outside it fall back to [`FileMetadata`] |
+//!
+//! [`ContentHash`] suits CI filesystems that don't keep modification times
+//! across checkouts or caches; [`GitStatus`] suits huge source trees.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, fingerprint::GitStatus};
+//!
+//! Cmd::default()
+//!     .set_path("./native")
+//!     .skip_configure_if_unchanged()
+//!     .set_fingerprinter(GitStatus)
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+    time::UNIX_EPOCH,
+};
+
+use crate::{compiler_cache::fnv1a, gc::STATE_DIR};
+
+/// The file name of the fingerprint in the state directory of a build tree.
+const FINGERPRINT_FILE: &str = "configure.fingerprint";
+
+/// File names read by configure besides `*.cmake` files.
+const CMAKE_FILES: [&str; 3] = [
+    "CMakeLists.txt",
+    "CMakePresets.json",
+    "CMakeUserPresets.json",
+];
+
+/// Fingerprints the files configure reads.
+///
+/// Implement it to plug in another strategy, e.g. the file hashes a build
+/// system already keeps.
+pub trait Fingerprinter: Send + Sync {
+    /// Returns a fingerprint of `files` that changes when any of them
+    /// changes. A missing file has a fingerprint of its own.
+    ///
+    /// # Arguments
+    ///
+    /// * `source_dir` - The source directory of the project.
+    /// * `files` - The files to fingerprint, sorted.
+    ///
+    /// # Errors
+    ///
+    /// An error fails the run before configure.
+    fn fingerprint(&self, source_dir: &Path, files: &[PathBuf]) -> io::Result<String>;
+}
+
+/// Fingerprints files by their size and modification time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileMetadata;
+
+impl Fingerprinter for FileMetadata {
+    fn fingerprint(&self, _source_dir: &Path, files: &[PathBuf]) -> io::Result<String> {
+        Ok(files.iter().map(|file| metadata_line(file)).collect())
+    }
+}
+
+/// Fingerprints files by a hash of their contents.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ContentHash;
+
+impl Fingerprinter for ContentHash {
+    fn fingerprint(&self, _source_dir: &Path, files: &[PathBuf]) -> io::Result<String> {
+        let mut lines = String::new();
+        for file in files {
+            let content = std::fs::read(file).unwrap_or_default();
+            lines.push_str(&format!("{}={:016x}\n", file.display(), fnv1a(&content)));
+        }
+        Ok(lines)
+    }
+}
+
+/// Fingerprints the files in a git repository by the checked out commit and
+/// `git status`, and the files with local changes by their
+/// [metadata](FileMetadata). Files outside the repository are fingerprinted
+/// by their metadata as well.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GitStatus;
+
+impl Fingerprinter for GitStatus {
+    fn fingerprint(&self, source_dir: &Path, files: &[PathBuf]) -> io::Result<String> {
+        let toplevel = PathBuf::from(git(source_dir, &["rev-parse", "--show-toplevel"])?.trim());
+        let toplevel = toplevel.canonicalize().unwrap_or(toplevel);
+        let (tracked, outside): (Vec<PathBuf>, Vec<PathBuf>) = files
+            .iter()
+            .map(|file| file.canonicalize().unwrap_or_else(|_| file.clone()))
+            .partition(|file| file.starts_with(&toplevel));
+
+        let mut lines = git(source_dir, &["rev-parse", "HEAD"])?;
+        if !tracked.is_empty() {
+            let mut args = vec!["status", "--porcelain", "--untracked-files=all", "--"];
+            args.extend(tracked.iter().filter_map(|file| file.to_str()));
+            let status = git(&toplevel, &args)?;
+            // a file edited again keeps its status line, but not its metadata
+            for line in status.lines() {
+                let path = line.get(3..).unwrap_or_default();
+                let path = path.rsplit(" -> ").next().unwrap_or(path);
+                lines.push_str(&metadata_line(&toplevel.join(path.trim_matches('"'))));
+            }
+            lines.push_str(&status);
+        }
+        lines.extend(outside.iter().map(|file| metadata_line(file)));
+        Ok(lines)
+    }
+}
+
+/// Returns the size and modification time of `file` as a line.
+fn metadata_line(file: &Path) -> String {
+    match std::fs::metadata(file) {
+        Ok(metadata) => {
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .unwrap_or_default();
+            format!(
+                "{} {} {}.{:09}\n",
+                file.display(),
+                metadata.len(),
+                modified.as_secs(),
+                modified.subsec_nanos()
+            )
+        }
+        Err(_) => format!("{} missing\n", file.display()),
+    }
+}
+
+/// Runs git with `args` in `dir` and returns its standard output.
+fn git(dir: &Path, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("git").arg("-C").arg(dir).args(args).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "git {} failed in {}: {}",
+            args.first().copied().unwrap_or_default(),
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Computes the fingerprint of a configure of `source_dir` into `binary_dir`.
+///
+/// `inputs` are the configure arguments and environment changes;
+/// `extra_files` are files outside the source tree that configure reads,
+/// such as a toolchain file. The files are fingerprinted by `fingerprinter`.
+pub(crate) fn compute(
+    fingerprinter: &dyn Fingerprinter,
+    cmake_version: &str,
+    inputs: &[String],
+    source_dir: &Path,
+    binary_dir: &Path,
+    extra_files: &[PathBuf],
+) -> io::Result<String> {
+    let mut files = project_files(source_dir, binary_dir)?;
+    files.extend(extra_files.iter().cloned());
+
+    let mut parts = vec![cmake_version.to_string()];
+    parts.extend(inputs.iter().cloned());
+    parts.push(fingerprinter.fingerprint(source_dir, &files)?);
+    Ok(format!("{:016x}", fnv1a(parts.join("\n").as_bytes())))
+}
+
+/// Returns whether the last successful configure of `binary_dir` had
+/// `fingerprint`.
+pub(crate) fn matches(binary_dir: &Path, fingerprint: &str) -> bool {
+    std::fs::read_to_string(path(binary_dir)).is_ok_and(|stored| stored.trim() == fingerprint)
+}
+
+/// Records `fingerprint` for a successful configure of `binary_dir`.
+pub(crate) fn store(binary_dir: &Path, fingerprint: &str) -> io::Result<()> {
+    let path = path(binary_dir);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, fingerprint)
+}
+
+/// Forgets the fingerprint of `binary_dir`, before a configure that may fail.
+pub(crate) fn clear(binary_dir: &Path) -> io::Result<()> {
+    match std::fs::remove_file(path(binary_dir)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the CMake files of the project at `source_dir`, sorted, without
+/// the ones in build trees such as `binary_dir`.
+pub(crate) fn project_files(source_dir: &Path, binary_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    cmake_files(source_dir, &binary_dir.canonicalize()?, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+fn path(binary_dir: &Path) -> PathBuf {
+    binary_dir.join(STATE_DIR).join(FINGERPRINT_FILE)
+}
+
+/// Adds the CMake files under `dir` to `files`, skipping hidden directories
+/// and build trees, including `binary_dir`.
+fn cmake_files(dir: &Path, binary_dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() {
+            let skipped = name.starts_with('.')
+                || path.join("CMakeCache.txt").is_file()
+                || path.canonicalize().is_ok_and(|path| path == binary_dir);
+            if !skipped {
+                cmake_files(&path, binary_dir, files)?;
+            }
+        } else if CMAKE_FILES.contains(&name.as_str()) || name.ends_with(".cmake") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::TempBuildDir;
+
+    #[test]
+    fn fingerprint_configure_inputs() {
+        let temp = TempBuildDir::new("fingerprint").unwrap();
+        let source = temp.path().join("src");
+        let binary = source.join("build");
+        std::fs::create_dir_all(source.join("lib")).unwrap();
+        std::fs::create_dir_all(&binary).unwrap();
+        std::fs::write(source.join("CMakeLists.txt"), "project(p)\n").unwrap();
+        std::fs::write(source.join("lib/CMakeLists.txt"), "add_library(l)\n").unwrap();
+        std::fs::write(source.join("main.c"), "int main() {}\n").unwrap();
+        std::fs::write(binary.join("generated.cmake"), "").unwrap();
+
+        let args = ["-DFOO=ON".to_string()];
+        let fingerprint = compute(&ContentHash, "3.28.3", &args, &source, &binary, &[]).unwrap();
+        assert!(!matches(&binary, &fingerprint));
+        store(&binary, &fingerprint).unwrap();
+        assert!(matches(&binary, &fingerprint));
+
+        // sources and the build tree don't count, CMake files and arguments do
+        std::fs::write(source.join("main.c"), "int main() { return 1; }\n").unwrap();
+        std::fs::write(binary.join("generated.cmake"), "set(X 1)\n").unwrap();
+        assert_eq!(
+            compute(&ContentHash, "3.28.3", &args, &source, &binary, &[]).unwrap(),
+            fingerprint
+        );
+        assert_ne!(
+            compute(&ContentHash, "3.28.3", &[], &source, &binary, &[]).unwrap(),
+            fingerprint
+        );
+        std::fs::write(source.join("lib/CMakeLists.txt"), "add_library(m)\n").unwrap();
+        assert_ne!(
+            compute(&ContentHash, "3.28.3", &args, &source, &binary, &[]).unwrap(),
+            fingerprint
+        );
+
+        clear(&binary).unwrap();
+        clear(&binary).unwrap();
+        assert!(!matches(&binary, &fingerprint));
+    }
+
+    #[test]
+    fn fingerprint_strategies() {
+        let temp = TempBuildDir::new("fingerprinters").unwrap();
+        let source = temp.path().join("src");
+        std::fs::create_dir_all(&source).unwrap();
+        let lists = source.join("CMakeLists.txt");
+        let toolchain = temp.path().join("toolchain.cmake");
+        std::fs::write(&lists, "project(p)\n").unwrap();
+        std::fs::write(&toolchain, "").unwrap();
+        let files = [lists.clone(), toolchain.clone()];
+
+        let before = FileMetadata.fingerprint(&source, &files).unwrap();
+        std::fs::write(&lists, "project(p C)\n").unwrap();
+        assert_ne!(FileMetadata.fingerprint(&source, &files).unwrap(), before);
+
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(&source)
+                .args(["-c", "user.name=cmakr", "-c", "user.email=cmakr@localhost"])
+                .args(args)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {:?}", args);
+        };
+        git(&["init", "-q"]);
+        git(&["add", "CMakeLists.txt"]);
+        git(&["commit", "-q", "-m", "init"]);
+        let clean = GitStatus.fingerprint(&source, &files).unwrap();
+        assert_eq!(GitStatus.fingerprint(&source, &files).unwrap(), clean);
+        std::fs::write(&lists, "project(p CXX)\n").unwrap();
+        let edited = GitStatus.fingerprint(&source, &files).unwrap();
+        assert_ne!(edited, clean);
+        // the toolchain file outside the repository counts by its metadata
+        std::fs::write(&toolchain, "set(CMAKE_SYSTEM_NAME Linux)\n").unwrap();
+        assert_ne!(GitStatus.fingerprint(&source, &files).unwrap(), edited);
+    }
+}
@@ -0,0 +1,276 @@
+//! Disk usage reporting and garbage collection of cmakr build directories.
+//!
+//! Every build directory used by [`Cmd`](crate::Cmd) gets a `.cmakr/last-used`
+//! stamp that is refreshed on each run. The functions in this module scan a
+//! root directory (typically a Cargo `target/` dir) for such build trees,
+//! report how much space they take, and prune the ones that are stale or
+//! exceed a size budget.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use cmakr::gc::{self, GcPolicy};
+//!
+//! for usage in gc::disk_usage("./target").unwrap() {
+//!     println!("{}: {} bytes", usage.path.display(), usage.size);
+//! }
+//!
+//! let policy = GcPolicy::default()
+//!     .set_max_age(Duration::from_secs(14 * 24 * 60 * 60))
+//!     .set_max_total_size(20 * 1024 * 1024 * 1024);
+//! let removed = gc::prune("./target", &policy).unwrap();
+//! println!("removed {} build directories", removed.len());
+//! ```
+
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::lock::{DirLock, LOCK_FILE_NAME};
+
+/// Directory inside a build tree where cmakr keeps its own bookkeeping files.
+pub(crate) const STATE_DIR: &str = ".cmakr";
+
+/// Name of the stamp file refreshed every time a build directory is used.
+const LAST_USED_FILE: &str = "last-used";
+
+/// Disk usage of a single cmakr build directory.
+#[derive(Debug, Clone)]
+pub struct BuildDirUsage {
+    /// Path to the build directory.
+    pub path: PathBuf,
+    /// Total size of all files in the directory, in bytes.
+    pub size: u64,
+    /// When cmakr last ran a build in this directory.
+    pub last_used: SystemTime,
+}
+
+/// Rules deciding which build directories [`prune`] removes.
+///
+/// A directory is removed if it has not been used for longer than
+/// `max_age`, or if keeping it would push the total size of all build
+/// directories over `max_total_size` (the least recently used directories
+/// are removed first). With no limits set, nothing is removed.
+#[derive(Debug, Clone, Default)]
+pub struct GcPolicy {
+    max_age: Option<Duration>,
+    max_total_size: Option<u64>,
+}
+
+impl GcPolicy {
+    /// Removes build directories not used for longer than `age`.
+    pub fn set_max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Keeps the total size of all build directories under `bytes`.
+    pub fn set_max_total_size(mut self, bytes: u64) -> Self {
+        self.max_total_size = Some(bytes);
+        self
+    }
+}
+
+/// Reports the disk usage of every cmakr build directory found under `root`.
+///
+/// The result is sorted by last use, most recent first.
+///
+/// # Errors
+///
+/// Returns an I/O error if `root` or one of its subdirectories cannot be read.
+pub fn disk_usage<P>(root: P) -> std::io::Result<Vec<BuildDirUsage>>
+where
+    P: AsRef<Path>,
+{
+    let mut found = Vec::new();
+    find_build_dirs(root.as_ref(), &mut found)?;
+
+    let mut usages = found
+        .into_iter()
+        .map(|path| {
+            let last_used = path
+                .join(STATE_DIR)
+                .join(LAST_USED_FILE)
+                .metadata()?
+                .modified()?;
+            let size = dir_size(&path)?;
+            Ok(BuildDirUsage {
+                path,
+                size,
+                last_used,
+            })
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    usages.sort_by_key(|u| std::cmp::Reverse(u.last_used));
+    Ok(usages)
+}
+
+/// Removes the build directories under `root` selected by `policy`.
+///
+/// Directories that a run is configuring or building, which holds their
+/// lock, are skipped and count towards the total size.
+///
+/// Returns the usage entries of the removed directories.
+///
+/// # Errors
+///
+/// Returns an I/O error if scanning `root` or removing a directory fails.
+pub fn prune<P>(root: P, policy: &GcPolicy) -> std::io::Result<Vec<BuildDirUsage>>
+where
+    P: AsRef<Path>,
+{
+    let now = SystemTime::now();
+    let mut total = 0u64;
+    let mut removed = Vec::new();
+
+    for usage in disk_usage(root)? {
+        let age = now.duration_since(usage.last_used).unwrap_or_default();
+        let too_old = policy.max_age.is_some_and(|max| age > max);
+        let over_budget = policy
+            .max_total_size
+            .is_some_and(|max| total + usage.size > max);
+
+        if (too_old || over_budget) && remove_unlocked(&usage.path)? {
+            removed.push(usage);
+        } else {
+            total += usage.size;
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Removes the build directory `dir`, unless another run holds its lock,
+/// and returns whether it was removed.
+fn remove_unlocked(dir: &Path) -> std::io::Result<bool> {
+    let lock = match DirLock::acquire_within(dir, Some(Duration::ZERO)) {
+        Ok(lock) => lock,
+        Err(e) if e.kind() == std::io::ErrorKind::TimedOut => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name() == LOCK_FILE_NAME {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            std::fs::remove_dir_all(entry.path())?;
+        } else {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+
+    // the lock file goes last, as Windows can't delete it while it is open;
+    // a run that locked the emptied tree in between keeps it
+    drop(lock);
+    let _ = std::fs::remove_file(dir.join(LOCK_FILE_NAME));
+    let _ = std::fs::remove_dir(dir);
+    Ok(true)
+}
+
+/// Refreshes the last-used stamp of a build directory.
+pub(crate) fn touch(binary_dir: &Path) -> std::io::Result<()> {
+    let state_dir = binary_dir.join(STATE_DIR);
+    std::fs::create_dir_all(&state_dir)?;
+
+    let stamp = File::create(state_dir.join(LAST_USED_FILE))?;
+    stamp.set_modified(SystemTime::now())
+}
+
+/// Recursively collects directories containing a cmakr state directory.
+///
+/// Build directories are not descended into, and symlinks are not followed.
+fn find_build_dirs(dir: &Path, found: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if dir.join(STATE_DIR).join(LAST_USED_FILE).is_file() {
+        found.push(dir.to_path_buf());
+        return Ok(());
+    }
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            find_build_dirs(&entry.path(), found)?;
+        }
+    }
+    Ok(())
+}
+
+/// Computes the total size of the files under `dir`, without following symlinks.
+fn dir_size(dir: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::TempBuildDir;
+
+    fn make_build_dir(root: &Path, name: &str, bytes: usize, age: Duration) -> PathBuf {
+        let dir = root.join(name);
+        touch(&dir).unwrap();
+        std::fs::write(dir.join("CMakeCache.txt"), vec![b'x'; bytes]).unwrap();
+
+        let stamp = File::options()
+            .write(true)
+            .open(dir.join(STATE_DIR).join(LAST_USED_FILE))
+            .unwrap();
+        stamp.set_modified(SystemTime::now() - age).unwrap();
+        dir
+    }
+
+    #[test]
+    fn report_and_prune_build_dirs() {
+        let temp = TempBuildDir::new("gc").unwrap();
+        let root = temp.path().to_path_buf();
+
+        let fresh = make_build_dir(&root, "a/fresh", 100, Duration::from_secs(60));
+        let stale = make_build_dir(&root, "b/stale", 100, Duration::from_secs(3600));
+        let old = make_build_dir(&root, "old", 100, Duration::from_secs(30 * 24 * 3600));
+
+        let usage = disk_usage(&root).unwrap();
+        let paths: Vec<_> = usage.iter().map(|u| u.path.clone()).collect();
+        assert_eq!(paths, vec![fresh.clone(), stale.clone(), old.clone()]);
+        assert!(usage.iter().all(|u| u.size >= 100));
+
+        let policy = GcPolicy::default().set_max_age(Duration::from_secs(24 * 3600));
+        let removed = prune(&root, &policy).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(!old.exists());
+
+        let policy = GcPolicy::default().set_max_total_size(usage[0].size);
+        let removed = prune(&root, &policy).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(fresh.exists());
+        assert!(!stale.exists());
+    }
+
+    #[test]
+    fn skip_locked_build_dirs() {
+        let temp = TempBuildDir::new("gc-locked").unwrap();
+        let root = temp.path().to_path_buf();
+        let building = make_build_dir(&root, "building", 100, Duration::from_secs(3600));
+        let policy = GcPolicy::default().set_max_age(Duration::from_secs(60));
+
+        let lock = DirLock::acquire(&building).unwrap();
+        assert!(prune(&root, &policy).unwrap().is_empty());
+        assert!(building.join("CMakeCache.txt").is_file());
+
+        drop(lock);
+        assert_eq!(prune(&root, &policy).unwrap().len(), 1);
+        assert!(!building.exists());
+    }
+}
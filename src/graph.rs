@@ -0,0 +1,570 @@
+//! Target dependency graph export.
+//!
+//! [`BuildGraph::load`] asks CMake for the target graph of a configured build
+//! tree (`cmake --graphviz`) and, for Ninja build trees, annotates every target
+//! with its source files and produced artifacts from `ninja -t graph`. External
+//! build schedulers can use the result to decide what to rebuild or cache.
+//!
+//! The graph serializes to JSON with `serde`, and with the `petgraph` feature
+//! enabled it converts into a `petgraph::graph::DiGraph`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::graph::BuildGraph;
+//!
+//! let graph = BuildGraph::load("./build").unwrap();
+//! for target in &graph.targets {
+//!     println!("{} ({} sources)", target.name, target.sources.len());
+//! }
+//! ```
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::Serialize;
+
+use crate::{cmake, gc::STATE_DIR, tools::Tools};
+
+/// File inside the state directory that CMake writes the target graph to.
+const GRAPHVIZ_FILE: &str = "targets.dot";
+
+/// The kind of a node in the target graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TargetKind {
+    Executable,
+    StaticLibrary,
+    SharedLibrary,
+    ModuleLibrary,
+    InterfaceLibrary,
+    ObjectLibrary,
+    /// A custom target (`add_custom_target`).
+    Custom,
+    /// A library that is linked but not built by the project (e.g. `m`).
+    External,
+}
+
+impl TargetKind {
+    /// Maps the node shape CMake uses in its graphviz output to a kind.
+    fn from_shape(shape: &str) -> Self {
+        match shape {
+            "egg" => TargetKind::Executable,
+            "octagon" => TargetKind::StaticLibrary,
+            "doubleoctagon" => TargetKind::SharedLibrary,
+            "tripleoctagon" => TargetKind::ModuleLibrary,
+            "pentagon" => TargetKind::InterfaceLibrary,
+            "hexagon" => TargetKind::ObjectLibrary,
+            "box" => TargetKind::Custom,
+            _ => TargetKind::External,
+        }
+    }
+}
+
+/// How a target links against one of its dependencies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DependencyKind {
+    /// `target_link_libraries(... PUBLIC ...)`, or no visibility given.
+    Public,
+    /// `target_link_libraries(... INTERFACE ...)`.
+    Interface,
+    /// `target_link_libraries(... PRIVATE ...)`.
+    Private,
+}
+
+/// A target in the build graph.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TargetNode {
+    /// The target name.
+    pub name: String,
+    /// What kind of target this is.
+    pub kind: TargetKind,
+    /// The source files compiled into the target.
+    ///
+    /// Only known for build trees generated by Ninja.
+    pub sources: Vec<PathBuf>,
+    /// The files produced by linking the target.
+    ///
+    /// Only known for build trees generated by Ninja.
+    pub artifacts: Vec<PathBuf>,
+}
+
+/// An edge of the build graph: `from` depends on `to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Dependency {
+    /// Index of the dependent target in [`BuildGraph::targets`].
+    pub from: usize,
+    /// Index of the dependency in [`BuildGraph::targets`].
+    pub to: usize,
+    /// How the dependency is linked.
+    pub kind: DependencyKind,
+}
+
+/// The target dependency graph of a configured build tree.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BuildGraph {
+    /// Every target of the project, plus the external libraries it links.
+    pub targets: Vec<TargetNode>,
+    /// Every dependency edge between [`targets`](BuildGraph::targets).
+    pub dependencies: Vec<Dependency>,
+}
+
+impl BuildGraph {
+    /// Loads the target graph of the already configured build tree `binary_dir`.
+    ///
+    /// This re-runs the configure step with `--graphviz`, so the source tree
+    /// must still be present. When the tree was generated by Ninja, sources
+    /// and artifacts are filled in from `ninja -t graph` as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `binary_dir` - The CMake build directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `cmake` (or `ninja`) cannot be run or fails, or if
+    /// the generated graph cannot be read.
+    pub fn load<P>(binary_dir: P) -> std::io::Result<BuildGraph>
+    where
+        P: AsRef<Path>,
+    {
+        Self::load_with(binary_dir.as_ref(), &Tools::default())
+    }
+
+    /// Loads the graph as [`load`](BuildGraph::load) does, running the
+    /// cmake and ninja of `tools`.
+    pub(crate) fn load_with(binary_dir: &Path, tools: &Tools) -> std::io::Result<BuildGraph> {
+        let binary_dir = binary_dir.canonicalize()?;
+        let state_dir = binary_dir.join(STATE_DIR);
+        std::fs::create_dir_all(&state_dir)?;
+
+        let dot_path = state_dir.join(GRAPHVIZ_FILE);
+        run(Command::new(tools.cmake())
+            .arg(format!("--graphviz={}", dot_path.display()))
+            .arg(&binary_dir))?;
+        let mut graph = Self::from_graphviz(&std::fs::read_to_string(&dot_path)?);
+
+        let generator = cmake::read_cache_value(&binary_dir, "CMAKE_GENERATOR");
+        if generator.is_some_and(|generator| generator.starts_with("Ninja")) {
+            let ninja_graph = run(Command::new(tools.ninja())
+                .arg("-C")
+                .arg(&binary_dir)
+                .args(["-t", "graph"]))?;
+            graph.annotate_from_ninja(&ninja_graph, &binary_dir);
+        }
+
+        Ok(graph)
+    }
+
+    /// Reads the graphviz file written by `cmake --graphviz`, e.g. by a
+    /// configure with [`Cmd::emit_graphviz`](crate::Cmd::emit_graphviz).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read.
+    pub fn read_graphviz<P>(path: P) -> std::io::Result<BuildGraph>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self::from_graphviz(&std::fs::read_to_string(path)?))
+    }
+
+    /// Parses the graphviz file written by `cmake --graphviz`.
+    ///
+    /// The legend subgraph is ignored. Sources and artifacts are left empty.
+    pub fn from_graphviz(dot: &str) -> BuildGraph {
+        let (nodes, edges) = parse_dot(dot);
+        let mut graph = BuildGraph::default();
+        let mut indices = HashMap::new();
+
+        for (id, attrs) in nodes {
+            indices.insert(id, graph.targets.len());
+            graph.targets.push(TargetNode {
+                name: attrs.get("label").cloned().unwrap_or_default(),
+                kind: TargetKind::from_shape(attrs.get("shape").map_or("", String::as_str)),
+                sources: Vec::new(),
+                artifacts: Vec::new(),
+            });
+        }
+
+        for (from, to, attrs) in edges {
+            let (Some(&from), Some(&to)) = (indices.get(&from), indices.get(&to)) else {
+                continue;
+            };
+            let kind = match attrs.get("style").map(String::as_str) {
+                Some("dashed") => DependencyKind::Interface,
+                Some("dotted") => DependencyKind::Private,
+                _ => DependencyKind::Public,
+            };
+            graph.dependencies.push(Dependency { from, to, kind });
+        }
+
+        graph
+    }
+
+    /// Returns the index of the target called `name`.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.targets.iter().position(|target| target.name == name)
+    }
+
+    /// Returns the targets that the target at `index` directly depends on.
+    pub fn dependencies_of(&self, index: usize) -> impl Iterator<Item = &TargetNode> {
+        self.dependencies
+            .iter()
+            .filter(move |dependency| dependency.from == index)
+            .map(|dependency| &self.targets[dependency.to])
+    }
+
+    /// Returns the targets that directly depend on the target at `index`.
+    pub fn dependents_of(&self, index: usize) -> impl Iterator<Item = &TargetNode> {
+        self.dependencies
+            .iter()
+            .filter(move |dependency| dependency.to == index)
+            .map(|dependency| &self.targets[dependency.from])
+    }
+
+    /// Returns the indices of `roots` and of every target that depends on them,
+    /// directly or transitively.
+    pub fn transitive_dependents<I>(&self, roots: I) -> Vec<usize>
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let mut affected: Vec<usize> = roots.into_iter().collect();
+        let mut next = 0;
+        while next < affected.len() {
+            let index = affected[next];
+            for dependency in &self.dependencies {
+                if dependency.to == index && !affected.contains(&dependency.from) {
+                    affected.push(dependency.from);
+                }
+            }
+            next += 1;
+        }
+        affected
+    }
+
+    /// Converts the graph into a `petgraph` directed graph.
+    ///
+    /// Node indices are the same as the indices into
+    /// [`targets`](BuildGraph::targets).
+    #[cfg(feature = "petgraph")]
+    pub fn to_petgraph(&self) -> petgraph::graph::DiGraph<TargetNode, DependencyKind> {
+        let mut graph = petgraph::graph::DiGraph::new();
+        for target in &self.targets {
+            graph.add_node(target.clone());
+        }
+        for dependency in &self.dependencies {
+            graph.add_edge(
+                petgraph::graph::NodeIndex::new(dependency.from),
+                petgraph::graph::NodeIndex::new(dependency.to),
+                dependency.kind,
+            );
+        }
+        graph
+    }
+
+    /// Fills in sources and artifacts from the output of `ninja -t graph`.
+    ///
+    /// CMake names the Ninja rules of a target `<LANG>_COMPILER__<target>_<config>`
+    /// and `<LANG>_<KIND>_LINKER__<target>_<config>`; the inputs of the former
+    /// are the target's sources and the outputs of the latter its artifacts.
+    /// Relative paths are resolved against `binary_dir`.
+    fn annotate_from_ninja(&mut self, ninja_graph: &str, binary_dir: &Path) {
+        let (nodes, edges) = parse_dot(ninja_graph);
+        let labels: HashMap<String, String> = nodes
+            .iter()
+            .filter_map(|(id, attrs)| Some((id.clone(), attrs.get("label")?.clone())))
+            .collect();
+        let rules: HashMap<&str, &str> = nodes
+            .iter()
+            .filter(|(_, attrs)| attrs.get("shape").is_some_and(|shape| shape == "ellipse"))
+            .filter_map(|(id, attrs)| Some((id.as_str(), attrs.get("label")?.as_str())))
+            .collect();
+
+        // (rule, input, output) triples; an edge through a rule node has one side missing
+        let mut steps: Vec<(&str, Option<&str>, Option<&str>)> = Vec::new();
+        for (from, to, attrs) in &edges {
+            if let Some(rule) = rules.get(to.as_str()) {
+                steps.push((rule, Some(from), None));
+            } else if let Some(rule) = rules.get(from.as_str()) {
+                steps.push((rule, None, Some(to)));
+            } else if let Some(rule) = attrs.get("label") {
+                steps.push((rule.trim(), Some(from), Some(to)));
+            }
+        }
+
+        let resolve = |id: &str| labels.get(id).map(|label| binary_dir.join(label));
+        for (rule, input, output) in steps {
+            let Some((index, is_compile)) = self.owner_of_rule(rule) else {
+                continue;
+            };
+            let (files, file) = match is_compile {
+                true => (&mut self.targets[index].sources, input.and_then(resolve)),
+                false => (&mut self.targets[index].artifacts, output.and_then(resolve)),
+            };
+            if let Some(file) = file
+                && !files.contains(&file)
+            {
+                files.push(file);
+            }
+        }
+    }
+
+    /// Returns the target a Ninja rule belongs to, and whether it is a compile rule.
+    ///
+    /// When several target names match, the longest one wins, so `foo_bar`
+    /// is not mistaken for `foo`.
+    fn owner_of_rule(&self, rule: &str) -> Option<(usize, bool)> {
+        let (is_compile, rest) = if let Some((_, rest)) = rule.split_once("_COMPILER__") {
+            (true, rest)
+        } else {
+            (false, rule.split_once("_LINKER__")?.1)
+        };
+
+        self.targets
+            .iter()
+            .enumerate()
+            .filter(|(_, target)| {
+                rest.strip_prefix(target.name.as_str())
+                    .is_some_and(|suffix| suffix.is_empty() || suffix.starts_with('_'))
+            })
+            .max_by_key(|(_, target)| target.name.len())
+            .map(|(index, _)| (index, is_compile))
+    }
+}
+
+/// Parses the output of `ninja -t deps` into `(output, dependencies)` pairs.
+///
+/// Each record is a `<output>: #deps N, ...` header followed by one indented
+/// dependency path per line. Paths are returned as printed by ninja.
+pub(crate) fn parse_ninja_deps(output: &str) -> Vec<(PathBuf, Vec<PathBuf>)> {
+    let mut records: Vec<(PathBuf, Vec<PathBuf>)> = Vec::new();
+
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.starts_with(char::is_whitespace) {
+            if let Some((_, deps)) = records.last_mut() {
+                deps.push(PathBuf::from(line.trim()));
+            }
+        } else if let Some((target, _)) = line.split_once(": #deps") {
+            records.push((PathBuf::from(target), Vec::new()));
+        }
+    }
+
+    records
+}
+
+/// Returns the name of the target an object file belongs to.
+///
+/// CMake places the objects of target `foo` under `CMakeFiles/foo.dir/`.
+pub(crate) fn target_of_object(object: &Path) -> Option<String> {
+    let mut components = object.components().map(|c| c.as_os_str().to_string_lossy());
+    components.find(|c| c == "CMakeFiles")?;
+    let dir = components.next()?;
+    dir.strip_suffix(".dir").map(str::to_string)
+}
+
+/// Runs `command` and returns its stdout, failing on a non-zero exit.
+pub(crate) fn run(command: &mut Command) -> std::io::Result<String> {
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "{:?} failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+type Attrs = HashMap<String, String>;
+
+/// Parses the node and edge statements of a graphviz file.
+///
+/// Only the simple one-statement-per-line layout written by CMake and Ninja
+/// is supported. Statements inside subgraphs and default attribute lists
+/// (`node [...]`) are skipped.
+#[allow(clippy::type_complexity)]
+fn parse_dot(dot: &str) -> (Vec<(String, Attrs)>, Vec<(String, String, Attrs)>) {
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    let mut depth = 0usize;
+
+    for line in dot.lines() {
+        let mut line = line.trim();
+        // drop trailing `// comment`s, unless the `//` is part of a quoted label
+        if let Some(i) = line.find("//")
+            && line[..i].matches('"').count() % 2 == 0
+        {
+            line = line[..i].trim_end();
+        }
+        if line.starts_with("subgraph") {
+            depth += 1;
+            continue;
+        }
+        if line.starts_with('}') {
+            depth = depth.saturating_sub(1);
+            continue;
+        }
+        if depth > 0 || !line.starts_with('"') {
+            continue;
+        }
+
+        let (statement, attrs) = match line.find('[') {
+            Some(i) => (&line[..i], parse_attrs(&line[i + 1..])),
+            None => (line, Attrs::new()),
+        };
+        match statement.split_once("->") {
+            Some((from, to)) => edges.push((unquote(from), unquote(to), attrs)),
+            None => nodes.push((unquote(statement), attrs)),
+        }
+    }
+
+    (nodes, edges)
+}
+
+/// Parses `key = value, key = "value" ]` attribute lists.
+fn parse_attrs(list: &str) -> Attrs {
+    let mut attrs = Attrs::new();
+    let mut chars = list.chars().peekable();
+
+    loop {
+        let key: String = chars
+            .by_ref()
+            .skip_while(|c| c.is_whitespace() || *c == ',')
+            .take_while(|c| *c != '=')
+            .collect();
+        let key = key.trim();
+        if key.is_empty() || key.starts_with(']') {
+            break;
+        }
+
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some(c) => value.push(c),
+                        None => break,
+                    },
+                    c => value.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !matches!(c, ',' | ']') && !c.is_whitespace()) {
+                value.push(c);
+            }
+        }
+        attrs.insert(key.to_string(), value);
+    }
+
+    attrs
+}
+
+/// Strips whitespace and surrounding quotes from a node id.
+fn unquote(id: &str) -> String {
+    id.trim().trim_matches('"').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cmake_graphviz_and_ninja_graph() {
+        let dot = r#"digraph "test" {
+node [
+  fontsize = "12"
+];
+subgraph clusterLegend {
+  label = "Legend";
+  legendNode0 [ label = "Executable", shape = egg ];
+}
+    "node0" [ label = "app", shape = egg ];
+    "node1" [ label = "test_lib", shape = octagon ];
+    "node0" -> "node1" [ style = dotted ] // app -> test_lib
+    "node2" [ label = "m", shape = septagon ];
+    "node1" -> "node2"  // test_lib -> m
+}
+"#;
+        let mut graph = BuildGraph::from_graphviz(dot);
+        assert_eq!(graph.targets.len(), 3);
+        assert_eq!(graph.targets[1].kind, TargetKind::StaticLibrary);
+        assert_eq!(graph.targets[2].kind, TargetKind::External);
+        assert_eq!(
+            graph.dependencies,
+            vec![
+                Dependency {
+                    from: 0,
+                    to: 1,
+                    kind: DependencyKind::Private
+                },
+                Dependency {
+                    from: 1,
+                    to: 2,
+                    kind: DependencyKind::Public
+                },
+            ]
+        );
+        let app = graph.index_of("app").unwrap();
+        assert_eq!(graph.dependencies_of(app).next().unwrap().name, "test_lib");
+
+        let ninja = r#"digraph ninja {
+rankdir="LR"
+node [fontsize=10, shape=box, height=0.25]
+edge [fontsize=10]
+"0x1" [label="CMakeFiles/test_lib.dir/func.c.o"]
+"0x2" [label="/src/func.c"]
+"0x2" -> "0x1" [label=" C_COMPILER__test_lib_unscanned_Debug"]
+"0x3" [label="libtest_lib.a"]
+"0x1" -> "0x3" [label=" C_STATIC_LIBRARY_LINKER__test_lib_Debug"]
+"0x4" [label="app"]
+"0x5" [label="C_EXECUTABLE_LINKER__app_Debug", shape=ellipse]
+"0x5" -> "0x4"
+"0x3" -> "0x5" [arrowhead=none]
+}
+"#;
+        graph.annotate_from_ninja(ninja, Path::new("/build"));
+        assert_eq!(graph.targets[1].sources, vec![PathBuf::from("/src/func.c")]);
+        assert_eq!(
+            graph.targets[1].artifacts,
+            vec![PathBuf::from("/build/libtest_lib.a")]
+        );
+        assert_eq!(
+            graph.targets[0].artifacts,
+            vec![PathBuf::from("/build/app")]
+        );
+
+        let test_lib = graph.index_of("test_lib").unwrap();
+        assert_eq!(graph.transitive_dependents([test_lib]), vec![1, 0]);
+    }
+
+    #[test]
+    fn parse_ninja_deps_log() {
+        let output = "\
+CMakeFiles/test_lib.dir/func.c.o: #deps 2, deps mtime 1700000000 (VALID)
+    /src/func.c
+    /src/func.h
+
+lib/CMakeFiles/util.dir/src/util.c.o: #deps 1, deps mtime 1700000000 (STALE)
+    ../lib/src/util.c
+
+";
+        let deps = parse_ninja_deps(output);
+        assert_eq!(deps.len(), 2);
+        assert_eq!(
+            deps[0].1,
+            vec![PathBuf::from("/src/func.c"), PathBuf::from("/src/func.h")]
+        );
+        assert_eq!(target_of_object(&deps[0].0).as_deref(), Some("test_lib"));
+        assert_eq!(target_of_object(&deps[1].0).as_deref(), Some("util"));
+        assert_eq!(target_of_object(Path::new("func.c.o")), None);
+    }
+}
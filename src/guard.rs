@@ -0,0 +1,193 @@
+//! Scope guards for temporary build state.
+//!
+//! [`TempBuildDir`] owns a freshly created directory and [`ScopedEnv`] owns
+//! changes to the process environment. Both undo their effect when dropped,
+//! whether the scope is left normally, through `?`, or by a panic, so
+//! throwaway build trees and injected variables don't outlive their use.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, guard::TempBuildDir};
+//!
+//! let build = TempBuildDir::new("probe").unwrap();
+//! let report = Cmd::default()
+//!     .set_path("./my_project")
+//!     .set_binary_path(build.path().to_string_lossy())
+//!     .set_output_path(build.path().join("out").to_string_lossy())
+//!     .build();
+//! // the build tree is removed here, even if the build failed
+//! ```
+
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Distinguishes directories created by the same process.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A uniquely named directory that is removed with its contents on drop.
+#[derive(Debug)]
+pub struct TempBuildDir {
+    path: PathBuf,
+}
+
+impl TempBuildDir {
+    /// Creates a directory in the system temporary directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - A readable part of the directory name (e.g. `"probe"`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created.
+    pub fn new(label: &str) -> std::io::Result<Self> {
+        Self::new_in(std::env::temp_dir(), label)
+    }
+
+    /// Creates a directory inside `parent`, which is created if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created.
+    pub fn new_in<P>(parent: P, label: &str) -> std::io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = parent.as_ref().join(format!(
+            "cmakr-{}-{}-{}",
+            label,
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        // a leftover of a crashed process with a recycled pid is stale
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+        std::fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+
+    /// Returns the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Keeps the directory instead of removing it, returning its path.
+    pub fn keep(self) -> PathBuf {
+        let path = self.path.clone();
+        std::mem::forget(self);
+        path
+    }
+}
+
+impl Drop for TempBuildDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Changes to the process environment that are reverted on drop.
+///
+/// Every variable set or removed through the guard gets back the value it
+/// had before the guard first touched it.
+#[derive(Debug, Default)]
+pub struct ScopedEnv {
+    /// The original values, in the order the variables were first touched.
+    saved: Vec<(OsString, Option<OsString>)>,
+}
+
+impl ScopedEnv {
+    /// Creates a guard without any changes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the variable `name` to `value` until the guard is dropped.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`std::env::set_var`]: no other thread may read or write the
+    /// environment while the variable is set, or when the guard is dropped.
+    pub unsafe fn set<K, V>(&mut self, name: K, value: V)
+    where
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.save(name.as_ref());
+        // SAFETY: upheld by the caller
+        unsafe { std::env::set_var(name, value) };
+    }
+
+    /// Removes the variable `name` until the guard is dropped.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`std::env::remove_var`]: no other thread may read or write
+    /// the environment while the variable is removed, or when the guard is
+    /// dropped.
+    pub unsafe fn remove<K>(&mut self, name: K)
+    where
+        K: AsRef<OsStr>,
+    {
+        self.save(name.as_ref());
+        // SAFETY: upheld by the caller
+        unsafe { std::env::remove_var(name) };
+    }
+
+    /// Records the current value of `name` unless it was recorded before.
+    fn save(&mut self, name: &OsStr) {
+        if !self.saved.iter().any(|(saved, _)| saved == name) {
+            self.saved
+                .push((name.to_os_string(), std::env::var_os(name)));
+        }
+    }
+}
+
+impl Drop for ScopedEnv {
+    fn drop(&mut self) {
+        for (name, value) in self.saved.drain(..).rev() {
+            // SAFETY: the callers of `set` and `remove` guarantee exclusive
+            // access to the environment until the guard is dropped
+            unsafe {
+                match value {
+                    Some(value) => std::env::set_var(&name, value),
+                    None => std::env::remove_var(&name),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remove_dir_and_restore_env_on_drop() {
+        let dir = TempBuildDir::new("guard").unwrap();
+        let path = dir.path().to_path_buf();
+        std::fs::write(path.join("CMakeCache.txt"), "").unwrap();
+        drop(dir);
+        assert!(!path.exists());
+
+        let kept = TempBuildDir::new("guard").unwrap().keep();
+        assert!(kept.is_dir());
+        std::fs::remove_dir_all(&kept).unwrap();
+
+        let name = "CMAKR_GUARD_TEST";
+        {
+            let mut env = ScopedEnv::new();
+            // SAFETY: no other test uses this variable
+            unsafe {
+                env.set(name, "a");
+                env.set(name, "b");
+            }
+            assert_eq!(std::env::var(name).unwrap(), "b");
+        }
+        assert!(std::env::var_os(name).is_none());
+    }
+}
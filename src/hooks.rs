@@ -0,0 +1,78 @@
+//! Callbacks run before and after the configure and build phases.
+//!
+//! Hooks let a build script take part in a run without reimplementing it:
+//! patch a generated header after configure, copy resources into the build
+//! tree before the build, or record metrics of every phase.
+//!
+//! | Hook | Runs | [`previous`](HookContext::previous) |
+//! |------|------|-------------------------------------|
+//! | [`Cmd::on_before_configure`](crate::Cmd::on_before_configure) | before every `cmake` configure | `None` |
+//! | [`Cmd::on_after_configure`](crate::Cmd::on_after_configure) | after every configure, failed or not | the configure |
+//! | [`Cmd::on_before_build`](crate::Cmd::on_before_build) | before every `cmake --build` attempt | the phase before, if any |
+//! | [`Cmd::on_after_build`](crate::Cmd::on_after_build) | after every build attempt, failed or not | the build |
+//!
+//! A configure replayed by [`quiet_configure`](crate::Cmd::quiet_configure)
+//! and a build retried with fewer jobs run their hooks again. A hook that
+//! returns an error stops the run with [`Error::Io`](crate::Error::Io).
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::Cmd;
+//!
+//! let report = Cmd::default()
+//!     .set_path("./native")
+//!     .on_after_configure(|context| {
+//!         let config = context.binary_dir.join("config.h");
+//!         let patched = std::fs::read_to_string(&config)?.replace("#define HAVE_FOO 1", "");
+//!         std::fs::write(config, patched)
+//!     })
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{io, path::Path, sync::Arc};
+
+use crate::report::{Phase, PhaseTiming};
+
+/// What a hook is told about the phase it runs around.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct HookContext<'a> {
+    /// The phase the hook runs before or after, [`Phase::Configure`] or
+    /// [`Phase::Build`].
+    pub phase: Phase,
+    /// The CMake source directory.
+    pub source_dir: &'a Path,
+    /// The build directory.
+    pub binary_dir: &'a Path,
+    /// The arguments of the phase's `cmake` command, as resolved by cmakr.
+    pub args: &'a [String],
+    /// The phase that ran last: the one the hook runs after, or for a hook
+    /// running before a phase, the one before it.
+    pub previous: Option<&'a PhaseTiming>,
+}
+
+/// A callback run before or after a phase.
+///
+/// An error it returns stops the run.
+pub(crate) type Hook = Arc<dyn Fn(&HookContext<'_>) -> io::Result<()> + Send + Sync>;
+
+/// The hooks of a [`Cmd`](crate::Cmd).
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    pub(crate) before_configure: Vec<Hook>,
+    pub(crate) after_configure: Vec<Hook>,
+    pub(crate) before_build: Vec<Hook>,
+    pub(crate) after_build: Vec<Hook>,
+}
+
+/// Runs `hooks` in order, stopping at the first error, which is prefixed
+/// with `moment` (e.g. `"before configure"`).
+pub(crate) fn run(hooks: &[Hook], moment: &str, context: &HookContext<'_>) -> io::Result<()> {
+    for hook in hooks {
+        hook(context)
+            .map_err(|e| io::Error::new(e.kind(), format!("{} hook failed: {}", moment, e)))?;
+    }
+    Ok(())
+}
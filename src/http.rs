@@ -0,0 +1,248 @@
+//! A minimal client for posting JSON to HTTP endpoints and downloading
+//! files.
+//!
+//! Used to deliver spans and notifications, and to download the sources of
+//! fetched projects, without pulling in an HTTP and TLS stack. Plain
+//! `http://` URLs are posted directly over a TCP connection; `https://` URLs
+//! and downloads are handed to the `curl` executable, which is available on
+//! every common CI image and developer machine.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{IpAddr, TcpStream, ToSocketAddrs},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+/// How long connecting to and talking with an endpoint may take.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Posts the JSON `body` to `url` with the extra `headers`.
+///
+/// # Errors
+///
+/// Returns an error if a header name is not a valid HTTP token or a header
+/// value contains a line break, if the URL points at another machine in
+/// [offline mode](crate::set_offline), if the URL is
+/// neither `http://` nor `https://`, if the endpoint cannot be reached, or if
+/// it answers with a non-2xx status.
+pub(crate) fn post_json(url: &str, headers: &[(String, String)], body: &str) -> io::Result<()> {
+    check_headers(headers)?;
+    if crate::offline::is_offline() && !is_local(url) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("not posting to {} in offline mode", url),
+        ));
+    }
+    if url.starts_with("https://") {
+        return post_with_curl(url, headers, body);
+    }
+    let rest = url.strip_prefix("http://").ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} is not an http:// or https:// URL", url),
+        )
+    })?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let address = if authority.ends_with(']') || !authority.contains(':') {
+        format!("{}:80", authority)
+    } else {
+        authority.to_string()
+    };
+    let address = address.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} has no address", authority),
+        )
+    })?;
+
+    let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        path,
+        authority,
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+    stream.write_all(body.as_bytes())?;
+
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status)?;
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "{} answered {}",
+            url,
+            status.trim()
+        ))),
+    }
+}
+
+/// Posts `body` with `curl`, which fails on a non-2xx status with `--fail`.
+///
+/// The headers and the body are passed in a config file on curl's stdin
+/// rather than as arguments, as headers carry credentials that would
+/// otherwise be visible in the process list.
+fn post_with_curl(url: &str, headers: &[(String, String)], body: &str) -> io::Result<()> {
+    let mut config = String::from("header = \"Content-Type: application/json\"\n");
+    for (name, value) in headers {
+        config.push_str(&format!(
+            "header = {}\n",
+            config_string(&format!("{}: {}", name, value))
+        ));
+    }
+    config.push_str(&format!("data-raw = {}\n", config_string(body)));
+
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--output"])
+        .arg(if cfg!(windows) { "NUL" } else { "/dev/null" })
+        .arg("--max-time")
+        .arg(TIMEOUT.as_secs().to_string())
+        .args(["--config", "-"])
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(config.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "posting to {} failed: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Quotes `value` as a string of a curl config file, in which a backslash
+/// escapes quotes, backslashes, and control characters.
+fn config_string(value: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Checks that `headers` can be sent as they are: names are HTTP tokens and
+/// values have no line breaks, which would inject headers of their own.
+fn check_headers(headers: &[(String, String)]) -> io::Result<()> {
+    let token = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    for (name, value) in headers {
+        if name.is_empty() || !name.chars().all(token) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} is not a valid HTTP header name", name),
+            ));
+        }
+        if value.contains(['\r', '\n', '\0']) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the value of the HTTP header {} contains a line break",
+                    name
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Downloads `url` to the file `path` with `curl`, following redirects.
+///
+/// # Errors
+///
+/// Returns an error if curl cannot be run or the download fails, including
+/// on a non-2xx status.
+#[cfg(feature = "fetch")]
+pub(crate) fn download(url: &str, path: &std::path::Path) -> io::Result<()> {
+    let output = Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--fail",
+            "--location",
+            "--output",
+        ])
+        .arg(path)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "downloading {} failed: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Returns whether `url` points at this machine, such as a local collector,
+/// which offline mode allows.
+fn is_local(url: &str) -> bool {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split('/').next().unwrap_or_default();
+    let authority = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = match authority.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next(),
+        None => authority.split(':').next(),
+    }
+    .unwrap_or_default();
+    host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_header_injection() {
+        let injected = [(
+            "Authorization".to_string(),
+            "Bearer x\r\nX-Injected: 1".to_string(),
+        )];
+        let error = post_json("http://127.0.0.1:9/", &injected, "{}").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        let named = [("X-Bad: 1\r\nX".to_string(), "v".to_string())];
+        assert!(check_headers(&named).is_err());
+        let valid = [("X-Api-Key".to_string(), "k=v; \"q\"".to_string())];
+        assert!(check_headers(&valid).is_ok());
+    }
+
+    #[test]
+    fn quote_curl_config_strings() {
+        assert_eq!(
+            config_string("{\"a\": \"b\\\\c\"}\n"),
+            r#""{\"a\": \"b\\\\c\"}\n""#
+        );
+    }
+}
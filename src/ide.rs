@@ -0,0 +1,294 @@
+//! Editor integration for the build tree.
+//!
+//! Language servers such as clangd look for `compile_commands.json` in the
+//! source tree, while CMake writes it into the build tree. With
+//! [`Cmd::ide_support`](crate::Cmd::ide_support), cmakr enables
+//! `CMAKE_EXPORT_COMPILE_COMMANDS`, links the database into the requested
+//! directory after every configure (a copy on Windows, where symlinks need
+//! extra privileges), and removes it again in [`Cmd::clean`](crate::Cmd::clean).
+//!
+//! [`Cmd::export_vscode`](crate::Cmd::export_vscode) mirrors the configuration
+//! into VS Code's CMake Tools instead: a `cmakr` kit in
+//! `.vscode/cmake-kits.json` and `cmake.*` entries in `.vscode/settings.json`,
+//! leaving other kits and settings in place.
+//!
+//! Generators other than Makefiles and Ninja (Visual Studio, Xcode) write no
+//! compilation database. For them,
+//! [`Cmd::export_compile_flags`](crate::Cmd::export_compile_flags) writes a
+//! `compile_flags.txt`, clangd's fallback that applies one set of flags to
+//! every file, built from the compile groups the CMake file API reports for
+//! the selected targets.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, ide::IdeIntegration};
+//!
+//! Cmd::default()
+//!     .set_path("./my_project")
+//!     .ide_support(IdeIntegration::Clangd {
+//!         link_to: "./my_project".into(),
+//!     })
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{Map, Value};
+
+use crate::{compile_commands::COMPILE_COMMANDS, error::Error, fileapi::CompileGroup};
+
+/// The name of the kit written to `cmake-kits.json`.
+const KIT_NAME: &str = "cmakr";
+
+/// An editor integration kept up to date by [`Cmd`](crate::Cmd).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdeIntegration {
+    /// Makes `compile_commands.json` available to clangd.
+    Clangd {
+        /// The directory the database is linked into, usually the source root.
+        link_to: PathBuf,
+    },
+}
+
+impl IdeIntegration {
+    /// Returns the path of the linked database.
+    pub fn link_path(&self) -> PathBuf {
+        match self {
+            IdeIntegration::Clangd { link_to } => link_to.join(COMPILE_COMMANDS),
+        }
+    }
+
+    /// Links the database of the build tree at `binary_dir` into place.
+    ///
+    /// Does nothing if the generator wrote no database (only the Makefile and
+    /// Ninja generators do).
+    pub(crate) fn refresh(&self, binary_dir: &Path) -> io::Result<()> {
+        let database = std::path::absolute(binary_dir.join(COMPILE_COMMANDS))?;
+        if !database.is_file() {
+            return Ok(());
+        }
+        let link = self.link_path();
+        if link.is_symlink() && std::fs::read_link(&link)? == database {
+            return Ok(());
+        }
+        if link.is_symlink() || link.is_file() {
+            std::fs::remove_file(&link)?;
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&database, &link)?;
+        #[cfg(not(unix))]
+        std::fs::copy(&database, &link)?;
+        Ok(())
+    }
+
+    /// Removes the linked database, unless it is a symlink into another
+    /// build tree than the one at `binary_dir`.
+    pub(crate) fn remove(&self, binary_dir: &Path) -> io::Result<()> {
+        let link = self.link_path();
+        if link.is_symlink() {
+            let database = std::path::absolute(binary_dir.join(COMPILE_COMMANDS))?;
+            if std::fs::read_link(&link)? == database {
+                std::fs::remove_file(&link)?;
+            }
+        } else if cfg!(not(unix)) && link.is_file() {
+            std::fs::remove_file(&link)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the `cmakr` kit and the `cmake.*` settings into the `.vscode`
+/// directory of `workspace`.
+///
+/// An existing `cmakr` kit and existing `cmake.*` settings of the same names
+/// are replaced; everything else in both files is kept.
+///
+/// # Errors
+///
+/// Returns an error if either file exists but is not a JSON array or object
+/// (VS Code also accepts comments, which are not supported here), or if a
+/// file cannot be written.
+pub(crate) fn write_vscode(
+    workspace: &Path,
+    settings: Map<String, Value>,
+    mut kit: Map<String, Value>,
+) -> Result<(), Error> {
+    let dir = workspace.join(".vscode");
+    std::fs::create_dir_all(&dir)?;
+
+    let kits_path = dir.join("cmake-kits.json");
+    let mut kits = match read_json(&kits_path)? {
+        Some(Value::Array(kits)) => kits,
+        Some(_) => return Err(not_json(&kits_path, "an array")),
+        None => Vec::new(),
+    };
+    kits.retain(|kit| kit.get("name").and_then(Value::as_str) != Some(KIT_NAME));
+    kit.insert("name".to_string(), Value::from(KIT_NAME));
+    kits.push(Value::Object(kit));
+    std::fs::write(&kits_path, serde_json::to_string_pretty(&kits)?)?;
+
+    let settings_path = dir.join("settings.json");
+    let mut existing = match read_json(&settings_path)? {
+        Some(Value::Object(existing)) => existing,
+        Some(_) => return Err(not_json(&settings_path, "an object")),
+        None => Map::new(),
+    };
+    existing.extend(settings);
+    std::fs::write(&settings_path, serde_json::to_string_pretty(&existing)?)?;
+    Ok(())
+}
+
+/// Returns the lines of a `compile_flags.txt` covering `groups`, one argument
+/// per line and without duplicates.
+///
+/// MSVC flags are kept as they are, with `--driver-mode=cl` in front so clangd
+/// reads them like `clang-cl` does. Compile command fragments are split at
+/// whitespace.
+pub(crate) fn compile_flags<'a, I>(groups: I, msvc: bool) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a CompileGroup>,
+{
+    let mut flags: Vec<String> = Vec::new();
+    let mut push = |flag: String| {
+        if !flags.contains(&flag) {
+            flags.push(flag);
+        }
+    };
+    if msvc {
+        push("--driver-mode=cl".to_string());
+    }
+    for group in groups {
+        for fragment in &group.fragments {
+            fragment
+                .split_whitespace()
+                .for_each(|flag| push(flag.to_string()));
+        }
+        for define in &group.defines {
+            push(format!("-D{}", define));
+        }
+        for (include, system) in &group.includes {
+            let flag = if *system { "-isystem" } else { "-I" };
+            push(format!("{}{}", flag, include.display()));
+        }
+    }
+    flags
+}
+
+/// Reads the JSON file at `path`, or returns `None` if it does not exist.
+fn read_json(path: &Path) -> Result<Option<Value>, Error> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|_| not_json(path, "plain JSON")),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns the error for a file at `path` that is not `expected`.
+fn not_json(path: &Path, expected: &str) -> Error {
+    Error::InvalidConfig(format!("{} is not {}", path.display(), expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_compile_commands() {
+        let temp = crate::guard::TempBuildDir::new("ide").unwrap();
+        let binary = temp.path().join("build");
+        std::fs::create_dir(&binary).unwrap();
+        let ide = IdeIntegration::Clangd {
+            link_to: temp.path().to_path_buf(),
+        };
+
+        // generators without a database leave nothing to link
+        ide.refresh(&binary).unwrap();
+        assert!(!ide.link_path().exists());
+
+        std::fs::write(binary.join(COMPILE_COMMANDS), "[]").unwrap();
+        ide.refresh(&binary).unwrap();
+        ide.refresh(&binary).unwrap();
+        assert_eq!(std::fs::read_to_string(ide.link_path()).unwrap(), "[]");
+
+        // a database of another tree is not ours to remove
+        let other = temp.path().join("other");
+        ide.remove(&other).unwrap();
+        assert_eq!(ide.link_path().exists(), cfg!(unix));
+
+        ide.remove(&binary).unwrap();
+        assert!(!ide.link_path().exists());
+    }
+
+    #[test]
+    fn collect_compile_flags() {
+        let group = |define: &str| CompileGroup {
+            language: "CXX".to_string(),
+            fragments: vec!["/W4 /EHsc".to_string()],
+            defines: vec![define.to_string()],
+            includes: vec![
+                (PathBuf::from("C:/src/include"), false),
+                (PathBuf::from("C:/deps/include"), true),
+            ],
+        };
+        let groups = [group("FOO=1"), group("BAR")];
+        assert_eq!(
+            compile_flags(&groups, true),
+            [
+                "--driver-mode=cl",
+                "/W4",
+                "/EHsc",
+                "-DFOO=1",
+                "-IC:/src/include",
+                "-isystemC:/deps/include",
+                "-DBAR"
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_vscode_settings() {
+        let temp = crate::guard::TempBuildDir::new("vscode").unwrap();
+        let dir = temp.path().join(".vscode");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(
+            dir.join("settings.json"),
+            r#"{"editor.tabSize": 4, "cmake.generator": "Unix Makefiles"}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("cmake-kits.json"),
+            r#"[{"name": "GCC"}, {"name": "cmakr", "toolchainFile": "/old.cmake"}]"#,
+        )
+        .unwrap();
+
+        let mut settings = Map::new();
+        settings.insert("cmake.generator".to_string(), Value::from("Ninja"));
+        let mut kit = Map::new();
+        kit.insert("toolchainFile".to_string(), Value::from("/new.cmake"));
+        write_vscode(temp.path(), settings.clone(), kit.clone()).unwrap();
+        write_vscode(temp.path(), settings.clone(), kit.clone()).unwrap();
+
+        let read = |name: &str| -> Value {
+            serde_json::from_str(&std::fs::read_to_string(dir.join(name)).unwrap()).unwrap()
+        };
+        assert_eq!(
+            read("settings.json"),
+            serde_json::json!({"editor.tabSize": 4, "cmake.generator": "Ninja"})
+        );
+        assert_eq!(
+            read("cmake-kits.json"),
+            serde_json::json!([{"name": "GCC"}, {"toolchainFile": "/new.cmake", "name": "cmakr"}])
+        );
+
+        std::fs::write(dir.join("settings.json"), "// comment\n{}").unwrap();
+        let error = write_vscode(temp.path(), settings, kit).unwrap_err();
+        assert!(matches!(error, Error::InvalidConfig(_)));
+    }
+}
@@ -0,0 +1,131 @@
+//! Test impact analysis.
+//!
+//! Maps the targets affected by a change (see
+//! [`Cmd::changed_targets`](crate::Cmd::changed_targets)) to the CTest tests
+//! that exercise them, as listed by `ctest --show-only=json-v1`. A test belongs
+//! to a target when its command runs one of the target's artifacts, or when it
+//! carries the target name in its `LABELS` property.
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::graph::TargetNode;
+
+/// The parts of `ctest --show-only=json-v1` output used for test selection.
+#[derive(Deserialize)]
+struct CTestInfo {
+    tests: Vec<CTestTest>,
+}
+
+/// A single test registered with `add_test`.
+#[derive(Deserialize)]
+pub(crate) struct CTestTest {
+    pub name: String,
+    #[serde(default)]
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub properties: Vec<CTestProperty>,
+}
+
+/// A test property such as `LABELS` or `WORKING_DIRECTORY`.
+#[derive(Deserialize)]
+pub(crate) struct CTestProperty {
+    pub name: String,
+    pub value: serde_json::Value,
+}
+
+impl CTestTest {
+    /// Returns the values of the test's `LABELS` property.
+    fn labels(&self) -> impl Iterator<Item = &str> {
+        self.properties
+            .iter()
+            .filter(|property| property.name == "LABELS")
+            .filter_map(|property| property.value.as_array())
+            .flatten()
+            .filter_map(serde_json::Value::as_str)
+    }
+
+    /// Returns whether the test exercises `target`.
+    fn exercises(&self, target: &TargetNode) -> bool {
+        let runs_artifact = self.command.first().is_some_and(|program| {
+            let program = Path::new(program);
+            let program = program.canonicalize().unwrap_or(program.to_path_buf());
+            target.artifacts.contains(&program)
+        });
+
+        runs_artifact || self.labels().any(|label| label == target.name)
+    }
+}
+
+/// Parses the JSON printed by `ctest --show-only=json-v1`.
+pub(crate) fn parse_tests(json: &str) -> serde_json::Result<Vec<CTestTest>> {
+    Ok(serde_json::from_str::<CTestInfo>(json)?.tests)
+}
+
+/// Returns the names of the tests that exercise any of `targets`, in test order.
+pub(crate) fn select_tests(tests: &[CTestTest], targets: &[&TargetNode]) -> Vec<String> {
+    tests
+        .iter()
+        .filter(|test| targets.iter().any(|target| test.exercises(target)))
+        .map(|test| test.name.clone())
+        .collect()
+}
+
+/// Builds a `ctest -R` regex that matches exactly the given test names.
+pub(crate) fn exact_match_regex(names: &[String]) -> String {
+    let escaped: Vec<String> = names
+        .iter()
+        .map(|name| {
+            name.chars()
+                .flat_map(|c| {
+                    let special = "\\.^$|?*+()[]{}".contains(c);
+                    special.then_some('\\').into_iter().chain([c])
+                })
+                .collect()
+        })
+        .collect();
+    format!("^({})$", escaped.join("|"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::graph::TargetKind;
+
+    #[test]
+    fn select_tests_of_affected_targets() {
+        let json = r#"{
+  "kind": "ctestInfo",
+  "version": { "major": 1, "minor": 0 },
+  "tests": [
+    { "name": "func.basic", "command": ["/build/func_test"], "properties": [] },
+    { "name": "app(smoke)", "command": ["/build/app", "--smoke"],
+      "properties": [{ "name": "LABELS", "value": ["test_lib"] }] },
+    { "name": "unrelated", "command": ["/build/other"] }
+  ]
+}"#;
+        let tests = parse_tests(json).unwrap();
+        let func_test = TargetNode {
+            name: "func_test".to_string(),
+            kind: TargetKind::Executable,
+            sources: Vec::new(),
+            artifacts: vec![PathBuf::from("/build/func_test")],
+        };
+        let test_lib = TargetNode {
+            name: "test_lib".to_string(),
+            kind: TargetKind::StaticLibrary,
+            sources: Vec::new(),
+            artifacts: vec![PathBuf::from("/build/libtest_lib.a")],
+        };
+
+        let selected = select_tests(&tests, &[&func_test, &test_lib]);
+        assert_eq!(selected, vec!["func.basic", "app(smoke)"]);
+        assert_eq!(
+            exact_match_regex(&selected),
+            "^(func\\.basic|app\\(smoke\\))$"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! `log` records, and with the `tracing` feature `tracing` spans, for the
+//! processes cmakr runs.
+//!
+//! Every configure, build, install, test, and package command is logged
+//! under the `cmakr` target at debug level when it starts, with its redacted
+//! command line and working directory, and when it exits, with its exit
+//! status and duration. Resolved configure presets are logged as well.
+//!
+//! With the `tracing` feature, each command runs inside a `cmake` span with
+//! the phase, command line, working directory, exit status, and duration as
+//! fields, and the records are `tracing` events. They still reach a `log`
+//! logger while no `tracing` subscriber is installed.
+
+use std::{io, process::Command, time::Instant};
+
+use crate::{
+    cmake::ResolvedPreset,
+    command::{PreparedCommand, Shell},
+    credentials::Redactor,
+    error::Error,
+    process::CapturedOutput,
+    report::{Phase, ProcessExit},
+};
+
+/// Logs a debug record under the `cmakr` target.
+macro_rules! debug {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::debug!(target: "cmakr", $($arg)*);
+        #[cfg(not(feature = "tracing"))]
+        log::debug!(target: "cmakr", $($arg)*);
+    }};
+}
+
+/// Runs `command` of `phase` with `run`, logging its start and exit.
+///
+/// The command line is redacted with `redactor` before it is logged.
+pub(crate) fn run<F>(
+    phase: Phase,
+    command: &mut Command,
+    redactor: &Redactor,
+    run: F,
+) -> io::Result<CapturedOutput>
+where
+    F: FnOnce(&mut Command) -> io::Result<CapturedOutput>,
+{
+    let mut prepared = PreparedCommand::from(&*command);
+    prepared.redact(redactor);
+    let line = prepared.to_shell(Shell::Posix);
+    let cwd = match &prepared.cwd {
+        Some(cwd) => cwd.display().to_string(),
+        None => ".".to_string(),
+    };
+
+    #[cfg(feature = "tracing")]
+    let span = tracing::debug_span!(
+        target: "cmakr",
+        "cmake",
+        phase = %phase,
+        command = %line,
+        cwd = %cwd,
+        exit = tracing::field::Empty,
+        duration_ms = tracing::field::Empty,
+    );
+    #[cfg(feature = "tracing")]
+    let _entered = span.enter();
+
+    debug!("{} started: {} (in {})", phase, line, cwd);
+    let started = Instant::now();
+    let result = run(command);
+    let duration = started.elapsed();
+    match &result {
+        Ok(output) => {
+            let exit = ProcessExit::from(output.status);
+            #[cfg(feature = "tracing")]
+            {
+                span.record("exit", tracing::field::display(exit));
+                span.record("duration_ms", duration.as_millis() as u64);
+            }
+            let reason = match (&output.killed_by, output.cancelled) {
+                (Some(trigger), _) => format!(", killed as it {}", trigger),
+                (None, true) => ", cancelled".to_string(),
+                (None, false) => String::new(),
+            };
+            debug!(
+                "{} exited with {} after {:.2}s{}",
+                phase,
+                exit,
+                duration.as_secs_f64(),
+                reason
+            );
+        }
+        Err(e) => debug!("{} could not be run: {}", phase, e),
+    }
+    result
+}
+
+/// Logs the outcome of resolving the configure preset `name`.
+pub(crate) fn preset_resolved(name: &str, result: &Result<ResolvedPreset, Error>) {
+    match result {
+        Ok(preset) => debug!(
+            "resolved preset {}: generator {}, binary dir {}, toolchain file {}, {} cache variables",
+            name,
+            preset.generator.as_deref().unwrap_or("unset"),
+            preset
+                .binary_dir
+                .as_ref()
+                .map_or("unset".into(), |dir| dir.display().to_string()),
+            preset
+                .toolchain_file
+                .as_ref()
+                .map_or("unset".into(), |file| file.display().to_string()),
+            preset.cache_variables.len()
+        ),
+        Err(e) => debug!("preset {} not resolved: {}", name, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials::Credentials;
+    use std::sync::{Arc, Mutex};
+
+    /// Collects the messages of the `cmakr` target.
+    struct Collector(Mutex<Vec<String>>);
+
+    impl log::Log for Collector {
+        fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+            metadata.target() == "cmakr"
+        }
+
+        fn log(&self, record: &log::Record<'_>) {
+            if self.enabled(record.metadata()) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static COLLECTOR: Collector = Collector(Mutex::new(Vec::new()));
+
+    #[cfg(unix)]
+    #[test]
+    fn log_command_runs() {
+        log::set_logger(&COLLECTOR).unwrap();
+        log::set_max_level(log::LevelFilter::Debug);
+
+        let redactor = Credentials::new().redactor(&["hunter2".to_string()]);
+        let mut command = Command::new("sh");
+        command.args(["-c", "exit 3", "hunter2"]).current_dir("/");
+        let quiet: crate::process::LineHandler = Arc::new(|_, _| {});
+        let output = run(Phase::Build, &mut command, &redactor, |command| {
+            crate::process::run(command, None, None, Some(&quiet), &redactor)
+        })
+        .unwrap();
+        assert_eq!(output.status.code(), Some(3));
+
+        // with the tracing feature, the span is logged as well
+        let messages = COLLECTOR.0.lock().unwrap();
+        let mut events = messages
+            .iter()
+            .filter(|message| message.starts_with("build "));
+        assert_eq!(
+            events.next().unwrap(),
+            "build started: sh -c 'exit 3' '***' (in /)"
+        );
+        assert!(
+            events
+                .next()
+                .unwrap()
+                .starts_with("build exited with exit code 3 after ")
+        );
+    }
+}
@@ -0,0 +1,278 @@
+//! Build descriptions for other build systems.
+//!
+//! [`Cmd::build_description`](crate::Cmd::build_description) turns the
+//! CMake file API codemodel of a configured tree into a
+//! [`BuildDescription`]: one [`TargetRule`] per library and executable, with
+//! the fields of Bazel's `cc_library` and `cc_binary` rules (sources,
+//! headers, compile options, defines, include directories, dependencies, and
+//! link options). It serializes to JSON for generators of Bazel or Buck
+//! rules, and [`BuildDescription::to_bazel`] renders a `BUILD` file to start
+//! a migration from.
+//!
+//! The rules describe the configured build type on the configured platform;
+//! generated sources and custom commands have no counterpart and are left
+//! out.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::Cmd;
+//!
+//! let cmd = Cmd::default().set_path("./native");
+//! cmd.clone().configure().unwrap();
+//! let description = cmd.build_description().unwrap();
+//! std::fs::write("native.json", serde_json::to_string_pretty(&description).unwrap()).unwrap();
+//! std::fs::write("BUILD.bazel", description.to_bazel()).unwrap();
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::fileapi::Target;
+
+/// File extensions of headers, listed as `hdrs` instead of `srcs`.
+const HEADER_EXTENSIONS: [&str; 7] = ["h", "hh", "hpp", "hxx", "h++", "inc", "ipp"];
+
+/// The targets of a configured tree, as build rules.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BuildDescription {
+    /// The absolute source directory that relative paths start from.
+    pub source_dir: PathBuf,
+    /// The configured build type, if the generator is single-config.
+    pub build_type: Option<String>,
+    /// The rules, in the order of the codemodel.
+    pub targets: Vec<TargetRule>,
+}
+
+/// A library or executable target as a build rule.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TargetRule {
+    /// The target name.
+    pub name: String,
+    /// The rule: `"cc_library"` or `"cc_binary"`.
+    pub rule: String,
+    /// The CMake target type (e.g. `"STATIC_LIBRARY"`).
+    pub cmake_type: String,
+    /// Sources other than headers. Paths below the source directory are
+    /// relative to it.
+    pub srcs: Vec<PathBuf>,
+    /// Headers listed as sources of the target.
+    pub hdrs: Vec<PathBuf>,
+    /// Compile options other than defines and include directories.
+    pub copts: Vec<String>,
+    /// Preprocessor definitions (`NAME` or `NAME=value`).
+    pub defines: Vec<String>,
+    /// Include directories.
+    pub includes: Vec<PathBuf>,
+    /// The names of the project targets this target depends on.
+    pub deps: Vec<String>,
+    /// Libraries and flags on the link line that are not project targets.
+    pub linkopts: Vec<String>,
+}
+
+impl BuildDescription {
+    /// Describes `targets`, with paths below `source_dir` made relative to it.
+    /// Utility targets, which build nothing, are left out.
+    pub(crate) fn new(targets: &[Target], source_dir: &Path, build_type: Option<String>) -> Self {
+        let rules = targets
+            .iter()
+            .filter(|target| target.ty != "UTILITY")
+            .map(|target| TargetRule::new(target, targets, source_dir))
+            .collect();
+        BuildDescription {
+            source_dir: source_dir.to_path_buf(),
+            build_type,
+            targets: rules,
+        }
+    }
+
+    /// Renders the rules as a Bazel `BUILD` file, one `cc_library` or
+    /// `cc_binary` per target.
+    ///
+    /// Paths outside the source directory stay absolute and need to be
+    /// replaced with external repositories by hand.
+    pub fn to_bazel(&self) -> String {
+        let mut build = String::new();
+        for target in &self.targets {
+            build.push_str(&format!("{}(\n", target.rule));
+            build.push_str(&format!("    name = {},\n", starlark_string(&target.name)));
+            let paths = |paths: &[PathBuf]| -> Vec<String> {
+                paths
+                    .iter()
+                    .map(|path| path.to_string_lossy().replace('\\', "/"))
+                    .collect()
+            };
+            let mut deps: Vec<String> = target.deps.iter().map(|dep| format!(":{}", dep)).collect();
+            deps.sort();
+            let lists = [
+                ("srcs", paths(&target.srcs)),
+                ("hdrs", paths(&target.hdrs)),
+                ("copts", target.copts.clone()),
+                ("defines", target.defines.clone()),
+                ("includes", paths(&target.includes)),
+                ("deps", deps),
+                ("linkopts", target.linkopts.clone()),
+            ];
+            for (attribute, values) in lists {
+                // cc_binary has no hdrs
+                if values.is_empty() || (attribute == "hdrs" && target.rule == "cc_binary") {
+                    continue;
+                }
+                build.push_str(&format!("    {} = [\n", attribute));
+                for value in values {
+                    build.push_str(&format!("        {},\n", starlark_string(&value)));
+                }
+                build.push_str("    ],\n");
+            }
+            build.push_str(")\n\n");
+        }
+        build.truncate(build.trim_end().len());
+        build.push('\n');
+        build
+    }
+}
+
+impl TargetRule {
+    /// Describes `target`, with its dependencies among `targets`.
+    fn new(target: &Target, targets: &[Target], source_dir: &Path) -> Self {
+        let relative = |path: &Path| {
+            path.strip_prefix(source_dir)
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|_| path.to_path_buf())
+        };
+        let (hdrs, srcs): (Vec<PathBuf>, Vec<PathBuf>) = target
+            .sources
+            .iter()
+            .map(|source| relative(source))
+            .partition(|source| is_header(source));
+
+        let mut rule = TargetRule {
+            name: target.name.clone(),
+            rule: if target.ty == "EXECUTABLE" {
+                "cc_binary"
+            } else {
+                "cc_library"
+            }
+            .to_string(),
+            cmake_type: target.ty.clone(),
+            srcs,
+            hdrs,
+            ..TargetRule::default()
+        };
+        for group in &target.compile_groups {
+            let copts = group.fragments.iter().flat_map(|f| f.split_whitespace());
+            push_unique(&mut rule.copts, copts.map(str::to_string));
+            push_unique(&mut rule.defines, group.defines.iter().cloned());
+            push_unique(
+                &mut rule.includes,
+                group.includes.iter().map(|(dir, _)| relative(dir)),
+            );
+        }
+
+        let dependencies: Vec<&Target> = targets
+            .iter()
+            .filter(|other| target.dependencies.contains(&other.id) && other.ty != "UTILITY")
+            .collect();
+        rule.deps = dependencies.iter().map(|dep| dep.name.clone()).collect();
+        // the artifacts of dependencies are linked through deps
+        let linkopts = target.link_libraries.iter().filter(|library| {
+            !dependencies.iter().any(|dep| {
+                dep.artifacts
+                    .iter()
+                    .any(|artifact| Path::new(library.as_str()).file_name() == artifact.file_name())
+            })
+        });
+        push_unique(&mut rule.linkopts, linkopts.cloned());
+        rule
+    }
+}
+
+/// Adds the `values` not yet in `list` to it, in order.
+fn push_unique<T, I>(list: &mut Vec<T>, values: I)
+where
+    T: PartialEq,
+    I: IntoIterator<Item = T>,
+{
+    for value in values {
+        if !list.contains(&value) {
+            list.push(value);
+        }
+    }
+}
+
+/// Returns whether `path` is a header.
+fn is_header(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .is_some_and(|extension| HEADER_EXTENSIONS.contains(&extension.as_str()))
+}
+
+/// Quotes `value` as a Starlark string literal.
+fn starlark_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fileapi::CompileGroup;
+
+    #[test]
+    fn describe_targets_as_rules() {
+        let source_dir = Path::new("/src/app");
+        let targets = [
+            Target {
+                id: "core::@1".to_string(),
+                name: "core".to_string(),
+                ty: "STATIC_LIBRARY".to_string(),
+                artifacts: vec![PathBuf::from("/build/libcore.a")],
+                compile_groups: vec![CompileGroup {
+                    language: "CXX".to_string(),
+                    fragments: vec!["-O2 -std=c++17".to_string()],
+                    defines: vec!["CORE_STATIC".to_string()],
+                    includes: vec![(source_dir.join("include"), false)],
+                }],
+                sources: vec![
+                    source_dir.join("src/core.cpp"),
+                    source_dir.join("include/core.hpp"),
+                ],
+                ..Target::default()
+            },
+            Target {
+                id: "app::@1".to_string(),
+                name: "app".to_string(),
+                ty: "EXECUTABLE".to_string(),
+                dependencies: vec!["core::@1".to_string()],
+                link_libraries: vec!["libcore.a".to_string(), "-lpthread".to_string()],
+                sources: vec![source_dir.join("main.cpp")],
+                ..Target::default()
+            },
+            Target {
+                name: "docs".to_string(),
+                ty: "UTILITY".to_string(),
+                ..Target::default()
+            },
+        ];
+        let description = BuildDescription::new(&targets, source_dir, Some("Release".to_string()));
+        assert_eq!(description.targets.len(), 2);
+
+        let core = &description.targets[0];
+        assert_eq!(core.rule, "cc_library");
+        assert_eq!(core.srcs, [PathBuf::from("src/core.cpp")]);
+        assert_eq!(core.hdrs, [PathBuf::from("include/core.hpp")]);
+        assert_eq!(core.copts, ["-O2", "-std=c++17"]);
+        assert_eq!(core.includes, [PathBuf::from("include")]);
+
+        let app = &description.targets[1];
+        assert_eq!(app.rule, "cc_binary");
+        assert_eq!(app.deps, ["core"]);
+        assert_eq!(app.linkopts, ["-lpthread"]);
+
+        let build = description.to_bazel();
+        assert!(build.starts_with(
+            "cc_library(\n    name = \"core\",\n    srcs = [\n        \"src/core.cpp\",\n"
+        ));
+        assert!(build.contains("    deps = [\n        \":core\",\n    ],\n"));
+    }
+}
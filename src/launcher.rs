@@ -0,0 +1,296 @@
+//! Compiler launchers and their statistics.
+//!
+//! Projects configured with `CMAKE_<LANG>_COMPILER_LAUNCHER` set to `ccache`
+//! or `sccache` compile through that cache, which makes repeated builds of
+//! the same sources, e.g. in CI, much faster.
+//! [`Cmd::with_compiler_launcher`](crate::Cmd::with_compiler_launcher) sets
+//! the launcher of the C and C++ compilers; [`Launcher::Auto`] uses a cache
+//! only if one is installed.
+//!
+//! Both tools keep global hit and miss counters, so querying them before and
+//! after a build yields what the build itself got out of the cache, as long
+//! as no other build used the same cache at the same time; the report lists
+//! them in [`compiler_cache`](crate::report::BuildReport::compiler_cache).
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, launcher::Launcher};
+//!
+//! let report = Cmd::default()
+//!     .set_path("./my_project")
+//!     .with_compiler_launcher(Launcher::Auto)
+//!     .build()
+//!     .unwrap();
+//! if let Some(stats) = report.compiler_cache {
+//!     println!("{}: {} hits", stats.tool, stats.hits);
+//! }
+//! ```
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+use serde_json::Value;
+
+use crate::cmake;
+
+/// The compiler launcher of a build, see
+/// [`Cmd::with_compiler_launcher`](crate::Cmd::with_compiler_launcher).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Launcher {
+    /// `ccache` on `PATH`.
+    Ccache,
+    /// `sccache` on `PATH`.
+    Sccache,
+    /// Another program, by path or name on `PATH`.
+    Custom(PathBuf),
+    /// `sccache` or else `ccache`, or no launcher if neither is installed.
+    Auto,
+}
+
+impl Launcher {
+    /// Returns the path of the launcher program, or `None` in
+    /// [`Auto`](Launcher::Auto) mode when no compiler cache is installed.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`NotFound`](io::ErrorKind::NotFound) error if the program
+    /// of another mode cannot be found.
+    pub fn program(&self) -> io::Result<Option<PathBuf>> {
+        let program = match self {
+            Launcher::Ccache => Path::new("ccache"),
+            Launcher::Sccache => Path::new("sccache"),
+            Launcher::Custom(program) => program,
+            Launcher::Auto => {
+                return Ok(["sccache", "ccache"]
+                    .into_iter()
+                    .find_map(|program| which::which(program).ok()));
+            }
+        };
+        which::which(program).map(Some).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("compiler launcher {} not found", program.display()),
+            )
+        })
+    }
+}
+
+impl FromStr for Launcher {
+    type Err = std::convert::Infallible;
+
+    /// Parses `"ccache"`, `"sccache"`, and `"auto"`; any other value is the
+    /// path or name of a [custom](Launcher::Custom) launcher.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "ccache" => Launcher::Ccache,
+            "sccache" => Launcher::Sccache,
+            "auto" => Launcher::Auto,
+            program => Launcher::Custom(PathBuf::from(program)),
+        })
+    }
+}
+
+impl fmt::Display for Launcher {
+    /// Writes the name [`from_str`](Launcher::from_str) parses back.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Launcher::Ccache => f.write_str("ccache"),
+            Launcher::Sccache => f.write_str("sccache"),
+            Launcher::Auto => f.write_str("auto"),
+            Launcher::Custom(program) => write!(f, "{}", program.display()),
+        }
+    }
+}
+
+/// A compiler cache used as the compiler launcher of a build tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Cache {
+    Ccache(String),
+    Sccache(String),
+}
+
+/// The hit and miss counters of a compiler cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct Counters {
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+}
+
+/// Returns the compiler cache configured as the C or C++ compiler launcher of
+/// the build tree at `binary_dir`, if any.
+pub(crate) fn detect(binary_dir: &Path) -> Option<Cache> {
+    ["CMAKE_C_COMPILER_LAUNCHER", "CMAKE_CXX_COMPILER_LAUNCHER"]
+        .into_iter()
+        .filter_map(|name| cmake::read_cache_value(binary_dir, name))
+        .find_map(|value| from_launcher(&value))
+}
+
+/// Recognizes the compiler cache in a launcher value, a CMake list whose first
+/// element is the program.
+fn from_launcher(value: &str) -> Option<Cache> {
+    let program = value.split(';').next()?.trim();
+    let stem = Path::new(program).file_stem()?.to_str()?;
+    match stem {
+        "ccache" => Some(Cache::Ccache(program.to_string())),
+        "sccache" => Some(Cache::Sccache(program.to_string())),
+        _ => None,
+    }
+}
+
+impl Cache {
+    /// Returns the name of the tool (`"ccache"` or `"sccache"`).
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Cache::Ccache(_) => "ccache",
+            Cache::Sccache(_) => "sccache",
+        }
+    }
+
+    /// Queries the current counters of the cache, with `prepare` applied to
+    /// the query command. Returns `None` if the query fails.
+    pub(crate) fn counters<F>(&self, prepare: F) -> Option<Counters>
+    where
+        F: FnOnce(&mut Command),
+    {
+        let mut command = match self {
+            Cache::Ccache(program) => {
+                let mut command = Command::new(program);
+                command.arg("--print-stats");
+                command
+            }
+            Cache::Sccache(program) => {
+                let mut command = Command::new(program);
+                command.args(["--show-stats", "--stats-format=json"]);
+                command
+            }
+        };
+        prepare(&mut command);
+        let output = command.output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        self.parse(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Parses the output of the stats query.
+    fn parse(&self, stdout: &str) -> Option<Counters> {
+        match self {
+            // one `<name>\t<value>` line per counter
+            Cache::Ccache(_) => {
+                let mut counters = Counters::default();
+                let mut found = false;
+                for line in stdout.lines() {
+                    let Some((name, value)) = line.split_once('\t') else {
+                        continue;
+                    };
+                    let Ok(value) = value.trim().parse::<u64>() else {
+                        continue;
+                    };
+                    match name {
+                        "direct_cache_hit" | "preprocessed_cache_hit" => counters.hits += value,
+                        "cache_miss" => counters.misses += value,
+                        _ => continue,
+                    }
+                    found = true;
+                }
+                found.then_some(counters)
+            }
+            // counts are split by language
+            Cache::Sccache(_) => {
+                let stats: Value = serde_json::from_str(stdout).ok()?;
+                let total = |name: &str| -> Option<u64> {
+                    let counts = stats.get("stats")?.get(name)?.get("counts")?.as_object()?;
+                    Some(counts.values().filter_map(Value::as_u64).sum())
+                };
+                Some(Counters {
+                    hits: total("cache_hits")?,
+                    misses: total("cache_misses")?,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_launcher_stats() {
+        let ccache = from_launcher("/usr/bin/ccache;--some-option").unwrap();
+        assert_eq!(ccache, Cache::Ccache("/usr/bin/ccache".to_string()));
+        assert_eq!(from_launcher("clang-tidy"), None);
+        assert_eq!(
+            ccache.parse(
+                "stats_updated_timestamp\t1700000000\ndirect_cache_hit\t12\npreprocessed_cache_hit\t3\ncache_miss\t5\n"
+            ),
+            Some(Counters {
+                hits: 15,
+                misses: 5
+            })
+        );
+        assert_eq!(ccache.parse("ccache version 3.7\n"), None);
+
+        let sccache = from_launcher("C:/tools/sccache.exe").unwrap();
+        assert_eq!(sccache.name(), "sccache");
+        assert_eq!(
+            sccache.parse(
+                r#"{"stats":{"cache_hits":{"counts":{"C/C++":7,"Rust":1}},"cache_misses":{"counts":{"C/C++":2}}}}"#
+            ),
+            Some(Counters { hits: 8, misses: 2 })
+        );
+
+        assert_eq!("auto".parse(), Ok(Launcher::Auto));
+        let missing: Launcher = "/nonexistent/cmakr-launcher".parse().unwrap();
+        assert_eq!(
+            missing.program().unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert!(
+            crate::Cmd::default()
+                .with_compiler_launcher(missing)
+                .effective_settings()
+                .is_err()
+        );
+        assert!(Launcher::Auto.program().is_ok());
+
+        if !cfg!(unix) {
+            return;
+        }
+        let settings = crate::Cmd::default()
+            .with_compiler_launcher(Launcher::Custom(PathBuf::from("sh")))
+            .add_define("CMAKE_CXX_COMPILER_LAUNCHER", "")
+            .effective_settings()
+            .unwrap();
+        let launchers: Vec<&str> = settings
+            .defines
+            .iter()
+            .filter(|(name, _)| name.ends_with("_COMPILER_LAUNCHER"))
+            .map(|(_, value)| value.as_str())
+            .collect();
+        let sh = which::which("sh").unwrap();
+        assert_eq!(launchers, ["", sh.to_str().unwrap()]);
+    }
+
+    #[test]
+    fn detect_launcher_of_build_tree() {
+        let temp = crate::guard::TempBuildDir::new("launcher").unwrap();
+        let binary = temp.path();
+        assert_eq!(detect(binary), None);
+
+        std::fs::write(
+            binary.join("CMakeCache.txt"),
+            "CMAKE_C_COMPILER_LAUNCHER:STRING=\nCMAKE_CXX_COMPILER_LAUNCHER:STRING=/usr/bin/sccache\n",
+        )
+        .unwrap();
+        assert_eq!(
+            detect(binary),
+            Some(Cache::Sccache("/usr/bin/sccache".to_string()))
+        );
+    }
+}
@@ -36,7 +36,84 @@
 //! assert!(result.is_ok());
 //! ```
 
+pub mod artifact;
+mod bundle;
+pub mod cache;
+#[cfg(feature = "cargo")]
+pub mod cargo;
+pub mod ci;
 pub mod cmake;
 pub mod cmd;
+pub mod command;
+pub mod compile_commands;
+mod compiler_cache;
+pub mod config;
+pub mod configure_log;
+pub mod cpack;
+pub mod credentials;
+pub mod ctest;
+pub mod diagnostics;
+pub mod embedded;
+pub mod emscripten;
+mod encoding;
+pub mod error;
+#[cfg(feature = "fetch")]
+pub mod fetch;
+mod fetch_cache;
+pub mod fileapi;
+pub mod fingerprint;
+pub mod gc;
+pub mod graph;
+pub mod guard;
+pub mod hooks;
+mod http;
+pub mod ide;
+mod impact;
+mod instrument;
+pub mod interop;
+pub mod launcher;
+mod lock;
+pub mod machine;
+pub mod mobile;
+pub mod msvc;
+pub mod notices;
+pub mod notify;
+mod offline;
+pub mod oom;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod output;
+pub mod pgo;
+pub mod pipeline;
+mod pool;
+pub mod preset_version;
+mod process;
+#[cfg(feature = "pty")]
+pub mod pty;
+pub mod rebuild;
+pub mod report;
+mod repro;
+pub mod retry;
+pub mod sanitizer;
+mod sarif;
+mod sdk;
+pub mod seed;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod size;
+mod source_copy;
+pub mod state;
+pub mod summary;
+pub mod testing;
+pub mod toolchain;
+mod tools;
+pub mod trace;
+#[cfg(feature = "triage")]
+pub mod triage;
+pub mod watchdog;
+pub mod workspace;
 
-pub use cmd::Cmd;
+pub use cmd::{BuildHandle, BuildType, Cmd, LogLevel};
+pub use error::Error;
+pub use offline::{is_offline, set_offline};
+pub use pool::set_worker_threads;
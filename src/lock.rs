@@ -0,0 +1,132 @@
+//! Advisory file locks on directories shared between cmakr invocations.
+//!
+//! Locks are taken on a `.cmakr.lock` file inside the guarded directory and
+//! released when the returned [`DirLock`] is dropped. The operating system
+//! releases the lock of a process that exits without dropping it, so a lock
+//! file left behind by a crashed or killed build is never stale: it is
+//! simply locked again by the next caller. The holder writes its process id
+//! into the file, which is reported when waiting for it times out.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::{Read, Seek, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Name of the lock file created inside a locked directory.
+pub(crate) const LOCK_FILE_NAME: &str = ".cmakr.lock";
+
+/// How often a held lock is retried while waiting with a timeout.
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// An exclusive advisory lock held on a directory.
+///
+/// The lock is released automatically when this value is dropped.
+pub(crate) struct DirLock {
+    _file: File,
+}
+
+impl DirLock {
+    /// Blocks until an exclusive lock on `dir` is acquired.
+    ///
+    /// The directory is created if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the directory or lock file cannot be created,
+    /// or if locking fails.
+    pub(crate) fn acquire(dir: &Path) -> std::io::Result<Self> {
+        Self::acquire_within(dir, None)
+    }
+
+    /// Acquires an exclusive lock on `dir`, waiting at most `timeout` for
+    /// another holder to release it, or indefinitely if `timeout` is `None`.
+    ///
+    /// The directory is created if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`TimedOut`](std::io::ErrorKind::TimedOut)
+    /// naming the holder's process id if the lock is still held after
+    /// `timeout`, and an I/O error if the directory or lock file cannot be
+    /// created, or if locking fails.
+    pub(crate) fn acquire_within(dir: &Path, timeout: Option<Duration>) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+
+        let path = dir.join(LOCK_FILE_NAME);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)?;
+
+        match timeout {
+            None => file.lock()?,
+            Some(timeout) => {
+                let deadline = Instant::now() + timeout;
+                loop {
+                    match file.try_lock() {
+                        Ok(()) => break,
+                        Err(TryLockError::Error(e)) => return Err(e),
+                        Err(TryLockError::WouldBlock) if Instant::now() >= deadline => {
+                            let holder = holder(&mut file)
+                                .map_or(String::new(), |pid| format!(" by process {}", pid));
+                            return Err(std::io::Error::new(
+                                std::io::ErrorKind::TimedOut,
+                                format!(
+                                    "{} is locked{} (waited {:.1}s)",
+                                    dir.display(),
+                                    holder,
+                                    timeout.as_secs_f64()
+                                ),
+                            ));
+                        }
+                        Err(TryLockError::WouldBlock) => thread::sleep(RETRY_INTERVAL),
+                    }
+                }
+            }
+        }
+
+        // whatever a previous holder wrote is stale now
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+
+        Ok(Self { _file: file })
+    }
+}
+
+/// Returns the process id recorded by the current holder of a lock file.
+fn holder(file: &mut File) -> Option<u32> {
+    let mut content = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::TempBuildDir;
+
+    #[test]
+    fn time_out_on_held_lock() {
+        let temp = TempBuildDir::new("lock").unwrap();
+        let held = DirLock::acquire(temp.path()).unwrap();
+
+        let err = DirLock::acquire_within(temp.path(), Some(Duration::from_millis(150)))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert!(
+            err.to_string()
+                .contains(&format!("by process {}", std::process::id()))
+        );
+
+        drop(held);
+        DirLock::acquire_within(temp.path(), Some(Duration::ZERO)).unwrap();
+    }
+}
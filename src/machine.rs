@@ -0,0 +1,445 @@
+//! Machine-readable progress events.
+//!
+//! With [`Cmd::machine_output`](crate::Cmd::machine_output) enabled, cmakr
+//! prints structured events about the run to its own stdout, one per line, so
+//! supervisors written in any language can follow a build reliably. The
+//! output of cmake and the native build tool is not forwarded as-is in this
+//! mode but wrapped in `output` events, so stdout only ever carries events.
+//!
+//! In [`Format::JsonLines`], every event is a JSON object whose `event` field
+//! names its kind:
+//!
+//! | `event` | Fields |
+//! |---------|--------|
+//! | `phase_started` | `phase`, `target` |
+//! | `output` | `phase`, `target`, `stream` (`"stdout"`/`"stderr"`), `line` |
+//! | `progress` | `phase`, `target`, `done`, `total` |
+//! | `phase_finished` | `phase`, `target`, `duration_secs`, `success`, `exit_code`, `signal` |
+//! | `diagnostic` | `file`, `line`, `column`, `severity`, `code`, `message` |
+//! | `cancelled` | `phase` |
+//! | `finished` | `success`, `errors`, `warnings`, `artifacts`, `include_dirs`, `header_only` |
+//!
+//! `target` is `null` unless the build is split per target. `progress` is
+//! derived from Ninja's `[done/total]` and Makefiles' `[ NN%]` line prefixes;
+//! for the latter `total` is 100.
+//!
+//! In-process, the same events can be received as [`ProgressEvent`] values
+//! with [`Cmd::on_progress`](crate::Cmd::on_progress), e.g. to drive a
+//! progress bar:
+//!
+//! ```no_run
+//! use cmakr::{Cmd, machine::ProgressEvent};
+//!
+//! let (sender, receiver) = std::sync::mpsc::channel();
+//! let build = Cmd::default()
+//!     .set_path("./my_project")
+//!     .on_progress(move |event| {
+//!         let _ = sender.send(event.clone());
+//!     })
+//!     .spawn();
+//! for event in receiver {
+//!     if let ProgressEvent::Progress { done, total, .. } = event {
+//!         println!("{}/{}", done, total);
+//!     }
+//! }
+//! build.recv().unwrap().unwrap();
+//! ```
+
+use std::{io::Write, path::PathBuf, sync::Arc, time::Duration};
+
+use serde_json::{Value, json};
+
+use crate::{
+    diagnostics::{Diagnostic, Severity},
+    process::{LineHandler, Stream},
+    report::{BuildReport, Phase, PhaseTiming},
+};
+
+/// The encoding of machine-readable events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One JSON object per line.
+    JsonLines,
+}
+
+/// The stream a line of child output was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    /// The child's standard output.
+    Stdout,
+    /// The child's standard error.
+    Stderr,
+}
+
+impl OutputStream {
+    /// Returns the lowercase name of the stream.
+    fn name(self) -> &'static str {
+        match self {
+            OutputStream::Stdout => "stdout",
+            OutputStream::Stderr => "stderr",
+        }
+    }
+}
+
+impl From<Stream> for OutputStream {
+    fn from(stream: Stream) -> Self {
+        match stream {
+            Stream::Stdout => OutputStream::Stdout,
+            Stream::Stderr => OutputStream::Stderr,
+        }
+    }
+}
+
+/// A progress event of a run, as handed to
+/// [`Cmd::on_progress`](crate::Cmd::on_progress).
+///
+/// The variants correspond to the JSON events described in the
+/// [module documentation](self).
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    /// A phase is about to run.
+    PhaseStarted {
+        phase: Phase,
+        /// The target of a build split per target.
+        target: Option<String>,
+    },
+    /// A line of cmake or build tool output, without the trailing newline.
+    Output {
+        phase: Phase,
+        target: Option<String>,
+        stream: OutputStream,
+        line: String,
+    },
+    /// The build tool reported `done` of `total` steps (`total` is 100 for
+    /// the percentages of Makefiles).
+    Progress {
+        phase: Phase,
+        target: Option<String>,
+        done: u64,
+        total: u64,
+    },
+    /// A phase has finished.
+    PhaseFinished {
+        phase: Phase,
+        target: Option<String>,
+        duration: Duration,
+        success: bool,
+        exit_code: Option<i32>,
+        signal: Option<i32>,
+    },
+    /// A diagnostic parsed from the build output, reported before [`Finished`](ProgressEvent::Finished).
+    Diagnostic(Diagnostic),
+    /// A phase was cancelled.
+    Cancelled { phase: Phase },
+    /// The run has finished.
+    Finished {
+        success: bool,
+        errors: usize,
+        warnings: usize,
+        artifacts: Vec<PathBuf>,
+        include_dirs: Vec<PathBuf>,
+        header_only: bool,
+    },
+}
+
+/// Receives events in place of stdout, e.g. to forward them to a client.
+#[cfg(feature = "server")]
+pub(crate) type EventSink = Arc<dyn Fn(Value) + Send + Sync>;
+
+/// Receives typed events, see [`Cmd::on_progress`](crate::Cmd::on_progress).
+pub(crate) type ProgressCallback = Arc<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+/// A destination of events.
+#[derive(Clone)]
+enum Destination {
+    Stdout(Format),
+    #[cfg(feature = "server")]
+    Sink(EventSink),
+    Callback(ProgressCallback),
+}
+
+/// Where and how events are written.
+#[derive(Clone, Default)]
+pub(crate) struct Emitter {
+    destinations: Vec<Destination>,
+}
+
+impl Emitter {
+    /// Adds printing events to stdout in `format`.
+    pub(crate) fn stdout(mut self, format: Format) -> Self {
+        self.destinations.push(Destination::Stdout(format));
+        self
+    }
+
+    /// Returns whether events are printed to stdout.
+    pub(crate) fn prints(&self) -> bool {
+        self.destinations
+            .iter()
+            .any(|destination| matches!(destination, Destination::Stdout(_)))
+    }
+
+    /// Adds handing JSON events to `sink`.
+    #[cfg(feature = "server")]
+    pub(crate) fn sink(mut self, sink: EventSink) -> Self {
+        self.destinations.push(Destination::Sink(sink));
+        self
+    }
+
+    /// Adds handing typed events to `callback`.
+    pub(crate) fn callback(mut self, callback: ProgressCallback) -> Self {
+        self.destinations.push(Destination::Callback(callback));
+        self
+    }
+}
+
+/// Writes a single event to every destination.
+fn emit(emitter: &Emitter, event: ProgressEvent) {
+    for destination in &emitter.destinations {
+        match destination {
+            Destination::Stdout(Format::JsonLines) => {
+                let mut stdout = std::io::stdout().lock();
+                let _ = writeln!(stdout, "{}", to_json(&event));
+                let _ = stdout.flush();
+            }
+            #[cfg(feature = "server")]
+            Destination::Sink(sink) => sink(to_json(&event)),
+            Destination::Callback(callback) => callback(&event),
+        }
+    }
+}
+
+/// Reports that a phase is about to run.
+pub(crate) fn phase_started(emitter: &Emitter, phase: Phase, target: Option<&str>) {
+    emit(
+        emitter,
+        ProgressEvent::PhaseStarted {
+            phase,
+            target: target.map(str::to_string),
+        },
+    );
+}
+
+/// Reports that a phase has finished.
+pub(crate) fn phase_finished(emitter: &Emitter, timing: &PhaseTiming) {
+    emit(
+        emitter,
+        ProgressEvent::PhaseFinished {
+            phase: timing.phase,
+            target: timing.target.clone(),
+            duration: timing.duration,
+            success: timing.exit.success(),
+            exit_code: timing.exit.code,
+            signal: timing.exit.signal,
+        },
+    );
+}
+
+/// Reports that a phase was cancelled.
+pub(crate) fn cancelled(emitter: &Emitter, phase: Phase) {
+    emit(emitter, ProgressEvent::Cancelled { phase });
+}
+
+/// Returns a handler that wraps child output lines of a phase in events.
+pub(crate) fn output_handler(
+    emitter: &Emitter,
+    phase: Phase,
+    target: Option<String>,
+) -> LineHandler {
+    let emitter = emitter.clone();
+    Arc::new(move |stream: Stream, line: &str| {
+        emit(
+            &emitter,
+            ProgressEvent::Output {
+                phase,
+                target: target.clone(),
+                stream: stream.into(),
+                line: line.to_string(),
+            },
+        );
+        if let Some((done, total)) = parse_progress(line) {
+            emit(
+                &emitter,
+                ProgressEvent::Progress {
+                    phase,
+                    target: target.clone(),
+                    done,
+                    total,
+                },
+            );
+        }
+    })
+}
+
+/// Reports the diagnostics and the outcome of a finished run.
+pub(crate) fn finished(emitter: &Emitter, report: &BuildReport, success: bool) {
+    for diagnostic in &report.diagnostics {
+        emit(emitter, ProgressEvent::Diagnostic(diagnostic.clone()));
+    }
+    emit(
+        emitter,
+        ProgressEvent::Finished {
+            success,
+            errors: report.errors().count(),
+            warnings: report.warnings().count(),
+            artifacts: report.artifacts.clone(),
+            include_dirs: report.include_dirs.clone(),
+            header_only: report.header_only,
+        },
+    );
+}
+
+/// Encodes an event as a JSON object.
+fn to_json(event: &ProgressEvent) -> Value {
+    match event {
+        ProgressEvent::PhaseStarted { phase, target } => {
+            json!({ "event": "phase_started", "phase": phase, "target": target })
+        }
+        ProgressEvent::Output {
+            phase,
+            target,
+            stream,
+            line,
+        } => json!({
+            "event": "output",
+            "phase": phase,
+            "target": target,
+            "stream": stream.name(),
+            "line": line,
+        }),
+        ProgressEvent::Progress {
+            phase,
+            target,
+            done,
+            total,
+        } => json!({
+            "event": "progress",
+            "phase": phase,
+            "target": target,
+            "done": done,
+            "total": total,
+        }),
+        ProgressEvent::PhaseFinished {
+            phase,
+            target,
+            duration,
+            success,
+            exit_code,
+            signal,
+        } => json!({
+            "event": "phase_finished",
+            "phase": phase,
+            "target": target,
+            "duration_secs": duration.as_secs_f64(),
+            "success": success,
+            "exit_code": exit_code,
+            "signal": signal,
+        }),
+        ProgressEvent::Diagnostic(diagnostic) => diagnostic_event(diagnostic),
+        ProgressEvent::Cancelled { phase } => json!({ "event": "cancelled", "phase": phase }),
+        ProgressEvent::Finished {
+            success,
+            errors,
+            warnings,
+            artifacts,
+            include_dirs,
+            header_only,
+        } => json!({
+            "event": "finished",
+            "success": success,
+            "errors": errors,
+            "warnings": warnings,
+            "artifacts": artifacts,
+            "include_dirs": include_dirs,
+            "header_only": header_only,
+        }),
+    }
+}
+
+/// Builds the event for a single diagnostic.
+fn diagnostic_event(diagnostic: &Diagnostic) -> Value {
+    let severity = match diagnostic.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+    };
+    json!({
+        "event": "diagnostic",
+        "file": diagnostic.file,
+        "line": diagnostic.line,
+        "column": diagnostic.column,
+        "severity": severity,
+        "code": diagnostic.code,
+        "message": diagnostic.message,
+    })
+}
+
+/// Parses a build tool progress prefix: Ninja's `[3/10]` or Make's `[ 30%]`.
+fn parse_progress(line: &str) -> Option<(u64, u64)> {
+    let inner = line.strip_prefix('[')?.split_once(']')?.0.trim();
+
+    if let Some(percent) = inner.strip_suffix('%') {
+        return Some((percent.trim().parse().ok()?, 100));
+    }
+    let (done, total) = inner.split_once('/')?;
+    Some((done.parse().ok()?, total.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn progress_and_diagnostic_events() {
+        assert_eq!(
+            parse_progress("[3/10] Building C object func.c.o"),
+            Some((3, 10))
+        );
+        assert_eq!(
+            parse_progress("[ 50%] Linking C static library"),
+            Some((50, 100))
+        );
+        assert_eq!(parse_progress("[build] something"), None);
+
+        let event = diagnostic_event(&Diagnostic {
+            file: PathBuf::from("/src/func.c"),
+            line: Some(3),
+            column: None,
+            severity: Severity::Warning,
+            code: Some("-Wunused-variable".to_string()),
+            message: "unused variable 'y'".to_string(),
+        });
+        assert_eq!(
+            event.to_string(),
+            r#"{"code":"-Wunused-variable","column":null,"event":"diagnostic","file":"/src/func.c","line":3,"message":"unused variable 'y'","severity":"warning"}"#
+        );
+    }
+
+    #[test]
+    fn deliver_typed_events() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received = events.clone();
+        let emitter = Emitter::default().callback(Arc::new(move |event: &ProgressEvent| {
+            received.lock().unwrap().push(event.clone());
+        }));
+        let handler = output_handler(&emitter, Phase::Build, None);
+        handler(Stream::Stderr, "[3/10] Building C object func.c.o");
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            to_json(&events[0]).to_string(),
+            r#"{"event":"output","line":"[3/10] Building C object func.c.o","phase":"build","stream":"stderr","target":null}"#
+        );
+        assert_eq!(
+            events[1],
+            ProgressEvent::Progress {
+                phase: Phase::Build,
+                target: None,
+                done: 3,
+                total: 10
+            }
+        );
+    }
+}
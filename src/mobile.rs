@@ -0,0 +1,131 @@
+//! Cross-build profiles for Android and iOS.
+//!
+//! Building C dependencies for a mobile app takes the same handful of
+//! settings every time. [`Cmd::for_android`](crate::Cmd::for_android)
+//! configures with the toolchain file of an Android NDK, the ABI, and the
+//! minimum API level; [`Cmd::for_ios`](crate::Cmd::for_ios) sets the system
+//! name, the SDK, the architecture, and the deployment target of an iOS
+//! device or simulator build.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{
+//!     Cmd,
+//!     mobile::{AndroidAbi, IosPlatform},
+//! };
+//!
+//! Cmd::for_android("/opt/android-ndk-r26d", AndroidAbi::Arm64V8a, 24)
+//!     .set_path("./native")
+//!     .build()
+//!     .unwrap();
+//! Cmd::for_ios(IosPlatform::Simulator, "15.0")
+//!     .set_path("./native")
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::path::{Path, PathBuf};
+
+/// The path of the CMake toolchain file in an Android NDK.
+const NDK_TOOLCHAIN_FILE: &str = "build/cmake/android.toolchain.cmake";
+
+/// An Android ABI (`ANDROID_ABI`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AndroidAbi {
+    /// 64-bit ARM (`arm64-v8a`).
+    Arm64V8a,
+    /// 32-bit ARM (`armeabi-v7a`).
+    ArmeabiV7a,
+    /// 32-bit x86 (`x86`), for emulators.
+    X86,
+    /// 64-bit x86 (`x86_64`), for emulators.
+    X86_64,
+}
+
+impl AndroidAbi {
+    /// Returns the ABI name of the NDK (e.g. `"arm64-v8a"`).
+    pub fn name(self) -> &'static str {
+        match self {
+            AndroidAbi::Arm64V8a => "arm64-v8a",
+            AndroidAbi::ArmeabiV7a => "armeabi-v7a",
+            AndroidAbi::X86 => "x86",
+            AndroidAbi::X86_64 => "x86_64",
+        }
+    }
+}
+
+/// Where an iOS build runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IosPlatform {
+    /// iPhone and iPad devices (`iphoneos` SDK, `arm64`).
+    Device,
+    /// The simulator on the building Mac (`iphonesimulator` SDK, the
+    /// host's architecture).
+    Simulator,
+}
+
+impl IosPlatform {
+    /// Returns the name of the SDK (`CMAKE_OSX_SYSROOT`).
+    pub fn sdk(self) -> &'static str {
+        match self {
+            IosPlatform::Device => "iphoneos",
+            IosPlatform::Simulator => "iphonesimulator",
+        }
+    }
+
+    /// Returns the architecture built for (`CMAKE_OSX_ARCHITECTURES`).
+    pub fn architecture(self) -> &'static str {
+        match self {
+            IosPlatform::Simulator if std::env::consts::ARCH == "x86_64" => "x86_64",
+            _ => "arm64",
+        }
+    }
+}
+
+/// Returns the toolchain file of the NDK at `ndk_path`.
+pub(crate) fn ndk_toolchain_file(ndk_path: &Path) -> PathBuf {
+    ndk_path.join(NDK_TOOLCHAIN_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cmd;
+
+    #[test]
+    fn mobile_profiles() {
+        let settings = Cmd::for_android("/opt/ndk", AndroidAbi::ArmeabiV7a, 24)
+            .effective_settings()
+            .unwrap();
+        assert_eq!(
+            settings.toolchain_file.as_deref(),
+            Some(Path::new("/opt/ndk/build/cmake/android.toolchain.cmake"))
+        );
+        assert!(
+            settings
+                .defines
+                .contains(&("ANDROID_ABI".to_string(), "armeabi-v7a".to_string()))
+        );
+        assert!(
+            settings
+                .defines
+                .contains(&("ANDROID_PLATFORM".to_string(), "android-24".to_string()))
+        );
+
+        let settings = Cmd::for_ios(IosPlatform::Device, "15.0")
+            .effective_settings()
+            .unwrap();
+        let define = |name: &str| {
+            settings
+                .defines
+                .iter()
+                .find(|(defined, _)| defined == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(define("CMAKE_SYSTEM_NAME"), Some("iOS"));
+        assert_eq!(define("CMAKE_OSX_SYSROOT"), Some("iphoneos"));
+        assert_eq!(define("CMAKE_OSX_ARCHITECTURES"), Some("arm64"));
+        assert_eq!(define("CMAKE_OSX_DEPLOYMENT_TARGET"), Some("15.0"));
+    }
+}
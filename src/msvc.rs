@@ -0,0 +1,219 @@
+//! Visual Studio developer environments on Windows.
+//!
+//! The Ninja and Makefile generators expect `cl`, `link`, and the Windows
+//! SDK on `PATH`, as a "Developer Command Prompt" sets them up; from a plain
+//! terminal or CI runner, configure fails with a confusing compiler check.
+//! [`Cmd::with_msvc_env`](crate::Cmd::with_msvc_env) locates the newest
+//! Visual Studio with the C++ tools through `vswhere`, runs its
+//! `vcvarsall.bat` for the target [`Arch`], and passes the environment it
+//! sets up to cmake and the build tool. The Visual Studio generators find
+//! the tools on their own and only get the target platform (`-A`).
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, msvc::Arch};
+//!
+//! Cmd::default()
+//!     .set_path("./native")
+//!     .set_generator("Ninja")
+//!     .with_msvc_env(Arch::X64)
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The target architecture of a Visual Studio environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    X86,
+    X64,
+    Arm64,
+}
+
+impl Arch {
+    /// Returns the platform of the Visual Studio generators (`-A`), e.g.
+    /// `"Win32"`.
+    pub fn platform(self) -> &'static str {
+        match self {
+            Arch::X86 => "Win32",
+            Arch::X64 => "x64",
+            Arch::Arm64 => "ARM64",
+        }
+    }
+
+    /// Returns the name `vcvarsall.bat` uses for the architecture.
+    fn name(self) -> &'static str {
+        match self {
+            Arch::X86 => "x86",
+            Arch::X64 => "x64",
+            Arch::Arm64 => "arm64",
+        }
+    }
+
+    /// Returns the architecture of a Rust `target_arch`.
+    fn from_rust(arch: &str) -> Option<Self> {
+        match arch {
+            "x86" => Some(Arch::X86),
+            "x86_64" => Some(Arch::X64),
+            "aarch64" => Some(Arch::Arm64),
+            _ => None,
+        }
+    }
+
+    /// Returns the argument of `vcvarsall.bat` for building on `host` (a
+    /// Rust `target_arch`): the target, or `<host>_<target>` for the cross
+    /// tools.
+    fn vcvars_arg(self, host: &str) -> String {
+        match Arch::from_rust(host) {
+            Some(host) if host != self => format!("{}_{}", host.name(), self.name()),
+            _ => self.name().to_string(),
+        }
+    }
+
+    /// Returns the Visual Studio component with the C++ tools for the
+    /// architecture.
+    fn component(self) -> &'static str {
+        match self {
+            Arch::X86 | Arch::X64 => "Microsoft.VisualStudio.Component.VC.Tools.x86.x64",
+            Arch::Arm64 => "Microsoft.VisualStudio.Component.VC.Tools.ARM64",
+        }
+    }
+}
+
+/// Returns whether a developer environment is already set up, e.g. in a
+/// Developer Command Prompt.
+pub(crate) fn is_active() -> bool {
+    std::env::var_os("VSCMD_ARG_TGT_ARCH").is_some() || std::env::var_os("VCINSTALLDIR").is_some()
+}
+
+/// Returns the installation directory of the newest Visual Studio with the
+/// C++ tools for `arch`, as `vswhere` reports it.
+pub(crate) fn installation(arch: Arch) -> Option<PathBuf> {
+    let installer = std::env::var_os("ProgramFiles(x86)")
+        .map(|dir| PathBuf::from(dir).join("Microsoft Visual Studio/Installer/vswhere.exe"))
+        .filter(|vswhere| vswhere.is_file());
+    let vswhere = installer.or_else(|| which::which("vswhere").ok())?;
+    let output = Command::new(vswhere)
+        .args(["-latest", "-products", "*", "-requires", arch.component()])
+        .args(["-property", "installationPath", "-utf8"])
+        .output()
+        .ok()?;
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !path.is_empty()).then(|| PathBuf::from(path))
+}
+
+/// Runs `vcvarsall.bat` of the installation at `install_dir` for `arch` and
+/// returns the variables it sets or changes, sorted by name.
+///
+/// # Errors
+///
+/// Returns an error if `vcvarsall.bat` is missing or fails, or on other
+/// platforms than Windows.
+pub(crate) fn capture(install_dir: &Path, arch: Arch) -> io::Result<Vec<(String, Option<String>)>> {
+    let vcvarsall = install_dir.join("VC/Auxiliary/Build/vcvarsall.bat");
+    if !vcvarsall.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} not found", vcvarsall.display()),
+        ));
+    }
+    let output = run_vcvarsall(&vcvarsall, &arch.vcvars_arg(std::env::consts::ARCH))?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{} failed: {}",
+            vcvarsall.display(),
+            output.status
+        )));
+    }
+    let before = std::env::vars().collect::<Vec<_>>();
+    Ok(diff(&before, &String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Runs `vcvarsall.bat <arg>` in `cmd` and prints the environment after it.
+#[cfg(windows)]
+fn run_vcvarsall(vcvarsall: &Path, arg: &str) -> io::Result<std::process::Output> {
+    use std::os::windows::process::CommandExt;
+
+    // cmd strips the outer quotes of a /s /c command line
+    Command::new("cmd")
+        .args(["/d", "/s", "/c"])
+        .raw_arg(format!(
+            "\"\"{}\" {} >nul && set\"",
+            vcvarsall.display(),
+            arg
+        ))
+        .output()
+}
+
+#[cfg(not(windows))]
+fn run_vcvarsall(_vcvarsall: &Path, _arg: &str) -> io::Result<std::process::Output> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "vcvarsall.bat runs on Windows only",
+    ))
+}
+
+/// Compares the environment `before` with the `set` output `after`, which
+/// lists one `NAME=value` per line. Names are compared ignoring case, as
+/// Windows does.
+fn diff(before: &[(String, String)], after: &str) -> Vec<(String, Option<String>)> {
+    let before: BTreeMap<String, &str> = before
+        .iter()
+        .map(|(name, value)| (name.to_uppercase(), value.as_str()))
+        .collect();
+    let mut changes: Vec<(String, Option<String>)> = after
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .filter(|(name, value)| !name.is_empty() && before.get(&name.to_uppercase()) != Some(value))
+        .map(|(name, value)| (name.to_string(), Some(value.to_string())))
+        .collect();
+    changes.sort();
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn msvc_environment() {
+        assert_eq!(Arch::X64.vcvars_arg("x86_64"), "x64");
+        assert_eq!(Arch::Arm64.vcvars_arg("x86_64"), "x64_arm64");
+        assert_eq!(Arch::X86.vcvars_arg("aarch64"), "arm64_x86");
+        assert_eq!(Arch::X86.platform(), "Win32");
+
+        let before = [
+            ("Path".to_string(), "C:\\Windows".to_string()),
+            ("TEMP".to_string(), "C:\\Temp".to_string()),
+        ];
+        let after = "INCLUDE=C:\\VC\\include\nPATH=C:\\VC\\bin;C:\\Windows\nTEMP=C:\\Temp\n";
+        assert_eq!(
+            diff(&before, after),
+            vec![
+                ("INCLUDE".to_string(), Some("C:\\VC\\include".to_string())),
+                (
+                    "PATH".to_string(),
+                    Some("C:\\VC\\bin;C:\\Windows".to_string())
+                ),
+            ]
+        );
+
+        // elsewhere, the environment is left alone
+        if !cfg!(windows) {
+            let cmd = crate::Cmd::default()
+                .set_path("./test/")
+                .set_generator("Ninja");
+            assert_eq!(
+                cmd.clone().with_msvc_env(Arch::X64).dry_run().unwrap(),
+                cmd.dry_run().unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! License and notice files of third-party dependencies.
+//!
+//! Statically linked C and C++ libraries usually require their license text
+//! to ship with the binary. With
+//! [`Cmd::collect_notices`](crate::Cmd::collect_notices), a successful build
+//! copies the license files of the dependencies the project pulled into its
+//! build tree into one notices directory, ready to be bundled:
+//!
+//! - `FetchContent` sources, `<name>-src` in `_deps` (or the
+//!   [fetch cache](crate::Cmd::set_fetch_cache_dir)).
+//! - `ExternalProject` sources, `<name>-prefix/src/<name>` anywhere in the
+//!   build tree.
+//!
+//! License files are the files at the top of a source tree named `LICENSE`,
+//! `LICENCE`, `COPYING`, `NOTICE` or `COPYRIGHT` (with any extension or
+//! suffix, e.g. `LICENSE.txt` or `COPYING.LESSER`), and the files of a
+//! top-level `LICENSES` directory. They are copied to
+//! `<notices>/<dependency>/`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::Cmd;
+//!
+//! let report = Cmd::default()
+//!     .set_path("./my_project")
+//!     .set_output_path("./dist")
+//!     .collect_notices("./dist/notices")
+//!     .build()
+//!     .unwrap();
+//! for notice in &report.notices {
+//!     println!("{}: {}", notice.dependency, notice.path.display());
+//! }
+//! ```
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Name prefixes of license files, matched case-insensitively.
+const LICENSE_NAMES: [&str; 5] = ["LICENSE", "LICENCE", "COPYING", "NOTICE", "COPYRIGHT"];
+
+/// The directory of per-license files used by REUSE-compliant projects.
+const LICENSES_DIR: &str = "LICENSES";
+
+/// Build tree directories that hold no `ExternalProject` prefixes.
+const SKIPPED_DIRS: [&str; 4] = ["_deps", "CMakeFiles", ".cmake", crate::gc::STATE_DIR];
+
+/// A license file copied into the notices directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notice {
+    /// The name of the dependency, as given to `FetchContent_Declare` or
+    /// `ExternalProject_Add` (lowercased by `FetchContent`).
+    pub dependency: String,
+    /// The license file in the dependency's sources.
+    pub source: PathBuf,
+    /// The copy in the notices directory.
+    pub path: PathBuf,
+}
+
+/// Copies the license files of the dependencies in the build tree at
+/// `binary_dir` into `notices_dir`, and returns them sorted by dependency.
+///
+/// `fetch_dirs` are the directories `FetchContent` populates sources in:
+/// the `_deps` directory of the tree, and the fetch cache if one is set.
+pub(crate) fn collect(
+    binary_dir: &Path,
+    fetch_dirs: &[PathBuf],
+    notices_dir: &Path,
+) -> io::Result<Vec<Notice>> {
+    let mut sources = Vec::new();
+    for fetch_dir in fetch_dirs {
+        sources.extend(fetched_sources(fetch_dir)?);
+    }
+    external_sources(binary_dir, &mut sources)?;
+    sources.sort();
+    sources.dedup_by(|a, b| a.0 == b.0);
+
+    let mut notices = Vec::new();
+    for (dependency, source_dir) in sources {
+        for source in license_files(&source_dir)? {
+            let relative = source.strip_prefix(&source_dir).unwrap_or(&source);
+            let path = notices_dir.join(&dependency).join(relative);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::copy(&source, &path)?;
+            notices.push(Notice {
+                dependency: dependency.clone(),
+                source,
+                path,
+            });
+        }
+    }
+    Ok(notices)
+}
+
+/// Returns the `FetchContent` dependencies populated in `fetch_dir`, as
+/// their names and source directories.
+fn fetched_sources(fetch_dir: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    if !fetch_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut sources = Vec::new();
+    for entry in std::fs::read_dir(fetch_dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if let Some(dependency) = name.strip_suffix("-src")
+            && path.is_dir()
+        {
+            sources.push((dependency.to_string(), path.clone()));
+        }
+    }
+    Ok(sources)
+}
+
+/// Adds the `ExternalProject` dependencies with a default prefix under
+/// `dir` to `sources`.
+fn external_sources(dir: &Path, sources: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if SKIPPED_DIRS.contains(&name.as_str()) {
+            continue;
+        }
+        match name.strip_suffix("-prefix") {
+            Some(dependency) => {
+                let source_dir = path.join("src").join(dependency);
+                if source_dir.is_dir() {
+                    sources.push((dependency.to_string(), source_dir));
+                }
+            }
+            None => external_sources(&path, sources)?,
+        }
+    }
+    Ok(())
+}
+
+/// Returns the license files of the source tree at `source_dir`, sorted.
+fn license_files(source_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(source_dir)? {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_uppercase();
+        if path.is_file() && LICENSE_NAMES.iter().any(|prefix| name.starts_with(prefix)) {
+            files.push(path);
+        } else if path.is_dir() && name == LICENSES_DIR {
+            for entry in std::fs::read_dir(&path)? {
+                let path = entry?.path();
+                if path.is_file() {
+                    files.push(path);
+                }
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::TempBuildDir;
+
+    #[test]
+    fn collect_dependency_licenses() {
+        let temp = TempBuildDir::new("notices").unwrap();
+        let binary = temp.path().join("build");
+        let write = |path: PathBuf| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, path.file_name().unwrap().as_encoded_bytes()).unwrap();
+        };
+        write(binary.join("_deps/fmt-src/LICENSE.rst"));
+        write(binary.join("_deps/fmt-src/README.md"));
+        write(binary.join("_deps/fmt-build/LICENSE"));
+        write(binary.join("_deps/zlib-src/LICENSES/Zlib.txt"));
+        write(binary.join("third_party/openssl-prefix/src/openssl/COPYING"));
+        write(binary.join("third_party/openssl-prefix/src/openssl/NOTICE.md"));
+        write(binary.join("CMakeFiles/x-prefix/src/x/LICENSE"));
+
+        let notices_dir = temp.path().join("notices");
+        let notices = collect(&binary, &[binary.join("_deps")], &notices_dir).unwrap();
+        let copied: Vec<(&str, PathBuf)> = notices
+            .iter()
+            .map(|notice| {
+                let path = notice.path.strip_prefix(&notices_dir).unwrap();
+                (notice.dependency.as_str(), path.to_path_buf())
+            })
+            .collect();
+        assert_eq!(
+            copied,
+            [
+                ("fmt", PathBuf::from("fmt/LICENSE.rst")),
+                ("openssl", PathBuf::from("openssl/COPYING")),
+                ("openssl", PathBuf::from("openssl/NOTICE.md")),
+                ("zlib", PathBuf::from("zlib/LICENSES/Zlib.txt")),
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(&notices[0].path).unwrap(),
+            "LICENSE.rst"
+        );
+        assert_eq!(
+            notices[1].source,
+            binary.join("third_party/openssl-prefix/src/openssl/COPYING")
+        );
+    }
+}
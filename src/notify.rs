@@ -0,0 +1,418 @@
+//! Notifications about finished builds.
+//!
+//! A [`Cmd`](crate::Cmd) given one or more [`Notifier`]s with
+//! [`Cmd::notify`](crate::Cmd::notify) hands each of them a [`Notification`]
+//! once a run is over, successful or not. Two notifiers are built in:
+//!
+//! - [`WebhookNotifier`] posts the notification as JSON to a URL, e.g. a
+//!   Slack or Mattermost incoming webhook, which render its `text` field.
+//! - [`CommandNotifier`] runs a command with the JSON on its standard input.
+//!
+//! Closures taking a `&Notification` implement [`Notifier`] as well. The
+//! notification is sent after secrets have been redacted from the report and
+//! the error. Notifier failures are ignored, so an unreachable chat server
+//! never fails a build.
+//!
+//! # Payload
+//!
+//! ```json
+//! {
+//!   "text": "cmake build of /src/engine failed after 312.40s: build failed",
+//!   "success": false,
+//!   "source_dir": "/src/engine",
+//!   "preset": "release",
+//!   "duration_secs": 312.4,
+//!   "error": "build failed",
+//!   "report": {
+//!     "binary_dir": "/src/engine/build",
+//!     "phases": [{ "phase": "build", "target": null, "command": "cmake --build ...", "exit_code": 2, "duration_secs": 310.1 }],
+//!     "diagnostics": [{ "file": "/src/engine/a.c", "line": 3, "column": 5, "severity": "error", "code": null, "message": "..." }],
+//!     "errors": 1,
+//!     "warnings": 0,
+//!     "artifacts": [],
+//!     "...": "..."
+//!   }
+//! }
+//! ```
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, notify::WebhookNotifier};
+//!
+//! let url = std::env::var("SLACK_WEBHOOK_URL").unwrap();
+//! Cmd::default()
+//!     .set_path("./my_project")
+//!     .notify(WebhookNotifier::new(url))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use serde_json::{Value, json};
+
+use crate::{
+    Error,
+    command::Shell,
+    diagnostics::Severity,
+    http,
+    report::BuildReport,
+    summary::{format_duration, plural},
+};
+
+/// Receives a [`Notification`] when a build is over.
+///
+/// Closures taking a `&Notification` implement this trait.
+pub trait Notifier: Send + Sync {
+    /// Delivers the notification.
+    ///
+    /// # Errors
+    ///
+    /// An error is ignored by the build; it never changes the build result.
+    fn notify(&self, notification: &Notification<'_>) -> io::Result<()>;
+}
+
+impl<F> Notifier for F
+where
+    F: Fn(&Notification<'_>) -> io::Result<()> + Send + Sync,
+{
+    fn notify(&self, notification: &Notification<'_>) -> io::Result<()> {
+        self(notification)
+    }
+}
+
+/// The outcome of a finished build.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct Notification<'a> {
+    /// The CMake source directory, as configured.
+    pub source_dir: &'a Path,
+    /// The preset the build used, if any.
+    pub preset: Option<&'a str>,
+    /// Wall-clock duration of the whole run.
+    pub duration: Duration,
+    /// The report of the run: the full report of a successful run, or the
+    /// report collected up to a failed step. `None` if the run failed
+    /// before cmake was started.
+    pub report: Option<&'a BuildReport>,
+    /// Why the run failed, or `None` if it succeeded.
+    pub error: Option<&'a Error>,
+}
+
+impl Notification<'_> {
+    /// Returns whether the build succeeded.
+    pub fn success(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Returns a one-line summary, e.g.
+    /// `cmake build of /src/engine succeeded in 12.34s (2 artifacts, 1 warning)`.
+    pub fn text(&self) -> String {
+        let source_dir = self
+            .report
+            .map_or(self.source_dir, |report| &report.source_dir);
+        let mut text = format!("cmake build of {}", source_dir.display());
+        if let Some(preset) = self.preset {
+            text.push_str(&format!(" (preset {})", preset));
+        }
+        match self.error {
+            None => {
+                text.push_str(&format!(" succeeded in {}", format_duration(self.duration)));
+                if let Some(report) = self.report {
+                    text.push_str(&format!(
+                        " ({}, {})",
+                        plural(report.artifacts.len(), "artifact"),
+                        plural(report.warnings().count(), "warning")
+                    ));
+                }
+            }
+            Some(error) => {
+                let message = error.to_string();
+                text.push_str(&format!(
+                    " failed after {}: {}",
+                    format_duration(self.duration),
+                    message.lines().next().unwrap_or_default()
+                ));
+            }
+        }
+        text
+    }
+
+    /// Returns the notification as the JSON payload described in the
+    /// [module documentation](self).
+    pub fn to_json(&self) -> Value {
+        json!({
+            "text": self.text(),
+            "success": self.success(),
+            "source_dir": self.source_dir,
+            "preset": self.preset,
+            "duration_secs": self.duration.as_secs_f64(),
+            "error": self.error.map(ToString::to_string),
+            "report": self.report.map(report_json),
+        })
+    }
+}
+
+/// Posts notifications as JSON to an HTTP endpoint.
+///
+/// `https://` URLs are posted with the `curl` executable.
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+impl WebhookNotifier {
+    /// Creates a notifier posting to `url`.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The webhook URL (e.g. a Slack incoming webhook).
+    pub fn new<T>(url: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Self {
+            url: url.into(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Adds a header to every request, e.g. for authentication.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The header name (e.g. `"Authorization"`).
+    /// * `value` - The header value.
+    pub fn header<T, U>(mut self, name: T, value: U) -> Self
+    where
+        T: Into<String>,
+        U: Into<String>,
+    {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&self, notification: &Notification<'_>) -> io::Result<()> {
+        let body = notification.to_json().to_string();
+        http::post_json(&self.url, &self.headers, &body)
+    }
+}
+
+/// Runs a command for every notification.
+///
+/// The command gets the JSON payload on its standard input, and
+/// `CMAKR_BUILD_SUCCESS` (`1` or `0`) and `CMAKR_BUILD_SUMMARY` (the
+/// [one-line summary](Notification::text)) in its environment.
+#[derive(Debug, Clone)]
+pub struct CommandNotifier {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandNotifier {
+    /// Creates a notifier running `program`.
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - The program name or path (e.g. `"./notify.sh"`).
+    pub fn new<T>(program: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Adds an argument to the command.
+    pub fn arg<T>(mut self, arg: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.args.push(arg.into());
+        self
+    }
+}
+
+impl Notifier for CommandNotifier {
+    fn notify(&self, notification: &Notification<'_>) -> io::Result<()> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .env(
+                "CMAKR_BUILD_SUCCESS",
+                if notification.success() { "1" } else { "0" },
+            )
+            .env("CMAKR_BUILD_SUMMARY", notification.text())
+            .stdin(Stdio::piped())
+            .spawn()?;
+        let body = notification.to_json().to_string();
+        // a hook that doesn't read its input closes the pipe early
+        let _ = child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(body.as_bytes());
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "{} exited with {}",
+                self.program, status
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Builds the `report` object of the payload.
+fn report_json(report: &BuildReport) -> Value {
+    let phases: Vec<Value> = report
+        .phases
+        .iter()
+        .map(|timing| {
+            json!({
+                "phase": timing.phase,
+                "target": timing.target,
+                "command": timing.command.to_shell(Shell::Posix),
+                "exit_code": timing.exit.code,
+                "duration_secs": timing.duration.as_secs_f64(),
+            })
+        })
+        .collect();
+    let diagnostics: Vec<Value> = report
+        .diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let severity = match diagnostic.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Note => "note",
+            };
+            json!({
+                "file": diagnostic.file,
+                "line": diagnostic.line,
+                "column": diagnostic.column,
+                "severity": severity,
+                "code": diagnostic.code,
+                "message": diagnostic.message,
+            })
+        })
+        .collect();
+    let environment: serde_json::Map<String, Value> = report
+        .environment
+        .iter()
+        .map(|(name, value)| (name.clone(), Value::from(value.as_str())))
+        .collect();
+
+    json!({
+        "source_dir": report.source_dir,
+        "binary_dir": report.binary_dir,
+        "phases": phases,
+        "diagnostics": diagnostics,
+        "errors": report.errors().count(),
+        "warnings": report.warnings().count(),
+        "artifacts": report.artifacts,
+        "installed": report.installed,
+        "include_dirs": report.include_dirs,
+        "parallel_jobs": report.parallel_jobs,
+        "cache_reused": report.cache_reused,
+        "header_only": report.header_only,
+        "debug_postfix": report.debug_postfix,
+        "environment": environment,
+        "compiler_cache": report.compiler_cache.as_ref().map(|stats| json!({
+            "tool": stats.tool,
+            "hits": stats.hits,
+            "misses": stats.misses,
+        })),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read},
+        net::TcpListener,
+        path::PathBuf,
+    };
+
+    use super::*;
+
+    #[test]
+    fn post_notification_to_webhook() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hooks/build", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.strip_prefix("Content-Length: ") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+                head.push_str(&line);
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                .unwrap();
+            (head, String::from_utf8(body).unwrap())
+        });
+
+        let report = BuildReport {
+            source_dir: PathBuf::from("/src/engine"),
+            artifacts: vec![PathBuf::from("/out/libengine.a")],
+            ..Default::default()
+        };
+        let notification = Notification {
+            source_dir: Path::new("engine"),
+            preset: Some("release"),
+            duration: Duration::from_millis(1500),
+            report: Some(&report),
+            error: None,
+        };
+        WebhookNotifier::new(url)
+            .header("X-Token", "secret")
+            .notify(&notification)
+            .unwrap();
+
+        let (head, body) = server.join().unwrap();
+        assert!(head.starts_with("POST /hooks/build HTTP/1.1\r\n"));
+        assert!(head.contains("X-Token: secret\r\n"));
+        let payload: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            payload["text"],
+            "cmake build of /src/engine (preset release) succeeded in 1.50s (1 artifact, 0 warnings)"
+        );
+        assert_eq!(payload["success"], true);
+        assert_eq!(payload["report"]["artifacts"][0], "/out/libengine.a");
+
+        let error = Error::InvalidConfig("bad generator\nmore details".to_string());
+        let notification = Notification {
+            report: None,
+            error: Some(&error),
+            ..notification
+        };
+        assert_eq!(
+            notification.text(),
+            "cmake build of engine (preset release) failed after 1.50s: bad generator"
+        );
+        assert_eq!(notification.to_json()["report"], Value::Null);
+    }
+}
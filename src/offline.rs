@@ -0,0 +1,260 @@
+//! The crate-wide offline mode.
+//!
+//! Offline builds (`cargo build --offline`, or `CARGO_NET_OFFLINE=true` in
+//! sandboxed and air-gapped CI) must not reach the network. In offline mode,
+//! cmakr passes `-DFETCHCONTENT_FULLY_DISCONNECTED=ON` to configure, sends
+//! [notifications](crate::notify) and [traces](crate::Cmd::set_otlp_exporter)
+//! only to endpoints on this machine, and checks before configuring that every `FetchContent` dependency
+//! declared by the project is already populated. A build that would need to
+//! download something fails right away with [`Error::Offline`], listing what
+//! it would have fetched, instead of deep inside configure.
+//!
+//! # Example
+//!
+//! ```no_run
+//! cmakr::set_offline(true);
+//! assert!(cmakr::is_offline());
+//! ```
+//!
+//! [`Error::Offline`]: crate::Error::Offline
+
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// The offline mode follows `CARGO_NET_OFFLINE`.
+const DETECT: u8 = 0;
+const ONLINE: u8 = 1;
+const OFFLINE: u8 = 2;
+
+/// The mode set with [`set_offline`].
+static MODE: AtomicU8 = AtomicU8::new(DETECT);
+
+/// The `FetchContent_Declare` options that name a remote source.
+const REMOTE_OPTIONS: [&str; 4] = ["GIT_REPOSITORY", "URL", "SVN_REPOSITORY", "HG_REPOSITORY"];
+
+/// The `FetchContent_Declare` options that pin the revision of a source.
+const REVISION_OPTIONS: [&str; 5] = ["GIT_TAG", "URL_HASH", "URL_MD5", "SVN_REVISION", "HG_TAG"];
+
+/// Turns the offline mode on or off for every build of this process,
+/// overriding the `CARGO_NET_OFFLINE` environment variable.
+///
+/// # Arguments
+///
+/// * `offline` - Whether builds must not use the network.
+pub fn set_offline(offline: bool) {
+    MODE.store(if offline { OFFLINE } else { ONLINE }, Ordering::SeqCst);
+}
+
+/// Returns whether builds must not use the network: as set with
+/// [`set_offline`], or else whether `CARGO_NET_OFFLINE` is `true` (or `1`),
+/// as cargo's `--offline` and `net.offline` setting do.
+pub fn is_offline() -> bool {
+    match MODE.load(Ordering::SeqCst) {
+        ONLINE => false,
+        OFFLINE => true,
+        _ => std::env::var("CARGO_NET_OFFLINE")
+            .is_ok_and(|value| value.eq_ignore_ascii_case("true") || value == "1"),
+    }
+}
+
+/// A dependency declared with `FetchContent_Declare` that names a remote source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Declared {
+    /// The dependency name, as given to `FetchContent_Declare`.
+    pub(crate) name: String,
+    /// The repository or archive URL.
+    pub(crate) url: String,
+    /// The tag, branch, commit, or archive hash of the sources, if declared.
+    pub(crate) revision: Option<String>,
+    /// Whether `revision` names exactly one set of sources: an archive hash,
+    /// a full commit hash, or a tag, but not a branch that moves on.
+    pub(crate) pinned: bool,
+}
+
+impl Declared {
+    /// Returns whether the sources are populated under `base_dir`, the
+    /// `FETCHCONTENT_BASE_DIR` of the build.
+    pub(crate) fn is_populated(&self, base_dir: &Path) -> bool {
+        base_dir
+            .join(format!("{}-src", self.name.to_lowercase()))
+            .is_dir()
+    }
+}
+
+/// Collects the remote `FetchContent` dependencies declared in the
+/// `CMakeLists.txt` and `*.cmake` files under `source_dir`, skipping `.git`
+/// and `exclude` (the build directory).
+pub(crate) fn declared_fetches(source_dir: &Path, exclude: &Path) -> Vec<Declared> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    collect_cmake_files(source_dir, exclude, &mut files);
+    files.sort();
+    let mut declared: Vec<Declared> = Vec::new();
+    for file in files {
+        let Ok(content) = std::fs::read_to_string(&file) else {
+            continue;
+        };
+        for dependency in parse_declarations(&content) {
+            if !declared.iter().any(|d| d.name == dependency.name) {
+                declared.push(dependency);
+            }
+        }
+    }
+    declared
+}
+
+/// Adds the CMake files in the tree at `dir` to `files`.
+fn collect_cmake_files(dir: &Path, exclude: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name == ".git" || path == exclude {
+            continue;
+        }
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => collect_cmake_files(&path, exclude, files),
+            Ok(_) if name == "CMakeLists.txt" || name.ends_with(".cmake") => files.push(path),
+            _ => {}
+        }
+    }
+}
+
+/// Parses the `FetchContent_Declare` calls with a remote source in a CMake file.
+fn parse_declarations(content: &str) -> Vec<Declared> {
+    // comments could hide or fake a declaration
+    let content: String = content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let lower = content.to_ascii_lowercase();
+
+    let mut declared = Vec::new();
+    let mut rest = 0;
+    while let Some(start) = lower[rest..].find("fetchcontent_declare") {
+        let after = rest + start + "fetchcontent_declare".len();
+        rest = after;
+        let Some(open) = content[after..].trim_start().strip_prefix('(') else {
+            continue;
+        };
+        let Some(end) = open.find(')') else {
+            break;
+        };
+        let arguments: Vec<&str> = open[..end]
+            .split_whitespace()
+            .map(|argument| argument.trim_matches('"'))
+            .collect();
+        let Some((name, options)) = arguments.split_first() else {
+            continue;
+        };
+        let option = |names: &[&str]| {
+            options
+                .windows(2)
+                .find(|pair| names.contains(&pair[0]))
+                .map(|pair| (pair[0], pair[1]))
+        };
+        if let Some((_, url)) = option(&REMOTE_OPTIONS) {
+            let revision = option(&REVISION_OPTIONS);
+            declared.push(Declared {
+                name: name.to_string(),
+                url: url.to_string(),
+                revision: revision.map(|(_, revision)| revision.to_string()),
+                pinned: revision.is_some_and(|(option, revision)| pins(option, revision)),
+            });
+        }
+    }
+    declared
+}
+
+/// Returns whether `revision`, given as the `option` of a declaration,
+/// names exactly one set of sources. Archive hashes always do; a `GIT_TAG`
+/// does if it is a full commit hash, a `refs/tags/` ref, or a version tag
+/// like `v1.2.3`, as anything else may be a branch.
+fn pins(option: &str, revision: &str) -> bool {
+    let is_hash = |revision: &str| {
+        matches!(revision.len(), 40 | 64) && revision.chars().all(|c| c.is_ascii_hexdigit())
+    };
+    match option {
+        "URL_HASH" | "URL_MD5" => true,
+        "GIT_TAG" => {
+            let version = revision.strip_prefix(['v', 'V']).unwrap_or(revision);
+            is_hash(revision)
+                || revision.starts_with("refs/tags/")
+                || (version.starts_with(|c: char| c.is_ascii_digit()) && version.contains('.'))
+        }
+        "HG_TAG" => is_hash(revision),
+        "SVN_REVISION" => {
+            let number = revision.strip_prefix("-r").unwrap_or(revision);
+            !number.is_empty() && number.chars().all(|c| c.is_ascii_digit())
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_unpopulated_fetches() {
+        let temp = crate::guard::TempBuildDir::new("offline").unwrap();
+        let source = temp.path().join("src");
+        std::fs::create_dir_all(source.join("cmake")).unwrap();
+        std::fs::write(
+            source.join("CMakeLists.txt"),
+            "include(FetchContent)\n\
+             FetchContent_Declare(fmt\n  GIT_REPOSITORY https://github.com/fmtlib/fmt.git\n  GIT_TAG 10.2.1)\n\
+             # FetchContent_Declare(old URL https://example.com/old.zip)\n\
+             FetchContent_Declare(local SOURCE_DIR ${CMAKE_SOURCE_DIR}/local)\n",
+        )
+        .unwrap();
+        std::fs::write(
+            source.join("cmake/deps.cmake"),
+            "fetchcontent_declare(\n  \"Json\" URL https://example.com/json.tar.xz)\n",
+        )
+        .unwrap();
+
+        let declared = declared_fetches(&source, &temp.path().join("build"));
+        assert_eq!(
+            declared.iter().map(|d| d.name.as_str()).collect::<Vec<_>>(),
+            ["fmt", "Json"]
+        );
+        assert_eq!(declared[0].url, "https://github.com/fmtlib/fmt.git");
+        assert_eq!(declared[0].revision.as_deref(), Some("10.2.1"));
+        assert!(declared[0].pinned);
+        assert_eq!(declared[1].revision, None);
+        assert!(!declared[1].pinned);
+
+        let deps = temp.path().join("_deps");
+        std::fs::create_dir_all(deps.join("json-src")).unwrap();
+        assert!(!declared[0].is_populated(&deps));
+        assert!(declared[1].is_populated(&deps));
+    }
+
+    #[test]
+    fn pin_hashes_and_tags_only() {
+        let commit = "0123456789abcdef0123456789abcdef01234567";
+        assert!(pins("GIT_TAG", commit));
+        assert!(pins("GIT_TAG", "v1.14.0"));
+        assert!(pins("GIT_TAG", "10.2.1"));
+        assert!(pins("GIT_TAG", "refs/tags/release"));
+        assert!(!pins("GIT_TAG", "main"));
+        assert!(!pins("GIT_TAG", "release/1.x"));
+        assert!(!pins("GIT_TAG", &commit[..12]));
+        assert!(pins("URL_HASH", "SHA256=abc"));
+        assert!(pins("URL_MD5", "abc"));
+        assert!(pins("SVN_REVISION", "-r1234"));
+        assert!(!pins("HG_TAG", "default"));
+
+        let declared = parse_declarations(
+            "FetchContent_Declare(gtest GIT_REPOSITORY https://example.com/gtest.git GIT_TAG main)",
+        );
+        assert_eq!(declared[0].revision.as_deref(), Some("main"));
+        assert!(!declared[0].pinned);
+    }
+}
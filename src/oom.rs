@@ -0,0 +1,245 @@
+//! Out-of-memory aware parallelism backoff.
+//!
+//! Template-heavy C++ builds can exhaust system memory when too many compiler
+//! processes run at once; the kernel's OOM killer then terminates one of them
+//! and the build fails with a cryptic error. With
+//! [`Cmd::oom_backoff`](crate::Cmd::oom_backoff) enabled, the build step runs
+//! with an explicit `--parallel` job count, system memory is sampled while it
+//! runs, and a build that failed with signs of memory exhaustion is retried
+//! with half the jobs.
+//!
+//! Memory sampling is only available on Linux (via `/proc/meminfo`); elsewhere
+//! the decision is based on the exit status and output alone.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::report::ProcessExit;
+
+/// Output fragments printed by compiler drivers and toolchains when a job ran
+/// out of memory.
+///
+/// Generic phrases such as a bare `out of memory` or `std::bad_alloc` are
+/// left out, as they show up in ordinary compiler and test output.
+const OOM_MARKERS: [&str; 5] = [
+    // the GCC driver, after the kernel's OOM killer ended a compiler
+    "Killed signal terminated program",
+    // cc1 and cc1plus
+    ": out of memory allocating",
+    "virtual memory exhausted",
+    "LLVM ERROR: out of memory",
+    // MSVC, C1060
+    "compiler is out of heap space",
+];
+
+/// Fraction of total memory below which the system is considered exhausted.
+const LOW_MEMORY_FRACTION: f64 = 0.03;
+
+/// How often memory is sampled while a build is running.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Settings for retrying out-of-memory builds with fewer parallel jobs.
+///
+/// By default the first attempt uses one job per available CPU, each retry
+/// halves the job count, and at most 3 retries are made.
+#[derive(Debug, Clone)]
+pub struct OomBackoff {
+    initial_jobs: Option<usize>,
+    min_jobs: usize,
+    max_retries: usize,
+}
+
+impl Default for OomBackoff {
+    fn default() -> Self {
+        Self {
+            initial_jobs: None,
+            min_jobs: 1,
+            max_retries: 3,
+        }
+    }
+}
+
+impl OomBackoff {
+    /// Sets the job count of the first build attempt.
+    pub fn set_initial_jobs(mut self, jobs: usize) -> Self {
+        self.initial_jobs = Some(jobs.max(1));
+        self
+    }
+
+    /// Sets the job count below which no further retries are made.
+    pub fn set_min_jobs(mut self, jobs: usize) -> Self {
+        self.min_jobs = jobs.max(1);
+        self
+    }
+
+    /// Sets the maximum number of retries after the first attempt.
+    pub fn set_max_retries(mut self, retries: usize) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Returns the job count of the first attempt.
+    pub(crate) fn initial_jobs(&self) -> usize {
+        self.initial_jobs
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+            .max(self.min_jobs)
+    }
+
+    /// Returns the job count for the next attempt, or `None` if no retry is left.
+    pub(crate) fn next_jobs(&self, jobs: usize, retries: usize) -> Option<usize> {
+        if retries >= self.max_retries || jobs <= self.min_jobs {
+            return None;
+        }
+        Some((jobs / 2).max(self.min_jobs))
+    }
+}
+
+/// The lowest available memory seen while a build was running.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MemorySample {
+    pub min_available: u64,
+    pub total: u64,
+}
+
+/// Returns whether a failed build shows signs of running out of memory.
+pub(crate) fn looks_like_oom(
+    exit: &ProcessExit,
+    output: &str,
+    memory: Option<MemorySample>,
+) -> bool {
+    if exit.success() {
+        return false;
+    }
+
+    let killed = exit.signal == Some(9);
+    let marked = OOM_MARKERS.iter().any(|marker| output.contains(marker));
+    let exhausted = memory.is_some_and(|m| {
+        m.total > 0 && (m.min_available as f64) < (m.total as f64) * LOW_MEMORY_FRACTION
+    });
+
+    killed || marked || exhausted
+}
+
+/// Samples system memory in a background thread until stopped.
+pub(crate) struct MemoryMonitor {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Option<MemorySample>>,
+}
+
+impl MemoryMonitor {
+    /// Starts sampling.
+    pub(crate) fn start() -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = stop.clone();
+
+        let handle = thread::spawn(move || {
+            let mut lowest: Option<MemorySample> = None;
+            loop {
+                if let Some(sample) = read_meminfo()
+                    && lowest.is_none_or(|l| sample.min_available < l.min_available)
+                {
+                    lowest = Some(sample);
+                }
+                if flag.load(Ordering::SeqCst) {
+                    break lowest;
+                }
+                thread::sleep(SAMPLE_INTERVAL);
+            }
+        });
+
+        Self { stop, handle }
+    }
+
+    /// Stops sampling and returns the lowest available memory seen.
+    pub(crate) fn finish(self) -> Option<MemorySample> {
+        self.stop.store(true, Ordering::SeqCst);
+        self.handle.join().ok().flatten()
+    }
+}
+
+/// Reads the current available and total memory from `/proc/meminfo`.
+fn read_meminfo() -> Option<MemorySample> {
+    let content = std::fs::read_to_string("/proc/meminfo").ok()?;
+    parse_meminfo(&content)
+}
+
+/// Parses the `MemTotal` and `MemAvailable` entries (in kB) into bytes.
+fn parse_meminfo(content: &str) -> Option<MemorySample> {
+    let field = |name: &str| -> Option<u64> {
+        let line = content.lines().find(|line| line.starts_with(name))?;
+        let kb = line[name.len()..].trim().trim_end_matches("kB").trim();
+        kb.parse::<u64>().ok().map(|kb| kb * 1024)
+    };
+
+    Some(MemorySample {
+        min_available: field("MemAvailable:")?,
+        total: field("MemTotal:")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_oom_failures() {
+        let killed = ProcessExit {
+            code: None,
+            signal: Some(9),
+            core_dumped: false,
+        };
+        let failed = ProcessExit {
+            code: Some(1),
+            signal: None,
+            core_dumped: false,
+        };
+
+        assert!(looks_like_oom(&killed, "", None));
+        assert!(looks_like_oom(
+            &failed,
+            "c++: fatal error: Killed signal terminated program cc1plus",
+            None
+        ));
+        assert!(looks_like_oom(
+            &failed,
+            "cc1plus: out of memory allocating 65536 bytes after a total of 1048576 bytes",
+            None
+        ));
+        assert!(looks_like_oom(&failed, "LLVM ERROR: out of memory", None));
+        assert!(!looks_like_oom(&failed, "func.c:1:1: error: oops", None));
+        // tests and messages that merely mention running out of memory
+        assert!(!looks_like_oom(
+            &failed,
+            "[  FAILED  ] Pool.ReportsOutOfMemory: expected \"out of memory\"",
+            None
+        ));
+        assert!(!looks_like_oom(
+            &failed,
+            "terminate called after throwing an instance of 'std::bad_alloc'",
+            None
+        ));
+
+        let memory = parse_meminfo("MemTotal:       16000000 kB\nMemAvailable:     100000 kB\n");
+        assert!(looks_like_oom(&failed, "", memory));
+    }
+
+    #[test]
+    fn halve_jobs_until_limits() {
+        let backoff = OomBackoff::default()
+            .set_initial_jobs(8)
+            .set_min_jobs(2)
+            .set_max_retries(5);
+
+        assert_eq!(backoff.initial_jobs(), 8);
+        assert_eq!(backoff.next_jobs(8, 0), Some(4));
+        assert_eq!(backoff.next_jobs(4, 1), Some(2));
+        assert_eq!(backoff.next_jobs(2, 2), None);
+        assert_eq!(backoff.set_max_retries(1).next_jobs(8, 1), None);
+    }
+}
@@ -0,0 +1,404 @@
+//! OpenTelemetry trace export of build phases.
+//!
+//! With the `otel` feature, a [`Cmd`](crate::Cmd) given an [`OtlpExporter`]
+//! sends one trace per run to an OTLP/HTTP collector once the run is over:
+//! a `cmake` root span covering the whole run, with a child span for every
+//! configure, build, and install command. Given the W3C `traceparent` of the
+//! service that triggered the build, the spans join that service's trace.
+//!
+//! The root span carries the source and build directories and, when known,
+//! `cmake.preset`, `cmake.generator`, `cmake.target_count`, and
+//! `cmake.cache_hit` (whether configure reused an existing cache). Phase
+//! spans carry `cmake.phase`, `cmake.target`, the redacted
+//! `process.command_line`, and `process.exit_code`. Spans of failed phases,
+//! and the root span of a failed run, have an error status.
+//!
+//! Spans are sent as OTLP JSON, which every collector accepts on port 4318.
+//! `https://` endpoints are posted with the `curl` executable. Export
+//! failures are ignored, so an unreachable collector never fails a build.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, otel::OtlpExporter};
+//!
+//! let exporter = OtlpExporter::new("http://localhost:4318")
+//!     .service_name("native-deps")
+//!     .parent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01");
+//! Cmd::default()
+//!     .set_path("./my_project")
+//!     .set_otlp_exporter(exporter)
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{
+    hash::{BuildHasher, RandomState},
+    io,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{Value, json};
+
+use crate::{cmake, command::Shell, fileapi, http, report::BuildReport};
+
+/// Span kind `SPAN_KIND_INTERNAL`.
+const SPAN_KIND_INTERNAL: u8 = 1;
+
+/// Status codes of the OTLP `Status` message.
+const STATUS_OK: u8 = 1;
+const STATUS_ERROR: u8 = 2;
+
+/// Sends the spans of build runs to an OTLP/HTTP collector.
+#[derive(Debug, Clone)]
+pub struct OtlpExporter {
+    /// The full URL spans are posted to.
+    url: String,
+    service_name: String,
+    headers: Vec<(String, String)>,
+    /// The trace and span ids of the remote parent span.
+    parent: Option<(u128, u64)>,
+}
+
+impl OtlpExporter {
+    /// Creates an exporter for the collector at `endpoint`.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - The base URL of the collector (e.g.
+    ///   `"http://localhost:4318"`); spans are posted to `<endpoint>/v1/traces`.
+    pub fn new<T>(endpoint: T) -> Self
+    where
+        T: Into<String>,
+    {
+        let endpoint = endpoint.into();
+        Self {
+            url: format!("{}/v1/traces", endpoint.trim_end_matches('/')),
+            service_name: "cmakr".to_string(),
+            headers: Vec::new(),
+            parent: None,
+        }
+    }
+
+    /// Creates an exporter from the standard OpenTelemetry environment
+    /// variables, or returns `None` if no endpoint is set.
+    ///
+    /// Reads `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` (a full URL) or
+    /// `OTEL_EXPORTER_OTLP_ENDPOINT` (a base URL), `OTEL_SERVICE_NAME`, and
+    /// the `TRACEPARENT` of the calling process.
+    pub fn from_env() -> Option<Self> {
+        let mut exporter = match std::env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT") {
+            Ok(url) => Self {
+                url,
+                ..Self::new("")
+            },
+            Err(_) => Self::new(std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok()?),
+        };
+        if let Ok(name) = std::env::var("OTEL_SERVICE_NAME") {
+            exporter = exporter.service_name(name);
+        }
+        if let Ok(traceparent) = std::env::var("TRACEPARENT") {
+            exporter = exporter.parent(&traceparent);
+        }
+        Some(exporter)
+    }
+
+    /// Sets the `service.name` resource attribute. Defaults to `"cmakr"`.
+    pub fn service_name<T>(mut self, name: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.service_name = name.into();
+        self
+    }
+
+    /// Adds a header to every export request, e.g. for authentication.
+    pub fn header<K, V>(mut self, name: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Makes the root span of every run a child of a remote span.
+    ///
+    /// As the W3C Trace Context specification requires, an invalid header
+    /// is ignored and runs start a new trace.
+    ///
+    /// # Arguments
+    ///
+    /// * `traceparent` - A W3C `traceparent` header value
+    ///   (`00-<trace id>-<span id>-<flags>`).
+    pub fn parent(mut self, traceparent: &str) -> Self {
+        self.parent = parse_traceparent(traceparent);
+        self
+    }
+
+    /// Sends the spans of a finished run.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the collector cannot be reached or rejects the spans.
+    pub(crate) fn export(&self, run: &Run<'_>) -> io::Result<()> {
+        let body = self.trace_request(run).to_string();
+        http::post_json(&self.url, &self.headers, &body)
+    }
+
+    /// Builds the `ExportTraceServiceRequest` of a run.
+    fn trace_request(&self, run: &Run<'_>) -> Value {
+        let (trace_id, parent_id) = match self.parent {
+            Some((trace_id, span_id)) => (trace_id, Some(span_id)),
+            None => (
+                (u128::from(random_id()) << 64) | u128::from(random_id()),
+                None,
+            ),
+        };
+        let trace_id = format!("{:032x}", trace_id);
+        let root_id = random_id();
+
+        let mut attributes = Vec::new();
+        if let Some(preset) = run.preset {
+            attributes.push(attribute("cmake.preset", preset));
+        }
+        let mut spans = Vec::new();
+        if let Some(report) = run.report {
+            attributes.push(attribute(
+                "cmake.source_dir",
+                report.source_dir.to_string_lossy(),
+            ));
+            attributes.push(attribute(
+                "cmake.binary_dir",
+                report.binary_dir.to_string_lossy(),
+            ));
+            if let Some(generator) = cmake::read_cache_value(&report.binary_dir, "CMAKE_GENERATOR")
+            {
+                attributes.push(attribute("cmake.generator", generator));
+            }
+            if let Ok(Some(targets)) = fileapi::targets(&report.binary_dir) {
+                attributes.push(attribute("cmake.target_count", targets.len() as i64));
+            }
+            attributes.push(attribute("cmake.cache_hit", report.cache_reused));
+
+            for timing in &report.phases {
+                let mut attributes = vec![
+                    attribute("cmake.phase", timing.phase.to_string()),
+                    attribute(
+                        "process.command_line",
+                        timing.command.to_shell(Shell::Posix),
+                    ),
+                ];
+                let mut name = format!("cmake {}", timing.phase);
+                if let Some(target) = &timing.target {
+                    attributes.push(attribute("cmake.target", target.as_str()));
+                    name = format!("{} {}", name, target);
+                }
+                if let Some(code) = timing.exit.code {
+                    attributes.push(attribute("process.exit_code", i64::from(code)));
+                }
+                let status = match timing.exit.success() {
+                    true => json!({"code": STATUS_OK}),
+                    false => json!({"code": STATUS_ERROR, "message": timing.exit.to_string()}),
+                };
+                spans.push(json!({
+                    "traceId": trace_id,
+                    "spanId": format!("{:016x}", random_id()),
+                    "parentSpanId": format!("{:016x}", root_id),
+                    "name": name,
+                    "kind": SPAN_KIND_INTERNAL,
+                    "startTimeUnixNano": unix_nanos(timing.started_at),
+                    "endTimeUnixNano": unix_nanos(timing.started_at + timing.duration),
+                    "attributes": attributes,
+                    "status": status,
+                }));
+            }
+        }
+
+        let status = match run.error {
+            None => json!({"code": STATUS_OK}),
+            Some(message) => json!({"code": STATUS_ERROR, "message": message}),
+        };
+        let mut root = json!({
+            "traceId": trace_id,
+            "spanId": format!("{:016x}", root_id),
+            "name": "cmake",
+            "kind": SPAN_KIND_INTERNAL,
+            "startTimeUnixNano": unix_nanos(run.started_at),
+            "endTimeUnixNano": unix_nanos(SystemTime::now()),
+            "attributes": attributes,
+            "status": status,
+        });
+        if let Some(parent_id) = parent_id {
+            root["parentSpanId"] = json!(format!("{:016x}", parent_id));
+        }
+        spans.insert(0, root);
+
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [attribute("service.name", self.service_name.as_str())],
+                },
+                "scopeSpans": [{
+                    "scope": {"name": "cmakr", "version": env!("CARGO_PKG_VERSION")},
+                    "spans": spans,
+                }],
+            }],
+        })
+    }
+}
+
+/// A finished run to export.
+pub(crate) struct Run<'a> {
+    pub started_at: SystemTime,
+    pub preset: Option<&'a str>,
+    /// The report of the run, also for a [`BuildFailure`](crate::report::BuildFailure).
+    pub report: Option<&'a BuildReport>,
+    /// The error message of a failed run.
+    pub error: Option<&'a str>,
+}
+
+/// Builds a `KeyValue` attribute.
+fn attribute(key: &str, value: impl Into<Value>) -> Value {
+    let value = match value.into() {
+        Value::Bool(value) => json!({"boolValue": value}),
+        // 64-bit integers are strings in OTLP JSON
+        Value::Number(value) => json!({"intValue": value.to_string()}),
+        value => json!({"stringValue": value}),
+    };
+    json!({"key": key, "value": value})
+}
+
+/// Returns the nanoseconds since the Unix epoch, as a string.
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos())
+        .to_string()
+}
+
+/// Returns a random, non-zero id.
+fn random_id() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let id =
+        RandomState::new().hash_one((COUNTER.fetch_add(1, Ordering::Relaxed), SystemTime::now()));
+    id.max(1)
+}
+
+/// Parses a version 00 `traceparent` into its trace and parent span ids.
+fn parse_traceparent(traceparent: &str) -> Option<(u128, u64)> {
+    let mut parts = traceparent.trim().split('-');
+    let (version, trace_id, span_id, flags) =
+        (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    let hex = |part: &str, len: usize| {
+        part.len() == len
+            && part
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    };
+    if version != "00"
+        || parts.next().is_some()
+        || !hex(trace_id, 32)
+        || !hex(span_id, 16)
+        || !hex(flags, 2)
+    {
+        return None;
+    }
+    let trace_id = u128::from_str_radix(trace_id, 16)
+        .ok()
+        .filter(|&id| id != 0)?;
+    let span_id = u64::from_str_radix(span_id, 16)
+        .ok()
+        .filter(|&id| id != 0)?;
+    Some((trace_id, span_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{Phase, PhaseTiming, ProcessExit};
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        time::Duration,
+    };
+
+    #[test]
+    fn export_phase_spans() {
+        let started_at = SystemTime::now();
+        let report = BuildReport {
+            phases: vec![PhaseTiming {
+                phase: Phase::Build,
+                target: Some("png".to_string()),
+                command: Default::default(),
+                started_at,
+                duration: Duration::from_millis(20),
+                exit: ProcessExit {
+                    code: Some(2),
+                    ..Default::default()
+                },
+                stdout: String::new(),
+                stderr: String::new(),
+            }],
+            cache_reused: true,
+            ..Default::default()
+        };
+        let run = Run {
+            started_at,
+            preset: Some("ci"),
+            report: Some(&report),
+            error: Some("cmake build failed: exit code 2"),
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let exporter = OtlpExporter::new(format!("http://{}/", listener.local_addr().unwrap()))
+            .parent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+            .header("Authorization", "Bearer t");
+        let collector = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            while !String::from_utf8_lossy(&request).contains("\"resourceSpans\"")
+                || !request.ends_with(b"}")
+            {
+                let n = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        exporter.export(&run).unwrap();
+
+        let request = collector.join().unwrap();
+        assert!(request.starts_with("POST /v1/traces HTTP/1.1\r\n"));
+        assert!(request.contains("Authorization: Bearer t\r\n"));
+        let body: Value =
+            serde_json::from_str(&request[request.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+        let spans = &body["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        let (root, build) = (&spans[0], &spans[1]);
+        assert_eq!(root["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(root["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!(root["status"]["code"], STATUS_ERROR);
+        assert!(
+            root["attributes"]
+                .as_array()
+                .unwrap()
+                .contains(&attribute("cmake.cache_hit", true))
+        );
+        assert_eq!(build["name"], "cmake build png");
+        assert_eq!(build["parentSpanId"], root["spanId"]);
+        assert!(
+            build["attributes"]
+                .as_array()
+                .unwrap()
+                .contains(&attribute("process.exit_code", 2))
+        );
+
+        assert_eq!(
+            parse_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01"),
+            None
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Attributing the forwarded output of concurrent builds.
+//!
+//! By default, every line of CMake and native build tool output is written
+//! to the parent's stdout or stderr as it arrives. With several builds
+//! running at once (a [`Workspace`](crate::workspace::Workspace), or builds
+//! on separate threads) the lines of all of them interleave. A `Cmd` can
+//! instead [prefix](crate::Cmd::set_output_prefix) every line with a label,
+//! optionally [colored](crate::Cmd::set_output_color), or
+//! [buffer](crate::Cmd::buffer_output) its output and write it as one block
+//! once the run finishes. [`Workspace::set_output_mode`] applies either to
+//! every project, labelled with its name.
+//!
+//! Forwarding reads the output through pipes, so the tools don't see a
+//! terminal and drop colors and progress displays. Frontends that show the
+//! output in a terminal of their own can
+//! [connect it directly](crate::Cmd::set_output_streams) to their handles,
+//! e.g. a pty, instead.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, output::OutputMode, workspace::Workspace};
+//!
+//! Workspace::default()
+//!     .add_project("zlib", Cmd::default().set_path("./vendor/zlib"))
+//!     .add_project("png", Cmd::default().set_path("./vendor/libpng"))
+//!     .set_output_mode(OutputMode::Prefixed)
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! [`Workspace::set_output_mode`]: crate::workspace::Workspace::set_output_mode
+
+use std::{
+    fs::File,
+    io::{IsTerminal, Write},
+    process::Stdio,
+    sync::{Arc, Mutex},
+};
+
+use crate::process::{LineHandler, Stream};
+
+const RESET: &str = "\x1b[0m";
+
+/// Output lines held back by a buffered `Cmd`, with their streams.
+type Buffer = Arc<Mutex<Vec<(Stream, String)>>>;
+
+/// How a [`Workspace`](crate::workspace::Workspace) forwards the output of
+/// its projects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    /// Lines are forwarded as they arrive, unchanged.
+    #[default]
+    Interleaved,
+    /// Lines are forwarded as they arrive, prefixed with `[name] `, colored
+    /// per project if stderr is a terminal and `NO_COLOR` is not set.
+    Prefixed,
+    /// Lines are prefixed with `[name] ` and held until the project's build
+    /// finishes, then written together.
+    Buffered,
+}
+
+/// The color of an output prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputColor {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl OutputColor {
+    /// The colors handed out to projects, in order.
+    const CYCLE: [OutputColor; 6] = [
+        OutputColor::Cyan,
+        OutputColor::Magenta,
+        OutputColor::Yellow,
+        OutputColor::Green,
+        OutputColor::Blue,
+        OutputColor::Red,
+    ];
+
+    /// Returns the color of the `index`-th of several projects, repeating
+    /// after six.
+    pub fn nth(index: usize) -> OutputColor {
+        Self::CYCLE[index % Self::CYCLE.len()]
+    }
+
+    /// Returns the ANSI escape sequence of the color.
+    fn code(self) -> &'static str {
+        match self {
+            OutputColor::Red => "\x1b[31m",
+            OutputColor::Green => "\x1b[32m",
+            OutputColor::Yellow => "\x1b[33m",
+            OutputColor::Blue => "\x1b[34m",
+            OutputColor::Magenta => "\x1b[35m",
+            OutputColor::Cyan => "\x1b[36m",
+        }
+    }
+}
+
+/// Returns whether prefixes are colored by default: if stderr is a terminal
+/// and `NO_COLOR` is not set.
+pub(crate) fn color_enabled() -> bool {
+    std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        && std::io::stderr().is_terminal()
+}
+
+/// How a `Cmd` forwards output lines to the parent's streams.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct OutputStyle {
+    pub prefix: Option<String>,
+    pub color: Option<OutputColor>,
+    /// The lines held back until [`flush`](OutputStyle::flush), if buffered.
+    pub buffer: Option<Buffer>,
+}
+
+impl OutputStyle {
+    /// Returns whether lines are forwarded unchanged.
+    pub(crate) fn is_plain(&self) -> bool {
+        self.prefix.is_none() && self.buffer.is_none()
+    }
+
+    /// Returns `line` with the prefix in front.
+    fn format(&self, line: &str) -> String {
+        match (&self.prefix, self.color) {
+            (Some(prefix), Some(color)) => format!("{}{}{}{}", color.code(), prefix, RESET, line),
+            (Some(prefix), None) => format!("{}{}", prefix, line),
+            (None, _) => line.to_string(),
+        }
+    }
+
+    /// Returns the handler forwarding lines in this style.
+    pub(crate) fn handler(&self) -> LineHandler {
+        let style = self.clone();
+        Arc::new(move |stream, line| {
+            let line = style.format(line);
+            match &style.buffer {
+                Some(buffer) => {
+                    if let Ok(mut buffer) = buffer.lock() {
+                        buffer.push((stream, line));
+                    }
+                }
+                None => write_line(stream, &line),
+            }
+        })
+    }
+
+    /// Writes the buffered lines, if any, as one block.
+    pub(crate) fn flush(&self) {
+        let Some(buffer) = &self.buffer else {
+            return;
+        };
+        let lines = match buffer.lock() {
+            Ok(mut buffer) => std::mem::take(&mut *buffer),
+            Err(_) => return,
+        };
+        // holding both locks keeps the block together
+        let mut stdout = std::io::stdout().lock();
+        let mut stderr = std::io::stderr().lock();
+        for (stream, line) in lines {
+            let _ = match stream {
+                Stream::Stdout => writeln!(stdout, "{}", line),
+                Stream::Stderr => writeln!(stderr, "{}", line),
+            };
+        }
+        let _ = stdout.flush();
+        let _ = stderr.flush();
+    }
+}
+
+/// The handles a `Cmd` connects the stdout and stderr of its tools to.
+#[derive(Debug, Clone)]
+pub(crate) struct OutputStreams {
+    stdout: Arc<File>,
+    stderr: Arc<File>,
+}
+
+impl OutputStreams {
+    pub(crate) fn new(stdout: File, stderr: File) -> Self {
+        Self {
+            stdout: Arc::new(stdout),
+            stderr: Arc::new(stderr),
+        }
+    }
+
+    /// Returns duplicates of the handles for a single child process.
+    pub(crate) fn stdio(&self) -> std::io::Result<(Stdio, Stdio)> {
+        Ok((
+            self.stdout.try_clone()?.into(),
+            self.stderr.try_clone()?.into(),
+        ))
+    }
+}
+
+/// Writes a single line to the parent's `stream`.
+fn write_line(stream: Stream, line: &str) {
+    let _ = match stream {
+        Stream::Stdout => writeln!(std::io::stdout().lock(), "{}", line),
+        Stream::Stderr => writeln!(std::io::stderr().lock(), "{}", line),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_and_buffer_lines() {
+        let style = OutputStyle {
+            prefix: Some("[zlib] ".to_string()),
+            color: Some(OutputColor::nth(0)),
+            buffer: Some(Arc::default()),
+        };
+        assert_eq!(
+            style.format("ninja: no work to do."),
+            "\x1b[36m[zlib] \x1b[0mninja: no work to do."
+        );
+        assert_eq!(OutputColor::nth(6), OutputColor::Cyan);
+        assert!(OutputStyle::default().is_plain());
+
+        let handler = style.handler();
+        handler(Stream::Stdout, "[1/2] Building C object");
+        handler(Stream::Stderr, "warning: unused variable");
+        let buffer = style.buffer.clone().unwrap();
+        assert_eq!(buffer.lock().unwrap().len(), 2);
+        assert_eq!(buffer.lock().unwrap()[1].0, Stream::Stderr);
+        style.flush();
+        assert!(buffer.lock().unwrap().is_empty());
+    }
+}
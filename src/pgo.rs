@@ -0,0 +1,340 @@
+//! Profile-guided optimization of CMake projects.
+//!
+//! [`Cmd::pgo`](crate::Cmd::pgo) runs the generate, train, use cycle as a
+//! [pipeline](crate::pipeline): the project is built with instrumentation,
+//! a training workload is run to record a profile, and the project is
+//! configured again and rebuilt with the profile. The flags are those of
+//! the compiler of the build tree:
+//!
+//! | Compiler | Instrumented build | Optimized build |
+//! |----------|--------------------|-----------------|
+//! | GCC | `-fprofile-generate=<dir>` | `-fprofile-use=<dir> -fprofile-correction` |
+//! | Clang | `-fprofile-generate=<dir>` | `-fprofile-use=<dir>/default.profdata`, merged with `llvm-profdata` |
+//! | MSVC | `/GL`, linked with `/LTCG /GENPROFILE` | `/GL`, linked with `/LTCG /USEPROFILE` |
+//!
+//! The flags are appended to the user's own `CMAKE_<LANG>_FLAGS` and linker
+//! flags defines, if any.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, pgo::PgoConfig};
+//!
+//! let report = Cmd::default()
+//!     .set_path("./my_project")
+//!     .set_output_path("./bin")
+//!     .pgo(PgoConfig::new(["./bin/bench", "--iterations", "100"]))
+//!     .unwrap();
+//! println!("{}", report.summary());
+//! ```
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{cmake, error::Error, report::ProcessExit};
+
+/// The file Clang's raw profiles are merged into.
+const CLANG_PROFILE: &str = "default.profdata";
+
+/// The compiler flags variables the PGO flags are added to.
+const COMPILE_FLAGS: [&str; 2] = ["CMAKE_C_FLAGS", "CMAKE_CXX_FLAGS"];
+
+/// The linker flags variables the PGO flags of MSVC are added to.
+const LINK_FLAGS: [&str; 2] = ["CMAKE_EXE_LINKER_FLAGS", "CMAKE_SHARED_LINKER_FLAGS"];
+
+/// A compiler family with known PGO flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PgoCompiler {
+    Gcc,
+    /// Clang and AppleClang.
+    Clang,
+    Msvc,
+}
+
+impl PgoCompiler {
+    /// Returns the compiler family of a `CMAKE_<LANG>_COMPILER_ID`.
+    fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "GNU" => Some(PgoCompiler::Gcc),
+            "Clang" | "AppleClang" => Some(PgoCompiler::Clang),
+            "MSVC" => Some(PgoCompiler::Msvc),
+            _ => None,
+        }
+    }
+
+    /// Returns the compiler family of a configured tree, as reported by the
+    /// C++ compiler id, or else the C compiler id.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Unsupported`] if the compiler has no known PGO flags.
+    pub(crate) fn detect(binary_dir: &Path) -> Result<Self, Error> {
+        let id = cmake::read_cache_value(binary_dir, "CMAKE_CXX_COMPILER_ID")
+            .filter(|id| !id.is_empty())
+            .or_else(|| cmake::read_cache_value(binary_dir, "CMAKE_C_COMPILER_ID"))
+            .unwrap_or_default();
+        Self::from_id(&id).ok_or_else(|| {
+            Error::Unsupported(format!(
+                "no PGO flags are known for the compiler {:?}; set one with PgoConfig::set_compiler",
+                id
+            ))
+        })
+    }
+
+    /// Returns the flags of the instrumented build, as the variables they are
+    /// added to and the flags, writing profiles to `profile_dir`.
+    pub(crate) fn generate_flags(self, profile_dir: &Path) -> Vec<(&'static str, String)> {
+        match self {
+            PgoCompiler::Gcc | PgoCompiler::Clang => with_flags(
+                &COMPILE_FLAGS,
+                format!("-fprofile-generate={}", flag_path(profile_dir)),
+            ),
+            PgoCompiler::Msvc => {
+                let mut flags = with_flags(&COMPILE_FLAGS, "/GL".to_string());
+                flags.extend(with_flags(&LINK_FLAGS, "/LTCG /GENPROFILE".to_string()));
+                flags
+            }
+        }
+    }
+
+    /// Returns the flags of the optimized build, as the variables they are
+    /// added to and the flags, reading profiles from `profile_dir`.
+    pub(crate) fn use_flags(self, profile_dir: &Path) -> Vec<(&'static str, String)> {
+        match self {
+            PgoCompiler::Gcc => with_flags(
+                &COMPILE_FLAGS,
+                format!(
+                    "-fprofile-use={} -fprofile-correction -Wno-missing-profile",
+                    flag_path(profile_dir)
+                ),
+            ),
+            PgoCompiler::Clang => with_flags(
+                &COMPILE_FLAGS,
+                format!(
+                    "-fprofile-use={}",
+                    flag_path(&profile_dir.join(CLANG_PROFILE))
+                ),
+            ),
+            PgoCompiler::Msvc => {
+                let mut flags = with_flags(&COMPILE_FLAGS, "/GL".to_string());
+                flags.extend(with_flags(&LINK_FLAGS, "/LTCG /USEPROFILE".to_string()));
+                flags
+            }
+        }
+    }
+
+    /// Prepares the profiles recorded by training for the optimized build:
+    /// Clang's raw profiles are merged with `llvm-profdata`, found next to
+    /// the compiler of the tree at `binary_dir` or on `PATH`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ToolFailed`] if training recorded no profile, or
+    /// `llvm-profdata` fails.
+    pub(crate) fn merge_profiles(
+        self,
+        binary_dir: &Path,
+        profile_dir: &Path,
+        apply_environment: impl Fn(&mut Command),
+    ) -> Result<(), Error> {
+        if self != PgoCompiler::Clang {
+            return Ok(());
+        }
+        let mut raw: Vec<PathBuf> = std::fs::read_dir(profile_dir)?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension() == Some(OsStr::new("profraw")))
+            .collect();
+        if raw.is_empty() {
+            return Err(Error::ToolFailed(format!(
+                "the training workload recorded no profile in {}",
+                profile_dir.display()
+            )));
+        }
+        raw.sort();
+
+        let profdata = cmake::read_cache_value(binary_dir, "CMAKE_CXX_COMPILER")
+            .or_else(|| cmake::read_cache_value(binary_dir, "CMAKE_C_COMPILER"))
+            .and_then(|compiler| {
+                let sibling = Path::new(&compiler)
+                    .parent()?
+                    .join(format!("llvm-profdata{}", std::env::consts::EXE_SUFFIX));
+                sibling.is_file().then_some(sibling)
+            })
+            .unwrap_or_else(|| PathBuf::from("llvm-profdata"));
+        let mut command = Command::new(&profdata);
+        command
+            .arg("merge")
+            .arg(format!(
+                "-output={}",
+                profile_dir.join(CLANG_PROFILE).display()
+            ))
+            .args(&raw);
+        apply_environment(&mut command);
+        let output = command.output().map_err(|e| {
+            Error::ToolFailed(format!("{} could not be run: {}", profdata.display(), e))
+        })?;
+        if !output.status.success() {
+            return Err(Error::ToolFailed(format!(
+                "llvm-profdata failed: {}\n{}",
+                ProcessExit::from(output.status),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Returns `path` as part of a flags variable, which CMake splits at
+/// spaces: double-quoted if it contains one.
+fn flag_path(path: &Path) -> String {
+    let path = path.display().to_string();
+    if path.contains(' ') {
+        format!("\"{}\"", path)
+    } else {
+        path
+    }
+}
+
+/// Returns `flags` for every variable in `names`.
+fn with_flags(names: &[&'static str], flags: String) -> Vec<(&'static str, String)> {
+    names.iter().map(|name| (*name, flags.clone())).collect()
+}
+
+/// Settings of a profile-guided optimization run, see
+/// [`Cmd::pgo`](crate::Cmd::pgo).
+#[derive(Debug, Clone)]
+pub struct PgoConfig {
+    training_cmd: Vec<String>,
+    profile_dir: Option<PathBuf>,
+    compiler: Option<PgoCompiler>,
+}
+
+impl PgoConfig {
+    /// Creates the settings of a run that trains with `training_cmd`.
+    ///
+    /// # Arguments
+    ///
+    /// * `training_cmd` - The program of the training workload, typically a
+    ///   benchmark built by the project, followed by its arguments. It runs
+    ///   in the current directory, with the environment of the `Cmd`, and
+    ///   must exit successfully.
+    pub fn new<I, T>(training_cmd: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        Self {
+            training_cmd: training_cmd.into_iter().map(Into::into).collect(),
+            profile_dir: None,
+            compiler: None,
+        }
+    }
+
+    /// Sets the directory profiles are recorded in.
+    ///
+    /// Defaults to `.cmakr/pgo` in the build directory. Existing profiles in
+    /// it are removed before the instrumented build.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Path to the profile directory.
+    pub fn set_profile_dir<T>(mut self, dir: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.profile_dir = Some(PathBuf::from(dir.into()));
+        self
+    }
+
+    /// Sets the compiler family whose flags are used, instead of detecting
+    /// it from the configured tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `compiler` - The compiler family.
+    pub fn set_compiler(mut self, compiler: PgoCompiler) -> Self {
+        self.compiler = Some(compiler);
+        self
+    }
+
+    /// Returns the profile directory of the tree at `binary_dir`.
+    pub(crate) fn profile_dir(&self, binary_dir: &Path) -> std::io::Result<PathBuf> {
+        match &self.profile_dir {
+            Some(dir) => std::path::absolute(dir),
+            None => Ok(binary_dir.join(crate::gc::STATE_DIR).join("pgo")),
+        }
+    }
+
+    /// Returns the compiler family of the tree at `binary_dir`.
+    pub(crate) fn compiler(&self, binary_dir: &Path) -> Result<PgoCompiler, Error> {
+        match self.compiler {
+            Some(compiler) => Ok(compiler),
+            None => PgoCompiler::detect(binary_dir),
+        }
+    }
+
+    /// Returns the training workload command, without its environment.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if no training program is set.
+    pub(crate) fn training_command(&self) -> Result<Command, Error> {
+        let Some((program, args)) = self.training_cmd.split_first() else {
+            return Err(Error::InvalidConfig(
+                "PGO needs a training command".to_string(),
+            ));
+        };
+        let mut command = Command::new(program);
+        command.args(args);
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pgo_flags_by_compiler() {
+        let temp = crate::guard::TempBuildDir::new("pgo").unwrap();
+        std::fs::write(
+            temp.path().join("CMakeCache.txt"),
+            "CMAKE_CXX_COMPILER_ID:STRING=\nCMAKE_C_COMPILER_ID:STRING=AppleClang\n",
+        )
+        .unwrap();
+        assert_eq!(
+            PgoCompiler::detect(temp.path()).unwrap(),
+            PgoCompiler::Clang
+        );
+        assert!(PgoCompiler::detect(&temp.path().join("missing")).is_err());
+
+        let dir = Path::new("/tmp/pgo");
+        assert_eq!(
+            PgoCompiler::Gcc.generate_flags(dir),
+            [
+                ("CMAKE_C_FLAGS", "-fprofile-generate=/tmp/pgo".to_string()),
+                ("CMAKE_CXX_FLAGS", "-fprofile-generate=/tmp/pgo".to_string())
+            ]
+        );
+        assert_eq!(
+            PgoCompiler::Clang.use_flags(dir)[1].1,
+            format!("-fprofile-use={}", dir.join("default.profdata").display())
+        );
+        assert_eq!(
+            PgoCompiler::Gcc.generate_flags(Path::new("/tmp/my pgo"))[0].1,
+            r#"-fprofile-generate="/tmp/my pgo""#
+        );
+        let msvc = PgoCompiler::Msvc.use_flags(dir);
+        assert!(msvc.contains(&("CMAKE_EXE_LINKER_FLAGS", "/LTCG /USEPROFILE".to_string())));
+
+        assert!(
+            PgoConfig::new(Vec::<String>::new())
+                .training_command()
+                .is_err()
+        );
+    }
+}
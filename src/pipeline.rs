@@ -0,0 +1,189 @@
+//! Custom sequences of CMake workflow phases.
+//!
+//! [`Cmd::build`](crate::Cmd::build) always runs configure, build and
+//! (if requested) install, in that order. A [`Pipeline`] lists the stages of
+//! a run explicitly instead, so they can be reordered, repeated, or mixed
+//! with tests, packaging and custom steps. Run one with
+//! [`Cmd::run_pipeline`](crate::Cmd::run_pipeline); every stage adds its
+//! phases to the single returned report.
+//!
+//! # Example
+//!
+//! A profile-guided build: build an instrumented binary, train it, then
+//! configure again to use the profile and build the final binary.
+//!
+//! ```no_run
+//! use cmakr::{Cmd, pipeline::Pipeline};
+//!
+//! let pipeline = Pipeline::new()
+//!     .configure()
+//!     .build()
+//!     .custom("train", |_cmd, report| {
+//!         let app = report.binary_dir.join("app");
+//!         std::process::Command::new(app).arg("--train").status()?;
+//!         Ok(())
+//!     })
+//!     .custom("use-profile", |cmd, _report| {
+//!         *cmd = cmd.clone().add_define("PGO_USE", "ON");
+//!         Ok(())
+//!     })
+//!     .configure()
+//!     .build()
+//!     .test();
+//! let report = Cmd::default()
+//!     .set_path("./my_project")
+//!     .add_define("PGO_GENERATE", "ON")
+//!     .run_pipeline(&pipeline)
+//!     .unwrap();
+//! assert!(report.tests.is_some_and(|tests| tests.success()));
+//! ```
+
+use std::{fmt, sync::Arc};
+
+use crate::{
+    cmd::Cmd,
+    error::Error,
+    report::{BuildReport, Phase},
+};
+
+/// The function run by a custom stage.
+type StageFn = Arc<dyn Fn(&mut Cmd, &BuildReport) -> Result<(), Error> + Send + Sync>;
+
+/// A stage of a [`Pipeline`].
+#[derive(Clone)]
+pub enum Stage {
+    /// Configures the build tree, as [`Cmd::configure`] does.
+    Configure,
+    /// Builds the configured tree without configuring or installing it.
+    Build,
+    /// Runs the tests of the build tree with [`Cmd::ctest`]. The pipeline
+    /// stops with [`Error::TestFailed`] if a test fails.
+    Test,
+    /// Installs the built tree into the
+    /// [install prefix](Cmd::set_install_prefix), which must be set.
+    Install,
+    /// Packages the built tree with [`Cmd::cpack`].
+    Package,
+    /// Runs a user-defined step, see [`Stage::custom`].
+    Custom(String, StageFn),
+}
+
+impl Stage {
+    /// Returns a stage that calls `run` under the given name.
+    ///
+    /// The function receives the `Cmd` of the run, which it may change for
+    /// the stages that follow, and the report of the stages so far. A
+    /// returned error stops the pipeline.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the stage, recorded as the target of its phase.
+    /// * `run` - The step to run.
+    pub fn custom<T, F>(name: T, run: F) -> Self
+    where
+        T: Into<String>,
+        F: Fn(&mut Cmd, &BuildReport) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        Stage::Custom(name.into(), Arc::new(run))
+    }
+
+    /// Returns the phase this stage records in the report.
+    pub fn phase(&self) -> Phase {
+        match self {
+            Stage::Configure => Phase::Configure,
+            Stage::Build => Phase::Build,
+            Stage::Test => Phase::Test,
+            Stage::Install => Phase::Install,
+            Stage::Package => Phase::Package,
+            Stage::Custom(..) => Phase::Custom,
+        }
+    }
+}
+
+impl fmt::Debug for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Custom(name, _) => f.debug_tuple("Custom").field(name).finish(),
+            stage => write!(f, "{:?}", stage.phase()),
+        }
+    }
+}
+
+/// An ordered list of stages, run by [`Cmd::run_pipeline`].
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    /// Creates an empty pipeline.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends `stage`.
+    pub fn then(mut self, stage: Stage) -> Self {
+        self.stages.push(stage);
+        self
+    }
+
+    /// Inserts `stage` at `index`, shifting the stages after it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the number of stages.
+    pub fn insert(mut self, index: usize, stage: Stage) -> Self {
+        self.stages.insert(index, stage);
+        self
+    }
+
+    /// Appends a [`Stage::Configure`].
+    pub fn configure(self) -> Self {
+        self.then(Stage::Configure)
+    }
+
+    /// Appends a [`Stage::Build`].
+    pub fn build(self) -> Self {
+        self.then(Stage::Build)
+    }
+
+    /// Appends a [`Stage::Test`].
+    pub fn test(self) -> Self {
+        self.then(Stage::Test)
+    }
+
+    /// Appends a [`Stage::Install`].
+    pub fn install(self) -> Self {
+        self.then(Stage::Install)
+    }
+
+    /// Appends a [`Stage::Package`].
+    pub fn package(self) -> Self {
+        self.then(Stage::Package)
+    }
+
+    /// Appends a custom stage, see [`Stage::custom`].
+    pub fn custom<T, F>(self, name: T, run: F) -> Self
+    where
+        T: Into<String>,
+        F: Fn(&mut Cmd, &BuildReport) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        self.then(Stage::custom(name, run))
+    }
+
+    /// Returns the stages, in order.
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    /// Returns the stages for reordering or removing them.
+    pub fn stages_mut(&mut self) -> &mut Vec<Stage> {
+        &mut self.stages
+    }
+}
+
+impl From<Vec<Stage>> for Pipeline {
+    fn from(stages: Vec<Stage>) -> Self {
+        Self { stages }
+    }
+}
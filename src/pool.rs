@@ -0,0 +1,144 @@
+//! The shared worker pool behind [`Cmd::spawn`](crate::Cmd::spawn).
+//!
+//! The pool is created on first use with one worker per available CPU. Jobs
+//! are queued and run in submission order by the next free worker, so many
+//! spawned builds share a bounded number of threads instead of each getting
+//! its own. [`set_worker_threads`] resizes the pool at any time: extra
+//! workers start as soon as there is queued work for them, surplus ones exit
+//! after their current job.
+
+use std::{
+    collections::VecDeque,
+    panic::{self, AssertUnwindSafe},
+    sync::{Condvar, Mutex, OnceLock},
+    thread,
+};
+
+/// A queued unit of work.
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The queue and worker bookkeeping, guarded by [`Pool::state`].
+struct State {
+    queue: VecDeque<Job>,
+    /// The number of workers the pool should have.
+    target: usize,
+    /// The number of workers currently alive.
+    workers: usize,
+}
+
+/// Workers sharing a job queue.
+struct Pool {
+    state: Mutex<State>,
+    /// Signalled when a job is queued or the target size shrinks.
+    wakeup: Condvar,
+}
+
+/// Returns the process-wide pool, creating it on first use.
+fn pool() -> &'static Pool {
+    static POOL: OnceLock<Pool> = OnceLock::new();
+    POOL.get_or_init(|| Pool {
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            target: thread::available_parallelism().map_or(1, |n| n.get()),
+            workers: 0,
+        }),
+        wakeup: Condvar::new(),
+    })
+}
+
+/// Sets the number of worker threads running spawned builds.
+///
+/// Defaults to the number of available CPUs. Builds queued beyond this number
+/// wait until a worker is free. A build that waits for another spawned build
+/// from inside the pool needs at least two workers.
+///
+/// # Arguments
+///
+/// * `threads` - The number of workers, at least 1.
+pub fn set_worker_threads(threads: usize) {
+    let pool = pool();
+    let mut state = pool.state.lock().unwrap_or_else(|e| e.into_inner());
+    state.target = threads.max(1);
+    // start the missing workers only once there is work for them
+    let missing = state
+        .target
+        .saturating_sub(state.workers)
+        .min(state.queue.len());
+    start_workers(&mut state, missing);
+    drop(state);
+    pool.wakeup.notify_all();
+}
+
+/// Queues `job` to run on the pool.
+pub(crate) fn execute<F>(job: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    let pool = pool();
+    let mut state = pool.state.lock().unwrap_or_else(|e| e.into_inner());
+    state.queue.push_back(Box::new(job));
+    if state.workers < state.target {
+        start_workers(&mut state, 1);
+    }
+    drop(state);
+    pool.wakeup.notify_one();
+}
+
+/// Starts `count` more workers.
+fn start_workers(state: &mut State, count: usize) {
+    for _ in 0..count {
+        state.workers += 1;
+        thread::spawn(work);
+    }
+}
+
+/// Runs queued jobs until the pool shrinks below this worker.
+fn work() {
+    let pool = pool();
+    loop {
+        let mut state = pool.state.lock().unwrap_or_else(|e| e.into_inner());
+        let job = loop {
+            if state.workers > state.target {
+                state.workers -= 1;
+                return;
+            }
+            if let Some(job) = state.queue.pop_front() {
+                break job;
+            }
+            state = pool.wakeup.wait(state).unwrap_or_else(|e| e.into_inner());
+        };
+        drop(state);
+
+        // a panicking job drops its result sender, which its receiver observes
+        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc;
+
+    #[test]
+    fn run_queued_jobs_on_resized_pool() {
+        set_worker_threads(2);
+        let (tx, rx) = mpsc::channel();
+        for i in 0..8 {
+            let tx = tx.clone();
+            execute(move || {
+                let _ = tx.send(i);
+            });
+        }
+        set_worker_threads(1);
+        let tx_last = tx.clone();
+        execute(move || {
+            let _ = tx_last.send(8);
+        });
+        drop(tx);
+
+        let mut results: Vec<i32> = rx.iter().collect();
+        results.sort();
+        assert_eq!(results, (0..9).collect::<Vec<_>>());
+    }
+}
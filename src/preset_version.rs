@@ -0,0 +1,417 @@
+//! Preset schema versions and pinning presets files to a CMake version.
+//!
+//! Each CMake release reads presets files up to a schema `version`; a newer
+//! one fails to parse, even if the file uses none of the new fields. When CI
+//! agents run different CMake releases,
+//! [`CMakePresets::pin_versions`](crate::cmake::CMakePresets::pin_versions)
+//! keeps `CMakePresets.json` and the files it includes readable by the
+//! oldest one: it lowers `version` to the newest schema that release reads,
+//! and reports the fields and macros that need a newer schema instead of
+//! dropping them. Files are only rewritten if nothing needs a newer schema,
+//! and only the `version` number is changed, so formatting and key order
+//! are kept.
+//!
+//! | Schema | CMake | Adds |
+//! |--------|-------|------|
+//! | 1 | 3.19 | configure presets |
+//! | 2 | 3.20 | build and test presets |
+//! | 3 | 3.21 | `installDir`, `toolchainFile`, `condition`, `${hostSystemName}` |
+//! | 4 | 3.23 | `include`, `resolvePackageReferences`, `${fileDir}` |
+//! | 5 | 3.24 | `testOutputTruncation`, `${pathListSep}` |
+//! | 6 | 3.25 | package and workflow presets, `outputJUnitFile` |
+//! | 7 | 3.27 | `trace` |
+//! | 8 | 3.28 | `$schema` |
+//! | 9 | 3.30 | macros in `include` |
+//! | 10 | 3.31 | `$comment`, `graphviz` |
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::cmake::{CMakePresets, CMakeVersion};
+//!
+//! let presets = CMakePresets::new("./native").unwrap();
+//! let report = presets.pin_versions(CMakeVersion::new(3, 22, 1)).unwrap();
+//! for field in &report.unsupported {
+//!     eprintln!("{}", field);
+//! }
+//! ```
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use crate::{cmake::CMakeVersion, error::Error};
+
+/// The schema versions and the CMake 3 minor releases that introduced them.
+const SCHEMAS: [(u32, u32); 10] = [
+    (1, 19),
+    (2, 20),
+    (3, 21),
+    (4, 23),
+    (5, 24),
+    (6, 25),
+    (7, 27),
+    (8, 28),
+    (9, 30),
+    (10, 31),
+];
+
+/// Fields of the presets file itself and the schema that introduced them.
+const FILE_FIELDS: [(&str, u32); 7] = [
+    ("buildPresets", 2),
+    ("testPresets", 2),
+    ("include", 4),
+    ("packagePresets", 6),
+    ("workflowPresets", 6),
+    ("$schema", 8),
+    ("$comment", 10),
+];
+
+/// Fields of a preset and the schema that introduced them, by kind.
+const PRESET_FIELDS: [(&str, &str, u32); 8] = [
+    ("configurePresets", "installDir", 3),
+    ("configurePresets", "toolchainFile", 3),
+    ("configurePresets", "trace", 7),
+    ("configurePresets", "graphviz", 10),
+    ("buildPresets", "resolvePackageReferences", 4),
+    ("testPresets", "output.testOutputTruncation", 5),
+    ("testPresets", "output.outputJUnitFile", 6),
+    ("*", "condition", 3),
+];
+
+/// Macros and the schema that introduced them.
+const MACROS: [(&str, u32); 3] = [
+    ("${hostSystemName}", 3),
+    ("${fileDir}", 4),
+    ("${pathListSep}", 5),
+];
+
+/// Returns the newest preset schema `version` that `cmake` reads, or `None`
+/// before presets (CMake 3.19).
+pub fn schema_version(cmake: CMakeVersion) -> Option<u32> {
+    if cmake.major > 3 {
+        return SCHEMAS.last().map(|(schema, _)| *schema);
+    }
+    SCHEMAS
+        .iter()
+        .rev()
+        .find(|(_, minor)| cmake >= CMakeVersion::new(3, *minor, 0))
+        .map(|(schema, _)| *schema)
+}
+
+/// Returns the first CMake release that reads the preset `schema`.
+fn cmake_version(schema: u32) -> CMakeVersion {
+    let minor = SCHEMAS
+        .iter()
+        .find(|(version, _)| *version >= schema)
+        .map_or(31, |(_, minor)| *minor);
+    CMakeVersion::new(3, minor, 0)
+}
+
+/// A field or macro of a presets file that the pinned CMake can't read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedField {
+    /// The presets file.
+    pub file: PathBuf,
+    /// Where the field is, e.g. `"configurePresets[1].toolchainFile"`, or
+    /// the macro and the field using it.
+    pub field: String,
+    /// The first CMake release that reads it.
+    pub requires: CMakeVersion,
+}
+
+impl fmt::Display for UnsupportedField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} requires CMake >= {}",
+            self.file.display(),
+            self.field,
+            self.requires
+        )
+    }
+}
+
+/// The result of [`CMakePresets::pin_versions`](crate::cmake::CMakePresets::pin_versions).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PinReport {
+    /// The schema version the files are pinned to.
+    pub schema_version: u32,
+    /// The files whose `version` was lowered.
+    pub rewritten: Vec<PathBuf>,
+    /// What the pinned CMake can't read. Nothing is rewritten unless this is
+    /// empty.
+    pub unsupported: Vec<UnsupportedField>,
+}
+
+impl PinReport {
+    /// Returns whether the pinned CMake reads all files.
+    pub fn is_compatible(&self) -> bool {
+        self.unsupported.is_empty()
+    }
+}
+
+/// A presets file read for pinning.
+struct PresetsFile {
+    path: PathBuf,
+    content: String,
+    version: u32,
+}
+
+/// Pins the presets file at `path` and the files it includes to the newest
+/// schema `cmake` reads.
+///
+/// # Errors
+///
+/// Returns [`Error::Unsupported`] for CMake releases before presets,
+/// [`Error::PresetParse`] if a file can't be parsed, or an I/O error.
+pub(crate) fn pin(path: &Path, cmake: CMakeVersion) -> Result<PinReport, Error> {
+    let schema = schema_version(cmake).ok_or_else(|| {
+        Error::Unsupported(format!("CMake {} does not read presets files", cmake))
+    })?;
+    let mut report = PinReport {
+        schema_version: schema,
+        ..PinReport::default()
+    };
+    let mut files = Vec::new();
+    read(path, cmake, &mut files, &mut report.unsupported)?;
+    if !report.is_compatible() {
+        return Ok(report);
+    }
+
+    for file in files.iter().filter(|file| file.version > schema) {
+        let content = set_version(&file.content, schema).ok_or_else(|| {
+            Error::PresetParse(format!("{}: no top-level version", file.path.display()))
+        })?;
+        std::fs::write(&file.path, content)?;
+        report.rewritten.push(file.path.clone());
+    }
+    Ok(report)
+}
+
+/// Reads the presets file at `path` and the files it includes into `files`,
+/// and adds what `cmake` can't read to `unsupported`.
+fn read(
+    path: &Path,
+    cmake: CMakeVersion,
+    files: &mut Vec<PresetsFile>,
+    unsupported: &mut Vec<UnsupportedField>,
+) -> Result<(), Error> {
+    let schema = schema_version(cmake).unwrap_or_default();
+    if files.iter().any(|file| file.path == path) {
+        return Ok(());
+    }
+    let content = std::fs::read_to_string(path)?;
+    let json: Value = serde_json::from_str(&content)
+        .map_err(|e| Error::PresetParse(format!("{}: {}", path.display(), e)))?;
+    let version = json["version"].as_u64().unwrap_or(0) as u32;
+
+    let required = &json["cmakeMinimumRequired"];
+    let part = |name: &str| required[name].as_u64().unwrap_or(0) as u32;
+    let required = CMakeVersion::new(part("major"), part("minor"), part("patch"));
+    if required > cmake {
+        unsupported.push(UnsupportedField {
+            file: path.to_path_buf(),
+            field: "cmakeMinimumRequired".to_string(),
+            requires: required,
+        });
+    }
+    let mut flag = |field: String, since: u32| {
+        if since > schema {
+            unsupported.push(UnsupportedField {
+                file: path.to_path_buf(),
+                field,
+                requires: cmake_version(since),
+            });
+        }
+    };
+    for (field, since) in FILE_FIELDS {
+        if json.get(field).is_some() {
+            flag(field.to_string(), since);
+        }
+    }
+    for (kind, field, since) in PRESET_FIELDS {
+        for (name, index, preset) in presets(&json, kind) {
+            let value = field
+                .split('.')
+                .try_fold(preset, |value, key| value.get(key));
+            if value.is_some() {
+                flag(format!("{}[{}].{}", name, index, field), since);
+            }
+        }
+    }
+    walk(&json, String::new(), &mut |at, value| {
+        // the top-level `$comment` is a file field
+        if at.ends_with(".$comment") {
+            flag(at.to_string(), 10);
+        }
+        if let Value::String(text) = value {
+            if at.starts_with("include[") && text.contains("${") {
+                flag(format!("macros in {}", at), 9);
+            }
+            for (name, since) in MACROS {
+                if text.contains(name) {
+                    flag(format!("{} in {}", name, at), since);
+                }
+            }
+        }
+    });
+
+    let includes: Vec<String> = json["include"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|include| include.as_str().map(str::to_string))
+        .collect();
+    files.push(PresetsFile {
+        path: path.to_path_buf(),
+        content,
+        version,
+    });
+    let dir = path.parent().unwrap_or(Path::new("."));
+    for include in includes {
+        if include.contains("${") {
+            // left to CMake, which expands macros in includes from schema 9 on
+            continue;
+        }
+        read(&dir.join(include), cmake, files, unsupported)?;
+    }
+    Ok(())
+}
+
+/// Returns the presets of `kind`, or of all kinds for `"*"`, with the name
+/// of their array and their index.
+fn presets<'a>(
+    json: &'a Value,
+    kind: &'a str,
+) -> impl Iterator<Item = (&'static str, usize, &'a Value)> {
+    [
+        "configurePresets",
+        "buildPresets",
+        "testPresets",
+        "packagePresets",
+        "workflowPresets",
+    ]
+    .into_iter()
+    .filter(move |name| kind == "*" || *name == kind)
+    .flat_map(|name| {
+        let presets = json[name].as_array().into_iter().flatten();
+        presets
+            .enumerate()
+            .map(move |(index, preset)| (name, index, preset))
+    })
+}
+
+/// Calls `visit` with every value below `value` and its location, e.g.
+/// `"configurePresets[0].name"`.
+fn walk<F>(value: &Value, at: String, visit: &mut F)
+where
+    F: FnMut(&str, &Value),
+{
+    match value {
+        Value::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                walk(item, format!("{}[{}]", at, index), visit);
+            }
+        }
+        Value::Object(fields) => {
+            for (key, field) in fields {
+                let at = if at.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", at, key)
+                };
+                walk(field, at, visit);
+            }
+        }
+        _ => {}
+    }
+    visit(&at, value);
+}
+
+/// Returns `content` with the number of the top-level `version` field
+/// replaced by `version`, leaving everything else as it is.
+fn set_version(content: &str, version: u32) -> Option<String> {
+    let bytes = content.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth -= 1,
+            b'"' => {
+                let start = i + 1;
+                i = start;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                let key = &content[start..i.min(bytes.len())];
+                let rest = &content[(i + 1).min(bytes.len())..];
+                if depth == 1
+                    && key == "version"
+                    && let Some(value) = rest.trim_start().strip_prefix(':')
+                {
+                    let value = value.trim_start();
+                    let digits =
+                        value.len() - value.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                    let offset = content.len() - value.len();
+                    return Some(format!(
+                        "{}{}{}",
+                        &content[..offset],
+                        version,
+                        &content[offset + digits..]
+                    ));
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pin_presets_to_cmake_version() {
+        assert_eq!(schema_version(CMakeVersion::new(3, 18, 4)), None);
+        assert_eq!(schema_version(CMakeVersion::new(3, 22, 1)), Some(3));
+        assert_eq!(schema_version(CMakeVersion::new(4, 0, 0)), Some(10));
+
+        let temp = crate::guard::TempBuildDir::new("pin").unwrap();
+        let dir = temp.path();
+        std::fs::write(
+            dir.join("CMakePresets.json"),
+            "{\n  \"version\": 6,\n  \"configurePresets\": [\n    {\"name\": \"ci\", \"toolchainFile\": \"${sourceDir}/tc.cmake\"}\n  ]\n}\n",
+        )
+        .unwrap();
+        let presets = crate::cmake::CMakePresets::new(dir).unwrap();
+
+        let report = presets.pin_versions(CMakeVersion::new(3, 20, 0)).unwrap();
+        assert_eq!(report.schema_version, 2);
+        assert_eq!(
+            report.unsupported[0].field,
+            "configurePresets[0].toolchainFile"
+        );
+        assert_eq!(report.unsupported[0].requires, CMakeVersion::new(3, 21, 0));
+        assert!(report.rewritten.is_empty());
+
+        let report = presets.pin_versions(CMakeVersion::new(3, 22, 1)).unwrap();
+        assert!(report.is_compatible());
+        assert_eq!(report.rewritten, [dir.join("CMakePresets.json")]);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("CMakePresets.json")).unwrap(),
+            "{\n  \"version\": 3,\n  \"configurePresets\": [\n    {\"name\": \"ci\", \"toolchainFile\": \"${sourceDir}/tc.cmake\"}\n  ]\n}\n"
+        );
+
+        assert_eq!(
+            set_version(r#"{"name": {"version": 9}, "version" : 10}"#, 4).as_deref(),
+            Some(r#"{"name": {"version": 9}, "version" : 4}"#)
+        );
+    }
+}
@@ -0,0 +1,578 @@
+//! Child process execution with output capture.
+//!
+//! CMake and the native build tool are run with piped stdout/stderr. Every
+//! line is forwarded to the parent's corresponding stream as it arrives (so
+//! the output looks the same as with inherited stdio), or handed to a
+//! [`LineHandler`] instead, and also collected, so that it can be parsed once
+//! the process exits. Secrets are redacted from every line before it is
+//! forwarded, handed over, or collected.
+//!
+//! Alternatively, [`run_direct`] connects the child's stdout and stderr to
+//! given handles, e.g. a pty, so the tools see a terminal. Nothing is
+//! collected then. With the `pty` feature, [`pty::run`](crate::pty::run)
+//! runs the child in a pseudo-terminal of its own and still collects its
+//! output.
+//!
+//! Every child leads a [`ProcessGroup`] holding everything it starts: a
+//! process group of its own on Unix, and a Job object on Windows. Cancelling
+//! or killing a child signals its whole group at once, including
+//! grandchildren whose parent already exited. As the group is not the
+//! terminal's foreground group, `Ctrl-C` reaches cmakr's process only; a
+//! build script that exits on it leaves no child behind only if it cancels
+//! the build first.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU8, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    credentials::Redactor,
+    encoding,
+    watchdog::{Trigger, Watchdog},
+};
+
+/// How often a running child is checked for exit or cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a terminated process tree may take to exit before it is killed.
+const TERMINATE_GRACE: Duration = Duration::from_secs(5);
+
+/// [`CancelToken`] states.
+const RUNNING: u8 = 0;
+const TERMINATE: u8 = 1;
+const KILL: u8 = 2;
+
+/// The stream a line of child output was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Receives every line of child output, without the trailing newline, in
+/// place of forwarding it to the parent's streams.
+pub(crate) type LineHandler = Arc<dyn Fn(Stream, &str) + Send + Sync>;
+
+/// A shared flag asking running child processes to be killed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CancelToken(Arc<AtomicU8>);
+
+impl CancelToken {
+    /// Requests cancellation of every process run with this token, killing
+    /// their process trees right away.
+    pub(crate) fn cancel(&self) {
+        self.0.store(KILL, Ordering::SeqCst);
+    }
+
+    /// Requests cancellation of every process run with this token, asking
+    /// their process trees to terminate (`SIGTERM`) and killing them if they
+    /// are still running after a grace period.
+    pub(crate) fn terminate(&self) {
+        let _ = self
+            .0
+            .compare_exchange(RUNNING, TERMINATE, Ordering::SeqCst, Ordering::SeqCst);
+    }
+
+    /// Returns whether cancellation has been requested.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst) != RUNNING
+    }
+
+    /// Returns whether the process trees must be killed without a grace period.
+    fn is_killed(&self) -> bool {
+        self.0.load(Ordering::SeqCst) == KILL
+    }
+}
+
+/// A running child process, spawned with pipes or into a pty.
+pub(crate) trait Process {
+    /// Returns the exit status if the process has exited.
+    fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>>;
+
+    /// Returns the process ID.
+    fn id(&self) -> u32;
+
+    /// Kills the process, ignoring errors.
+    fn kill(&mut self);
+}
+
+impl Process for Child {
+    fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>> {
+        Child::try_wait(self)
+    }
+
+    fn id(&self) -> u32 {
+        Child::id(self)
+    }
+
+    fn kill(&mut self) {
+        let _ = Child::kill(self);
+    }
+}
+
+/// The exit status and collected output of a finished child process.
+pub(crate) struct CapturedOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether the process was killed because its [`CancelToken`] was cancelled.
+    pub cancelled: bool,
+    /// Why the process was killed by its [`Watchdog`], if it was.
+    pub killed_by: Option<Trigger>,
+}
+
+/// Runs `command` to completion, teeing its stdout and stderr.
+///
+/// If `on_line` is given, output lines are passed to it instead of being
+/// written to the parent's stdout and stderr. If `cancel` is given and gets
+/// cancelled while the process is running, the process is killed and the
+/// result is marked as cancelled. If `watchdog` is given, it is checked while
+/// the process is running and may kill it. Output is redacted with `redactor`.
+///
+/// # Errors
+///
+/// Returns an I/O error if the process cannot be spawned or waited on.
+pub(crate) fn run(
+    command: &mut Command,
+    cancel: Option<&CancelToken>,
+    watchdog: Option<&Watchdog>,
+    on_line: Option<&LineHandler>,
+    redactor: &Redactor,
+) -> std::io::Result<CapturedOutput> {
+    let mut child = own_group(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let child_stdout = child.stdout.take().expect("stdout is piped");
+    let child_stderr = child.stderr.take().expect("stderr is piped");
+
+    let stdout_handler = on_line.cloned();
+    let stderr_handler = on_line.cloned();
+    let stdout_redactor = redactor.clone();
+    let stderr_redactor = redactor.clone();
+    let started = Instant::now();
+    let last_output = Arc::new(Mutex::new(started));
+    let stdout_output = Arc::clone(&last_output);
+    let stderr_output = Arc::clone(&last_output);
+    let stdout_reader = thread::spawn(move || {
+        tee(
+            child_stdout,
+            Stream::Stdout,
+            stdout_handler,
+            &stdout_redactor,
+            &stdout_output,
+        )
+    });
+    let stderr_reader = thread::spawn(move || {
+        tee(
+            child_stderr,
+            Stream::Stderr,
+            stderr_handler,
+            &stderr_redactor,
+            &stderr_output,
+        )
+    });
+
+    let (status, cancelled, killed_by) = wait(
+        &mut child,
+        command,
+        cancel,
+        watchdog,
+        started,
+        Some(&last_output),
+        on_line,
+    )?;
+
+    let stdout = stdout_reader.join().unwrap_or_default();
+    let stderr = stderr_reader.join().unwrap_or_default();
+
+    Ok(CapturedOutput {
+        status,
+        stdout,
+        stderr,
+        cancelled,
+        killed_by,
+    })
+}
+
+/// Runs `command` to completion with its stdout and stderr connected to
+/// `stdout` and `stderr`, like [`run`] otherwise.
+///
+/// The output is neither collected nor redacted, and the watchdog's stall
+/// detection does not apply, as the output is not seen.
+///
+/// # Errors
+///
+/// Returns an I/O error if the process cannot be spawned or waited on.
+pub(crate) fn run_direct(
+    command: &mut Command,
+    cancel: Option<&CancelToken>,
+    watchdog: Option<&Watchdog>,
+    stdout: Stdio,
+    stderr: Stdio,
+) -> std::io::Result<CapturedOutput> {
+    let mut child = own_group(command)
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr)
+        .spawn()?;
+    let (status, cancelled, killed_by) = wait(
+        &mut child,
+        command,
+        cancel,
+        watchdog,
+        Instant::now(),
+        None,
+        None,
+    )?;
+    Ok(CapturedOutput {
+        status,
+        stdout: String::new(),
+        stderr: String::new(),
+        cancelled,
+        killed_by,
+    })
+}
+
+/// Waits for `child` to exit, killing it if `cancel` is cancelled or
+/// `watchdog` fires. Returns the exit status, whether it was cancelled, and
+/// why the watchdog killed it, if it did.
+///
+/// `last_output` is the time of the last output line, if the output is seen.
+/// Keepalive heartbeats go to `on_line` if given, else to stderr.
+pub(crate) fn wait<P>(
+    child: &mut P,
+    command: &Command,
+    cancel: Option<&CancelToken>,
+    watchdog: Option<&Watchdog>,
+    started: Instant,
+    last_output: Option<&Mutex<Instant>>,
+    on_line: Option<&LineHandler>,
+) -> std::io::Result<(ExitStatus, bool, Option<Trigger>)>
+where
+    P: Process,
+{
+    let program = command.get_program().to_string_lossy().into_owned();
+    let group = ProcessGroup::of(child.id());
+    let mut cancelled = false;
+    let mut terminated_at: Option<Instant> = None;
+    let mut force_killed = false;
+    let mut killed_by = None;
+    let mut stall_reported = false;
+    let mut heartbeats = 0;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if let Some(cancel) = cancel
+            && cancel.is_cancelled()
+        {
+            // a terminated tree that outlives the grace period is killed
+            let force = cancel.is_killed()
+                || terminated_at.is_some_and(|at| at.elapsed() >= TERMINATE_GRACE);
+            if force && !force_killed {
+                kill_tree(child, &group, true);
+                force_killed = true;
+            } else if !force && terminated_at.is_none() {
+                kill_tree(child, &group, false);
+                terminated_at = Some(Instant::now());
+            }
+            cancelled = true;
+        }
+        if killed_by.is_none()
+            && let Some(watchdog) = watchdog
+        {
+            let silent = last_output.and_then(|at| at.lock().ok().map(|at| at.elapsed()));
+            // unseen output is always silent to the keepalive
+            let quiet = silent.unwrap_or_else(|| started.elapsed());
+            if let Some(line) = watchdog.heartbeat(started.elapsed(), quiet, &mut heartbeats) {
+                match on_line {
+                    Some(handler) => handler(Stream::Stderr, &line),
+                    None => eprintln!("{}", line),
+                }
+            }
+            killed_by = watchdog.check(
+                &program,
+                child.id(),
+                started.elapsed(),
+                silent.unwrap_or_default(),
+                &mut stall_reported,
+            );
+            if killed_by.is_some() {
+                kill_tree(child, &group, true);
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    Ok((status, cancelled, killed_by))
+}
+
+/// Stops `child` and its descendants in `group`, which would otherwise keep
+/// running and keep the output pipes open: with `SIGKILL` if `force` is set,
+/// else with `SIGTERM`. On Windows, the tree is always killed.
+fn kill_tree<P>(child: &mut P, group: &ProcessGroup, force: bool)
+where
+    P: Process,
+{
+    group.stop(force);
+    if force {
+        child.kill();
+    }
+}
+
+/// Makes `command` start the child in a process group of its own, which
+/// [`ProcessGroup::of`] then finds by its pid.
+fn own_group(command: &mut Command) -> &mut Command {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+    command
+}
+
+/// The processes started by a child, which are stopped together.
+///
+/// On Unix, the child's process group: children run by [`run`] and
+/// [`run_direct`] lead their own group, and pty children lead a session.
+/// On Windows, a Job object the child is assigned to once it started, whose
+/// processes are killed when the group is stopped.
+struct ProcessGroup {
+    #[cfg(unix)]
+    pgid: i32,
+    #[cfg(windows)]
+    job: Option<ffi::Handle>,
+}
+
+impl ProcessGroup {
+    /// Returns the group led by the child with the process id `pid`.
+    #[cfg(unix)]
+    fn of(pid: u32) -> Self {
+        Self { pgid: pid as i32 }
+    }
+
+    /// Returns the group of the child with the process id `pid`, assigning
+    /// it to a new Job object; what it started before is not in the job.
+    #[cfg(windows)]
+    fn of(pid: u32) -> Self {
+        Self {
+            job: ffi::job_with(pid),
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn of(_pid: u32) -> Self {
+        Self {}
+    }
+
+    /// Asks every process of the group to terminate, or kills them if
+    /// `force` is set. Errors, e.g. for a group that already exited, are
+    /// ignored.
+    fn stop(&self, force: bool) {
+        #[cfg(unix)]
+        ffi::signal_group(self.pgid, force);
+        // Windows has no way to ask a process tree to terminate
+        #[cfg(windows)]
+        if let Some(job) = &self.job {
+            ffi::terminate(job);
+        }
+        #[cfg(not(unix))]
+        let _ = force;
+    }
+}
+
+#[cfg(unix)]
+mod ffi {
+    const SIGKILL: i32 = 9;
+    const SIGTERM: i32 = 15;
+
+    unsafe extern "C" {
+        fn kill(pid: i32, signal: i32) -> i32;
+    }
+
+    /// Sends `SIGKILL` if `force` is set, else `SIGTERM`, to the process
+    /// group `pgid`.
+    pub(super) fn signal_group(pgid: i32, force: bool) {
+        // a non-positive pgid would signal cmakr's own group or every process
+        if pgid <= 1 {
+            return;
+        }
+        let signal = if force { SIGKILL } else { SIGTERM };
+        // SAFETY: kill has no memory-safety requirements; a negative pid names a group
+        unsafe { kill(-pgid, signal) };
+    }
+}
+
+#[cfg(windows)]
+mod ffi {
+    use std::ffi::c_void;
+
+    const PROCESS_SET_QUOTA: u32 = 0x0100;
+    const PROCESS_TERMINATE: u32 = 0x0001;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn CreateJobObjectW(attributes: *mut c_void, name: *const u16) -> *mut c_void;
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+        fn AssignProcessToJobObject(job: *mut c_void, process: *mut c_void) -> i32;
+        fn TerminateJobObject(job: *mut c_void, exit_code: u32) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+
+    /// An owned kernel handle, closed when dropped.
+    pub(super) struct Handle(*mut c_void);
+
+    // SAFETY: kernel handles may be used and closed from any thread
+    unsafe impl Send for Handle {}
+    // SAFETY: the handle is only passed to thread-safe kernel calls
+    unsafe impl Sync for Handle {}
+
+    impl Drop for Handle {
+        fn drop(&mut self) {
+            // SAFETY: the handle is open and owned by this value
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    /// Creates a Job object and assigns the process `pid` to it.
+    pub(super) fn job_with(pid: u32) -> Option<Handle> {
+        // SAFETY: null attributes and name create an unnamed job with default security
+        let job = unsafe { CreateJobObjectW(std::ptr::null_mut(), std::ptr::null()) };
+        if job.is_null() {
+            return None;
+        }
+        let job = Handle(job);
+        // SAFETY: OpenProcess has no memory-safety requirements
+        let process = unsafe { OpenProcess(PROCESS_SET_QUOTA | PROCESS_TERMINATE, 0, pid) };
+        if process.is_null() {
+            return None;
+        }
+        let process = Handle(process);
+        // SAFETY: both handles are open
+        let assigned = unsafe { AssignProcessToJobObject(job.0, process.0) } != 0;
+        assigned.then_some(job)
+    }
+
+    /// Kills every process of `job`.
+    pub(super) fn terminate(job: &Handle) {
+        // SAFETY: the job handle is open
+        unsafe { TerminateJobObject(job.0, 1) };
+    }
+}
+
+/// Copies `source` line by line into the parent's `stream`, or into `handler`
+/// if one is given, returning everything that was read. The time of the last
+/// line is stored in `last_output`.
+///
+/// Lines that are not UTF-8 are [decoded](encoding::decode) from the
+/// console's code page rather than treated as an error, since compilers
+/// frequently emit output in it.
+fn tee<R>(
+    source: R,
+    stream: Stream,
+    handler: Option<LineHandler>,
+    redactor: &Redactor,
+    last_output: &Mutex<Instant>,
+) -> String
+where
+    R: Read,
+{
+    let mut reader = BufReader::new(source);
+    let mut sink: Box<dyn Write> = match stream {
+        Stream::Stdout => Box::new(std::io::stdout()),
+        Stream::Stderr => Box::new(std::io::stderr()),
+    };
+    let mut collected = String::new();
+    let mut line = Vec::new();
+
+    while let Ok(n) = reader.read_until(b'\n', &mut line) {
+        if n == 0 {
+            break;
+        }
+        if let Ok(mut at) = last_output.lock() {
+            *at = Instant::now();
+        }
+        let text = encoding::decode(&line);
+        let text = redactor.redact(&text);
+        match &handler {
+            Some(handler) => handler(stream, text.trim_end_matches(['\r', '\n'])),
+            None => {
+                let _ = sink.write_all(text.as_bytes());
+            }
+        }
+        collected.push_str(&text);
+        line.clear();
+    }
+
+    let _ = sink.flush();
+    collected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel_process_tree() {
+        if !cfg!(unix) {
+            return;
+        }
+        // the background sleep keeps the output pipes open unless it is killed too
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 30 & sleep 30"]);
+        let cancel = CancelToken::default();
+        let canceller = cancel.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            canceller.terminate();
+        });
+
+        let quiet: LineHandler = Arc::new(|_: Stream, _: &str| {});
+        let started = Instant::now();
+        let output = run(
+            &mut command,
+            Some(&cancel),
+            None,
+            Some(&quiet),
+            &Redactor::default(),
+        )
+        .unwrap();
+        assert!(output.cancelled);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn cancel_reparented_grandchildren() {
+        if !cfg!(unix) {
+            return;
+        }
+        // the subshell exits at once, so its sleep is no longer a descendant of the child
+        let mut command = Command::new("sh");
+        command.args(["-c", "(sleep 30 &); sleep 30"]);
+        let cancel = CancelToken::default();
+        let canceller = cancel.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            canceller.terminate();
+        });
+
+        let quiet: LineHandler = Arc::new(|_: Stream, _: &str| {});
+        let started = Instant::now();
+        let output = run(
+            &mut command,
+            Some(&cancel),
+            None,
+            Some(&quiet),
+            &Redactor::default(),
+        )
+        .unwrap();
+        assert!(output.cancelled);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
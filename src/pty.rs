@@ -0,0 +1,285 @@
+//! Running CMake and the native build tool in a pseudo-terminal.
+//!
+//! Output that cmakr forwards passes through pipes, so the tools don't see a
+//! terminal: Ninja and Make drop their progress displays and compilers their
+//! colors. With [`Cmd::use_pty`](crate::Cmd::use_pty), each tool runs in a
+//! pseudo-terminal of its own instead and writes exactly what it would
+//! write in an interactive shell, while cmakr still reads the output:
+//! it is forwarded with colors and progress lines, and collected, without
+//! escape sequences and overwritten progress, for the report and its
+//! diagnostics. Requires the `pty` feature.
+//!
+//! A terminal has a single output stream, so everything the tools write,
+//! including their stderr, arrives as stdout.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::Cmd;
+//!
+//! let report = Cmd::default()
+//!     .set_path("./my_project")
+//!     .use_pty(true)
+//!     .build()
+//!     .unwrap();
+//! println!("{} warnings", report.warnings().count());
+//! ```
+
+use std::{
+    io::{self, Read, Write},
+    process::{Command, ExitStatus},
+    sync::{Arc, Mutex},
+    thread,
+    time::Instant,
+};
+
+use portable_pty::{CommandBuilder, PtySize};
+
+use crate::{
+    credentials::Redactor,
+    process::{self, CancelToken, CapturedOutput, LineHandler, Process, Stream},
+    watchdog::Watchdog,
+};
+
+/// The size of the terminal the tools run in.
+const SIZE: PtySize = PtySize {
+    rows: 24,
+    cols: 120,
+    pixel_width: 0,
+    pixel_height: 0,
+};
+
+impl Process for Box<dyn portable_pty::Child + Send + Sync> {
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        Ok(portable_pty::Child::try_wait(self.as_mut())?.map(exit_status))
+    }
+
+    fn id(&self) -> u32 {
+        self.process_id().unwrap_or_default()
+    }
+
+    fn kill(&mut self) {
+        let _ = portable_pty::ChildKiller::kill(self.as_mut());
+    }
+}
+
+/// Runs `command` to completion in a new pseudo-terminal, like
+/// [`process::run`] otherwise.
+///
+/// Without `on_line`, the output is written to the parent's stdout as it
+/// arrives, escape sequences and carriage returns included. Secrets are
+/// redacted from every piece the tool writes at once.
+///
+/// # Errors
+///
+/// Returns an I/O error if the pty cannot be opened or the process cannot be
+/// spawned or waited on.
+pub(crate) fn run(
+    command: &Command,
+    cancel: Option<&CancelToken>,
+    watchdog: Option<&Watchdog>,
+    on_line: Option<&LineHandler>,
+    redactor: &Redactor,
+) -> io::Result<CapturedOutput> {
+    let pair = portable_pty::native_pty_system()
+        .openpty(SIZE)
+        .map_err(io::Error::other)?;
+    let mut child = pair
+        .slave
+        .spawn_command(builder(command))
+        .map_err(io::Error::other)?;
+    // the reader only sees the end of the output once every slave handle is closed
+    drop(pair.slave);
+    let reader = pair.master.try_clone_reader().map_err(io::Error::other)?;
+
+    let handler = on_line.cloned();
+    let reader_redactor = redactor.clone();
+    let started = Instant::now();
+    let last_output = Arc::new(Mutex::new(started));
+    let reader_output = Arc::clone(&last_output);
+    let reader = thread::spawn(move || read(reader, handler, &reader_redactor, &reader_output));
+
+    let (status, cancelled, killed_by) = process::wait(
+        &mut child,
+        command,
+        cancel,
+        watchdog,
+        started,
+        Some(&last_output),
+        on_line,
+    )?;
+    let stdout = reader.join().unwrap_or_default();
+    drop(pair.master);
+
+    Ok(CapturedOutput {
+        status,
+        stdout,
+        stderr: String::new(),
+        cancelled,
+        killed_by,
+    })
+}
+
+/// Returns a pty command with the program, arguments, environment, and
+/// working directory of `command`.
+fn builder(command: &Command) -> CommandBuilder {
+    let mut builder = CommandBuilder::new(command.get_program());
+    builder.args(command.get_args());
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => builder.env(key, value),
+            None => builder.env_remove(key),
+        }
+    }
+    if let Some(dir) = command.get_current_dir() {
+        builder.cwd(dir);
+    }
+    builder
+}
+
+/// Converts the exit status of a pty child.
+fn exit_status(status: portable_pty::ExitStatus) -> ExitStatus {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        ExitStatus::from_raw(((status.exit_code() & 0xff) as i32) << 8)
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::ExitStatusExt;
+        ExitStatus::from_raw(status.exit_code())
+    }
+}
+
+/// Reads the output of a pty until the child and its descendants close it.
+///
+/// Every line is handed to `handler` as the terminal received it, or
+/// without a handler, the output is written to the parent's stdout as it
+/// arrives. Returns the lines as they were left on the screen, with
+/// escape sequences removed, for parsing.
+fn read<R>(
+    mut source: R,
+    handler: Option<LineHandler>,
+    redactor: &Redactor,
+    last_output: &Mutex<Instant>,
+) -> String
+where
+    R: Read,
+{
+    let mut stdout = io::stdout();
+    let mut collected = String::new();
+    let mut line = Vec::new();
+    let mut forwarded = 0;
+    let mut buffer = [0; 4096];
+
+    // reading fails instead of returning 0 on Linux once the child is gone
+    while let Ok(n) = source.read(&mut buffer) {
+        if n == 0 {
+            break;
+        }
+        if let Ok(mut at) = last_output.lock() {
+            *at = Instant::now();
+        }
+        line.extend_from_slice(&buffer[..n]);
+        if handler.is_none() {
+            let text = String::from_utf8_lossy(&line[forwarded..]);
+            let _ = stdout.write_all(redactor.redact(&text).as_bytes());
+            let _ = stdout.flush();
+        }
+        while let Some(end) = line.iter().position(|&byte| byte == b'\n') {
+            let text = String::from_utf8_lossy(&line[..end]);
+            let text = redactor.redact(text.trim_end_matches('\r'));
+            if let Some(handler) = &handler {
+                handler(Stream::Stdout, &text);
+            }
+            collected.push_str(&screen_line(&text));
+            collected.push('\n');
+            line.drain(..=end);
+        }
+        forwarded = line.len();
+    }
+
+    if !line.is_empty() {
+        let text = String::from_utf8_lossy(&line);
+        let text = redactor.redact(&text);
+        if let Some(handler) = &handler {
+            handler(Stream::Stdout, &text);
+        }
+        collected.push_str(&screen_line(&text));
+    }
+    collected
+}
+
+/// Returns what `line` leaves on the screen: the text after its last
+/// carriage return, without escape sequences.
+fn screen_line(line: &str) -> String {
+    let last = line.rsplit('\r').next().unwrap_or_default();
+    let mut text = String::with_capacity(last.len());
+    let mut chars = last.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters up to a final byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: up to BEL or ST
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next().is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_in_pty() {
+        assert_eq!(
+            screen_line("\r[1/2] Building C\x1b[K\r\x1b[1m[2/2] Linking C\x1b[0m\x1b[K"),
+            "[2/2] Linking C"
+        );
+        assert_eq!(
+            screen_line("\x1b]8;;file:///a.c\x1b\\a.c\x1b]8;;\x1b\\:1: warning"),
+            "a.c:1: warning"
+        );
+
+        if !cfg!(unix) {
+            return;
+        }
+        let mut command = Command::new("sh");
+        command.args([
+            "-c",
+            "test -t 1 && echo \"tty $GREETING\"; printf '\\033[31merror\\033[0m: x\\n' >&2; exit 3",
+        ]);
+        command.env("GREETING", "hello");
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&lines);
+        let handler: LineHandler = Arc::new(move |stream: Stream, line: &str| {
+            seen.lock().unwrap().push((stream, line.to_string()));
+        });
+
+        let output = run(&command, None, None, Some(&handler), &Redactor::default()).unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(output.stdout, "tty hello\nerror: x\n");
+        assert_eq!(
+            lines.lock().unwrap()[1],
+            (Stream::Stdout, "\x1b[31merror\x1b[0m: x".to_string())
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! Why a build tree is configured or rebuilt again.
+//!
+//! An "incremental" CI build that keeps configuring from scratch is hard to
+//! debug from the build log alone. [`Cmd::explain_rebuild`](crate::Cmd::explain_rebuild)
+//! compares the builder with the build tree and its
+//! [state file](crate::state) and lists the [`RebuildReason`]s the next run
+//! would configure for, without running anything:
+//!
+//! | Reason | Detected from |
+//! |--------|---------------|
+//! | [`CacheMissing`](RebuildReason::CacheMissing) | no `CMakeCache.txt` in the build directory |
+//! | [`Forced`](RebuildReason::Forced) | [`Cmd::fresh`](crate::Cmd::fresh) |
+//! | [`GeneratorMismatch`](RebuildReason::GeneratorMismatch) | `CMAKE_GENERATOR` in the cache |
+//! | [`DefineChanged`](RebuildReason::DefineChanged), [`PresetChanged`](RebuildReason::PresetChanged), [`ArgsChanged`](RebuildReason::ArgsChanged), [`CMakeVersionChanged`](RebuildReason::CMakeVersionChanged) | the configuration of the last run in the state file |
+//! | [`LastRunFailed`](RebuildReason::LastRunFailed) | the history in the state file |
+//! | [`PresetFileNewer`](RebuildReason::PresetFileNewer), [`ProjectFileNewer`](RebuildReason::ProjectFileNewer) | modification times against the cache |
+//! | [`AlwaysConfigures`](RebuildReason::AlwaysConfigures) | [`Cmd::skip_configure_if_unchanged`](crate::Cmd::skip_configure_if_unchanged) not enabled |
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::Cmd;
+//!
+//! let cmd = Cmd::default().set_path("./native").skip_configure_if_unchanged();
+//! for reason in cmd.explain_rebuild().unwrap() {
+//!     println!("reconfigure: {}", reason);
+//! }
+//! ```
+
+use std::{fmt, path::PathBuf};
+
+/// A reason for the next run to configure the build tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RebuildReason {
+    /// The build directory has no cache, so the project is configured and
+    /// built from scratch.
+    CacheMissing,
+    /// A [fresh](crate::Cmd::fresh) configure was requested.
+    Forced,
+    /// The tree was configured with another generator, which cmake refuses
+    /// to switch without a fresh configure.
+    GeneratorMismatch {
+        configured: String,
+        requested: String,
+    },
+    /// A definition differs from the last run. `before` is `None` for a new
+    /// definition, `after` is `None` for a removed one.
+    DefineChanged {
+        name: String,
+        before: Option<String>,
+        after: Option<String>,
+    },
+    /// The configure preset differs from the last run.
+    PresetChanged {
+        before: Option<String>,
+        after: Option<String>,
+    },
+    /// The extra cmake arguments differ from the last run.
+    ArgsChanged,
+    /// The cmake executable has another version than in the last run.
+    CMakeVersionChanged { before: String, after: String },
+    /// The last run failed, so its configure may not have completed.
+    LastRunFailed,
+    /// A presets file changed since the last configure.
+    PresetFileNewer(PathBuf),
+    /// A `CMakeLists.txt` or `*.cmake` file of the project changed since the
+    /// last configure; the native build tool re-runs cmake for it as well.
+    ProjectFileNewer(PathBuf),
+    /// Configure runs on every build, as
+    /// [`skip_configure_if_unchanged`](crate::Cmd::skip_configure_if_unchanged)
+    /// is not enabled.
+    AlwaysConfigures,
+}
+
+impl fmt::Display for RebuildReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |value: &Option<String>| match value {
+            Some(value) => format!("{:?}", value),
+            None => "unset".to_string(),
+        };
+        match self {
+            RebuildReason::CacheMissing => write!(f, "the build tree has no CMakeCache.txt"),
+            RebuildReason::Forced => write!(f, "a fresh configure was requested"),
+            RebuildReason::GeneratorMismatch {
+                configured,
+                requested,
+            } => write!(
+                f,
+                "the tree was configured with {:?}, not {:?}",
+                configured, requested
+            ),
+            RebuildReason::DefineChanged {
+                name,
+                before,
+                after,
+            } => write!(
+                f,
+                "{} changed from {} to {}",
+                name,
+                value(before),
+                value(after)
+            ),
+            RebuildReason::PresetChanged { before, after } => write!(
+                f,
+                "the preset changed from {} to {}",
+                value(before),
+                value(after)
+            ),
+            RebuildReason::ArgsChanged => write!(f, "the cmake arguments changed"),
+            RebuildReason::CMakeVersionChanged { before, after } => {
+                write!(f, "cmake changed from {} to {}", before, after)
+            }
+            RebuildReason::LastRunFailed => write!(f, "the last run failed"),
+            RebuildReason::PresetFileNewer(path) => {
+                write!(f, "{} changed since the last configure", path.display())
+            }
+            RebuildReason::ProjectFileNewer(path) => {
+                write!(f, "{} changed since the last configure", path.display())
+            }
+            RebuildReason::AlwaysConfigures => write!(
+                f,
+                "configure runs on every build without skip_configure_if_unchanged"
+            ),
+        }
+    }
+}
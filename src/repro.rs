@@ -0,0 +1,137 @@
+//! Reproduction scripts for failed builds.
+//!
+//! With [`Cmd::write_repro_script`](crate::Cmd::write_repro_script) enabled,
+//! a failed run writes `repro.sh` and `repro.ps1` into the build directory.
+//! Both scripts set the build-relevant environment variables, change to the
+//! directory cmakr ran in, and run the exact configure and build commands, so
+//! the failure can be reproduced without cmakr or Rust installed.
+
+use std::path::{Path, PathBuf};
+
+use crate::command::{self, PreparedCommand};
+
+/// Environment variables that influence how CMake and the compilers behave.
+const ENV_VARS: [&str; 8] = [
+    "CC",
+    "CXX",
+    "AR",
+    "CFLAGS",
+    "CXXFLAGS",
+    "CPPFLAGS",
+    "LDFLAGS",
+    "PKG_CONFIG_PATH",
+];
+
+/// Collects the values of the build-relevant environment variables that are set.
+///
+/// Besides [`ENV_VARS`], every variable starting with `CMAKE_` is included.
+fn build_env() -> Vec<(String, String)> {
+    let mut env: Vec<(String, String)> = std::env::vars()
+        .filter(|(name, _)| ENV_VARS.contains(&name.as_str()) || name.starts_with("CMAKE_"))
+        .collect();
+    env.sort();
+    env
+}
+
+/// Renders the POSIX shell script.
+fn render_posix(cwd: &Path, env: &[(String, String)], commands: &[&PreparedCommand]) -> String {
+    let mut script = String::from("#!/bin/sh\n# Reproduces a failed cmakr build.\nset -e\n\n");
+    for (name, value) in env {
+        script.push_str(&format!(
+            "export {}={}\n",
+            name,
+            command::quote_posix(value)
+        ));
+    }
+    script.push_str(&format!(
+        "cd {}\n\n",
+        command::quote_posix(&cwd.to_string_lossy())
+    ));
+    for command in commands {
+        script.push_str(&command.to_shell(command::Shell::Posix));
+        script.push('\n');
+    }
+    script
+}
+
+/// Renders the PowerShell script.
+fn render_powershell(
+    cwd: &Path,
+    env: &[(String, String)],
+    commands: &[&PreparedCommand],
+) -> String {
+    let mut script =
+        String::from("# Reproduces a failed cmakr build.\n$ErrorActionPreference = 'Stop'\n\n");
+    for (name, value) in env {
+        script.push_str(&format!(
+            "$env:{} = {}\n",
+            name,
+            command::quote_powershell(value)
+        ));
+    }
+    script.push_str(&format!(
+        "Set-Location -LiteralPath {}\n\n",
+        command::quote_powershell(&cwd.to_string_lossy())
+    ));
+    for command in commands {
+        script.push_str(&command.to_shell(command::Shell::PowerShell));
+        script.push_str("\nif ($LASTEXITCODE -ne 0) { exit $LASTEXITCODE }\n");
+    }
+    script
+}
+
+/// Writes `repro.sh` and `repro.ps1` running `commands` into `binary_dir`.
+///
+/// Returns the path of the POSIX script.
+pub(crate) fn write(binary_dir: &Path, commands: &[&PreparedCommand]) -> std::io::Result<PathBuf> {
+    let cwd = std::env::current_dir()?;
+    let env = build_env();
+
+    let sh = binary_dir.join("repro.sh");
+    std::fs::write(&sh, render_posix(&cwd, &env, commands))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&sh, std::fs::Permissions::from_mode(0o755))?;
+    }
+    std::fs::write(
+        binary_dir.join("repro.ps1"),
+        render_powershell(&cwd, &env, commands),
+    )?;
+
+    Ok(sh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_repro_scripts() {
+        let configure = PreparedCommand {
+            program: "cmake".to_string(),
+            args: vec!["-S".to_string(), "my project".to_string()],
+            ..PreparedCommand::default()
+        };
+        let build = PreparedCommand {
+            program: "cmake".to_string(),
+            args: vec!["--build".to_string(), "build".to_string()],
+            ..PreparedCommand::default()
+        };
+        let env = vec![("CC".to_string(), "clang -m32".to_string())];
+        let cwd = Path::new("/work");
+
+        assert_eq!(
+            render_posix(cwd, &env, &[&configure, &build]),
+            "#!/bin/sh\n# Reproduces a failed cmakr build.\nset -e\n\n\
+             export CC='clang -m32'\ncd /work\n\n\
+             cmake -S 'my project'\ncmake --build build\n"
+        );
+        assert!(
+            render_powershell(cwd, &env, &[&configure, &build]).contains(
+                "$env:CC = 'clang -m32'\nSet-Location -LiteralPath /work\n\n\
+                 cmake -S 'my project'\nif ($LASTEXITCODE -ne 0) { exit $LASTEXITCODE }\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Retrying configure and build steps that failed for transient reasons.
+//!
+//! A `FetchContent` download or a `find_package` that reaches the network
+//! fails every now and then in CI for reasons that have nothing to do with
+//! the project: a DNS hiccup, a reset connection, a server error. With a
+//! [`RetryPolicy`] set through [`Cmd::retry`](crate::Cmd::retry), a failed
+//! configure (and optionally build) whose output has one of the policy's
+//! markers is run again after a backoff. Every retry is recorded as a
+//! [`Retry`] in [`BuildReport::retries`](crate::report::BuildReport::retries),
+//! and the phases of the failed attempts stay in
+//! [`BuildReport::phases`](crate::report::BuildReport::phases), so flaky
+//! steps show up in the result instead of disappearing.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use cmakr::{Cmd, retry::RetryPolicy};
+//!
+//! let report = Cmd::default()
+//!     .set_path("./native")
+//!     .retry(RetryPolicy::default().set_attempts(4).set_backoff(Duration::from_secs(10)))
+//!     .build()
+//!     .unwrap();
+//! for retry in &report.retries {
+//!     println!("retried {} after: {}", retry.phase, retry.reason);
+//! }
+//! ```
+
+use std::time::Duration;
+
+use crate::report::Phase;
+
+/// Output of transient network failures, from cmake's downloads, git, and
+/// curl.
+const NETWORK_ERRORS: [&str; 12] = [
+    "Could not resolve host",
+    "Temporary failure in name resolution",
+    "Connection timed out",
+    "Connection reset by peer",
+    "Connection refused",
+    "Timeout was reached",
+    "Operation timed out",
+    "SSL connect error",
+    "HTTP response code said error",
+    "The requested URL returned error: 5",
+    "early EOF",
+    "RPC failed",
+];
+
+/// When and how often failed steps are run again.
+///
+/// By default, a configure is attempted at most 3 times, retries wait 5
+/// seconds and then twice as long as before, and only failures whose output
+/// has one of the built-in markers of network errors (e.g. `Could not
+/// resolve host`, `Connection reset by peer`) are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    attempts: usize,
+    backoff: Duration,
+    retry_on: Vec<String>,
+    build: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_secs(5),
+            retry_on: NETWORK_ERRORS
+                .iter()
+                .map(|marker| marker.to_string())
+                .collect(),
+            build: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Sets the number of attempts, including the first one.
+    pub fn set_attempts(mut self, attempts: usize) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Sets how long to wait before the first retry. Every further retry
+    /// waits twice as long as the one before.
+    pub fn set_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Also retries failures whose output contains `marker`.
+    ///
+    /// # Arguments
+    ///
+    /// * `marker` - Text of an output line (e.g. a project's own download
+    ///   error message).
+    pub fn retry_on<T>(mut self, marker: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.retry_on.push(marker.into());
+        self
+    }
+
+    /// Retries only failures whose output contains one of `markers`,
+    /// replacing the built-in markers. An empty marker retries every
+    /// failure.
+    pub fn set_retry_on<I, T>(mut self, markers: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.retry_on = markers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets whether failed build steps are retried as well, not only
+    /// configure.
+    pub fn retry_build(mut self, build: bool) -> Self {
+        self.build = build;
+        self
+    }
+
+    /// Returns the retry after a failed `phase` with `output`, if the
+    /// policy retries it after `retried` retries.
+    pub(crate) fn check(&self, phase: Phase, output: &str, retried: usize) -> Option<Retry> {
+        let retried_phase = phase == Phase::Configure || (self.build && phase == Phase::Build);
+        if !retried_phase || retried + 1 >= self.attempts {
+            return None;
+        }
+        let reason = output.lines().find(|line| {
+            self.retry_on
+                .iter()
+                .any(|marker| line.contains(marker.as_str()))
+        })?;
+        let factor = 1u32.checked_shl(retried as u32).unwrap_or(u32::MAX);
+        Some(Retry {
+            phase,
+            attempt: retried + 2,
+            reason: reason.trim().to_string(),
+            delay: self.backoff.saturating_mul(factor),
+        })
+    }
+}
+
+/// A step run again after a transient failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Retry {
+    /// The phase that failed.
+    pub phase: Phase,
+    /// The number of the attempt that followed, 2 for the first retry.
+    pub attempt: usize,
+    /// The output line that made the failure transient.
+    pub reason: String,
+    /// How long the retry waited.
+    pub delay: Duration,
+}
@@ -0,0 +1,268 @@
+//! Sanitizer and link-time optimization flags.
+//!
+//! [`Cmd::enable_sanitizer`](crate::Cmd::enable_sanitizer) and
+//! [`Cmd::enable_lto`](crate::Cmd::enable_lto) add the flags of the
+//! compiler of the build tree to `CMAKE_C_FLAGS`, `CMAKE_CXX_FLAGS`, and the
+//! linker flags, after the user's own values of these variables:
+//!
+//! | Compiler | Sanitizers | LTO |
+//! |----------|------------|-----|
+//! | GCC, Clang | `-fsanitize=<list>`, plus `-fno-omit-frame-pointer` for ASan and TSan, when compiling and linking | `-flto` when compiling and linking |
+//! | MSVC | `/fsanitize=address` when compiling; other sanitizers are not supported | `/GL` when compiling, `/LTCG` when linking |
+//!
+//! The compiler is the one recorded in the cache of a configured tree.
+//! Before the first configure, MSVC is assumed for Visual Studio
+//! generators, for `CC`/`CXX` set to `cl` or `clang-cl`, and on Windows when
+//! `cl` is on `PATH` and neither is set nor a MinGW, MSYS, or Unix
+//! Makefiles generator selected.
+//!
+//! MSVC's default debug flags include `/RTC1`, which ASan doesn't support;
+//! define `CMAKE_C_FLAGS_DEBUG` and `CMAKE_CXX_FLAGS_DEBUG` without it for
+//! debug builds.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, sanitizer::Sanitizer};
+//!
+//! Cmd::default()
+//!     .set_path("./fuzz")
+//!     .set_build_type("RelWithDebInfo")
+//!     .enable_sanitizer(Sanitizer::Address)
+//!     .enable_sanitizer(Sanitizer::Undefined)
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cmake;
+
+/// The compiler flags variables the flags are added to.
+const COMPILE_FLAGS: [&str; 2] = ["CMAKE_C_FLAGS", "CMAKE_CXX_FLAGS"];
+
+/// The linker flags variables of linked binaries.
+const LINK_FLAGS: [&str; 3] = [
+    "CMAKE_EXE_LINKER_FLAGS",
+    "CMAKE_SHARED_LINKER_FLAGS",
+    "CMAKE_MODULE_LINKER_FLAGS",
+];
+
+/// A runtime error detector built into the compiled code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sanitizer {
+    /// AddressSanitizer: out-of-bounds accesses, use after free, leaks.
+    Address,
+    /// UndefinedBehaviorSanitizer: overflows, misaligned pointers, and other
+    /// undefined behavior.
+    Undefined,
+    /// ThreadSanitizer: data races. Can't be combined with
+    /// [`Address`](Sanitizer::Address).
+    Thread,
+}
+
+impl Sanitizer {
+    /// Returns the name in `-fsanitize=` (e.g. `"address"`).
+    pub fn name(self) -> &'static str {
+        match self {
+            Sanitizer::Address => "address",
+            Sanitizer::Undefined => "undefined",
+            Sanitizer::Thread => "thread",
+        }
+    }
+}
+
+/// The command line syntax of a compiler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Syntax {
+    /// GCC and Clang.
+    Gnu,
+    /// MSVC and `clang-cl`.
+    Msvc,
+}
+
+impl Syntax {
+    /// Returns the syntax of the compiler of the tree at `binary_dir`, or of
+    /// the compiler `generator` is expected to find.
+    fn detect(binary_dir: &Path, generator: Option<&str>) -> Self {
+        let configured = ["CMAKE_CXX_COMPILER_ID", "CMAKE_C_COMPILER_ID"]
+            .iter()
+            .filter_map(|name| cmake::read_cache_value(binary_dir, name))
+            .find(|id| !id.is_empty());
+        if let Some(id) = configured {
+            let simulated = cmake::read_cache_value(binary_dir, "CMAKE_CXX_SIMULATE_ID");
+            return match (id.as_str(), simulated.as_deref()) {
+                ("MSVC", _) | (_, Some("MSVC")) => Syntax::Msvc,
+                _ => Syntax::Gnu,
+            };
+        }
+
+        let generator = generator.unwrap_or_default();
+        if generator.starts_with("Visual Studio") {
+            return Syntax::Msvc;
+        }
+        let compiler = std::env::var("CXX").or_else(|_| std::env::var("CC"));
+        if let Ok(compiler) = compiler {
+            let stem = Path::new(compiler.split_whitespace().next().unwrap_or_default())
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_lowercase());
+            return match stem.as_deref() {
+                Some("cl" | "clang-cl") => Syntax::Msvc,
+                _ => Syntax::Gnu,
+            };
+        }
+        let gnu_generator = ["MinGW", "MSYS", "Unix"]
+            .iter()
+            .any(|name| generator.contains(name));
+        if cfg!(windows) && !gnu_generator && which::which("cl").is_ok() {
+            Syntax::Msvc
+        } else {
+            Syntax::Gnu
+        }
+    }
+}
+
+/// Returns the flags of `sanitizers` and link-time optimization for the
+/// compiler of the tree at `binary_dir`, as the variables they are added
+/// to and the flags.
+///
+/// # Errors
+///
+/// Returns a message if the sanitizers can't be combined or the compiler
+/// doesn't support one of them.
+pub(crate) fn flags(
+    sanitizers: &[Sanitizer],
+    lto: bool,
+    binary_dir: &Path,
+    generator: Option<&str>,
+) -> Result<Vec<(&'static str, String)>, String> {
+    if sanitizers.is_empty() && !lto {
+        return Ok(Vec::new());
+    }
+    if sanitizers.contains(&Sanitizer::Address) && sanitizers.contains(&Sanitizer::Thread) {
+        return Err("the address and thread sanitizers can't be combined".to_string());
+    }
+    let syntax = Syntax::detect(binary_dir, generator);
+
+    let mut compile = Vec::new();
+    let mut link = Vec::new();
+    match syntax {
+        Syntax::Gnu => {
+            if !sanitizers.is_empty() {
+                let names: Vec<&str> = sanitizers.iter().map(|s| s.name()).collect();
+                let sanitize = format!("-fsanitize={}", names.join(","));
+                compile.push(sanitize.clone());
+                link.push(sanitize);
+                if sanitizers.iter().any(|s| *s != Sanitizer::Undefined) {
+                    compile.push("-fno-omit-frame-pointer".to_string());
+                }
+            }
+            if lto {
+                compile.push("-flto".to_string());
+                link.push("-flto".to_string());
+            }
+        }
+        Syntax::Msvc => {
+            if let Some(unsupported) = sanitizers.iter().find(|s| **s != Sanitizer::Address) {
+                return Err(format!(
+                    "MSVC does not support the {} sanitizer",
+                    unsupported.name()
+                ));
+            }
+            if !sanitizers.is_empty() {
+                compile.push("/fsanitize=address".to_string());
+            }
+            if lto {
+                compile.push("/GL".to_string());
+                link.push("/LTCG".to_string());
+            }
+        }
+    }
+
+    let mut flags: Vec<(&'static str, String)> = Vec::new();
+    for (names, values) in [(&COMPILE_FLAGS[..], &compile), (&LINK_FLAGS[..], &link)] {
+        if !values.is_empty() {
+            flags.extend(names.iter().map(|name| (*name, values.join(" "))));
+        }
+    }
+    // MSVC's librarian warns about /GL objects unless told about them
+    if lto && syntax == Syntax::Msvc {
+        flags.push(("CMAKE_STATIC_LINKER_FLAGS", "/LTCG".to_string()));
+    }
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitizer_flags() {
+        let temp = crate::guard::TempBuildDir::new("sanitizer").unwrap();
+        std::fs::write(
+            temp.path().join("CMakeCache.txt"),
+            "CMAKE_CXX_COMPILER_ID:STRING=GNU\n",
+        )
+        .unwrap();
+        let flags = flags(
+            &[Sanitizer::Address, Sanitizer::Undefined],
+            true,
+            temp.path(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            flags[0],
+            (
+                "CMAKE_C_FLAGS",
+                "-fsanitize=address,undefined -fno-omit-frame-pointer -flto".to_string()
+            )
+        );
+        assert_eq!(
+            flags[2],
+            (
+                "CMAKE_EXE_LINKER_FLAGS",
+                "-fsanitize=address,undefined -flto".to_string()
+            )
+        );
+        assert!(
+            super::flags(
+                &[Sanitizer::Address, Sanitizer::Thread],
+                false,
+                temp.path(),
+                None
+            )
+            .is_err()
+        );
+
+        let settings = crate::Cmd::default()
+            .set_binary_path(temp.path().to_string_lossy())
+            .add_define("CMAKE_C_FLAGS", "-O1")
+            .enable_sanitizer(Sanitizer::Address)
+            .effective_settings()
+            .unwrap();
+        assert!(settings.defines.contains(&(
+            "CMAKE_C_FLAGS".to_string(),
+            "-O1 -fsanitize=address -fno-omit-frame-pointer".to_string()
+        )));
+
+        std::fs::write(
+            temp.path().join("CMakeCache.txt"),
+            "CMAKE_CXX_COMPILER_ID:STRING=MSVC\n",
+        )
+        .unwrap();
+        let flags = super::flags(&[Sanitizer::Address], true, temp.path(), None).unwrap();
+        assert_eq!(
+            flags[0],
+            ("CMAKE_C_FLAGS", "/fsanitize=address /GL".to_string())
+        );
+        assert_eq!(
+            flags.last().unwrap(),
+            &("CMAKE_STATIC_LINKER_FLAGS", "/LTCG".to_string())
+        );
+        assert!(super::flags(&[Sanitizer::Thread], false, temp.path(), None).is_err());
+    }
+}
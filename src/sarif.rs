@@ -0,0 +1,298 @@
+//! SARIF export of compiler diagnostics.
+//!
+//! [`BuildReport::write_sarif`](crate::report::BuildReport::write_sarif)
+//! writes the parsed diagnostics as a [SARIF 2.1.0] log for code-scanning
+//! tools.
+//!
+//! Rule IDs name the compiler of the build tree and the diagnostic's code
+//! when the compiler prints one (`msvc/C4101`, `gcc/-Wunused-variable`,
+//! `clang/-Wshadow`), and otherwise the message with its quoted names and
+//! numbers left out (`gcc/undeclared-first-use-in-this-function`), so the
+//! same problem keeps its rule across builds. Locations inside the source
+//! directory are reported relative to the `SRCROOT` base URI, so results
+//! map to repository files regardless of where the build ran.
+//!
+//! [SARIF 2.1.0]: https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html
+
+use std::path::Path;
+
+use serde_json::{Value, json};
+
+use crate::{
+    cmake,
+    diagnostics::{Diagnostic, Severity},
+    report::BuildReport,
+};
+
+/// The base URI ID under which source-relative locations are reported.
+const SOURCE_ROOT_ID: &str = "SRCROOT";
+
+/// Builds the SARIF log for the diagnostics in `report`.
+pub(crate) fn to_sarif(report: &BuildReport) -> Value {
+    let compiler = ["CMAKE_CXX_COMPILER_ID", "CMAKE_C_COMPILER_ID"]
+        .iter()
+        .find_map(|name| cmake::read_cache_value(&report.binary_dir, name));
+    let mut rules: Vec<String> = Vec::new();
+    let mut results = Vec::new();
+
+    for diagnostic in &report.diagnostics {
+        let rule_id = rule_id(compiler.as_deref(), diagnostic);
+        let rule_index = match rules.iter().position(|r| *r == rule_id) {
+            Some(index) => index,
+            None => {
+                rules.push(rule_id.clone());
+                rules.len() - 1
+            }
+        };
+
+        let file = report.resolve_path(diagnostic);
+        let artifact_location = match file.strip_prefix(&report.source_dir) {
+            Ok(relative) => json!({
+                "uri": path_to_uri(relative),
+                "uriBaseId": SOURCE_ROOT_ID,
+            }),
+            Err(_) => json!({ "uri": file_uri(&file) }),
+        };
+
+        let mut region = serde_json::Map::new();
+        if let Some(line) = diagnostic.line {
+            region.insert("startLine".to_string(), json!(line));
+        }
+        if let Some(column) = diagnostic.column {
+            region.insert("startColumn".to_string(), json!(column));
+        }
+
+        let mut physical_location = json!({ "artifactLocation": artifact_location });
+        if !region.is_empty() {
+            physical_location["region"] = Value::Object(region);
+        }
+
+        results.push(json!({
+            "ruleId": rule_id,
+            "ruleIndex": rule_index,
+            "level": level(diagnostic.severity),
+            "message": { "text": diagnostic.message },
+            "locations": [{ "physicalLocation": physical_location }],
+        }));
+    }
+
+    let rules: Vec<Value> = rules.into_iter().map(|id| json!({ "id": id })).collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "cmakr",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": rules,
+                }
+            },
+            "originalUriBaseIds": {
+                SOURCE_ROOT_ID: { "uri": format!("{}/", file_uri(&report.source_dir)) }
+            },
+            "results": results,
+        }]
+    })
+}
+
+/// Returns the stable rule ID of a diagnostic reported by the compiler with
+/// the CMake compiler ID `compiler`, if known.
+fn rule_id(compiler: Option<&str>, diagnostic: &Diagnostic) -> String {
+    let msvc_code = |code: &str| {
+        let digits = code.trim_start_matches(|c: char| c.is_ascii_uppercase());
+        digits.len() < code.len()
+            && !digits.is_empty()
+            && digits.bytes().all(|b| b.is_ascii_digit())
+    };
+    let compiler = match compiler {
+        Some("MSVC") => "msvc",
+        Some("Clang" | "AppleClang") => "clang",
+        Some("GNU") => "gcc",
+        Some(other) => return format!("{}/{}", other.to_lowercase(), problem(diagnostic)),
+        // without a build tree to ask, the code tells MSVC from GCC-style compilers
+        None if diagnostic.code.as_deref().is_some_and(msvc_code) => "msvc",
+        None => "gcc",
+    };
+    format!("{}/{}", compiler, problem(diagnostic))
+}
+
+/// Returns what identifies the problem of a diagnostic among those of its
+/// compiler: its code, or its message without quoted names and numbers.
+fn problem(diagnostic: &Diagnostic) -> String {
+    if let Some(code) = &diagnostic.code {
+        return code.clone();
+    }
+    let mut words: Vec<String> = Vec::new();
+    let mut quoted = None;
+    for word in diagnostic
+        .message
+        .split(|c: char| c.is_whitespace() || "()[]{},;.".contains(c))
+    {
+        // names are quoted with ASCII or typographic quotes, possibly over several words
+        if let Some(close) = quoted {
+            if word.ends_with(close) {
+                quoted = None;
+            }
+            continue;
+        }
+        let open = word.chars().next();
+        let close = match open {
+            Some('\'') => Some('\''),
+            Some('"') => Some('"'),
+            Some('\u{2018}') => Some('\u{2019}'),
+            Some('`') => Some('\''),
+            _ => None,
+        };
+        if let Some(close) = close {
+            if word.chars().count() == 1 || !word.ends_with(close) {
+                quoted = Some(close);
+            }
+            continue;
+        }
+        let word: String = word
+            .chars()
+            .filter(char::is_ascii_alphabetic)
+            .collect::<String>()
+            .to_lowercase();
+        if !word.is_empty() {
+            words.push(word);
+        }
+    }
+    match words.is_empty() {
+        true => level(diagnostic.severity).to_string(),
+        false => words.join("-"),
+    }
+}
+
+/// Maps a severity to a SARIF result level.
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+    }
+}
+
+/// Converts an absolute path to a `file://` URI.
+fn file_uri(path: &Path) -> String {
+    let uri = path_to_uri(path);
+    let uri = uri.strip_prefix("//%3F/").unwrap_or(&uri);
+    match uri.starts_with('/') {
+        true => format!("file://{}", uri),
+        false => format!("file:///{}", uri),
+    }
+}
+
+/// Converts a path to a URI reference with `/` separators, percent-encoding
+/// everything but unreserved characters, separators, and drive colons.
+fn path_to_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::with_capacity(path.len());
+
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn sarif_locations_and_rules() {
+        let report = BuildReport {
+            diagnostics: vec![
+                Diagnostic {
+                    file: PathBuf::from("/work/my project/src/func.c"),
+                    line: Some(3),
+                    column: Some(10),
+                    severity: Severity::Warning,
+                    code: Some("-Wunused-variable".to_string()),
+                    message: "unused variable 'y'".to_string(),
+                },
+                Diagnostic {
+                    file: PathBuf::from("/usr/include/stdio.h"),
+                    line: None,
+                    column: None,
+                    severity: Severity::Error,
+                    code: None,
+                    message: "broken".to_string(),
+                },
+            ],
+            source_dir: PathBuf::from("/work/my project"),
+            binary_dir: PathBuf::from("/work/my project/build"),
+            ..Default::default()
+        };
+
+        let log = to_sarif(&report);
+        let run = &log["runs"][0];
+        assert_eq!(
+            run["originalUriBaseIds"]["SRCROOT"]["uri"],
+            "file:///work/my%20project/"
+        );
+        assert_eq!(
+            run["tool"]["driver"]["rules"][0]["id"],
+            "gcc/-Wunused-variable"
+        );
+        assert_eq!(run["tool"]["driver"]["rules"][1]["id"], "gcc/broken");
+
+        let first = &run["results"][0]["locations"][0]["physicalLocation"];
+        assert_eq!(first["artifactLocation"]["uri"], "src/func.c");
+        assert_eq!(first["artifactLocation"]["uriBaseId"], "SRCROOT");
+        assert_eq!(first["region"]["startLine"], 3);
+
+        let second = &run["results"][1]["locations"][0]["physicalLocation"];
+        assert_eq!(
+            second["artifactLocation"]["uri"],
+            "file:///usr/include/stdio.h"
+        );
+        assert!(second.get("region").is_none());
+    }
+
+    #[test]
+    fn stable_rule_ids() {
+        let diagnostic = |code: Option<&str>, message: &str| Diagnostic {
+            file: PathBuf::from("/work/src/func.c"),
+            line: Some(1),
+            column: None,
+            severity: Severity::Error,
+            code: code.map(str::to_string),
+            message: message.to_string(),
+        };
+
+        let deprecated = diagnostic(Some("C4996"), "'strcpy': This function may be unsafe.");
+        assert_eq!(rule_id(None, &deprecated), "msvc/C4996");
+        assert_eq!(rule_id(Some("MSVC"), &deprecated), "msvc/C4996");
+        let shadow = diagnostic(Some("-Wshadow"), "declaration shadows a local variable");
+        assert_eq!(rule_id(Some("AppleClang"), &shadow), "clang/-Wshadow");
+
+        // the same problem with other names has the same rule
+        let undeclared = diagnostic(None, "'x' undeclared (first use in this function)");
+        let other = diagnostic(
+            None,
+            "\u{2018}count\u{2019} undeclared (first use in this function)",
+        );
+        assert_eq!(
+            rule_id(Some("GNU"), &undeclared),
+            "gcc/undeclared-first-use-in-this-function"
+        );
+        assert_eq!(
+            rule_id(Some("GNU"), &other),
+            rule_id(Some("GNU"), &undeclared)
+        );
+        let spaced = diagnostic(None, "no member named 'a b' in 'struct s'");
+        assert_eq!(rule_id(Some("Clang"), &spaced), "clang/no-member-named-in");
+    }
+}
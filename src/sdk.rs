@@ -0,0 +1,133 @@
+//! Capturing the environment of a Yocto or Buildroot SDK.
+//!
+//! Cross-compilation SDKs ship an `environment-setup-*` script that exports
+//! the compilers, flags, sysroot, and `PATH` of the target toolchain into an
+//! interactive shell. To use it from a non-shell process, the script is
+//! sourced in a `bash` (or `sh`) child, and the exported variables are
+//! compared before and after: every variable it sets or changes, and every
+//! variable it unsets, is recorded and later applied to cmake's process.
+
+use std::{collections::BTreeMap, io, path::Path, process::Command};
+
+/// Separates the environment before the script from the one after it.
+const MARKER: &str = "--cmakr-sdk-environment--";
+
+/// Variables the shell itself maintains, which say nothing about the SDK.
+const SHELL_VARIABLES: [&str; 4] = ["_", "SHLVL", "PWD", "OLDPWD"];
+
+/// Sources `script` and returns the variables it changes, sorted by name,
+/// with `None` for variables it unsets.
+///
+/// # Errors
+///
+/// Returns an error on Windows, if no shell can be run, or if sourcing the
+/// script fails.
+pub(crate) fn capture(script: &Path) -> io::Result<Vec<(String, Option<String>)>> {
+    if cfg!(windows) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "SDK environment scripts need a POSIX shell",
+        ));
+    }
+    let script = std::path::absolute(script)?;
+    let shell = if which::which("bash").is_ok() {
+        "bash"
+    } else {
+        "sh"
+    };
+    // the script's own output would be mixed into the environment dumps
+    let output = Command::new(shell)
+        .arg("-c")
+        .arg(format!(
+            "env -0; printf '%s\\0' '{}'; . \"$1\" >/dev/null 2>&1 || exit $?; env -0",
+            MARKER
+        ))
+        .arg(shell)
+        .arg(&script)
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "sourcing {} failed: {}",
+            script.display(),
+            output.status
+        )));
+    }
+    Ok(diff(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Compares the NUL-separated environment dumps before and after [`MARKER`].
+fn diff(dumps: &str) -> Vec<(String, Option<String>)> {
+    let mut before = BTreeMap::new();
+    let mut after = BTreeMap::new();
+    let mut current = &mut before;
+    for entry in dumps.split('\0') {
+        if entry == MARKER {
+            current = &mut after;
+        } else if let Some((name, value)) = entry.split_once('=')
+            && !SHELL_VARIABLES.contains(&name)
+        {
+            current.insert(name, value);
+        }
+    }
+
+    let mut changes: Vec<(String, Option<String>)> = after
+        .iter()
+        .filter(|(name, value)| before.get(*name) != Some(*value))
+        .map(|(name, value)| (name.to_string(), Some(value.to_string())))
+        .collect();
+    changes.extend(
+        before
+            .keys()
+            .filter(|name| !after.contains_key(*name))
+            .map(|name| (name.to_string(), None)),
+    );
+    changes.sort();
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_environment_dumps() {
+        let dumps = format!(
+            "PATH=/usr/bin\0HOME=/root\0PYTHONHOME=/usr\0SHLVL=1\0{}\0PATH=/opt/sdk/bin:/usr/bin\0HOME=/root\0CC=aarch64-poky-linux-gcc --sysroot=/opt/sdk\0SHLVL=2\0",
+            MARKER
+        );
+        assert_eq!(
+            diff(&dumps),
+            vec![
+                (
+                    "CC".to_string(),
+                    Some("aarch64-poky-linux-gcc --sysroot=/opt/sdk".to_string())
+                ),
+                (
+                    "PATH".to_string(),
+                    Some("/opt/sdk/bin:/usr/bin".to_string())
+                ),
+                ("PYTHONHOME".to_string(), None),
+            ]
+        );
+
+        if cfg!(unix) {
+            let temp = crate::guard::TempBuildDir::new("sdk").unwrap();
+            let script = temp.path().join("environment-setup-aarch64");
+            std::fs::write(
+                &script,
+                "echo setting up\nexport OECORE_TARGET_ARCH=aarch64\nexport PATH=/opt/sdk/bin:$PATH\n",
+            )
+            .unwrap();
+            let changes = capture(&script).unwrap();
+            assert_eq!(
+                changes[0],
+                (
+                    "OECORE_TARGET_ARCH".to_string(),
+                    Some("aarch64".to_string())
+                )
+            );
+            assert!(changes[1].1.as_ref().unwrap().starts_with("/opt/sdk/bin:"));
+            assert!(capture(&temp.path().join("missing")).is_err());
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Configure cache seeding across machines.
+//!
+//! Much of a small project's configure time goes into feature detection
+//! (`check_include_file`, `check_symbol_exists`, `find_program`, ...), whose
+//! results CMake stores in `CMakeCache.txt`. A [`CacheSeed`] exported from one
+//! configured tree carries those results to other machines of a homogeneous
+//! CI fleet; [`Cmd::seed_cache`](crate::Cmd::seed_cache) pre-populates a fresh
+//! build tree with it, so the checks are skipped there. Compiler
+//! identification is stored outside the cache and still runs.
+//!
+//! Entries that describe the tree itself (its source and build directories,
+//! the cmake executable, the generator, ...) are left out, and remaining paths
+//! inside the source or build directory are templated, so a seed works for
+//! checkouts in different locations.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, seed::CacheSeed};
+//!
+//! // on the machine that configured the tree
+//! CacheSeed::export("./build").unwrap().save("cache-seed.txt").unwrap();
+//!
+//! // on another machine
+//! let seed = CacheSeed::load("cache-seed.txt").unwrap();
+//! Cmd::default().set_path("./my_project").seed_cache(seed).build().unwrap();
+//! ```
+
+use std::path::Path;
+
+use crate::cmake::{self, CacheEntry};
+
+/// First line of a saved seed, identifying the format.
+const HEADER: &str = "# cmakr cache seed v1";
+
+/// Placeholder for the source directory in templated values.
+const SOURCE_DIR: &str = "@CMAKR_SOURCE_DIR@";
+
+/// Placeholder for the build directory in templated values.
+const BINARY_DIR: &str = "@CMAKR_BINARY_DIR@";
+
+/// Cache entries that belong to a single build tree and must not be seeded.
+const TREE_ENTRIES: [&str; 15] = [
+    "CMAKE_CACHEFILE_DIR",
+    "CMAKE_HOME_DIRECTORY",
+    "CMAKE_CACHE_MAJOR_VERSION",
+    "CMAKE_CACHE_MINOR_VERSION",
+    "CMAKE_CACHE_PATCH_VERSION",
+    "CMAKE_COMMAND",
+    "CMAKE_CPACK_COMMAND",
+    "CMAKE_CTEST_COMMAND",
+    "CMAKE_EDIT_COMMAND",
+    "CMAKE_ROOT",
+    "CMAKE_GENERATOR",
+    "CMAKE_EXTRA_GENERATOR",
+    "CMAKE_PLATFORM_INFO_INITIALIZED",
+    "CMAKE_NUMBER_OF_MAKEFILES",
+    "FETCHCONTENT_BASE_DIR",
+];
+
+/// A sanitized set of `CMakeCache.txt` entries that can seed other build trees.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheSeed {
+    entries: Vec<CacheEntry>,
+}
+
+impl CacheSeed {
+    /// Exports a seed from the configured build tree `binary_dir`.
+    ///
+    /// # Arguments
+    ///
+    /// * `binary_dir` - A build directory containing a `CMakeCache.txt`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the cache cannot be read.
+    pub fn export<P>(binary_dir: P) -> std::io::Result<CacheSeed>
+    where
+        P: AsRef<Path>,
+    {
+        let binary_dir = binary_dir.as_ref();
+        let content = std::fs::read_to_string(binary_dir.join("CMakeCache.txt"))?;
+        Ok(Self::from_cache(&content))
+    }
+
+    /// Builds a seed from the contents of a `CMakeCache.txt`.
+    fn from_cache(content: &str) -> CacheSeed {
+        let entries = cmake::parse_cache(content);
+        let lookup = |name: &str| {
+            entries
+                .iter()
+                .find(|entry| entry.name == name)
+                .map(|entry| entry.value.clone())
+        };
+        let source_dir = lookup("CMAKE_HOME_DIRECTORY");
+        let binary_dir = lookup("CMAKE_CACHEFILE_DIR");
+
+        let entries = entries
+            .iter()
+            .filter(|entry| is_seedable(entry))
+            .map(|entry| {
+                let mut value = entry.value.clone();
+                // the build dir usually lies inside the source dir, so it goes first
+                if let Some(dir) = binary_dir.as_deref().filter(|dir| !dir.is_empty()) {
+                    value = value.replace(dir, BINARY_DIR);
+                }
+                if let Some(dir) = source_dir.as_deref().filter(|dir| !dir.is_empty()) {
+                    value = value.replace(dir, SOURCE_DIR);
+                }
+                CacheEntry {
+                    value,
+                    ..entry.clone()
+                }
+            })
+            .collect();
+
+        CacheSeed { entries }
+    }
+
+    /// Loads a seed written by [`save`](CacheSeed::save).
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the file cannot be read or is not a cache seed.
+    pub fn load<P>(path: P) -> std::io::Result<CacheSeed>
+    where
+        P: AsRef<Path>,
+    {
+        let content = std::fs::read_to_string(path.as_ref())?;
+        if content.lines().next() != Some(HEADER) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} is not a cmakr cache seed", path.as_ref().display()),
+            ));
+        }
+        Ok(CacheSeed {
+            entries: cmake::parse_cache(&content),
+        })
+    }
+
+    /// Saves the seed as a text file in `CMakeCache.txt` syntax.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the file cannot be written.
+    pub fn save<P>(&self, path: P) -> std::io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let mut content = format!("{}\n", HEADER);
+        for entry in &self.entries {
+            content.push_str(&format!("{}:{}={}\n", entry.name, entry.ty, entry.value));
+        }
+        std::fs::write(path, content)
+    }
+
+    /// Returns the number of entries in the seed.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the seed has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Renders the seed as an initial-cache script for `cmake -C`, with the
+    /// placeholders replaced by `source_dir` and `binary_dir`.
+    pub(crate) fn to_initial_cache(&self, source_dir: &str, binary_dir: &str) -> String {
+        let mut script = String::new();
+        for entry in &self.entries {
+            let value = entry
+                .value
+                .replace(SOURCE_DIR, source_dir)
+                .replace(BINARY_DIR, binary_dir);
+            script.push_str(&format!(
+                "set({} \"{}\" CACHE {} \"\")\n",
+                entry.name,
+                escape_cmake(&value),
+                entry.ty
+            ));
+        }
+        script
+    }
+}
+
+/// Returns whether an entry is not tied to the build tree it was exported from.
+fn is_seedable(entry: &CacheEntry) -> bool {
+    !TREE_ENTRIES.contains(&entry.name.as_str())
+        && entry.ty != "STATIC"
+        // `NAME-ADVANCED` and `NAME-STRINGS` hold properties of other entries
+        && !entry.name.contains('-')
+        && !entry.name.starts_with("CMAKE_GENERATOR")
+        && !entry.name.ends_with("_BINARY_DIR")
+        && !entry.name.ends_with("_SOURCE_DIR")
+        && !entry.name.ends_with("_IS_TOP_LEVEL")
+        && !entry.name.ends_with("_OUTPUT_DIRECTORY")
+}
+
+/// Escapes a value for a quoted CMake argument.
+fn escape_cmake(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "\\$")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_and_apply_seed() {
+        let cache = "\
+# This is the CMakeCache file.
+//Path to a program.
+CMAKE_AR:FILEPATH=/usr/bin/ar
+CMAKE_AR-ADVANCED:INTERNAL=1
+CMAKE_BUILD_TYPE:STRING=Release
+CMAKE_CACHEFILE_DIR:INTERNAL=/src/app/build
+CMAKE_COMMAND:INTERNAL=/usr/bin/cmake
+CMAKE_GENERATOR:INTERNAL=Ninja
+CMAKE_HOME_DIRECTORY:INTERNAL=/src/app
+HAVE_STDIO_H:INTERNAL=1
+app_BINARY_DIR:STATIC=/src/app/build
+GENERATED_HEADER:FILEPATH=/src/app/build/gen/config.h
+VENDOR_DIR:PATH=/src/app/vendor
+";
+        let seed = CacheSeed::from_cache(cache);
+        let names: Vec<_> = seed.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "CMAKE_AR",
+                "CMAKE_BUILD_TYPE",
+                "HAVE_STDIO_H",
+                "GENERATED_HEADER",
+                "VENDOR_DIR"
+            ]
+        );
+        assert_eq!(seed.entries[3].value, "@CMAKR_BINARY_DIR@/gen/config.h");
+        assert_eq!(seed.entries[4].value, "@CMAKR_SOURCE_DIR@/vendor");
+
+        let script = seed.to_initial_cache("C:/ci/app", "C:/ci/app/out");
+        assert!(script.contains("set(HAVE_STDIO_H \"1\" CACHE INTERNAL \"\")\n"));
+        assert!(script.contains(
+            "set(GENERATED_HEADER \"C:/ci/app/out/gen/config.h\" CACHE FILEPATH \"\")\n"
+        ));
+    }
+}
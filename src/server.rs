@@ -0,0 +1,284 @@
+//! Serving workspace builds to other processes.
+//!
+//! With the `server` feature, a [`Workspace`] can listen for build requests
+//! on an HTTP endpoint ([`Workspace::serve_http`]) or, on Unix, a Unix
+//! domain socket ([`Workspace::serve_unix`]), so that editors, schedulers or
+//! webhooks can trigger builds of a long-lived workspace.
+//!
+//! A request names a project and optionally a preset to build it with:
+//!
+//! ```json
+//! {"project": "png", "preset": "release"}
+//! ```
+//!
+//! Over HTTP it is the body of a `POST /build`; over a Unix socket it is a
+//! single line. The project is built together with the projects it depends
+//! on, and the [machine-readable events](crate::machine) of every build are
+//! streamed back as JSON lines, each with an added `project` field. The
+//! stream ends with a `workspace_finished` event carrying `success` and the
+//! status of every built project, after which the connection is closed. A
+//! rejected request gets a single `error` event with a `message`.
+//!
+//! Connections are handled concurrently, but only one build runs at a time.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::workspace::Workspace;
+
+/// A request to build one project of the workspace.
+#[derive(Deserialize)]
+struct BuildRequest {
+    project: String,
+    preset: Option<String>,
+}
+
+/// The stream events of a build are written to.
+type Output = Arc<Mutex<dyn Write + Send>>;
+
+/// State shared by all connections of a server.
+struct Server {
+    workspace: Workspace,
+    /// Held while a build runs, so builds of the same trees never overlap.
+    build_lock: Mutex<()>,
+}
+
+impl Workspace {
+    /// Serves build requests over HTTP on `addr`, blocking forever.
+    ///
+    /// Requests are accepted as `POST /build` with a JSON body. See the
+    /// [`server`](crate::server) module for the protocol.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `addr` cannot be bound or accepting a connection fails.
+    pub fn serve_http<A>(self, addr: A) -> io::Result<()>
+    where
+        A: ToSocketAddrs,
+    {
+        let listener = TcpListener::bind(addr)?;
+        let server = Arc::new(Server::new(self));
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = server.clone();
+            thread::spawn(move || {
+                let Ok(reader) = stream.try_clone() else {
+                    return;
+                };
+                let _ = server.handle_http(BufReader::new(reader), Arc::new(Mutex::new(stream)));
+            });
+        }
+        Ok(())
+    }
+
+    /// Serves build requests on a Unix domain socket at `path`, blocking forever.
+    ///
+    /// Each connection sends one request as a line of JSON. See the
+    /// [`server`](crate::server) module for the protocol.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the socket cannot be bound or accepting a
+    /// connection fails.
+    #[cfg(unix)]
+    pub fn serve_unix<T>(self, path: T) -> io::Result<()>
+    where
+        T: AsRef<std::path::Path>,
+    {
+        let listener = std::os::unix::net::UnixListener::bind(path)?;
+        let server = Arc::new(Server::new(self));
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = server.clone();
+            thread::spawn(move || {
+                let Ok(reader) = stream.try_clone() else {
+                    return;
+                };
+                let mut line = String::new();
+                if BufReader::new(reader).read_line(&mut line).is_err() {
+                    return;
+                }
+                let output: Output = Arc::new(Mutex::new(stream));
+                match server.prepare(&line) {
+                    Ok(workspace) => server.run(workspace, &output),
+                    Err((_, message)) => write_event(&output, &error_event(&message)),
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Server {
+    fn new(workspace: Workspace) -> Self {
+        Self {
+            workspace,
+            build_lock: Mutex::new(()),
+        }
+    }
+
+    /// Reads an HTTP request and answers it with a stream of events.
+    fn handle_http(&self, mut reader: impl BufRead, output: Output) -> io::Result<()> {
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':')
+                && name.trim().eq_ignore_ascii_case("content-length")
+            {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+
+        let mut parts = request_line.split_whitespace();
+        let prepared = match (parts.next(), parts.next()) {
+            (Some("POST"), Some("/build")) => self.prepare(&String::from_utf8_lossy(&body)),
+            (_, Some("/build")) => Err((405, "only POST is supported".to_string())),
+            _ => Err((404, "not found".to_string())),
+        };
+
+        let status = match &prepared {
+            Ok(_) => "200 OK",
+            Err((400, _)) => "400 Bad Request",
+            Err((404, _)) => "404 Not Found",
+            Err(_) => "405 Method Not Allowed",
+        };
+        {
+            let mut output = output.lock().unwrap_or_else(|e| e.into_inner());
+            write!(
+                output,
+                "HTTP/1.1 {}\r\nContent-Type: application/x-ndjson\r\nConnection: close\r\n\r\n",
+                status
+            )?;
+        }
+        match prepared {
+            Ok(workspace) => self.run(workspace, &output),
+            Err((_, message)) => write_event(&output, &error_event(&message)),
+        }
+        Ok(())
+    }
+
+    /// Parses a build request into the workspace to build, or an HTTP status
+    /// code and message to reject it with.
+    fn prepare(&self, request: &str) -> Result<Workspace, (u16, String)> {
+        let request: BuildRequest = serde_json::from_str(request.trim())
+            .map_err(|e| (400, format!("invalid build request: {}", e)))?;
+        let workspace = self
+            .workspace
+            .subset(&request.project)
+            .ok_or_else(|| (404, format!("unknown project {}", request.project)))?;
+
+        Ok(workspace.map_projects(|name, cmd| match &request.preset {
+            Some(preset) if name == request.project => cmd.set_preset(preset.as_str()),
+            _ => cmd,
+        }))
+    }
+
+    /// Builds `workspace`, streaming its events to `output`.
+    fn run(&self, workspace: Workspace, output: &Output) {
+        let _guard = self.build_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let workspace = workspace.map_projects(|name, cmd| {
+            let name = name.to_string();
+            let output = output.clone();
+            cmd.set_event_sink(Arc::new(move |mut event: Value| {
+                event["project"] = Value::from(name.as_str());
+                write_event(&output, &event);
+            }))
+        });
+
+        let event = match workspace.build() {
+            Ok(report) => {
+                let projects: serde_json::Map<String, Value> = report
+                    .projects
+                    .iter()
+                    .map(|(name, status)| (name.clone(), Value::from(status.to_string())))
+                    .collect();
+                json!({
+                    "event": "workspace_finished",
+                    "success": report.is_success(),
+                    "projects": projects,
+                })
+            }
+            Err(e) => error_event(&e.to_string()),
+        };
+        write_event(output, &event);
+    }
+}
+
+/// Returns an `error` event with `message`.
+fn error_event(message: &str) -> Value {
+    json!({ "event": "error", "message": message })
+}
+
+/// Writes one event as a line, ignoring clients that went away.
+fn write_event(output: &Output, event: &Value) {
+    let mut output = output.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = writeln!(output, "{}", event);
+    let _ = output.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cmd;
+
+    #[test]
+    fn stream_events_of_requested_project() {
+        // the preset does not exist, so the build fails before cmake is run
+        let server = Server::new(
+            Workspace::default()
+                .add_project("a", Cmd::default().set_path("./test/"))
+                .add_project("b", Cmd::default().set_path("./test/"))
+                .add_dependency("b", "a"),
+        );
+        let request = |body: &str| {
+            let buffer = Arc::new(Mutex::new(Vec::new()));
+            let http = format!(
+                "POST /build HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            server.handle_http(http.as_bytes(), buffer.clone()).unwrap();
+            let response = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+            let (head, events) = response.split_once("\r\n\r\n").unwrap();
+            let events: Vec<Value> = events
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            (head.lines().next().unwrap().to_string(), events)
+        };
+
+        let (status, events) = request(r#"{"project":"a","preset":"missing"}"#);
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        let finished = events.last().unwrap();
+        assert_eq!(finished["event"], "workspace_finished");
+        assert_eq!(finished["success"], false);
+        assert!(
+            finished["projects"]["a"]
+                .as_str()
+                .unwrap()
+                .starts_with("failed")
+        );
+        assert!(finished["projects"].get("b").is_none());
+
+        let (status, events) = request(r#"{"project":"c"}"#);
+        assert_eq!(status, "HTTP/1.1 404 Not Found");
+        assert_eq!(events[0]["event"], "error");
+
+        let (status, _) = request("not json");
+        assert_eq!(status, "HTTP/1.1 400 Bad Request");
+    }
+}
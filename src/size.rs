@@ -0,0 +1,446 @@
+//! Binary size analysis of build artifacts.
+//!
+//! With [`Cmd::analyze_size`](crate::Cmd::analyze_size), the binaries a
+//! build produced (executables, shared and static libraries) are measured
+//! after the build: their file size, their sections as reported by
+//! `size -A` (or `dumpbin /summary` on Windows), and their largest symbols
+//! as reported by `nm --size-sort`. Where these tools are missing, only the
+//! file size is recorded.
+//!
+//! Sizes can be checked against an absolute limit, and against the growth
+//! allowed over a baseline saved from an earlier run, to catch native bloat
+//! in pull requests the way `cargo bloat` does for Rust code.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, size::SizeAnalysis};
+//!
+//! let report = Cmd::default()
+//!     .set_path("./my_project")
+//!     .analyze_size(
+//!         SizeAnalysis::new()
+//!             .top_symbols(20)
+//!             .max_growth(5.0)
+//!             .baseline("size-baseline.json")
+//!             .fail_on_regression(true),
+//!     )
+//!     .build()
+//!     .unwrap();
+//! let size = report.size.unwrap();
+//! for artifact in &size.artifacts {
+//!     println!("{}: {} bytes", artifact.path.display(), artifact.size);
+//! }
+//! size.save("size-baseline.json").unwrap();
+//! ```
+
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The number of symbols listed per artifact by default.
+const DEFAULT_TOP_SYMBOLS: usize = 10;
+
+/// Settings of the size analysis of a build, see
+/// [`Cmd::analyze_size`](crate::Cmd::analyze_size).
+#[derive(Debug, Clone)]
+pub struct SizeAnalysis {
+    top_symbols: usize,
+    max_size: Option<u64>,
+    max_growth: Option<f64>,
+    baseline: Option<PathBuf>,
+    fail_on_regression: bool,
+}
+
+impl Default for SizeAnalysis {
+    fn default() -> Self {
+        Self {
+            top_symbols: DEFAULT_TOP_SYMBOLS,
+            max_size: None,
+            max_growth: None,
+            baseline: None,
+            fail_on_regression: false,
+        }
+    }
+}
+
+impl SizeAnalysis {
+    /// Creates the default settings: the 10 largest symbols per artifact,
+    /// and no thresholds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many of the largest symbols are listed per artifact.
+    pub fn top_symbols(mut self, count: usize) -> Self {
+        self.top_symbols = count;
+        self
+    }
+
+    /// Sets the size no artifact may exceed, in bytes.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Sets how much an artifact may grow over its size in the
+    /// [baseline](SizeAnalysis::baseline), in percent.
+    pub fn max_growth(mut self, percent: f64) -> Self {
+        self.max_growth = Some(percent);
+        self
+    }
+
+    /// Sets the [saved](SizeReport::save) size report to compare against.
+    ///
+    /// Artifacts are matched by file name. A missing baseline file is not an
+    /// error, so the first run can create it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the baseline report.
+    pub fn baseline<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.baseline = Some(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Sets whether a run with a [regression](SizeReport::regressions) fails
+    /// with [`Error::SizeRegression`](crate::Error::SizeRegression) instead
+    /// of only reporting it.
+    pub fn fail_on_regression(mut self, fail: bool) -> Self {
+        self.fail_on_regression = fail;
+        self
+    }
+
+    pub(crate) fn fails_on_regression(&self) -> bool {
+        self.fail_on_regression
+    }
+}
+
+/// The size of a section of an artifact.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionSize {
+    /// The section name, e.g. `.text`.
+    pub name: String,
+    /// The size in bytes, summed over the members of a static library.
+    pub size: u64,
+}
+
+/// The size of a symbol of an artifact.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymbolSize {
+    /// The demangled symbol name.
+    pub name: String,
+    /// The size in bytes.
+    pub size: u64,
+}
+
+/// The measured size of a binary artifact.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactSize {
+    pub path: PathBuf,
+    /// The file size in bytes.
+    pub size: u64,
+    /// The sections, in the order the tool listed them.
+    pub sections: Vec<SectionSize>,
+    /// The largest symbols, largest first.
+    pub symbols: Vec<SymbolSize>,
+}
+
+/// An artifact larger than the thresholds of its [`SizeAnalysis`] allow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeRegression {
+    pub artifact: PathBuf,
+    /// The size in bytes.
+    pub size: u64,
+    /// The size in bytes the thresholds allow.
+    pub limit: u64,
+    /// The size in bytes recorded in the baseline, if the artifact is in it.
+    pub baseline: Option<u64>,
+}
+
+/// The sizes of the binary artifacts of a build.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SizeReport {
+    /// The measured artifacts, in the order of the build report.
+    pub artifacts: Vec<ArtifactSize>,
+    /// The artifacts that exceed a threshold.
+    #[serde(skip)]
+    pub regressions: Vec<SizeRegression>,
+}
+
+impl SizeReport {
+    /// Returns the total size of all artifacts, in bytes.
+    pub fn total_size(&self) -> u64 {
+        self.artifacts.iter().map(|artifact| artifact.size).sum()
+    }
+
+    /// Loads a report written by [`save`](SizeReport::save).
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the file cannot be read or is not a size
+    /// report.
+    pub fn load<P>(path: P) -> std::io::Result<SizeReport>
+    where
+        P: AsRef<Path>,
+    {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Saves the report as JSON, to serve as the baseline of later runs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the file cannot be written.
+    pub fn save<P>(&self, path: P) -> std::io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Measures the binary `artifacts` and checks them against the thresholds of
+/// `analysis`. Files that are not binaries are skipped.
+///
+/// # Errors
+///
+/// Returns an I/O error if an artifact or the baseline cannot be read.
+pub(crate) fn analyze<'a>(
+    analysis: &SizeAnalysis,
+    artifacts: impl Iterator<Item = &'a PathBuf>,
+) -> std::io::Result<SizeReport> {
+    let mut report = SizeReport::default();
+    for path in artifacts {
+        if !is_binary(path)? {
+            continue;
+        }
+        let mut symbols = symbols(path);
+        symbols.truncate(analysis.top_symbols);
+        report.artifacts.push(ArtifactSize {
+            path: path.clone(),
+            size: std::fs::metadata(path)?.len(),
+            sections: sections(path),
+            symbols,
+        });
+    }
+
+    let baseline = match &analysis.baseline {
+        Some(path) if path.is_file() => Some(SizeReport::load(path)?),
+        _ => None,
+    };
+    for artifact in &report.artifacts {
+        let previous = baseline.as_ref().and_then(|baseline| {
+            baseline
+                .artifacts
+                .iter()
+                .find(|other| other.path.file_name() == artifact.path.file_name())
+                .map(|other| other.size)
+        });
+        let grown = previous
+            .zip(analysis.max_growth)
+            .map(|(size, percent)| (size as f64 * (1.0 + percent / 100.0)) as u64);
+        let limit = match (analysis.max_size, grown) {
+            (Some(max), Some(grown)) => Some(max.min(grown)),
+            (max, grown) => max.or(grown),
+        };
+        if let Some(limit) = limit.filter(|limit| artifact.size > *limit) {
+            report.regressions.push(SizeRegression {
+                artifact: artifact.path.clone(),
+                size: artifact.size,
+                limit,
+                baseline: previous,
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// Returns whether `path` is an object file format the tools can measure:
+/// ELF, Mach-O, PE, or an `ar` archive.
+fn is_binary(path: &Path) -> std::io::Result<bool> {
+    let mut magic = [0u8; 8];
+    let mut file = std::fs::File::open(path)?;
+    let read = file.read(&mut magic)?;
+    let magic = &magic[..read];
+    Ok(magic.starts_with(b"\x7fELF")
+        || magic.starts_with(b"!<arch>\n")
+        || magic.starts_with(b"MZ")
+        || [
+            [0xfe, 0xed, 0xfa, 0xce],
+            [0xfe, 0xed, 0xfa, 0xcf],
+            [0xce, 0xfa, 0xed, 0xfe],
+            [0xcf, 0xfa, 0xed, 0xfe],
+            [0xca, 0xfe, 0xba, 0xbe],
+        ]
+        .iter()
+        .any(|mach_o| magic.starts_with(mach_o)))
+}
+
+/// Returns the standard output of a successful run of `command`.
+fn run(command: &mut Command) -> Option<String> {
+    let output = command.output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Returns the sections of `path`, or none if the tool is missing.
+fn sections(path: &Path) -> Vec<SectionSize> {
+    if cfg!(windows) {
+        run(Command::new("dumpbin")
+            .args(["/nologo", "/summary"])
+            .arg(path))
+        .map(|output| parse_dumpbin_summary(&output))
+        .unwrap_or_default()
+    } else {
+        run(Command::new("size").arg("-A").arg(path))
+            .map(|output| parse_size(&output))
+            .unwrap_or_default()
+    }
+}
+
+/// Returns the symbols of `path`, largest first, or none if the tool is
+/// missing.
+fn symbols(path: &Path) -> Vec<SymbolSize> {
+    run(Command::new("nm")
+        .args(["--size-sort", "--reverse-sort", "-S", "-C", "--radix=d"])
+        .arg(path))
+    .map(|output| parse_nm(&output))
+    .unwrap_or_default()
+}
+
+/// Parses the System V format of `size -A`, summing the sections of the
+/// members of an archive.
+fn parse_size(output: &str) -> Vec<SectionSize> {
+    let mut sections: Vec<SectionSize> = Vec::new();
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [name, size, _address] = fields.as_slice() else {
+            continue;
+        };
+        let Ok(size) = size.parse::<u64>() else {
+            continue;
+        };
+        match sections.iter_mut().find(|section| section.name == *name) {
+            Some(section) => section.size += size,
+            None => sections.push(SectionSize {
+                name: name.to_string(),
+                size,
+            }),
+        }
+    }
+    sections
+}
+
+/// Parses the section summary of `dumpbin /summary`, whose sizes are hex.
+fn parse_dumpbin_summary(output: &str) -> Vec<SectionSize> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (size, name) = line.trim().split_once(char::is_whitespace)?;
+            let name = name.trim();
+            name.starts_with('.').then_some(())?;
+            Some(SectionSize {
+                name: name.to_string(),
+                size: u64::from_str_radix(size, 16).ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Parses `nm -S --radix=d` output, sorted by size, largest first.
+fn parse_nm(output: &str) -> Vec<SymbolSize> {
+    let mut symbols: Vec<SymbolSize> = output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, ' ');
+            let (_address, size, _kind) = (fields.next()?, fields.next()?, fields.next()?);
+            Some(SymbolSize {
+                name: fields.next()?.to_string(),
+                size: size.parse().ok()?,
+            })
+        })
+        .collect();
+    symbols.sort_by_key(|symbol| std::cmp::Reverse(symbol.size));
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measure_artifacts() {
+        let size = "\
+/tmp/app  :
+section      size    addr
+.text        4096    4160
+.data          16   16384
+Total        4112
+";
+        assert_eq!(
+            parse_size(size),
+            [
+                SectionSize {
+                    name: ".text".to_string(),
+                    size: 4096
+                },
+                SectionSize {
+                    name: ".data".to_string(),
+                    size: 16
+                }
+            ]
+        );
+        let dumpbin = "\n  Summary\n\n        1000 .data\n       12000 .rdata\n";
+        assert_eq!(parse_dumpbin_summary(dumpbin)[1].size, 0x12000);
+        let nm = "0000000000004160 0000000000000012 T main\n\
+                  0000000000004200 0000000000000300 T foo::bar(int)\n";
+        let symbols = parse_nm(nm);
+        assert_eq!(symbols[0].name, "foo::bar(int)");
+        assert_eq!(symbols[0].size, 300);
+
+        let temp = crate::guard::TempBuildDir::new("size").unwrap();
+        let binary = temp.path().join("app");
+        std::fs::write(&binary, b"\x7fELF\x02\x01\x01 not really").unwrap();
+        let text = temp.path().join("notes.txt");
+        std::fs::write(&text, "text").unwrap();
+        let baseline = temp.path().join("baseline.json");
+        SizeReport {
+            artifacts: vec![ArtifactSize {
+                path: PathBuf::from("/elsewhere/app"),
+                size: 10,
+                sections: Vec::new(),
+                symbols: Vec::new(),
+            }],
+            regressions: Vec::new(),
+        }
+        .save(&baseline)
+        .unwrap();
+
+        let analysis = SizeAnalysis::new()
+            .max_growth(50.0)
+            .baseline(baseline.to_string_lossy());
+        let report = analyze(&analysis, [binary.clone(), text].iter()).unwrap();
+        assert_eq!(report.artifacts.len(), 1);
+        assert_eq!(report.total_size(), 18);
+        assert_eq!(
+            report.regressions,
+            [SizeRegression {
+                artifact: binary,
+                size: 18,
+                limit: 15,
+                baseline: Some(10),
+            }]
+        );
+    }
+}
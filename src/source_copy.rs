@@ -0,0 +1,312 @@
+//! Building read-only source trees from a writable copy.
+//!
+//! Crates unpacked into the cargo registry, sources on read-only mounts, and
+//! Nix store paths can't be written to. Most projects never write into their
+//! sources, but some generate files next to them (`configure_file` into
+//! `CMAKE_CURRENT_SOURCE_DIR`, `FetchContent` with `SOURCE_DIR` in the tree,
+//! patch steps), and fail with an obscure permission error deep in configure.
+//! Under [`ReadOnlySourcePolicy::Copy`](crate::cmd::ReadOnlySourcePolicy::Copy),
+//! such a tree is mirrored into the build directory and built from there,
+//! with reported paths mapped back to the original sources.
+
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// The directory of the build tree the sources are copied to.
+pub(crate) const COPY_DIR: &str = "cmakr-source";
+
+/// Returns whether files can be created in `dir`, without creating one.
+///
+/// Permission bits don't tell (read-only mounts, ACLs, running as root), so
+/// the operating system is asked whether the directory can be written to.
+pub(crate) fn is_writable(dir: &Path) -> bool {
+    dir.is_dir() && ffi::can_add_files(dir)
+}
+
+/// Copies the tree at `from` to `to`, skipping `.git` and `exclude` (the
+/// build directory, if it is inside the sources).
+///
+/// Files whose copy has the size and modification time of the original are
+/// kept, so repeated builds only copy what changed. Copies are made
+/// writable. Files copied by an earlier mirror whose original is gone are
+/// removed, while files configure generated in the copy are kept. Linked
+/// directories are copied as well, unless they link back into the tree
+/// being copied.
+pub(crate) fn mirror(from: &Path, to: &Path, exclude: &Path) -> io::Result<()> {
+    let mut copied = Vec::new();
+    let mut ancestors = vec![from.canonicalize()?];
+    copy_tree(
+        from,
+        to,
+        Path::new(""),
+        exclude,
+        &mut ancestors,
+        &mut copied,
+    )?;
+
+    // the files of the last mirror that are not in this one were deleted from the sources
+    let manifest = manifest_path(to);
+    let previous = std::fs::read_to_string(&manifest).unwrap_or_default();
+    let current: HashSet<&Path> = copied.iter().map(PathBuf::as_path).collect();
+    for file in previous.lines().map(Path::new) {
+        if !current.contains(file) && to.join(file).is_file() {
+            std::fs::remove_file(to.join(file))?;
+        }
+    }
+    let listing: String = copied
+        .iter()
+        .map(|file| format!("{}\n", file.display()))
+        .collect();
+    std::fs::write(manifest, listing)
+}
+
+/// Copies the directory `from` to `to`, adding the paths of the copied
+/// files relative to the root of the mirror, `relative`, to `copied`.
+///
+/// `ancestors` are the canonical directories being copied, which a
+/// symlinked directory inside them is not copied again.
+fn copy_tree(
+    from: &Path,
+    to: &Path,
+    relative: &Path,
+    exclude: &Path,
+    ancestors: &mut Vec<PathBuf>,
+    copied: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let source = entry.path();
+        if entry.file_name() == ".git" || source == exclude {
+            continue;
+        }
+        let target = to.join(entry.file_name());
+        let relative = relative.join(entry.file_name());
+        // symlinks are followed, so linked directories are copied as well
+        let metadata = match std::fs::metadata(&source) {
+            Ok(metadata) => metadata,
+            // a dangling symlink has nothing to copy
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        if metadata.is_dir() {
+            let canonical = source.canonicalize()?;
+            if ancestors.contains(&canonical) {
+                continue;
+            }
+            ancestors.push(canonical);
+            copy_tree(&source, &target, &relative, exclude, ancestors, copied)?;
+            ancestors.pop();
+        } else {
+            if is_stale(&target, &metadata) {
+                if target.exists() {
+                    std::fs::remove_file(&target)?;
+                }
+                std::fs::copy(&source, &target)?;
+                make_writable(&target)?;
+                std::fs::File::options()
+                    .write(true)
+                    .open(&target)?
+                    .set_modified(metadata.modified()?)?;
+            }
+            copied.push(relative);
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether the copy at `target` is missing, or differs in size or
+/// modification time from the original with `metadata`.
+fn is_stale(target: &Path, metadata: &std::fs::Metadata) -> bool {
+    let Ok(copied) = std::fs::metadata(target) else {
+        return true;
+    };
+    match (copied.modified(), metadata.modified()) {
+        (Ok(copied_at), Ok(modified)) => copied.len() != metadata.len() || copied_at != modified,
+        _ => true,
+    }
+}
+
+/// Returns the file listing what the last mirror into `to` copied.
+fn manifest_path(to: &Path) -> PathBuf {
+    let mut name = to.file_name().unwrap_or_default().to_os_string();
+    name.push(".files");
+    to.with_file_name(name)
+}
+
+/// Clears the read-only flag that [`std::fs::copy`] carries over.
+fn make_writable(path: &Path) -> io::Result<()> {
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(permissions.mode() | 0o200);
+    }
+    #[cfg(not(unix))]
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    std::fs::set_permissions(path, permissions)
+}
+
+#[cfg(unix)]
+mod ffi {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt, path::Path};
+
+    const W_OK: i32 = 2;
+
+    unsafe extern "C" {
+        fn access(path: *const std::ffi::c_char, mode: i32) -> i32;
+    }
+
+    /// Returns whether the process may create files in the directory `dir`.
+    pub(super) fn can_add_files(dir: &Path) -> bool {
+        let Ok(path) = CString::new(dir.as_os_str().as_bytes()) else {
+            return false;
+        };
+        // SAFETY: `path` is a valid NUL-terminated string for the duration of the call
+        unsafe { access(path.as_ptr(), W_OK) == 0 }
+    }
+}
+
+#[cfg(windows)]
+mod ffi {
+    use std::{os::windows::ffi::OsStrExt, path::Path};
+
+    const FILE_ADD_FILE: u32 = 0x0002;
+    const FILE_SHARE_ALL: u32 = 0x0007;
+    const OPEN_EXISTING: u32 = 3;
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+    const INVALID_HANDLE_VALUE: isize = -1;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn CreateFileW(
+            name: *const u16,
+            access: u32,
+            share_mode: u32,
+            security: *mut std::ffi::c_void,
+            disposition: u32,
+            flags: u32,
+            template: isize,
+        ) -> isize;
+        fn CloseHandle(handle: isize) -> i32;
+    }
+
+    /// Returns whether the process may create files in the directory `dir`,
+    /// by opening it with the right to add files, which checks its ACL and
+    /// whether its volume is read-only.
+    pub(super) fn can_add_files(dir: &Path) -> bool {
+        let name: Vec<u16> = dir.as_os_str().encode_wide().chain([0]).collect();
+        // SAFETY: `name` is a valid NUL-terminated wide string for the duration of the call
+        let handle = unsafe {
+            CreateFileW(
+                name.as_ptr(),
+                FILE_ADD_FILE,
+                FILE_SHARE_ALL,
+                std::ptr::null_mut(),
+                OPEN_EXISTING,
+                FILE_FLAG_BACKUP_SEMANTICS,
+                0,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return false;
+        }
+        // SAFETY: the handle was just opened and is closed once
+        unsafe { CloseHandle(handle) };
+        true
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod ffi {
+    use std::path::Path;
+
+    /// Returns whether the directory `dir` is not marked read-only.
+    pub(super) fn can_add_files(dir: &Path) -> bool {
+        std::fs::metadata(dir).is_ok_and(|metadata| !metadata.permissions().readonly())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::TempBuildDir;
+
+    #[test]
+    fn mirror_read_only_sources() {
+        let temp = TempBuildDir::new("source-copy").unwrap();
+        let source = temp.path().join("src");
+        let build = source.join("build");
+        std::fs::create_dir_all(source.join("lib")).unwrap();
+        std::fs::create_dir_all(source.join(".git")).unwrap();
+        std::fs::create_dir_all(&build).unwrap();
+        std::fs::write(source.join("CMakeLists.txt"), "project(p)").unwrap();
+        std::fs::write(source.join("lib/CMakeLists.txt"), "").unwrap();
+        std::fs::write(build.join("CMakeCache.txt"), "").unwrap();
+        assert!(is_writable(&source));
+
+        let copy = temp.path().join("copy");
+        mirror(&source, &copy, &build).unwrap();
+        mirror(&source, &copy, &build).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(copy.join("CMakeLists.txt")).unwrap(),
+            "project(p)"
+        );
+        assert!(copy.join("lib/CMakeLists.txt").is_file());
+        assert!(!copy.join(".git").exists());
+        assert!(!copy.join("build").exists());
+        assert!(!is_writable(&temp.path().join("missing")));
+        // checking writability leaves no trace in the directory
+        assert_eq!(std::fs::read_dir(&source).unwrap().count(), 4);
+    }
+
+    #[test]
+    fn remove_files_deleted_from_sources() {
+        let temp = TempBuildDir::new("source-copy-delete").unwrap();
+        let source = temp.path().join("src");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("a.c"), "int a;").unwrap();
+        std::fs::write(source.join("b.c"), "int b;").unwrap();
+
+        let copy = temp.path().join("copy");
+        mirror(&source, &copy, &temp.path().join("build")).unwrap();
+        // configure generates a file next to the copied sources
+        std::fs::write(copy.join("config.h"), "").unwrap();
+        std::fs::remove_file(source.join("b.c")).unwrap();
+        // same size, different content and time
+        std::fs::write(source.join("a.c"), "int x;").unwrap();
+        let earlier = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(source.join("a.c"))
+            .unwrap()
+            .set_modified(earlier)
+            .unwrap();
+        mirror(&source, &copy, &temp.path().join("build")).unwrap();
+
+        assert!(!copy.join("b.c").exists());
+        assert!(copy.join("config.h").is_file());
+        assert_eq!(std::fs::read_to_string(copy.join("a.c")).unwrap(), "int x;");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skip_symlink_loops() {
+        let temp = TempBuildDir::new("source-copy-loop").unwrap();
+        let source = temp.path().join("src");
+        std::fs::create_dir_all(source.join("lib")).unwrap();
+        std::fs::write(source.join("lib/lib.c"), "").unwrap();
+        std::os::unix::fs::symlink(&source, source.join("lib/up")).unwrap();
+        std::os::unix::fs::symlink("missing", source.join("dangling")).unwrap();
+
+        let copy = temp.path().join("copy");
+        mirror(&source, &copy, &temp.path().join("build")).unwrap();
+
+        assert!(copy.join("lib/lib.c").is_file());
+        assert!(!copy.join("lib/up").exists());
+    }
+}
@@ -0,0 +1,430 @@
+//! Persistent per-build-tree state.
+//!
+//! Every [`Cmd`](crate::Cmd) run records the effective configuration, the
+//! CMake version, and the timings of the run in `.cmakr/state.json` inside the
+//! build directory. [`BuildState::load`] reads it back, so tools can show when
+//! and how a tree was last built without running anything.
+//!
+//! The last runs are kept as a [`BuildHistory`], whose [`trend`](BuildHistory::trend)
+//! shows whether native build times are regressing.
+//!
+//! The file carries a schema `version`, and files written by a newer version
+//! of cmakr than the running one are rejected instead of misread.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::state::BuildState;
+//!
+//! if let Some(state) = BuildState::load("./build").unwrap()
+//!     && let Some(run) = state.last_run()
+//! {
+//!     let preset = state.configuration.preset.as_deref().unwrap_or("none");
+//!     println!("last built at {} with preset={}", run.finished_at, preset);
+//! }
+//! ```
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    gc::STATE_DIR,
+    report::{BuildReport, Phase},
+};
+
+/// Name of the state file inside the state directory.
+const STATE_FILE: &str = "state.json";
+
+/// The schema version written by this version of cmakr.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How many runs are kept in a [`BuildHistory`].
+const MAX_HISTORY: usize = 20;
+
+/// The recorded state of one build directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildState {
+    /// The schema version of the file.
+    pub version: u32,
+    /// The configuration used by the most recent run.
+    pub configuration: Configuration,
+    /// The version of the `cmake` executable used by the most recent run.
+    pub cmake_version: Option<String>,
+    /// The most recent runs, oldest first.
+    pub history: BuildHistory,
+}
+
+/// The effective configuration of a run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Configuration {
+    /// The CMake source directory.
+    pub source_dir: PathBuf,
+    /// The configure preset, if one was used.
+    pub preset: Option<String>,
+    /// The `-D` cache variable definitions, as `(name, value)` pairs.
+    pub defines: Vec<(String, String)>,
+    /// The extra arguments passed to cmake.
+    pub args: Vec<String>,
+    /// The generator of the build tree (`CMAKE_GENERATOR`).
+    pub generator: Option<String>,
+    /// The build directory of the configuration, if the tree was moved to a
+    /// [short one](crate::Cmd::short_build_dir).
+    #[serde(default)]
+    pub relocated_from: Option<PathBuf>,
+}
+
+/// The outcome of a single run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// When the run finished, in seconds since the Unix epoch.
+    pub finished_at: u64,
+    /// Whether configure and build succeeded.
+    pub success: bool,
+    /// Whether configure reused an existing `CMakeCache.txt`.
+    pub cache_reused: bool,
+    /// The phases of the run in order.
+    pub phases: Vec<PhaseRecord>,
+    /// The number of compiler errors reported by the run.
+    #[serde(default)]
+    pub errors: usize,
+    /// The number of compiler warnings reported by the run.
+    #[serde(default)]
+    pub warnings: usize,
+}
+
+/// The timing of a single phase of a run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhaseRecord {
+    pub phase: Phase,
+    /// The target built by this phase, when the build was split per target.
+    pub target: Option<String>,
+    /// How long the phase took, in seconds.
+    pub seconds: f64,
+    /// Whether the phase's process succeeded.
+    pub success: bool,
+}
+
+impl RunRecord {
+    /// Returns when the run finished.
+    pub fn finished(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.finished_at)
+    }
+
+    /// Returns the total duration of all phases.
+    pub fn duration(&self) -> Duration {
+        self.phases
+            .iter()
+            .map(|phase| Duration::from_secs_f64(phase.seconds))
+            .sum()
+    }
+}
+
+/// A bounded, oldest-first list of recorded runs of one build directory.
+///
+/// At most the last 20 runs are kept.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BuildHistory {
+    pub runs: Vec<RunRecord>,
+}
+
+/// Build time development over a [`BuildHistory`].
+#[derive(Debug, Clone)]
+pub struct Trend {
+    /// One point per run, oldest first.
+    pub points: Vec<TrendPoint>,
+    /// The mean duration of the successful runs.
+    pub mean_duration: Option<Duration>,
+    /// The least-squares slope of the successful runs' durations, in seconds
+    /// per run. Positive values mean builds are getting slower.
+    pub slope: f64,
+    /// How much slower the latest successful run was than the mean of the
+    /// successful runs before it, as a fraction (`0.25` is 25% slower).
+    pub latest_change: Option<f64>,
+}
+
+/// A single run in a [`Trend`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrendPoint {
+    /// When the run finished, in seconds since the Unix epoch.
+    pub finished_at: u64,
+    /// The total duration of the run.
+    pub duration: Duration,
+    /// Whether the run succeeded.
+    pub success: bool,
+    /// The number of compiler errors.
+    pub errors: usize,
+    /// The number of compiler warnings.
+    pub warnings: usize,
+}
+
+impl BuildHistory {
+    /// Loads the history of the build directory `binary_dir`.
+    ///
+    /// The history is empty if cmakr has not run in the directory yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state file cannot be loaded (see [`BuildState::load`]).
+    pub fn load<P>(binary_dir: P) -> std::io::Result<BuildHistory>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(BuildState::load(binary_dir)?
+            .map(|state| state.history)
+            .unwrap_or_default())
+    }
+
+    /// Returns the last `n` runs.
+    pub fn last(&self, n: usize) -> BuildHistory {
+        let skip = self.runs.len().saturating_sub(n);
+        BuildHistory {
+            runs: self.runs[skip..].to_vec(),
+        }
+    }
+
+    /// Computes the build time trend over all runs in the history.
+    ///
+    /// Failed runs are included in [`Trend::points`] but left out of the
+    /// duration statistics, since they usually stop early.
+    pub fn trend(&self) -> Trend {
+        let points: Vec<TrendPoint> = self
+            .runs
+            .iter()
+            .map(|run| TrendPoint {
+                finished_at: run.finished_at,
+                duration: run.duration(),
+                success: run.success,
+                errors: run.errors,
+                warnings: run.warnings,
+            })
+            .collect();
+        let durations: Vec<f64> = points
+            .iter()
+            .filter(|point| point.success)
+            .map(|point| point.duration.as_secs_f64())
+            .collect();
+
+        let mean = |values: &[f64]| match values.len() {
+            0 => None,
+            n => Some(values.iter().sum::<f64>() / n as f64),
+        };
+
+        let slope = match mean(&durations) {
+            Some(mean_y) if durations.len() > 1 => {
+                let mean_x = (durations.len() - 1) as f64 / 2.0;
+                let (mut covariance, mut variance) = (0.0, 0.0);
+                for (x, y) in durations.iter().enumerate() {
+                    covariance += (x as f64 - mean_x) * (y - mean_y);
+                    variance += (x as f64 - mean_x).powi(2);
+                }
+                covariance / variance
+            }
+            _ => 0.0,
+        };
+
+        let latest_change = durations.split_last().and_then(|(latest, earlier)| {
+            let baseline = mean(earlier)?;
+            (baseline > 0.0).then(|| latest / baseline - 1.0)
+        });
+
+        Trend {
+            points,
+            mean_duration: mean(&durations).map(Duration::from_secs_f64),
+            slope,
+            latest_change,
+        }
+    }
+}
+
+impl BuildState {
+    /// Loads the state of the build directory `binary_dir`.
+    ///
+    /// Returns `Ok(None)` if cmakr has not run in the directory yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `binary_dir` - The CMake build directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state file cannot be read, is malformed, or was
+    /// written by a newer version of cmakr.
+    pub fn load<P>(binary_dir: P) -> std::io::Result<Option<BuildState>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = binary_dir.as_ref().join(STATE_DIR).join(STATE_FILE);
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let value: serde_json::Value = serde_json::from_str(&content)?;
+        let version = value.get("version").and_then(serde_json::Value::as_u64);
+        match version {
+            Some(version) if version <= u64::from(SCHEMA_VERSION) => {
+                Ok(Some(serde_json::from_value(value)?))
+            }
+            Some(version) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "{} has schema version {}, newer than the supported version {}",
+                    path.display(),
+                    version,
+                    SCHEMA_VERSION
+                ),
+            )),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} has no schema version", path.display()),
+            )),
+        }
+    }
+
+    /// Returns the most recent run.
+    pub fn last_run(&self) -> Option<&RunRecord> {
+        self.history.runs.last()
+    }
+
+    /// Returns the most recent successful run.
+    pub fn last_success(&self) -> Option<&RunRecord> {
+        self.history.runs.iter().rev().find(|run| run.success)
+    }
+
+    /// Appends a run to the state of `binary_dir` and writes it back.
+    ///
+    /// A missing or unreadable state file starts a fresh history.
+    pub(crate) fn record(
+        binary_dir: &Path,
+        configuration: Configuration,
+        cmake_version: Option<String>,
+        report: &BuildReport,
+        success: bool,
+    ) -> std::io::Result<()> {
+        let mut state = Self::load(binary_dir).ok().flatten().unwrap_or(BuildState {
+            version: SCHEMA_VERSION,
+            configuration: Configuration::default(),
+            cmake_version: None,
+            history: BuildHistory::default(),
+        });
+        state.version = SCHEMA_VERSION;
+        state.configuration = configuration;
+        state.cmake_version = cmake_version;
+
+        let finished_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        state.history.runs.push(RunRecord {
+            finished_at,
+            success,
+            cache_reused: report.cache_reused,
+            phases: report
+                .phases
+                .iter()
+                .map(|timing| PhaseRecord {
+                    phase: timing.phase,
+                    target: timing.target.clone(),
+                    seconds: timing.duration.as_secs_f64(),
+                    success: timing.exit.success(),
+                })
+                .collect(),
+            errors: report.errors().count(),
+            warnings: report.warnings().count(),
+        });
+        let excess = state.history.runs.len().saturating_sub(MAX_HISTORY);
+        state.history.runs.drain(..excess);
+
+        state.save(binary_dir)
+    }
+
+    /// Writes the state to `binary_dir`, replacing the previous file atomically.
+    fn save(&self, binary_dir: &Path) -> std::io::Result<()> {
+        let state_dir = binary_dir.join(STATE_DIR);
+        std::fs::create_dir_all(&state_dir)?;
+
+        let temp = state_dir.join(format!("{}.tmp", STATE_FILE));
+        std::fs::write(&temp, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(temp, state_dir.join(STATE_FILE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::TempBuildDir;
+
+    #[test]
+    fn record_and_load_state() {
+        let temp = TempBuildDir::new("state").unwrap();
+        let dir = temp.path().to_path_buf();
+        assert!(BuildState::load(&dir).unwrap().is_none());
+
+        let configuration = Configuration {
+            preset: Some("release".to_string()),
+            ..Default::default()
+        };
+        for success in [true, false] {
+            BuildState::record(
+                &dir,
+                configuration.clone(),
+                Some("3.28.3".to_string()),
+                &BuildReport::default(),
+                success,
+            )
+            .unwrap();
+        }
+
+        let state = BuildState::load(&dir).unwrap().unwrap();
+        assert_eq!(state.version, SCHEMA_VERSION);
+        assert_eq!(state.configuration, configuration);
+        assert_eq!(state.history.runs.len(), 2);
+        assert!(!state.last_run().unwrap().success);
+        assert!(state.last_success().is_some());
+
+        let path = dir.join(STATE_DIR).join(STATE_FILE);
+        std::fs::write(&path, r#"{"version": 99}"#).unwrap();
+        assert!(BuildState::load(&dir).is_err());
+    }
+
+    #[test]
+    fn compute_build_time_trend() {
+        let run = |seconds: f64, success: bool| RunRecord {
+            finished_at: 0,
+            success,
+            cache_reused: true,
+            phases: vec![PhaseRecord {
+                phase: Phase::Build,
+                target: None,
+                seconds,
+                success,
+            }],
+            errors: usize::from(!success),
+            warnings: 0,
+        };
+        let history = BuildHistory {
+            runs: vec![
+                run(10.0, true),
+                run(1.0, false),
+                run(12.0, true),
+                run(14.0, true),
+                run(19.5, true),
+            ],
+        };
+
+        let trend = history.trend();
+        assert_eq!(trend.points.len(), 5);
+        assert_eq!(trend.mean_duration, Some(Duration::from_secs_f64(13.875)));
+        assert!((trend.slope - 3.05).abs() < 1e-9);
+        assert!((trend.latest_change.unwrap() - 0.625).abs() < 1e-9);
+        assert_eq!(
+            history.last(2).trend().points[0].duration,
+            Duration::from_secs(14)
+        );
+    }
+}
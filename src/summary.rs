@@ -0,0 +1,212 @@
+//! Human-readable end-of-build summaries.
+//!
+//! [`BuildSummary`] renders a [`BuildReport`] as a short block of text with
+//! diagnostic counts, per-phase durations, and the produced artifacts,
+//! optionally colored for terminals.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::io::IsTerminal;
+//! use cmakr::Cmd;
+//!
+//! let report = Cmd::default().set_path("./my_project").build().unwrap();
+//! eprintln!("{}", report.summary().colored(std::io::stderr().is_terminal()));
+//! ```
+
+use std::{fmt, time::Duration};
+
+use crate::report::{BuildReport, Phase};
+
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const GREEN: &str = "\x1b[32m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// A [`Display`](fmt::Display)able summary of a [`BuildReport`].
+///
+/// Created with [`BuildReport::summary`].
+pub struct BuildSummary<'a> {
+    report: &'a BuildReport,
+    colored: bool,
+}
+
+impl<'a> BuildSummary<'a> {
+    pub(crate) fn new(report: &'a BuildReport) -> Self {
+        Self {
+            report,
+            colored: false,
+        }
+    }
+
+    /// Enables or disables ANSI color codes in the rendered summary.
+    ///
+    /// Disabled by default.
+    pub fn colored(mut self, colored: bool) -> Self {
+        self.colored = colored;
+        self
+    }
+
+    /// Wraps `text` in the given color code if coloring is enabled.
+    fn paint(&self, color: &str, text: String) -> String {
+        if self.colored {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text
+        }
+    }
+}
+
+impl fmt::Display for BuildSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors = self.report.errors().count();
+        let warnings = self.report.warnings().count();
+
+        let errors_text = plural(errors, "error");
+        let errors_text = match errors {
+            0 => self.paint(GREEN, errors_text),
+            _ => self.paint(RED, errors_text),
+        };
+        let warnings_text = plural(warnings, "warning");
+        let warnings_text = match warnings {
+            0 => warnings_text,
+            _ => self.paint(YELLOW, warnings_text),
+        };
+
+        write!(
+            f,
+            "{}: {}, {}",
+            self.paint(BOLD, "Build summary".to_string()),
+            errors_text,
+            warnings_text
+        )?;
+
+        if !self.report.phases.is_empty() {
+            for timing in &self.report.phases {
+                let label = match &timing.target {
+                    Some(target) => format!("{} ({})", timing.phase, target),
+                    None => timing.phase.to_string(),
+                };
+                write!(f, "\n  {:<10} {}", label, format_duration(timing.duration))?;
+                if timing.phase == Phase::Configure && self.report.cache_reused {
+                    write!(f, " (cache reused)")?;
+                }
+            }
+            write!(
+                f,
+                "\n  {:<10} {}",
+                "total",
+                format_duration(self.report.duration())
+            )?;
+        }
+
+        if let Some(stats) = &self.report.compiler_cache {
+            write!(
+                f,
+                "\nCompiler cache ({}): {} hits, {} misses",
+                stats.tool, stats.hits, stats.misses
+            )?;
+            if let Some(rate) = stats.hit_rate() {
+                write!(f, " ({:.0}% hit rate)", rate * 100.0)?;
+            }
+        }
+
+        if !self.report.artifacts.is_empty() {
+            write!(f, "\nArtifacts ({}):", self.report.artifacts.len())?;
+            for artifact in &self.report.artifacts {
+                write!(f, "\n  {}", artifact.display())?;
+            }
+        } else if self.report.header_only {
+            write!(
+                f,
+                "\nNo artifacts: the project only defines interface targets (use `install_only` to install its headers)"
+            )?;
+        }
+
+        if !self.report.include_dirs.is_empty() {
+            write!(
+                f,
+                "\nInclude directories ({}):",
+                self.report.include_dirs.len()
+            )?;
+            for dir in &self.report.include_dirs {
+                write!(f, "\n  {}", dir.display())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats `count` followed by `noun`, pluralized with a trailing `s`.
+pub(crate) fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        _ => format!("{} {}s", count, noun),
+    }
+}
+
+/// Formats a duration as seconds with two decimals (e.g. `12.34s`).
+pub(crate) fn format_duration(duration: Duration) -> String {
+    format!("{:.2}s", duration.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        diagnostics::{Diagnostic, Severity},
+        report::{PhaseTiming, ProcessExit},
+    };
+
+    #[test]
+    fn render_summary() {
+        let report = BuildReport {
+            diagnostics: vec![Diagnostic {
+                file: PathBuf::from("func.c"),
+                line: Some(3),
+                column: None,
+                severity: Severity::Warning,
+                code: None,
+                message: "unused variable".to_string(),
+            }],
+            phases: vec![
+                PhaseTiming {
+                    phase: Phase::Configure,
+                    target: None,
+                    command: Default::default(),
+                    started_at: std::time::SystemTime::UNIX_EPOCH,
+                    duration: Duration::from_millis(1500),
+                    exit: ProcessExit::default(),
+                    stdout: String::new(),
+                    stderr: String::new(),
+                },
+                PhaseTiming {
+                    phase: Phase::Build,
+                    target: None,
+                    command: Default::default(),
+                    started_at: std::time::SystemTime::UNIX_EPOCH,
+                    duration: Duration::from_millis(250),
+                    exit: ProcessExit::default(),
+                    stdout: String::new(),
+                    stderr: String::new(),
+                },
+            ],
+            artifacts: vec![PathBuf::from("bin/libtest_lib.so")],
+            ..Default::default()
+        };
+
+        let expected = "\
+Build summary: 0 errors, 1 warning
+  configure  1.50s
+  build      0.25s
+  total      1.75s
+Artifacts (1):
+  bin/libtest_lib.so";
+        assert_eq!(report.summary().to_string(), expected);
+        assert!(report.summary().colored(true).to_string().contains(YELLOW));
+    }
+}
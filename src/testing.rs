@@ -0,0 +1,187 @@
+//! Tiny CMake projects for the integration tests of crates that use cmakr.
+//!
+//! [`fixture_project`] writes a self-contained project of a
+//! [`FixtureKind`] into a temporary directory, so that a test can run a
+//! real configure and build without vendoring C sources. The directory is
+//! removed when the [`FixtureProject`] is dropped.
+//!
+//! | Kind | Project |
+//! |------|---------|
+//! | [`SharedLib`](FixtureKind::SharedLib) | a C shared library `fixture`, installed |
+//! | [`StaticLib`](FixtureKind::StaticLib) | a C static library `fixture`, installed |
+//! | [`Executable`](FixtureKind::Executable) | a C executable `fixture` that exits with 0, installed |
+//! | [`FailingConfigure`](FixtureKind::FailingConfigure) | a project whose configure fails with `FATAL_ERROR` |
+//! | [`SlowBuild`](FixtureKind::SlowBuild) | a static library and a target that sleeps for [`SLOW_BUILD_SECONDS`] |
+//!
+//! The sleep of a slow build can be changed with the `CMAKR_FIXTURE_SLEEP`
+//! cache variable, e.g. `add_define("CMAKR_FIXTURE_SLEEP", "2")`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::testing::{FixtureKind, fixture_project};
+//!
+//! let fixture = fixture_project(FixtureKind::SharedLib).unwrap();
+//! let report = fixture.cmd().build().unwrap();
+//! assert!(!report.artifacts.is_empty());
+//! ```
+
+use std::{io, path::PathBuf};
+
+use crate::{Cmd, guard::TempBuildDir};
+
+/// How long the build of a [`FixtureKind::SlowBuild`] sleeps by default.
+pub const SLOW_BUILD_SECONDS: u32 = 30;
+
+/// The name of the target of a fixture project.
+pub const FIXTURE_TARGET: &str = "fixture";
+
+/// The project a fixture contains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixtureKind {
+    SharedLib,
+    StaticLib,
+    Executable,
+    FailingConfigure,
+    SlowBuild,
+}
+
+/// A fixture project in a temporary directory, removed on drop.
+///
+/// The directory has the sources in `src` and room for the build tree in
+/// `build` and the artifacts in `out`.
+#[derive(Debug)]
+pub struct FixtureProject {
+    dir: TempBuildDir,
+    kind: FixtureKind,
+}
+
+impl FixtureProject {
+    /// Returns the kind of the project.
+    pub fn kind(&self) -> FixtureKind {
+        self.kind
+    }
+
+    /// Returns the source directory.
+    pub fn path(&self) -> PathBuf {
+        self.dir.path().join("src")
+    }
+
+    /// Returns the build directory [`cmd`](FixtureProject::cmd) uses.
+    pub fn build_dir(&self) -> PathBuf {
+        self.dir.path().join("build")
+    }
+
+    /// Returns the artifact output directory [`cmd`](FixtureProject::cmd)
+    /// uses.
+    pub fn output_dir(&self) -> PathBuf {
+        self.dir.path().join("out")
+    }
+
+    /// Returns a [`Cmd`] that builds the project in the fixture directory.
+    pub fn cmd(&self) -> Cmd {
+        Cmd::default()
+            .set_path(self.path().to_string_lossy())
+            .set_binary_path(self.build_dir().to_string_lossy())
+            .set_output_path(self.output_dir().to_string_lossy())
+    }
+
+    /// Keeps the directory instead of removing it, e.g. to inspect a failed
+    /// build, returning its path.
+    pub fn keep(self) -> PathBuf {
+        self.dir.keep()
+    }
+}
+
+/// Writes a project of `kind` into a new temporary directory.
+///
+/// # Arguments
+///
+/// * `kind` - The project to write.
+///
+/// # Errors
+///
+/// Returns an error if the directory or a file cannot be created.
+pub fn fixture_project(kind: FixtureKind) -> io::Result<FixtureProject> {
+    let dir = TempBuildDir::new("fixture")?;
+    let source_dir = dir.path().join("src");
+    std::fs::create_dir_all(&source_dir)?;
+    for (name, content) in files(kind) {
+        std::fs::write(source_dir.join(name), content)?;
+    }
+    Ok(FixtureProject { dir, kind })
+}
+
+/// Returns the files of a project of `kind`, by name.
+fn files(kind: FixtureKind) -> Vec<(&'static str, String)> {
+    let mut lists = String::from("cmake_minimum_required(VERSION 3.13)\nproject(fixture C)\n");
+    let library = |ty: &str| {
+        format!(
+            "set(CMAKE_WINDOWS_EXPORT_ALL_SYMBOLS ON)\n\
+             add_library({0} {1} fixture.c)\n\
+             target_include_directories({0} PUBLIC ${{CMAKE_CURRENT_SOURCE_DIR}})\n\
+             install(TARGETS {0})\n",
+            FIXTURE_TARGET, ty
+        )
+    };
+    let mut files = vec![
+        ("fixture.h", "int fixture_answer(void);\n".to_string()),
+        (
+            "fixture.c",
+            "#include \"fixture.h\"\n\nint fixture_answer(void) { return 42; }\n".to_string(),
+        ),
+    ];
+    match kind {
+        FixtureKind::SharedLib => lists.push_str(&library("SHARED")),
+        FixtureKind::StaticLib => lists.push_str(&library("STATIC")),
+        FixtureKind::Executable => {
+            lists.push_str(&format!(
+                "add_executable({0} main.c fixture.c)\ninstall(TARGETS {0})\n",
+                FIXTURE_TARGET
+            ));
+            files.push((
+                "main.c",
+                "#include \"fixture.h\"\n\nint main(void) { return fixture_answer() == 42 ? 0 : 1; }\n"
+                    .to_string(),
+            ));
+        }
+        FixtureKind::FailingConfigure => {
+            lists.push_str("message(FATAL_ERROR \"cmakr fixture: configure fails on purpose\")\n")
+        }
+        FixtureKind::SlowBuild => {
+            lists.push_str(&library("STATIC"));
+            lists.push_str(&format!(
+                "set(CMAKR_FIXTURE_SLEEP {} CACHE STRING \"Seconds the build sleeps\")\n\
+                 add_custom_target(slow ALL COMMAND ${{CMAKE_COMMAND}} -E sleep ${{CMAKR_FIXTURE_SLEEP}})\n",
+                SLOW_BUILD_SECONDS
+            ));
+        }
+    }
+    files.push(("CMakeLists.txt", lists));
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_fixture_projects() {
+        let fixture = fixture_project(FixtureKind::Executable).unwrap();
+        let lists = std::fs::read_to_string(fixture.path().join("CMakeLists.txt")).unwrap();
+        assert!(lists.contains("add_executable(fixture main.c fixture.c)"));
+        assert!(fixture.path().join("main.c").is_file());
+        let configure = &fixture.cmd().dry_run().unwrap().commands[0];
+        let b = configure.args.iter().position(|arg| arg == "-B").unwrap();
+        assert!(configure.args[b + 1].ends_with("build"));
+
+        let fixture = fixture_project(FixtureKind::SlowBuild).unwrap();
+        let lists = std::fs::read_to_string(fixture.path().join("CMakeLists.txt")).unwrap();
+        assert!(lists.contains("add_library(fixture STATIC fixture.c)"));
+        assert!(lists.contains("set(CMAKR_FIXTURE_SLEEP 30 CACHE STRING"));
+
+        let path = fixture.path();
+        drop(fixture);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,509 @@
+//! Generated CMake toolchain files.
+//!
+//! A [`ToolchainFile`] describes a cross-compilation toolchain with typed
+//! fields: the target system, the compilers and their flags, the sysroot,
+//! and where `find_*` commands may look. Build one with
+//! [`ToolchainFile::builder`] and hand it to
+//! [`Cmd::set_toolchain`](crate::Cmd::set_toolchain), which writes it into the
+//! build directory (`.cmakr/toolchain.cmake`) and configures with it. The
+//! [bare-metal helpers](crate::embedded) are built on it.
+//!
+//! [`ToolchainFile::for_rust_target`] derives one from a Rust target
+//! triple, as Cargo passes it to build scripts in `TARGET`;
+//! [`Cmd::cross_compile_for_cargo`](crate::Cmd::cross_compile_for_cargo)
+//! uses it when the target is not the host.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, toolchain::ToolchainFile};
+//!
+//! let toolchain = ToolchainFile::builder()
+//!     .system_name("Linux")
+//!     .system_processor("aarch64")
+//!     .c_compiler("aarch64-linux-gnu-gcc")
+//!     .cxx_compiler("aarch64-linux-gnu-g++")
+//!     .sysroot("/opt/sysroots/aarch64")
+//!     .cross_find_modes()
+//!     .build();
+//! Cmd::default()
+//!     .set_path("./my_project")
+//!     .set_toolchain(toolchain)
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::path::{Path, PathBuf};
+
+/// Where a kind of `find_*` command searches, relative to `CMAKE_FIND_ROOT_PATH`
+/// and the sysroot (`CMAKE_FIND_ROOT_PATH_MODE_*`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindRootMode {
+    /// Only on the host, ignoring the root paths.
+    Never,
+    /// Only under the root paths.
+    Only,
+    /// Under the root paths first, then on the host.
+    Both,
+}
+
+impl FindRootMode {
+    fn name(self) -> &'static str {
+        match self {
+            FindRootMode::Never => "NEVER",
+            FindRootMode::Only => "ONLY",
+            FindRootMode::Both => "BOTH",
+        }
+    }
+}
+
+/// A kind of `find_*` command, whose search is set by a [`FindRootMode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FindTarget {
+    /// `find_program`.
+    Program,
+    /// `find_library`.
+    Library,
+    /// `find_path` and `find_file`.
+    Include,
+    /// `find_package`.
+    Package,
+}
+
+impl FindTarget {
+    fn name(self) -> &'static str {
+        match self {
+            FindTarget::Program => "PROGRAM",
+            FindTarget::Library => "LIBRARY",
+            FindTarget::Include => "INCLUDE",
+            FindTarget::Package => "PACKAGE",
+        }
+    }
+}
+
+/// A CMake toolchain file, created with [`ToolchainFile::builder`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToolchainFile {
+    system_name: Option<String>,
+    system_processor: Option<String>,
+    /// Compilers by language (`C`, `CXX`, `ASM`).
+    compilers: Vec<(&'static str, String)>,
+    /// Flags for every language (`CMAKE_<LANG>_FLAGS_INIT`).
+    flags: Vec<String>,
+    /// Flags for linking executables and shared libraries.
+    linker_flags: Vec<String>,
+    sysroot: Option<PathBuf>,
+    find_root_paths: Vec<PathBuf>,
+    find_root_modes: Vec<(FindTarget, FindRootMode)>,
+    try_compile_static_library: bool,
+    /// Other variables, in the order they were added.
+    variables: Vec<(String, String)>,
+}
+
+impl ToolchainFile {
+    /// Returns a builder for a toolchain file.
+    pub fn builder() -> ToolchainFileBuilder {
+        ToolchainFileBuilder::default()
+    }
+
+    /// Returns a builder with the CMake settings of a Rust target triple
+    /// (e.g. `"aarch64-unknown-linux-gnu"`), or `None` if the target's
+    /// system is not known to CMake (e.g. `wasm32-unknown-unknown`).
+    ///
+    /// The builder sets the system name and processor as CMake names them
+    /// (`Linux`/`aarch64`, `Windows`/`ARM64`, `Android`/`armv7-a`, ...). For
+    /// Linux, MinGW, and bare-metal ARM targets it also sets the GCC cross
+    /// compilers by their usual prefix (`aarch64-linux-gnu-gcc`,
+    /// `x86_64-w64-mingw32-gcc`, `arm-none-eabi-gcc`) and the find modes of
+    /// a cross build; for Android the ABI, and for Apple targets the
+    /// architecture. Bare-metal targets build try-compiles as static
+    /// libraries.
+    ///
+    /// # Arguments
+    ///
+    /// * `triple` - The Rust target triple.
+    pub fn for_rust_target(triple: &str) -> Option<ToolchainFileBuilder> {
+        let (arch, rest) = triple.split_once('-')?;
+        let parts: Vec<&str> = rest.split('-').collect();
+        let has = |part: &str| parts.contains(&part);
+        let env = parts.last().copied().unwrap_or_default();
+        let android = parts.iter().any(|part| part.starts_with("android"));
+        let system_name = if android {
+            "Android"
+        } else if has("linux") {
+            "Linux"
+        } else if has("windows") {
+            "Windows"
+        } else if has("darwin") {
+            "Darwin"
+        } else if has("ios") {
+            "iOS"
+        } else if has("tvos") {
+            "tvOS"
+        } else if has("watchos") {
+            "watchOS"
+        } else if has("freebsd") {
+            "FreeBSD"
+        } else if has("netbsd") {
+            "NetBSD"
+        } else if has("openbsd") {
+            "OpenBSD"
+        } else if has("emscripten") {
+            "Emscripten"
+        } else if has("none") {
+            "Generic"
+        } else {
+            return None;
+        };
+        let apple = has("apple");
+        let arm = arch.starts_with("arm") || arch.starts_with("thumb");
+        let processor = match arch {
+            "x86_64" if system_name == "Windows" => "AMD64",
+            "i586" | "i686" if system_name == "Windows" => "X86",
+            "i586" | "i686" => "i686",
+            "aarch64" | "arm64" | "arm64e" if apple => "arm64",
+            "aarch64" if system_name == "Windows" => "ARM64",
+            "arm64ec" => "ARM64",
+            _ if arm && android => "armv7-a",
+            _ if arm => "arm",
+            _ if arch.starts_with("riscv64") => "riscv64",
+            _ if arch.starts_with("riscv32") => "riscv32",
+            _ => arch,
+        };
+
+        let mut builder = ToolchainFile::builder()
+            .system_name(system_name)
+            .system_processor(processor);
+        let gnu_arch = match processor {
+            "arm" | "i686" | "riscv64" | "riscv32" => processor,
+            _ => arch,
+        };
+        let gcc_prefix = match system_name {
+            "Linux" => Some(format!("{}-linux-{}", gnu_arch, env)),
+            "Windows" if env == "gnu" => Some(format!("{}-w64-mingw32", gnu_arch)),
+            "Generic" if arm => Some("arm-none-eabi".to_string()),
+            _ => None,
+        };
+        if let Some(prefix) = gcc_prefix {
+            builder = builder
+                .c_compiler(format!("{}-gcc", prefix))
+                .cxx_compiler(format!("{}-g++", prefix))
+                .cross_find_modes();
+        }
+        if system_name == "Generic" {
+            builder = builder.try_compile_static_library(true);
+        }
+        if android {
+            let abi = match processor {
+                "aarch64" => "arm64-v8a",
+                "armv7-a" => "armeabi-v7a",
+                "i686" => "x86",
+                _ => processor,
+            };
+            builder = builder.set_variable("CMAKE_ANDROID_ARCH_ABI", abi);
+        }
+        if apple {
+            builder = builder.set_variable("CMAKE_OSX_ARCHITECTURES", processor);
+            if system_name == "iOS" && (env == "sim" || arch == "x86_64") {
+                builder = builder.set_variable("CMAKE_OSX_SYSROOT", "iphonesimulator");
+            }
+        }
+        Some(builder)
+    }
+
+    /// Returns the contents of the `.cmake` file.
+    pub fn contents(&self) -> String {
+        let mut file = String::from("# generated by cmakr\n");
+        let mut set = |name: &str, value: &str| {
+            file.push_str(&format!("set({} {})\n", name, quote(value)));
+        };
+        if let Some(name) = &self.system_name {
+            set("CMAKE_SYSTEM_NAME", name);
+        }
+        if let Some(processor) = &self.system_processor {
+            set("CMAKE_SYSTEM_PROCESSOR", processor);
+        }
+        if self.try_compile_static_library {
+            set("CMAKE_TRY_COMPILE_TARGET_TYPE", "STATIC_LIBRARY");
+        }
+        if let Some(sysroot) = &self.sysroot {
+            set("CMAKE_SYSROOT", &cmake_path(sysroot));
+        }
+        for (language, compiler) in &self.compilers {
+            set(&format!("CMAKE_{}_COMPILER", language), compiler);
+        }
+        if !self.flags.is_empty() {
+            let flags = self.flags.join(" ");
+            for language in ["C", "CXX", "ASM"] {
+                set(&format!("CMAKE_{}_FLAGS_INIT", language), &flags);
+            }
+        }
+        if !self.linker_flags.is_empty() {
+            let flags = self.linker_flags.join(" ");
+            set("CMAKE_EXE_LINKER_FLAGS_INIT", &flags);
+            set("CMAKE_SHARED_LINKER_FLAGS_INIT", &flags);
+        }
+        if !self.find_root_paths.is_empty() {
+            let paths: Vec<String> = self.find_root_paths.iter().map(|p| cmake_path(p)).collect();
+            set("CMAKE_FIND_ROOT_PATH", &paths.join(";"));
+        }
+        for (target, mode) in &self.find_root_modes {
+            set(
+                &format!("CMAKE_FIND_ROOT_PATH_MODE_{}", target.name()),
+                mode.name(),
+            );
+        }
+        for (name, value) in &self.variables {
+            set(name, value);
+        }
+        file
+    }
+}
+
+/// A builder for a [`ToolchainFile`].
+#[derive(Debug, Clone, Default)]
+pub struct ToolchainFileBuilder {
+    toolchain: ToolchainFile,
+}
+
+impl ToolchainFileBuilder {
+    /// Sets the target system (`CMAKE_SYSTEM_NAME`, e.g. `"Linux"`,
+    /// `"Generic"` for bare metal).
+    pub fn system_name<T>(mut self, name: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.toolchain.system_name = Some(name.into());
+        self
+    }
+
+    /// Sets the target processor (`CMAKE_SYSTEM_PROCESSOR`, e.g. `"aarch64"`).
+    pub fn system_processor<T>(mut self, processor: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.toolchain.system_processor = Some(processor.into());
+        self
+    }
+
+    /// Sets the C compiler (`CMAKE_C_COMPILER`), a name on `PATH` or a path.
+    pub fn c_compiler<T>(self, compiler: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.compiler("C", compiler.into())
+    }
+
+    /// Sets the C++ compiler (`CMAKE_CXX_COMPILER`), a name on `PATH` or a path.
+    pub fn cxx_compiler<T>(self, compiler: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.compiler("CXX", compiler.into())
+    }
+
+    /// Sets the assembler (`CMAKE_ASM_COMPILER`), a name on `PATH` or a path.
+    pub fn asm_compiler<T>(self, compiler: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.compiler("ASM", compiler.into())
+    }
+
+    /// Adds a flag passed to every compile and link of C, C++, and
+    /// assembly (`CMAKE_<LANG>_FLAGS_INIT`), e.g. `"-mcpu=cortex-m4"`.
+    pub fn add_flag<T>(mut self, flag: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.toolchain.flags.push(flag.into());
+        self
+    }
+
+    /// Adds a flag passed when linking executables and shared libraries
+    /// (`CMAKE_EXE_LINKER_FLAGS_INIT`, `CMAKE_SHARED_LINKER_FLAGS_INIT`).
+    pub fn add_linker_flag<T>(mut self, flag: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.toolchain.linker_flags.push(flag.into());
+        self
+    }
+
+    /// Sets the sysroot (`CMAKE_SYSROOT`) and adds it to the root paths.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the target's root filesystem.
+    pub fn sysroot<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        let path = PathBuf::from(path.into());
+        self.toolchain.find_root_paths.push(path.clone());
+        self.toolchain.sysroot = Some(path);
+        self
+    }
+
+    /// Adds a root path under which `find_*` commands search
+    /// (`CMAKE_FIND_ROOT_PATH`), e.g. a staging directory.
+    pub fn add_find_root_path<T>(mut self, path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.toolchain
+            .find_root_paths
+            .push(PathBuf::from(path.into()));
+        self
+    }
+
+    /// Sets where one kind of `find_*` command searches.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The kind of command.
+    /// * `mode` - Where it searches.
+    pub fn find_root_mode(mut self, target: FindTarget, mode: FindRootMode) -> Self {
+        self.toolchain
+            .find_root_modes
+            .retain(|(set, _)| *set != target);
+        self.toolchain.find_root_modes.push((target, mode));
+        self
+    }
+
+    /// Sets the find modes of a typical cross build: programs are searched
+    /// on the host only, and libraries, headers, and packages under the root
+    /// paths only.
+    pub fn cross_find_modes(self) -> Self {
+        self.find_root_mode(FindTarget::Program, FindRootMode::Never)
+            .find_root_mode(FindTarget::Library, FindRootMode::Only)
+            .find_root_mode(FindTarget::Include, FindRootMode::Only)
+            .find_root_mode(FindTarget::Package, FindRootMode::Only)
+    }
+
+    /// Makes try-compiles build static libraries instead of executables
+    /// (`CMAKE_TRY_COMPILE_TARGET_TYPE`), for targets where programs can't
+    /// be linked without a startup file and linker script.
+    pub fn try_compile_static_library(mut self, enabled: bool) -> Self {
+        self.toolchain.try_compile_static_library = enabled;
+        self
+    }
+
+    /// Sets any other variable in the toolchain file, e.g. `CMAKE_OBJCOPY`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The variable name.
+    /// * `value` - The value, quoted in the file.
+    pub fn set_variable<T, U>(mut self, name: T, value: U) -> Self
+    where
+        T: Into<String>,
+        U: Into<String>,
+    {
+        self.toolchain.variables.push((name.into(), value.into()));
+        self
+    }
+
+    /// Returns the toolchain file.
+    pub fn build(self) -> ToolchainFile {
+        self.toolchain
+    }
+
+    fn compiler(mut self, language: &'static str, compiler: String) -> Self {
+        self.toolchain.compilers.retain(|(set, _)| *set != language);
+        self.toolchain.compilers.push((language, compiler));
+        self
+    }
+}
+
+/// Quotes a value as a CMake bracket-free quoted argument.
+fn quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "\\$");
+    format!("\"{}\"", escaped)
+}
+
+/// Renders a path with forward slashes, as CMake expects.
+fn cmake_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_toolchain_file() {
+        let toolchain = ToolchainFile::builder()
+            .system_name("Linux")
+            .system_processor("aarch64")
+            .c_compiler("aarch64-linux-gnu-gcc")
+            .c_compiler("/opt/bin/aarch64-linux-gnu-gcc")
+            .add_flag("-march=armv8-a")
+            .add_flag("-DNAME=\"a$b\"")
+            .sysroot("/opt/sysroot")
+            .add_find_root_path("/opt/stage")
+            .cross_find_modes()
+            .find_root_mode(FindTarget::Package, FindRootMode::Both)
+            .set_variable("CMAKE_OBJCOPY", "aarch64-linux-gnu-objcopy")
+            .build();
+        assert_eq!(
+            toolchain.contents(),
+            "# generated by cmakr\n\
+             set(CMAKE_SYSTEM_NAME \"Linux\")\n\
+             set(CMAKE_SYSTEM_PROCESSOR \"aarch64\")\n\
+             set(CMAKE_SYSROOT \"/opt/sysroot\")\n\
+             set(CMAKE_C_COMPILER \"/opt/bin/aarch64-linux-gnu-gcc\")\n\
+             set(CMAKE_C_FLAGS_INIT \"-march=armv8-a -DNAME=\\\"a\\$b\\\"\")\n\
+             set(CMAKE_CXX_FLAGS_INIT \"-march=armv8-a -DNAME=\\\"a\\$b\\\"\")\n\
+             set(CMAKE_ASM_FLAGS_INIT \"-march=armv8-a -DNAME=\\\"a\\$b\\\"\")\n\
+             set(CMAKE_FIND_ROOT_PATH \"/opt/sysroot;/opt/stage\")\n\
+             set(CMAKE_FIND_ROOT_PATH_MODE_PROGRAM \"NEVER\")\n\
+             set(CMAKE_FIND_ROOT_PATH_MODE_LIBRARY \"ONLY\")\n\
+             set(CMAKE_FIND_ROOT_PATH_MODE_INCLUDE \"ONLY\")\n\
+             set(CMAKE_FIND_ROOT_PATH_MODE_PACKAGE \"BOTH\")\n\
+             set(CMAKE_OBJCOPY \"aarch64-linux-gnu-objcopy\")\n"
+        );
+    }
+
+    #[test]
+    fn map_rust_targets() {
+        let contents = |triple: &str| {
+            ToolchainFile::for_rust_target(triple)
+                .map(|builder| builder.build().contents())
+                .unwrap_or_default()
+        };
+        let linux = contents("armv7-unknown-linux-gnueabihf");
+        assert!(linux.contains("set(CMAKE_SYSTEM_NAME \"Linux\")\n"));
+        assert!(linux.contains("set(CMAKE_SYSTEM_PROCESSOR \"arm\")\n"));
+        assert!(linux.contains("set(CMAKE_C_COMPILER \"arm-linux-gnueabihf-gcc\")\n"));
+        assert!(linux.contains("set(CMAKE_FIND_ROOT_PATH_MODE_LIBRARY \"ONLY\")\n"));
+        assert!(
+            contents("aarch64-unknown-linux-gnu")
+                .contains("set(CMAKE_CXX_COMPILER \"aarch64-linux-gnu-g++\")\n")
+        );
+        assert!(
+            contents("x86_64-pc-windows-gnu")
+                .contains("set(CMAKE_C_COMPILER \"x86_64-w64-mingw32-gcc\")\n")
+        );
+
+        let msvc = contents("aarch64-pc-windows-msvc");
+        assert!(msvc.contains("set(CMAKE_SYSTEM_PROCESSOR \"ARM64\")\n"));
+        assert!(!msvc.contains("COMPILER"));
+        assert!(contents("armv7-linux-androideabi").contains(
+            "set(CMAKE_SYSTEM_NAME \"Android\")\n\
+             set(CMAKE_SYSTEM_PROCESSOR \"armv7-a\")\n\
+             set(CMAKE_ANDROID_ARCH_ABI \"armeabi-v7a\")\n"
+        ));
+        assert!(
+            contents("aarch64-apple-ios-sim").contains("set(CMAKE_OSX_ARCHITECTURES \"arm64\")\n")
+        );
+        let firmware = contents("thumbv7em-none-eabihf");
+        assert!(firmware.contains("set(CMAKE_SYSTEM_NAME \"Generic\")\n"));
+        assert!(firmware.contains("set(CMAKE_TRY_COMPILE_TARGET_TYPE \"STATIC_LIBRARY\")\n"));
+        assert!(firmware.contains("set(CMAKE_C_COMPILER \"arm-none-eabi-gcc\")\n"));
+        assert!(ToolchainFile::for_rust_target("wasm32-unknown-unknown").is_none());
+    }
+}
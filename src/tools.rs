@@ -0,0 +1,164 @@
+//! Locating cmake and the tools that come with it.
+//!
+//! Unless set explicitly, `cmake` is the one named by the `CMAKE` environment
+//! variable (as with the `cmake` crate), or else the first one on `PATH`.
+//! `ctest` and `cpack` are taken from the directory of that cmake when they
+//! are installed next to it, so a cmake outside `PATH` comes with matching
+//! tools, and are looked up on `PATH` otherwise.
+//!
+//! The lookups use the `PATH` and working directory cmake will run with,
+//! as set with [`Cmd::env`](crate::Cmd::env) and
+//! [`Cmd::current_dir`](crate::Cmd::current_dir).
+
+use std::{
+    env::consts::EXE_SUFFIX,
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+};
+
+use crate::error::Error;
+
+/// The programs a [`Cmd`](crate::Cmd) runs, where set explicitly.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Tools {
+    pub(crate) cmake: Option<PathBuf>,
+    pub(crate) ctest: Option<PathBuf>,
+    pub(crate) cpack: Option<PathBuf>,
+    pub(crate) ninja: Option<PathBuf>,
+    /// The `PATH` the programs run with, if not the one of this process.
+    pub(crate) search_path: Option<OsString>,
+    /// The directory the programs run in, if not the one of this process.
+    pub(crate) current_dir: Option<PathBuf>,
+}
+
+impl Tools {
+    /// Finds the cmake executable.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidConfig`] if the explicitly set executable does
+    /// not exist, and [`Error::CMakeNotFound`] if no cmake is found otherwise.
+    pub(crate) fn find_cmake(&self) -> Result<PathBuf, Error> {
+        if let Some(cmake) = &self.cmake {
+            return self.which(cmake).map_err(|_| {
+                Error::InvalidConfig(format!("cmake executable {} not found", cmake.display()))
+            });
+        }
+        if let Some(cmake) = std::env::var_os("CMAKE").filter(|cmake| !cmake.is_empty()) {
+            return self.which(&cmake).map_err(|_| Error::CMakeNotFound);
+        }
+        self.which("cmake").map_err(|_| Error::CMakeNotFound)
+    }
+
+    /// Resolves `program` against the `PATH` and working directory the
+    /// programs run with.
+    fn which<T>(&self, program: T) -> which::Result<PathBuf>
+    where
+        T: AsRef<OsStr>,
+    {
+        let search_path = self
+            .search_path
+            .clone()
+            .or_else(|| std::env::var_os("PATH"));
+        let current_dir = match &self.current_dir {
+            Some(dir) => dir.clone(),
+            None => std::env::current_dir()
+                .map_err(|_| which::Error::CannotGetCurrentDirAndPathListEmpty)?,
+        };
+        which::which_in(program, search_path, current_dir)
+    }
+
+    /// Returns the cmake program to run, falling back to `cmake` on `PATH`
+    /// where cmake is not required to exist.
+    pub(crate) fn cmake(&self) -> PathBuf {
+        self.find_cmake().unwrap_or_else(|_| PathBuf::from("cmake"))
+    }
+
+    /// Returns the ctest program to run.
+    pub(crate) fn ctest(&self) -> PathBuf {
+        self.companion(self.ctest.as_deref(), "ctest")
+    }
+
+    /// Returns the cpack program to run.
+    pub(crate) fn cpack(&self) -> PathBuf {
+        self.companion(self.cpack.as_deref(), "cpack")
+    }
+
+    /// Returns the ninja program to run.
+    pub(crate) fn ninja(&self) -> PathBuf {
+        self.ninja.clone().unwrap_or_else(|| PathBuf::from("ninja"))
+    }
+
+    /// Returns `explicit`, or the tool `name` installed next to cmake, or
+    /// else `name` to be looked up on `PATH`.
+    fn companion(&self, explicit: Option<&Path>, name: &str) -> PathBuf {
+        if let Some(explicit) = explicit {
+            return explicit.to_path_buf();
+        }
+        self.find_cmake()
+            .ok()
+            .and_then(|cmake| Some(cmake.parent()?.join(format!("{}{}", name, EXE_SUFFIX))))
+            .filter(|sibling| sibling.is_file())
+            .unwrap_or_else(|| PathBuf::from(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn find_tools_next_to_cmake() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("tools").unwrap();
+        let bin = temp.path().canonicalize().unwrap();
+        for tool in ["cmake", "ctest"] {
+            std::fs::write(bin.join(tool), "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(bin.join(tool), std::fs::Permissions::from_mode(0o755))
+                .unwrap();
+        }
+
+        let tools = Tools {
+            cmake: Some(bin.join("cmake")),
+            ..Tools::default()
+        };
+        assert_eq!(tools.find_cmake().unwrap(), bin.join("cmake"));
+        assert_eq!(tools.ctest(), bin.join("ctest"));
+        assert_eq!(tools.cpack(), PathBuf::from("cpack"));
+        assert_eq!(tools.ninja(), PathBuf::from("ninja"));
+
+        let missing = Tools {
+            cmake: Some(bin.join("missing")),
+            ..Tools::default()
+        };
+        assert!(matches!(missing.find_cmake(), Err(Error::InvalidConfig(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_cmake_on_run_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = crate::guard::TempBuildDir::new("tools-path").unwrap();
+        let bin = temp.path().canonicalize().unwrap().join("bin");
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::write(bin.join("cmake"), "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(bin.join("cmake"), std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+
+        let tools = Tools {
+            search_path: Some(bin.clone().into()),
+            ..Tools::default()
+        };
+        assert_eq!(tools.find_cmake().unwrap(), bin.join("cmake"));
+
+        let relative = Tools {
+            cmake: Some(PathBuf::from("bin/cmake")),
+            current_dir: Some(bin.parent().unwrap().to_path_buf()),
+            ..Tools::default()
+        };
+        assert_eq!(relative.find_cmake().unwrap(), bin.join("cmake"));
+    }
+}
@@ -0,0 +1,146 @@
+//! Traces of CMake's configure step, for debugging the build system.
+//!
+//! With [`Cmd::trace`](crate::Cmd::trace), configure runs with
+//! `--trace-expand` and writes every CMake command it executes, with its
+//! variables expanded, to a file in the `.cmakr` directory of the build tree
+//! instead of the output. In the [`Json`](TraceFormat::Json) format
+//! (`--trace-format=json-v1`), [`BuildReport::trace`](crate::report::BuildReport::trace)
+//! reads it back as [`TraceEvent`]s:
+//!
+//! | Format | File | Read with |
+//! |--------|------|-----------|
+//! | [`Human`](TraceFormat::Human) | `.cmakr/trace.txt` | a text editor |
+//! | [`Json`](TraceFormat::Json) | `.cmakr/trace.json` | [`BuildReport::trace`](crate::report::BuildReport::trace), [`parse`] |
+//!
+//! Traces are large and slow configure down noticeably, so they are meant
+//! for debugging runs rather than every build.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, trace::TraceFormat};
+//!
+//! let report = Cmd::default()
+//!     .set_path("./native")
+//!     .trace(TraceFormat::Json)
+//!     .configure()
+//!     .unwrap();
+//! for event in report.trace().unwrap().unwrap_or_default() {
+//!     if event.command == "find_package" {
+//!         println!("{}:{} find_package({})", event.file.display(), event.line, event.args.join(" "));
+//!     }
+//! }
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::gc::STATE_DIR;
+
+/// The trace file of the human-readable format, in the state directory.
+const HUMAN_FILE: &str = "trace.txt";
+
+/// The trace file of the JSON format, in the state directory.
+const JSON_FILE: &str = "trace.json";
+
+/// The format of a configure trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    /// CMake's plain text trace, one command per line.
+    #[default]
+    Human,
+    /// One JSON object per command (`--trace-format=json-v1`).
+    Json,
+}
+
+impl TraceFormat {
+    /// Returns the configure arguments writing the trace into the build tree
+    /// `binary_dir`.
+    pub(crate) fn args(self, binary_dir: &Path) -> Vec<String> {
+        let mut args = vec!["--trace-expand".to_string()];
+        if self == TraceFormat::Json {
+            args.push("--trace-format=json-v1".to_string());
+        }
+        args.push(format!(
+            "--trace-redirect={}",
+            self.file(binary_dir).display()
+        ));
+        args
+    }
+
+    /// Returns the trace file in the build tree `binary_dir`.
+    pub(crate) fn file(self, binary_dir: &Path) -> PathBuf {
+        let name = match self {
+            TraceFormat::Human => HUMAN_FILE,
+            TraceFormat::Json => JSON_FILE,
+        };
+        binary_dir.join(STATE_DIR).join(name)
+    }
+}
+
+/// A CMake command executed during configure.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceEvent {
+    /// The file the command is in.
+    pub file: PathBuf,
+    /// The line the command starts on.
+    pub line: u64,
+    /// The line the command ends on (CMake 3.19 and later).
+    #[serde(default)]
+    pub line_end: Option<u64>,
+    /// The command name, as written (e.g. `"find_package"`).
+    #[serde(rename = "cmd")]
+    pub command: String,
+    /// The arguments, with variables expanded.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// When the command ran, in seconds since the Unix epoch.
+    pub time: f64,
+    /// The depth of the command in the current file's call stack.
+    pub frame: u64,
+    /// The depth of the command in the whole call stack (CMake 3.19 and
+    /// later).
+    #[serde(default)]
+    pub global_frame: Option<u64>,
+    /// The id of the `cmake_language(DEFER)` call that ran the command.
+    #[serde(default)]
+    pub defer: Option<String>,
+}
+
+/// Parses a `json-v1` trace, skipping its version line.
+///
+/// # Errors
+///
+/// Returns an error if a line is not a trace event.
+pub fn parse(trace: &str) -> Result<Vec<TraceEvent>, serde_json::Error> {
+    trace
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with("{\"version\""))
+        .map(serde_json::from_str)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_json_trace() {
+        let trace = r#"{"version":{"major":1,"minor":2}}
+{"args":["VERSION","3.20"],"cmd":"cmake_minimum_required","file":"/src/CMakeLists.txt","frame":1,"global_frame":1,"line":1,"line_end":1,"time":1700000000.25}
+{"args":["ZLIB","REQUIRED"],"cmd":"find_package","file":"/src/CMakeLists.txt","frame":1,"line":4,"time":1700000000.5}
+"#;
+        let events = parse(trace).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].command, "find_package");
+        assert_eq!(events[1].args, ["ZLIB", "REQUIRED"]);
+        assert_eq!(events[1].line, 4);
+        assert_eq!(events[1].global_frame, None);
+        assert_eq!(events[0].line_end, Some(1));
+
+        let args = TraceFormat::Json.args(Path::new("/build"));
+        assert_eq!(args[1], "--trace-format=json-v1");
+        assert!(args[2].starts_with("--trace-redirect=/build/.cmakr/trace.json"));
+    }
+}
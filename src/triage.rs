@@ -0,0 +1,423 @@
+//! Likely root causes of a failed run, built with the `triage` feature.
+//!
+//! [`triage`] looks at the output and diagnostics of a failed run's
+//! [`BuildReport`] and at the cache of its build tree, and returns the
+//! [`Finding`]s of a set of rules, most likely first. Each finding names a
+//! [`Cause`], the line it is based on, and a suggested change to the
+//! [`Cmd`](crate::Cmd), for tools that want to explain a failure instead of
+//! printing the build log:
+//!
+//! | Cause | Recognized from |
+//! |-------|-----------------|
+//! | [`MissingDependency`](Cause::MissingDependency) | `find_package` and `pkg_check_modules` failures, missing headers and libraries |
+//! | [`WrongGenerator`](Cause::WrongGenerator) | a generator or its build tool that cannot be used |
+//! | [`StaleCache`](Cause::StaleCache) | a cache of another source directory, generator, or platform |
+//! | [`CompilerTooOld`](Cause::CompilerTooOld) | a language standard the compiler or CMake doesn't know |
+//!
+//! Rules of a project's own, e.g. for the message of a failed version
+//! check, are added with [`Triage::add_rule`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, triage};
+//!
+//! if let Err(e) = Cmd::default().set_path("./my_project").build() {
+//!     if let Some(report) = e.report() {
+//!         for finding in triage::triage(report) {
+//!             eprintln!("{:?}: {}\n  {}", finding.cause, finding.evidence, finding.suggestion);
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::{cache::CMakeCache, cmake, report::BuildReport};
+
+/// A kind of root cause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Cause {
+    /// A package, header, or library the project needs is not installed or
+    /// not found.
+    MissingDependency,
+    /// The generator or its build tool cannot be used on this host.
+    WrongGenerator,
+    /// The build tree was configured for another source directory,
+    /// generator, or platform.
+    StaleCache,
+    /// The compiler or CMake doesn't support the requested language
+    /// standard.
+    CompilerTooOld,
+}
+
+/// A likely root cause of a failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// The kind of root cause.
+    pub cause: Cause,
+    /// How likely the cause is, from 1 to 100. Findings are ranked by it.
+    pub confidence: u8,
+    /// The line of output or the cache entry the finding is based on.
+    pub evidence: String,
+    /// What to change, as [`Cmd`](crate::Cmd) calls where possible.
+    pub suggestion: String,
+}
+
+/// What the rules look at.
+#[non_exhaustive]
+pub struct Evidence<'a> {
+    /// The report of the failed run.
+    pub report: &'a BuildReport,
+    /// The output of the phases of the run, the failed one first.
+    pub output: String,
+    /// The cache of the build tree, if it has one.
+    pub cache: Option<CMakeCache>,
+}
+
+impl Evidence<'_> {
+    /// Returns the lines of the output, trimmed.
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.output.lines().map(str::trim)
+    }
+}
+
+/// A rule recognizing root causes.
+///
+/// Closures taking an `&Evidence` implement this trait.
+pub trait Rule: Send + Sync {
+    /// Returns the findings of the rule, empty if it doesn't apply.
+    fn check(&self, evidence: &Evidence<'_>) -> Vec<Finding>;
+}
+
+impl<F> Rule for F
+where
+    F: Fn(&Evidence<'_>) -> Vec<Finding> + Send + Sync,
+{
+    fn check(&self, evidence: &Evidence<'_>) -> Vec<Finding> {
+        self(evidence)
+    }
+}
+
+/// A set of rules, the built-in ones by default.
+pub struct Triage {
+    rules: Vec<Box<dyn Rule>>,
+}
+
+impl Default for Triage {
+    fn default() -> Self {
+        Self {
+            rules: vec![
+                Box::new(missing_dependency),
+                Box::new(wrong_generator),
+                Box::new(stale_cache),
+                Box::new(compiler_too_old),
+            ],
+        }
+    }
+}
+
+impl Triage {
+    /// Creates a triage with the built-in rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a triage without rules.
+    pub fn empty() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Adds `rule` after the others.
+    pub fn add_rule<R>(mut self, rule: R) -> Self
+    where
+        R: Rule + 'static,
+    {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// Returns the findings of every rule for `report`, the most likely
+    /// first. A finding repeated by several rules is returned once.
+    pub fn run(&self, report: &BuildReport) -> Vec<Finding> {
+        let evidence = Evidence {
+            report,
+            output: report
+                .phases
+                .iter()
+                .rev()
+                .flat_map(|timing| [timing.stderr.as_str(), timing.stdout.as_str()])
+                .collect::<Vec<_>>()
+                .join("\n"),
+            cache: CMakeCache::load(&report.binary_dir).ok(),
+        };
+        let mut findings: Vec<Finding> = Vec::new();
+        for finding in self.rules.iter().flat_map(|rule| rule.check(&evidence)) {
+            let seen = findings
+                .iter()
+                .any(|f| f.cause == finding.cause && f.evidence == finding.evidence);
+            if !seen {
+                findings.push(finding);
+            }
+        }
+        // stable, so equally likely findings keep the order of the rules
+        findings.sort_by_key(|finding| std::cmp::Reverse(finding.confidence));
+        findings
+    }
+}
+
+/// Returns the findings of the built-in rules for `report`, the most
+/// likely first.
+pub fn triage(report: &BuildReport) -> Vec<Finding> {
+    Triage::new().run(report)
+}
+
+fn finding(cause: Cause, confidence: u8, evidence: &str, suggestion: String) -> Finding {
+    Finding {
+        cause,
+        confidence,
+        evidence: evidence.to_string(),
+        suggestion,
+    }
+}
+
+/// Returns the text between the first two quotes of `line` after `marker`.
+fn quoted_after<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    let rest = &line[line.find(marker)? + marker.len()..];
+    let rest = &rest[rest.find(['"', '\''])? + 1..];
+    rest.split(['"', '\'']).next()
+}
+
+/// Failed `find_package` and `pkg_check_modules` calls, and headers and
+/// libraries the compiler or linker doesn't find.
+fn missing_dependency(evidence: &Evidence<'_>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for line in evidence.lines() {
+        let package = quoted_after(line, "package configuration file provided by")
+            .map(|name| (name, 90))
+            .or_else(|| {
+                let rest = line.strip_prefix("Could NOT find ")?;
+                rest.split_whitespace().next().map(|name| (name, 85))
+            });
+        if let Some((name, confidence)) = package {
+            findings.push(finding(
+                Cause::MissingDependency,
+                confidence,
+                line,
+                format!(
+                    "install {0}, or point CMake at it with .set_prefix_path([\"<{0} prefix>\"]) or .add_define(\"{0}_DIR\", \"<dir of its config file>\")",
+                    name
+                ),
+            ));
+        } else if let Some(name) =
+            quoted_after(line, "No package").filter(|_| line.ends_with("found"))
+        {
+            findings.push(finding(
+                Cause::MissingDependency,
+                80,
+                line,
+                format!(
+                    "install the development package of {}, or add its .pc directory to PKG_CONFIG_PATH with .env(\"PKG_CONFIG_PATH\", ...)",
+                    name
+                ),
+            ));
+        } else if let Some(library) = line.split("cannot find -l").nth(1) {
+            let library = library.split_whitespace().next().unwrap_or(library);
+            findings.push(finding(
+                Cause::MissingDependency,
+                70,
+                line,
+                format!(
+                    "install the library {0}, or add its directory with .add_define(\"CMAKE_LIBRARY_PATH\", \"<dir of lib{0}>\")",
+                    library
+                ),
+            ));
+        }
+    }
+    for diagnostic in evidence.report.errors() {
+        let message = &diagnostic.message;
+        let header = message
+            .strip_suffix(": No such file or directory")
+            .or_else(|| quoted_after(message, "").filter(|_| message.ends_with("file not found")))
+            .or_else(|| quoted_after(message, "Cannot open include file"));
+        if let Some(header) = header {
+            findings.push(finding(
+                Cause::MissingDependency,
+                60,
+                message,
+                format!(
+                    "install the development package that provides {}, or add its include directory with .set_prefix_path",
+                    header
+                ),
+            ));
+        }
+    }
+    findings
+}
+
+/// A generator or build tool that is not available.
+fn wrong_generator(evidence: &Evidence<'_>) -> Vec<Finding> {
+    let Some(line) = evidence
+        .lines()
+        .find(|line| cmake::generator_unavailable(line))
+    else {
+        return Vec::new();
+    };
+    let generator = evidence
+        .cache
+        .as_ref()
+        .and_then(|cache| cache.value("CMAKE_GENERATOR"));
+    let suggestion = match generator {
+        Some(generator) => format!(
+            "install the build tool of {:?}, or pick another with .set_generator(...) or .fallback_generators([...])",
+            generator
+        ),
+        None => "install the build tool of the generator, or pick another with .set_generator(...) or .fallback_generators([...])".to_string(),
+    };
+    vec![finding(Cause::WrongGenerator, 90, line, suggestion)]
+}
+
+/// A cache of another source directory, generator, or platform.
+fn stale_cache(evidence: &Evidence<'_>) -> Vec<Finding> {
+    const MARKERS: [&str; 5] = [
+        "does not match the generator used previously",
+        "does not match the platform used previously",
+        "does not match the toolset used previously",
+        "is different than the directory",
+        "does not match the source",
+    ];
+    let fresh = "configure from scratch with .fresh(true), or use another build directory with .set_binary_path(...)";
+    let mut findings: Vec<Finding> = evidence
+        .lines()
+        .filter(|line| {
+            let line = line.to_lowercase();
+            MARKERS.iter().any(|marker| line.contains(marker))
+        })
+        .map(|line| finding(Cause::StaleCache, 95, line, fresh.to_string()))
+        .collect();
+
+    let home = evidence
+        .cache
+        .as_ref()
+        .and_then(|cache| cache.value("CMAKE_HOME_DIRECTORY"));
+    if let Some(home) = home {
+        let source_dir = &evidence.report.source_dir;
+        let same = match (
+            std::path::Path::new(&home).canonicalize(),
+            source_dir.canonicalize(),
+        ) {
+            (Ok(home), Ok(source)) => home == source,
+            _ => true,
+        };
+        if !same {
+            findings.push(finding(
+                Cause::StaleCache,
+                90,
+                &format!("CMAKE_HOME_DIRECTORY={}", home),
+                fresh.to_string(),
+            ));
+        }
+    }
+    if findings.is_empty() && evidence.report.cache_reused && evidence.report.phases.len() == 1 {
+        let configure_failed = evidence
+            .report
+            .phases
+            .first()
+            .is_some_and(|timing| timing.phase == crate::report::Phase::Configure);
+        if configure_failed {
+            findings.push(finding(
+                Cause::StaleCache,
+                30,
+                "configure failed with an existing CMakeCache.txt",
+                fresh.to_string(),
+            ));
+        }
+    }
+    findings
+}
+
+/// A language standard the compiler or CMake doesn't know.
+fn compiler_too_old(evidence: &Evidence<'_>) -> Vec<Finding> {
+    const MARKERS: [&str; 6] = [
+        "unrecognized command-line option '-std=",
+        "unrecognized command line option '-std=",
+        "invalid value 'c++",
+        "unknown argument: '-std=",
+        "requires the language dialect",
+        "CXX_STANDARD is set to invalid value",
+    ];
+    let compiler = evidence
+        .cache
+        .as_ref()
+        .and_then(|cache| cache.value("CMAKE_CXX_COMPILER"));
+    evidence
+        .lines()
+        .filter(|line| MARKERS.iter().any(|marker| line.contains(marker)))
+        .map(|line| {
+            let suggestion = format!(
+                "use a newer compiler than {} with .add_define(\"CMAKE_CXX_COMPILER\", ...), or lower the standard with .set_cxx_standard(...)",
+                compiler.as_deref().unwrap_or("the default one")
+            );
+            finding(Cause::CompilerTooOld, 80, line, suggestion)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        diagnostics,
+        report::{Phase, PhaseTiming, ProcessExit},
+    };
+    use std::time::{Duration, SystemTime};
+
+    fn failed(phase: Phase, stderr: &str) -> BuildReport {
+        BuildReport {
+            phases: vec![PhaseTiming {
+                phase,
+                target: None,
+                command: Default::default(),
+                started_at: SystemTime::now(),
+                duration: Duration::ZERO,
+                exit: ProcessExit::default(),
+                stdout: String::new(),
+                stderr: stderr.to_string(),
+            }],
+            diagnostics: diagnostics::parse(stderr),
+            ..BuildReport::default()
+        }
+    }
+
+    #[test]
+    fn rank_likely_causes() {
+        let report = failed(
+            Phase::Configure,
+            "CMake Error at CMakeLists.txt:4 (find_package):\n  \
+             Could not find a package configuration file provided by \"fmt\" with any\n\
+             CMake Error: Error: generator : Ninja\n\
+             Does not match the generator used previously: Unix Makefiles\n",
+        );
+        let findings = triage(&report);
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].cause, Cause::StaleCache);
+        assert!(findings[0].suggestion.contains(".fresh(true)"));
+        assert_eq!(findings[1].cause, Cause::MissingDependency);
+        assert!(findings[1].suggestion.starts_with("install fmt"));
+
+        let report = failed(
+            Phase::Build,
+            "src/a.c:1:10: fatal error: zlib.h: No such file or directory\n\
+             cc1plus: error: unrecognized command-line option '-std=c++23'\n",
+        );
+        let causes: Vec<Cause> = triage(&report).iter().map(|f| f.cause).collect();
+        assert_eq!(causes, [Cause::CompilerTooOld, Cause::MissingDependency]);
+
+        let custom = Triage::empty().add_rule(|evidence: &Evidence<'_>| {
+            evidence
+                .lines()
+                .filter(|line| line.contains("zlib.h"))
+                .map(|line| finding(Cause::MissingDependency, 100, line, "vendor zlib".into()))
+                .collect()
+        });
+        assert_eq!(custom.run(&report)[0].suggestion, "vendor zlib");
+    }
+}
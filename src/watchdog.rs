@@ -0,0 +1,346 @@
+//! Timeouts and stall detection for the phases of a run.
+//!
+//! A hung build tool is a CI zombie: it holds a runner until the job's own
+//! timeout, often hours later, and leaves no hint of where it hung. A
+//! [`Watchdog`] set for a phase with
+//! [`Cmd::set_watchdog`](crate::Cmd::set_watchdog) limits how long the
+//! phase's command may run, and how long it may go without printing a line.
+//! On a stall it can warn, dump the stacks of the command and its child
+//! processes, or kill the command. A killed command fails the run with
+//! [`Error::TimedOut`](crate::Error::TimedOut).
+//!
+//! Some CI systems kill jobs that print nothing for a while, e.g. while a
+//! huge C++ target links. A [keepalive](Watchdog::keepalive), or
+//! [`Cmd::keepalive`](crate::Cmd::keepalive) for every phase, prints a
+//! heartbeat line during such silences instead, through the same output
+//! handler as the command's lines, or to stderr.
+//!
+//! Stacks are dumped to stderr with `gdb` on Linux (falling back to the
+//! command line and kernel wait channel of every process) and with `sample`
+//! on macOS. On Windows only the stall is reported.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use cmakr::{Cmd, report::Phase, watchdog::{StallAction, Watchdog}};
+//!
+//! let watchdog = Watchdog::new()
+//!     .timeout(Duration::from_secs(2 * 60 * 60))
+//!     .on_stall(Duration::from_secs(10 * 60), StallAction::Kill);
+//! Cmd::default()
+//!     .set_path("./my_project")
+//!     .set_watchdog(Phase::Build, watchdog)
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::{fmt, process::Command, time::Duration};
+
+/// What a [`Watchdog`] does when a command prints nothing for too long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallAction {
+    /// Prints a warning to stderr.
+    Warn,
+    /// Prints a warning and the stacks of the command and its child
+    /// processes to stderr, where supported.
+    DumpStacks,
+    /// Kills the command, failing the run.
+    Kill,
+}
+
+/// Limits on the runtime and silence of a phase's command.
+///
+/// Warnings and stack dumps are repeated for every stall, i.e. once the
+/// command prints again and then falls silent for the stall period again.
+#[derive(Debug, Clone, Default)]
+pub struct Watchdog {
+    timeout: Option<Duration>,
+    stall: Option<(Duration, StallAction)>,
+    keepalive: Option<(Duration, String)>,
+}
+
+impl Watchdog {
+    /// Creates a watchdog without limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Kills the command once it has run for `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Takes `action` once the command has printed nothing for `after`.
+    ///
+    /// # Arguments
+    ///
+    /// * `after` - How long the command may go without printing a line.
+    /// * `action` - What to do about a stalled command.
+    pub fn on_stall(mut self, after: Duration, action: StallAction) -> Self {
+        self.stall = Some((after, action));
+        self
+    }
+
+    /// Prints `message` every `interval` while the command prints nothing.
+    ///
+    /// `{elapsed}` in the message is replaced with how long the command has
+    /// run, e.g. `"still linking ({elapsed})"` prints
+    /// `"still linking (754s)"`. The heartbeat doesn't end a stall. Without
+    /// a line handler seeing the output, e.g. with
+    /// [output streams](crate::Cmd::set_output_streams), it is printed every
+    /// `interval` regardless.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - How long the command may go without a line before a
+    ///   heartbeat, and between heartbeats.
+    /// * `message` - The heartbeat line.
+    pub fn keepalive<T>(mut self, interval: Duration, message: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.keepalive = Some((interval, message.into()));
+        self
+    }
+
+    /// Returns whether a timeout is set.
+    pub(crate) fn has_timeout(&self) -> bool {
+        self.timeout.is_some()
+    }
+
+    /// Returns whether a keepalive is set.
+    pub(crate) fn has_keepalive(&self) -> bool {
+        self.keepalive.is_some()
+    }
+
+    /// Returns the heartbeat line if one is due.
+    ///
+    /// `running` is how long the command has run, `silent` how long it has
+    /// printed nothing, and `sent` the number of heartbeats sent during the
+    /// current silence.
+    pub(crate) fn heartbeat(
+        &self,
+        running: Duration,
+        silent: Duration,
+        sent: &mut u32,
+    ) -> Option<String> {
+        let (interval, message) = self.keepalive.as_ref()?;
+        if silent < *interval {
+            *sent = 0;
+            return None;
+        }
+        if silent < *interval * (*sent + 1) {
+            return None;
+        }
+        *sent += 1;
+        Some(message.replace("{elapsed}", &format!("{}s", running.as_secs())))
+    }
+
+    /// Checks a running command, taking the stall action if it is due.
+    ///
+    /// `running` is how long the command has run, `silent` how long it has
+    /// printed nothing, and `reported` whether the current stall was already
+    /// warned about. Returns why the command must be killed, if it must.
+    pub(crate) fn check(
+        &self,
+        program: &str,
+        pid: u32,
+        running: Duration,
+        silent: Duration,
+        reported: &mut bool,
+    ) -> Option<Trigger> {
+        if let Some(timeout) = self.timeout
+            && running >= timeout
+        {
+            return Some(Trigger::Timeout(timeout));
+        }
+        let (after, action) = self.stall?;
+        if silent < after {
+            *reported = false;
+            return None;
+        }
+        match action {
+            StallAction::Kill => return Some(Trigger::Stall(after)),
+            _ if *reported => {}
+            StallAction::Warn => {
+                eprintln!(
+                    "warning: {} (pid {}) has printed nothing for {}s",
+                    program,
+                    pid,
+                    silent.as_secs()
+                );
+            }
+            StallAction::DumpStacks => {
+                eprintln!(
+                    "warning: {} (pid {}) has printed nothing for {}s, dumping stacks",
+                    program,
+                    pid,
+                    silent.as_secs()
+                );
+                dump_stacks(pid);
+            }
+        }
+        *reported = true;
+        None
+    }
+}
+
+/// Why a [`Watchdog`] killed a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// The command ran longer than the timeout.
+    Timeout(Duration),
+    /// The command printed nothing for the stall period.
+    Stall(Duration),
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trigger::Timeout(timeout) => write!(f, "ran longer than {}s", timeout.as_secs()),
+            Trigger::Stall(after) => write!(f, "printed nothing for {}s", after.as_secs()),
+        }
+    }
+}
+
+/// Prints the stacks of `pid` and the processes of its group to stderr.
+fn dump_stacks(pid: u32) {
+    if cfg!(windows) {
+        eprintln!("stack dumps are not supported on Windows");
+        return;
+    }
+    let gdb = cfg!(target_os = "linux") && which::which("gdb").is_ok();
+    for pid in process_group(pid) {
+        let mut command = if cfg!(target_os = "macos") {
+            let mut command = Command::new("sample");
+            command.arg(pid.to_string()).arg("1");
+            command
+        } else if gdb {
+            let mut command = Command::new("gdb");
+            command
+                .args(["-p", &pid.to_string(), "-batch", "-nx"])
+                .args(["-ex", "thread apply all bt"]);
+            command
+        } else {
+            let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+            let wchan = std::fs::read_to_string(format!("/proc/{}/wchan", pid)).unwrap_or_default();
+            eprintln!(
+                "--- pid {}: {} (waiting in {})",
+                pid,
+                String::from_utf8_lossy(&cmdline).replace('\0', " ").trim(),
+                if wchan.is_empty() {
+                    "?"
+                } else {
+                    wchan.as_str()
+                }
+            );
+            continue;
+        };
+        eprintln!("--- pid {}", pid);
+        // the warning is printed even if the dump fails
+        let _ = command.status();
+    }
+}
+
+/// Returns the pids of the processes in the process group `pgid`, which
+/// every child cmakr runs leads, the leader first.
+///
+/// On Linux, the groups are read from `/proc`; elsewhere `pgrep -g` lists
+/// them.
+fn process_group(pgid: u32) -> Vec<u32> {
+    let mut pids = vec![pgid];
+    if cfg!(target_os = "linux") {
+        let Ok(entries) = std::fs::read_dir("/proc") else {
+            return pids;
+        };
+        for entry in entries.flatten() {
+            let Some(pid) = entry.file_name().to_str().and_then(|pid| pid.parse().ok()) else {
+                continue;
+            };
+            // `pid (comm) state ppid pgrp ...`, where comm may contain anything
+            let stat = std::fs::read_to_string(entry.path().join("stat")).unwrap_or_default();
+            let pgrp = stat
+                .rsplit_once(')')
+                .and_then(|(_, fields)| fields.split_whitespace().nth(2))
+                .and_then(|pgrp| pgrp.parse::<u32>().ok());
+            if pgrp == Some(pgid) && pid != pgid {
+                pids.push(pid);
+            }
+        }
+    } else {
+        let members = Command::new("pgrep")
+            .arg("-g")
+            .arg(pgid.to_string())
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+            .unwrap_or_default();
+        pids.extend(
+            members
+                .lines()
+                .filter_map(|line| line.trim().parse::<u32>().ok())
+                .filter(|&pid| pid != pgid),
+        );
+    }
+    pids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trigger_on_timeout_and_stall() {
+        let secs = Duration::from_secs;
+        let mut reported = false;
+
+        let watchdog = Watchdog::new()
+            .timeout(secs(60))
+            .on_stall(secs(10), StallAction::Warn);
+        assert_eq!(
+            watchdog.check("cmake", 1, secs(5), secs(5), &mut reported),
+            None
+        );
+        assert_eq!(
+            watchdog.check("cmake", 1, secs(30), secs(12), &mut reported),
+            None
+        );
+        assert!(reported);
+        assert_eq!(
+            watchdog.check("cmake", 1, secs(31), secs(0), &mut reported),
+            None
+        );
+        assert!(!reported);
+        assert_eq!(
+            watchdog.check("cmake", 1, secs(60), secs(0), &mut reported),
+            Some(Trigger::Timeout(secs(60)))
+        );
+
+        let watchdog = Watchdog::new().on_stall(secs(10), StallAction::Kill);
+        let trigger = watchdog.check("cmake", 1, secs(600), secs(10), &mut reported);
+        assert_eq!(trigger, Some(Trigger::Stall(secs(10))));
+        assert_eq!(trigger.unwrap().to_string(), "printed nothing for 10s");
+
+        let watchdog = Watchdog::new().keepalive(secs(60), "still running ({elapsed})");
+        let mut sent = 0;
+        assert_eq!(watchdog.heartbeat(secs(90), secs(30), &mut sent), None);
+        assert_eq!(
+            watchdog
+                .heartbeat(secs(120), secs(60), &mut sent)
+                .as_deref(),
+            Some("still running (120s)")
+        );
+        assert_eq!(watchdog.heartbeat(secs(121), secs(61), &mut sent), None);
+        assert!(
+            watchdog
+                .heartbeat(secs(180), secs(120), &mut sent)
+                .is_some()
+        );
+        assert_eq!(sent, 2);
+        // output ends the silence
+        assert_eq!(watchdog.heartbeat(secs(181), secs(0), &mut sent), None);
+        assert_eq!(sent, 0);
+    }
+}
@@ -0,0 +1,547 @@
+//! Building several CMake projects together.
+//!
+//! A [`Workspace`] holds a set of named [`Cmd`] configurations with declared
+//! dependencies between them. Projects are built in dependency order, with
+//! independent projects running in parallel, and a [`WorkspaceReport`]
+//! records the outcome of every project.
+//! [`build_order`](Workspace::build_order) returns the order without
+//! building anything.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use cmakr::{Cmd, workspace::{FailurePolicy, Workspace}};
+//!
+//! let report = Workspace::default()
+//!     .add_project("zlib", Cmd::default().set_path("./vendor/zlib"))
+//!     .add_project("png", Cmd::default().set_path("./vendor/libpng"))
+//!     .add_dependency("png", "zlib")
+//!     .set_failure_policy(FailurePolicy::ContinueIndependent)
+//!     .build()
+//!     .unwrap();
+//!
+//! for (name, status) in &report.projects {
+//!     println!("{}: {}", name, status);
+//! }
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::mpsc,
+    thread,
+};
+
+use crate::{
+    Cmd,
+    error::Error,
+    output::{self, OutputColor, OutputMode},
+    process::CancelToken,
+    report::BuildReport,
+};
+
+/// What a [`Workspace`] does with the remaining projects once one fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailurePolicy {
+    /// Kill running builds and start nothing else.
+    FailFast,
+    /// Let running builds finish, but start nothing else.
+    #[default]
+    FinishRunning,
+    /// Keep building every project that does not depend on a failed one.
+    ContinueIndependent,
+}
+
+/// The outcome of a single project in a workspace build.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum ProjectStatus {
+    /// The project was configured and built successfully.
+    Succeeded(BuildReport),
+    /// The project's build returned an error.
+    Failed(Error),
+    /// The project was not built because one of its dependencies did not succeed.
+    Skipped,
+    /// The project was not built, or was killed, because of the failure policy.
+    Cancelled,
+}
+
+impl ProjectStatus {
+    /// Returns whether the project was built successfully.
+    pub fn is_success(&self) -> bool {
+        matches!(self, ProjectStatus::Succeeded(_))
+    }
+}
+
+impl fmt::Display for ProjectStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectStatus::Succeeded(_) => write!(f, "succeeded"),
+            ProjectStatus::Failed(e) => write!(f, "failed: {}", e),
+            ProjectStatus::Skipped => write!(f, "skipped"),
+            ProjectStatus::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+/// The outcome of every project in a workspace build, in the order the
+/// projects were added.
+#[derive(Debug)]
+pub struct WorkspaceReport {
+    pub projects: Vec<(String, ProjectStatus)>,
+}
+
+impl WorkspaceReport {
+    /// Returns whether every project was built successfully.
+    pub fn is_success(&self) -> bool {
+        self.projects.iter().all(|(_, status)| status.is_success())
+    }
+
+    /// Returns the status of the project named `name`.
+    pub fn status(&self, name: &str) -> Option<&ProjectStatus> {
+        self.projects
+            .iter()
+            .find(|(project, _)| project == name)
+            .map(|(_, status)| status)
+    }
+}
+
+/// A project registered with a [`Workspace`].
+#[derive(Clone)]
+struct Project {
+    name: String,
+    cmd: Cmd,
+    dependencies: Vec<String>,
+}
+
+/// A set of CMake projects built together in dependency order.
+///
+/// At most `jobs` projects are built at the same time (defaults to the number
+/// of available CPUs). What happens after a project fails is controlled by
+/// the [`FailurePolicy`].
+pub struct Workspace {
+    projects: Vec<Project>,
+    jobs: usize,
+    policy: FailurePolicy,
+    output_mode: OutputMode,
+    /// Mistakes in the declared projects, reported by
+    /// [`build_order`](Workspace::build_order) and [`build`](Workspace::build).
+    errors: Vec<String>,
+}
+
+impl Default for Workspace {
+    fn default() -> Self {
+        Self {
+            projects: Vec::new(),
+            jobs: thread::available_parallelism().map_or(1, |n| n.get()),
+            policy: FailurePolicy::default(),
+            output_mode: OutputMode::default(),
+            errors: Vec::new(),
+        }
+    }
+}
+
+impl Workspace {
+    /// Adds a project to the workspace.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - A unique name identifying the project in dependencies and reports.
+    /// * `cmd` - The configured build of the project.
+    ///
+    /// A second project with the same name is ignored, and makes
+    /// [`build_order`](Workspace::build_order) and [`build`](Workspace::build)
+    /// fail.
+    pub fn add_project<T>(mut self, name: T, cmd: Cmd) -> Self
+    where
+        T: Into<String>,
+    {
+        let name = name.into();
+        if self.projects.iter().any(|p| p.name == name) {
+            self.errors
+                .push(format!("project {} is added more than once", name));
+            return self;
+        }
+        self.projects.push(Project {
+            name,
+            cmd,
+            dependencies: Vec::new(),
+        });
+        self
+    }
+
+    /// Declares that `project` must be built after `dependency`.
+    ///
+    /// Both projects must have been added with [`add_project`](Workspace::add_project);
+    /// otherwise [`build_order`](Workspace::build_order) and
+    /// [`build`](Workspace::build) fail.
+    pub fn add_dependency<T, U>(mut self, project: T, dependency: U) -> Self
+    where
+        T: Into<String>,
+        U: Into<String>,
+    {
+        let project = project.into();
+        let dependency = dependency.into();
+        match self.projects.iter_mut().find(|p| p.name == project) {
+            Some(p) => p.dependencies.push(dependency),
+            None => self.errors.push(format!(
+                "dependency on {} declared for unknown project {}",
+                dependency, project
+            )),
+        }
+        self
+    }
+
+    /// Sets the maximum number of projects built at the same time.
+    pub fn set_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Sets what happens to the remaining projects once one fails.
+    pub fn set_failure_policy(mut self, policy: FailurePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets how the output of projects building at the same time is told
+    /// apart; see [`output`](crate::output).
+    pub fn set_output_mode(mut self, mode: OutputMode) -> Self {
+        self.output_mode = mode;
+        self
+    }
+
+    /// Returns a workspace with only `name` and the projects it transitively
+    /// depends on, or `None` if there is no such project.
+    #[cfg(feature = "server")]
+    pub(crate) fn subset(&self, name: &str) -> Option<Workspace> {
+        let mut wanted = vec![name];
+        let mut keep: Vec<&str> = Vec::new();
+        while let Some(name) = wanted.pop() {
+            if keep.contains(&name) {
+                continue;
+            }
+            let project = self.projects.iter().find(|p| p.name == name)?;
+            keep.push(name);
+            wanted.extend(project.dependencies.iter().map(String::as_str));
+        }
+
+        Some(Workspace {
+            projects: self
+                .projects
+                .iter()
+                .filter(|p| keep.contains(&p.name.as_str()))
+                .cloned()
+                .collect(),
+            jobs: self.jobs,
+            policy: self.policy,
+            output_mode: self.output_mode,
+            errors: self.errors.clone(),
+        })
+    }
+
+    /// Replaces the build of every project with `f(name, cmd)`.
+    #[cfg(feature = "server")]
+    pub(crate) fn map_projects(mut self, f: impl Fn(&str, Cmd) -> Cmd) -> Self {
+        self.projects = self
+            .projects
+            .into_iter()
+            .map(|p| Project {
+                cmd: f(&p.name, p.cmd),
+                ..p
+            })
+            .collect();
+        self
+    }
+
+    /// Returns the projects in the order they are built, as stages: every
+    /// project of a stage depends only on projects of earlier stages, so the
+    /// projects of a stage can be built in parallel.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a project was added twice, if a dependency names an
+    /// unknown project, or if the dependencies form a cycle.
+    pub fn build_order(&self) -> Result<Vec<Vec<String>>, Error> {
+        let (names, dependencies) = self.resolve()?;
+        let mut stage_of: Vec<Option<usize>> = vec![None; names.len()];
+        let mut stages: Vec<Vec<String>> = Vec::new();
+        while stage_of.iter().any(Option::is_none) {
+            let stage = stages.len();
+            let ready: Vec<usize> = (0..names.len())
+                .filter(|&i| {
+                    stage_of[i].is_none()
+                        && dependencies[i]
+                            .iter()
+                            .all(|&d| stage_of[d].is_some_and(|s| s < stage))
+                })
+                .collect();
+            for &i in &ready {
+                stage_of[i] = Some(stage);
+            }
+            stages.push(ready.into_iter().map(|i| names[i].clone()).collect());
+        }
+        Ok(stages)
+    }
+
+    /// Returns the project names and the indices of the dependencies of
+    /// every project, checking that they were declared correctly and form no
+    /// cycle.
+    fn resolve(&self) -> Result<(Vec<String>, Vec<Vec<usize>>), Error> {
+        if let Some(error) = self.errors.first() {
+            return Err(Error::InvalidConfig(error.clone()));
+        }
+        let names: Vec<String> = self.projects.iter().map(|p| p.name.clone()).collect();
+        let index: HashMap<&str, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.as_str(), i))
+            .collect();
+
+        let mut dependencies: Vec<Vec<usize>> = Vec::with_capacity(names.len());
+        for project in &self.projects {
+            let mut deps = Vec::new();
+            for dependency in &project.dependencies {
+                let Some(&i) = index.get(dependency.as_str()) else {
+                    return Err(Error::InvalidConfig(format!(
+                        "project {} depends on unknown project {}",
+                        project.name, dependency
+                    )));
+                };
+                deps.push(i);
+            }
+            dependencies.push(deps);
+        }
+        check_acyclic(&names, &dependencies)?;
+        Ok((names, dependencies))
+    }
+
+    /// Builds every project, returning the outcome of each.
+    ///
+    /// Individual project failures are reported in the [`WorkspaceReport`],
+    /// not as an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a project was added twice, if a dependency names an
+    /// unknown project, or if the dependencies form a cycle.
+    pub fn build(self) -> Result<WorkspaceReport, Error> {
+        let (names, dependencies) = self.resolve()?;
+
+        let cancel = CancelToken::default();
+        let colored = output::color_enabled();
+        let mode = self.output_mode;
+        let mut pending: Vec<Option<Cmd>> = self
+            .projects
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let mut cmd = p.cmd.set_cancel_token(cancel.clone());
+                if mode != OutputMode::Interleaved {
+                    cmd = cmd.set_output_prefix(format!("[{}] ", p.name));
+                    if colored {
+                        cmd = cmd.set_output_color(OutputColor::nth(i));
+                    }
+                }
+                if mode == OutputMode::Buffered {
+                    cmd = cmd.buffer_output(true);
+                }
+                Some(cmd)
+            })
+            .collect();
+        let mut statuses: Vec<Option<ProjectStatus>> = names.iter().map(|_| None).collect();
+
+        let (tx, rx) = mpsc::channel::<(usize, Result<BuildReport, Error>)>();
+        let mut running = 0;
+        let mut stopped = false;
+
+        loop {
+            // resolve projects whose dependencies did not all succeed, transitively
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for i in 0..names.len() {
+                    if pending[i].is_some()
+                        && dependencies[i]
+                            .iter()
+                            .any(|&d| statuses[d].as_ref().is_some_and(|s| !s.is_success()))
+                    {
+                        pending[i] = None;
+                        statuses[i] = Some(ProjectStatus::Skipped);
+                        changed = true;
+                    }
+                }
+            }
+
+            // start every ready project while there is a free job slot
+            if !stopped {
+                for i in 0..names.len() {
+                    if running >= self.jobs {
+                        break;
+                    }
+                    let ready = pending[i].is_some()
+                        && dependencies[i]
+                            .iter()
+                            .all(|&d| statuses[d].as_ref().is_some_and(ProjectStatus::is_success));
+                    if ready {
+                        let cmd = pending[i].take().expect("pending project");
+                        let tx = tx.clone();
+                        thread::spawn(move || {
+                            // a panicking build must still report back, or the scheduler would wait forever
+                            let result = panic::catch_unwind(AssertUnwindSafe(|| cmd.build()))
+                                .unwrap_or_else(|_| Err(Error::Panicked));
+                            let _ = tx.send((i, result));
+                        });
+                        running += 1;
+                    }
+                }
+            }
+
+            if running == 0 {
+                break;
+            }
+
+            let (i, result) = rx.recv().expect("worker threads hold a sender");
+            running -= 1;
+            statuses[i] = Some(match result {
+                Ok(report) => ProjectStatus::Succeeded(report),
+                Err(_) if cancel.is_cancelled() => ProjectStatus::Cancelled,
+                Err(e) => {
+                    match self.policy {
+                        FailurePolicy::FailFast => {
+                            stopped = true;
+                            cancel.cancel();
+                        }
+                        FailurePolicy::FinishRunning => stopped = true,
+                        FailurePolicy::ContinueIndependent => {}
+                    }
+                    ProjectStatus::Failed(e)
+                }
+            });
+        }
+
+        // anything never started was cut off by the failure policy
+        let projects = names
+            .into_iter()
+            .zip(statuses)
+            .map(|(name, status)| (name, status.unwrap_or(ProjectStatus::Cancelled)))
+            .collect();
+
+        Ok(WorkspaceReport { projects })
+    }
+}
+
+/// Returns an error naming a project on a dependency cycle, if there is one.
+fn check_acyclic(names: &[String], dependencies: &[Vec<usize>]) -> Result<(), Error> {
+    // 0 = unvisited, 1 = on the current path, 2 = done
+    fn visit(i: usize, dependencies: &[Vec<usize>], state: &mut [u8]) -> Option<usize> {
+        match state[i] {
+            1 => return Some(i),
+            2 => return None,
+            _ => {}
+        }
+        state[i] = 1;
+        for &d in &dependencies[i] {
+            if let Some(cycle) = visit(d, dependencies, state) {
+                return Some(cycle);
+            }
+        }
+        state[i] = 2;
+        None
+    }
+
+    let mut state = vec![0u8; names.len()];
+    for i in 0..names.len() {
+        if let Some(cycle) = visit(i, dependencies, &mut state) {
+            return Err(Error::InvalidConfig(format!(
+                "dependency cycle involving project {}",
+                names[cycle]
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_unknown_and_cyclic_dependencies() {
+        let unknown = Workspace::default()
+            .add_project("a", Cmd::default())
+            .add_dependency("a", "missing")
+            .build();
+        assert!(unknown.is_err());
+
+        let cyclic = Workspace::default()
+            .add_project("a", Cmd::default())
+            .add_project("b", Cmd::default())
+            .add_dependency("a", "b")
+            .add_dependency("b", "a")
+            .build();
+        assert!(cyclic.is_err());
+    }
+
+    #[test]
+    fn reject_duplicate_and_unknown_projects() {
+        let duplicate = Workspace::default()
+            .add_project("a", Cmd::default())
+            .add_project("a", Cmd::default().set_path("./other"));
+        let error = duplicate.build_order().unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("project a is added more than once")
+        );
+
+        let unknown = Workspace::default()
+            .add_project("a", Cmd::default())
+            .add_dependency("missing", "a");
+        let error = unknown.build_order().unwrap_err();
+        assert!(error.to_string().contains("unknown project missing"));
+        assert!(unknown.build().is_err());
+    }
+
+    #[test]
+    fn skip_dependents_of_failed_project() {
+        // the preset does not exist, so "a" fails before cmake is run
+        let report = Workspace::default()
+            .add_project(
+                "a",
+                Cmd::default().set_path("./test/").set_preset("missing"),
+            )
+            .add_project(
+                "b",
+                Cmd::default().set_path("./test/").set_preset("missing"),
+            )
+            .add_dependency("b", "a")
+            .set_jobs(1)
+            .set_failure_policy(FailurePolicy::ContinueIndependent)
+            .build()
+            .unwrap();
+
+        assert!(!report.is_success());
+        assert!(matches!(report.status("a"), Some(ProjectStatus::Failed(_))));
+        assert!(matches!(report.status("b"), Some(ProjectStatus::Skipped)));
+    }
+
+    #[test]
+    fn order_projects_in_stages() {
+        let workspace = Workspace::default()
+            .add_project("app", Cmd::default())
+            .add_project("png", Cmd::default())
+            .add_project("zlib", Cmd::default())
+            .add_project("fmt", Cmd::default())
+            .add_dependency("app", "png")
+            .add_dependency("app", "fmt")
+            .add_dependency("png", "zlib");
+        assert_eq!(
+            workspace.build_order().unwrap(),
+            [vec!["zlib", "fmt"], vec!["png"], vec!["app"]]
+        );
+        let cyclic = workspace.add_dependency("zlib", "app");
+        assert!(cyclic.build_order().is_err());
+    }
+}